@@ -43,6 +43,10 @@ pub const EC_SMC64: u64 = 0x17;
 // ── SPSR_EL2 defaults ────────────────────────────────────────────────
 pub const SPSR_EL1H_DAIF_MASKED: u64 = 0x3C5;
 pub const SPSR_EL1H: u64 = 0b0101;
+/// SPSR_EL2.M[3:0]: exception level and stack pointer select of the trapped context
+pub const SPSR_M_MASK: u64 = 0xF;
+/// SPSR_EL2.I: IRQs masked in the trapped context
+pub const SPSR_I: u64 = 1 << 7;
 
 // ── CPTR_EL2 bits ────────────────────────────────────────────────────
 pub const CPTR_TZ: u64 = 1 << 8;
@@ -56,6 +60,8 @@ pub const ICH_HCR_TALL1: u64 = 1 << 13;
 
 // ── ICC register bits ────────────────────────────────────────────────
 pub const ICC_SRE_SRE: u32 = 1 << 0;
+pub const ICC_SRE_DFB: u32 = 1 << 1;
+pub const ICC_SRE_DIB: u32 = 1 << 2;
 pub const ICC_SRE_ENABLE: u32 = 1 << 3;
 pub const ICC_CTLR_EOIMODE: u32 = 1 << 1;
 pub const ICC_PMR_ALLOW_ALL: u32 = 0xFF;
@@ -78,19 +84,32 @@ pub const IRQ_DEFAULT_PRIORITY: u8 = 0xA0;
 // ── VTCR_EL2 fields ─────────────────────────────────────────────────
 pub const VTCR_T0SZ_48BIT: u64 = 16;
 pub const VTCR_SL0_LEVEL0: u64 = 2 << 6;
+/// SL0 for the 64KB granule: 0b10 = start at level 1
+pub const VTCR_SL0_64KB_LEVEL1: u64 = 2 << 6;
 pub const VTCR_IRGN0_WB: u64 = 0b01 << 8;
 pub const VTCR_ORGN0_WB: u64 = 0b01 << 10;
 pub const VTCR_SH0_INNER: u64 = 0b11 << 12;
 pub const VTCR_TG0_4KB: u64 = 0b00 << 14;
+pub const VTCR_TG0_64KB: u64 = 0b01 << 14;
 pub const VTCR_PS_48BIT: u64 = 0b101 << 16;
 
 // ── CNTHCTL_EL2 bits ─────────────────────────────────────────────────
 pub const CNTHCTL_EL1PCTEN: u64 = 1 << 0;
 pub const CNTHCTL_EL1PCEN: u64 = 1 << 1;
 
+// ── CNTKCTL_EL1 bits ─────────────────────────────────────────────────
+pub const CNTKCTL_EL0PCTEN: u64 = 1 << 0;
+pub const CNTKCTL_EL0VCTEN: u64 = 1 << 1;
+pub const CNTKCTL_EL0VTEN: u64 = 1 << 8;
+pub const CNTKCTL_EL0PTEN: u64 = 1 << 9;
+/// Architected fields [9:0]: EL0PCTEN/EL0VCTEN, event stream, EL0VTEN/EL0PTEN
+pub const CNTKCTL_VALID_MASK: u64 = 0x3FF;
+
 // ── Page table constants ─────────────────────────────────────────────
 pub const PTE_VALID: u64 = 1 << 0;
 pub const PTE_TABLE: u64 = 1 << 1;
+/// Access Flag: a block/page entry without it faults on first access
+pub const PTE_AF: u64 = 1 << 10;
 pub const PTE_ADDR_MASK: u64 = 0x0000_FFFF_FFFF_F000;
 pub const PAGE_OFFSET_MASK: u64 = 0xFFF;
 pub const PT_INDEX_MASK: u64 = 0x1FF;
@@ -102,6 +121,8 @@ pub const PAGE_MASK_4KB: u64 = PAGE_SIZE_4KB - 1;
 // ── Stage-2 PTE Software bits (for page ownership tracking) ────────
 pub const PTE_SW_SHIFT: u32 = 55;
 pub const PTE_SW_MASK: u64 = 0x3 << PTE_SW_SHIFT; // bits [56:55]
+/// SW bit [57]: writable page made read-only for dirty logging
+pub const PTE_DIRTY_LOG: u64 = 1 << 57;
 
 // ── Stage-2 Access Permissions (S2AP, PTE bits [7:6]) ────────────
 pub const S2AP_SHIFT: u32 = 6;
@@ -110,6 +131,14 @@ pub const S2AP_NONE: u64 = 0b00 << S2AP_SHIFT; // No access
 pub const S2AP_RO: u64 = 0b01 << S2AP_SHIFT; // Read-only
 pub const S2AP_RW: u64 = 0b11 << S2AP_SHIFT; // Read-write
 
+// ── Stage-2 Memory Attributes (MemAttr, PTE bits [5:2]) ──────────
+pub const S2_MEMATTR_SHIFT: u32 = 2;
+pub const S2_MEMATTR_MASK: u64 = 0xF << S2_MEMATTR_SHIFT;
+pub const S2_MEMATTR_DEVICE_NGNRNE: u8 = 0b0000;
+pub const S2_MEMATTR_DEVICE_NGNRE: u8 = 0b0001;
+pub const S2_MEMATTR_NORMAL_NC: u8 = 0b0101; // Outer/Inner Non-cacheable
+pub const S2_MEMATTR_NORMAL_WB: u8 = 0b1111; // Outer/Inner Write-back
+
 // ── Preemptive scheduling ────────────────────────────────────────────
 // Preemption is now handled by CNTHP timer (INTID 26) armed before each
 // vcpu.run(). See timer::arm_preemption_timer(). This ensures preemption
@@ -1,3 +1,5 @@
+use crate::arch::aarch64::regs::VcpuContext;
+
 /// ARM64 instruction decoder for MMIO emulation
 ///
 /// This module decodes load/store instructions that cause data aborts
@@ -8,13 +10,14 @@
 pub enum MmioAccess {
     /// Load instruction: LDR, LDRB, LDRH, etc.
     Load {
-        reg: u8,  // Destination register (0-30)
+        reg: u8,  // Destination register (0-30, 31 = XZR)
         size: u8, // Access size in bytes (1, 2, 4, 8)
         sign_extend: bool,
+        reg_size: u8, // Destination width in bytes (4 = Wt, 8 = Xt)
     },
     /// Store instruction: STR, STRB, STRH, etc.
     Store {
-        reg: u8,  // Source register (0-30)
+        reg: u8,  // Source register (0-30, 31 = XZR)
         size: u8, // Access size in bytes (1, 2, 4, 8)
     },
 }
@@ -40,10 +43,10 @@ impl MmioAccess {
         // ISS is valid, extract fields
         let sas = (iss >> 22) & 0x3; // Size: 00=byte, 01=half, 10=word, 11=double
         let srt = (iss >> 16) & 0x1F; // Source/dest register
-        let _sf = (iss >> 15) & 1; // 0=32-bit, 1=64-bit
+        let sf = (iss >> 15) & 1; // 0=32-bit, 1=64-bit
         let _ar = (iss >> 14) & 1; // Acquire/Release
         let wnr = (iss >> 6) & 1; // Write not Read: 0=read, 1=write
-        let sext = (iss >> 23) & 1; // Sign extend
+        let sse = (iss >> 21) & 1; // Syndrome Sign Extend
 
         let size = match sas {
             0 => 1, // Byte
@@ -64,7 +67,8 @@ impl MmioAccess {
             Some(MmioAccess::Load {
                 reg: srt as u8,
                 size: size as u8,
-                sign_extend: sext != 0,
+                sign_extend: sse != 0,
+                reg_size: if sf != 0 { 8 } else { 4 },
             })
         }
     }
@@ -82,21 +86,38 @@ impl MmioAccess {
         let _op3 = (insn >> 22) & 0x3;
 
         // Load/Store register (unsigned immediate)
-        // xx|111|0|01|xx|...... where xx is size
+        // xx|111|0|01|opc|...... where xx is size
         if (insn & 0x3B000000) == 0x39000000 {
             let size_bits = (insn >> 30) & 0x3;
             let size = 1u8 << size_bits;
             let rt = (insn & 0x1F) as u8;
-            let is_load = (insn >> 22) & 1;
+            let opc = (insn >> 22) & 0x3;
 
-            if is_load == 1 {
-                Some(MmioAccess::Load {
+            match (opc, size) {
+                // STR, STRB, STRH
+                (0b00, _) => Some(MmioAccess::Store { reg: rt, size }),
+                // LDR, LDRB, LDRH: zero-extend into Wt (Xt for LDR Xt)
+                (0b01, _) => Some(MmioAccess::Load {
                     reg: rt,
                     size,
                     sign_extend: false,
-                })
-            } else {
-                Some(MmioAccess::Store { reg: rt, size })
+                    reg_size: if size == 8 { 8 } else { 4 },
+                }),
+                // LDRSB, LDRSH, LDRSW Xt (opc=10, size 8 is PRFM)
+                (0b10, 1 | 2 | 4) => Some(MmioAccess::Load {
+                    reg: rt,
+                    size,
+                    sign_extend: true,
+                    reg_size: 8,
+                }),
+                // LDRSB, LDRSH Wt
+                (0b11, 1 | 2) => Some(MmioAccess::Load {
+                    reg: rt,
+                    size,
+                    sign_extend: true,
+                    reg_size: 4,
+                }),
+                _ => None,
             }
         } else {
             // Unsupported instruction
@@ -104,7 +125,22 @@ impl MmioAccess {
         }
     }
 
-    /// Get the register number
+    /// Address accessed by a load/store (unsigned immediate) that
+    /// `decode_instruction` handles: base register Rn plus imm12 scaled by
+    /// the access size. Rn = 31 is the guest SP, not XZR.
+    ///
+    /// Returns `None` for any other encoding.
+    pub fn effective_address(insn: u32, context: &VcpuContext) -> Option<u64> {
+        if (insn & 0x3B000000) != 0x39000000 {
+            return None;
+        }
+        let size_bits = (insn >> 30) & 0x3;
+        let rn = ((insn >> 5) & 0x1F) as u8;
+        let imm12 = ((insn >> 10) & 0xFFF) as u64;
+        Some(context.get_base_reg(rn).wrapping_add(imm12 << size_bits))
+    }
+
+    /// Get the register number (31 = XZR: loads discard, stores write 0)
     pub fn reg(&self) -> u8 {
         match self {
             MmioAccess::Load { reg, .. } => *reg,
@@ -120,6 +156,36 @@ impl MmioAccess {
         }
     }
 
+    /// Value a load leaves in its destination register, given the `size`
+    /// bytes read from the device: sign- or zero-extended to the access's
+    /// register width (upper 32 bits clear for a Wt destination).
+    /// Stores return `value` unchanged.
+    pub fn extend(&self, value: u64) -> u64 {
+        let MmioAccess::Load {
+            size,
+            sign_extend,
+            reg_size,
+            ..
+        } = *self
+        else {
+            return value;
+        };
+        let bits = size as u32 * 8;
+        let mut v = if bits < 64 {
+            value & ((1u64 << bits) - 1)
+        } else {
+            value
+        };
+        if sign_extend && bits < 64 {
+            let shift = 64 - bits;
+            v = (((v << shift) as i64) >> shift) as u64;
+        }
+        if reg_size == 4 {
+            v &= 0xFFFF_FFFF;
+        }
+        v
+    }
+
     /// Check if this is a load instruction
     pub fn is_load(&self) -> bool {
         matches!(self, MmioAccess::Load { .. })
@@ -130,3 +196,126 @@ impl MmioAccess {
         matches!(self, MmioAccess::Store { .. })
     }
 }
+
+/// Addressing mode of a load/store pair
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PairIndex {
+    /// `[Xn, #imm]` (also LDNP/STNP): no writeback
+    Offset,
+    /// `[Xn, #imm]!`: access at Xn + imm, then Xn = Xn + imm
+    PreIndex,
+    /// `[Xn], #imm`: access at Xn, then Xn = Xn + imm
+    PostIndex,
+}
+
+/// Decoded LDP/STP/LDPSW on general-purpose registers
+///
+/// The pair is emulated as two element accesses of `size` bytes: `first`
+/// (Rt) at the lower address and `second` (Rt2) right after it.
+#[derive(Debug, Clone, Copy)]
+pub struct PairAccess {
+    /// Element access for Rt
+    pub first: MmioAccess,
+    /// Element access for Rt2, at the first element's address + size
+    pub second: MmioAccess,
+    /// Base register Rn (31 = SP)
+    pub base: u8,
+    /// imm7 scaled by the element size
+    pub offset: i64,
+    pub index: PairIndex,
+}
+
+impl PairAccess {
+    /// Decode a load/store pair.
+    ///
+    /// Pair accesses never report a valid syndrome, so this returns `None`
+    /// when ISS.ISV is set, as well as for anything other than integer
+    /// LDP/STP/LDNP/STNP/LDPSW (SIMD pairs, STGP).
+    pub fn decode(insn: u32, iss: u32) -> Option<Self> {
+        if (iss >> 24) & 1 != 0 {
+            return None;
+        }
+        // opc|101|V=0|idx|L|imm7|Rt2|Rn|Rt with idx (bits 24:23) in 00..11
+        if (insn >> 25) & 0x1F != 0b10100 {
+            return None;
+        }
+        let opc = insn >> 30;
+        let index = match (insn >> 23) & 0x3 {
+            0b00 | 0b10 => PairIndex::Offset,
+            0b01 => PairIndex::PostIndex,
+            _ => PairIndex::PreIndex,
+        };
+        let load = (insn >> 22) & 1 != 0;
+        let non_temporal = (insn >> 23) & 0x3 == 0;
+        let (size, sign_extend, reg_size) = match opc {
+            0b00 => (4u8, false, 4u8),
+            0b10 => (8, false, 8),
+            // LDPSW; the store form is STGP and there is no LDNPSW
+            0b01 if load && !non_temporal => (4, true, 8),
+            _ => return None,
+        };
+        let element = |reg: u8| {
+            if load {
+                MmioAccess::Load {
+                    reg,
+                    size,
+                    sign_extend,
+                    reg_size,
+                }
+            } else {
+                MmioAccess::Store { reg, size }
+            }
+        };
+        let imm7 = ((((insn >> 15) & 0x7F) as i64) << 57) >> 57;
+        Some(Self {
+            first: element((insn & 0x1F) as u8),
+            second: element(((insn >> 10) & 0x1F) as u8),
+            base: ((insn >> 5) & 0x1F) as u8,
+            offset: imm7 * size as i64,
+            index,
+        })
+    }
+
+    /// Element size in bytes
+    pub fn size(&self) -> u8 {
+        self.first.size()
+    }
+
+    /// Check if this is a load pair
+    pub fn is_load(&self) -> bool {
+        self.first.is_load()
+    }
+
+    /// Address of the first element, given the base register's value
+    pub fn address(&self, base: u64) -> u64 {
+        match self.index {
+            PairIndex::PostIndex => base,
+            _ => base.wrapping_add(self.offset as u64),
+        }
+    }
+
+    /// Base register value after the access, if this form writes back
+    pub fn writeback(&self, base: u64) -> Option<u64> {
+        match self.index {
+            PairIndex::Offset => None,
+            _ => Some(base.wrapping_add(self.offset as u64)),
+        }
+    }
+
+    /// IPA of the first element, given the faulting IPA `addr`, the
+    /// faulting VA `far` and the base register's value.
+    ///
+    /// FAR names whichever element aborted; an STP whose first element is
+    /// ordinary memory faults on the second. `None` if `far` is neither.
+    pub fn first_ipa(&self, addr: u64, far: u64, base: u64) -> Option<u64> {
+        let va = self.address(base);
+        let size = self.size() as u64;
+        if far == va {
+            Some(addr)
+        } else if far == va.wrapping_add(size) {
+            Some(addr.wrapping_sub(size))
+        } else {
+            None
+        }
+    }
+}
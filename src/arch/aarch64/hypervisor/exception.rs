@@ -6,8 +6,9 @@
 use crate::arch::aarch64::defs::*;
 use crate::arch::aarch64::regs::VcpuContext;
 use crate::uart_put_hex;
+use crate::uart_put_u64;
 use crate::uart_puts;
-use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use core::sync::atomic::{AtomicPtr, AtomicU32, AtomicU64, Ordering};
 
 // External assembly functions defined in exception.S
 extern "C" {
@@ -111,11 +112,179 @@ fn reset_exception_count() {
     }
 }
 
+/// Current consecutive-exception count (this pCPU in multi-pCPU mode).
+pub fn consecutive_exception_count() -> u32 {
+    #[cfg(not(feature = "multi_pcpu"))]
+    {
+        EXCEPTION_COUNT.load(Ordering::Relaxed)
+    }
+    #[cfg(feature = "multi_pcpu")]
+    unsafe {
+        (*crate::percpu::this_cpu()).exception_count
+    }
+}
+
+/// Stop the current VM after an exception storm, leaving other VMs running.
+///
+/// Marks the current vCPU's terminal exit and the VM-wide `vm_terminated`
+/// flag (`run_one_iteration()` then retires the remaining vCPUs), reports
+/// the VM as crashed on the lifecycle channel, and resets the counter so
+/// the next VM starts clean.
+pub fn terminate_current_vm() {
+    let vs = crate::global::current_vm_state();
+    let vcpu_id = crate::global::current_vcpu_id();
+    vs.terminal_exit[vcpu_id].store(true, Ordering::Release);
+    vs.vm_terminated.store(true, Ordering::Release);
+    crate::pcpu_pin::release_vm(crate::global::current_vm_id());
+    crate::global::LIFECYCLE.push(
+        crate::global::current_vm_id(),
+        crate::global::LifecycleState::Crashed,
+    );
+    reset_exception_count();
+}
+
 /// Reset all exception counters (call before entering a new guest)
 pub fn reset_exception_counters() {
     reset_exception_count();
-    WFI_CONSECUTIVE_COUNT.store(0, Ordering::Relaxed);
-    LAST_WFI_PC.store(0, Ordering::Relaxed);
+    for idle in WFI_IDLE.iter().flatten() {
+        idle.clear();
+    }
+}
+
+// Re-entrancy guard: set while handle_exception() is running.
+// Current-EL synchronous exceptions share the same vector path, so a fault
+// taken inside the handler (bad pointer in a debug dump, unmapped MMIO, ...)
+// re-enters handle_exception(). exception.S has by then saved the inner
+// fault's GP registers into the TPIDR_EL2 context, so the outer exception
+// cannot be resumed; the guard turns the recursion into a report of both
+// faults and a halt.
+#[cfg(not(feature = "multi_pcpu"))]
+static IN_EXCEPTION: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+#[cfg(not(feature = "multi_pcpu"))]
+static OUTER_ESR: AtomicU64 = AtomicU64::new(0);
+#[cfg(not(feature = "multi_pcpu"))]
+static OUTER_FAR: AtomicU64 = AtomicU64::new(0);
+
+/// Mark entry into the EL2 exception handler.
+///
+/// Returns `Err((outer_esr, outer_far))` if the handler is already running
+/// on this CPU, i.e. this is a nested EL2 fault.
+pub fn enter_exception_guard(esr: u64, far: u64) -> Result<(), (u64, u64)> {
+    #[cfg(not(feature = "multi_pcpu"))]
+    {
+        if IN_EXCEPTION.swap(true, Ordering::Acquire) {
+            return Err((
+                OUTER_ESR.load(Ordering::Relaxed),
+                OUTER_FAR.load(Ordering::Relaxed),
+            ));
+        }
+        OUTER_ESR.store(esr, Ordering::Relaxed);
+        OUTER_FAR.store(far, Ordering::Relaxed);
+        Ok(())
+    }
+    #[cfg(feature = "multi_pcpu")]
+    unsafe {
+        let percpu = crate::percpu::this_cpu();
+        if (*percpu).in_exception {
+            return Err(((*percpu).outer_esr, (*percpu).outer_far));
+        }
+        (*percpu).in_exception = true;
+        (*percpu).outer_esr = esr;
+        (*percpu).outer_far = far;
+        Ok(())
+    }
+}
+
+/// Mark exit from the EL2 exception handler.
+pub fn exit_exception_guard() {
+    #[cfg(not(feature = "multi_pcpu"))]
+    {
+        IN_EXCEPTION.store(false, Ordering::Release);
+    }
+    #[cfg(feature = "multi_pcpu")]
+    unsafe {
+        (*crate::percpu::this_cpu()).in_exception = false;
+    }
+}
+
+/// Print a concise report for a fault taken while handling another fault.
+pub fn report_nested_fault(outer: (u64, u64), esr: u64, far: u64) {
+    uart_puts(b"\n[FATAL] nested EL2 fault\n");
+    uart_puts(b"  outer ESR_EL2=0x");
+    uart_put_hex(outer.0);
+    uart_puts(b" FAR_EL2=0x");
+    uart_put_hex(outer.1);
+    uart_puts(b"\n  inner ESR_EL2=0x");
+    uart_put_hex(esr);
+    uart_puts(b" FAR_EL2=0x");
+    uart_put_hex(far);
+    uart_puts(b"\n");
+}
+
+// Early guest crash heuristic: a guest that faults while VBAR_EL1 is still
+// at its reset value (0) within its first few synchronous exits almost
+// certainly died in its entry path (head.S for Linux) before installing
+// exception vectors. Later crashes with vectors installed are reported as-is.
+
+/// Synchronous exits after which a VBAR_EL1==0 fault is no longer "early".
+pub const EARLY_CRASH_MAX_EXITS: u32 = 64;
+
+static EARLY_CRASH_CHECK: core::sync::atomic::AtomicBool =
+    core::sync::atomic::AtomicBool::new(true);
+
+/// Per-(VM, vCPU) count of synchronous exits since the vCPU was created.
+static GUEST_EXITS: [[AtomicU32; crate::global::MAX_VCPUS]; crate::global::MAX_VMS] =
+    [const { [const { AtomicU32::new(0) }; crate::global::MAX_VCPUS] }; crate::global::MAX_VMS];
+
+/// Enable or disable the early-crash diagnostic.
+pub fn set_early_crash_check(enabled: bool) {
+    EARLY_CRASH_CHECK.store(enabled, Ordering::Relaxed);
+}
+
+/// Reset a vCPU's exit count (call when the vCPU is (re)created).
+pub fn reset_guest_exit_count(vm_id: usize, vcpu_id: usize) {
+    if let Some(c) = GUEST_EXITS.get(vm_id).and_then(|v| v.get(vcpu_id)) {
+        c.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Count a synchronous exit for the current vCPU; returns the new total.
+fn count_guest_exit() -> u32 {
+    let vm_id = crate::global::current_vm_id();
+    let vcpu_id = crate::global::current_vcpu_id();
+    match GUEST_EXITS.get(vm_id).and_then(|v| v.get(vcpu_id)) {
+        Some(c) => c.fetch_add(1, Ordering::Relaxed).saturating_add(1),
+        None => u32::MAX,
+    }
+}
+
+/// Emit the early-crash diagnostic if `vbar_el1` is still 0 and the vCPU has
+/// taken at most `EARLY_CRASH_MAX_EXITS` exits. Returns whether it was emitted.
+pub fn check_early_crash(vbar_el1: u64, exits: u32) -> bool {
+    if !EARLY_CRASH_CHECK.load(Ordering::Relaxed) || vbar_el1 != 0 || exits > EARLY_CRASH_MAX_EXITS
+    {
+        return false;
+    }
+    uart_puts(b"[VCPU] *** guest has not installed exception vectors yet - likely crashed in head.S ***\n");
+    uart_puts(b"[VCPU]     (VBAR_EL1=0 after ");
+    uart_put_u64(exits as u64);
+    uart_puts(b" exits)\n");
+    true
+}
+
+/// Run the early-crash check for the current vCPU on a fatal abort.
+fn report_if_early_crash() {
+    let vbar_el1: u64;
+    unsafe {
+        core::arch::asm!("mrs {}, vbar_el1", out(reg) vbar_el1, options(nostack, nomem));
+    }
+    let vm_id = crate::global::current_vm_id();
+    let vcpu_id = crate::global::current_vcpu_id();
+    let exits = GUEST_EXITS
+        .get(vm_id)
+        .and_then(|v| v.get(vcpu_id))
+        .map_or(u32::MAX, |c| c.load(Ordering::Relaxed));
+    check_early_crash(vbar_el1, exits);
 }
 
 /// Exception handler called from assembly
@@ -134,7 +303,6 @@ pub extern "C" fn handle_exception(context: &mut VcpuContext) -> bool {
             options(nostack, nomem),
         );
     }
-    context.sys_regs.esr_el2 = esr;
 
     // Read FAR_EL2 for fault address
     let far: u64;
@@ -145,12 +313,33 @@ pub extern "C" fn handle_exception(context: &mut VcpuContext) -> bool {
             options(nostack, nomem),
         );
     }
+
+    // Nested EL2 fault: the outer exception's context has already been
+    // overwritten, so report and halt deterministically.
+    if let Err(outer) = enter_exception_guard(esr, far) {
+        report_nested_fault(outer, esr, far);
+        loop {
+            unsafe {
+                core::arch::asm!("wfe");
+            }
+        }
+    }
+
+    context.sys_regs.esr_el2 = esr;
     context.sys_regs.far_el2 = far;
+    count_guest_exit();
 
+    let should_continue = dispatch_exception(context, esr, far);
+    exit_exception_guard();
+    should_continue
+}
+
+/// Dispatch a synchronous guest exit by exception class.
+fn dispatch_exception(context: &mut VcpuContext, esr: u64, far: u64) -> bool {
     // Check for exception loop
     let count = inc_exception_count();
     if count > MAX_CONSECUTIVE_EXCEPTIONS {
-        uart_puts(b"\n[FATAL] Too many consecutive exceptions, halting system\n");
+        uart_puts(b"\n[FATAL] Too many consecutive exceptions\n");
         uart_puts(b"[DEBUG] ESR_EL2=0x");
         uart_put_hex(esr);
         uart_puts(b" FAR_EL2=0x");
@@ -158,10 +347,23 @@ pub extern "C" fn handle_exception(context: &mut VcpuContext) -> bool {
         uart_puts(b" PC=0x");
         uart_put_hex(context.pc);
         uart_puts(b"\n");
-        // Halt the system completely to prevent further execution
-        loop {
-            unsafe {
-                core::arch::asm!("wfe");
+        // Multi-VM: only the offending VM dies, the others keep running
+        #[cfg(feature = "multi_vm")]
+        {
+            uart_puts(b"[FATAL] Terminating VM ");
+            uart_put_u64(crate::global::current_vm_id() as u64);
+            uart_puts(b"\n");
+            terminate_current_vm();
+            return false;
+        }
+        // Single VM: halt the system completely to prevent further execution
+        #[cfg(not(feature = "multi_vm"))]
+        {
+            uart_puts(b"[FATAL] Halting system\n");
+            loop {
+                unsafe {
+                    core::arch::asm!("wfe");
+                }
             }
         }
     }
@@ -254,6 +456,7 @@ pub extern "C" fn handle_exception(context: &mut VcpuContext) -> bool {
             uart_puts(b" PC=0x");
             uart_put_hex(context.pc);
             uart_puts(b"\n");
+            report_if_early_crash();
 
             // Read EL1 registers to understand what caused the ORIGINAL EL1 exception
             let elr_el1: u64;
@@ -447,6 +650,22 @@ pub extern "C" fn handle_exception(context: &mut VcpuContext) -> bool {
             let page_offset = context.sys_regs.far_el2 & 0xFFF;
             let addr = ipa_page | page_offset;
 
+            // Write to a page write-protected by dirty tracking: recorded
+            // and made writable again, re-execute the store
+            if crate::dirty_log::handle_write_fault(addr, esr) {
+                reset_exception_count();
+                return true;
+            }
+
+            // Strict unmapped-MMIO policy: the guest takes the abort at its
+            // own vector, PC already points there
+            if reflect_unmapped_mmio(context, addr, esr)
+                || reflect_unmapped_pair(context, addr, esr)
+            {
+                reset_exception_count();
+                return true;
+            }
+
             // Try to handle as MMIO
             if handle_mmio_abort(context, addr) {
                 // Reset exception counter on successful MMIO
@@ -467,13 +686,21 @@ pub extern "C" fn handle_exception(context: &mut VcpuContext) -> bool {
                 uart_puts(b" VA=0x");
                 uart_put_hex(context.sys_regs.far_el2);
                 uart_puts(b" (not MMIO)\n");
-                false // Exit
+                inject_unhandled_abort(context, esr)
             }
         }
 
         ExitReason::Other(ec) => {
             // Handle specific ECs that aren't fatal
             match ec {
+                EC_TRAPPED_SIMD_FP if context.fp_state != 0 => {
+                    // First FP/SIMD use since entry (CPTR_EL2.TFP): mark the
+                    // guest's FP state live so exception.S loads it before
+                    // ERET, then re-execute the instruction untrapped
+                    reset_exception_count();
+                    context.fp_live = 1;
+                    true
+                }
                 EC_TRAPPED_SIMD_FP => {
                     // Trapped SIMD/FP access - skip instruction
                     // (Should not happen after CPTR_EL2 fix)
@@ -508,6 +735,7 @@ pub extern "C" fn handle_exception(context: &mut VcpuContext) -> bool {
                     uart_puts(b" PC=0x");
                     uart_put_hex(context.pc);
                     uart_puts(b"\n");
+                    report_if_early_crash();
                     false // Exit
                 }
             }
@@ -519,6 +747,7 @@ pub extern "C" fn handle_exception(context: &mut VcpuContext) -> bool {
             uart_puts(b" PC=0x");
             uart_put_hex(context.pc);
             uart_puts(b"\n");
+            report_if_early_crash();
             false // Exit
         }
     }
@@ -536,16 +765,16 @@ pub extern "C" fn handle_exception(context: &mut VcpuContext) -> bool {
 
 #[no_mangle]
 pub extern "C" fn handle_irq_exception(_context: &mut VcpuContext) -> bool {
-    use crate::arch::aarch64::peripherals::gicv3::{
-        GicV3SystemRegs, GicV3VirtualInterface, VTIMER_IRQ,
-    };
+    use crate::arch::aarch64::peripherals::gic;
+    use crate::arch::aarch64::peripherals::gicv3::{PTIMER_IRQ, VTIMER_IRQ};
     use crate::arch::aarch64::peripherals::timer;
 
     // Reset sync exception counter (guest is making progress)
     reset_exception_count();
 
-    // Acknowledge the physical interrupt
-    let intid = GicV3SystemRegs::read_iar1();
+    // Acknowledge the physical interrupt on the driver picked at boot
+    let iar = gic::acknowledge();
+    let intid = gic::iar_intid(iar);
 
     // Check for spurious interrupt (INTID >= 1020)
     if intid >= GIC_SPURIOUS_INTID {
@@ -556,8 +785,8 @@ pub extern "C" fn handle_irq_exception(_context: &mut VcpuContext) -> bool {
     // ACK, set flag, exit to SPMC event loop which returns FFA_INTERRUPT.
     #[cfg(feature = "sel2")]
     {
-        GicV3SystemRegs::write_eoir1(intid);
-        GicV3SystemRegs::write_dir(intid);
+        gic::end_of_interrupt(iar);
+        gic::deactivate(iar);
         crate::spmc_handler::SP_IRQ_PREEMPTED.store(true, core::sync::atomic::Ordering::Release);
         return false;
     }
@@ -565,8 +794,8 @@ pub extern "C" fn handle_irq_exception(_context: &mut VcpuContext) -> bool {
     match intid {
         0..=15 => {
             // Physical SGI arrived.
-            GicV3SystemRegs::write_eoir1(intid);
-            GicV3SystemRegs::write_dir(intid);
+            gic::end_of_interrupt(iar);
+            gic::deactivate(iar);
 
             #[cfg(feature = "multi_pcpu")]
             {
@@ -581,7 +810,7 @@ pub extern "C" fn handle_irq_exception(_context: &mut VcpuContext) -> bool {
                 // Single-pCPU: physical SGI → inject into current vCPU.
                 let current_vcpu = crate::global::current_vcpu_id();
                 if current_vcpu == 0 {
-                    let _ = GicV3VirtualInterface::inject_interrupt(intid, IRQ_DEFAULT_PRIORITY);
+                    let _ = gic::inject_interrupt(intid, IRQ_DEFAULT_PRIORITY);
                 } else {
                     crate::global::current_vm_state().pending_sgis[0]
                         .fetch_or(1 << intid, Ordering::Relaxed);
@@ -599,16 +828,20 @@ pub extern "C" fn handle_irq_exception(_context: &mut VcpuContext) -> bool {
                 .vcpu_online_mask
                 .load(Ordering::Relaxed);
             let multi_vcpu = online != 0 && (online & (online - 1)) != 0;
-            if multi_vcpu {
+            let exclusive = crate::pcpu_pin::is_exclusive(
+                crate::global::current_vm_id(),
+                crate::pcpu_pin::pcpu_of_vcpu(crate::global::current_vcpu_id()),
+            );
+            if multi_vcpu && !exclusive {
                 crate::global::current_vm_state()
                     .preemption_exit
                     .store(true, Ordering::Release);
-                GicV3SystemRegs::write_eoir1(intid);
-                GicV3SystemRegs::write_dir(intid); // No HW linkage
+                gic::end_of_interrupt(iar);
+                gic::deactivate(iar); // No HW linkage
                 return false; // exit to host for scheduling
             }
-            GicV3SystemRegs::write_eoir1(intid);
-            GicV3SystemRegs::write_dir(intid);
+            gic::end_of_interrupt(iar);
+            gic::deactivate(iar);
             return true;
         }
         33 => {
@@ -638,8 +871,8 @@ pub extern "C" fn handle_irq_exception(_context: &mut VcpuContext) -> bool {
                 }
                 crate::global::UART_RX.push((data & 0xFF) as u8);
             }
-            GicV3SystemRegs::write_eoir1(intid);
-            GicV3SystemRegs::write_dir(intid);
+            gic::end_of_interrupt(iar);
+            gic::deactivate(iar);
             return false; // exit to host to deliver RX data to VirtualUart
         }
         27 => {
@@ -650,11 +883,8 @@ pub extern "C" fn handle_irq_exception(_context: &mut VcpuContext) -> bool {
             // Inject virtual interrupt to guest with HW=1.
             // HW=1 links virtual and physical interrupt: guest's virtual EOI
             // automatically deactivates the physical interrupt (pINTID=27).
-            let _inject_result = GicV3VirtualInterface::inject_hw_interrupt(
-                VTIMER_IRQ,
-                VTIMER_IRQ,
-                IRQ_DEFAULT_PRIORITY,
-            );
+            let _inject_result =
+                gic::inject_hw_interrupt(VTIMER_IRQ, VTIMER_IRQ, IRQ_DEFAULT_PRIORITY);
 
             // DO NOT modify SPSR_EL2 (guest's saved PSTATE).
 
@@ -685,35 +915,75 @@ pub extern "C" fn handle_irq_exception(_context: &mut VcpuContext) -> bool {
                         crate::global::current_vm_state()
                             .preemption_exit
                             .store(true, Ordering::Release);
-                        GicV3SystemRegs::write_eoir1(intid);
+                        gic::end_of_interrupt(iar);
                         return false; // exit to host
                     }
                 }
             }
 
             // EOImode=1: priority drop only
-            GicV3SystemRegs::write_eoir1(intid);
+            gic::end_of_interrupt(iar);
             // No DIR for HW=1 timer
             return true;
         }
+        30 => {
+            // Emulated guest physical timer (PPI 30): mask it to stop it
+            // firing and inject the virtual PPI; the guest re-arms it
+            timer::mask_guest_ptimer();
+            let _ = gic::inject_interrupt(PTIMER_IRQ, IRQ_DEFAULT_PRIORITY);
+            gic::end_of_interrupt(iar);
+            gic::deactivate(iar);
+            return true;
+        }
         _ => {
-            uart_puts(b"[IRQ] Unhandled INTID=");
-            uart_put_hex(intid as u64);
-            uart_puts(b"\n");
+            // Passthrough device interrupt assigned via Vm::assign_device()
+            if let Some((vm_id, virt_intid)) = crate::global::lookup_passthrough_irq(intid) {
+                if deliver_passthrough_irq(vm_id, virt_intid, intid) {
+                    gic::end_of_interrupt(iar); // priority drop only
+                    return true;
+                }
+                // Masked and queued for the owner: deactivate below
+            } else {
+                uart_puts(b"[IRQ] Unhandled INTID=");
+                uart_put_hex(intid as u64);
+                uart_puts(b"\n");
+            }
         }
     }
 
     // EOImode=1: EOIR only does priority drop (not deactivation).
-    GicV3SystemRegs::write_eoir1(intid);
+    gic::end_of_interrupt(iar);
 
     // For non-HW interrupts, explicitly deactivate
     if intid != 27 {
-        GicV3SystemRegs::write_dir(intid);
+        gic::deactivate(iar);
     }
 
     true // Continue guest
 }
 
+/// Deliver passthrough physical INTID `phys_intid` to VM `vm_id` as
+/// `virt_intid`.
+///
+/// Returns true if it was injected with HW=1 into the running VM: the
+/// guest's virtual EOI then deactivates the physical INTID. Otherwise (owner
+/// VM not running, or no free List Register) the physical INTID is masked
+/// and `virt_intid` queued for the owner's vCPU its IROUTER names; the
+/// caller must deactivate the physical INTID itself. The owner's next SPI
+/// flush injects it with HW=1 and unmasks it.
+pub fn deliver_passthrough_irq(vm_id: usize, virt_intid: u32, phys_intid: u32) -> bool {
+    use crate::arch::aarch64::peripherals::gic;
+
+    if vm_id == crate::global::current_vm_id()
+        && gic::inject_hw_interrupt(virt_intid, phys_intid, IRQ_DEFAULT_PRIORITY).is_ok()
+    {
+        return true;
+    }
+    crate::global::mask_passthrough_irq(phys_intid);
+    crate::global::inject_spi(vm_id, virt_intid);
+    false
+}
+
 /// Handle MSR/MRS trap (EC=0x18)
 ///
 /// Decodes the ISS to identify the trapped system register and emulates
@@ -721,7 +991,7 @@ pub extern "C" fn handle_irq_exception(_context: &mut VcpuContext) -> bool {
 ///
 /// ISS encoding (from KVM/ARM):
 ///   [21:20] Op0, [19:17] Op2, [16:14] Op1, [13:10] CRn, [9:5] Rt, [4:1] CRm, [0] Direction
-fn handle_msr_mrs_trap(context: &mut VcpuContext, esr: u64) {
+pub fn handle_msr_mrs_trap(context: &mut VcpuContext, esr: u64) {
     let iss = (esr & ESR_ISS_MASK) as u32;
     let op0 = (iss >> 20) & 0x3;
     let op2 = (iss >> 17) & 0x7;
@@ -731,6 +1001,27 @@ fn handle_msr_mrs_trap(context: &mut VcpuContext, esr: u64) {
     let crm = (iss >> 1) & 0xF;
     let is_read = (iss & 1) == 1;
 
+    // DISR_EL1 (S3_0_C12_C1_1) is backed by the vCPU's virtual deferred-error
+    // record; a read may defer a pending virtual SError, so it needs PSTATE.
+    if (op0, op1, crn, crm, op2) == (3, 0, 12, 1, 1) {
+        let vm_id = crate::global::current_vm_id();
+        let vcpu_id = crate::global::current_vcpu_id();
+        if is_read {
+            let value = super::serror::read_disr(vm_id, vcpu_id, context.spsr_el2);
+            if rt < 31 {
+                context.gp_regs.set_reg(rt, value);
+            }
+        } else {
+            let value = if rt < 31 {
+                context.gp_regs.get_reg(rt)
+            } else {
+                0
+            };
+            super::serror::write_disr(vm_id, vcpu_id, value);
+        }
+        return;
+    }
+
     if is_read {
         // MRS: Read system register, write value to Rt
         let value = emulate_mrs(op0, op1, crn, crm, op2);
@@ -753,6 +1044,8 @@ fn handle_msr_mrs_trap(context: &mut VcpuContext, esr: u64) {
 ///
 /// Returns the value that should be placed in the destination register.
 fn emulate_mrs(op0: u32, op1: u32, crn: u32, crm: u32, op2: u32) -> u64 {
+    use crate::arch::aarch64::peripherals::timer;
+
     match (op0, op1, crn, crm, op2) {
         // Debug registers (Op0=2) - return safe defaults
         (2, 0, 0, 2, 2) => {
@@ -771,6 +1064,30 @@ fn emulate_mrs(op0: u32, op1: u32, crn: u32, crm: u32, op2: u32) -> u64 {
             // OSDLR_EL1 - OS Double Lock Register (report unlocked)
             0
         }
+        (3, 0, 12, 12, 5) => {
+            // ICC_SRE_EL1 - GICv3-only: sysreg interface always on, bypass
+            // disabled (trapped via ICC_SRE_EL2.Enable=0)
+            (ICC_SRE_SRE | ICC_SRE_DFB | ICC_SRE_DIB) as u64
+        }
+        (3, 0, 14, 1, 0) => {
+            // CNTKCTL_EL1 - live value is the current vCPU's (restored on entry)
+            unsafe {
+                let val: u64;
+                core::arch::asm!("mrs {}, cntkctl_el1", out(reg) val);
+                val
+            }
+        }
+        // CNTP_TVAL/CTL/CVAL_EL0 - trapped by CNTHCTL_EL2.EL1PCEN=0; the
+        // hardware physical timer holds the current vCPU's (restored on entry)
+        (3, 3, 14, 2, 0) => timer::get_ptimer_tval() as u64,
+        (3, 3, 14, 2, 1) => timer::get_ptimer_ctl(),
+        (3, 3, 14, 2, 2) => timer::get_ptimer_cval(),
+        // ID group 3 (CRn=0, CRm=1-7) - trapped by HCR_EL2.TID3 while the VM
+        // overrides an ID field: hardware value with the overrides applied
+        (3, 0, 0, 1..=7, _) => super::id_regs::read(
+            crate::global::current_vm_id(),
+            super::id_regs::IdReg::new(crm as u8, op2 as u8),
+        ),
         // PMU registers (Op0=3, Op1=3, CRn=9) - return 0 (no PMU)
         (3, 3, 9, _, _) => 0,
         // PMU registers (Op0=3, Op1=0, CRn=9) - return 0
@@ -784,12 +1101,18 @@ fn emulate_mrs(op0: u32, op1: u32, crn: u32, crm: u32, op2: u32) -> u64 {
 ///
 /// Writes the value to the system register if we know how, otherwise ignores.
 fn emulate_msr(op0: u32, op1: u32, crn: u32, crm: u32, op2: u32, value: u64) {
+    use crate::arch::aarch64::peripherals::timer;
+
     match (op0, op1, crn, crm, op2) {
         // ICC_SGI1R_EL1 (S3_0_C12_C11_5) — Software Generated Interrupt
         // Trapped by ICH_HCR_EL2.TALL1. Decode target vCPUs and queue SGIs.
         (3, 0, 12, 11, 5) => {
             handle_sgi_trap(value);
         }
+        // ICC_EOIR1_EL1 (S3_0_C12_C12_1) / ICC_DIR_EL1 (S3_0_C12_C11_1) —
+        // a deactivation point: emulate on the LRs, re-check SPI lines
+        (3, 0, 12, 12, 1) => handle_eoi_trap(value as u32, false),
+        (3, 0, 12, 11, 1) => handle_eoi_trap(value as u32, true),
         // Debug registers
         (2, 0, 0, 2, 2) => {
             // MDSCR_EL1 - Debug Status and Control
@@ -807,6 +1130,23 @@ fn emulate_msr(op0: u32, op1: u32, crn: u32, crm: u32, op2: u32, value: u64) {
             // OSDLR_EL1 - OS Double Lock
             // Ignore (don't actually lock)
         }
+        (3, 0, 12, 12, 5) => {
+            // ICC_SRE_EL1 - SRE/DFB/DIB are RAO/WI: a guest can't fall back
+            // to the memory-mapped GICC interface, which we don't emulate
+        }
+        (3, 0, 14, 1, 0) => {
+            // CNTKCTL_EL1 - write the hardware register; VcpuArchState::save()
+            // captures it on exit so the EL0 timer-access config follows the vCPU
+            let value = value & CNTKCTL_VALID_MASK;
+            unsafe {
+                core::arch::asm!("msr cntkctl_el1, {}", "isb", in(reg) value);
+            }
+        }
+        // CNTP_TVAL/CTL/CVAL_EL0 - emulated on the hardware physical timer;
+        // VcpuArchState::save() captures it on exit. ISTATUS is read-only.
+        (3, 3, 14, 2, 0) => timer::set_ptimer_tval(value as u32),
+        (3, 3, 14, 2, 1) => timer::set_ptimer_ctl(value & timer::TIMER_CTL_WRITABLE),
+        (3, 3, 14, 2, 2) => timer::set_ptimer_cval(value),
         // PMU registers - ignore writes
         (3, 3, 9, _, _) | (3, 0, 9, _, _) => {}
         // Any other trapped register: Write-Ignored
@@ -814,28 +1154,63 @@ fn emulate_msr(op0: u32, op1: u32, crn: u32, crm: u32, op2: u32, value: u64) {
     }
 }
 
+/// ICH_VMCR_EL2.VEOIM: the guest split priority drop and deactivation
+const ICH_VMCR_VEOIM: u32 = 1 << 9;
+
+/// Handle a trapped ICC_EOIR1_EL1 (`is_dir` false) or ICC_DIR_EL1 write
+/// for the running vCPU.
+///
+/// EOIR drops the running priority (the lowest set ICH_AP1R0_EL2 bit)
+/// and, unless the guest set ICH_VMCR_EL2.VEOIM, deactivates `intid`;
+/// DIR only deactivates. Deactivating clears the Active state in the LR
+/// holding `intid` and hands the SPI to `global::spi_deactivated`, which
+/// queues it again if it is level-triggered and its line is still high.
+pub fn handle_eoi_trap(intid: u32, is_dir: bool) {
+    use crate::arch::aarch64::peripherals::gicv3::GicV3VirtualInterface;
+
+    let intid = intid & 0xFF_FFFF;
+    if !is_dir {
+        let ap = GicV3VirtualInterface::read_ap1r0();
+        GicV3VirtualInterface::write_ap1r0(ap & ap.wrapping_sub(1));
+        if GicV3VirtualInterface::read_vmcr() & ICH_VMCR_VEOIM != 0 {
+            return;
+        }
+    }
+    GicV3VirtualInterface::deactivate_interrupt(intid);
+    crate::global::spi_deactivated(crate::global::current_vm_id(), intid);
+}
+
 /// Handle trapped ICC_SGI1R_EL1 write (MSR trap via TALL1)
 ///
 /// Decodes the SGI target affinity and INTID from the value the guest
 /// intended to write, then queues SGIs in PENDING_SGIS for injection
-/// on vCPU entry.
+/// on vCPU entry and wakes the pCPUs of remote targets.
 ///
 /// ICC_SGI1R_EL1 encoding:
 ///   [55:48] Aff3, [47:44] RS, [40] IRM, [39:32] Aff2,
-///   [27:24] Aff1, [23:16] TargetList, [3:0] INTID
-fn handle_sgi_trap(value: u64) {
-    use crate::arch::aarch64::peripherals::gicv3::GicV3VirtualInterface;
+///   [27:24] INTID, [23:16] Aff1, [15:0] TargetList
+///
+/// With IRM=0, TargetList bit N names the PE at Aff3.Aff2.Aff1.(RS * 16 + N),
+/// mapped to a vCPU through the VM's MPIDR layout (`global::vcpu_at_affinity`);
+/// bits naming no vCPU are ignored.
+pub fn handle_sgi_trap(value: u64) {
+    use crate::arch::aarch64::peripherals::gic;
 
     // ICC_SGI1R_EL1 encoding (from ARM GICv3 spec):
     //   [55:48] Aff3, [47:44] RS, [40] IRM, [39:32] Aff2,
     //   [27:24] INTID, [23:16] Aff1, [15:0] TargetList
     let target_list = (value & 0xFFFF) as u32; // bits [15:0]
+    let aff1 = (value >> 16) & 0xFF; // bits [23:16]
     let intid = ((value >> 24) & 0xF) as u32; // bits [27:24]
+    let aff2 = (value >> 32) & 0xFF; // bits [39:32]
     let irm = (value >> 40) & 1; // bit [40]
+    let rs = (value >> 44) & 0xF; // bits [47:44]
+    let aff3 = (value >> 48) & 0xFF; // bits [55:48]
     let current_vcpu = crate::global::current_vcpu_id();
+    let vm_id = crate::global::current_vm_id();
 
-    #[allow(unused_mut)]
-    let mut _remote_queued = false;
+    // Remote vCPUs that had the SGI queued (bit N = vCPU N)
+    let mut remote_targets: u16 = 0;
 
     if irm == 1 {
         // IRM=1: target all PEs except self
@@ -846,57 +1221,89 @@ fn handle_sgi_trap(value: u64) {
             if id != current_vcpu && online & (1 << id) != 0 {
                 crate::global::current_vm_state().pending_sgis[id]
                     .fetch_or(1 << intid, Ordering::Release);
-                _remote_queued = true;
+                remote_targets |= 1 << id;
             }
         }
     } else {
         // IRM=0: target based on TargetList bitmap (bits [15:0]).
         // Bit N of TargetList = PE with Aff0 = (RS * 16) + N.
-        for bit in 0..crate::global::MAX_VCPUS {
+        for bit in 0..16 {
             if target_list & (1 << bit) == 0 {
                 continue;
             }
-            let target_vcpu = bit;
+            let affinity = (aff3 << 32) | (aff2 << 16) | (aff1 << 8) | (rs * 16 + bit);
+            let Some(target_vcpu) = crate::global::vcpu_at_affinity(vm_id, affinity) else {
+                continue;
+            };
             if target_vcpu == current_vcpu {
                 // Self-targeting: inject directly into hardware LR
-                let _ = GicV3VirtualInterface::inject_interrupt(intid, IRQ_DEFAULT_PRIORITY);
+                let _ = gic::inject_interrupt(intid, IRQ_DEFAULT_PRIORITY);
             } else if target_vcpu < crate::global::MAX_VCPUS {
                 // Queue for target vCPU
                 crate::global::current_vm_state().pending_sgis[target_vcpu]
                     .fetch_or(1 << intid, Ordering::Release);
-                _remote_queued = true;
+                remote_targets |= 1 << target_vcpu;
             }
         }
     }
 
-    // Multi-pCPU: send physical SGI to wake remote pCPUs from WFI.
-    // The virtual SGI is already queued in PENDING_SGIS; the physical SGI
-    // just forces the target pCPU out of WFI so it can process the queue.
-    // We use SGI 0 as the wakeup IPI (INTID = 0, TargetList = bitmap).
-    #[cfg(feature = "multi_pcpu")]
-    if _remote_queued {
-        // Build target bitmap: all target vCPUs that are remote
-        let mut target_bitmap: u64 = 0;
-        if irm == 1 {
-            let online = crate::global::current_vm_state()
-                .vcpu_online_mask
-                .load(Ordering::Relaxed);
-            for id in 0..crate::global::MAX_VCPUS {
-                if id != current_vcpu && online & (1 << id) != 0 {
-                    target_bitmap |= 1 << id;
-                }
-            }
-        } else {
-            for bit in 0..crate::global::MAX_VCPUS {
-                if target_list & (1 << bit) != 0 && bit != current_vcpu {
-                    target_bitmap |= 1 << bit;
-                }
-            }
-        }
-        if target_bitmap != 0 {
-            send_physical_sgi(0, target_bitmap as u16);
-        }
+    wake_remote_vcpus(remote_targets);
+}
+
+/// Test hook called instead of sending the wake SGI (null = not installed)
+static SGI_WAKE_HOOK: AtomicPtr<fn(u16)> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Install (or with `None`, remove) a hook that receives the target bitmap
+/// of each wake IPI in place of the physical SGI.
+pub fn set_sgi_wake_hook(hook: Option<&'static fn(u16)>) {
+    let ptr = hook.map_or(core::ptr::null_mut(), |f| core::ptr::from_ref(f).cast_mut());
+    SGI_WAKE_HOOK.store(ptr, Ordering::Release);
+}
+
+/// Wake the pCPUs running the vCPUs in `targets` after their PENDING_SGIS
+/// bits were set.
+///
+/// The Release `fetch_or` only orders memory accesses, not the later
+/// ICC_SGI1R_EL1 write, so without a DSB the IPI can reach the target
+/// before the queued bit does: it would find its queue empty and go back
+/// to WFI. The DSB makes the bits visible in the inner shareable domain
+/// before the SGI is generated. Single-pCPU builds only have the fence.
+fn wake_remote_vcpus(targets: u16) {
+    if targets == 0 {
+        return;
+    }
+    unsafe {
+        core::arch::asm!("dsb ish", options(nostack, preserves_flags));
     }
+
+    // SAFETY: the hook is null or was stored from a `&'static fn(u16)`
+    if let Some(hook) = unsafe { SGI_WAKE_HOOK.load(Ordering::Acquire).as_ref() } {
+        hook(targets);
+    } else {
+        // Multi-pCPU: send physical SGI to wake remote pCPUs from WFI.
+        // The physical SGI just forces the target pCPU out of WFI so it can
+        // process the queue. We use SGI 0 as the wakeup IPI.
+        #[cfg(feature = "multi_pcpu")]
+        send_physical_sgi(0, targets);
+    }
+}
+
+/// Kick the vCPUs other than the current one that have interrupts queued
+/// in `queue` (bit N of entry V = INTID `N + base` for vCPU V) among the
+/// `unmasked` bits the guest just enabled in its GICD/GICR shadow.
+///
+/// `has_pending_irqs()` ignores disabled interrupts, so an idle vCPU with
+/// only those queued sleeps until this wakes it.
+pub fn wake_unmasked(queue: &[core::sync::atomic::AtomicU32], vcpus: u16, unmasked: u32) {
+    let current = crate::global::current_vcpu_id();
+    let targets = queue
+        .iter()
+        .enumerate()
+        .filter(|&(id, bits)| {
+            id != current && vcpus & (1 << id) != 0 && bits.load(Ordering::Acquire) & unmasked != 0
+        })
+        .fold(0u16, |targets, (id, _)| targets | 1 << id);
+    wake_remote_vcpus(targets);
 }
 
 /// Send a physical SGI (IPI) from EL2 to wake remote pCPUs.
@@ -904,6 +1311,7 @@ fn handle_sgi_trap(value: u64) {
 /// Writes ICC_SGI1R_EL1 at EL2 (not subject to TALL1 trap).
 /// INTID is placed in bits [27:24], TargetList in bits [15:0].
 /// Assumes all PEs are in Aff1=0, Aff2=0, Aff3=0, RS=0.
+/// Not `nomem`: the compiler must not hoist the write above the queue stores.
 #[cfg(feature = "multi_pcpu")]
 fn send_physical_sgi(intid: u32, target_list: u16) {
     let val: u64 = ((intid as u64 & 0xF) << 24) | (target_list as u64);
@@ -912,7 +1320,7 @@ fn send_physical_sgi(intid: u32, target_list: u16) {
             "msr icc_sgi1r_el1, {val}",
             "isb",
             val = in(reg) val,
-            options(nostack, nomem),
+            options(nostack),
         );
     }
 }
@@ -929,10 +1337,17 @@ const PSCI_MIGRATE_INFO_TYPE: u64 = 0x84000006;
 const PSCI_SYSTEM_OFF: u64 = 0x84000008;
 const PSCI_SYSTEM_RESET: u64 = 0x84000009;
 const PSCI_FEATURES: u64 = 0x8400000A;
+const PSCI_SYSTEM_SUSPEND_32: u64 = 0x8400000E;
+const PSCI_SYSTEM_SUSPEND_64: u64 = 0xC400000E;
 
 // PSCI return values
 const PSCI_SUCCESS: u64 = 0;
 const PSCI_NOT_SUPPORTED: u64 = 0xFFFFFFFF; // -1 as unsigned
+const PSCI_INVALID_PARAMETERS: u64 = 0xFFFFFFFE; // -2 as unsigned
+#[cfg(not(feature = "multi_pcpu"))]
+const PSCI_DENIED: u64 = 0xFFFFFFFD; // -3 as unsigned
+#[cfg(not(feature = "multi_pcpu"))]
+const PSCI_INVALID_ADDRESS: u64 = 0xFFFFFFF7; // -9 as unsigned
 
 // PSCI version: v0.2
 const PSCI_VERSION_0_2: u64 = 0x00000002;
@@ -943,13 +1358,18 @@ const JAILHOUSE_HVC_IMMEDIATE: u32 = 0x4a48;
 const JAILHOUSE_HC_DEBUG_CONSOLE_PUTC: u64 = 8;
 const JAILHOUSE_HC_DEBUG_CONSOLE_GETC: u64 = 9;
 
+/// Hypercall number (x0): returns the VM's reboot count in x0.
+pub const HC_REBOOT_COUNT: u64 = 13;
+
 /// Handle hypercalls from guest
 ///
 /// Supports:
-/// - Custom hypercalls (x0 = 0, 1, ...)
+/// - Custom hypercalls (x0 = 0, 1, 9/10 = console ring register/doorbell,
+///   12 = map shared buffer, 13 = reboot count, 14 = DMA cache maintenance,
+///   15 = exclusive pCPU)
 /// - PSCI standard calls (x0 has bit 31 set)
 /// - Jailhouse debug console (HVC #0x4a48)
-fn handle_hypercall_with_imm(context: &mut VcpuContext, hvc_imm: u32) -> bool {
+pub fn handle_hypercall_with_imm(context: &mut VcpuContext, hvc_imm: u32) -> bool {
     // Check for Jailhouse debug console hypercall
     if hvc_imm == JAILHOUSE_HVC_IMMEDIATE {
         return handle_jailhouse_debug_console(context);
@@ -979,6 +1399,46 @@ fn handle_hypercall_with_imm(context: &mut VcpuContext, hvc_imm: u32) -> bool {
             false // Exit - guest wants to terminate
         }
 
+        crate::pv_console::HC_CONSOLE_REGISTER => {
+            // Hypercall 9: register the VM's console ring at IPA x1
+            crate::pv_console::handle_register_hypercall(context);
+            true
+        }
+
+        crate::pv_console::HC_CONSOLE_DOORBELL => {
+            // Hypercall 10: drain the console ring to the VM's UART
+            crate::pv_console::handle_doorbell_hypercall(context);
+            true
+        }
+
+        crate::shared_buffer::HC_MAP_SHARED_BUFFER => {
+            // Hypercall 12: map the VM's shared buffer page at IPA x1
+            let walker = crate::ffa::stage2_walker::Stage2Walker::from_vttbr();
+            crate::shared_buffer::handle_map_hypercall(context, &walker);
+            true
+        }
+
+        crate::cache_maint::HC_CACHE_MAINT => {
+            // Hypercall 14: clean/invalidate IPA range x1..x1+x2 for DMA (x3)
+            let walker = crate::ffa::stage2_walker::Stage2Walker::from_vttbr();
+            crate::cache_maint::handle_cache_maint_hypercall(context, &walker);
+            true
+        }
+
+        crate::pcpu_pin::HC_EXCLUSIVE_PCPU => {
+            // Hypercall 15: claim (x1=1) or release (x1=0) the caller's pCPU
+            crate::pcpu_pin::handle_exclusive_hypercall(context);
+            true
+        }
+
+        HC_REBOOT_COUNT => {
+            // Hypercall 13: how many times this VM has rebooted
+            context.gp_regs.x0 = crate::global::current_vm_state()
+                .reboot_count
+                .load(Ordering::Relaxed) as u64;
+            true
+        }
+
         _ => {
             // Unknown hypercall
             uart_puts(b"\n[VCPU] Unknown hypercall: 0x");
@@ -1101,6 +1561,8 @@ fn is_psci_function(fid: u64) -> bool {
             | PSCI_SYSTEM_OFF
             | PSCI_SYSTEM_RESET
             | PSCI_FEATURES
+            | PSCI_SYSTEM_SUSPEND_32
+            | PSCI_SYSTEM_SUSPEND_64
     )
 }
 
@@ -1114,7 +1576,7 @@ fn is_ffa_function(fid: u64) -> bool {
 /// Handle PSCI (Power State Coordination Interface) calls
 ///
 /// Implements PSCI v0.2 for guest power management.
-fn handle_psci(context: &mut VcpuContext, function_id: u64) -> bool {
+pub fn handle_psci(context: &mut VcpuContext, function_id: u64) -> bool {
     match function_id {
         PSCI_VERSION => {
             // Return PSCI v0.2
@@ -1131,6 +1593,8 @@ fn handle_psci(context: &mut VcpuContext, function_id: u64) -> bool {
                 | PSCI_FEATURES => PSCI_SUCCESS,
                 PSCI_CPU_ON_32 | PSCI_CPU_ON_64 => PSCI_SUCCESS,
                 PSCI_AFFINITY_INFO_32 | PSCI_AFFINITY_INFO_64 => PSCI_SUCCESS,
+                #[cfg(not(feature = "multi_pcpu"))]
+                PSCI_SYSTEM_SUSPEND_32 | PSCI_SYSTEM_SUSPEND_64 => PSCI_SUCCESS,
                 _ => PSCI_NOT_SUPPORTED,
             };
             context.gp_regs.x0 = result;
@@ -1138,11 +1602,20 @@ fn handle_psci(context: &mut VcpuContext, function_id: u64) -> bool {
         }
 
         PSCI_CPU_OFF => {
-            // CPU off - for single vCPU, this is like system halt
+            // CPU off - only the calling vCPU goes offline. The VM keeps
+            // running on its other vCPUs and is done once none is online.
             uart_puts(b"[PSCI] CPU_OFF\n");
             context.gp_regs.x0 = PSCI_SUCCESS;
             let vcpu_id = crate::global::current_vcpu_id();
-            crate::global::current_vm_state().terminal_exit[vcpu_id].store(true, Ordering::Release);
+            let vs = crate::global::current_vm_state();
+            // Offline right away: AFFINITY_INFO and IRM=1 SGIs skip it
+            vs.vcpu_online_mask
+                .fetch_and(!(1 << vcpu_id), Ordering::AcqRel);
+            vs.terminal_exit[vcpu_id].store(true, Ordering::Release);
+            crate::pcpu_pin::release(
+                crate::global::current_vm_id(),
+                crate::pcpu_pin::pcpu_of_vcpu(vcpu_id),
+            );
             false
         }
 
@@ -1157,23 +1630,32 @@ fn handle_psci(context: &mut VcpuContext, function_id: u64) -> bool {
             uart_put_hex(entry_point);
             uart_puts(b"\n");
 
+            // Full MPIDR affinity -> linear vCPU ID; no vCPU has that MPIDR
+            let Some(target_id) =
+                crate::global::vcpu_at_affinity(crate::global::current_vm_id(), target_cpu)
+            else {
+                context.gp_regs.x0 = PSCI_INVALID_PARAMETERS;
+                return true;
+            };
+            // The target's pCPU is gone or held by an exclusive vCPU
+            if !crate::pcpu_pin::cpu_on_candidate(crate::global::current_vm_id(), target_id) {
+                context.gp_regs.x0 = PSCI_INVALID_PARAMETERS;
+                return true;
+            }
+
             #[cfg(not(feature = "multi_pcpu"))]
             {
                 crate::global::current_vm_state().pending_cpu_on.request(
-                    target_cpu,
+                    target_id as u64,
                     entry_point,
                     context_id,
                 );
             }
             #[cfg(feature = "multi_pcpu")]
             {
-                let target_id = (target_cpu & 0xFF) as usize;
-                if target_id < crate::platform::num_cpus() {
-                    crate::global::PENDING_CPU_ON_PER_VCPU[target_id]
-                        .request(entry_point, context_id);
-                    // Wake the target pCPU from WFE
-                    unsafe { core::arch::asm!("sev") };
-                }
+                crate::global::PENDING_CPU_ON_PER_VCPU[target_id].request(entry_point, context_id);
+                // Wake the target pCPU from WFE
+                unsafe { core::arch::asm!("sev") };
             }
             context.gp_regs.x0 = PSCI_SUCCESS;
             // Exit to host so run_smp() can pick up the request and boot the vCPU
@@ -1183,11 +1665,12 @@ fn handle_psci(context: &mut VcpuContext, function_id: u64) -> bool {
         PSCI_AFFINITY_INFO_32 | PSCI_AFFINITY_INFO_64 => {
             // Return affinity state: 0 = ON, 1 = OFF, 2 = ON_PENDING
             let target_affinity = context.gp_regs.x1;
-            let vcpu_id = target_affinity & 0xFF;
+            let vcpu_id =
+                crate::global::vcpu_at_affinity(crate::global::current_vm_id(), target_affinity);
             let online_mask = crate::global::current_vm_state()
                 .vcpu_online_mask
                 .load(Ordering::Acquire);
-            if online_mask & (1 << vcpu_id) != 0 {
+            if vcpu_id.is_some_and(|id| online_mask & (1 << id) != 0) {
                 context.gp_regs.x0 = 0; // ON
             } else {
                 context.gp_regs.x0 = 1; // OFF
@@ -1206,6 +1689,11 @@ fn handle_psci(context: &mut VcpuContext, function_id: u64) -> bool {
             uart_puts(b"[PSCI] SYSTEM_OFF\n");
             let vcpu_id = crate::global::current_vcpu_id();
             crate::global::current_vm_state().terminal_exit[vcpu_id].store(true, Ordering::Release);
+            crate::pcpu_pin::release_vm(crate::global::current_vm_id());
+            crate::global::LIFECYCLE.push(
+                crate::global::current_vm_id(),
+                crate::global::LifecycleState::ShutDown,
+            );
             false // Exit guest
         }
 
@@ -1213,7 +1701,10 @@ fn handle_psci(context: &mut VcpuContext, function_id: u64) -> bool {
             // System reset
             uart_puts(b"[PSCI] SYSTEM_RESET\n");
             let vcpu_id = crate::global::current_vcpu_id();
-            crate::global::current_vm_state().terminal_exit[vcpu_id].store(true, Ordering::Release);
+            let vs = crate::global::current_vm_state();
+            vs.reboot_count.fetch_add(1, Ordering::Relaxed);
+            vs.terminal_exit[vcpu_id].store(true, Ordering::Release);
+            crate::pcpu_pin::release_vm(crate::global::current_vm_id());
             false
         }
 
@@ -1224,6 +1715,8 @@ fn handle_psci(context: &mut VcpuContext, function_id: u64) -> bool {
             true
         }
 
+        PSCI_SYSTEM_SUSPEND_32 | PSCI_SYSTEM_SUSPEND_64 => handle_system_suspend(context),
+
         _ => {
             // Unknown PSCI function
             uart_puts(b"[PSCI] Unknown function: 0x");
@@ -1235,34 +1728,93 @@ fn handle_psci(context: &mut VcpuContext, function_id: u64) -> bool {
     }
 }
 
+/// PSCI SYSTEM_SUSPEND: suspend the whole VM until a wakeup source fires.
+///
+/// Only the last online vCPU may suspend the system. On success nothing is
+/// returned to the caller: the run loop later re-enters the vCPU at the
+/// entry point in x1 with the context ID from x2 in x0, as for CPU_ON.
+/// An RTC alarm armed at the time of the call is registered as the wakeup
+/// source.
+#[cfg(not(feature = "multi_pcpu"))]
+fn handle_system_suspend(context: &mut VcpuContext) -> bool {
+    let entry_point = context.gp_regs.x1;
+    let context_id = context.gp_regs.x2;
+    let vcpu_id = crate::global::current_vcpu_id();
+    let vs = crate::global::current_vm_state();
+
+    if vs.vcpu_online_mask.load(Ordering::Acquire) & !(1 << vcpu_id) != 0 {
+        uart_puts(b"[PSCI] SYSTEM_SUSPEND denied: other vCPUs online\n");
+        context.gp_regs.x0 = PSCI_DENIED;
+        return true;
+    }
+    if !is_fetchable_guest_pc(entry_point) {
+        context.gp_regs.x0 = PSCI_INVALID_ADDRESS;
+        return true;
+    }
+
+    let mut wake_sources = 0;
+    if crate::global::current_devices().rtc_alarm_armed() {
+        wake_sources |= crate::global::WAKE_SRC_RTC;
+    }
+    uart_puts(b"[PSCI] SYSTEM_SUSPEND entry=0x");
+    uart_put_hex(entry_point);
+    uart_puts(b" wake=0x");
+    uart_put_hex(wake_sources as u64);
+    uart_puts(b"\n");
+    vs.system_suspend
+        .suspend(vcpu_id, entry_point, context_id, wake_sources);
+    // Exit to host; the run loop holds the VM until it wakes
+    false
+}
+
+/// PSCI SYSTEM_SUSPEND is not supported with one pCPU per vCPU.
+#[cfg(feature = "multi_pcpu")]
+fn handle_system_suspend(context: &mut VcpuContext) -> bool {
+    context.gp_regs.x0 = PSCI_NOT_SUPPORTED;
+    true
+}
+
+/// Whether the faulting instruction at `pc` can be read from EL2:
+/// 4-byte aligned and inside the identity-mapped guest RAM window.
+/// Anything else (guest VA, MMIO, unbacked IPA) would fault at EL2.
+pub fn is_fetchable_guest_pc(pc: u64) -> bool {
+    use crate::platform::{GUEST_RAM_BASE, LINUX_MEM_SIZE};
+    pc & 0x3 == 0 && (GUEST_RAM_BASE..GUEST_RAM_BASE + LINUX_MEM_SIZE).contains(&pc)
+}
+
 /// Handle MMIO data abort
 ///
 /// # Returns
 /// * `true` if successfully handled
 /// * `false` if not MMIO or handling failed
 fn handle_mmio_abort(context: &mut VcpuContext, addr: u64) -> bool {
-    use crate::arch::aarch64::hypervisor::decode::MmioAccess;
+    use crate::arch::aarch64::hypervisor::decode::{MmioAccess, PairAccess};
 
     // Get ISS from ESR_EL2
     let iss = (context.sys_regs.esr_el2 & ESR_ISS_MASK) as u32;
     let isv = (iss >> 24) & 1;
 
     // Try ISS-based decode first (works even when guest MMU is on)
-    // Only read instruction from context.pc if ISV=0 AND pc is a plausible physical address
+    // Only read instruction from context.pc if ISV=0 AND pc lies in guest RAM
     // (when guest MMU is on, context.pc is a virtual address we can't read from EL2)
     let insn = if isv == 1 {
         0 // ISS decode doesn't need the instruction
-    } else if context.pc < 0x8000_0000_0000 {
-        // PC looks like a physical address, safe to read
+    } else if is_fetchable_guest_pc(context.pc) {
+        // SAFETY: pc is 4-byte aligned and inside identity-mapped guest RAM
         unsafe { core::ptr::read_volatile(context.pc as *const u32) }
     } else {
-        // PC is a virtual address (guest MMU is on), can't read instruction
-        uart_puts(b"[MMIO] Can't decode: guest VA PC=0x");
+        // PC is a guest VA (MMU on) or outside RAM, can't read instruction
+        uart_puts(b"[MMIO] Can't decode: unfetchable PC=0x");
         uart_put_hex(context.pc);
         uart_puts(b" ISV=0\n");
         return false;
     };
 
+    // LDP/STP: two element accesses (ISV is never set for pairs)
+    if let Some(pair) = PairAccess::decode(insn, iss) {
+        return handle_mmio_pair(context, addr, &pair);
+    }
+
     // Decode the instruction
     let access = match MmioAccess::decode(insn, iss) {
         Some(a) => a,
@@ -1274,6 +1826,10 @@ fn handle_mmio_abort(context: &mut VcpuContext, addr: u64) -> bool {
         }
     };
 
+    // Devices resolve their VM through CURRENT_VM_ID; it must name the VM
+    // that faulted
+    crate::global::debug_assert_current_vm();
+
     // Handle the MMIO access
     if access.is_store() {
         // Store: get value from source register
@@ -1284,7 +1840,8 @@ fn handle_mmio_abort(context: &mut VcpuContext, addr: u64) -> bool {
         // Load: get value from device and write to destination register
         match crate::global::current_devices().handle_mmio(addr, 0, access.size(), false) {
             Some(value) => {
-                context.gp_regs.set_reg(access.reg(), value);
+                // LDRSB/LDRSH/LDRSW sign-extend; Wt destinations clear bits 63:32
+                context.gp_regs.set_reg(access.reg(), access.extend(value));
                 true
             }
             None => {
@@ -1297,65 +1854,333 @@ fn handle_mmio_abort(context: &mut VcpuContext, addr: u64) -> bool {
     }
 }
 
-/// WFI counter - track consecutive WFIs to detect infinite loops
-static WFI_CONSECUTIVE_COUNT: AtomicU32 = AtomicU32::new(0);
-static LAST_WFI_PC: AtomicU64 = AtomicU64::new(0);
+/// Emulate an LDP/STP that aborted at `addr`: both elements in order,
+/// then base register writeback for the pre/post-index forms. A load only
+/// writes its registers once both element reads succeeded.
+///
+/// A pair may straddle guest RAM and a device: the RAM element is then
+/// performed on guest memory through the VM's Stage-2, only the device
+/// element goes through MMIO emulation.
+pub fn handle_mmio_pair(
+    context: &mut VcpuContext,
+    addr: u64,
+    pair: &crate::arch::aarch64::hypervisor::decode::PairAccess,
+) -> bool {
+    let base = context.get_base_reg(pair.base);
+    let Some(first) = pair.first_ipa(addr, context.sys_regs.far_el2, base) else {
+        uart_puts(b"[MMIO] LDP/STP address mismatch at PC=0x");
+        uart_put_hex(context.pc);
+        uart_puts(b"\n");
+        return false;
+    };
+    let size = pair.size();
+    let second = first + size as u64;
+
+    crate::global::debug_assert_current_vm();
+    let devices = crate::global::current_devices();
+    let element = |ipa: u64, value: u64, is_write: bool| {
+        if !devices.is_mapped(ipa) && is_guest_ram(ipa, size, is_write) {
+            guest_ram_access(ipa, value, size, is_write)
+        } else {
+            devices.handle_mmio(ipa, value, size, is_write)
+        }
+    };
+    if pair.is_load() {
+        let (Some(v1), Some(v2)) = (element(first, 0, false), element(second, 0, false)) else {
+            uart_puts(b"[MMIO] Pair read failed at 0x");
+            uart_put_hex(first);
+            uart_puts(b"\n");
+            return false;
+        };
+        context.set_gpr(pair.first.reg(), pair.first.extend(v1));
+        context.set_gpr(pair.second.reg(), pair.second.extend(v2));
+    } else {
+        let v1 = context.get_gpr(pair.first.reg());
+        let v2 = context.get_gpr(pair.second.reg());
+        element(first, v1, true);
+        element(second, v2, true);
+    }
+
+    if let Some(new_base) = pair.writeback(base) {
+        context.set_base_reg(pair.base, new_base);
+    }
+    true
+}
+
+/// Whether `[ipa, ipa + size)` is ordinary memory the current VM can
+/// access itself (writable if `is_write`) according to its Stage-2.
+fn is_guest_ram(ipa: u64, size: u8, is_write: bool) -> bool {
+    let dma = crate::devices::dma::DmaMapper::for_vm(crate::global::current_vm_id());
+    dma.translate(ipa, size as u64, is_write).is_some()
+}
+
+/// Perform one `size`-byte pair element on guest memory at `ipa`.
+/// Returns the loaded value (0 for a store), `None` if the access failed.
+fn guest_ram_access(ipa: u64, value: u64, size: u8, is_write: bool) -> Option<u64> {
+    let dma = crate::devices::dma::DmaMapper::for_vm(crate::global::current_vm_id());
+    let len = size as usize;
+    if is_write {
+        return dma.write(ipa, &value.to_le_bytes()[..len]).then_some(0);
+    }
+    let mut buf = [0u8; 8];
+    dma.read(ipa, &mut buf[..len])
+        .then(|| u64::from_le_bytes(buf))
+}
+
+/// Strict unmapped-MMIO policy for LDP/STP: if the element other than the
+/// faulting one is unmapped, abort the whole pair before either access,
+/// with FAR naming the unmapped element. An element in guest RAM is not
+/// unmapped; `handle_mmio_pair` performs it on guest memory.
+pub fn reflect_unmapped_pair(context: &mut VcpuContext, addr: u64, esr: u64) -> bool {
+    use crate::arch::aarch64::hypervisor::decode::PairAccess;
+
+    let iss = (esr & ESR_ISS_MASK) as u32;
+    if (iss >> 24) & 1 != 0 || !is_fetchable_guest_pc(context.pc) {
+        return false;
+    }
+    // SAFETY: pc is 4-byte aligned and inside identity-mapped guest RAM
+    let insn = unsafe { core::ptr::read_volatile(context.pc as *const u32) };
+    let Some(pair) = PairAccess::decode(insn, iss) else {
+        return false;
+    };
+    let base = context.get_base_reg(pair.base);
+    let far = context.sys_regs.far_el2;
+    let Some(first) = pair.first_ipa(addr, far, base) else {
+        return false;
+    };
+    let size = pair.size() as u64;
+    let other = if first == addr { addr + size } else { first };
+    if !crate::global::current_devices().unmapped_mmio_faults(other)
+        || is_guest_ram(other, pair.size(), !pair.is_load())
+    {
+        return false;
+    }
+    uart_puts(b"[MMIO] Unmapped pair element at 0x");
+    uart_put_hex(other);
+    uart_puts(b", injecting external abort\n");
+    let other_far = far.wrapping_add(other).wrapping_sub(addr);
+    inject_data_abort(context, other_far, !pair.is_load());
+    true
+}
+
+/// DFSC: synchronous external abort, not on translation table walk
+const DFSC_SYNC_EXTERNAL_ABORT: u64 = 0x10;
+/// ESR_ELx.IL: 32-bit instruction
+const ESR_IL: u64 = 1 << 25;
+/// ESR_ELx.WnR (data aborts)
+const ESR_WNR: u64 = 1 << 6;
+
+/// Inject a synchronous external data abort into the guest at EL1.
+///
+/// Emulates the exception entry the guest would have taken from a real bus
+/// error: ESR_EL1/FAR_EL1 describe the fault, ELR_EL1/SPSR_EL1 capture the
+/// faulting PC and PSTATE, and the guest resumes at its VBAR_EL1 sync vector
+/// with DAIF masked. The faulting instruction is not skipped.
+pub fn inject_data_abort(context: &mut VcpuContext, far: u64, is_write: bool) {
+    let vbar: u64;
+    unsafe {
+        core::arch::asm!("mrs {}, vbar_el1", out(reg) vbar, options(nostack, nomem));
+    }
+
+    // SPSR.M[3:0]: 0b0000 EL0t, 0b0100 EL1t, 0b0101 EL1h
+    let (ec, vector) = match context.spsr_el2 & 0xF {
+        0b0101 => (EC_DABT_SAME, 0x200), // current EL, SP_ELx
+        0b0100 => (EC_DABT_SAME, 0x000), // current EL, SP_EL0
+        _ => (EC_DABT_LOWER, 0x400),     // lower EL, AArch64
+    };
+    let wnr = if is_write { ESR_WNR } else { 0 };
+    let esr = (ec << ESR_EC_SHIFT) | ESR_IL | wnr | DFSC_SYNC_EXTERNAL_ABORT;
+
+    unsafe {
+        core::arch::asm!("msr esr_el1, {}", in(reg) esr, options(nostack, nomem));
+        core::arch::asm!("msr far_el1, {}", in(reg) far, options(nostack, nomem));
+    }
+    // ELR_EL1/SPSR_EL1 are restored from the context on guest re-entry
+    context.sys_regs.elr_el1 = context.pc;
+    context.sys_regs.spsr_el1 = context.spsr_el2;
+    context.spsr_el2 = SPSR_EL1H_DAIF_MASKED;
+    context.pc = vbar + vector;
+}
+
+/// Reflect an access to an unclaimed MMIO address back to the guest when
+/// the current VM uses `UnmappedMmioPolicy::Strict`.
+///
+/// Returns `true` if an external abort was injected (the caller must not
+/// advance the PC), `false` if the access should be emulated as usual.
+pub fn reflect_unmapped_mmio(context: &mut VcpuContext, addr: u64, esr: u64) -> bool {
+    if !crate::global::current_devices().unmapped_mmio_faults(addr) {
+        return false;
+    }
+    uart_puts(b"[MMIO] Unmapped access at 0x");
+    uart_put_hex(addr);
+    uart_puts(b", injecting external abort\n");
+    inject_data_abort(context, context.sys_regs.far_el2, esr & ESR_WNR != 0);
+    true
+}
+
+/// Data abort no device could emulate (unbacked IPA, or an access the MMIO
+/// decoder cannot handle).
+///
+/// A Linux guest probing absent hardware takes a synchronous external abort
+/// at its own vector and keeps running; the exception counter is left alone
+/// so a guest that faults again and again is still stopped. Other builds,
+/// and guests without a vector table yet, exit so the fault stays visible.
+///
+/// Returns `true` if the abort was injected and the guest continues.
+fn inject_unhandled_abort(context: &mut VcpuContext, esr: u64) -> bool {
+    let vbar: u64;
+    unsafe {
+        core::arch::asm!("mrs {}, vbar_el1", out(reg) vbar, options(nostack, nomem));
+    }
+    if !cfg!(feature = "linux_guest") || vbar == 0 {
+        report_if_early_crash();
+        return false;
+    }
+    uart_puts(b"[VCPU] Injecting external abort\n");
+    inject_data_abort(context, context.sys_regs.far_el2, esr & ESR_WNR != 0);
+    true
+}
+
+/// One vCPU's run of idle WFIs
+struct WfiIdle {
+    /// Consecutive WFIs at `last_pc` - to detect infinite loops
+    count: AtomicU32,
+    last_pc: AtomicU64,
+    /// Counter value when the current run of idle WFIs started
+    since: AtomicU64,
+}
+
+impl WfiIdle {
+    const fn new() -> Self {
+        Self {
+            count: AtomicU32::new(0),
+            last_pc: AtomicU64::new(0),
+            since: AtomicU64::new(0),
+        }
+    }
+
+    fn clear(&self) {
+        self.count.store(0, Ordering::Relaxed);
+        self.last_pc.store(0, Ordering::Relaxed);
+    }
+}
+
+/// WFI idle runs, per VM and vCPU
+static WFI_IDLE: [[WfiIdle; crate::global::MAX_VCPUS]; crate::global::MAX_VMS] =
+    [const { [const { WfiIdle::new() }; crate::global::MAX_VCPUS] }; crate::global::MAX_VMS];
+/// Idle WFIs at one PC before giving up on the guest, whatever the
+/// VM's `wfi_timeout_ns`
 const MAX_CONSECUTIVE_WFI: u32 = 500_000;
 
+/// The current vCPU's idle WFI run.
+fn wfi_idle() -> &'static WfiIdle {
+    &WFI_IDLE[crate::global::current_vm_id()][crate::global::current_vcpu_id()]
+}
+
+/// Start a new run of idle WFIs: the guest made progress.
+fn reset_wfi_idle() {
+    let idle = wfi_idle();
+    idle.count.store(0, Ordering::Relaxed);
+    idle.since
+        .store(crate::time::now_ticks(), Ordering::Relaxed);
+}
+
+/// True once the current VM's WFI timeout (if set) has elapsed since the
+/// current vCPU's idle run started.
+fn wfi_timed_out() -> bool {
+    let timeout_ns = crate::global::current_vm_state()
+        .wfi_timeout_ns
+        .load(Ordering::Relaxed);
+    if timeout_ns == 0 {
+        return false;
+    }
+    let idle = crate::time::now_ticks().wrapping_sub(wfi_idle().since.load(Ordering::Relaxed));
+    idle >= crate::time::ns_to_ticks(timeout_ns)
+}
+
 /// Handle WFI by checking and injecting virtual timer interrupt
 ///
 /// When guest executes WFI, it's waiting for an interrupt.
-/// We check if the virtual timer has fired and inject it via GICv3 List Registers.
+/// We check if the virtual or (emulated) physical timer has fired and inject
+/// it via GICv3 List Registers.
+///
+/// Synthetic VTIMER_IRQ ticks (first WFI at a new PC, every 100th WFI) are
+/// only injected while the guest's virtual timer is enabled and unmasked; a
+/// guest that turned its timer off waits for a real interrupt instead.
+///
+/// A guest that keeps idling at the same PC with nothing to inject is given
+/// up on after the VM's WFI timeout (`Vm::set_wfi_timeout_ns`), or after
+/// `MAX_CONSECUTIVE_WFI` WFIs if no timeout is set.
 ///
 /// # Returns
 /// * `true` - Guest should continue (interrupt injected)
 /// * `false` - Guest should exit (stuck in WFI loop)
-fn handle_wfi_with_timer_injection(context: &mut VcpuContext) -> bool {
-    use crate::arch::aarch64::peripherals::gicv3::{GicV3VirtualInterface, VTIMER_IRQ};
+pub fn handle_wfi_with_timer_injection(context: &mut VcpuContext) -> bool {
+    use crate::arch::aarch64::peripherals::gic;
+    use crate::arch::aarch64::peripherals::gicv3::{PTIMER_IRQ, VTIMER_IRQ};
     use crate::arch::aarch64::peripherals::timer;
 
     let pc = context.pc;
-    let last_pc = LAST_WFI_PC.load(Ordering::Relaxed);
+    let idle = wfi_idle();
+    let last_pc = idle.last_pc.load(Ordering::Relaxed);
+    let vtimer_armed = timer::is_guest_vtimer_armed();
 
     // Check if PC changed - that means guest is making progress
     if pc != last_pc {
-        WFI_CONSECUTIVE_COUNT.store(0, Ordering::Relaxed);
-        LAST_WFI_PC.store(pc, Ordering::Relaxed);
+        reset_wfi_idle();
+        idle.last_pc.store(pc, Ordering::Relaxed);
 
         // Inject an interrupt on first WFI at new location
-        let _ = GicV3VirtualInterface::inject_interrupt(VTIMER_IRQ, IRQ_DEFAULT_PRIORITY);
+        if vtimer_armed {
+            let _ = gic::inject_interrupt(VTIMER_IRQ, IRQ_DEFAULT_PRIORITY);
+        }
         // Don't modify SPSR_EL2 - respect guest's PSTATE.I
         return true;
     }
 
-    // Increment WFI counter
-    let count = WFI_CONSECUTIVE_COUNT.fetch_add(1, Ordering::Relaxed) + 1;
-    if count > MAX_CONSECUTIVE_WFI {
-        uart_puts(b"[WFI] Guest idle (");
-        uart_put_hex(count as u64);
-        uart_puts(b" WFIs at same PC), exiting\n");
-        return false;
-    }
+    // Pending work wakes the WFI before the idle limits are considered
 
     // Check if virtual timer is pending
     if timer::is_guest_vtimer_pending() {
-        WFI_CONSECUTIVE_COUNT.store(0, Ordering::Relaxed);
+        reset_wfi_idle();
         timer::mask_guest_vtimer();
-        let _ = GicV3VirtualInterface::inject_interrupt(VTIMER_IRQ, IRQ_DEFAULT_PRIORITY);
+        let _ = gic::inject_interrupt(VTIMER_IRQ, IRQ_DEFAULT_PRIORITY);
         // Don't modify SPSR_EL2 - respect guest's PSTATE.I
         return true;
     }
 
+    // Same for the emulated physical timer
+    if timer::is_guest_ptimer_pending() {
+        reset_wfi_idle();
+        timer::mask_guest_ptimer();
+        let _ = gic::inject_interrupt(PTIMER_IRQ, IRQ_DEFAULT_PRIORITY);
+        return true;
+    }
+
     // Check if any virtual interrupt is pending in List Registers
-    if GicV3VirtualInterface::pending_count() > 0 {
-        WFI_CONSECUTIVE_COUNT.store(0, Ordering::Relaxed);
+    if gic::pending_count() > 0 {
+        reset_wfi_idle();
         // Don't modify SPSR_EL2 - respect guest's PSTATE.I
         return true;
     }
 
+    // Increment WFI counter
+    let count = idle.count.fetch_add(1, Ordering::Relaxed) + 1;
+    if count > MAX_CONSECUTIVE_WFI {
+        uart_puts(b"[WFI] Guest idle (");
+        uart_put_hex(count as u64);
+        uart_puts(b" WFIs at same PC), exiting\n");
+        return false;
+    }
+    if wfi_timed_out() {
+        // Next WFI starts a fresh window once the scheduler comes back
+        reset_wfi_idle();
+        return false;
+    }
+
     // No interrupts pending - inject periodic tick to help guest make progress
-    if count % 100 == 0 {
-        let _ = GicV3VirtualInterface::inject_interrupt(VTIMER_IRQ, IRQ_DEFAULT_PRIORITY);
+    if vtimer_armed && count % 100 == 0 {
+        let _ = gic::inject_interrupt(VTIMER_IRQ, IRQ_DEFAULT_PRIORITY);
         // Don't modify SPSR_EL2 - respect guest's PSTATE.I
     }
 
@@ -1381,15 +2206,37 @@ fn flush_pending_spis_to_hardware() {
         return;
     }
 
+    let devices = crate::global::current_devices();
     for bit in 0..32u32 {
         if pending & (1 << bit) == 0 {
             continue;
         }
         let intid = bit + 32; // SPI INTIDs start at 32
-        if GicV3VirtualInterface::inject_interrupt(intid, IRQ_DEFAULT_PRIORITY).is_err() {
-            // No free LR — re-queue for later
+        let phys = crate::global::passthrough_phys_intid(crate::global::current_vm_id(), intid);
+
+        // Disabled in the guest's GICD, or no free LR — re-queue for later.
+        // A deferred passthrough SPI goes in with HW=1, as it would have
+        // had its owner been running.
+        if !devices.irq_enabled(vcpu_id, intid)
+            || match phys {
+                Some(p) => {
+                    GicV3VirtualInterface::inject_hw_interrupt(intid, p, IRQ_DEFAULT_PRIORITY)
+                }
+                None => GicV3VirtualInterface::inject_interrupt_in_group(
+                    intid,
+                    IRQ_DEFAULT_PRIORITY,
+                    devices.irq_group1(vcpu_id, intid),
+                ),
+            }
+            .is_err()
+        {
             crate::global::current_vm_state().pending_spis[vcpu_id]
                 .fetch_or(1 << bit, Ordering::Relaxed);
+        } else {
+            if let Some(p) = phys {
+                crate::global::unmask_passthrough_irq(p);
+            }
+            crate::global::record_spi_delivered(intid);
         }
     }
 }
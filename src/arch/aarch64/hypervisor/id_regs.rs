@@ -0,0 +1,217 @@
+//! Guest-visible ID register overrides.
+//!
+//! A VM can present ID register fields that differ from the hardware, e.g.
+//! ID_AA64ISAR0_EL1.Atomics = 0 so the guest falls back to its non-LSE
+//! code, or a specific MIDR_EL1 to trigger an erratum workaround. Each VM
+//! keeps a mask/value pair per register of the ID space (Op0=3, Op1=0,
+//! CRn=0):
+//!
+//! - ID group 3 registers (CRm=1-7) are trapped with HCR_EL2.TID3 while the
+//!   VM overrides any of them; `read()` backs the emulated MRS with the
+//!   hardware value patched by the overrides.
+//! - MIDR_EL1 cannot be trapped; `enter()` loads the patched value into
+//!   VPIDR_EL2, which guest reads of MIDR_EL1 return.
+
+use crate::global::MAX_VMS;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// HCR_EL2.TID3: trap ID group 3 register reads
+pub const HCR_TID3: u64 = 1 << 18;
+
+/// Registers in the ID space, indexed by CRm:Op2
+const ID_SPACE_REGS: usize = 64;
+
+/// An ID register, identified by its CRm and Op2 (Op0=3, Op1=0, CRn=0).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IdReg(u8);
+
+impl IdReg {
+    pub const fn new(crm: u8, op2: u8) -> Self {
+        Self(((crm & 0x7) << 3) | (op2 & 0x7))
+    }
+
+    /// Whether reads of this register trap with HCR_EL2.TID3.
+    fn is_trapped(self) -> bool {
+        self.0 >> 3 != 0
+    }
+}
+
+pub const MIDR_EL1: IdReg = IdReg::new(0, 0);
+pub const ID_AA64PFR0_EL1: IdReg = IdReg::new(4, 0);
+pub const ID_AA64PFR1_EL1: IdReg = IdReg::new(4, 1);
+pub const ID_AA64DFR0_EL1: IdReg = IdReg::new(5, 0);
+pub const ID_AA64ISAR0_EL1: IdReg = IdReg::new(6, 0);
+pub const ID_AA64ISAR1_EL1: IdReg = IdReg::new(6, 1);
+pub const ID_AA64ISAR2_EL1: IdReg = IdReg::new(6, 2);
+pub const ID_AA64MMFR0_EL1: IdReg = IdReg::new(7, 0);
+pub const ID_AA64MMFR1_EL1: IdReg = IdReg::new(7, 1);
+pub const ID_AA64MMFR2_EL1: IdReg = IdReg::new(7, 2);
+
+/// Overridden bits of each register
+static MASK: [[AtomicU64; ID_SPACE_REGS]; MAX_VMS] =
+    [const { [const { AtomicU64::new(0) }; ID_SPACE_REGS] }; MAX_VMS];
+/// Values of the overridden bits (zero elsewhere)
+static VALUE: [[AtomicU64; ID_SPACE_REGS]; MAX_VMS] =
+    [const { [const { AtomicU64::new(0) }; ID_SPACE_REGS] }; MAX_VMS];
+/// The VM overrides a register that needs HCR_EL2.TID3
+static TRAPPED: [AtomicBool; MAX_VMS] = [const { AtomicBool::new(false) }; MAX_VMS];
+
+/// Present bits `[shift, shift + width)` of `reg` as `value` to `vm_id`'s
+/// guest, replacing any earlier override of those bits.
+///
+/// MPIDR_EL1 and REVIDR_EL1 cannot be overridden (VMPIDR_EL2 is per vCPU).
+pub fn set_override(
+    vm_id: usize,
+    reg: IdReg,
+    shift: u32,
+    width: u32,
+    value: u64,
+) -> Result<(), &'static str> {
+    if vm_id >= MAX_VMS {
+        return Err("Invalid VM ID");
+    }
+    if !reg.is_trapped() && reg != MIDR_EL1 {
+        return Err("Register cannot be overridden");
+    }
+    if width == 0 || width > 64 || shift > 64 - width {
+        return Err("Field out of range");
+    }
+    let field = u64::MAX >> (64 - width);
+    if value & !field != 0 {
+        return Err("Value wider than field");
+    }
+    let i = reg.0 as usize;
+    let mask = field << shift;
+    let old = VALUE[vm_id][i].load(Ordering::Relaxed);
+    VALUE[vm_id][i].store((old & !mask) | (value << shift), Ordering::Relaxed);
+    MASK[vm_id][i].fetch_or(mask, Ordering::Release);
+    if reg.is_trapped() {
+        TRAPPED[vm_id].store(true, Ordering::Release);
+    }
+    Ok(())
+}
+
+/// Drop all of `vm_id`'s overrides: the guest sees the hardware values.
+pub fn clear_overrides(vm_id: usize) {
+    if vm_id >= MAX_VMS {
+        return;
+    }
+    TRAPPED[vm_id].store(false, Ordering::Release);
+    for i in 0..ID_SPACE_REGS {
+        MASK[vm_id][i].store(0, Ordering::Relaxed);
+        VALUE[vm_id][i].store(0, Ordering::Relaxed);
+    }
+}
+
+/// `hw`, the hardware value of `reg`, with `vm_id`'s overrides applied.
+pub fn apply(vm_id: usize, reg: IdReg, hw: u64) -> u64 {
+    if vm_id >= MAX_VMS {
+        return hw;
+    }
+    let i = reg.0 as usize;
+    let mask = MASK[vm_id][i].load(Ordering::Acquire);
+    (hw & !mask) | (VALUE[vm_id][i].load(Ordering::Relaxed) & mask)
+}
+
+/// Value of `reg` as `vm_id`'s guest sees it (trapped MRS).
+pub fn read(vm_id: usize, reg: IdReg) -> u64 {
+    apply(vm_id, reg, read_hw(reg))
+}
+
+/// Before entering one of `vm_id`'s vCPUs: load its MIDR_EL1 into
+/// VPIDR_EL2 and return `hcr` with TID3 set only if the VM overrides a
+/// trapped register.
+pub fn enter(vm_id: usize, hcr: u64) -> u64 {
+    let vpidr = read(vm_id, MIDR_EL1);
+    unsafe {
+        core::arch::asm!("msr vpidr_el2, {}", in(reg) vpidr, options(nostack, nomem));
+    }
+    if TRAPPED
+        .get(vm_id)
+        .is_some_and(|t| t.load(Ordering::Acquire))
+    {
+        hcr | HCR_TID3
+    } else {
+        hcr & !HCR_TID3
+    }
+}
+
+/// MRS of `S3_0_C0_C<crm>_<op2>` (the encoding must be a literal).
+macro_rules! mrs_id {
+    ($crm:literal, $op2:literal) => {{
+        let val: u64;
+        unsafe {
+            core::arch::asm!(
+                concat!("mrs {}, S3_0_C0_C", $crm, "_", $op2),
+                out(reg) val,
+                options(nostack, nomem),
+            );
+        }
+        val
+    }};
+}
+
+/// Hardware value of `reg`. Unallocated encodings in the ID space read as
+/// zero; MPIDR_EL1 and REVIDR_EL1 are not needed and read as zero too.
+fn read_hw(reg: IdReg) -> u64 {
+    match (reg.0 >> 3, reg.0 & 0x7) {
+        (0, 0) => mrs_id!(0, 0),
+        (1, 0) => mrs_id!(1, 0),
+        (1, 1) => mrs_id!(1, 1),
+        (1, 2) => mrs_id!(1, 2),
+        (1, 3) => mrs_id!(1, 3),
+        (1, 4) => mrs_id!(1, 4),
+        (1, 5) => mrs_id!(1, 5),
+        (1, 6) => mrs_id!(1, 6),
+        (1, 7) => mrs_id!(1, 7),
+        (2, 0) => mrs_id!(2, 0),
+        (2, 1) => mrs_id!(2, 1),
+        (2, 2) => mrs_id!(2, 2),
+        (2, 3) => mrs_id!(2, 3),
+        (2, 4) => mrs_id!(2, 4),
+        (2, 5) => mrs_id!(2, 5),
+        (2, 6) => mrs_id!(2, 6),
+        (2, 7) => mrs_id!(2, 7),
+        (3, 0) => mrs_id!(3, 0),
+        (3, 1) => mrs_id!(3, 1),
+        (3, 2) => mrs_id!(3, 2),
+        (3, 3) => mrs_id!(3, 3),
+        (3, 4) => mrs_id!(3, 4),
+        (3, 5) => mrs_id!(3, 5),
+        (3, 6) => mrs_id!(3, 6),
+        (3, 7) => mrs_id!(3, 7),
+        (4, 0) => mrs_id!(4, 0),
+        (4, 1) => mrs_id!(4, 1),
+        (4, 2) => mrs_id!(4, 2),
+        (4, 3) => mrs_id!(4, 3),
+        (4, 4) => mrs_id!(4, 4),
+        (4, 5) => mrs_id!(4, 5),
+        (4, 6) => mrs_id!(4, 6),
+        (4, 7) => mrs_id!(4, 7),
+        (5, 0) => mrs_id!(5, 0),
+        (5, 1) => mrs_id!(5, 1),
+        (5, 2) => mrs_id!(5, 2),
+        (5, 3) => mrs_id!(5, 3),
+        (5, 4) => mrs_id!(5, 4),
+        (5, 5) => mrs_id!(5, 5),
+        (5, 6) => mrs_id!(5, 6),
+        (5, 7) => mrs_id!(5, 7),
+        (6, 0) => mrs_id!(6, 0),
+        (6, 1) => mrs_id!(6, 1),
+        (6, 2) => mrs_id!(6, 2),
+        (6, 3) => mrs_id!(6, 3),
+        (6, 4) => mrs_id!(6, 4),
+        (6, 5) => mrs_id!(6, 5),
+        (6, 6) => mrs_id!(6, 6),
+        (6, 7) => mrs_id!(6, 7),
+        (7, 0) => mrs_id!(7, 0),
+        (7, 1) => mrs_id!(7, 1),
+        (7, 2) => mrs_id!(7, 2),
+        (7, 3) => mrs_id!(7, 3),
+        (7, 4) => mrs_id!(7, 4),
+        (7, 5) => mrs_id!(7, 5),
+        (7, 6) => mrs_id!(7, 6),
+        (7, 7) => mrs_id!(7, 7),
+        _ => 0,
+    }
+}
@@ -3,9 +3,13 @@
 //! This module contains code that runs at EL2 (Hypervisor mode):
 //! - Exception handling and trap processing
 //! - Instruction decoding for MMIO emulation
+//! - Virtual SError / DISR_EL1 state (RAS)
+//! - Guest-visible ID register overrides
 
 pub mod decode;
 pub mod exception;
+pub mod id_regs;
+pub mod serror;
 
 pub use decode::*;
 pub use exception::*;
@@ -0,0 +1,130 @@
+//! Virtual SError injection and DISR_EL1 virtualization (FEAT_RAS).
+//!
+//! With HCR_EL2.AMO set, a guest's DISR_EL1 accesses are redirected to
+//! VDISR_EL2 and a virtual SError is raised with HCR_EL2.VSE, its syndrome
+//! taken from VSESR_EL2. This module keeps that state per vCPU so it
+//! follows the vCPU across context switches:
+//!
+//! - `inject_virtual_serror()` queues a virtual SError with a syndrome.
+//! - `enter()` / `exit()` move the state into and out of VSE, VSESR_EL2 and
+//!   VDISR_EL2 around guest entry (no-ops without FEAT_RAS).
+//! - `read_disr()` / `write_disr()` back trapped DISR_EL1 accesses. A read
+//!   with PSTATE.A masked defers a pending SError into DISR, as the ESB the
+//!   guest issues before polling DISR would.
+
+use crate::global::{MAX_VCPUS, MAX_VMS};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// HCR_EL2.VSE: virtual SError pending
+pub const HCR_VSE: u64 = 1 << 8;
+/// DISR_EL1.A: a deferred SError was recorded
+pub const DISR_A: u64 = 1 << 31;
+/// VSESR_EL2 / DISR_EL1 IDS: syndrome is IMPLEMENTATION DEFINED
+pub const VSESR_IDS: u64 = 1 << 24;
+/// VSESR_EL2 / DISR_EL1 ISS field
+pub const VSESR_ISS_MASK: u64 = 0xFF_FFFF;
+/// Bits of a deferred-error record that DISR_EL1 reports
+const DISR_VALID_MASK: u64 = DISR_A | VSESR_IDS | VSESR_ISS_MASK;
+/// PSTATE.A in SPSR_EL2: SError masked
+const SPSR_A: u64 = 1 << 8;
+
+/// Virtual SError queued for the vCPU, not yet taken or deferred
+static PENDING: [[AtomicBool; MAX_VCPUS]; MAX_VMS] =
+    [const { [const { AtomicBool::new(false) }; MAX_VCPUS] }; MAX_VMS];
+/// Syndrome of the queued virtual SError (VSESR_EL2)
+static VSESR: [[AtomicU64; MAX_VCPUS]; MAX_VMS] =
+    [const { [const { AtomicU64::new(0) }; MAX_VCPUS] }; MAX_VMS];
+/// Deferred-error record the guest sees in DISR_EL1 (VDISR_EL2)
+static VDISR: [[AtomicU64; MAX_VCPUS]; MAX_VMS] =
+    [const { [const { AtomicU64::new(0) }; MAX_VCPUS] }; MAX_VMS];
+
+/// Whether the CPU implements FEAT_RAS (ID_AA64PFR0_EL1.RAS != 0).
+pub fn ras_implemented() -> bool {
+    let pfr0: u64;
+    unsafe {
+        core::arch::asm!("mrs {}, id_aa64pfr0_el1", out(reg) pfr0, options(nostack, nomem));
+    }
+    (pfr0 >> 28) & 0xF != 0
+}
+
+/// Queue a virtual SError with `syndrome` (IDS + ISS) for a vCPU.
+pub fn inject_virtual_serror(vm_id: usize, vcpu_id: usize, syndrome: u64) {
+    if vm_id >= MAX_VMS || vcpu_id >= MAX_VCPUS {
+        return;
+    }
+    VSESR[vm_id][vcpu_id].store(syndrome & (VSESR_IDS | VSESR_ISS_MASK), Ordering::Relaxed);
+    PENDING[vm_id][vcpu_id].store(true, Ordering::Release);
+}
+
+/// Whether a virtual SError is still pending for the vCPU.
+pub fn serror_pending(vm_id: usize, vcpu_id: usize) -> bool {
+    vm_id < MAX_VMS && vcpu_id < MAX_VCPUS && PENDING[vm_id][vcpu_id].load(Ordering::Acquire)
+}
+
+/// Drop all virtual SError state for a vCPU (call when it is (re)created).
+pub fn reset(vm_id: usize, vcpu_id: usize) {
+    if vm_id >= MAX_VMS || vcpu_id >= MAX_VCPUS {
+        return;
+    }
+    PENDING[vm_id][vcpu_id].store(false, Ordering::Relaxed);
+    VSESR[vm_id][vcpu_id].store(0, Ordering::Relaxed);
+    VDISR[vm_id][vcpu_id].store(0, Ordering::Relaxed);
+}
+
+/// DISR_EL1 as seen by the guest. With PSTATE.A masked (`spsr` is the
+/// guest's saved PSTATE), a pending virtual SError is deferred first.
+pub fn read_disr(vm_id: usize, vcpu_id: usize, spsr: u64) -> u64 {
+    if vm_id >= MAX_VMS || vcpu_id >= MAX_VCPUS {
+        return 0;
+    }
+    if spsr & SPSR_A != 0 && PENDING[vm_id][vcpu_id].swap(false, Ordering::AcqRel) {
+        let record = DISR_A | VSESR[vm_id][vcpu_id].load(Ordering::Relaxed);
+        VDISR[vm_id][vcpu_id].store(record, Ordering::Relaxed);
+    }
+    VDISR[vm_id][vcpu_id].load(Ordering::Relaxed)
+}
+
+/// Guest write to DISR_EL1 (typically 0 to acknowledge the record).
+pub fn write_disr(vm_id: usize, vcpu_id: usize, value: u64) {
+    if vm_id >= MAX_VMS || vcpu_id >= MAX_VCPUS {
+        return;
+    }
+    VDISR[vm_id][vcpu_id].store(value & DISR_VALID_MASK, Ordering::Relaxed);
+}
+
+/// Load the vCPU's virtual SError state into VSESR_EL2/VDISR_EL2 and return
+/// `hcr` with VSE set if an SError is pending. Call right before guest entry.
+pub fn enter(vm_id: usize, vcpu_id: usize, hcr: u64) -> u64 {
+    if vm_id >= MAX_VMS || vcpu_id >= MAX_VCPUS || !ras_implemented() {
+        return hcr & !HCR_VSE;
+    }
+    let vsesr = VSESR[vm_id][vcpu_id].load(Ordering::Relaxed);
+    let vdisr = VDISR[vm_id][vcpu_id].load(Ordering::Relaxed);
+    unsafe {
+        // VSESR_EL2 = S3_4_C5_C2_3, VDISR_EL2 = S3_4_C12_C1_1
+        core::arch::asm!("msr s3_4_c5_c2_3, {}", in(reg) vsesr, options(nostack, nomem));
+        core::arch::asm!("msr s3_4_c12_c1_1, {}", in(reg) vdisr, options(nostack, nomem));
+    }
+    if PENDING[vm_id][vcpu_id].load(Ordering::Acquire) {
+        hcr | HCR_VSE
+    } else {
+        hcr & !HCR_VSE
+    }
+}
+
+/// Capture VDISR_EL2 after guest exit. Hardware clears HCR_EL2.VSE once the
+/// guest took or deferred the SError; `entry_hcr` is what `enter()` returned
+/// and `hcr` the live value after exit.
+pub fn exit(vm_id: usize, vcpu_id: usize, entry_hcr: u64, hcr: u64) {
+    if vm_id >= MAX_VMS || vcpu_id >= MAX_VCPUS || !ras_implemented() {
+        return;
+    }
+    let vdisr: u64;
+    unsafe {
+        core::arch::asm!("mrs {}, s3_4_c12_c1_1", out(reg) vdisr, options(nostack, nomem));
+    }
+    VDISR[vm_id][vcpu_id].store(vdisr & DISR_VALID_MASK, Ordering::Relaxed);
+    if entry_hcr & HCR_VSE != 0 && hcr & HCR_VSE == 0 {
+        PENDING[vm_id][vcpu_id].store(false, Ordering::Release);
+    }
+}
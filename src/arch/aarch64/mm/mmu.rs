@@ -8,6 +8,11 @@
 //! - Level 1: 1GB blocks (entry covers bits [38:30])
 //! - Level 2: 2MB blocks (entry covers bits [29:21])
 //! - Level 3: 4KB pages (entry covers bits [20:12])
+//!
+//! With the 64KB granule (`Granule::Size64KB`) the walk starts at level 1:
+//! - Level 1: 4TB regions (entry covers bits [47:42])
+//! - Level 2: 512MB blocks (entry covers bits [41:29])
+//! - Level 3: 64KB pages (entry covers bits [28:16])
 
 use crate::arch::aarch64::defs::*;
 use crate::arch::traits::{MemoryType, Stage2Mapper};
@@ -16,6 +21,163 @@ use crate::arch::traits::{MemoryType, Stage2Mapper};
 pub const PAGE_SIZE: usize = 4096;
 pub const PAGE_SHIFT: usize = 12;
 
+/// Stage-2 translation granule (VTCR_EL2.TG0) with 48-bit IPA.
+///
+/// Selects the table size, the per-level index shifts and the starting
+/// level. The mapper, `Stage2Config` and `Stage2Walker` of one VM must all
+/// use the same granule.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Granule {
+    /// 4KB pages, 2MB L2 blocks, 4-level walk from L0
+    #[default]
+    Size4KB,
+    /// 64KB pages, 512MB L2 blocks, 3-level walk from L1
+    Size64KB,
+}
+
+impl Granule {
+    /// log2 of the page (and table) size
+    pub const fn page_shift(self) -> u32 {
+        match self {
+            Granule::Size4KB => 12,
+            Granule::Size64KB => 16,
+        }
+    }
+
+    /// Page size, also the size and alignment of each translation table
+    pub const fn page_size(self) -> u64 {
+        1 << self.page_shift()
+    }
+
+    /// Index bits resolved per level (512 or 8192 entries per table)
+    pub const fn index_bits(self) -> u32 {
+        self.page_shift() - 3
+    }
+
+    /// First lookup level for a 48-bit IPA
+    pub const fn start_level(self) -> usize {
+        match self {
+            Granule::Size4KB => 0,
+            Granule::Size64KB => 1,
+        }
+    }
+
+    /// Lowest IPA bit indexed at `level` (0-3)
+    pub const fn level_shift(self, level: usize) -> u32 {
+        self.page_shift() + self.index_bits() * (3 - level as u32)
+    }
+
+    /// Table index of `ipa` at `level`
+    pub const fn index(self, ipa: u64, level: usize) -> usize {
+        ((ipa >> self.level_shift(level)) & ((1 << self.index_bits()) - 1)) as usize
+    }
+
+    /// Size of an L2 block descriptor (2MB or 512MB)
+    pub const fn block_size(self) -> u64 {
+        1 << self.level_shift(2)
+    }
+
+    /// Size of an L1 block descriptor: 1GB with 4KB, none with 64KB (its
+    /// 4TB level-1 blocks need 52-bit output addresses)
+    pub const fn l1_block_size(self) -> Option<u64> {
+        match self {
+            Granule::Size4KB => Some(1 << self.level_shift(1)),
+            Granule::Size64KB => None,
+        }
+    }
+
+    /// VTCR_EL2.TG0 field
+    pub const fn vtcr_tg0(self) -> u64 {
+        match self {
+            Granule::Size4KB => VTCR_TG0_4KB,
+            Granule::Size64KB => VTCR_TG0_64KB,
+        }
+    }
+
+    /// Granule selected by the TG0 field of a VTCR_EL2 value (0 = 4KB)
+    pub const fn from_vtcr(vtcr: u64) -> Self {
+        if vtcr & (0b11 << 14) == VTCR_TG0_64KB {
+            Granule::Size64KB
+        } else {
+            Granule::Size4KB
+        }
+    }
+
+    /// Whether an ID_AA64MMFR0_EL1 value reports this granule at Stage-2.
+    ///
+    /// TGranX_2 (0b0010 supported, 0b0001 not) defers to the Stage-1
+    /// TGranX field when 0b0000: TGran4 [31:28] is 0b0000 or 0b0001
+    /// (52-bit) when supported, TGran64 [27:24] only 0b0000.
+    pub const fn stage2_supported_by(self, mmfr0: u64) -> bool {
+        let (tgran_2, tgran) = match self {
+            Granule::Size4KB => ((mmfr0 >> 40) & 0xF, (mmfr0 >> 28) & 0xF),
+            Granule::Size64KB => ((mmfr0 >> 32) & 0xF, (mmfr0 >> 24) & 0xF),
+        };
+        match tgran_2 {
+            0b0000 => match self {
+                Granule::Size4KB => tgran <= 0b0001,
+                Granule::Size64KB => tgran == 0b0000,
+            },
+            0b0010 => true,
+            // 4KB with 52-bit output addresses
+            0b0011 => matches!(self, Granule::Size4KB),
+            _ => false,
+        }
+    }
+
+    /// Whether this PE supports the granule at Stage-2.
+    pub fn stage2_supported(self) -> bool {
+        let mmfr0: u64;
+        unsafe {
+            core::arch::asm!("mrs {}, id_aa64mmfr0_el1", out(reg) mmfr0, options(nomem, nostack));
+        }
+        self.stage2_supported_by(mmfr0)
+    }
+
+    /// VTCR_EL2 value: 48-bit IPA, inner-shareable write-back walks
+    pub const fn vtcr(self) -> u64 {
+        let sl0 = match self {
+            Granule::Size4KB => VTCR_SL0_LEVEL0,
+            Granule::Size64KB => VTCR_SL0_64KB_LEVEL1,
+        };
+        VTCR_T0SZ_48BIT
+            | sl0
+            | VTCR_IRGN0_WB
+            | VTCR_ORGN0_WB
+            | VTCR_SH0_INNER
+            | self.vtcr_tg0()
+            | VTCR_PS_48BIT
+    }
+
+    /// Allocate a zeroed, naturally aligned translation table.
+    pub(crate) fn alloc_table(self) -> Option<u64> {
+        let table = match self {
+            Granule::Size4KB => crate::mm::heap::alloc_page()?,
+            Granule::Size64KB => {
+                crate::mm::heap::alloc_aligned(self.page_size(), self.page_size())?
+            }
+        };
+        unsafe {
+            core::ptr::write_bytes(table as *mut u8, 0, self.page_size() as usize);
+        }
+        Some(table)
+    }
+
+    /// Return a table from `alloc_table()` to the heap free-list. A 64KB
+    /// table only comes back as 16 4KB pages, which later 64KB tables do
+    /// not reuse.
+    ///
+    /// # Safety
+    /// `table` must come from `alloc_table()` with this granule and must no
+    /// longer be reachable from any installed Stage-2.
+    pub(crate) unsafe fn free_table(self, table: u64) {
+        match self {
+            Granule::Size4KB => crate::mm::heap::free_page(table),
+            Granule::Size64KB => crate::mm::heap::free_aligned(table, self.page_size()),
+        }
+    }
+}
+
 /// Stage-2 page table entry
 #[repr(transparent)]
 #[derive(Clone, Copy)]
@@ -164,14 +326,12 @@ impl Stage2Config {
     ///
     /// VTTBR_EL2 format: VMID in bits [63:48], page table base in bits [47:1]
     pub fn new_with_vmid(page_table_addr: u64, vmid: u16) -> Self {
-        // VTCR_EL2 configuration for 4KB granule, 48-bit IPA
-        let vtcr = VTCR_T0SZ_48BIT
-            | VTCR_SL0_LEVEL0
-            | VTCR_IRGN0_WB
-            | VTCR_ORGN0_WB
-            | VTCR_SH0_INNER
-            | VTCR_TG0_4KB
-            | VTCR_PS_48BIT;
+        Self::new_with_granule(page_table_addr, vmid, Granule::Size4KB)
+    }
+
+    /// Create Stage-2 configuration with explicit VMID and granule
+    pub fn new_with_granule(page_table_addr: u64, vmid: u16, granule: Granule) -> Self {
+        let vtcr = granule.vtcr();
 
         // VTTBR_EL2: VMID[63:48] | page table base[47:1]
         let vttbr = (page_table_addr & 0x0000_FFFF_FFFF_FFFE) | ((vmid as u64) << 48);
@@ -314,42 +474,76 @@ impl IdentityMapper {
 pub enum MemoryAttribute {
     /// Normal memory, write-back cacheable
     Normal,
+    /// Normal memory, inner/outer non-cacheable (non-coherent DMA testing)
+    NormalNC,
     /// Device memory (MMIO)
     Device,
     /// Read-only memory
     ReadOnly,
 }
 
+impl MemoryAttribute {
+    /// Stage-2 MemAttr[3:0] value. Stage-2 descriptors encode the memory
+    /// type directly (no MAIR index) while HCR_EL2.FWB is clear.
+    pub const fn s2_mem_attr(self) -> u8 {
+        match self {
+            MemoryAttribute::Normal | MemoryAttribute::ReadOnly => S2_MEMATTR_NORMAL_WB,
+            MemoryAttribute::NormalNC => S2_MEMATTR_NORMAL_NC,
+            MemoryAttribute::Device => S2_MEMATTR_DEVICE_NGNRNE,
+        }
+    }
+
+    /// Stage-2 S2AP value (0b01 = read-only, 0b11 = read/write)
+    pub const fn s2ap(self) -> u8 {
+        match self {
+            MemoryAttribute::ReadOnly => 0b01,
+            _ => 0b11,
+        }
+    }
+}
+
 /// Dynamic identity mapper using heap allocation for page tables
 pub struct DynamicIdentityMapper {
+    /// Root table (L0 for 4KB, the L1 table itself for 64KB)
     l0_table: u64,
     l1_table: u64,
-    l2_tables: [u64; 4],
-    l2_count: usize,
+    granule: Granule,
 }
 
 impl DynamicIdentityMapper {
-    /// Create a new dynamic identity mapper
+    /// Create a new dynamic identity mapper (4KB granule)
     pub fn new() -> Self {
-        let l0 = crate::mm::heap::alloc_page().expect("Failed to allocate L0 table");
-        let l1 = crate::mm::heap::alloc_page().expect("Failed to allocate L1 table");
+        Self::with_granule(Granule::Size4KB)
+    }
 
-        unsafe {
-            core::ptr::write_bytes(l0 as *mut u8, 0, PAGE_SIZE);
-            core::ptr::write_bytes(l1 as *mut u8, 0, PAGE_SIZE);
+    /// Create a new dynamic identity mapper using `granule`
+    pub fn with_granule(granule: Granule) -> Self {
+        let root = granule
+            .alloc_table()
+            .expect("Failed to allocate root table");
+        let l1 = if granule.start_level() == 0 {
+            let l1 = granule.alloc_table().expect("Failed to allocate L1 table");
             // Link L0[0] -> L1
-            let l0_ptr = l0 as *mut u64;
-            *l0_ptr = l1 | (PTE_VALID | PTE_TABLE); // Valid + Table descriptor
-        }
+            unsafe {
+                *(root as *mut u64) = l1 | (PTE_VALID | PTE_TABLE); // Valid + Table descriptor
+            }
+            l1
+        } else {
+            root
+        };
 
         Self {
-            l0_table: l0,
+            l0_table: root,
             l1_table: l1,
-            l2_tables: [0; 4],
-            l2_count: 0,
+            granule,
         }
     }
 
+    /// Translation granule of these tables
+    pub fn granule(&self) -> Granule {
+        self.granule
+    }
+
     /// Map a memory region with identity mapping
     pub fn map_region(
         &mut self,
@@ -357,13 +551,31 @@ impl DynamicIdentityMapper {
         size: u64,
         attr: MemoryAttribute,
     ) -> Result<(), &'static str> {
+        let g = self.granule;
+        let block = g.block_size();
         let mut offset = 0;
 
         while offset < size {
             let current_ipa = ipa + offset;
-            let l1_idx = ((current_ipa >> 30) & PT_INDEX_MASK) as usize;
-            let l2_table = self.get_or_create_l2(l1_idx)?;
-            let l2_idx = ((current_ipa >> 21) & PT_INDEX_MASK) as usize;
+            // 1GB-aligned runs of at least 1GB take a single L1 block
+            if let Some(l1_block) = g.l1_block_size() {
+                if current_ipa & (l1_block - 1) == 0
+                    && size - offset >= l1_block
+                    && self.map_l1_block(current_ipa, attr)
+                {
+                    offset += l1_block;
+                    continue;
+                }
+            }
+            // 4KB rounds partial ranges up to 2MB blocks; a 512MB block
+            // would over-map, so 64KB maps partial ranges with pages.
+            if g == Granule::Size64KB && (current_ipa & (block - 1) != 0 || size - offset < block) {
+                self.map_page(current_ipa, attr)?;
+                offset += g.page_size();
+                continue;
+            }
+            let l2_table = self.get_or_create_l2(g.index(current_ipa, 1))?;
+            let l2_idx = g.index(current_ipa, 2);
             let entry = self.make_block_entry(current_ipa, attr);
 
             unsafe {
@@ -371,12 +583,30 @@ impl DynamicIdentityMapper {
                 *l2_ptr.add(l2_idx) = entry;
             }
 
-            offset += BLOCK_SIZE_2MB;
+            offset += block;
         }
         Ok(())
     }
 
-    /// Get or create L2 table for given L1 index
+    /// Install an L1 block for the 1GB at `ipa` (1GB-aligned), unless its
+    /// L1 slot already holds an L2 table whose finer mappings would be lost.
+    /// Returns whether the block was installed.
+    fn map_l1_block(&mut self, ipa: u64, attr: MemoryAttribute) -> bool {
+        let l1_ptr = unsafe { (self.l1_table as *mut u64).add(self.granule.index(ipa, 1)) };
+        unsafe {
+            if *l1_ptr & (PTE_VALID | PTE_TABLE) == (PTE_VALID | PTE_TABLE) {
+                return false;
+            }
+            *l1_ptr = self.make_l1_block_entry(ipa, attr);
+        }
+        true
+    }
+
+    /// Get or create L2 table for given L1 index.
+    ///
+    /// The live L1 entry is the only record of an L2 table, so any number
+    /// of L1 slots (i.e. any IPA span the L1 table covers) can be mapped.
+    /// A 1GB block in the slot is split into an L2 table of 2MB blocks.
     fn get_or_create_l2(&mut self, l1_idx: usize) -> Result<u64, &'static str> {
         let l1_entry = unsafe {
             let l1_ptr = self.l1_table as *const u64;
@@ -385,22 +615,17 @@ impl DynamicIdentityMapper {
 
         // Check if valid table entry already exists
         if l1_entry & (PTE_VALID | PTE_TABLE) == (PTE_VALID | PTE_TABLE) {
-            return Ok(l1_entry & !PAGE_OFFSET_MASK);
+            return Ok(l1_entry & PTE_ADDR_MASK);
         }
-
-        // Need to allocate new L2 table
-        if self.l2_count >= 4 {
-            return Err("Too many L2 tables");
-        }
-
-        let l2 = crate::mm::heap::alloc_page().ok_or("Failed to allocate L2 table")?;
-
-        unsafe {
-            core::ptr::write_bytes(l2 as *mut u8, 0, PAGE_SIZE);
+        if l1_entry & PTE_VALID != 0 {
+            return self.split_block(1, self.l1_table, l1_idx, l1_entry);
         }
 
-        self.l2_tables[self.l2_count] = l2;
-        self.l2_count += 1;
+        // Need to allocate new L2 table
+        let l2 = self
+            .granule
+            .alloc_table()
+            .ok_or("Failed to allocate L2 table")?;
 
         // Create table descriptor and write to L1
         let l1_entry = l2 | (PTE_VALID | PTE_TABLE);
@@ -412,14 +637,22 @@ impl DynamicIdentityMapper {
         Ok(l2)
     }
 
-    /// Create a 2MB block entry
+    /// Create an L2 block entry (2MB, or 512MB with the 64KB granule)
     fn make_block_entry(&self, pa: u64, attr: MemoryAttribute) -> u64 {
         let attr_bits = match attr {
             MemoryAttribute::Normal => (0b1111 << 2) | (0b11 << 6) | (0b11 << 8) | (1 << 10),
+            MemoryAttribute::NormalNC => (0b0101 << 2) | (0b11 << 6) | (0b11 << 8) | (1 << 10),
             MemoryAttribute::Device => (0b0000 << 2) | (0b11 << 6) | (0b00 << 8) | (1 << 10),
             MemoryAttribute::ReadOnly => (0b1111 << 2) | (0b01 << 6) | (0b11 << 8) | (1 << 10),
         };
-        (pa & !BLOCK_MASK_2MB) | attr_bits | PTE_VALID
+        (pa & !(self.granule.block_size() - 1)) | attr_bits | PTE_VALID
+    }
+
+    /// Create an L1 block entry (1GB, 4KB granule only): same Stage-2
+    /// attributes and AF as an L2 block, output address bits [47:30]
+    fn make_l1_block_entry(&self, pa: u64, attr: MemoryAttribute) -> u64 {
+        let l1_block = 1u64 << self.granule.level_shift(1);
+        self.make_block_entry(pa & !(l1_block - 1), attr)
     }
 
     /// Map a single 4KB page (identity mapping: IPA == PA).
@@ -427,25 +660,30 @@ impl DynamicIdentityMapper {
     /// If the target L2 entry is a 2MB block, it is first split into 512 x 4KB
     /// page entries preserving the original mapping attributes.
     pub fn map_4kb_page(&mut self, ipa: u64, attr: MemoryAttribute) -> Result<(), &'static str> {
-        let l1_idx = ((ipa >> 30) & PT_INDEX_MASK) as usize;
-        let l2_table = self.get_or_create_l2(l1_idx)?;
-        let l2_idx = ((ipa >> 21) & PT_INDEX_MASK) as usize;
-        let l3_idx = ((ipa >> 12) & PT_INDEX_MASK) as usize;
+        self.map_page(ipa, attr)
+    }
+
+    /// Map the granule page containing `ipa` (identity mapping: IPA == PA).
+    ///
+    /// Same as `map_4kb_page()` on a 4KB mapper; maps a 64KB page (splitting
+    /// a 512MB block) on a 64KB mapper.
+    pub fn map_page(&mut self, ipa: u64, attr: MemoryAttribute) -> Result<(), &'static str> {
+        let g = self.granule;
+        let l2_table = self.get_or_create_l2(g.index(ipa, 1))?;
+        let l2_idx = g.index(ipa, 2);
+        let l3_idx = g.index(ipa, 3);
 
         let l2_entry = unsafe { *(l2_table as *const u64).add(l2_idx) };
 
         let l3_table = if l2_entry & PTE_VALID != 0 && l2_entry & PTE_TABLE == 0 {
-            // L2 entry is a 2MB block — split into L3 table
-            self.split_2mb_block(l2_table, l2_idx, l2_entry)?
+            // L2 entry is a block — split into L3 table
+            self.split_block(2, l2_table, l2_idx, l2_entry)?
         } else if l2_entry & (PTE_VALID | PTE_TABLE) == (PTE_VALID | PTE_TABLE) {
             // L2 entry already points to an L3 table
             l2_entry & PTE_ADDR_MASK
         } else {
             // L2 entry invalid — create fresh L3 table (all invalid entries)
-            let l3 = crate::mm::heap::alloc_page().ok_or("Failed to allocate L3 table")?;
-            unsafe {
-                core::ptr::write_bytes(l3 as *mut u8, 0, PAGE_SIZE);
-            }
+            let l3 = g.alloc_table().ok_or("Failed to allocate L3 table")?;
             let l3_desc = l3 | PTE_VALID | PTE_TABLE;
             unsafe {
                 *(l2_table as *mut u64).add(l2_idx) = l3_desc;
@@ -453,8 +691,8 @@ impl DynamicIdentityMapper {
             l3
         };
 
-        // Write the 4KB page entry (L3 page descriptor: bit[1]=1 means page at L3)
-        let page_entry = self.make_page_entry(ipa & !PAGE_MASK_4KB, attr);
+        // Write the page entry (L3 page descriptor: bit[1]=1 means page at L3)
+        let page_entry = self.make_page_entry(ipa & !(g.page_size() - 1), attr);
         unsafe {
             *(l3_table as *mut u64).add(l3_idx) = page_entry;
         }
@@ -466,20 +704,21 @@ impl DynamicIdentityMapper {
     }
 
     /// Remove a 4KB page mapping (mark L3 entry invalid).
-    /// If the L2 entry is a 2MB block, it is first split into an L3 table.
+    /// A 1GB or 2MB block covering it is first split down to an L3 table.
     pub fn unmap_4kb_page(&mut self, ipa: u64) -> Result<(), &'static str> {
-        let l1_idx = ((ipa >> 30) & PT_INDEX_MASK) as usize;
+        let g = self.granule;
+        let l1_idx = g.index(ipa, 1);
         let l1_entry = unsafe { *(self.l1_table as *const u64).add(l1_idx) };
-        if l1_entry & (PTE_VALID | PTE_TABLE) != (PTE_VALID | PTE_TABLE) {
+        if l1_entry & PTE_VALID == 0 {
             return Err("L1 entry not valid");
         }
-        let l2_table = l1_entry & PTE_ADDR_MASK;
-        let l2_idx = ((ipa >> 21) & PT_INDEX_MASK) as usize;
+        let l2_table = self.get_or_create_l2(l1_idx)?;
+        let l2_idx = g.index(ipa, 2);
         let l2_entry = unsafe { *(l2_table as *const u64).add(l2_idx) };
 
         let l3_table = if l2_entry & PTE_VALID != 0 && l2_entry & PTE_TABLE == 0 {
-            // L2 entry is a 2MB block — split into L3 first
-            self.split_2mb_block(l2_table, l2_idx, l2_entry)?
+            // L2 entry is a block — split into L3 first
+            self.split_block(2, l2_table, l2_idx, l2_entry)?
         } else if l2_entry & (PTE_VALID | PTE_TABLE) == (PTE_VALID | PTE_TABLE) {
             // L2 entry already points to an L3 table
             l2_entry & PTE_ADDR_MASK
@@ -487,7 +726,7 @@ impl DynamicIdentityMapper {
             return Err("L2 entry not valid");
         };
 
-        let l3_idx = ((ipa >> 12) & PT_INDEX_MASK) as usize;
+        let l3_idx = g.index(ipa, 3);
         unsafe {
             *(l3_table as *mut u64).add(l3_idx) = 0;
         }
@@ -495,58 +734,68 @@ impl DynamicIdentityMapper {
         Ok(())
     }
 
-    /// Split a 2MB block entry into 512 x 4KB page entries.
+    /// Split a block entry at `level` of `table` into a next-level table
+    /// covering the same range: a 1GB L1 block into 512 x 2MB L2 blocks, or
+    /// an L2 block into L3 pages (512 x 4KB, or 8192 x 64KB with the 64KB
+    /// granule). Returns the new table.
     ///
-    /// Uses break-before-make: invalidate L2 entry → TLB flush → write new table.
-    fn split_2mb_block(
+    /// Uses break-before-make: invalidate the entry → TLB flush → write new table.
+    fn split_block(
         &self,
-        l2_table: u64,
-        l2_idx: usize,
+        level: usize,
+        table: u64,
+        idx: usize,
         block_entry: u64,
     ) -> Result<u64, &'static str> {
-        let block_pa = block_entry & !BLOCK_MASK_2MB;
-        let block_attr_bits = block_entry & BLOCK_MASK_2MB & !0x3; // strip valid+type bits
-
-        // Allocate L3 table
-        let l3 = crate::mm::heap::alloc_page().ok_or("Failed to allocate L3 table for split")?;
-
-        // Fill L3 with 512 page entries preserving original attributes.
-        // L3 page descriptor: [PA | attrs | bit1=1(page) | bit0=1(valid)]
+        let g = self.granule;
+        let block_mask = (1u64 << g.level_shift(level)) - 1;
+        let child_size = 1u64 << g.level_shift(level + 1);
+        let block_pa = block_entry & !block_mask;
+        let block_attr_bits = block_entry & block_mask & !0x3; // strip valid+type bits
+                                                               // bit[1] = 1 marks an L3 page; at L2 it must be 0 (block)
+        let child_type = if level + 1 == 3 { PTE_TABLE } else { 0 };
+
+        // Allocate the next-level table
+        let next = g
+            .alloc_table()
+            .ok_or("Failed to allocate table for block split")?;
+
+        // Fill it with entries preserving the original attributes.
+        // [PA | attrs | bit1=type | bit0=1(valid)]
         unsafe {
-            let l3_ptr = l3 as *mut u64;
-            for i in 0..512u64 {
-                let pa = block_pa + i * PAGE_SIZE_4KB;
-                // Page descriptor: same attr bits as block, but bit[1] = 1 (page)
-                let page = pa | block_attr_bits | PTE_TABLE | PTE_VALID;
-                *l3_ptr.add(i as usize) = page;
+            let next_ptr = next as *mut u64;
+            for i in 0..1u64 << g.index_bits() {
+                let pa = block_pa + i * child_size;
+                *next_ptr.add(i as usize) = pa | block_attr_bits | child_type | PTE_VALID;
             }
         }
 
-        // Break-before-make: invalidate old L2 entry
+        // Break-before-make: invalidate old block entry
         unsafe {
-            *(l2_table as *mut u64).add(l2_idx) = 0;
+            *(table as *mut u64).add(idx) = 0;
         }
         Self::tlbi_all();
 
-        // Write new L2 table descriptor pointing to L3
-        let l2_desc = l3 | PTE_VALID | PTE_TABLE;
+        // Write new table descriptor
+        let desc = next | PTE_VALID | PTE_TABLE;
         unsafe {
-            *(l2_table as *mut u64).add(l2_idx) = l2_desc;
+            *(table as *mut u64).add(idx) = desc;
         }
         Self::tlbi_all();
 
-        Ok(l3)
+        Ok(next)
     }
 
-    /// Create a 4KB page entry (L3 level).
+    /// Create a page entry (L3 level).
     fn make_page_entry(&self, pa: u64, attr: MemoryAttribute) -> u64 {
         let attr_bits = match attr {
             MemoryAttribute::Normal => (0b1111 << 2) | (0b11 << 6) | (0b11 << 8) | (1 << 10),
+            MemoryAttribute::NormalNC => (0b0101 << 2) | (0b11 << 6) | (0b11 << 8) | (1 << 10),
             MemoryAttribute::Device => (0b0000 << 2) | (0b11 << 6) | (0b00 << 8) | (1 << 10),
             MemoryAttribute::ReadOnly => (0b1111 << 2) | (0b01 << 6) | (0b11 << 8) | (1 << 10),
         };
         // L3 page: bit[1] = 1 (page), bit[0] = 1 (valid)
-        (pa & !PAGE_MASK_4KB) | attr_bits | PTE_TABLE | PTE_VALID
+        (pa & !(self.granule.page_size() - 1)) | attr_bits | PTE_TABLE | PTE_VALID
     }
 
     /// Invalidate all Stage-2 TLB entries.
@@ -589,7 +838,7 @@ impl DynamicIdentityMapper {
 
     /// Get the configuration for this mapper
     pub fn config(&self) -> Stage2Config {
-        Stage2Config::new(self.l0_table)
+        Stage2Config::new_with_granule(self.l0_table, 0, self.granule)
     }
 
     // ── Page Ownership (SW bits) ─────────────────────────────────────
@@ -625,48 +874,24 @@ impl DynamicIdentityMapper {
     }
 
     /// Walk page table to the leaf PTE pointer for a given IPA.
+    ///
+    /// Returns the first valid block (L1/L2) or page (L3) entry; the
+    /// granule's start level only holds table descriptors.
     fn walk_to_leaf_ptr(&self, ipa: u64) -> Option<*mut u64> {
-        // L0
-        let l0_idx = ((ipa >> 39) & PT_INDEX_MASK) as usize;
-        let l0_entry = unsafe { *(self.l0_table as *const u64).add(l0_idx) };
-        if l0_entry & (PTE_VALID | PTE_TABLE) != (PTE_VALID | PTE_TABLE) {
-            return None;
-        }
-
-        // L1
-        let l1_table = l0_entry & PTE_ADDR_MASK;
-        let l1_idx = ((ipa >> 30) & PT_INDEX_MASK) as usize;
-        let l1_entry = unsafe { *(l1_table as *const u64).add(l1_idx) };
-        if l1_entry & PTE_VALID == 0 {
-            return None;
-        }
-        // L1 block (1GB)
-        if l1_entry & PTE_TABLE == 0 {
-            return Some(unsafe { (l1_table as *mut u64).add(l1_idx) });
-        }
-
-        // L2
-        let l2_table = l1_entry & PTE_ADDR_MASK;
-        let l2_idx = ((ipa >> 21) & PT_INDEX_MASK) as usize;
-        let l2_ptr = unsafe { (l2_table as *mut u64).add(l2_idx) };
-        let l2_entry = unsafe { core::ptr::read_volatile(l2_ptr) };
-        if l2_entry & PTE_VALID == 0 {
-            return None;
-        }
-        // L2 block (2MB)
-        if l2_entry & PTE_TABLE == 0 {
-            return Some(l2_ptr);
-        }
-
-        // L3 (4KB page)
-        let l3_table = l2_entry & PTE_ADDR_MASK;
-        let l3_idx = ((ipa >> 12) & PT_INDEX_MASK) as usize;
-        let l3_ptr = unsafe { (l3_table as *mut u64).add(l3_idx) };
-        let l3_entry = unsafe { core::ptr::read_volatile(l3_ptr) };
-        if l3_entry & PTE_VALID == 0 {
-            return None;
+        let g = self.granule;
+        let mut table = self.l0_table;
+        for level in g.start_level()..=3 {
+            let ptr = unsafe { (table as *mut u64).add(g.index(ipa, level)) };
+            let entry = unsafe { core::ptr::read_volatile(ptr) };
+            if entry & PTE_VALID == 0 {
+                return None;
+            }
+            if level == 3 || entry & PTE_TABLE == 0 {
+                return (level != g.start_level()).then_some(ptr);
+            }
+            table = entry & PTE_ADDR_MASK;
         }
-        Some(l3_ptr)
+        None
     }
 }
 
@@ -676,6 +901,40 @@ impl Default for DynamicIdentityMapper {
     }
 }
 
+/// Frees every table reachable from the root: L1, the L2 tables and any L3
+/// tables created by page mappings or block splits (including those added
+/// later by `Stage2Walker` through this mapper's VTTBR). Call
+/// `core::mem::forget()` instead while the Stage-2 is still installed.
+impl Drop for DynamicIdentityMapper {
+    fn drop(&mut self) {
+        let g = self.granule;
+        let entries = (g.page_size() / 8) as usize;
+        let is_table = |e: u64| e & (PTE_VALID | PTE_TABLE) == (PTE_VALID | PTE_TABLE);
+        unsafe {
+            let l1 = self.l1_table as *const u64;
+            for i in 0..entries {
+                let l1_entry = *l1.add(i);
+                if !is_table(l1_entry) {
+                    continue;
+                }
+                let l2_table = l1_entry & PTE_ADDR_MASK;
+                let l2 = l2_table as *const u64;
+                for j in 0..entries {
+                    let l2_entry = *l2.add(j);
+                    if is_table(l2_entry) {
+                        g.free_table(l2_entry & PTE_ADDR_MASK);
+                    }
+                }
+                g.free_table(l2_table);
+            }
+            g.free_table(self.l1_table);
+            if self.l0_table != self.l1_table {
+                g.free_table(self.l0_table);
+            }
+        }
+    }
+}
+
 // ── Stage2Mapper trait implementation ─────────────────────────────────
 
 impl Stage2Mapper for DynamicIdentityMapper {
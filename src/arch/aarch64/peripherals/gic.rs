@@ -1,3 +1,5 @@
+use super::gicv3::{GicV3SystemRegs, GicV3VirtualInterface};
+use crate::dtb::{self, GicVersion, PlatformInfo};
 use crate::platform;
 /// ARM Generic Interrupt Controller (GICv2) support
 ///
@@ -6,12 +8,17 @@ use crate::platform;
 /// GIC Architecture:
 /// - GICD (Distributor): Manages interrupt prioritization and distribution
 /// - GICC (CPU Interface): Per-CPU interface for interrupt acknowledgment and EOI
+/// - GICH (Virtual Interface Control): List registers for virtual injection
+///
+/// Used instead of the GICv3 system-register interface when the host DTB
+/// describes a GICv2 or the CPU has no ICC_* registers (see `select_driver`).
 ///
 /// Interrupt Types:
 /// - SGI (0-15): Software Generated Interrupts
 /// - PPI (16-31): Private Peripheral Interrupts (per-CPU, includes timers)
 /// - SPI (32-1019): Shared Peripheral Interrupts
 use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicBool, Ordering};
 
 /// GICD Register offsets
 const GICD_CTLR: u64 = 0x000; // Distributor Control Register
@@ -32,6 +39,34 @@ const GICC_CTLR: u64 = 0x000; // CPU Interface Control Register
 const GICC_PMR: u64 = 0x004; // Interrupt Priority Mask Register
 const GICC_IAR: u64 = 0x00C; // Interrupt Acknowledge Register
 const GICC_EOIR: u64 = 0x010; // End of Interrupt Register
+const GICC_DIR: u64 = 0x1000; // Deactivate Interrupt Register
+
+/// GICC_CTLR bits
+const GICC_CTLR_ENABLE: u32 = 1 << 0;
+const GICC_CTLR_EOIMODE: u32 = 1 << 9; // EOIR drops priority, DIR deactivates
+
+/// GICH Register offsets
+const GICH_HCR: u64 = 0x000; // Hypervisor Control Register
+const GICH_VTR: u64 = 0x004; // VGIC Type Register
+const GICH_VMCR: u64 = 0x008; // Virtual Machine Control Register
+const GICH_LR0: u64 = 0x100; // List Register 0
+
+/// GICH_HCR.En: enable the virtual CPU interface
+const GICH_HCR_EN: u32 = 1 << 0;
+/// GICH_VTR.ListRegs: number of list registers minus one
+const GICH_VTR_LISTREGS_MASK: u32 = 0x3F;
+
+/// GICC_IAR.InterruptID; bits [12:10] hold the source CPU of an SGI
+const GICC_IAR_INTID_MASK: u32 = 0x3FF;
+
+/// GICH_LR fields
+const GICH_LR_VIRTUALID_MASK: u32 = 0x3FF;
+const GICH_LR_PHYSICALID_SHIFT: u32 = 10;
+const GICH_LR_PRIORITY_SHIFT: u32 = 23; // 5-bit priority: bits [7:3] of the 8-bit value
+const GICH_LR_STATE_SHIFT: u32 = 28;
+const GICH_LR_STATE_MASK: u32 = 0x3;
+const GICH_LR_GRP1: u32 = 1 << 30;
+const GICH_LR_HW: u32 = 1 << 31;
 
 /// Virtual Timer interrupt number (PPI 27)
 pub const VTIMER_IRQ: u32 = 27;
@@ -132,8 +167,9 @@ impl GicCpuInterface {
         // Set priority mask to lowest priority (allow all interrupts)
         self.write_reg(GICC_PMR, 0xFF);
 
-        // Enable CPU interface
-        self.write_reg(GICC_CTLR, 1);
+        // Enable CPU interface with split priority drop/deactivation,
+        // matching ICC_CTLR_EL1.EOImode=1 on GICv3
+        self.write_reg(GICC_CTLR, GICC_CTLR_ENABLE | GICC_CTLR_EOIMODE);
     }
 
     /// Acknowledge an interrupt (returns interrupt ID)
@@ -141,23 +177,260 @@ impl GicCpuInterface {
         self.read_reg(GICC_IAR)
     }
 
-    /// Signal end of interrupt
+    /// Signal end of interrupt (priority drop only with EOImode=1)
     pub fn end_of_interrupt(&self, irq: u32) {
         self.write_reg(GICC_EOIR, irq);
     }
+
+    /// Deactivate an interrupt after its priority drop
+    pub fn deactivate(&self, irq: u32) {
+        self.write_reg(GICC_DIR, irq);
+    }
+}
+
+/// GICv2 virtual interface control (GICH) wrapper
+pub struct GicV2VirtualInterface {
+    base: u64,
 }
 
-/// Global GIC instances
+impl GicV2VirtualInterface {
+    /// LR state: Invalid (00)
+    pub const LR_STATE_INVALID: u32 = 0b00;
+    /// LR state: Pending (01)
+    pub const LR_STATE_PENDING: u32 = 0b01;
+
+    pub const fn new(base: u64) -> Self {
+        Self { base }
+    }
+
+    /// Read a 32-bit register
+    fn read_reg(&self, offset: u64) -> u32 {
+        unsafe { read_volatile((self.base + offset) as *const u32) }
+    }
+
+    /// Write a 32-bit register
+    fn write_reg(&self, offset: u64, value: u32) {
+        unsafe { write_volatile((self.base + offset) as *mut u32, value) }
+    }
+
+    /// Enable the virtual CPU interface
+    pub fn init(&self) {
+        self.write_reg(GICH_HCR, GICH_HCR_EN);
+    }
+
+    /// Get number of implemented list registers
+    pub fn num_list_registers(&self) -> u32 {
+        (self.read_reg(GICH_VTR) & GICH_VTR_LISTREGS_MASK) + 1
+    }
+
+    /// Read GICH_VMCR (the guest's virtual GICC_CTLR/PMR/BPR view)
+    pub fn read_vmcr(&self) -> u32 {
+        self.read_reg(GICH_VMCR)
+    }
+
+    /// Write GICH_VMCR
+    pub fn write_vmcr(&self, value: u32) {
+        self.write_reg(GICH_VMCR, value)
+    }
+
+    /// Read List Register `n`
+    pub fn read_lr(&self, n: u32) -> u32 {
+        self.read_reg(GICH_LR0 + n as u64 * 4)
+    }
+
+    /// Write List Register `n`
+    pub fn write_lr(&self, n: u32, value: u32) {
+        self.write_reg(GICH_LR0 + n as u64 * 4, value)
+    }
+
+    /// Build a pending Group 1 List Register value.
+    ///
+    /// GICH_LR keeps only the top 5 bits of the priority.
+    pub const fn build_lr(intid: u32, priority: u8) -> u32 {
+        (Self::LR_STATE_PENDING << GICH_LR_STATE_SHIFT)
+            | GICH_LR_GRP1
+            | (((priority >> 3) as u32) << GICH_LR_PRIORITY_SHIFT)
+            | (intid & GICH_LR_VIRTUALID_MASK)
+    }
+
+    /// Extract the state field from a List Register value
+    #[inline]
+    pub const fn get_lr_state(lr: u32) -> u32 {
+        (lr >> GICH_LR_STATE_SHIFT) & GICH_LR_STATE_MASK
+    }
+
+    /// Extract the virtual INTID field from a List Register value
+    #[inline]
+    pub const fn get_lr_intid(lr: u32) -> u32 {
+        lr & GICH_LR_VIRTUALID_MASK
+    }
+
+    /// Only Invalid LRs are free; see `GicV3VirtualInterface::lr_is_free`.
+    #[inline]
+    pub const fn lr_is_free(lr: u32) -> bool {
+        Self::get_lr_state(lr) == Self::LR_STATE_INVALID
+    }
+
+    /// Number of List Registers holding a pending (or pending+active) interrupt
+    pub fn pending_count(&self) -> usize {
+        (0..self.num_list_registers())
+            .filter(|&i| Self::get_lr_state(self.read_lr(i)) & Self::LR_STATE_PENDING != 0)
+            .count()
+    }
+
+    /// Find a free (invalid state) List Register
+    pub fn find_free_lr(&self) -> Option<u32> {
+        (0..self.num_list_registers()).find(|&i| Self::lr_is_free(self.read_lr(i)))
+    }
+
+    /// Inject a virtual interrupt into the guest
+    pub fn inject_interrupt(&self, intid: u32, priority: u8) -> Result<(), &'static str> {
+        let i = self
+            .find_free_lr()
+            .ok_or("No free list register for interrupt injection")?;
+        self.write_lr(i, Self::build_lr(intid, priority));
+        Ok(())
+    }
+
+    /// Inject a hardware-linked virtual interrupt (HW=1): the guest's
+    /// virtual EOI deactivates physical interrupt `pintid`.
+    pub fn inject_hw_interrupt(
+        &self,
+        intid: u32,
+        pintid: u32,
+        priority: u8,
+    ) -> Result<(), &'static str> {
+        let i = self
+            .find_free_lr()
+            .ok_or("No free list register for interrupt injection")?;
+        let lr = Self::build_lr(intid, priority)
+            | GICH_LR_HW
+            | ((pintid & GICH_LR_VIRTUALID_MASK) << GICH_LR_PHYSICALID_SHIFT);
+        self.write_lr(i, lr);
+        Ok(())
+    }
+
+    /// Clear a virtual interrupt from list registers
+    pub fn clear_interrupt(&self, intid: u32) {
+        for i in 0..self.num_list_registers() {
+            let lr = self.read_lr(i);
+            if !Self::lr_is_free(lr) && Self::get_lr_intid(lr) == intid {
+                self.write_lr(i, 0);
+                return;
+            }
+        }
+    }
+}
+
+/// Global GIC distributor instance
 pub static GICD: GicDistributor = GicDistributor::new(platform::GICD_BASE);
-pub static GICC: GicCpuInterface = GicCpuInterface::new(platform::GICC_BASE);
 
-/// Initialize the GIC
+/// Set once `init` has brought up the GICv2 driver
+static GICV2_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the GICv2 driver was selected at boot
+pub fn is_active() -> bool {
+    GICV2_ACTIVE.load(Ordering::Relaxed)
+}
+
+/// GICC at the base discovered from the host DTB
+pub fn gicc() -> GicCpuInterface {
+    GicCpuInterface::new(dtb::platform_info().gicc_base)
+}
+
+/// GICH at the base discovered from the host DTB
+pub fn gich() -> GicV2VirtualInterface {
+    GicV2VirtualInterface::new(dtb::platform_info().gich_base)
+}
+
+// Host interrupt operations, dispatched to the driver `init` selected:
+// GICC/GICH on a GICv2 host, the ICC_*/ICH_* system registers otherwise.
+
+/// Acknowledge the highest-priority pending Group 1 interrupt. Returns the
+/// raw IAR value, which `end_of_interrupt`/`deactivate` take back unchanged
+/// (a GICv2 SGI carries its source CPU in it); `iar_intid` extracts the INTID.
+pub fn acknowledge() -> u32 {
+    if is_active() {
+        gicc().acknowledge()
+    } else {
+        GicV3SystemRegs::read_iar1()
+    }
+}
+
+/// INTID of an IAR value returned by `acknowledge`
+pub fn iar_intid(iar: u32) -> u32 {
+    if is_active() {
+        iar & GICC_IAR_INTID_MASK
+    } else {
+        iar
+    }
+}
+
+/// Priority drop for `iar` (EOImode=1 on both drivers)
+pub fn end_of_interrupt(iar: u32) {
+    if is_active() {
+        gicc().end_of_interrupt(iar)
+    } else {
+        GicV3SystemRegs::write_eoir1(iar)
+    }
+}
+
+/// Deactivate `iar` after its priority drop
+pub fn deactivate(iar: u32) {
+    if is_active() {
+        gicc().deactivate(iar)
+    } else {
+        GicV3SystemRegs::write_dir(iar)
+    }
+}
+
+/// Inject a pending Group 1 virtual interrupt into the running vCPU's LRs
+pub fn inject_interrupt(intid: u32, priority: u8) -> Result<(), &'static str> {
+    if is_active() {
+        gich().inject_interrupt(intid, priority)
+    } else {
+        GicV3VirtualInterface::inject_interrupt(intid, priority)
+    }
+}
+
+/// Inject a hardware-linked virtual interrupt (HW=1) for physical `pintid`
+pub fn inject_hw_interrupt(intid: u32, pintid: u32, priority: u8) -> Result<(), &'static str> {
+    if is_active() {
+        gich().inject_hw_interrupt(intid, pintid, priority)
+    } else {
+        GicV3VirtualInterface::inject_hw_interrupt(intid, pintid, priority)
+    }
+}
+
+/// Number of the running vCPU's LRs with a pending interrupt
+pub fn pending_count() -> usize {
+    if is_active() {
+        gich().pending_count()
+    } else {
+        GicV3VirtualInterface::pending_count()
+    }
+}
+
+/// Pick the GIC driver: GICv2 if the DTB describes one, or if the CPU
+/// lacks the GICv3 system-register interface (`sysreg_gic` false).
+pub fn select_driver(info: &PlatformInfo, sysreg_gic: bool) -> GicVersion {
+    if info.gic_version == GicVersion::V2 || !sysreg_gic {
+        GicVersion::V2
+    } else {
+        GicVersion::V3
+    }
+}
+
+/// Initialize the GICv2 CPU and virtual interfaces
 pub fn init() {
-    crate::uart_puts(b"[GIC] Initializing GICv2 (system register interface)...\n");
+    crate::uart_puts(b"[GIC] Initializing GICv2 (memory-mapped GICC/GICH)...\n");
 
-    // For GICv2 in QEMU, we'll skip detailed initialization for now
-    // The GIC should be in a usable state by default
-    // We'll enable interrupt routing through HCR_EL2 (already done in exception::init)
+    gicc().init();
+    let gich = gich();
+    gich.init();
+    GICV2_ACTIVE.store(true, Ordering::Relaxed);
 
-    crate::uart_puts(b"[GIC] Using default GIC configuration\n");
+    crate::uart_puts(b"[GIC] GICv2 list registers: ");
+    crate::uart_put_hex(gich.num_list_registers() as u64);
+    crate::uart_puts(b"\n");
 }
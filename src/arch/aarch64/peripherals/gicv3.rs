@@ -323,6 +323,33 @@ impl GicV3VirtualInterface {
         }
     }
 
+    /// Read ICH_AP1R0_EL2 - Group 1 active priorities (one bit per
+    /// priority group with a virtual interrupt in progress)
+    #[inline]
+    pub fn read_ap1r0() -> u32 {
+        let ap: u64;
+        unsafe {
+            asm!(
+                "mrs {ap}, ICH_AP1R0_EL2",
+                ap = out(reg) ap,
+                options(nostack, nomem),
+            );
+        }
+        ap as u32
+    }
+
+    /// Write ICH_AP1R0_EL2
+    #[inline]
+    pub fn write_ap1r0(value: u32) {
+        unsafe {
+            asm!(
+                "msr ICH_AP1R0_EL2, {value}",
+                value = in(reg) value as u64,
+                options(nostack, nomem),
+            );
+        }
+    }
+
     /// Read ICH_VTR_EL2 - VGIC Type Register
     #[inline]
     pub fn read_vtr() -> u32 {
@@ -378,18 +405,27 @@ impl GicV3VirtualInterface {
 
     /// Inject a virtual interrupt into the guest
     pub fn inject_interrupt(intid: u32, priority: u8) -> Result<(), &'static str> {
+        Self::inject_interrupt_in_group(intid, priority, true)
+    }
+
+    /// Inject a virtual interrupt as Group 1 (vIRQ) or, with `group1`
+    /// false, Group 0 (vFIQ)
+    pub fn inject_interrupt_in_group(
+        intid: u32,
+        priority: u8,
+        group1: bool,
+    ) -> Result<(), &'static str> {
+        let group = if group1 { LR_GROUP1_BIT } else { 0 };
         // Find a free list register
         let vtr = Self::read_vtr();
         let num_lrs = ((vtr & VTR_LISTREGS_MASK) + 1) as u32;
 
         for i in 0..num_lrs {
             let lr = Self::read_lr(i);
-            let state = (lr >> LR_STATE_SHIFT) & LR_STATE_MASK;
 
-            // If state is 00 (Invalid), this LR is free
-            if state == 0 {
+            if Self::lr_is_free(lr) {
                 let lr_value = (Self::LR_STATE_PENDING << LR_STATE_SHIFT)
-                    | LR_GROUP1_BIT
+                    | group
                     | ((priority as u64) << LR_PRIORITY_SHIFT)
                     | (intid as u64);
 
@@ -422,9 +458,8 @@ impl GicV3VirtualInterface {
         // Find a free LR and inject
         for i in 0..num_lrs {
             let lr = Self::read_lr(i);
-            let state = (lr >> LR_STATE_SHIFT) & LR_STATE_MASK;
 
-            if state == 0 {
+            if Self::lr_is_free(lr) {
                 let lr_value = (Self::LR_STATE_PENDING << LR_STATE_SHIFT)
                     | LR_HW_BIT
                     | LR_GROUP1_BIT
@@ -457,6 +492,22 @@ impl GicV3VirtualInterface {
         }
     }
 
+    /// Deactivate `intid` in the list registers: Active becomes Invalid,
+    /// Pending+Active becomes Pending. Returns whether an LR held it active.
+    pub fn deactivate_interrupt(intid: u32) -> bool {
+        for i in 0..Self::num_list_registers() {
+            let lr = Self::read_lr(i);
+            let state = Self::get_lr_state(lr);
+            if state & Self::LR_STATE_ACTIVE != 0 && Self::get_lr_intid(lr) == intid {
+                let lr = lr & !(Self::LR_STATE_ACTIVE << LR_STATE_SHIFT);
+                let free = Self::get_lr_state(lr) == Self::LR_STATE_INVALID;
+                Self::write_lr(i, if free { 0 } else { lr });
+                return true;
+            }
+        }
+        false
+    }
+
     /// Initialize virtual interrupt interface
     pub fn init() {
         // Enable virtual interrupts + TALL1 (trap ICC_SGI1R_EL1 writes from EL1).
@@ -517,13 +568,33 @@ impl GicV3VirtualInterface {
         ((lr >> LR_PRIORITY_SHIFT) & 0xFF) as u8
     }
 
+    /// Whether a List Register value may be reused for a new interrupt.
+    ///
+    /// Only Invalid LRs are free. Pending, Active and Pending+Active LRs
+    /// belong to an interrupt the guest has not yet deactivated; reusing one
+    /// would lose it. The other fields of an Invalid LR are ignored; they may
+    /// hold a stale INTID and must be overwritten in full.
+    #[inline]
+    pub fn lr_is_free(lr: u64) -> bool {
+        match Self::get_lr_state(lr) {
+            Self::LR_STATE_INVALID => true,
+            Self::LR_STATE_PENDING | Self::LR_STATE_ACTIVE | Self::LR_STATE_PENDING_ACTIVE => false,
+            _ => false, // the state field is 2 bits wide
+        }
+    }
+
+    /// Index of the first free LR in a saved `ich_lr` array.
+    pub fn free_lr_index(lrs: &[u64]) -> Option<usize> {
+        lrs.iter().position(|&lr| Self::lr_is_free(lr))
+    }
+
     /// Find a free (invalid state) List Register
     pub fn find_free_lr() -> Option<usize> {
         let num_lrs = Self::num_list_registers() as usize;
 
         for i in 0..num_lrs {
             let lr = Self::read_lr(i as u32);
-            if Self::get_lr_state(lr) == Self::LR_STATE_INVALID {
+            if Self::lr_is_free(lr) {
                 return Some(i);
             }
         }
@@ -583,11 +654,51 @@ pub fn is_gicv3_available() -> bool {
     gic_version >= 1
 }
 
+/// GICR_WAKER.ProcessorSleep (bit 1)
+pub const GICR_WAKER_PROCESSOR_SLEEP: u32 = 1 << 1;
+/// GICR_WAKER.ChildrenAsleep (bit 2, read-only)
+pub const GICR_WAKER_CHILDREN_ASLEEP: u32 = 1 << 2;
+/// Polls of GICR_WAKER before a redistributor wake is given up.
+pub const GICR_WAKE_SPIN_LIMIT: u32 = 1_000_000;
+
+/// Number of redistributor wakes that timed out since boot.
+static GICR_WAKE_TIMEOUTS: core::sync::atomic::AtomicU32 = core::sync::atomic::AtomicU32::new(0);
+
+/// Number of redistributor wakes that timed out since boot.
+pub fn gicr_wake_timeouts() -> u32 {
+    GICR_WAKE_TIMEOUTS.load(core::sync::atomic::Ordering::Relaxed)
+}
+
+/// Wake the redistributor whose RD frame is at `rd_base`: clear
+/// GICR_WAKER.ProcessorSleep and poll until ChildrenAsleep clears.
+///
+/// Gives up after `spin_limit` polls with a "GICR wake timeout" diagnostic
+/// instead of hanging the boot. Returns whether the redistributor woke.
+pub fn wake_redistributor(rd_base: u64, spin_limit: u32) -> bool {
+    let waker_addr = (rd_base + crate::platform::GICR_WAKER_OFF) as *mut u32;
+    unsafe {
+        let waker = core::ptr::read_volatile(waker_addr);
+        core::ptr::write_volatile(waker_addr, waker & !GICR_WAKER_PROCESSOR_SLEEP);
+        for _ in 0..spin_limit {
+            if core::ptr::read_volatile(waker_addr) & GICR_WAKER_CHILDREN_ASLEEP == 0 {
+                return true;
+            }
+            core::hint::spin_loop();
+        }
+    }
+    GICR_WAKE_TIMEOUTS.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+    crate::uart_puts(b"[GIC] GICR wake timeout on frame 0x");
+    crate::uart_put_hex(rd_base);
+    crate::uart_puts(b"\n");
+    false
+}
+
 /// Initialize GICv3 for hypervisor use
 pub fn init() {
     crate::uart_puts(b"[GIC] Checking GICv3/v4 availability...\n");
 
-    if !is_gicv3_available() {
+    let info = crate::dtb::platform_info();
+    if super::gic::select_driver(info, is_gicv3_available()) == crate::dtb::GicVersion::V2 {
         crate::uart_puts(b"[GIC] GICv3 not available, falling back to GICv2\n");
         super::gic::init();
         return;
@@ -595,10 +706,12 @@ pub fn init() {
 
     crate::uart_puts(b"[GIC] Initializing GICv3/v4 (system register interface)...\n");
 
-    // Configure ICC_SRE_EL2 - CRITICAL for guest interrupt handling
-    let sre_el2: u32 = ICC_SRE_SRE | ICC_SRE_ENABLE;
+    // Configure ICC_SRE_EL2 - CRITICAL for guest interrupt handling.
+    // Enable=0 traps guest ICC_SRE_EL1 accesses to EL2, where SRE is
+    // emulated as RAO/WI (see emulate_mrs/emulate_msr).
+    let sre_el2: u32 = ICC_SRE_SRE;
     GicV3SystemRegs::write_sre_el2(sre_el2);
-    crate::uart_puts(b"[GIC] ICC_SRE_EL2 configured (Enable=1, SRE=1)\n");
+    crate::uart_puts(b"[GIC] ICC_SRE_EL2 configured (Enable=0, SRE=1)\n");
 
     // Also set ICC_SRE_EL1 to enable system register interface for guest
     GicV3SystemRegs::write_sre_el1(ICC_SRE_SRE);
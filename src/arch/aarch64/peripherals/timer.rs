@@ -6,7 +6,10 @@ use super::super::defs::*;
 /// - Virtual Timer (EL1): Accessed via CNTVCT_EL0, CNTV_*
 /// - Hypervisor Timer (EL2): Accessed via CNTHCTL_EL2
 ///
-/// For guest VMs, we use the Virtual Timer which generates PPI 27.
+/// For guest VMs, we use the Virtual Timer which generates PPI 27. Guest
+/// accesses to the EL1 Physical Timer (PPI 30) trap to EL2 and are
+/// emulated on top of the hardware CNTP registers, which hold the current
+/// vCPU's comparator while it runs.
 use core::arch::asm;
 
 /// Timer control register bits
@@ -14,6 +17,8 @@ const TIMER_ENABLE: u64 = 1 << 0; // Enable timer
 #[allow(dead_code)]
 const TIMER_IMASK: u64 = 1 << 1; // Interrupt mask (1 = masked)
 const TIMER_ISTATUS: u64 = 1 << 2; // Interrupt status (read-only)
+/// CTL bits a guest can write (ISTATUS is read-only)
+pub const TIMER_CTL_WRITABLE: u64 = TIMER_ENABLE | TIMER_IMASK;
 
 /// Read the virtual counter frequency
 pub fn get_frequency() -> u64 {
@@ -81,6 +86,54 @@ pub fn set_tval(tval: u32) {
     }
 }
 
+/// Read the physical timer control register (the guest's CNTP_CTL_EL0)
+pub fn get_ptimer_ctl() -> u64 {
+    let ctl: u64;
+    unsafe {
+        asm!("mrs {}, cntp_ctl_el0", out(reg) ctl);
+    }
+    ctl
+}
+
+/// Write the physical timer control register
+pub fn set_ptimer_ctl(ctl: u64) {
+    unsafe {
+        asm!("msr cntp_ctl_el0, {}", "isb", in(reg) ctl);
+    }
+}
+
+/// Read the physical timer compare value
+pub fn get_ptimer_cval() -> u64 {
+    let cval: u64;
+    unsafe {
+        asm!("mrs {}, cntp_cval_el0", out(reg) cval);
+    }
+    cval
+}
+
+/// Write the physical timer compare value
+pub fn set_ptimer_cval(cval: u64) {
+    unsafe {
+        asm!("msr cntp_cval_el0, {}", "isb", in(reg) cval);
+    }
+}
+
+/// Read the physical timer countdown value (signed CVAL - CNTPCT)
+pub fn get_ptimer_tval() -> u32 {
+    let tval: u64;
+    unsafe {
+        asm!("mrs {0:x}, cntp_tval_el0", out(reg) tval);
+    }
+    tval as u32
+}
+
+/// Write the physical timer countdown value: CVAL = CNTPCT + sign-extended `tval`
+pub fn set_ptimer_tval(tval: u32) {
+    unsafe {
+        asm!("msr cntp_tval_el0, {0:x}", "isb", in(reg) tval as u64);
+    }
+}
+
 /// Configure hypervisor control of timers
 pub fn init_hypervisor_timer() {
     let mut cnthctl: u64;
@@ -103,15 +156,18 @@ pub fn init_guest_timer() {
         asm!("mrs {}, cnthctl_el2", out(reg) cnthctl);
     }
 
-    // Allow EL1 access to physical counter
+    // Allow EL1 access to physical counter; trap the physical timer
+    // (CNTP_CTL/CVAL/TVAL) so it is emulated per vCPU
     cnthctl |= CNTHCTL_EL1PCTEN;
+    cnthctl &= !CNTHCTL_EL1PCEN;
 
     unsafe {
         asm!("msr cnthctl_el2, {}", in(reg) cnthctl);
         asm!("isb");
     }
 
-    // Set virtual timer offset to 0
+    // Clear the virtual timer offset; each vCPU's own CNTVOFF_EL2 is loaded
+    // on entry by VcpuArchState::restore()
     unsafe {
         asm!("msr cntvoff_el2, xzr");
         asm!("isb");
@@ -130,6 +186,25 @@ pub fn is_guest_vtimer_pending() -> bool {
     enabled && pending && !masked
 }
 
+/// Check if the guest's virtual timer is enabled and unmasked, i.e. the
+/// guest is expecting timer interrupts (a tickless guest may leave it off)
+pub fn is_guest_vtimer_armed() -> bool {
+    let ctl = get_ctl();
+    (ctl & TIMER_ENABLE) != 0 && (ctl & TIMER_IMASK) == 0
+}
+
+/// Check if the guest's physical timer is enabled, unmasked and expired
+pub fn is_guest_ptimer_pending() -> bool {
+    let ctl = get_ptimer_ctl();
+    (ctl & TIMER_ENABLE) != 0 && (ctl & TIMER_IMASK) == 0 && (ctl & TIMER_ISTATUS) != 0
+}
+
+/// Mask the guest's physical timer interrupt once it has been injected;
+/// the guest unmasks it when it re-arms the timer, as with the virtual timer
+pub fn mask_guest_ptimer() {
+    set_ptimer_ctl((get_ptimer_ctl() & TIMER_CTL_WRITABLE) | TIMER_IMASK);
+}
+
 /// Mask the guest's virtual timer interrupt
 pub fn mask_guest_vtimer() {
     let mut ctl: u64;
@@ -155,16 +230,23 @@ pub fn disable_timer() {
     set_ctl(0);
 }
 
+/// Preemption time slice (10ms)
+pub const PREEMPTION_SLICE_NS: u64 = 10_000_000;
+
+/// Absolute CNTHP compare value for a preemption slice starting now.
+pub fn preemption_deadline() -> u64 {
+    crate::time::deadline_after_ns(PREEMPTION_SLICE_NS)
+}
+
 /// Arm the EL2 hypervisor physical timer (CNTHP) for preemption.
 ///
 /// This timer is independent of the guest virtual timer and fires INTID 26.
 /// Used as a preemption watchdog to guarantee context switches even when
 /// the guest timer is masked (e.g., during multi_cpu_stop with IRQs disabled).
 pub fn arm_preemption_timer() {
-    // 10ms at counter frequency
-    let ticks = get_frequency() / 100;
+    let deadline = preemption_deadline();
     unsafe {
-        asm!("msr cnthp_tval_el2, {}", in(reg) ticks, options(nostack, nomem));
+        asm!("msr cnthp_cval_el2, {}", in(reg) deadline, options(nostack, nomem));
         asm!("msr cnthp_ctl_el2, {}", in(reg) 1u64, options(nostack, nomem)); // ENABLE=1, IMASK=0
         asm!("isb", options(nostack, nomem));
     }
@@ -178,6 +260,15 @@ pub fn disarm_preemption_timer() {
     }
 }
 
+/// Whether the preemption timer (CNTHP) is armed.
+pub fn preemption_timer_armed() -> bool {
+    let ctl: u64;
+    unsafe {
+        asm!("mrs {}, cnthp_ctl_el2", out(reg) ctl, options(nostack, nomem));
+    }
+    ctl & TIMER_ENABLE != 0
+}
+
 /// Check if timer interrupt is pending
 pub fn is_pending() -> bool {
     let ctl = get_ctl();
@@ -13,7 +13,11 @@ use core::fmt;
 /// - x0-x30: General purpose registers
 /// - x29: Frame Pointer (FP)
 /// - x30: Link Register (LR)
-/// - SP: Stack Pointer (separate from x31)
+/// - SP: Stack Pointer (separate from the GP file)
+///
+/// Encoding 31 names XZR in a data operand but SP in a load/store base
+/// operand; `get_reg`/`set_reg` model the former, `VcpuContext::get_base_reg`
+/// the latter.
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct GeneralPurposeRegs {
@@ -92,7 +96,7 @@ impl GeneralPurposeRegs {
     /// Get value of a general purpose register
     ///
     /// # Arguments
-    /// * `reg` - Register number (0-30, x31/SP not accessible)
+    /// * `reg` - Register number (0-30; 31 is XZR and reads as zero)
     pub fn get_reg(&self, reg: u8) -> u64 {
         match reg {
             0 => self.x0,
@@ -133,7 +137,7 @@ impl GeneralPurposeRegs {
     /// Set value of a general purpose register
     ///
     /// # Arguments
-    /// * `reg` - Register number (0-30, x31/SP not accessible)
+    /// * `reg` - Register number (0-30; writes to 31/XZR are discarded)
     /// * `value` - Value to set
     pub fn set_reg(&mut self, reg: u8, value: u64) {
         match reg {
@@ -265,7 +269,8 @@ pub struct VcpuContext {
     /// System registers
     pub sys_regs: SystemRegs,
 
-    /// Stack pointer for this vCPU context
+    /// Stack pointer saved at exception entry. On a guest trap this is
+    /// the hypervisor's SP_EL2, not the guest's; see `guest_sp()`.
     pub sp: u64,
 
     /// Program counter - where to resume execution
@@ -275,8 +280,22 @@ pub struct VcpuContext {
     /// Saved on exception entry, restored on ERET.
     /// Handlers can modify this (e.g., clear I bit to unmask guest IRQ).
     pub spsr_el2: u64,
+
+    /// Address of the vCPU's `FpState` save area, or 0 if the guest's
+    /// FP/SIMD state is not switched (it then runs untrapped and shares
+    /// the live registers). Set by `Vcpu::run()` before each entry.
+    pub fp_state: u64,
+
+    /// Non-zero once the guest has used FP/SIMD since `enter_guest`: its
+    /// registers are saved on exception entry and reloaded before ERET.
+    /// Cleared on every entry, set by the FP/SIMD access trap.
+    pub fp_live: u64,
 }
 
+// exception.S hard-codes the lazy FP/SIMD fields' offsets
+const _: () = assert!(core::mem::offset_of!(VcpuContext, fp_state) == 408);
+const _: () = assert!(core::mem::offset_of!(VcpuContext, fp_live) == 416);
+
 impl Default for VcpuContext {
     fn default() -> Self {
         Self {
@@ -285,6 +304,8 @@ impl Default for VcpuContext {
             sp: 0,
             pc: 0,
             spsr_el2: SPSR_EL1H_DAIF_MASKED,
+            fp_state: 0,
+            fp_live: 0,
         }
     }
 }
@@ -318,6 +339,54 @@ impl VcpuContext {
         self.gp_regs.set_reg(reg, value);
     }
 
+    /// Stack pointer the guest was using when it trapped, selected by
+    /// SPSR_EL2.M: SP_EL1 for EL1h, SP_EL0 for EL1t and EL0t.
+    ///
+    /// SP_EL0 is not part of the trap frame, but EL2 never uses it, so the
+    /// hardware register still holds the guest's value while handling.
+    pub fn guest_sp(&self) -> u64 {
+        if self.spsr_el2 & SPSR_M_MASK == SPSR_EL1H {
+            self.sys_regs.sp_el1
+        } else {
+            let sp_el0: u64;
+            unsafe {
+                core::arch::asm!("mrs {}, sp_el0", out(reg) sp_el0, options(nostack, nomem));
+            }
+            sp_el0
+        }
+    }
+
+    /// Write the guest stack pointer selected by SPSR_EL2.M (see
+    /// `guest_sp()`); SP_EL1 goes back to the CPU on the exception return.
+    pub fn set_guest_sp(&mut self, value: u64) {
+        if self.spsr_el2 & SPSR_M_MASK == SPSR_EL1H {
+            self.sys_regs.sp_el1 = value;
+        } else {
+            unsafe {
+                core::arch::asm!("msr sp_el0, {}", in(reg) value, options(nostack, nomem));
+            }
+        }
+    }
+
+    /// Value of a load/store base register Rn: 0-30 are x0-x30, 31 is
+    /// the guest SP (never XZR in the addressing context).
+    pub fn get_base_reg(&self, reg: u8) -> u64 {
+        if reg == 31 {
+            self.guest_sp()
+        } else {
+            self.gp_regs.get_reg(reg)
+        }
+    }
+
+    /// Write back a load/store base register Rn (31 = the guest SP).
+    pub fn set_base_reg(&mut self, reg: u8, value: u64) {
+        if reg == 31 {
+            self.set_guest_sp(value);
+        } else {
+            self.gp_regs.set_reg(reg, value);
+        }
+    }
+
     /// Get the exit reason from ESR_EL2
     pub fn exit_reason(&self) -> ExitReason {
         let ec = (self.sys_regs.esr_el2 >> ESR_EC_SHIFT) & ESR_EC_MASK;
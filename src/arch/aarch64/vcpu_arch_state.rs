@@ -1,23 +1,69 @@
 //! Per-vCPU architectural state that must be saved/restored on context switch.
 //!
-//! This includes GICv3 virtual interface registers, virtual timer state,
-//! CPU identity (VMPIDR), and EL1 system registers not saved by exception.S.
+//! This includes GICv3 virtual interface registers, virtual and emulated
+//! physical timer state, CPU identity (VMPIDR), EL1 system registers not
+//! saved by exception.S, and the guest FP/SIMD save area.
 
+use crate::arch::aarch64::peripherals::gic;
 use core::arch::asm;
 
 /// Number of GICv3 list registers to save/restore
-const NUM_LRS: usize = 4;
+pub const NUM_LRS: usize = 4;
+
+/// Guest FP/SIMD registers (Q0-Q31, FPSR, FPCR).
+///
+/// Switched lazily by exception.S: the guest's first FP/SIMD instruction
+/// after `enter_guest` traps (CPTR_EL2.TFP), the registers are loaded from
+/// here, and from then on they are saved on every exception entry and
+/// reloaded before every ERET until the guest exits. The layout (q at 0,
+/// fpsr at 512, fpcr at 520) is hard-coded there.
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+pub struct FpState {
+    pub q: [u128; 32],
+    pub fpsr: u64,
+    pub fpcr: u64,
+}
+
+impl FpState {
+    /// Architectural reset state: all registers zero
+    pub const fn new() -> Self {
+        Self {
+            q: [0; 32],
+            fpsr: 0,
+            fpcr: 0,
+        }
+    }
+}
+
+impl Default for FpState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 /// Per-vCPU architectural state
+#[derive(Clone, Copy)]
 pub struct VcpuArchState {
     // GICv3 virtual interface
     pub ich_lr: [u64; NUM_LRS],
     pub ich_vmcr: u64,
     pub ich_hcr: u64,
 
+    // GICv2 virtual interface (GICH), switched instead of ICH_* on a GICv2 host
+    pub gich_lr: [u32; NUM_LRS],
+    pub gich_vmcr: u32,
+
     // Virtual timer
     pub cntv_ctl: u64,
     pub cntv_cval: u64,
+    /// CNTVOFF_EL2: the guest sees CNTVCT = CNTPCT - cntvoff
+    pub cntvoff: u64,
+
+    // Physical timer (guest accesses trap; the hardware CNTP holds the
+    // running vCPU's comparator)
+    pub cntp_ctl: u64,
+    pub cntp_cval: u64,
 
     // CPU identity
     pub vmpidr: u64,
@@ -58,6 +104,9 @@ pub struct VcpuArchState {
     pub apdb_key_hi: u64,
     pub apga_key_lo: u64,
     pub apga_key_hi: u64,
+
+    // FP/SIMD (switched lazily by exception.S, not by save()/restore())
+    pub fp: FpState,
 }
 
 impl VcpuArchState {
@@ -67,8 +116,13 @@ impl VcpuArchState {
             ich_lr: [0; NUM_LRS],
             ich_vmcr: 0,
             ich_hcr: 0,
+            gich_lr: [0; NUM_LRS],
+            gich_vmcr: 0,
             cntv_ctl: 0,
             cntv_cval: 0,
+            cntvoff: 0,
+            cntp_ctl: 0,
+            cntp_cval: 0,
             vmpidr: 0,
             sctlr_el1: 0,
             ttbr0_el1: 0,
@@ -103,21 +157,33 @@ impl VcpuArchState {
             apdb_key_hi: 0,
             apga_key_lo: 0,
             apga_key_hi: 0,
+            fp: FpState::new(),
         }
     }
 
+    /// Replace the affinity fields of VMPIDR (see
+    /// `global::MPIDR_AFFINITY_MASK`), keeping its other bits.
+    pub fn set_affinity(&mut self, affinity: u64) {
+        let mask = crate::global::MPIDR_AFFINITY_MASK;
+        self.vmpidr = (self.vmpidr & !mask) | (affinity & mask);
+    }
+
     /// Initialize state for a specific vCPU ID
     ///
     /// Sets VMPIDR based on MPIDR layout (Aff0 = vcpu_id),
-    /// and default GIC/timer values.
+    /// and default GIC/timer values. The counter offset is the current
+    /// physical count, so the virtual counter starts near zero; VMs whose
+    /// vCPUs must agree on time override it with `Vcpu::set_counter_offset`.
     pub fn init_for_vcpu(&mut self, vcpu_id: usize) {
-        // VMPIDR: use real MPIDR as template, override Aff0 with vcpu_id
+        // VMPIDR: use real MPIDR as template (RES1, U, MT), affinity in the
+        // default layout (Aff0 = vcpu_id); the owning VM may re-lay it out
+        // with set_affinity()
         let mpidr: u64;
         unsafe {
             asm!("mrs {}, mpidr_el1", out(reg) mpidr, options(nostack, nomem));
         }
-        // Clear Aff0 (bits [7:0]) and set to vcpu_id
-        self.vmpidr = (mpidr & !0xFF) | (vcpu_id as u64 & 0xFF);
+        self.vmpidr = mpidr;
+        self.set_affinity(vcpu_id as u64 & 0xFF);
 
         // Default GIC virtual interface: enable virtual interrupts + TALL1
         // TALL1 traps ICC_SGI1R_EL1 writes (SGI generation) to EL2 for emulation.
@@ -126,14 +192,40 @@ impl VcpuArchState {
                                       // VMCR: VPMR=0xFF (allow all priorities), VENG1=1 (enable Group 1)
         self.ich_vmcr = (0xFF << 24) | (1 << 1);
         self.ich_lr = [0; NUM_LRS];
+        // GICv2 equivalent: VMPriMask=0x1F (allow all), VMGrp1En=1
+        self.gich_vmcr = (0x1F << 27) | (1 << 1);
+        self.gich_lr = [0; NUM_LRS];
 
         // Timer: disabled by default
         self.cntv_ctl = 0;
         self.cntv_cval = 0;
+        self.cntp_ctl = 0;
+        self.cntp_cval = 0;
+        self.cntvoff = crate::time::now_ticks();
     }
 
-    /// Save all per-vCPU registers from hardware
-    pub fn save(&mut self) {
+    /// Save the GICv2 virtual interface from the memory-mapped GICH
+    fn save_gich(&mut self) {
+        let gich = gic::gich();
+        let lrs = (gich.num_list_registers() as usize).min(NUM_LRS);
+        for (i, lr) in self.gich_lr.iter_mut().enumerate().take(lrs) {
+            *lr = gich.read_lr(i as u32);
+        }
+        self.gich_vmcr = gich.read_vmcr();
+    }
+
+    /// Restore the GICv2 virtual interface to the memory-mapped GICH
+    fn restore_gich(&self) {
+        let gich = gic::gich();
+        let lrs = (gich.num_list_registers() as usize).min(NUM_LRS);
+        for (i, &lr) in self.gich_lr.iter().enumerate().take(lrs) {
+            gich.write_lr(i as u32, lr);
+        }
+        gich.write_vmcr(self.gich_vmcr);
+    }
+
+    /// Save the GICv3 virtual interface from the ICH_* system registers
+    fn save_ich(&mut self) {
         unsafe {
             // GICv3 List Registers
             asm!("mrs {}, ICH_LR0_EL2", out(reg) self.ich_lr[0], options(nostack, nomem));
@@ -148,11 +240,41 @@ impl VcpuArchState {
             let hcr: u64;
             asm!("mrs {}, ICH_HCR_EL2", out(reg) hcr, options(nostack, nomem));
             self.ich_hcr = hcr;
+        }
+    }
 
+    /// Restore the GICv3 virtual interface to the ICH_* system registers
+    fn restore_ich(&self) {
+        unsafe {
+            // GICv3 List Registers
+            asm!("msr ICH_LR0_EL2, {}", in(reg) self.ich_lr[0], options(nostack, nomem));
+            asm!("msr ICH_LR1_EL2, {}", in(reg) self.ich_lr[1], options(nostack, nomem));
+            asm!("msr ICH_LR2_EL2, {}", in(reg) self.ich_lr[2], options(nostack, nomem));
+            asm!("msr ICH_LR3_EL2, {}", in(reg) self.ich_lr[3], options(nostack, nomem));
+
+            // GICv3 virtual interface control
+            asm!("msr ICH_VMCR_EL2, {}", in(reg) self.ich_vmcr, options(nostack, nomem));
+            asm!("msr ICH_HCR_EL2, {}", in(reg) self.ich_hcr, options(nostack, nomem));
+        }
+    }
+
+    /// Save all per-vCPU registers from hardware. The ICH_* registers do
+    /// not exist on a GICv2 host, whose GICH is switched instead.
+    pub fn save(&mut self) {
+        if gic::is_active() {
+            self.save_gich();
+        } else {
+            self.save_ich();
+        }
+        unsafe {
             // Virtual timer
             asm!("mrs {}, cntv_ctl_el0", out(reg) self.cntv_ctl, options(nostack, nomem));
             asm!("mrs {}, cntv_cval_el0", out(reg) self.cntv_cval, options(nostack, nomem));
 
+            // Physical timer
+            asm!("mrs {}, cntp_ctl_el0", out(reg) self.cntp_ctl, options(nostack, nomem));
+            asm!("mrs {}, cntp_cval_el0", out(reg) self.cntp_cval, options(nostack, nomem));
+
             // EL1 system registers
             asm!("mrs {}, sctlr_el1", out(reg) self.sctlr_el1, options(nostack, nomem));
             asm!("mrs {}, ttbr0_el1", out(reg) self.ttbr0_el1, options(nostack, nomem));
@@ -195,26 +317,30 @@ impl VcpuArchState {
         }
     }
 
-    /// Restore all per-vCPU registers to hardware
+    /// Restore all per-vCPU registers to hardware (GICH instead of ICH_*
+    /// on a GICv2 host)
     pub fn restore(&self) {
+        if gic::is_active() {
+            self.restore_gich();
+        } else {
+            self.restore_ich();
+        }
         unsafe {
             // CPU identity - must be set before guest runs
             asm!("msr vmpidr_el2, {}", in(reg) self.vmpidr, options(nostack, nomem));
 
-            // GICv3 List Registers
-            asm!("msr ICH_LR0_EL2, {}", in(reg) self.ich_lr[0], options(nostack, nomem));
-            asm!("msr ICH_LR1_EL2, {}", in(reg) self.ich_lr[1], options(nostack, nomem));
-            asm!("msr ICH_LR2_EL2, {}", in(reg) self.ich_lr[2], options(nostack, nomem));
-            asm!("msr ICH_LR3_EL2, {}", in(reg) self.ich_lr[3], options(nostack, nomem));
-
-            // GICv3 virtual interface control
-            asm!("msr ICH_VMCR_EL2, {}", in(reg) self.ich_vmcr, options(nostack, nomem));
-            asm!("msr ICH_HCR_EL2, {}", in(reg) self.ich_hcr, options(nostack, nomem));
-
-            // Virtual timer
+            // Virtual timer (offset first, so the compare is against guest time)
+            asm!("msr cntvoff_el2, {}", in(reg) self.cntvoff, options(nostack, nomem));
             asm!("msr cntv_ctl_el0, {}", in(reg) self.cntv_ctl, options(nostack, nomem));
             asm!("msr cntv_cval_el0, {}", in(reg) self.cntv_cval, options(nostack, nomem));
 
+            // Physical timer (comparator before CTL, so enabling never
+            // compares against the previous vCPU's deadline)
+            asm!("msr cntp_cval_el0, {}", in(reg) self.cntp_cval, options(nostack, nomem));
+            let cntp_ctl =
+                self.cntp_ctl & crate::arch::aarch64::peripherals::timer::TIMER_CTL_WRITABLE;
+            asm!("msr cntp_ctl_el0, {}", in(reg) cntp_ctl, options(nostack, nomem));
+
             // EL1 system registers
             asm!("msr sctlr_el1, {}", in(reg) self.sctlr_el1, options(nostack, nomem));
             asm!("msr ttbr0_el1, {}", in(reg) self.ttbr0_el1, options(nostack, nomem));
@@ -0,0 +1,136 @@
+//! Guest-directed cache maintenance for non-coherent DMA.
+//!
+//! A guest that drives DMA without cacheable (coherent) mappings asks the
+//! hypervisor (hypercall 14) to make an IPA range coherent with memory at
+//! the PoC before handing it to a device, or after the device wrote it:
+//!
+//! - `DMA_TO_DEVICE`: clean (DC CVAC), so the device reads current data.
+//! - `DMA_FROM_DEVICE`: invalidate (DC IVAC), so the guest sees device
+//!   writes. Lines only partly inside the range are cleaned and
+//!   invalidated (DC CIVAC) instead, so neighbouring data is not lost.
+//!
+//! The range is translated page by page through the VM's Stage-2 and
+//! operated on by PA (identity-mapped at EL2).
+
+use crate::arch::aarch64::defs::*;
+use crate::arch::aarch64::regs::VcpuContext;
+use crate::ffa::stage2_walker::Stage2Walker;
+use crate::shared_buffer::{HC_INVALID_IPA, HC_SUCCESS};
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// Hypercall number (x0): x1 = IPA, x2 = length, x3 = direction.
+pub const HC_CACHE_MAINT: u64 = 14;
+
+/// Direction (x3): guest wrote the buffer, device will read it
+pub const DMA_TO_DEVICE: u64 = 0;
+/// Direction (x3): device wrote the buffer, guest will read it
+pub const DMA_FROM_DEVICE: u64 = 1;
+
+/// Length zero or above `MAX_CACHE_MAINT_LEN`, or unknown direction
+pub const HC_INVALID_ARG: u64 = -4i64 as u64;
+
+/// Longest range one hypercall may cover (bounds time spent at EL2)
+pub const MAX_CACHE_MAINT_LEN: u64 = 1024 * 1024;
+
+/// Data cache maintenance operation on one line, by PA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheOp {
+    /// DC CVAC
+    Clean,
+    /// DC IVAC
+    Invalidate,
+    /// DC CIVAC
+    CleanInvalidate,
+}
+
+/// Test hook called with each line operated on (null = not installed)
+static CACHE_OP_HOOK: AtomicPtr<fn(CacheOp, u64)> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Install (or with `None`, remove) a hook that observes every cache line
+/// operation (after it was issued) with the line's PA.
+pub fn set_cache_op_hook(hook: Option<&'static fn(CacheOp, u64)>) {
+    let ptr = hook.map_or(core::ptr::null_mut(), |f| core::ptr::from_ref(f).cast_mut());
+    CACHE_OP_HOOK.store(ptr, Ordering::Release);
+}
+
+/// Smallest data cache line size in bytes (CTR_EL0.DminLine).
+pub fn dcache_line_size() -> u64 {
+    let ctr: u64;
+    unsafe {
+        core::arch::asm!("mrs {}, ctr_el0", out(reg) ctr, options(nomem, nostack));
+    }
+    4u64 << ((ctr >> 16) & 0xF)
+}
+
+fn line_op(op: CacheOp, pa: u64) {
+    unsafe {
+        match op {
+            CacheOp::Clean => core::arch::asm!("dc cvac, {}", in(reg) pa, options(nostack)),
+            CacheOp::Invalidate => core::arch::asm!("dc ivac, {}", in(reg) pa, options(nostack)),
+            CacheOp::CleanInvalidate => {
+                core::arch::asm!("dc civac, {}", in(reg) pa, options(nostack))
+            }
+        }
+    }
+    // SAFETY: the hook is null or was stored from a `&'static fn(CacheOp, u64)`
+    if let Some(hook) = unsafe { CACHE_OP_HOOK.load(Ordering::Acquire).as_ref() } {
+        hook(op, pa);
+    }
+}
+
+/// Clean (`DMA_TO_DEVICE`) or invalidate (`DMA_FROM_DEVICE`) the IPA
+/// range `[ipa, ipa + len)` to the PoC.
+///
+/// The whole range must be mapped in `walker`'s Stage-2; it is checked
+/// before any line is touched.
+pub fn cache_maint_range(
+    walker: &Stage2Walker,
+    ipa: u64,
+    len: u64,
+    direction: u64,
+) -> Result<(), u64> {
+    if len == 0 || len > MAX_CACHE_MAINT_LEN || direction > DMA_FROM_DEVICE {
+        return Err(HC_INVALID_ARG);
+    }
+    if !walker.has_stage2() {
+        return Err(HC_INVALID_IPA);
+    }
+    let end = ipa.checked_add(len).ok_or(HC_INVALID_IPA)?;
+    let mut page = ipa & !PAGE_MASK_4KB;
+    while page < end {
+        walker.translate(page).ok_or(HC_INVALID_IPA)?;
+        page += PAGE_SIZE_4KB;
+    }
+
+    let line = dcache_line_size();
+    let mut addr = ipa & !(line - 1);
+    let (mut page, mut page_pa) = (u64::MAX, 0);
+    while addr < end {
+        if addr & !PAGE_MASK_4KB != page {
+            page = addr & !PAGE_MASK_4KB;
+            page_pa = walker.translate(page).ok_or(HC_INVALID_IPA)?;
+        }
+        let op = if direction == DMA_TO_DEVICE {
+            CacheOp::Clean
+        } else if addr < ipa || addr + line > end {
+            CacheOp::CleanInvalidate
+        } else {
+            CacheOp::Invalidate
+        };
+        line_op(op, page_pa + (addr & PAGE_MASK_4KB));
+        addr += line;
+    }
+    unsafe {
+        core::arch::asm!("dsb sy", options(nostack));
+    }
+    Ok(())
+}
+
+/// Handle hypercall 14 for the current VM; result in x0.
+pub fn handle_cache_maint_hypercall(context: &mut VcpuContext, walker: &Stage2Walker) {
+    let (ipa, len, direction) = (context.gp_regs.x1, context.gp_regs.x2, context.gp_regs.x3);
+    context.gp_regs.x0 = match cache_maint_range(walker, ipa, len, direction) {
+        Ok(()) => HC_SUCCESS,
+        Err(e) => e,
+    };
+}
@@ -0,0 +1,175 @@
+//! DMA address validation for emulated devices (minimal IOMMU stand-in).
+//!
+//! Virtio rings and buffers are addressed by guest IPAs. Instead of turning
+//! those straight into host pointers, devices go through a `DmaMapper`,
+//! which only hands out memory the owning VM can reach itself: every page
+//! of the access must be mapped as Normal memory in the VM's Stage-2 with
+//! the needed S2AP permission, and the backing PAs must be contiguous.
+//! Pages write-protected for dirty tracking count as writable: a device
+//! write to one is logged in the owning VM's dirty bitmap.
+//!
+//! Until a VM has Stage-2 tables (or for transports not bound to a VM), the
+//! access must lie within the guest RAM window instead.
+
+use crate::arch::aarch64::defs::{PAGE_MASK_4KB, PAGE_SIZE_4KB};
+use crate::ffa::stage2_walker::Stage2Walker;
+
+/// S2AP read / write permission bits
+const S2AP_READ: u8 = 0b01;
+const S2AP_WRITE: u8 = 0b10;
+/// MemAttr[3:2] == 0b00 selects Device memory
+const S2_MEMATTR_TYPE_MASK: u8 = 0b1100;
+
+/// Address space a `DmaMapper` validates against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DmaDomain {
+    /// Guest RAM window only (no VM bound)
+    GuestRam,
+    /// Stage-2 of a VM, looked up in `PER_VM_VTTBR`/`PER_VM_VTCR` at access time
+    Vm(usize),
+    /// Explicit Stage-2 L0 table
+    Stage2(u64),
+}
+
+/// Validates and performs device accesses to guest memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DmaMapper {
+    domain: DmaDomain,
+}
+
+impl DmaMapper {
+    /// Mapper not bound to any VM: accesses must stay in guest RAM.
+    pub const fn new() -> Self {
+        Self {
+            domain: DmaDomain::GuestRam,
+        }
+    }
+
+    /// Mapper validating against `vm_id`'s Stage-2 tables.
+    pub const fn for_vm(vm_id: usize) -> Self {
+        Self {
+            domain: DmaDomain::Vm(vm_id),
+        }
+    }
+
+    /// Mapper validating against an explicit Stage-2 L0 table (for testing).
+    pub const fn with_stage2(l0_table: u64) -> Self {
+        Self {
+            domain: DmaDomain::Stage2(l0_table),
+        }
+    }
+
+    fn walker(&self) -> Option<Stage2Walker> {
+        let walker = match self.domain {
+            DmaDomain::GuestRam => return None,
+            DmaDomain::Vm(id) => Stage2Walker::for_vm(id),
+            DmaDomain::Stage2(l0) => Stage2Walker::new(l0),
+        };
+        walker.has_stage2().then_some(walker)
+    }
+
+    /// VM whose Stage-2 this mapper validates against, if any.
+    fn vm_id(&self) -> Option<usize> {
+        match self.domain {
+            DmaDomain::GuestRam => None,
+            DmaDomain::Vm(id) => Some(id),
+            DmaDomain::Stage2(l0) => crate::global::PER_VM_VTTBR
+                .iter()
+                .position(|v| v.load(core::sync::atomic::Ordering::Acquire) == l0),
+        }
+    }
+
+    /// Host address backing `[ipa, ipa + len)`, or `None` if any byte of
+    /// the range is not accessible to the device (writable if `write`).
+    pub fn translate(&self, ipa: u64, len: u64, write: bool) -> Option<u64> {
+        let end = ipa.checked_add(len.max(1))?;
+        let walker = match self.walker() {
+            Some(w) => w,
+            None => {
+                use crate::platform::{GUEST_RAM_BASE, LINUX_MEM_SIZE};
+                let in_ram = ipa >= GUEST_RAM_BASE && end <= GUEST_RAM_BASE + LINUX_MEM_SIZE;
+                return in_ram.then_some(ipa);
+            }
+        };
+
+        let need = if write { S2AP_WRITE } else { S2AP_READ };
+        // Dirty-logged pages are writable only when there is a log to record into
+        let log_vm = if write { self.vm_id() } else { None };
+        let first_page = ipa & !PAGE_MASK_4KB;
+        let base = walker.translate(first_page)?;
+        let mut logged = false;
+        let mut page = first_page;
+        while page < end {
+            let s2ap = walker.read_s2ap(page)?;
+            let mem_attr = walker.read_mem_attr(page)?;
+            let dirty_logged = s2ap & need == 0 && log_vm.is_some() && walker.is_dirty_logged(page);
+            if (s2ap & need == 0 && !dirty_logged) || mem_attr & S2_MEMATTR_TYPE_MASK == 0 {
+                return None;
+            }
+            if walker.translate(page)? != base + (page - first_page) {
+                return None;
+            }
+            logged |= dirty_logged;
+            page += PAGE_SIZE_4KB;
+        }
+        if let Some(vm_id) = log_vm.filter(|_| logged) {
+            let mut page = first_page;
+            while page < end {
+                if walker.is_dirty_logged(page) {
+                    crate::dirty_log::record_dma_write(vm_id, page);
+                }
+                page += PAGE_SIZE_4KB;
+            }
+        }
+        Some(base + (ipa - first_page))
+    }
+
+    /// Copy `buf.len()` bytes from guest memory at `ipa`.
+    pub fn read(&self, ipa: u64, buf: &mut [u8]) -> bool {
+        match self.translate(ipa, buf.len() as u64, false) {
+            Some(pa) => {
+                unsafe {
+                    core::ptr::copy_nonoverlapping(pa as *const u8, buf.as_mut_ptr(), buf.len());
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Copy `data` into guest memory at `ipa`.
+    pub fn write(&self, ipa: u64, data: &[u8]) -> bool {
+        match self.translate(ipa, data.len() as u64, true) {
+            Some(pa) => {
+                unsafe {
+                    core::ptr::copy_nonoverlapping(data.as_ptr(), pa as *mut u8, data.len());
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Volatile read of a `T` (ring index, descriptor, request header).
+    pub fn read_val<T: Copy>(&self, ipa: u64) -> Option<T> {
+        let pa = self.translate(ipa, core::mem::size_of::<T>() as u64, false)?;
+        Some(unsafe { core::ptr::read_volatile(pa as *const T) })
+    }
+
+    /// Volatile write of a `T` (used ring entry, status byte).
+    pub fn write_val<T: Copy>(&self, ipa: u64, val: T) -> bool {
+        match self.translate(ipa, core::mem::size_of::<T>() as u64, true) {
+            Some(pa) => {
+                unsafe { core::ptr::write_volatile(pa as *mut T, val) };
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Default for DmaMapper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,179 @@
+//! Linear framebuffer for headless graphical guests
+//!
+//! Not an MMIO device: a page-aligned range of guest RAM the guest draws
+//! into, described to it by a `simple-framebuffer` DTB node (Linux
+//! simplefb) and kept out of its allocator by a memory reservation. There
+//! is no acceleration and no trapping; the host reads what was rendered
+//! with `snapshot()`, through the VM's Stage-2 like any device DMA.
+
+use crate::arch::aarch64::defs::{PAGE_MASK_4KB, PAGE_SIZE_4KB};
+use crate::devices::dma::DmaMapper;
+
+/// Largest framebuffer accepted (4K at 32 bpp)
+pub const MAX_FRAMEBUFFER_SIZE: u64 = 3840 * 2160 * 4;
+
+/// Pixel layout, named as in the simple-framebuffer binding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 32 bpp, alpha in the top byte
+    A8R8G8B8,
+    /// 32 bpp, top byte unused
+    X8R8G8B8,
+    /// 16 bpp
+    R5G6B5,
+}
+
+impl PixelFormat {
+    pub const fn bytes_per_pixel(self) -> u32 {
+        match self {
+            Self::A8R8G8B8 | Self::X8R8G8B8 => 4,
+            Self::R5G6B5 => 2,
+        }
+    }
+
+    /// `format` property value (NUL-terminated)
+    pub const fn dtb_name(self) -> &'static [u8] {
+        match self {
+            Self::A8R8G8B8 => b"a8r8g8b8\0",
+            Self::X8R8G8B8 => b"x8r8g8b8\0",
+            Self::R5G6B5 => b"r5g6b5\0",
+        }
+    }
+}
+
+/// Framebuffer in guest RAM, addressed by IPA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VirtualFramebuffer {
+    base: u64,
+    width: u32,
+    height: u32,
+    stride: u32,
+    format: PixelFormat,
+}
+
+impl VirtualFramebuffer {
+    /// Framebuffer of `width` x `height` pixels at `base`, rows packed
+    /// (stride = width * bytes per pixel).
+    pub fn new(
+        base: u64,
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+    ) -> Result<Self, &'static str> {
+        if base & PAGE_MASK_4KB != 0 {
+            return Err("Framebuffer base not page-aligned");
+        }
+        if width == 0 || height == 0 {
+            return Err("Empty framebuffer");
+        }
+        let stride = width
+            .checked_mul(format.bytes_per_pixel())
+            .ok_or("Framebuffer too large")?;
+        let size = stride as u64 * height as u64;
+        if size > MAX_FRAMEBUFFER_SIZE || base.checked_add(size).is_none() {
+            return Err("Framebuffer too large");
+        }
+        Ok(Self {
+            base,
+            width,
+            height,
+            stride,
+            format,
+        })
+    }
+
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Bytes per row
+    pub fn stride(&self) -> u32 {
+        self.stride
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// Bytes of guest RAM the framebuffer covers
+    pub fn size(&self) -> u64 {
+        self.stride as u64 * self.height as u64
+    }
+
+    /// Describe the framebuffer in the guest DTB at the start of `blob`:
+    /// a `framebuffer@<base>` root node (`#address-cells` and
+    /// `#size-cells` of 2, as on QEMU virt) and a memory reservation.
+    pub fn add_to_dtb(&self, blob: &mut [u8]) -> Result<(), &'static str> {
+        let mut name = *b"framebuffer@0000000000000000";
+        let digits = (64 - self.base.leading_zeros()).div_ceil(4).max(1) as usize;
+        let len = 12 + digits;
+        for i in 0..digits {
+            let nibble = (self.base >> (4 * (digits - 1 - i))) & 0xF;
+            name[12 + i] = b"0123456789abcdef"[nibble as usize];
+        }
+        let name = core::str::from_utf8(&name[..len]).map_err(|_| "bad node name")?;
+
+        let mut reg = [0u8; 16];
+        reg[..8].copy_from_slice(&self.base.to_be_bytes());
+        reg[8..].copy_from_slice(&self.size().to_be_bytes());
+        crate::dtb::add_mem_reserve(blob, self.base, self.size())?;
+        crate::dtb::add_root_node(
+            blob,
+            name,
+            &[
+                ("compatible", b"simple-framebuffer\0"),
+                ("reg", &reg),
+                ("width", &self.width.to_be_bytes()),
+                ("height", &self.height.to_be_bytes()),
+                ("stride", &self.stride.to_be_bytes()),
+                ("format", self.format.dtb_name()),
+            ],
+        )
+    }
+
+    /// Copy what the guest rendered into `out` (row-major, `stride` bytes
+    /// per row), up to `out.len()` bytes. Returns the number copied, which
+    /// stops short at the first page `dma` does not let us read.
+    ///
+    /// Each page is translated through `dma` (the VM's Stage-2), so the
+    /// copy comes from the memory backing the guest's IPAs. The guest may
+    /// have written with caches off, so the lines are cleaned and
+    /// invalidated first: the copy sees memory, not stale hypervisor cache
+    /// lines.
+    pub fn snapshot(&self, dma: &DmaMapper, out: &mut [u8]) -> usize {
+        let n = out.len().min(self.size() as usize);
+        let line = crate::cache_maint::dcache_line_size();
+        let mut done = 0;
+        while done < n {
+            // `base` is page-aligned, so each chunk is one page
+            let chunk = (n - done).min(PAGE_SIZE_4KB as usize);
+            let Some(pa) = dma.translate(self.base + done as u64, chunk as u64, false) else {
+                break;
+            };
+            let mut addr = pa;
+            while addr < pa + chunk as u64 {
+                unsafe {
+                    core::arch::asm!("dc civac, {}", in(reg) addr, options(nostack));
+                }
+                addr += line;
+            }
+            unsafe {
+                core::arch::asm!("dsb sy", options(nostack));
+            }
+            for (i, byte) in out[done..done + chunk].iter_mut().enumerate() {
+                // SAFETY: `dma` validated [pa, pa + chunk) as guest RAM
+                *byte = unsafe { core::ptr::read_volatile((pa + i as u64) as *const u8) };
+            }
+            done += chunk;
+        }
+        done
+    }
+}
@@ -5,9 +5,27 @@
 /// are served from shadow state (with corrections like ARE_NS read-as-one),
 /// and writes are forwarded to both shadow state and the physical GICD.
 ///
+/// The guest sees a distributor with a single (Non-secure) security state:
+/// GICD_CTLR.DS reads as one, and the registers that only exist with two
+/// security states (GICD_IGRPMODR, GICD_NSACR) are RAZ/WI and never reach
+/// the physical GICD.
+///
+/// SPI priorities (GICD_IPRIORITYR, byte-accessible) and edge/level
+/// configuration (GICD_ICFGR) read back what the guest wrote, at the width
+/// it accesses them; the SGI/PPI copies of both are RAZ/WI, as affinity
+/// routing is always on.
+///
+/// SPI active state (GICD_ISACTIVER/ICACTIVER) combines guest writes with
+/// what the list registers show: `update_active_from_lrs()` marks an SPI
+/// active once the guest acknowledges it and clears it on deactivation.
+/// Deactivated level-triggered SPIs (ICFGR Int_config[1] clear) are
+/// reported back so the caller can re-queue those whose device line
+/// (`global::set_spi_level`) is still high.
+///
 /// Write-through is required because the physical GIC must stay in sync with
 /// the guest's configuration (EnableGrp1NS, ISENABLER, IROUTER, etc.) for
 /// physical interrupt forwarding to work correctly.
+use crate::arch::aarch64::peripherals::gicv3::GicV3VirtualInterface;
 use crate::devices::MmioDevice;
 
 const GICD_SIZE: u64 = 0x10000;
@@ -17,8 +35,13 @@ fn gicd_base() -> u64 {
     crate::dtb::platform_info().gicd_base
 }
 
-/// GICD_CTLR bit definitions
+/// GICD_CTLR bit definitions (single security state layout)
+const GICD_CTLR_ENABLE_GRP0: u32 = 1 << 0;
+const GICD_CTLR_ENABLE_GRP1: u32 = 1 << 1;
 const GICD_CTLR_ARE_NS: u32 = 1 << 4; // Affinity Routing Enable, Non-Secure
+const GICD_CTLR_DS: u32 = 1 << 6; // Disable Security: RAO/WI
+/// Bits the guest can change; ARE_NS and DS are read-as-one
+const GICD_CTLR_WRITABLE: u32 = GICD_CTLR_ENABLE_GRP0 | GICD_CTLR_ENABLE_GRP1;
 
 /// GICD register offsets
 const GICD_CTLR: u64 = 0x000;
@@ -45,17 +68,56 @@ const GICD_ISACTIVER_END: u64 = 0x37C;
 // ICACTIVER: 0x380..0x3FC
 const GICD_ICACTIVER_BASE: u64 = 0x380;
 const GICD_ICACTIVER_END: u64 = 0x3FC;
-// IPRIORITYR: 0x400..0x7FC (256 regs, 4 bytes per reg, 1 byte per interrupt)
+// IPRIORITYR: 0x400..0x7FC (256 regs, 4 bytes per reg, 1 byte per interrupt);
+// byte-accessible, so the range ends at the last byte
 const GICD_IPRIORITYR_BASE: u64 = 0x400;
-const GICD_IPRIORITYR_END: u64 = 0x7FC;
-// ICFGR: 0xC00..0xC3C (16 regs for SPIs, 2 bits per interrupt)
+const GICD_IPRIORITYR_END: u64 = 0x7FF;
+// ICFGR: 0xC00..0xCFC (64 regs, 2 bits per interrupt); ends at the last byte
 const GICD_ICFGR_BASE: u64 = 0xC00;
-const GICD_ICFGR_END: u64 = 0xCFC;
+const GICD_ICFGR_END: u64 = 0xCFF;
+/// ICFGR Int_config[1] (1 = edge) of each field; Int_config[0] is RES0
+const GICD_ICFGR_WRITABLE: u32 = 0xAAAA_AAAA;
+/// First SPI; with affinity routing the SGI/PPI priority and config
+/// registers are RAZ/WI here (they live in the redistributor)
+const FIRST_SPI: usize = 32;
+/// INTIDs 1020-1023 are special, never real interrupts
+const LAST_INTID: usize = 1019;
+// IGRPMODR: 0xD00..0xD7C (RAZ/WI with a single security state)
+const GICD_IGRPMODR_BASE: u64 = 0xD00;
+const GICD_IGRPMODR_END: u64 = 0xD7C;
+// NSACR: 0xE00..0xEFC (RAZ/WI with a single security state)
+const GICD_NSACR_BASE: u64 = 0xE00;
+const GICD_NSACR_END: u64 = 0xEFC;
 // IROUTER: 0x6100..0x7FD8 (64-bit per SPI, SPIs 32-1019)
 const GICD_IROUTER_BASE: u64 = 0x6100;
 const GICD_IROUTER_END: u64 = 0x7FD8;
-// PIDR2: 0xFFE8 (Peripheral ID, reports GIC version)
+// ID registers: 0xFFD0..0xFFFC (PIDR4-7, PIDR0-3, CIDR0-3)
+const GICD_PIDR4: u64 = 0xFFD0;
 const GICD_PIDR2: u64 = 0xFFE8;
+const GICD_CIDR0: u64 = 0xFFF0;
+const GICD_CIDR3: u64 = 0xFFFC;
+
+/// Component/peripheral ID bytes, one per word from PIDR4 to CIDR3.
+///
+/// Identifies a GIC-500 (part 0x492) from ARM (JEP106 0x3B, continuation
+/// code 4); PIDR2 carries ArchRev = 3 (GICv3) with the JEDEC bit set.
+/// PIDR4 packs SIZE = 4 (2^4 4KB pages, the 64KB GICD frame) with DES_2,
+/// the JEP106 continuation code.
+const GICD_ID_REGS: [u8; 12] = [
+    0x44, 0x00, 0x00, 0x00, // PIDR4-7: SIZE 4 (64KB frame), JEP106 continuation 4
+    0x92, 0xB4, 0x3B, 0x00, // PIDR0-3: part 0x492, JEP106 0x3B, ArchRev 3
+    0x0D, 0xF0, 0x05, 0xB1, // CIDR0-3: CoreSight preamble, class 0xF
+];
+
+/// Enable/pending/active/group bitmaps captured for a VM checkpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GicdIrqState {
+    pub enabled: [u32; 32],
+    pub pending: [u32; 32],
+    pub active: [u32; 32],
+    /// IGROUPR bits (1 = Group 1)
+    pub group: [u32; 32],
+}
 
 /// Virtual GICD device
 pub struct VirtualGicd {
@@ -65,8 +127,8 @@ pub struct VirtualGicd {
     enabled: [u32; 32],
     /// Interrupt group assignment (1 bit per interrupt)
     igroupr: [u32; 32],
-    /// Interrupt priority (1 byte per interrupt, packed 4 per u32)
-    ipriorityr: [u32; 256],
+    /// Interrupt priority (1 byte per interrupt)
+    ipriorityr: [u8; 1024],
     /// Interrupt configuration (2 bits per interrupt)
     icfgr: [u32; 64],
     /// Pending state
@@ -77,6 +139,8 @@ pub struct VirtualGicd {
     irouter: [u64; 988],
     /// Number of online vCPUs (for TYPER.CPUNumber)
     num_cpus: u32,
+    /// VM whose vCPU affinities IROUTER names (None = the current VM)
+    owner_vm: Option<usize>,
 }
 
 impl VirtualGicd {
@@ -86,30 +150,195 @@ impl VirtualGicd {
             ctlr: 0,
             enabled: [0; 32],
             igroupr: [0; 32],
-            ipriorityr: [0; 256],
+            ipriorityr: [0; 1024],
             icfgr: [0; 64],
             ispendr: [0; 32],
             isactiver: [0; 32],
             irouter: [0; 988],
             num_cpus: crate::platform::num_cpus() as u32,
+            owner_vm: None,
         }
     }
 
+    /// Resolve IROUTER affinities against VM `vm_id`'s vCPUs rather than
+    /// the current VM's.
+    pub fn set_owner_vm(&mut self, vm_id: usize) {
+        self.owner_vm = Some(vm_id);
+    }
+
     /// Set the number of online vCPUs (affects GICD_TYPER)
     pub fn set_num_cpus(&mut self, n: u32) {
         self.num_cpus = n;
     }
 
+    /// Capture the shadow enable/pending/active state.
+    pub fn irq_state(&self) -> GicdIrqState {
+        GicdIrqState {
+            enabled: self.enabled,
+            pending: self.ispendr,
+            active: self.isactiver,
+            group: self.igroupr,
+        }
+    }
+
+    /// Restore shadow state captured by `irq_state()`.
+    ///
+    /// Only the emulated copy is updated; the physical GICD is not written.
+    pub fn restore_irq_state(&mut self, state: &GicdIrqState) {
+        self.enabled = state.enabled;
+        self.ispendr = state.pending;
+        self.isactiver = state.active;
+        self.igroupr = state.group;
+    }
+
+    /// Whether the guest has enabled `intid` in its shadow ISENABLER.
+    pub fn is_enabled(&self, intid: u32) -> bool {
+        let reg = (intid / 32) as usize;
+        reg < 32 && self.enabled[reg] & (1 << (intid % 32)) != 0
+    }
+
+    /// Whether the guest placed `intid` in Group 1 via its shadow IGROUPR.
+    pub fn is_group1(&self, intid: u32) -> bool {
+        let reg = (intid / 32) as usize;
+        reg < 32 && self.igroupr[reg] & (1 << (intid % 32)) != 0
+    }
+
+    /// Whether `intid` is active in the shadow ISACTIVER.
+    pub fn is_active(&self, intid: u32) -> bool {
+        let reg = (intid / 32) as usize;
+        reg < 32 && self.isactiver[reg] & (1 << (intid % 32)) != 0
+    }
+
+    fn set_active(&mut self, intid: u32, active: bool) {
+        let reg = (intid / 32) as usize;
+        if reg < 32 {
+            if active {
+                self.isactiver[reg] |= 1 << (intid % 32);
+            } else {
+                self.isactiver[reg] &= !(1 << (intid % 32));
+            }
+        }
+    }
+
+    /// Whether the guest configured `intid` as level-sensitive (ICFGR
+    /// Int_config[1] clear). SGIs and PPIs are never reported level here.
+    pub fn is_level_triggered(&self, intid: u32) -> bool {
+        let intid = intid as usize;
+        (FIRST_SPI..=LAST_INTID).contains(&intid)
+            && self.icfgr[intid / 16] & (0b10 << ((intid % 16) * 2)) == 0
+    }
+
+    /// The guest deactivated `intid` (trapped ICC_EOIR1/ICC_DIR): clear
+    /// its active bit. Returns whether it is a level-triggered SPI, whose
+    /// line the caller must check again.
+    pub fn deactivate(&mut self, intid: u32) -> bool {
+        self.set_active(intid, false);
+        self.is_level_triggered(intid)
+    }
+
+    /// Track SPI active state across a guest run of one vCPU, given its
+    /// saved ICH_LR<n>_EL2 values at entry (`before`) and exit (`after`).
+    ///
+    /// An SPI the guest acknowledged (Active or Pending+Active) becomes
+    /// active; one still only Pending, or gone from the LRs since entry
+    /// (the guest deactivated it), becomes inactive. SPIs this vCPU never
+    /// held in an LR keep their state.
+    ///
+    /// Returns the deactivated level-triggered SPIs as a `PENDING_SPIS`
+    /// bitmap (bit N = INTID 32 + N): the caller queues those whose line
+    /// is still high again so the interrupt re-fires.
+    pub fn update_active_from_lrs(&mut self, before: &[u64], after: &[u64]) -> u32 {
+        let spi = |lr: u64| {
+            let state = GicV3VirtualInterface::get_lr_state(lr);
+            let intid = GicV3VirtualInterface::get_lr_intid(lr);
+            let is_spi = (FIRST_SPI..=LAST_INTID).contains(&(intid as usize));
+            (state != GicV3VirtualInterface::LR_STATE_INVALID && is_spi).then_some((intid, state))
+        };
+        let mut reassert = 0;
+        for (intid, _) in before.iter().filter_map(|&lr| spi(lr)) {
+            if !after
+                .iter()
+                .any(|&lr| spi(lr).is_some_and(|(id, _)| id == intid))
+            {
+                self.set_active(intid, false);
+                if intid < 64 && self.is_level_triggered(intid) {
+                    reassert |= 1 << (intid - 32);
+                }
+            }
+        }
+        for (intid, state) in after.iter().filter_map(|&lr| spi(lr)) {
+            self.set_active(intid, state & GicV3VirtualInterface::LR_STATE_ACTIVE != 0);
+        }
+        reassert
+    }
+
     /// Look up the target vCPU for an SPI via IROUTER.
-    /// Returns the Aff0 field (bits [7:0]) which we use as vCPU ID.
+    /// Returns the vCPU whose MPIDR affinity IROUTER names
+    /// (`global::vcpu_at_affinity`), or 0 when none matches.
     /// Returns 0 for SGIs/PPIs (INTIDs < 32) or out-of-range INTIDs.
     pub fn route_spi(&self, intid: u32) -> usize {
         if intid < 32 || intid >= 1020 {
             return 0;
         }
-        let idx = (intid - 32) as usize;
-        // Aff0 (bits [7:0]) = vCPU ID in our simple affinity model
-        (self.irouter[idx] & 0xFF) as usize
+        self.target_vcpu(self.irouter[(intid - 32) as usize])
+    }
+
+    /// VM whose interrupt state this distributor drives: its owner, or the
+    /// current VM if no owner was set.
+    fn vm_id(&self) -> usize {
+        self.owner_vm.unwrap_or_else(crate::global::current_vm_id)
+    }
+
+    /// vCPU of the owning VM at IROUTER value `irouter`'s affinity, or 0
+    /// when it names no vCPU.
+    fn target_vcpu(&self, irouter: u64) -> usize {
+        crate::global::vcpu_at_affinity(self.vm_id(), irouter).unwrap_or(0)
+    }
+
+    /// IPRIORITYR bytes `[offset, offset + size)`, lowest INTID in the
+    /// low byte (byte, halfword or word access).
+    fn read_priority(&self, offset: u64, size: u8) -> u64 {
+        let first = (offset - GICD_IPRIORITYR_BASE) as usize;
+        (first..first + size as usize).rev().fold(0, |acc, intid| {
+            let prio = if (FIRST_SPI..=LAST_INTID).contains(&intid) {
+                self.ipriorityr[intid]
+            } else {
+                0
+            };
+            (acc << 8) | prio as u64
+        })
+    }
+
+    fn write_priority(&mut self, offset: u64, value: u64, size: u8) {
+        let first = (offset - GICD_IPRIORITYR_BASE) as usize;
+        for (i, intid) in (first..first + size as usize).enumerate() {
+            if (FIRST_SPI..=LAST_INTID).contains(&intid) {
+                self.ipriorityr[intid] = (value >> (i * 8)) as u8;
+            }
+        }
+    }
+
+    /// ICFGR bytes `[offset, offset + size)` (ICFGR0/1 read as zero).
+    fn read_config(&self, offset: u64, size: u8) -> u64 {
+        let reg = ((offset - GICD_ICFGR_BASE) / 4) as usize;
+        if reg < FIRST_SPI / 16 {
+            return 0;
+        }
+        let lanes = self.icfgr[reg] as u64 >> ((offset & 0x3) * 8);
+        lanes & (u64::MAX >> (64 - size as u32 * 8))
+    }
+
+    /// Update the ICFGR bytes `[offset, offset + size)`; only the edge/level
+    /// bit of each SPI is writable.
+    fn write_config(&mut self, offset: u64, value: u64, size: u8) {
+        let reg = ((offset - GICD_ICFGR_BASE) / 4) as usize;
+        if reg < FIRST_SPI / 16 {
+            return;
+        }
+        let shift = (offset & 0x3) * 8;
+        let mask = ((u64::MAX >> (64 - size as u32 * 8)) << shift) as u32 & GICD_ICFGR_WRITABLE;
+        let val = (value << shift) as u32;
+        self.icfgr[reg] = (self.icfgr[reg] & !mask) | (val & mask);
     }
 
     /// Handle a 64-bit IROUTER read (used for 8-byte accesses)
@@ -128,6 +357,12 @@ impl VirtualGicd {
     }
 
     /// Handle a 64-bit IROUTER write
+    ///
+    /// If the write changes the target vCPU (`route_spi`) of an SPI that is already
+    /// queued in the old target's pending bitmap, the pending bit is moved to
+    /// the new target so the interrupt is not stranded on the old vCPU.
+    /// `route_spi` reads `irouter` directly, so no separate cache needs
+    /// invalidating.
     fn write_irouter(&mut self, offset: u64, value: u64) {
         let byte_off = offset - GICD_IROUTER_BASE;
         if byte_off & 0x7 != 0 {
@@ -135,7 +370,17 @@ impl VirtualGicd {
         }
         let idx = (byte_off / 8) as usize;
         if idx < self.irouter.len() {
+            let old_target = self.target_vcpu(self.irouter[idx]);
+            let new_target = self.target_vcpu(value);
             self.irouter[idx] = value;
+            if old_target != new_target {
+                crate::global::migrate_pending_spi(
+                    self.vm_id(),
+                    idx as u32 + 32,
+                    old_target,
+                    new_target,
+                );
+            }
         }
     }
 }
@@ -158,6 +403,13 @@ impl MmioDevice for VirtualGicd {
                     return Some(full >> 32);
                 }
             }
+            // Byte-accessible (IPRIORITYR) and sub-word (ICFGR) registers
+            GICD_IPRIORITYR_BASE..=GICD_IPRIORITYR_END if matches!(size, 1 | 2 | 4) => {
+                return Some(self.read_priority(offset, size));
+            }
+            GICD_ICFGR_BASE..=GICD_ICFGR_END if matches!(size, 1 | 2 | 4) => {
+                return Some(self.read_config(offset, size));
+            }
             _ => {}
         }
 
@@ -168,8 +420,9 @@ impl MmioDevice for VirtualGicd {
         match offset {
             GICD_CTLR => {
                 // ARE_NS (bit 4) is read-as-one (affinity routing always enabled).
+                // DS (bit 6) is read-as-one (single security state).
                 // RWP (bit 31) always reads 0 (writes are instant in emulation).
-                let val = self.ctlr | GICD_CTLR_ARE_NS;
+                let val = self.ctlr | GICD_CTLR_ARE_NS | GICD_CTLR_DS;
                 Some(val as u64)
             }
 
@@ -251,28 +504,15 @@ impl MmioDevice for VirtualGicd {
                 }
             }
 
-            GICD_IPRIORITYR_BASE..=GICD_IPRIORITYR_END => {
-                let reg = ((offset - GICD_IPRIORITYR_BASE) / 4) as usize;
-                if reg < 256 {
-                    Some(self.ipriorityr[reg] as u64)
-                } else {
-                    Some(0)
-                }
-            }
-
-            GICD_ICFGR_BASE..=GICD_ICFGR_END => {
-                let reg = ((offset - GICD_ICFGR_BASE) / 4) as usize;
-                if reg < 64 {
-                    Some(self.icfgr[reg] as u64)
-                } else {
-                    Some(0)
+            GICD_PIDR4..=GICD_CIDR3 => {
+                if offset & 0x3 != 0 {
+                    return Some(0);
                 }
+                Some(GICD_ID_REGS[((offset - GICD_PIDR4) / 4) as usize] as u64)
             }
 
-            GICD_PIDR2 => {
-                // ArchRev[7:4] = 0x3 → GICv3
-                Some(0x30)
-            }
+            // Secure-only configuration: RAZ with a single security state
+            GICD_IGRPMODR_BASE..=GICD_IGRPMODR_END | GICD_NSACR_BASE..=GICD_NSACR_END => Some(0),
 
             _ => Some(0),
         }
@@ -280,8 +520,15 @@ impl MmioDevice for VirtualGicd {
 
     fn write(&mut self, offset: u64, value: u64, size: u8) -> bool {
         // Write-through to physical GICD at EL2 (bypasses Stage-2).
-        // Skip read-only registers; force ARE_NS on CTLR writes.
-        let forward = !matches!(offset, GICD_TYPER | GICD_IIDR | GICD_PIDR2);
+        // Skip read-only and RAZ/WI registers; force ARE_NS on CTLR writes.
+        let forward = !matches!(
+            offset,
+            GICD_TYPER
+                | GICD_IIDR
+                | GICD_PIDR4..=GICD_CIDR3
+                | GICD_IGRPMODR_BASE..=GICD_IGRPMODR_END
+                | GICD_NSACR_BASE..=GICD_NSACR_END
+        );
         if forward {
             let fwd_value = if offset == GICD_CTLR {
                 value | GICD_CTLR_ARE_NS as u64 // enforce affinity routing
@@ -320,6 +567,14 @@ impl MmioDevice for VirtualGicd {
                 self.write_irouter(aligned, new);
                 return true;
             }
+            GICD_IPRIORITYR_BASE..=GICD_IPRIORITYR_END if matches!(size, 1 | 2 | 4) => {
+                self.write_priority(offset, value, size);
+                return true;
+            }
+            GICD_ICFGR_BASE..=GICD_ICFGR_END if matches!(size, 1 | 2 | 4) => {
+                self.write_config(offset, value, size);
+                return true;
+            }
             _ => {}
         }
 
@@ -331,7 +586,8 @@ impl MmioDevice for VirtualGicd {
 
         match offset {
             GICD_CTLR => {
-                self.ctlr = val | GICD_CTLR_ARE_NS;
+                // ARE_NS/DS are supplied on read; reserved bits are dropped
+                self.ctlr = val & GICD_CTLR_WRITABLE;
                 true
             }
 
@@ -346,7 +602,16 @@ impl MmioDevice for VirtualGicd {
             GICD_ISENABLER_BASE..=GICD_ISENABLER_END => {
                 let reg = ((offset - GICD_ISENABLER_BASE) / 4) as usize;
                 if reg < 32 {
+                    let unmasked = val & !self.enabled[reg];
                     self.enabled[reg] |= val;
+                    // Queued SPIs (32-63) held while disabled can go now
+                    if reg == 1 && unmasked != 0 {
+                        crate::arch::aarch64::hypervisor::exception::wake_unmasked(
+                            &crate::global::vm_state(self.vm_id()).pending_spis,
+                            u16::MAX,
+                            unmasked,
+                        );
+                    }
                 }
                 true
             }
@@ -391,22 +656,6 @@ impl MmioDevice for VirtualGicd {
                 true
             }
 
-            GICD_IPRIORITYR_BASE..=GICD_IPRIORITYR_END => {
-                let reg = ((offset - GICD_IPRIORITYR_BASE) / 4) as usize;
-                if reg < 256 {
-                    self.ipriorityr[reg] = val;
-                }
-                true
-            }
-
-            GICD_ICFGR_BASE..=GICD_ICFGR_END => {
-                let reg = ((offset - GICD_ICFGR_BASE) / 4) as usize;
-                if reg < 64 {
-                    self.icfgr[reg] = val;
-                }
-                true
-            }
-
             _ => true, // Silently accept writes to unimplemented registers
         }
     }
@@ -418,4 +667,29 @@ impl MmioDevice for VirtualGicd {
     fn size(&self) -> u64 {
         GICD_SIZE
     }
+
+    fn decode_offset(&self, offset: u64) -> &'static str {
+        match offset {
+            GICD_CTLR => "CTLR",
+            GICD_TYPER => "TYPER",
+            GICD_IIDR => "IIDR",
+            GICD_IGROUPR_BASE..=GICD_IGROUPR_END => "IGROUPR",
+            GICD_ISENABLER_BASE..=GICD_ISENABLER_END => "ISENABLER",
+            GICD_ICENABLER_BASE..=GICD_ICENABLER_END => "ICENABLER",
+            GICD_ISPENDR_BASE..=GICD_ISPENDR_END => "ISPENDR",
+            GICD_ICPENDR_BASE..=GICD_ICPENDR_END => "ICPENDR",
+            GICD_ISACTIVER_BASE..=GICD_ISACTIVER_END => "ISACTIVER",
+            GICD_ICACTIVER_BASE..=GICD_ICACTIVER_END => "ICACTIVER",
+            GICD_IPRIORITYR_BASE..=GICD_IPRIORITYR_END => "IPRIORITYR",
+            GICD_ICFGR_BASE..=GICD_ICFGR_END => "ICFGR",
+            GICD_IGRPMODR_BASE..=GICD_IGRPMODR_END => "IGRPMODR",
+            GICD_NSACR_BASE..=GICD_NSACR_END => "NSACR",
+            // Index is (offset - 0x6100) / 8; the last register ends at 0x7FDF
+            GICD_IROUTER_BASE..=0x7FDF => "IROUTER",
+            GICD_PIDR2 => "PIDR2",
+            GICD_PIDR4..=0xFFEF => "PIDR",
+            GICD_CIDR0..=GICD_CIDR3 => "CIDR",
+            _ => "unknown",
+        }
+    }
 }
@@ -6,5 +6,5 @@
 mod distributor;
 mod redistributor;
 
-pub use distributor::VirtualGicd;
-pub use redistributor::VirtualGicr;
+pub use distributor::{GicdIrqState, VirtualGicd};
+pub use redistributor::{GicrIrqState, VirtualGicr};
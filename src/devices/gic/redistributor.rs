@@ -67,6 +67,17 @@ impl GicrState {
     }
 }
 
+/// Per-vCPU SGI/PPI enable/pending/active/group bits captured for a VM
+/// checkpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GicrIrqState {
+    pub enabled: u32,
+    pub pending: u32,
+    pub active: u32,
+    /// GICR_IGROUPR0 bits (1 = Group 1)
+    pub group: u32,
+}
+
 /// Virtual GIC Redistributor covering all vCPUs
 pub struct VirtualGicr {
     state: [GicrState; MAX_VCPUS],
@@ -86,6 +97,45 @@ impl VirtualGicr {
         }
     }
 
+    /// Capture each vCPU's SGI/PPI enable/pending/active state.
+    pub fn irq_state(&self) -> [GicrIrqState; MAX_VCPUS] {
+        let mut out = [GicrIrqState {
+            enabled: 0,
+            pending: 0,
+            active: 0,
+            group: 0,
+        }; MAX_VCPUS];
+        for (dst, st) in out.iter_mut().zip(self.state.iter()) {
+            dst.enabled = st.isenabler0;
+            dst.pending = st.ispendr0;
+            dst.active = st.isactiver0;
+            dst.group = st.igroupr0;
+        }
+        out
+    }
+
+    /// Restore state captured by `irq_state()`.
+    pub fn restore_irq_state(&mut self, state: &[GicrIrqState; MAX_VCPUS]) {
+        for (st, src) in self.state.iter_mut().zip(state.iter()) {
+            st.isenabler0 = src.enabled;
+            st.ispendr0 = src.pending;
+            st.isactiver0 = src.active;
+            st.igroupr0 = src.group;
+        }
+    }
+
+    /// Whether `vcpu_id` has enabled SGI/PPI `intid` in its shadow
+    /// GICR_ISENABLER0. INTIDs >= 32 are never enabled here.
+    pub fn is_enabled(&self, vcpu_id: usize, intid: u32) -> bool {
+        intid < 32 && vcpu_id < MAX_VCPUS && self.state[vcpu_id].isenabler0 & (1 << intid) != 0
+    }
+
+    /// Whether `vcpu_id` placed SGI/PPI `intid` in Group 1 via its shadow
+    /// GICR_IGROUPR0. INTIDs >= 32 are never Group 1 here.
+    pub fn is_group1(&self, vcpu_id: usize, intid: u32) -> bool {
+        intid < 32 && vcpu_id < MAX_VCPUS && self.state[vcpu_id].igroupr0 & (1 << intid) != 0
+    }
+
     /// Build GICR_TYPER value for a given vCPU
     ///
     /// GICR_TYPER layout (GICv3 spec):
@@ -93,14 +143,17 @@ impl VirtualGicr {
     ///   [23:8]  Processor_Number
     ///   [4]     Last (1 = last redistributor in this series)
     fn typer_value(&self, vcpu_id: usize) -> u64 {
-        let aff0 = (vcpu_id as u64) << 32; // Aff0 at bits [39:32]
+        // MPIDR layout Aff3 [39:32], Aff2 [23:16], Aff1 [15:8], Aff0 [7:0]
+        let mpidr = crate::global::vcpu_affinity(crate::global::current_vm_id(), vcpu_id);
+        let affinity = ((mpidr >> 8) & 0xFF_0000_0000) | (mpidr & 0xFF_FFFF);
+        let aff = affinity << 32; // Aff3:Aff2:Aff1:Aff0 at bits [63:32]
         let proc_num = (vcpu_id as u64) << 8; // Processor_Number at bits [23:8]
         let last = if vcpu_id == self.num_vcpus - 1 {
             1u64 << 4
         } else {
             0
         };
-        aff0 | proc_num | last
+        aff | proc_num | last
     }
 
     /// Decode offset into (vcpu_id, is_sgi_frame, frame_offset)
@@ -183,7 +236,18 @@ impl VirtualGicr {
         let st = &mut self.state[vcpu_id];
         match offset {
             GICR_IGROUPR0 => st.igroupr0 = val,
-            GICR_ISENABLER0 => st.isenabler0 |= val, // write-1-to-set
+            GICR_ISENABLER0 => {
+                // write-1-to-set; queued SGIs held while disabled can go now
+                let unmasked = val & !st.isenabler0;
+                st.isenabler0 |= val;
+                if unmasked != 0 {
+                    crate::arch::aarch64::hypervisor::exception::wake_unmasked(
+                        &crate::global::current_vm_state().pending_sgis,
+                        1 << vcpu_id,
+                        unmasked,
+                    );
+                }
+            }
             GICR_ICENABLER0 => st.isenabler0 &= !val, // write-1-to-clear
             GICR_ISPENDR0 => st.ispendr0 |= val,
             GICR_ICPENDR0 => st.ispendr0 &= !val,
@@ -2,12 +2,23 @@
 //!
 //! Routes MMIO accesses to emulated devices via enum dispatch.
 //! Devices are registered dynamically into an array of up to `MAX_DEVICES` slots.
+//!
+//! `DeviceManager` itself is unsynchronized: the single-pCPU build relies on
+//! exception handling being serialized. Multi-pCPU builds share it through a
+//! `LockedDeviceManager`, which locks each device separately.
 
+pub mod dma;
+pub mod framebuffer;
 pub mod gic;
 pub mod pl011;
 pub mod pl031;
+pub mod sched_stats;
+pub mod sensor;
+pub mod trace;
 pub mod virtio;
 
+use crate::sync::{SpinLock, SpinLockGuard};
+
 /// Trait for MMIO-accessible devices
 ///
 /// - `read()`/`write()` receive offsets relative to `base_address()`
@@ -20,8 +31,9 @@ pub trait MmioDevice {
     fn size(&self) -> u64;
 
     fn contains(&self, addr: u64) -> bool {
-        let base = self.base_address();
-        addr >= base && addr < base + self.size()
+        // wrapping_sub: addresses below base wrap to huge offsets and miss,
+        // and base + size is never computed, so it cannot overflow.
+        addr.wrapping_sub(self.base_address()) < self.size()
     }
 
     /// Return a pending SPI INTID if the device wants to assert an interrupt.
@@ -31,6 +43,11 @@ pub trait MmioDevice {
 
     /// Acknowledge/clear the device-side interrupt.
     fn ack_irq(&mut self) {}
+
+    /// Register name at `offset`, for MMIO traces.
+    fn decode_offset(&self, _offset: u64) -> &'static str {
+        "unknown"
+    }
 }
 
 // ── Enum dispatch ──────────────────────────────────────────────────
@@ -43,7 +60,167 @@ pub enum Device {
     Gicr(gic::VirtualGicr),
     VirtioBlk(virtio::mmio::VirtioMmioTransport<virtio::blk::VirtioBlk>),
     VirtioNet(virtio::mmio::VirtioMmioTransport<virtio::net::VirtioNet>),
+    VirtioInput(virtio::mmio::VirtioMmioTransport<virtio::input::VirtioInput>),
+    VirtioVsock(virtio::mmio::VirtioMmioTransport<virtio::vsock::VirtioVsock>),
+    VirtioBalloon(virtio::mmio::VirtioMmioTransport<virtio::balloon::VirtioBalloon>),
     Pl031(pl031::VirtualPl031),
+    Sensor(sensor::VirtualSensor),
+    SchedStats(sched_stats::VirtualSchedStats),
+}
+
+impl Device {
+    /// Make a device that raises SPIs raise them in `vm_id`.
+    pub fn set_owner_vm(&mut self, vm_id: usize) {
+        match self {
+            Device::VirtioBlk(d) => d.set_owner_vm(vm_id),
+            Device::VirtioNet(d) => d.set_owner_vm(vm_id),
+            Device::VirtioInput(d) => d.set_owner_vm(vm_id),
+            Device::VirtioVsock(d) => d.set_owner_vm(vm_id),
+            Device::VirtioBalloon(d) => d.set_owner_vm(vm_id),
+            Device::Sensor(d) => d.set_owner_vm(vm_id),
+            Device::SchedStats(d) => d.set_owner_vm(vm_id),
+            Device::Pl031(d) => d.set_owner_vm(vm_id),
+            Device::Uart(d) => d.set_owner_vm(vm_id),
+            _ => {}
+        }
+    }
+
+    /// The PL011 UART, if this is one.
+    pub fn uart_mut(&mut self) -> Option<&mut pl011::VirtualUart> {
+        match self {
+            Device::Uart(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    /// The GICD shadow, if this is one.
+    pub fn gicd_mut(&mut self) -> Option<&mut gic::VirtualGicd> {
+        match self {
+            Device::Gicd(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    /// The GICR shadow, if this is one.
+    pub fn gicr_mut(&mut self) -> Option<&mut gic::VirtualGicr> {
+        match self {
+            Device::Gicr(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    /// The PL031 RTC, if this is one.
+    pub fn pl031_mut(&mut self) -> Option<&mut pl031::VirtualPl031> {
+        match self {
+            Device::Pl031(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    /// The sensor, if this is one.
+    pub fn sensor_mut(&mut self) -> Option<&mut sensor::VirtualSensor> {
+        match self {
+            Device::Sensor(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    /// The virtio-input transport, if this is one.
+    pub fn virtio_input_mut(
+        &mut self,
+    ) -> Option<&mut virtio::mmio::VirtioMmioTransport<virtio::input::VirtioInput>> {
+        match self {
+            Device::VirtioInput(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    /// The virtio-net transport, if this is one.
+    pub fn virtio_net_mut(
+        &mut self,
+    ) -> Option<&mut virtio::mmio::VirtioMmioTransport<virtio::net::VirtioNet>> {
+        match self {
+            Device::VirtioNet(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    /// The virtio-vsock transport, if this is one.
+    pub fn virtio_vsock_mut(
+        &mut self,
+    ) -> Option<&mut virtio::mmio::VirtioMmioTransport<virtio::vsock::VirtioVsock>> {
+        match self {
+            Device::VirtioVsock(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    /// The virtio-balloon transport, if this is one.
+    pub fn virtio_balloon_mut(
+        &mut self,
+    ) -> Option<&mut virtio::mmio::VirtioMmioTransport<virtio::balloon::VirtioBalloon>> {
+        match self {
+            Device::VirtioBalloon(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    /// Notification/interrupt counters, if this is a virtio transport.
+    pub fn virtio_stats(&self) -> Option<virtio::mmio::VirtioStats> {
+        match self {
+            Device::VirtioBlk(t) => Some(t.stats()),
+            Device::VirtioNet(t) => Some(t.stats()),
+            Device::VirtioInput(t) => Some(t.stats()),
+            Device::VirtioVsock(t) => Some(t.stats()),
+            Device::VirtioBalloon(t) => Some(t.stats()),
+            _ => None,
+        }
+    }
+
+    /// Perform an access already validated by `mmio_access_mask`. Returns
+    /// the masked read value (`None` for writes) and its trace entry.
+    fn mmio_access(
+        &mut self,
+        addr: u64,
+        value: u64,
+        size: u8,
+        is_write: bool,
+        mask: u64,
+    ) -> (Option<u64>, trace::MmioTraceEntry) {
+        let offset = addr - self.base_address();
+        let result = if is_write {
+            self.write(offset, value & mask, size);
+            None
+        } else {
+            self.read(offset, size).map(|v| v & mask)
+        };
+        let entry = trace::MmioTraceEntry {
+            addr,
+            offset,
+            value: if is_write {
+                value & mask
+            } else {
+                result.unwrap_or(0)
+            },
+            size,
+            is_write,
+            reg: self.decode_offset(offset),
+        };
+        (result, entry)
+    }
+}
+
+/// Value mask for a `size`-byte access at `addr`, or `None` if the access
+/// is not 1/2/4/8 bytes wide or not naturally aligned.
+fn mmio_access_mask(addr: u64, size: u8) -> Option<u64> {
+    if !matches!(size, 1 | 2 | 4 | 8) || addr & (size as u64 - 1) != 0 {
+        return None;
+    }
+    Some(if size == 8 {
+        u64::MAX
+    } else {
+        (1u64 << (size * 8)) - 1
+    })
 }
 
 impl MmioDevice for Device {
@@ -54,7 +231,12 @@ impl MmioDevice for Device {
             Device::Gicr(d) => d.read(offset, size),
             Device::VirtioBlk(d) => d.read(offset, size),
             Device::VirtioNet(d) => d.read(offset, size),
+            Device::VirtioInput(d) => d.read(offset, size),
+            Device::VirtioVsock(d) => d.read(offset, size),
+            Device::VirtioBalloon(d) => d.read(offset, size),
             Device::Pl031(d) => d.read(offset, size),
+            Device::Sensor(d) => d.read(offset, size),
+            Device::SchedStats(d) => d.read(offset, size),
         }
     }
 
@@ -65,7 +247,12 @@ impl MmioDevice for Device {
             Device::Gicr(d) => d.write(offset, value, size),
             Device::VirtioBlk(d) => d.write(offset, value, size),
             Device::VirtioNet(d) => d.write(offset, value, size),
+            Device::VirtioInput(d) => d.write(offset, value, size),
+            Device::VirtioVsock(d) => d.write(offset, value, size),
+            Device::VirtioBalloon(d) => d.write(offset, value, size),
             Device::Pl031(d) => d.write(offset, value, size),
+            Device::Sensor(d) => d.write(offset, value, size),
+            Device::SchedStats(d) => d.write(offset, value, size),
         }
     }
 
@@ -76,7 +263,12 @@ impl MmioDevice for Device {
             Device::Gicr(d) => d.base_address(),
             Device::VirtioBlk(d) => d.base_address(),
             Device::VirtioNet(d) => d.base_address(),
+            Device::VirtioInput(d) => d.base_address(),
+            Device::VirtioVsock(d) => d.base_address(),
+            Device::VirtioBalloon(d) => d.base_address(),
             Device::Pl031(d) => d.base_address(),
+            Device::Sensor(d) => d.base_address(),
+            Device::SchedStats(d) => d.base_address(),
         }
     }
 
@@ -87,7 +279,12 @@ impl MmioDevice for Device {
             Device::Gicr(d) => d.size(),
             Device::VirtioBlk(d) => d.size(),
             Device::VirtioNet(d) => d.size(),
+            Device::VirtioInput(d) => d.size(),
+            Device::VirtioVsock(d) => d.size(),
+            Device::VirtioBalloon(d) => d.size(),
             Device::Pl031(d) => d.size(),
+            Device::Sensor(d) => d.size(),
+            Device::SchedStats(d) => d.size(),
         }
     }
 
@@ -98,7 +295,12 @@ impl MmioDevice for Device {
             Device::Gicr(d) => d.pending_irq(),
             Device::VirtioBlk(d) => d.pending_irq(),
             Device::VirtioNet(d) => d.pending_irq(),
+            Device::VirtioInput(d) => d.pending_irq(),
+            Device::VirtioVsock(d) => d.pending_irq(),
+            Device::VirtioBalloon(d) => d.pending_irq(),
             Device::Pl031(d) => d.pending_irq(),
+            Device::Sensor(d) => d.pending_irq(),
+            Device::SchedStats(d) => d.pending_irq(),
         }
     }
 
@@ -109,23 +311,76 @@ impl MmioDevice for Device {
             Device::Gicr(d) => d.ack_irq(),
             Device::VirtioBlk(d) => d.ack_irq(),
             Device::VirtioNet(d) => d.ack_irq(),
+            Device::VirtioInput(d) => d.ack_irq(),
+            Device::VirtioVsock(d) => d.ack_irq(),
+            Device::VirtioBalloon(d) => d.ack_irq(),
             Device::Pl031(d) => d.ack_irq(),
+            Device::Sensor(d) => d.ack_irq(),
+            Device::SchedStats(d) => d.ack_irq(),
+        }
+    }
+
+    fn decode_offset(&self, offset: u64) -> &'static str {
+        match self {
+            Device::Uart(d) => d.decode_offset(offset),
+            Device::Gicd(d) => d.decode_offset(offset),
+            Device::Gicr(d) => d.decode_offset(offset),
+            Device::VirtioBlk(d) => d.decode_offset(offset),
+            Device::VirtioNet(d) => d.decode_offset(offset),
+            Device::VirtioInput(d) => d.decode_offset(offset),
+            Device::VirtioVsock(d) => d.decode_offset(offset),
+            Device::VirtioBalloon(d) => d.decode_offset(offset),
+            Device::Pl031(d) => d.decode_offset(offset),
+            Device::Sensor(d) => d.decode_offset(offset),
+            Device::SchedStats(d) => d.decode_offset(offset),
         }
     }
 }
 
 // ── Device Manager ─────────────────────────────────────────────────
 
-const MAX_DEVICES: usize = 8;
+const MAX_DEVICES: usize = 16;
 
 use crate::platform;
-const VIRTIO_BLK_BASE: u64 = platform::virtio_slot(0).0;
-const VIRTIO_BLK_INTID: u32 = platform::virtio_slot(0).1;
+
+/// Emulated device state captured for a VM checkpoint.
+#[derive(Clone)]
+pub struct DeviceSnapshot {
+    /// Full PL011 state including the RX FIFO
+    pub uart: Option<pl011::VirtualUart>,
+    /// GICD shadow enable/pending/active bitmaps
+    pub gicd: Option<gic::GicdIrqState>,
+    /// Per-vCPU GICR SGI/PPI enable/pending/active bits
+    pub gicr: Option<[gic::GicrIrqState; platform::MAX_SMP_CPUS]>,
+    /// Virtio transport state, indexed by device slot
+    pub virtio: [Option<virtio::mmio::VirtioTransportState>; MAX_DEVICES],
+}
+
+/// What the guest sees when it touches an address no device claims.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnmappedMmioPolicy {
+    /// Reads return 0, writes are ignored
+    #[default]
+    Lenient,
+    /// The access is reflected to the guest as a synchronous external abort
+    Strict,
+}
 
 /// MMIO Device Manager — routes accesses to registered devices by address.
 pub struct DeviceManager {
     devices: [Option<Device>; MAX_DEVICES],
     count: usize,
+    /// Recent MMIO accesses (disabled by default)
+    trace: trace::MmioTrace,
+    /// Handling of accesses that match no registered device
+    unmapped_policy: UnmappedMmioPolicy,
+    /// Guest GICD/GICR accesses trap to the shadows, so their enable bits
+    /// reflect what the guest programmed
+    gic_trapped: bool,
+    /// DMA domain virtio transports validate guest buffers against
+    dma: dma::DmaMapper,
+    /// VM whose devices these are; their SPIs are raised there
+    owner_vm: Option<usize>,
 }
 
 impl DeviceManager {
@@ -133,80 +388,383 @@ impl DeviceManager {
         Self {
             devices: [const { None }; MAX_DEVICES],
             count: 0,
+            trace: trace::MmioTrace::new(),
+            unmapped_policy: UnmappedMmioPolicy::Lenient,
+            gic_trapped: false,
+            dma: dma::DmaMapper::new(),
+            owner_vm: None,
         }
     }
 
-    /// Remove all registered devices.
+    /// Device manager of VM `vm_id`: devices registered in it raise their
+    /// SPIs in that VM, whichever VM is current at the time.
+    pub const fn for_vm(vm_id: usize) -> Self {
+        let mut dm = Self::new();
+        dm.owner_vm = Some(vm_id);
+        dm
+    }
+
+    /// VM this manager's devices belong to (`None` = the current VM).
+    pub fn owner_vm(&self) -> Option<usize> {
+        self.owner_vm
+    }
+
+    /// Remove all registered devices and restore the lenient unmapped policy.
+    /// The owning VM is kept.
     pub fn reset(&mut self) {
         for slot in self.devices.iter_mut() {
             *slot = None;
         }
         self.count = 0;
+        self.unmapped_policy = UnmappedMmioPolicy::Lenient;
+        self.gic_trapped = false;
+        self.dma = dma::DmaMapper::new();
+    }
+
+    /// Bind virtio devices attached from now on to `dma`'s address space.
+    pub fn set_dma(&mut self, dma: dma::DmaMapper) {
+        self.dma = dma;
+    }
+
+    /// Set how accesses to unclaimed addresses are handled.
+    pub fn set_unmapped_policy(&mut self, policy: UnmappedMmioPolicy) {
+        self.unmapped_policy = policy;
+    }
+
+    pub fn unmapped_policy(&self) -> UnmappedMmioPolicy {
+        self.unmapped_policy
+    }
+
+    /// True if a registered device claims `addr`.
+    pub fn is_mapped(&self, addr: u64) -> bool {
+        self.devices.iter().flatten().any(|dev| dev.contains(addr))
+    }
+
+    /// True if any registered device claims part of `[base, base + size)`.
+    pub fn overlaps(&self, base: u64, size: u64) -> bool {
+        self.devices
+            .iter()
+            .flatten()
+            .any(|dev| dev.contains(base) || dev.base_address().wrapping_sub(base) < size)
     }
 
     /// Register a device. Returns slot index on success.
-    pub fn register_device(&mut self, dev: Device) -> Option<usize> {
+    pub fn register_device(&mut self, mut dev: Device) -> Option<usize> {
         if self.count >= MAX_DEVICES {
             return None;
         }
+        if let Some(vm_id) = self.owner_vm {
+            dev.set_owner_vm(vm_id);
+        }
         let idx = self.count;
         self.devices[idx] = Some(dev);
         self.count += 1;
         Some(idx)
     }
 
-    /// Attach a virtio-blk device backed by an in-memory disk image.
+    /// Attach a virtio-blk device backed by an in-memory disk image
+    /// (virtio-mmio slot 0).
     pub fn attach_virtio_blk(&mut self, disk_base: u64, disk_size: u64) {
+        let _ = self.attach_virtio_blk_at(platform::VIRTIO_SLOT_BLK, disk_base, disk_size);
+    }
+
+    /// Attach a virtio-blk device backed by the image at
+    /// `[disk_base, disk_base + disk_size)` in virtio-mmio slot `slot`.
+    ///
+    /// Each instance has its own MMIO window, INTID and capacity, so a
+    /// data partition can sit next to the root disk (`VIRTIO_SLOT_DATA`).
+    pub fn attach_virtio_blk_at(
+        &mut self,
+        slot: usize,
+        disk_base: u64,
+        disk_size: u64,
+    ) -> Result<(), &'static str> {
+        if slot >= platform::VIRTIO_MAX_SLOTS {
+            return Err("virtio-mmio slot out of range");
+        }
+        let (base, intid) = platform::virtio_slot(slot);
+        if self.overlaps(base, platform::VIRTIO_MMIO_STRIDE) {
+            return Err("virtio-mmio slot already in use");
+        }
         let blk = virtio::blk::VirtioBlk::new(disk_base, disk_size);
-        let transport =
-            virtio::mmio::VirtioMmioTransport::new(VIRTIO_BLK_BASE, blk, VIRTIO_BLK_INTID);
+        let mut transport = virtio::mmio::VirtioMmioTransport::new(base, blk, intid);
+        transport.set_dma(self.dma);
+        self.register_device(Device::VirtioBlk(transport))
+            .ok_or("Device table full")?;
+        Ok(())
+    }
+
+    /// Attach a read-only virtio-blk "CD-ROM" backed by the image at
+    /// `[base, base + size)` (virtio-mmio slot 3).
+    pub fn attach_virtio_cdrom(&mut self, base: u64, size: u64) {
+        let (mmio_base, intid) = platform::virtio_slot(platform::VIRTIO_SLOT_CDROM);
+        let cdrom = virtio::blk::VirtioBlk::new_read_only(base, size);
+        let mut transport = virtio::mmio::VirtioMmioTransport::new(mmio_base, cdrom, intid);
+        transport.set_dma(self.dma);
         self.register_device(Device::VirtioBlk(transport));
     }
 
     /// Attach a virtio-net device for the given VM.
     pub fn attach_virtio_net(&mut self, vm_id: usize) {
-        let (base, intid) = crate::platform::virtio_slot(1);
+        let (base, intid) = platform::virtio_slot(platform::VIRTIO_SLOT_NET);
         let net = virtio::net::VirtioNet::new(vm_id);
-        let transport = virtio::mmio::VirtioMmioTransport::new(base, net, intid);
+        let mut transport = virtio::mmio::VirtioMmioTransport::new(base, net, intid);
+        transport.set_dma(self.dma);
         self.register_device(Device::VirtioNet(transport));
         crate::vswitch::vswitch_add_port(vm_id);
     }
 
+    /// Attach a virtio-input keyboard (virtio-mmio slot 2).
+    pub fn attach_virtio_input(&mut self) {
+        let (base, intid) = platform::virtio_slot(platform::VIRTIO_SLOT_INPUT);
+        let input = virtio::input::VirtioInput::new();
+        let mut transport = virtio::mmio::VirtioMmioTransport::new(base, input, intid);
+        transport.set_dma(self.dma);
+        self.register_device(Device::VirtioInput(transport));
+    }
+
+    /// Attach a virtio-vsock device for the given VM (virtio-mmio slot 5),
+    /// with guest CID `VirtioVsock::guest_cid_for_vm(vm_id)`.
+    pub fn attach_virtio_vsock(&mut self, vm_id: usize) {
+        let (base, intid) = platform::virtio_slot(platform::VIRTIO_SLOT_VSOCK);
+        let vsock = virtio::vsock::VirtioVsock::new(vm_id);
+        let mut transport = virtio::mmio::VirtioMmioTransport::new(base, vsock, intid);
+        transport.set_dma(self.dma);
+        self.register_device(Device::VirtioVsock(transport));
+    }
+
+    /// Attach a virtio-balloon device (virtio-mmio slot 6).
+    pub fn attach_virtio_balloon(&mut self) {
+        let (base, intid) = platform::virtio_slot(platform::VIRTIO_SLOT_BALLOON);
+        let balloon = virtio::balloon::VirtioBalloon::new();
+        let mut transport = virtio::mmio::VirtioMmioTransport::new(base, balloon, intid);
+        transport.set_dma(self.dma);
+        self.register_device(Device::VirtioBalloon(transport));
+    }
+
+    /// Attach the emulated temperature/voltage sensor.
+    pub fn attach_sensor(&mut self) {
+        self.register_device(Device::Sensor(sensor::VirtualSensor::new()));
+    }
+
+    /// Attach the read-only uptime/scheduler-statistics device.
+    pub fn attach_sched_stats(&mut self) {
+        self.register_device(Device::SchedStats(sched_stats::VirtualSchedStats::new()));
+    }
+
+    /// Get a mutable reference to the sensor (for host-driven readings).
+    pub fn sensor_mut(&mut self) -> Option<&mut sensor::VirtualSensor> {
+        self.devices
+            .iter_mut()
+            .flatten()
+            .find_map(Device::sensor_mut)
+    }
+
+    /// Get a mutable reference to the PL031 RTC (for host-side alarm polling).
+    pub fn pl031_mut(&mut self) -> Option<&mut pl031::VirtualPl031> {
+        self.devices
+            .iter_mut()
+            .flatten()
+            .find_map(Device::pl031_mut)
+    }
+
+    /// Get a mutable reference to the virtio-input transport (for event injection).
+    pub fn virtio_input_mut(
+        &mut self,
+    ) -> Option<&mut virtio::mmio::VirtioMmioTransport<virtio::input::VirtioInput>> {
+        self.devices
+            .iter_mut()
+            .flatten()
+            .find_map(Device::virtio_input_mut)
+    }
+
     /// Get a mutable reference to the virtio-net transport (for RX injection).
     pub fn virtio_net_mut(
         &mut self,
     ) -> Option<&mut virtio::mmio::VirtioMmioTransport<virtio::net::VirtioNet>> {
-        for slot in self.devices.iter_mut() {
-            if let Some(Device::VirtioNet(transport)) = slot {
-                return Some(transport);
-            }
-        }
-        None
+        self.devices
+            .iter_mut()
+            .flatten()
+            .find_map(Device::virtio_net_mut)
+    }
+
+    /// Get a mutable reference to the virtio-vsock transport (host side of
+    /// the control channel).
+    pub fn virtio_vsock_mut(
+        &mut self,
+    ) -> Option<&mut virtio::mmio::VirtioMmioTransport<virtio::vsock::VirtioVsock>> {
+        self.devices
+            .iter_mut()
+            .flatten()
+            .find_map(Device::virtio_vsock_mut)
+    }
+
+    /// Get a mutable reference to the virtio-balloon transport (target size,
+    /// stats requests).
+    pub fn virtio_balloon_mut(
+        &mut self,
+    ) -> Option<&mut virtio::mmio::VirtioMmioTransport<virtio::balloon::VirtioBalloon>> {
+        self.devices
+            .iter_mut()
+            .flatten()
+            .find_map(Device::virtio_balloon_mut)
+    }
+
+    /// Notification/interrupt counters of the virtio device at `base`.
+    pub fn virtio_stats(&self, base: u64) -> Option<virtio::mmio::VirtioStats> {
+        self.devices
+            .iter()
+            .flatten()
+            .find(|dev| dev.base_address() == base)
+            .and_then(Device::virtio_stats)
+    }
+
+    /// SPI INTID of the virtio device at `base`.
+    pub fn virtio_intid(&self, base: u64) -> Option<u32> {
+        self.devices.iter().flatten().find_map(|dev| match dev {
+            Device::VirtioBlk(t) if t.base_address() == base => Some(t.irq_intid()),
+            Device::VirtioNet(t) if t.base_address() == base => Some(t.irq_intid()),
+            Device::VirtioInput(t) if t.base_address() == base => Some(t.irq_intid()),
+            Device::VirtioVsock(t) if t.base_address() == base => Some(t.irq_intid()),
+            Device::VirtioBalloon(t) if t.base_address() == base => Some(t.irq_intid()),
+            _ => None,
+        })
     }
 
     /// Handle MMIO access by scanning registered devices.
+    ///
+    /// Safe for arbitrary guest input: accesses that are not 1/2/4/8 bytes
+    /// wide or not naturally aligned are rejected (`None`) before reaching a
+    /// device, since some devices forward writes to physical registers.
+    /// Read results are truncated to the access width.
     pub fn handle_mmio(&mut self, addr: u64, value: u64, size: u8, is_write: bool) -> Option<u64> {
-        for slot in self.devices.iter_mut() {
-            if let Some(dev) = slot {
-                if dev.contains(addr) {
-                    let offset = addr - dev.base_address();
-                    return if is_write {
-                        dev.write(offset, value, size);
-                        None
-                    } else {
-                        dev.read(offset, size)
-                    };
-                }
+        let mask = mmio_access_mask(addr, size)?;
+        match self.slot_of(addr) {
+            Some(idx) => {
+                let dev = self.devices[idx].as_mut()?;
+                let (result, entry) = dev.mmio_access(addr, value, size, is_write, mask);
+                self.trace.record(entry);
+                result
             }
+            None => self.unmapped_mmio(addr, value & mask, size, is_write),
         }
-        // Unknown device — return 0 for reads, ignore writes
-        if is_write {
+    }
+
+    /// Slot of the device claiming `addr`.
+    fn slot_of(&self, addr: u64) -> Option<usize> {
+        self.devices
+            .iter()
+            .position(|slot| slot.as_ref().is_some_and(|dev| dev.contains(addr)))
+    }
+
+    /// Complete an access no device claims (`value` already masked).
+    fn unmapped_mmio(&mut self, addr: u64, value: u64, size: u8, is_write: bool) -> Option<u64> {
+        // Unknown device — lenient: return 0 for reads, ignore writes.
+        // Strict: fail the read so the caller can reflect an abort.
+        self.trace.record(trace::MmioTraceEntry {
+            addr,
+            offset: 0,
+            value: if is_write { value } else { 0 },
+            size,
+            is_write,
+            reg: "unmapped",
+        });
+        if is_write || self.unmapped_policy == UnmappedMmioPolicy::Strict {
             None
         } else {
             Some(0)
         }
     }
 
+    /// Enable or disable MMIO access tracing. Enabling clears old entries.
+    pub fn set_trace(&mut self, enabled: bool) {
+        if enabled {
+            self.trace.clear();
+        }
+        self.trace.set_enabled(enabled);
+    }
+
+    /// Recorded MMIO accesses.
+    pub fn trace(&self) -> &trace::MmioTrace {
+        &self.trace
+    }
+
+    /// Capture UART, GIC and virtio transport state.
+    pub fn snapshot(&self) -> DeviceSnapshot {
+        let mut snap = DeviceSnapshot {
+            uart: None,
+            gicd: None,
+            gicr: None,
+            virtio: [None; MAX_DEVICES],
+        };
+        for (i, slot) in self.devices.iter().enumerate() {
+            match slot {
+                Some(Device::Uart(uart)) => snap.uart = Some(uart.clone()),
+                Some(Device::Gicd(gicd)) => snap.gicd = Some(gicd.irq_state()),
+                Some(Device::Gicr(gicr)) => snap.gicr = Some(gicr.irq_state()),
+                Some(Device::VirtioBlk(t)) => snap.virtio[i] = Some(t.snapshot()),
+                Some(Device::VirtioNet(t)) => snap.virtio[i] = Some(t.snapshot()),
+                Some(Device::VirtioInput(t)) => snap.virtio[i] = Some(t.snapshot()),
+                Some(Device::VirtioVsock(t)) => snap.virtio[i] = Some(t.snapshot()),
+                Some(Device::VirtioBalloon(t)) => snap.virtio[i] = Some(t.snapshot()),
+                _ => {}
+            }
+        }
+        snap
+    }
+
+    /// Restore state captured by `snapshot()`.
+    ///
+    /// Devices are matched by type (UART/GIC) or slot (virtio); devices
+    /// absent from the snapshot are left untouched.
+    pub fn restore(&mut self, snap: &DeviceSnapshot) {
+        for (i, slot) in self.devices.iter_mut().enumerate() {
+            match slot {
+                Some(Device::Uart(uart)) => {
+                    if let Some(s) = &snap.uart {
+                        *uart = s.clone();
+                    }
+                }
+                Some(Device::Gicd(gicd)) => {
+                    if let Some(s) = &snap.gicd {
+                        gicd.restore_irq_state(s);
+                    }
+                }
+                Some(Device::Gicr(gicr)) => {
+                    if let Some(s) = &snap.gicr {
+                        gicr.restore_irq_state(s);
+                    }
+                }
+                Some(Device::VirtioBlk(t)) => {
+                    if let Some(s) = &snap.virtio[i] {
+                        t.restore(s);
+                    }
+                }
+                Some(Device::VirtioNet(t)) => {
+                    if let Some(s) = &snap.virtio[i] {
+                        t.restore(s);
+                    }
+                }
+                Some(Device::VirtioInput(t)) => {
+                    if let Some(s) = &snap.virtio[i] {
+                        t.restore(s);
+                    }
+                }
+                Some(Device::VirtioVsock(t)) => {
+                    if let Some(s) = &snap.virtio[i] {
+                        t.restore(s);
+                    }
+                }
+                Some(Device::VirtioBalloon(t)) => {
+                    if let Some(s) = &snap.virtio[i] {
+                        t.restore(s);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
     /// Look up SPI routing via GICD_IROUTER.
     pub fn route_spi(&self, intid: u32) -> usize {
         for slot in &self.devices {
@@ -217,14 +775,84 @@ impl DeviceManager {
         0
     }
 
-    /// Get a mutable reference to the UART device (for RX injection).
-    pub fn uart_mut(&mut self) -> Option<&mut pl011::VirtualUart> {
-        for slot in self.devices.iter_mut() {
-            if let Some(Device::Uart(uart)) = slot {
-                return Some(uart);
+    /// Mark the GICD/GICR shadows as authoritative for enable state.
+    ///
+    /// Set once the guest's GIC frames are unmapped from Stage-2. With the
+    /// GIC passed through, the shadows never see the guest's enables.
+    pub fn set_gic_trapped(&mut self, trapped: bool) {
+        self.gic_trapped = trapped;
+    }
+
+    /// Whether the GIC shadows are authoritative (`set_gic_trapped`).
+    pub fn gic_trapped(&self) -> bool {
+        self.gic_trapped
+    }
+
+    /// Whether the guest has enabled `intid` for `vcpu_id`.
+    ///
+    /// SGIs/PPIs consult the GICR shadow, SPIs the GICD shadow. Always true
+    /// while the GIC is not trapped or the matching shadow is not registered.
+    pub fn irq_enabled(&self, vcpu_id: usize, intid: u32) -> bool {
+        if !self.gic_trapped {
+            return true;
+        }
+        for dev in self.devices.iter().flatten() {
+            match dev {
+                Device::Gicr(gicr) if intid < 32 => return gicr.is_enabled(vcpu_id, intid),
+                Device::Gicd(gicd) if intid >= 32 => return gicd.is_enabled(intid),
+                _ => {}
             }
         }
-        None
+        true
+    }
+
+    /// Whether the guest placed `intid` in Group 1 for `vcpu_id`, i.e.
+    /// whether it is injected as a Group 1 (IRQ) rather than a Group 0
+    /// (FIQ) virtual interrupt.
+    ///
+    /// Same shadow lookup as `irq_enabled`. Always true while the GIC is not
+    /// trapped or the matching shadow is not registered.
+    pub fn irq_group1(&self, vcpu_id: usize, intid: u32) -> bool {
+        if !self.gic_trapped {
+            return true;
+        }
+        for dev in self.devices.iter().flatten() {
+            match dev {
+                Device::Gicr(gicr) if intid < 32 => return gicr.is_group1(vcpu_id, intid),
+                Device::Gicd(gicd) if intid >= 32 => return gicd.is_group1(intid),
+                _ => {}
+            }
+        }
+        true
+    }
+
+    /// Update the GICD shadow's SPI active bits from one vCPU's list
+    /// registers at guest entry and exit (see
+    /// `VirtualGicd::update_active_from_lrs`). Returns the deactivated
+    /// level-triggered SPIs; 0 without a GICD shadow.
+    pub fn update_spi_active(&mut self, before: &[u64], after: &[u64]) -> u32 {
+        for dev in self.devices.iter_mut().flatten() {
+            if let Device::Gicd(gicd) = dev {
+                return gicd.update_active_from_lrs(before, after);
+            }
+        }
+        0
+    }
+
+    /// Mark SPI `intid` inactive after a trapped guest deactivation.
+    /// Returns whether it is level-triggered (false without a GICD shadow).
+    pub fn deactivate_spi(&mut self, intid: u32) -> bool {
+        for dev in self.devices.iter_mut().flatten() {
+            if let Device::Gicd(gicd) = dev {
+                return gicd.deactivate(intid);
+            }
+        }
+        false
+    }
+
+    /// Get a mutable reference to the UART device (for RX injection).
+    pub fn uart_mut(&mut self) -> Option<&mut pl011::VirtualUart> {
+        self.devices.iter_mut().flatten().find_map(Device::uart_mut)
     }
 }
 
@@ -233,3 +861,195 @@ impl Default for DeviceManager {
         Self::new()
     }
 }
+
+// ── Per-device locking ─────────────────────────────────────────────
+
+/// `DeviceManager` shared between pCPUs, with one lock per device slot.
+///
+/// Each device lives in its own `SpinLock<Option<Device>>` slot; the table
+/// lock covers manager-wide state (unmapped policy, trace, DMA domain, GIC
+/// trap flag, owner VM) and the MMIO window of every slot. An MMIO access
+/// holds the table lock only to find its slot, then that slot's lock alone,
+/// so accesses to different devices proceed in parallel while accesses to
+/// one device are serialized. Host-side helpers (`with_device`) take slot
+/// locks only.
+///
+/// Lock order: table, then slots by ascending index. Slot contents and
+/// windows are only replaced with every lock held (`with_all`).
+pub struct LockedDeviceManager {
+    table: SpinLock<DeviceTable>,
+    slots: [SpinLock<Option<Device>>; MAX_DEVICES],
+}
+
+/// Manager-wide state of a `LockedDeviceManager`. `dm` holds no devices
+/// except inside `with_all`.
+struct DeviceTable {
+    dm: DeviceManager,
+    /// `(base, size)` of the device in each slot
+    windows: [Option<(u64, u64)>; MAX_DEVICES],
+}
+
+impl DeviceTable {
+    /// Slot of the device claiming `addr`.
+    fn slot_of(&self, addr: u64) -> Option<usize> {
+        self.windows
+            .iter()
+            .position(|w| w.is_some_and(|(base, size)| addr.wrapping_sub(base) < size))
+    }
+}
+
+/// Exclusive access to one device of a `LockedDeviceManager`; other
+/// devices stay accessible while it is held.
+pub struct DeviceGuard<'a> {
+    slot: SpinLockGuard<'a, Option<Device>>,
+}
+
+impl core::ops::Deref for DeviceGuard<'_> {
+    type Target = Device;
+    fn deref(&self) -> &Device {
+        match &*self.slot {
+            Some(dev) => dev,
+            None => unreachable!("DeviceGuard over an empty slot"),
+        }
+    }
+}
+
+impl core::ops::DerefMut for DeviceGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Device {
+        match &mut *self.slot {
+            Some(dev) => dev,
+            None => unreachable!("DeviceGuard over an empty slot"),
+        }
+    }
+}
+
+impl LockedDeviceManager {
+    /// Locked device manager owned by VM `vm_id`.
+    pub const fn new(vm_id: usize) -> Self {
+        Self {
+            table: SpinLock::new(DeviceTable {
+                dm: DeviceManager::for_vm(vm_id),
+                windows: [None; MAX_DEVICES],
+            }),
+            slots: [const { SpinLock::new(None) }; MAX_DEVICES],
+        }
+    }
+
+    /// Run `f` under the table lock only. The `DeviceManager` it gets holds
+    /// the manager-wide settings but no devices; they stay in their slots.
+    pub fn with_table<R>(&self, f: impl FnOnce(&mut DeviceManager) -> R) -> R {
+        f(&mut self.table.lock().dm)
+    }
+
+    /// Run `f` on the first device `get` accepts, holding that device's
+    /// slot lock only. `None` if no device matches.
+    ///
+    /// Slots are locked one at a time in index order, so this never waits
+    /// on the table lock and may run while another pCPU dispatches MMIO.
+    pub fn with_device<T, R>(
+        &self,
+        get: fn(&mut Device) -> Option<&mut T>,
+        f: impl FnOnce(&mut T) -> R,
+    ) -> Option<R> {
+        for slot in &self.slots {
+            let mut slot = slot.lock();
+            if let Some(target) = slot.as_mut().and_then(get) {
+                return Some(f(target));
+            }
+        }
+        None
+    }
+
+    /// Run `f` with every lock held and every device moved into the
+    /// `DeviceManager`: registration, reset, snapshots.
+    pub fn with_all<R>(&self, f: impl FnOnce(&mut DeviceManager) -> R) -> R {
+        let mut table = self.table.lock();
+        let mut slots: [SpinLockGuard<'_, Option<Device>>; MAX_DEVICES] =
+            core::array::from_fn(|idx| self.slots[idx].lock());
+        for (dev, slot) in table.dm.devices.iter_mut().zip(slots.iter_mut()) {
+            core::mem::swap(dev, &mut **slot);
+        }
+        let result = f(&mut table.dm);
+        let DeviceTable { dm, windows } = &mut *table;
+        for ((dev, slot), window) in dm.devices.iter_mut().zip(slots.iter_mut()).zip(windows) {
+            *window = dev.as_ref().map(|d| (d.base_address(), d.size()));
+            core::mem::swap(dev, &mut **slot);
+        }
+        result
+    }
+
+    /// True if a registered device claims `addr`.
+    pub fn is_mapped(&self, addr: u64) -> bool {
+        self.table.lock().slot_of(addr).is_some()
+    }
+
+    /// True if any registered device claims part of `[base, base + size)`.
+    pub fn overlaps(&self, base: u64, size: u64) -> bool {
+        self.table
+            .lock()
+            .windows
+            .iter()
+            .flatten()
+            .any(|&(dev_base, dev_size)| {
+                base.wrapping_sub(dev_base) < dev_size || dev_base.wrapping_sub(base) < size
+            })
+    }
+
+    /// Lock the device claiming `addr`, waiting for any access in progress.
+    pub fn lock_device(&self, addr: u64) -> Option<DeviceGuard<'_>> {
+        loop {
+            let (idx, _) = self.find(addr)?;
+            if let Some(dev) = Self::claim(self.slots[idx].lock(), addr) {
+                return Some(dev);
+            }
+        }
+    }
+
+    /// Lock the device claiming `addr` if no other access to it is in
+    /// progress. `None` if it is busy or no device claims `addr`.
+    pub fn try_lock_device(&self, addr: u64) -> Option<DeviceGuard<'_>> {
+        let (idx, _) = self.find(addr)?;
+        Self::claim(self.slots[idx].try_lock()?, addr)
+    }
+
+    /// `DeviceManager::handle_mmio` holding only the target device's lock
+    /// during the access.
+    pub fn handle_mmio(&self, addr: u64, value: u64, size: u8, is_write: bool) -> Option<u64> {
+        let mask = mmio_access_mask(addr, size)?;
+        loop {
+            let Some((idx, tracing)) = self.find(addr) else {
+                return self
+                    .table
+                    .lock()
+                    .dm
+                    .unmapped_mmio(addr, value & mask, size, is_write);
+            };
+            let Some(mut dev) = Self::claim(self.slots[idx].lock(), addr) else {
+                continue;
+            };
+            let (result, entry) = dev.mmio_access(addr, value, size, is_write, mask);
+            drop(dev);
+            if tracing {
+                self.table.lock().dm.trace.record(entry);
+            }
+            return result;
+        }
+    }
+
+    /// Slot index of the device claiming `addr`, and whether MMIO tracing
+    /// is on, read under the table lock.
+    fn find(&self, addr: u64) -> Option<(usize, bool)> {
+        let table = self.table.lock();
+        let idx = table.slot_of(addr)?;
+        Some((idx, table.dm.trace.is_enabled()))
+    }
+
+    /// Turn a held slot lock into a device guard, if the slot still holds a
+    /// device claiming `addr` (the table may have been reset between
+    /// `find` and taking the lock).
+    fn claim(slot: SpinLockGuard<'_, Option<Device>>, addr: u64) -> Option<DeviceGuard<'_>> {
+        slot.as_ref()
+            .is_some_and(|dev| dev.contains(addr))
+            .then_some(DeviceGuard { slot })
+    }
+}
@@ -2,7 +2,12 @@
 ///
 /// Full trap-and-emulate PL011 with:
 /// - TX: writes directly to physical UART via inline asm
-/// - RX: ring buffer filled by hypervisor when physical UART IRQ fires
+/// - RX: 32-entry FIFO (1-entry holding register with LCR_H.FEN clear)
+///   filled by hypervisor when physical UART IRQ fires; overflow is an
+///   overrun error
+/// - Interrupts: RX at the UARTIFLS watermark, RX timeout below it, TX and
+///   overrun, masked by UARTIMSC and cleared through UARTICR; the SPI line
+///   follows UARTMIS (`global::set_spi_level`)
 /// - Linux-compatible peripheral ID registers for amba-pl011.c probe
 use crate::devices::MmioDevice;
 
@@ -48,21 +53,33 @@ const FR_RXFF: u32 = 1 << 6; // Receive FIFO full
 const FR_TXFF: u32 = 1 << 5; // Transmit FIFO full (reserved for TX flow control)
 const FR_RXFE: u32 = 1 << 4; // Receive FIFO empty
 
+/// UARTLCR_H.FEN: FIFOs enabled (otherwise 1-character holding registers)
+const LCR_H_FEN: u32 = 1 << 4;
+/// UARTRSR.OE: overrun, a character arrived with the RX FIFO full
+const RSR_OE: u32 = 1 << 3;
+
 // ── Interrupt bits ──────────────────────────────────────────────────
 
 const INT_RX: u32 = 1 << 4; // Receive interrupt
 const INT_TX: u32 = 1 << 5; // Transmit interrupt
-#[allow(dead_code)]
-const INT_RT: u32 = 1 << 6; // Receive timeout interrupt (reserved for timeout emulation)
+/// Receive timeout: data below the watermark. Raised as soon as such
+/// data arrives, as no line timing is emulated.
+const INT_RT: u32 = 1 << 6;
+const INT_OE: u32 = 1 << 10; // Overrun error interrupt
 
 /// UART SPI: SPI 1 = INTID 33
 const UART_SPI_INTID: u32 = 33;
 
-// ── RX Ring Buffer ──────────────────────────────────────────────────
+// ── RX FIFO ─────────────────────────────────────────────────────────
 
-const RX_BUF_SIZE: usize = 64;
+/// PL011 RX FIFO depth with LCR_H.FEN set
+pub const RX_FIFO_DEPTH: usize = 32;
+
+/// Bytes of transmitted output kept for `tx_log()`
+pub const TX_LOG_SIZE: usize = 256;
 
 /// Virtual UART device with RX ring buffer and full Linux compatibility.
+#[derive(Clone)]
 pub struct VirtualUart {
     // Control/config registers
     cr: u32,
@@ -73,10 +90,19 @@ pub struct VirtualUart {
     imsc: u32,
     ris: u32,
     dmacr: u32,
-    // RX ring buffer
-    rx_buf: [u8; RX_BUF_SIZE],
+    /// Receive status (error bits) of the last character read
+    rsr: u32,
+    // RX FIFO
+    rx_buf: [u8; RX_FIFO_DEPTH],
     rx_head: usize, // next read position
-    rx_tail: usize, // next write position
+    rx_count: usize,
+    // Most recent TX output (ring, oldest bytes overwritten)
+    tx_log: [u8; TX_LOG_SIZE],
+    tx_count: usize, // total bytes ever transmitted
+    /// UART line level last signalled to the vGIC
+    irq_level: bool,
+    /// VM the UART SPI is raised in (`None` = whichever VM is current)
+    owner_vm: Option<usize>,
 }
 
 impl VirtualUart {
@@ -90,9 +116,68 @@ impl VirtualUart {
             imsc: 0,
             ris: 0,
             dmacr: 0,
-            rx_buf: [0; RX_BUF_SIZE],
+            rsr: 0,
+            rx_buf: [0; RX_FIFO_DEPTH],
             rx_head: 0,
-            rx_tail: 0,
+            rx_count: 0,
+            tx_log: [0; TX_LOG_SIZE],
+            tx_count: 0,
+            irq_level: false,
+            owner_vm: None,
+        }
+    }
+
+    /// Raise the UART SPI in `vm_id` rather than the current VM.
+    pub fn set_owner_vm(&mut self, vm_id: usize) {
+        self.owner_vm = Some(vm_id);
+    }
+
+    /// Drive the UART SPI line from UARTMIS: raised on a rising edge,
+    /// withdrawn on a falling one, re-fired after EOI while it stays high.
+    fn update_irq(&mut self) {
+        let level = self.ris & self.imsc != 0;
+        if level == self.irq_level {
+            return;
+        }
+        self.irq_level = level;
+        let vm_id = self.owner_vm.unwrap_or_else(crate::global::current_vm_id);
+        crate::global::set_spi_level(vm_id, UART_SPI_INTID, level);
+    }
+
+    /// RX FIFO depth: 32 entries, or 1 with the FIFOs disabled.
+    fn rx_depth(&self) -> usize {
+        if self.lcr_h & LCR_H_FEN != 0 {
+            RX_FIFO_DEPTH
+        } else {
+            1
+        }
+    }
+
+    /// FIFO level at which the RX interrupt asserts (UARTIFLS.RXIFLSEL:
+    /// 1/8, 1/4, 1/2, 3/4 or 7/8 full). Any character with FIFOs disabled.
+    fn rx_watermark(&self) -> usize {
+        if self.lcr_h & LCR_H_FEN == 0 {
+            return 1;
+        }
+        let eighths = match (self.ifls >> 3) & 0x7 {
+            0 => 1,
+            1 => 2,
+            2 => 4,
+            3 => 6,
+            _ => 7,
+        };
+        RX_FIFO_DEPTH * eighths / 8
+    }
+
+    /// Recompute the RX and RX timeout interrupts from the FIFO level.
+    fn update_rx_status(&mut self) {
+        if self.rx_count >= self.rx_watermark() {
+            self.ris |= INT_RX;
+        } else {
+            self.ris &= !INT_RX;
+        }
+        if self.rx_count == 0 {
+            self.ris &= !INT_RT;
         }
     }
 
@@ -109,46 +194,71 @@ impl VirtualUart {
         }
     }
 
-    /// Push a received byte into the RX ring buffer.
+    /// Transmit a byte: write it to the physical UART, record it in the
+    /// TX log, and assert the TX interrupt (the FIFO empties immediately).
+    pub fn transmit(&mut self, ch: u8) {
+        self.output_char(ch);
+        self.tx_log[self.tx_count % TX_LOG_SIZE] = ch;
+        self.tx_count += 1;
+        self.ris |= INT_TX;
+        self.update_irq();
+    }
+
+    /// Copy the most recent transmitted bytes, oldest first, into `out`.
+    /// Returns the number of bytes copied.
+    pub fn tx_log(&self, out: &mut [u8]) -> usize {
+        let n = out.len().min(self.tx_count).min(TX_LOG_SIZE);
+        let start = self.tx_count - n;
+        for (i, b) in out[..n].iter_mut().enumerate() {
+            *b = self.tx_log[(start + i) % TX_LOG_SIZE];
+        }
+        n
+    }
+
+    /// Push a received byte into the RX FIFO.
     /// Called by the hypervisor when physical UART data is available.
+    ///
+    /// A byte arriving with the FIFO full is lost and flagged as an
+    /// overrun (UARTRSR.OE, overrun interrupt).
     pub fn push_rx(&mut self, ch: u8) {
-        let next_tail = (self.rx_tail + 1) % RX_BUF_SIZE;
-        if next_tail == self.rx_head {
-            return; // buffer full, drop
+        if self.rx_count >= self.rx_depth() {
+            self.rsr |= RSR_OE;
+            self.ris |= INT_OE;
+        } else {
+            self.rx_buf[(self.rx_head + self.rx_count) % RX_FIFO_DEPTH] = ch;
+            self.rx_count += 1;
+            // Below the watermark only the timeout interrupt fires
+            self.ris |= INT_RT;
+            self.update_rx_status();
         }
-        self.rx_buf[self.rx_tail] = ch;
-        self.rx_tail = next_tail;
-        // Assert RX interrupt
-        self.ris |= INT_RX;
+        self.update_irq();
     }
 
-    /// Pop a byte from the RX ring buffer.
+    /// Pop a byte from the RX FIFO.
     fn pop_rx(&mut self) -> Option<u8> {
-        if self.rx_head == self.rx_tail {
+        if self.rx_count == 0 {
             return None; // empty
         }
         let ch = self.rx_buf[self.rx_head];
-        self.rx_head = (self.rx_head + 1) % RX_BUF_SIZE;
-        // If buffer now empty, clear RX interrupt
-        if self.rx_head == self.rx_tail {
-            self.ris &= !INT_RX;
-        }
+        self.rx_head = (self.rx_head + 1) % RX_FIFO_DEPTH;
+        self.rx_count -= 1;
+        self.update_rx_status();
+        self.update_irq();
         Some(ch)
     }
 
-    /// Check if RX buffer has data.
-    fn rx_has_data(&self) -> bool {
-        self.rx_head != self.rx_tail
+    /// Number of bytes waiting in the RX FIFO.
+    pub fn rx_level(&self) -> usize {
+        self.rx_count
     }
 
-    /// Get flag register value based on RX buffer state.
+    /// Get flag register value based on RX FIFO state.
     fn get_flags(&self) -> u32 {
         let mut fr = FR_TXFE; // TX always ready
-        if !self.rx_has_data() {
+        if self.rx_count == 0 {
             fr |= FR_RXFE;
         }
-        let count = (self.rx_tail + RX_BUF_SIZE - self.rx_head) % RX_BUF_SIZE;
-        if count >= RX_BUF_SIZE - 1 {
+        if self.rx_count >= self.rx_depth() {
             fr |= FR_RXFF;
         }
         fr
@@ -166,7 +276,7 @@ impl MmioDevice for VirtualUart {
                 Some(ch) => ch as u64,
                 None => 0,
             },
-            UARTRSR => 0, // No errors
+            UARTRSR => self.rsr as u64,
             UARTFR => self.get_flags() as u64,
             UARTILPR => 0,
             UARTIBRD => self.ibrd as u64,
@@ -202,13 +312,14 @@ impl MmioDevice for VirtualUart {
 
         match offset {
             UARTDR => {
-                let ch = (value & 0xFF) as u8;
-                self.output_char(ch);
-                // Assert TX interrupt (TX FIFO always immediately empties)
-                self.ris |= INT_TX;
+                self.transmit((value & 0xFF) as u8);
+                true
+            }
+            UARTRSR => {
+                // Error clear
+                self.rsr = 0;
                 true
             }
-            UARTRSR => true,  // Error clear — no-op
             UARTILPR => true, // IrDA — ignore
             UARTIBRD => {
                 self.ibrd = (value & 0xFFFF) as u32;
@@ -220,6 +331,8 @@ impl MmioDevice for VirtualUart {
             }
             UARTLCR_H => {
                 self.lcr_h = (value & 0xFF) as u32;
+                self.update_rx_status();
+                self.update_irq();
                 true
             }
             UARTCR => {
@@ -228,14 +341,18 @@ impl MmioDevice for VirtualUart {
             }
             UARTIFLS => {
                 self.ifls = (value & 0x3F) as u32;
+                self.update_rx_status();
+                self.update_irq();
                 true
             }
             UARTIMSC => {
                 self.imsc = (value & 0x7FF) as u32;
+                self.update_irq();
                 true
             }
             UARTICR => {
                 self.ris &= !(value as u32);
+                self.update_irq();
                 true
             }
             UARTDMACR => {
@@ -4,4 +4,4 @@
 
 mod emulator;
 
-pub use emulator::VirtualUart;
+pub use emulator::{VirtualUart, RX_FIFO_DEPTH, TX_LOG_SIZE};
@@ -1,23 +1,29 @@
 /// Virtual RTC (PL031) device
 ///
 /// Minimal trap-and-emulate PL031 RTC for Linux guest probing.
-/// Uses the ARM architectural counter (CNTVCT_EL0 / CNTFRQ_EL0) as the
-/// time source so the guest sees monotonically increasing seconds.
+/// Uses the hypervisor timebase (`crate::time`) as the time source so the
+/// guest sees monotonically increasing seconds.
 ///
 /// Register map (offsets from base 0x0901_0000):
 ///   0x000 RTCDR  — Data Register (read-only, current time in seconds)
-///   0x004 RTCMR  — Match Register (stub)
+///   0x004 RTCMR  — Match Register (alarm time in seconds)
 ///   0x008 RTCLR  — Load Register (write-only, sets epoch)
 ///   0x00C RTCCR  — Control Register (bit 0 = enable, default 1)
-///   0x010 RTCIMSC — Interrupt Mask Set/Clear (stub)
-///   0x014 RTCRIS — Raw Interrupt Status (stub)
-///   0x018 RTCMIS — Masked Interrupt Status (stub)
-///   0x01C RTCICR — Interrupt Clear Register (stub)
+///   0x010 RTCIMSC — Interrupt Mask Set/Clear (bit 0 = alarm enable)
+///   0x014 RTCRIS — Raw Interrupt Status (bit 0 = alarm matched)
+///   0x018 RTCMIS — Masked Interrupt Status
+///   0x01C RTCICR — Interrupt Clear Register (write 1 to clear)
 ///   0xFE0-0xFFC — PrimeCell identification registers
+///
+/// The match interrupt is evaluated lazily: on guest register accesses and
+/// whenever the host calls `poll()` (e.g. while the VM is suspended with
+/// the RTC as its wakeup source).
 use crate::devices::MmioDevice;
 
 /// PL031 RTC base address (QEMU virt machine)
 pub const PL031_BASE: u64 = 0x0901_0000;
+/// Alarm (match) interrupt (SPI 2)
+pub const PL031_INTID: u32 = 34;
 
 const PL031_SIZE: u64 = 0x1000;
 
@@ -42,34 +48,6 @@ const PCELLID1: u64 = 0xFF4;
 const PCELLID2: u64 = 0xFF8;
 const PCELLID3: u64 = 0xFFC;
 
-// ── Counter helpers ─────────────────────────────────────────────────
-
-/// Read the virtual counter (CNTVCT_EL0).
-fn read_cntvct() -> u64 {
-    let val: u64;
-    unsafe {
-        core::arch::asm!(
-            "mrs {}, cntvct_el0",
-            out(reg) val,
-            options(nostack, nomem),
-        );
-    }
-    val
-}
-
-/// Read the counter frequency (CNTFRQ_EL0).
-fn read_cntfrq() -> u64 {
-    let val: u64;
-    unsafe {
-        core::arch::asm!(
-            "mrs {}, cntfrq_el0",
-            out(reg) val,
-            options(nostack, nomem),
-        );
-    }
-    val
-}
-
 // ── Virtual PL031 device ────────────────────────────────────────────
 
 /// Virtual PL031 RTC device.
@@ -79,28 +57,76 @@ fn read_cntfrq() -> u64 {
 pub struct VirtualPl031 {
     /// Base epoch set via RTCLR (seconds).
     load_value: u64,
-    /// CNTVCT_EL0 snapshot taken when load_value was written.
+    /// Timebase tick snapshot taken when load_value was written.
     load_counter: u64,
-    /// Match register (stub — not wired to interrupts).
+    /// Match register (alarm time in seconds).
     match_value: u32,
+    /// Set when RTCMR is written; cleared once the time reaches it.
+    match_armed: bool,
     /// Control register: bit 0 = RTC enabled.
     control: u32,
-    /// Interrupt mask (stub).
+    /// Interrupt mask: bit 0 = alarm interrupt enabled.
     imsc: u32,
-    /// Raw interrupt status (stub).
+    /// Raw interrupt status: bit 0 = alarm matched.
     ris: u32,
+    /// Alarm line level last signalled to the vGIC.
+    irq_level: bool,
+    /// VM the alarm SPI is raised in (`None` = whichever VM is current)
+    owner_vm: Option<usize>,
 }
 
 impl VirtualPl031 {
     pub fn new() -> Self {
         Self {
             load_value: 0,
-            load_counter: read_cntvct(),
+            load_counter: crate::time::now_ticks(),
             match_value: 0,
+            match_armed: false,
             control: 1, // enabled by default (matches QEMU)
             imsc: 0,
             ris: 0,
+            irq_level: false,
+            owner_vm: None,
+        }
+    }
+
+    /// Raise the alarm SPI in `vm_id` rather than the current VM.
+    pub fn set_owner_vm(&mut self, vm_id: usize) {
+        self.owner_vm = Some(vm_id);
+    }
+
+    /// Whether an enabled alarm is waiting for its match time, i.e. the
+    /// RTC can wake a suspended VM.
+    pub fn alarm_armed(&self) -> bool {
+        self.match_armed && self.imsc & 1 != 0
+    }
+
+    /// Whether the alarm interrupt is asserted (RTCMIS).
+    pub fn alarm_pending(&self) -> bool {
+        self.ris & self.imsc & 1 != 0
+    }
+
+    /// Latch the match if the RTC has reached RTCMR and update the alarm
+    /// line. Returns whether the alarm interrupt is asserted.
+    pub fn poll(&mut self) -> bool {
+        if self.match_armed && self.current_time() >= self.match_value as u64 {
+            self.match_armed = false;
+            self.ris |= 1;
         }
+        self.update_irq();
+        self.alarm_pending()
+    }
+
+    /// Drive the alarm SPI line: raised on a rising edge, withdrawn on a
+    /// falling one, re-fired after EOI while it stays high.
+    fn update_irq(&mut self) {
+        let level = self.alarm_pending();
+        if level == self.irq_level {
+            return;
+        }
+        self.irq_level = level;
+        let vm_id = self.owner_vm.unwrap_or_else(crate::global::current_vm_id);
+        crate::global::set_spi_level(vm_id, PL031_INTID, level);
     }
 
     /// Current RTC time in seconds.
@@ -109,22 +135,33 @@ impl VirtualPl031 {
             // RTC disabled — freeze at load_value
             return self.load_value;
         }
-        let freq = read_cntfrq();
+        let freq = crate::time::frequency();
         if freq == 0 {
             return self.load_value;
         }
-        let elapsed_ticks = read_cntvct().wrapping_sub(self.load_counter);
+        let elapsed_ticks = crate::time::now_ticks().wrapping_sub(self.load_counter);
         let elapsed_seconds = elapsed_ticks / freq;
         self.load_value + elapsed_seconds
     }
 }
 
+impl Drop for VirtualPl031 {
+    /// A removed RTC no longer drives its alarm line.
+    fn drop(&mut self) {
+        if self.irq_level {
+            let vm_id = self.owner_vm.unwrap_or_else(crate::global::current_vm_id);
+            crate::global::set_spi_level(vm_id, PL031_INTID, false);
+        }
+    }
+}
+
 impl MmioDevice for VirtualPl031 {
     fn read(&mut self, offset: u64, size: u8) -> Option<u64> {
         if size != 4 {
             return Some(0);
         }
 
+        self.poll();
         let value = match offset {
             RTCDR => self.current_time(),
             RTCMR => self.match_value as u64,
@@ -159,28 +196,19 @@ impl MmioDevice for VirtualPl031 {
         match offset {
             RTCMR => {
                 self.match_value = value as u32;
-                true
+                self.match_armed = true;
             }
             RTCLR => {
                 self.load_value = value & 0xFFFF_FFFF;
-                self.load_counter = read_cntvct();
-                true
-            }
-            RTCCR => {
-                self.control = (value & 1) as u32;
-                true
-            }
-            RTCIMSC => {
-                self.imsc = (value & 1) as u32;
-                true
+                self.load_counter = crate::time::now_ticks();
             }
-            RTCICR => {
-                self.ris &= !(value as u32);
-                true
-            }
-            RTCDR => true, // read-only, ignore writes
-            _ => true,     // unknown — accept silently
+            RTCCR => self.control = (value & 1) as u32,
+            RTCIMSC => self.imsc = (value & 1) as u32,
+            RTCICR => self.ris &= !(value as u32),
+            _ => return true, // read-only or unknown — ignore
         }
+        self.poll();
+        true
     }
 
     fn base_address(&self) -> u64 {
@@ -190,4 +218,8 @@ impl MmioDevice for VirtualPl031 {
     fn size(&self) -> u64 {
         PL031_SIZE
     }
+
+    fn pending_irq(&self) -> Option<u32> {
+        self.alarm_pending().then_some(PL031_INTID)
+    }
 }
@@ -0,0 +1,167 @@
+/// Virtual uptime / scheduler-statistics device
+///
+/// Read-only MMIO register bank exposing the owning VM's `SchedStats` so a
+/// guest can observe how the hypervisor schedules it. Counters are 64-bit;
+/// they can be read with one 8-byte access or as two 4-byte halves (low
+/// word first). Writes are ignored.
+///
+/// Register map (offsets from base 0x090D_0000):
+///   0x000 STATS_ID     — Identification, reads "SCHD" (32-bit)
+///   0x004 NUM_VCPUS    — Number of per-vCPU counter slots (32-bit)
+///   0x008 UPTIME_NS    — Nanoseconds since the VM was created
+///   0x010 ITERATIONS   — Scheduler iterations (`pick_next()` calls)
+///   0x018 PREEMPTIONS  — Slices ended by the preemption watchdog
+///   0x020 RUN_COUNT[n] — Times vCPU n was scheduled (8 bytes per vCPU)
+///   0x060 SLICE_NS[n]  — Time vCPU n spent in the guest (8 bytes per vCPU)
+///   0x0A0 CURRENT_VCPU — vCPU picked by the last scheduling decision
+///                        (all ones = none)
+///   0x0A8 ONLINE_VCPUS — Number of online vCPUs
+///   0x0B0 QUANTUM_NS   — Preemption slice length, 0 while the running vCPU
+///                        is not preempted (single vCPU online, or pCPU
+///                        claimed exclusively)
+use core::sync::atomic::Ordering;
+
+use crate::devices::MmioDevice;
+use crate::scheduler::{sched_stats, SchedStats};
+use crate::vm::MAX_VCPUS;
+
+/// Stats device base address (unused slot in the QEMU virt 0x0900_0000 block)
+pub const SCHED_STATS_BASE: u64 = 0x090D_0000;
+
+const SCHED_STATS_SIZE: u64 = 0x1000;
+
+// ── Register offsets ────────────────────────────────────────────────
+
+const STATS_ID: u64 = 0x000;
+const NUM_VCPUS: u64 = 0x004;
+const UPTIME_NS: u64 = 0x008;
+const ITERATIONS: u64 = 0x010;
+const PREEMPTIONS: u64 = 0x018;
+const RUN_COUNT: u64 = 0x020;
+const SLICE_NS: u64 = RUN_COUNT + 8 * MAX_VCPUS as u64;
+const CURRENT_VCPU: u64 = SLICE_NS + 8 * MAX_VCPUS as u64;
+const ONLINE_VCPUS: u64 = CURRENT_VCPU + 8;
+const QUANTUM_NS: u64 = ONLINE_VCPUS + 8;
+
+/// "SCHD"
+const STATS_ID_VALUE: u64 = 0x5343_4844;
+
+// ── Virtual stats device ────────────────────────────────────────────
+
+/// Virtual scheduler-statistics device.
+///
+/// Holds no counters of its own; every read samples the owning VM's
+/// `SchedStats`.
+pub struct VirtualSchedStats {
+    /// VM whose statistics are exposed (`None` = whichever VM is current)
+    owner_vm: Option<usize>,
+}
+
+impl VirtualSchedStats {
+    pub fn new() -> Self {
+        Self { owner_vm: None }
+    }
+
+    /// Expose `vm_id`'s statistics rather than the current VM's.
+    pub fn set_owner_vm(&mut self, vm_id: usize) {
+        self.owner_vm = Some(vm_id);
+    }
+
+    fn vm_id(&self) -> usize {
+        self.owner_vm.unwrap_or_else(crate::global::current_vm_id)
+    }
+
+    fn stats(&self) -> &'static SchedStats {
+        sched_stats(self.vm_id())
+    }
+
+    /// Preemption slice the current vCPU runs under, as `run_one_iteration`
+    /// arms it: only with two or more vCPUs online and no exclusive claim.
+    fn quantum_ns(&self, online: u32) -> u64 {
+        let vm_id = self.vm_id();
+        let exclusive = self.stats().current_vcpu().is_some_and(|id| {
+            crate::pcpu_pin::is_exclusive(vm_id, crate::pcpu_pin::pcpu_of_vcpu(id))
+        });
+        if online >= 2 && !exclusive {
+            crate::arch::aarch64::peripherals::timer::PREEMPTION_SLICE_NS
+        } else {
+            0
+        }
+    }
+
+    /// Value of the 64-bit register at 8-byte aligned `offset`.
+    fn reg64(&self, offset: u64) -> u64 {
+        let stats = self.stats();
+        let online = crate::global::vm_state(self.vm_id())
+            .vcpu_online_mask
+            .load(Ordering::Relaxed)
+            .count_ones();
+        match offset {
+            STATS_ID => STATS_ID_VALUE | (MAX_VCPUS as u64) << 32,
+            UPTIME_NS => crate::time::ticks_to_ns(stats.uptime_ticks()),
+            ITERATIONS => stats.iterations(),
+            PREEMPTIONS => stats.preemptions(),
+            RUN_COUNT..SLICE_NS => stats.run_count(((offset - RUN_COUNT) / 8) as usize),
+            SLICE_NS..CURRENT_VCPU => {
+                crate::time::ticks_to_ns(stats.slice_ticks(((offset - SLICE_NS) / 8) as usize))
+            }
+            CURRENT_VCPU => stats.current_vcpu().map_or(u64::MAX, |id| id as u64),
+            ONLINE_VCPUS => online as u64,
+            QUANTUM_NS => self.quantum_ns(online),
+            _ => 0,
+        }
+    }
+}
+
+impl Default for VirtualSchedStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MmioDevice for VirtualSchedStats {
+    fn read(&mut self, offset: u64, size: u8) -> Option<u64> {
+        let value = match size {
+            8 if offset % 8 == 0 => self.reg64(offset),
+            4 if offset % 4 == 0 => {
+                let reg = self.reg64(offset & !7);
+                if offset % 8 == 0 {
+                    reg & 0xFFFF_FFFF
+                } else {
+                    reg >> 32
+                }
+            }
+            _ => 0,
+        };
+
+        Some(value)
+    }
+
+    fn write(&mut self, _offset: u64, _value: u64, _size: u8) -> bool {
+        true // read-only — ignore
+    }
+
+    fn base_address(&self) -> u64 {
+        SCHED_STATS_BASE
+    }
+
+    fn size(&self) -> u64 {
+        SCHED_STATS_SIZE
+    }
+
+    fn decode_offset(&self, offset: u64) -> &'static str {
+        match offset {
+            STATS_ID => "STATS_ID",
+            NUM_VCPUS => "NUM_VCPUS",
+            UPTIME_NS..ITERATIONS => "UPTIME_NS",
+            ITERATIONS..PREEMPTIONS => "ITERATIONS",
+            PREEMPTIONS..RUN_COUNT => "PREEMPTIONS",
+            RUN_COUNT..SLICE_NS => "RUN_COUNT",
+            SLICE_NS..CURRENT_VCPU => "SLICE_NS",
+            CURRENT_VCPU => "CURRENT_VCPU",
+            ONLINE_VCPUS => "ONLINE_VCPUS",
+            QUANTUM_NS => "QUANTUM_NS",
+            _ => "unknown",
+        }
+    }
+}
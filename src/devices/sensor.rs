@@ -0,0 +1,190 @@
+/// Virtual temperature/voltage sensor
+///
+/// Simple MMIO sensor for exercising guest thermal-management code. The
+/// host drives the readings with `set_temp()` / `set_voltage()`; the guest
+/// programs a threshold and gets a level-triggered alarm SPI while the
+/// temperature is above it.
+///
+/// Register map (offsets from base 0x090C_0000, 32-bit accesses):
+///   0x000 SENSOR_ID  — Identification, reads "SENS" (read-only)
+///   0x004 TEMP       — Temperature in millidegrees Celsius, signed (read-only)
+///   0x008 VOLTAGE    — Supply voltage in millivolts (read-only)
+///   0x00C THRESHOLD  — Alarm threshold in millidegrees Celsius, signed
+///   0x010 CTRL       — bit 0 = alarm interrupt enable
+///   0x014 STATUS     — bit 0 = temperature above threshold (read-only)
+use crate::devices::MmioDevice;
+
+/// Sensor base address (unused slot in the QEMU virt 0x0900_0000 block)
+pub const SENSOR_BASE: u64 = 0x090C_0000;
+/// Alarm interrupt (SPI 10)
+pub const SENSOR_INTID: u32 = 42;
+
+const SENSOR_SIZE: u64 = 0x1000;
+
+// ── Register offsets ────────────────────────────────────────────────
+
+const SENSOR_ID: u64 = 0x000;
+const TEMP: u64 = 0x004;
+const VOLTAGE: u64 = 0x008;
+const THRESHOLD: u64 = 0x00C;
+const CTRL: u64 = 0x010;
+const STATUS: u64 = 0x014;
+
+/// "SENS"
+const SENSOR_ID_VALUE: u64 = 0x5345_4E53;
+const CTRL_ALARM_EN: u32 = 1 << 0;
+const STATUS_ALARM: u32 = 1 << 0;
+
+/// Power-on readings: 40 °C, 0.9 V, alarm at 85 °C
+const DEFAULT_TEMP: i32 = 40_000;
+const DEFAULT_VOLTAGE: u32 = 900;
+const DEFAULT_THRESHOLD: i32 = 85_000;
+
+// ── Virtual sensor device ───────────────────────────────────────────
+
+/// Virtual sensor device.
+///
+/// The alarm line is asserted while `CTRL.ALARM_EN` is set and the
+/// temperature is above the threshold. It is re-evaluated whenever the
+/// host changes a reading or the guest reprograms the threshold/control.
+pub struct VirtualSensor {
+    /// Current temperature (m°C).
+    temp: i32,
+    /// Current supply voltage (mV).
+    voltage: u32,
+    /// Alarm threshold (m°C).
+    threshold: i32,
+    /// Control register.
+    ctrl: u32,
+    /// Alarm line level last signalled to the vGIC.
+    alarm: bool,
+    /// VM the alarm SPI is raised in (`None` = whichever VM is current)
+    owner_vm: Option<usize>,
+}
+
+impl VirtualSensor {
+    pub fn new() -> Self {
+        Self {
+            temp: DEFAULT_TEMP,
+            voltage: DEFAULT_VOLTAGE,
+            threshold: DEFAULT_THRESHOLD,
+            ctrl: 0,
+            alarm: false,
+            owner_vm: None,
+        }
+    }
+
+    /// Raise the alarm SPI in `vm_id` rather than the current VM.
+    pub fn set_owner_vm(&mut self, vm_id: usize) {
+        self.owner_vm = Some(vm_id);
+    }
+
+    /// Set the temperature reported to the guest (m°C).
+    pub fn set_temp(&mut self, millicelsius: i32) {
+        self.temp = millicelsius;
+        self.update_alarm();
+    }
+
+    /// Set the supply voltage reported to the guest (mV).
+    pub fn set_voltage(&mut self, millivolts: u32) {
+        self.voltage = millivolts;
+    }
+
+    pub fn temp(&self) -> i32 {
+        self.temp
+    }
+
+    fn status(&self) -> u32 {
+        if self.temp > self.threshold {
+            STATUS_ALARM
+        } else {
+            0
+        }
+    }
+
+    /// Drive the alarm SPI line: raised on a rising edge, withdrawn on a
+    /// falling one, re-fired after EOI while it stays high.
+    fn update_alarm(&mut self) {
+        let level = self.ctrl & CTRL_ALARM_EN != 0 && self.status() & STATUS_ALARM != 0;
+        if level == self.alarm {
+            return;
+        }
+        self.alarm = level;
+        let vm_id = self.owner_vm.unwrap_or_else(crate::global::current_vm_id);
+        crate::global::set_spi_level(vm_id, SENSOR_INTID, level);
+    }
+}
+
+impl Default for VirtualSensor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for VirtualSensor {
+    /// A removed sensor no longer drives its alarm line.
+    fn drop(&mut self) {
+        if self.alarm {
+            let vm_id = self.owner_vm.unwrap_or_else(crate::global::current_vm_id);
+            crate::global::set_spi_level(vm_id, SENSOR_INTID, false);
+        }
+    }
+}
+
+impl MmioDevice for VirtualSensor {
+    fn read(&mut self, offset: u64, size: u8) -> Option<u64> {
+        if size != 4 {
+            return Some(0);
+        }
+
+        let value = match offset {
+            SENSOR_ID => SENSOR_ID_VALUE,
+            TEMP => self.temp as u32 as u64,
+            VOLTAGE => self.voltage as u64,
+            THRESHOLD => self.threshold as u32 as u64,
+            CTRL => self.ctrl as u64,
+            STATUS => self.status() as u64,
+            _ => 0,
+        };
+
+        Some(value)
+    }
+
+    fn write(&mut self, offset: u64, value: u64, size: u8) -> bool {
+        if size != 4 {
+            return false;
+        }
+
+        match offset {
+            THRESHOLD => self.threshold = value as u32 as i32,
+            CTRL => self.ctrl = value as u32 & CTRL_ALARM_EN,
+            _ => return true, // read-only or unknown — ignore
+        }
+        self.update_alarm();
+        true
+    }
+
+    fn base_address(&self) -> u64 {
+        SENSOR_BASE
+    }
+
+    fn size(&self) -> u64 {
+        SENSOR_SIZE
+    }
+
+    fn pending_irq(&self) -> Option<u32> {
+        self.alarm.then_some(SENSOR_INTID)
+    }
+
+    fn decode_offset(&self, offset: u64) -> &'static str {
+        match offset {
+            SENSOR_ID => "SENSOR_ID",
+            TEMP => "TEMP",
+            VOLTAGE => "VOLTAGE",
+            THRESHOLD => "THRESHOLD",
+            CTRL => "CTRL",
+            STATUS => "STATUS",
+            _ => "unknown",
+        }
+    }
+}
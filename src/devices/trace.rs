@@ -0,0 +1,132 @@
+//! MMIO access trace.
+//!
+//! A small per-DeviceManager ring of recent guest MMIO accesses. Each entry
+//! carries the register name from `MmioDevice::decode_offset()`, so a dump
+//! reads "GICD ISENABLER" instead of a bare address. Disabled by default;
+//! recording costs one copy per access when enabled.
+
+use crate::{uart_put_hex, uart_put_u64, uart_puts};
+
+/// Number of entries kept; older entries are overwritten.
+pub const MMIO_TRACE_LEN: usize = 32;
+
+/// One traced MMIO access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MmioTraceEntry {
+    /// Guest physical address accessed
+    pub addr: u64,
+    /// Offset within the device (0 for unmapped addresses)
+    pub offset: u64,
+    /// Value written, or value returned for reads
+    pub value: u64,
+    /// Access width in bytes
+    pub size: u8,
+    pub is_write: bool,
+    /// Decoded register name
+    pub reg: &'static str,
+}
+
+impl MmioTraceEntry {
+    const EMPTY: Self = Self {
+        addr: 0,
+        offset: 0,
+        value: 0,
+        size: 0,
+        is_write: false,
+        reg: "",
+    };
+}
+
+/// Ring buffer of recent MMIO accesses.
+pub struct MmioTrace {
+    enabled: bool,
+    entries: [MmioTraceEntry; MMIO_TRACE_LEN],
+    /// Next slot to write
+    head: usize,
+    /// Number of valid entries (saturates at MMIO_TRACE_LEN)
+    len: usize,
+}
+
+impl MmioTrace {
+    pub const fn new() -> Self {
+        Self {
+            enabled: false,
+            entries: [MmioTraceEntry::EMPTY; MMIO_TRACE_LEN],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Drop all recorded entries.
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    /// Record an access (no-op while disabled).
+    pub fn record(&mut self, entry: MmioTraceEntry) {
+        if !self.enabled {
+            return;
+        }
+        self.entries[self.head] = entry;
+        self.head = (self.head + 1) % MMIO_TRACE_LEN;
+        if self.len < MMIO_TRACE_LEN {
+            self.len += 1;
+        }
+    }
+
+    /// Number of recorded entries.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Entry `i`, oldest first.
+    pub fn get(&self, i: usize) -> Option<&MmioTraceEntry> {
+        if i >= self.len {
+            return None;
+        }
+        let start = (self.head + MMIO_TRACE_LEN - self.len) % MMIO_TRACE_LEN;
+        Some(&self.entries[(start + i) % MMIO_TRACE_LEN])
+    }
+
+    /// Print all entries, oldest first, one per line:
+    /// `[MMIO-TRACE] W ISENABLER +0x100 @0x8000100 = 0x1 (4B)`
+    pub fn dump(&self) {
+        uart_puts(b"[MMIO-TRACE] ");
+        uart_put_u64(self.len as u64);
+        uart_puts(b" entries\n");
+        for i in 0..self.len {
+            let e = self.get(i).unwrap();
+            uart_puts(b"[MMIO-TRACE] ");
+            uart_puts(if e.is_write { b"W " } else { b"R " });
+            uart_puts(e.reg.as_bytes());
+            uart_puts(b" +0x");
+            uart_put_hex(e.offset);
+            uart_puts(b" @0x");
+            uart_put_hex(e.addr);
+            uart_puts(b" = 0x");
+            uart_put_hex(e.value);
+            uart_puts(b" (");
+            uart_put_u64(e.size as u64);
+            uart_puts(b"B)\n");
+        }
+    }
+}
+
+impl Default for MmioTrace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,220 @@
+//! Virtio memory balloon device backend.
+//!
+//! Implements a virtio-balloon (device ID 5) with the stats queue.
+//! inflateq (0) / deflateq (1): PFN arrays, counted and returned. Pages are
+//! not unmapped from Stage-2 yet.
+//! statsq (2): guest → host memory statistics. The guest keeps one buffer
+//! posted; the device parses it and holds it until `request_stats()` hands
+//! it back to ask for fresh numbers.
+
+use super::queue::Virtqueue;
+use super::VirtioDevice;
+
+// ── Feature bits ────────────────────────────────────────────────────
+const VIRTIO_BALLOON_F_STATS_VQ: u64 = 1 << 1;
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+// ── Queue indices ───────────────────────────────────────────────────
+const INFLATEQ: u16 = 0;
+const DEFLATEQ: u16 = 1;
+pub const STATSQ: u16 = 2;
+
+// ── Stat tags (virtio spec 5.5.6.3) ────────────────────────────────
+pub const VIRTIO_BALLOON_S_SWAP_IN: u16 = 0;
+pub const VIRTIO_BALLOON_S_SWAP_OUT: u16 = 1;
+pub const VIRTIO_BALLOON_S_MAJFLT: u16 = 2;
+pub const VIRTIO_BALLOON_S_MINFLT: u16 = 3;
+pub const VIRTIO_BALLOON_S_MEMFREE: u16 = 4;
+pub const VIRTIO_BALLOON_S_MEMTOT: u16 = 5;
+pub const VIRTIO_BALLOON_S_AVAIL: u16 = 6;
+pub const VIRTIO_BALLOON_S_CACHES: u16 = 7;
+
+/// Size of a virtio_balloon_stat entry (le16 tag + le64 val, packed).
+pub const VIRTIO_BALLOON_STAT_SIZE: usize = 10;
+
+/// Size of one PFN entry in the inflate/deflate queues.
+const PFN_SIZE: u32 = 4;
+
+/// Latest memory statistics reported by the guest.
+///
+/// Memory values are in bytes; swap and fault values are counts.
+/// Tags the guest did not report keep their previous value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BalloonStats {
+    pub swap_in: u64,
+    pub swap_out: u64,
+    pub major_faults: u64,
+    pub minor_faults: u64,
+    pub free_memory: u64,
+    pub total_memory: u64,
+    pub available_memory: u64,
+    pub disk_caches: u64,
+    /// Number of stats buffers parsed
+    pub updates: u32,
+}
+
+impl BalloonStats {
+    /// Apply one (tag, value) entry. Unknown tags are ignored.
+    fn apply(&mut self, tag: u16, val: u64) {
+        match tag {
+            VIRTIO_BALLOON_S_SWAP_IN => self.swap_in = val,
+            VIRTIO_BALLOON_S_SWAP_OUT => self.swap_out = val,
+            VIRTIO_BALLOON_S_MAJFLT => self.major_faults = val,
+            VIRTIO_BALLOON_S_MINFLT => self.minor_faults = val,
+            VIRTIO_BALLOON_S_MEMFREE => self.free_memory = val,
+            VIRTIO_BALLOON_S_MEMTOT => self.total_memory = val,
+            VIRTIO_BALLOON_S_AVAIL => self.available_memory = val,
+            VIRTIO_BALLOON_S_CACHES => self.disk_caches = val,
+            _ => {}
+        }
+    }
+}
+
+/// Virtio-balloon device backend.
+pub struct VirtioBalloon {
+    /// Target balloon size in 4KB pages (config `num_pages`, host-set)
+    num_pages: u32,
+    /// Current balloon size in 4KB pages (config `actual`, guest-set)
+    actual: u32,
+    /// Net pages received on inflateq minus deflateq
+    inflated_pages: u32,
+    stats: BalloonStats,
+    /// Head of the stats buffer held until the next `request_stats()`
+    stats_head: Option<u16>,
+}
+
+impl VirtioBalloon {
+    pub const fn new() -> Self {
+        Self {
+            num_pages: 0,
+            actual: 0,
+            inflated_pages: 0,
+            stats: BalloonStats {
+                swap_in: 0,
+                swap_out: 0,
+                major_faults: 0,
+                minor_faults: 0,
+                free_memory: 0,
+                total_memory: 0,
+                available_memory: 0,
+                disk_caches: 0,
+                updates: 0,
+            },
+            stats_head: None,
+        }
+    }
+
+    /// Latest statistics reported on the stats queue.
+    pub fn stats(&self) -> BalloonStats {
+        self.stats
+    }
+
+    /// Set the target balloon size (in 4KB pages).
+    pub fn set_target_pages(&mut self, pages: u32) {
+        self.num_pages = pages;
+    }
+
+    /// Balloon size last reported by the guest in config `actual`.
+    pub fn actual_pages(&self) -> u32 {
+        self.actual
+    }
+
+    /// Net pages the guest has handed over on inflateq/deflateq.
+    pub fn inflated_pages(&self) -> u32 {
+        self.inflated_pages
+    }
+
+    /// Take the held stats buffer head (used by the transport to return it).
+    pub(crate) fn take_stats_head(&mut self) -> Option<u16> {
+        self.stats_head.take()
+    }
+
+    /// Drain inflateq/deflateq, counting the PFNs in each chain.
+    fn process_pfns(&mut self, queue_idx: u16, queue: &mut Virtqueue) {
+        while let Some(chain) = queue.get_avail_desc() {
+            let pfns: u32 = chain.descs[..chain.count]
+                .iter()
+                .map(|d| d.len / PFN_SIZE)
+                .sum();
+            if queue_idx == INFLATEQ {
+                self.inflated_pages = self.inflated_pages.saturating_add(pfns);
+            } else {
+                self.inflated_pages = self.inflated_pages.saturating_sub(pfns);
+            }
+            queue.put_used(chain.head, 0);
+        }
+    }
+
+    /// Parse the posted stats buffer and hold on to it.
+    fn process_stats(&mut self, queue: &mut Virtqueue) {
+        let dma = queue.dma();
+        while let Some(chain) = queue.get_avail_desc() {
+            // A second buffer without a request in between: return the old one
+            if let Some(old) = self.stats_head.take() {
+                queue.put_used(old, 0);
+            }
+            for desc in &chain.descs[..chain.count] {
+                let entries = desc.len as usize / VIRTIO_BALLOON_STAT_SIZE;
+                for i in 0..entries {
+                    let mut raw = [0u8; VIRTIO_BALLOON_STAT_SIZE];
+                    let ipa = desc.addr + (i * VIRTIO_BALLOON_STAT_SIZE) as u64;
+                    if !dma.read(ipa, &mut raw) {
+                        break;
+                    }
+                    let tag = u16::from_le_bytes([raw[0], raw[1]]);
+                    let mut val = [0u8; 8];
+                    val.copy_from_slice(&raw[2..10]);
+                    self.stats.apply(tag, u64::from_le_bytes(val));
+                }
+            }
+            self.stats.updates = self.stats.updates.wrapping_add(1);
+            self.stats_head = Some(chain.head);
+        }
+    }
+}
+
+impl Default for VirtioBalloon {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtioDevice for VirtioBalloon {
+    fn device_id(&self) -> u32 {
+        5 // VIRTIO_ID_BALLOON
+    }
+
+    fn device_features(&self) -> u64 {
+        VIRTIO_F_VERSION_1 | VIRTIO_BALLOON_F_STATS_VQ
+    }
+
+    fn config_read(&self, offset: u64, size: u8) -> u64 {
+        // Virtio-balloon config space layout:
+        //   0x00: num_pages (u32, target)
+        //   0x04: actual (u32, guest-reported)
+        match (offset, size) {
+            (0, 4) => self.num_pages as u64,
+            (4, 4) => self.actual as u64,
+            _ => 0,
+        }
+    }
+
+    fn config_write(&mut self, offset: u64, value: u64, size: u8) {
+        // Only `actual` is driver-writable
+        if (offset, size) == (4, 4) {
+            self.actual = value as u32;
+        }
+    }
+
+    fn queue_notify(&mut self, queue_idx: u16, queue: &mut Virtqueue) {
+        match queue_idx {
+            INFLATEQ | DEFLATEQ => self.process_pfns(queue_idx, queue),
+            STATSQ => self.process_stats(queue),
+            _ => {}
+        }
+    }
+
+    fn num_queues(&self) -> u16 {
+        3 // inflateq, deflateq, statsq
+    }
+}
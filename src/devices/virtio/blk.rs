@@ -2,14 +2,23 @@
 //!
 //! Implements a simple virtio-blk device backed by an in-memory disk image.
 //! The disk image is loaded into guest physical memory by QEMU's -device loader.
-//! Since we use identity mapping, the hypervisor reads/writes the image directly.
+//! A device created with `new_read_only` advertises VIRTIO_BLK_F_RO and
+//! fails writes, for images such as a CD-ROM that must not change.
+//! Requests reaching past the end of the image fail as a whole with
+//! VIRTIO_BLK_S_IOERR; FLUSH always succeeds since the image is RAM.
+//! The hypervisor reads/writes the image directly; guest request buffers are
+//! accessed through the queue's `DmaMapper`. Requests may come through an
+//! indirect descriptor table (VIRTIO_RING_F_INDIRECT_DESC), which the queue
+//! unrolls. A request with more data segments than the advertised seg_max
+//! fails with VIRTIO_BLK_S_IOERR.
 
-use super::queue::Virtqueue;
+use super::queue::{Virtqueue, MAX_CHAIN_DESCS};
 use super::VirtioDevice;
 
 // ── Virtio-blk request types ────────────────────────────────────────
 const VIRTIO_BLK_T_IN: u32 = 0; // Read from disk
 const VIRTIO_BLK_T_OUT: u32 = 1; // Write to disk
+const VIRTIO_BLK_T_FLUSH: u32 = 4; // Flush volatile write cache
 const VIRTIO_BLK_T_GET_ID: u32 = 8; // Get device ID string
 
 // ── Virtio-blk status codes ────────────────────────────────────────
@@ -20,9 +29,16 @@ const VIRTIO_BLK_S_UNSUPP: u8 = 2;
 // ── Virtio-blk feature bits ────────────────────────────────────────
 const VIRTIO_BLK_F_SIZE_MAX: u64 = 1 << 1;
 const VIRTIO_BLK_F_SEG_MAX: u64 = 1 << 2;
+const VIRTIO_BLK_F_RO: u64 = 1 << 5;
 const VIRTIO_BLK_F_BLK_SIZE: u64 = 1 << 6;
+const VIRTIO_BLK_F_FLUSH: u64 = 1 << 9;
+const VIRTIO_RING_F_INDIRECT_DESC: u64 = 1 << 28;
 const VIRTIO_F_VERSION_1: u64 = 1 << 32;
 
+/// Data segments per request (config seg_max): a chain with header and
+/// status must fit in one `DescChain`
+const SEG_MAX: u32 = (MAX_CHAIN_DESCS - 2) as u32;
+
 /// Virtio-blk request header (16 bytes, from guest memory).
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -40,6 +56,8 @@ pub struct VirtioBlk {
     disk_size: u64,
     /// Capacity in 512-byte sectors
     capacity: u64,
+    /// Advertise VIRTIO_BLK_F_RO and fail write requests
+    read_only: bool,
 }
 
 impl VirtioBlk {
@@ -52,9 +70,47 @@ impl VirtioBlk {
             disk_base,
             disk_size,
             capacity: disk_size / 512,
+            read_only: false,
+        }
+    }
+
+    /// Create a read-only virtio-blk device (e.g. a CD-ROM image).
+    ///
+    /// Write requests complete with `VIRTIO_BLK_S_IOERR`; the image is
+    /// never modified.
+    pub fn new_read_only(disk_base: u64, disk_size: u64) -> Self {
+        Self {
+            read_only: true,
+            ..Self::new(disk_base, disk_size)
         }
     }
 
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Byte offset in the image of an IN/OUT request at `sector` whose data
+    /// descriptors (all but the header and status) fit entirely inside
+    /// `disk_size`, or None if any part of it would fall outside.
+    fn data_offset(
+        &self,
+        sector: u64,
+        descs: &[super::queue::VirtqDesc],
+        count: usize,
+    ) -> Option<u64> {
+        let offset = sector.checked_mul(512)?;
+        let len: u64 = descs[1..count - 1].iter().map(|d| d.len as u64).sum();
+        let end = offset.checked_add(len)?;
+        (end <= self.disk_size).then_some(offset)
+    }
+
+    /// Complete a request with VIRTIO_BLK_S_IOERR without touching its
+    /// data buffers.
+    fn fail_request(queue: &mut Virtqueue, head: u16, status_desc: &super::queue::VirtqDesc) {
+        let written = queue.dma().write_val(status_desc.addr, VIRTIO_BLK_S_IOERR);
+        queue.put_used(head, written as u32);
+    }
+
     /// Process a single virtio-blk request from a descriptor chain.
     fn process_request(
         &mut self,
@@ -68,15 +124,29 @@ impl VirtioBlk {
             return;
         }
 
+        let dma = queue.dma();
+
         // Descriptor 0: request header (device-readable, 16 bytes)
-        let hdr_addr = descs[0].addr;
-        let header: VirtioBlkReqHeader =
-            unsafe { core::ptr::read_volatile(hdr_addr as *const VirtioBlkReqHeader) };
+        let header: VirtioBlkReqHeader = match dma.read_val(descs[0].addr) {
+            Some(h) => h,
+            None => {
+                // Header outside guest memory — complete without touching it
+                queue.put_used(head, 0);
+                return;
+            }
+        };
 
         let mut status = VIRTIO_BLK_S_OK;
         let mut total_written = 0u32;
 
         match header.req_type {
+            VIRTIO_BLK_T_IN | VIRTIO_BLK_T_OUT
+                if self.data_offset(header.sector, descs, count).is_none() =>
+            {
+                // Past the end of the image: fail before touching anything
+                status = VIRTIO_BLK_S_IOERR;
+            }
+
             VIRTIO_BLK_T_IN => {
                 // Read from disk: copy data from disk image to guest buffers
                 let byte_offset = header.sector * 512;
@@ -87,23 +157,25 @@ impl VirtioBlk {
                     let desc = &descs[i];
                     let len = desc.len as u64;
 
-                    if disk_off + len > self.disk_size {
-                        status = VIRTIO_BLK_S_IOERR;
-                        break;
-                    }
-
-                    unsafe {
-                        core::ptr::copy_nonoverlapping(
+                    let src = unsafe {
+                        core::slice::from_raw_parts(
                             (self.disk_base + disk_off) as *const u8,
-                            desc.addr as *mut u8,
                             len as usize,
-                        );
+                        )
+                    };
+                    if !dma.write(desc.addr, src) {
+                        status = VIRTIO_BLK_S_IOERR;
+                        break;
                     }
                     disk_off += len;
                     total_written += desc.len;
                 }
             }
 
+            VIRTIO_BLK_T_OUT if self.read_only => {
+                status = VIRTIO_BLK_S_IOERR;
+            }
+
             VIRTIO_BLK_T_OUT => {
                 // Write to disk: copy data from guest buffers to disk image
                 let byte_offset = header.sector * 512;
@@ -113,32 +185,39 @@ impl VirtioBlk {
                     let desc = &descs[i];
                     let len = desc.len as u64;
 
-                    if disk_off + len > self.disk_size {
-                        status = VIRTIO_BLK_S_IOERR;
-                        break;
-                    }
-
-                    unsafe {
-                        core::ptr::copy_nonoverlapping(
-                            desc.addr as *const u8,
+                    let dst = unsafe {
+                        core::slice::from_raw_parts_mut(
                             (self.disk_base + disk_off) as *mut u8,
                             len as usize,
-                        );
+                        )
+                    };
+                    if !dma.read(desc.addr, dst) {
+                        status = VIRTIO_BLK_S_IOERR;
+                        break;
                     }
                     disk_off += len;
                 }
             }
 
+            VIRTIO_BLK_T_FLUSH => {
+                // The image is plain RAM: nothing is cached, nothing to flush
+            }
+
             VIRTIO_BLK_T_GET_ID => {
                 // Return a device ID string
                 if count >= 3 {
                     let desc = &descs[1];
-                    let id = b"hypervisor-vda\0\0\0\0\0\0";
+                    let id = if self.read_only {
+                        b"hypervisor-cdrom\0\0\0\0"
+                    } else {
+                        b"hypervisor-vda\0\0\0\0\0\0"
+                    };
                     let copy_len = core::cmp::min(desc.len as usize, 20);
-                    unsafe {
-                        core::ptr::copy_nonoverlapping(id.as_ptr(), desc.addr as *mut u8, copy_len);
+                    if dma.write(desc.addr, &id[..copy_len]) {
+                        total_written = copy_len as u32;
+                    } else {
+                        status = VIRTIO_BLK_S_IOERR;
                     }
-                    total_written = copy_len as u32;
                 }
             }
 
@@ -149,10 +228,9 @@ impl VirtioBlk {
 
         // Last descriptor: status byte (device-writable, 1 byte)
         let status_desc = &descs[count - 1];
-        unsafe {
-            core::ptr::write_volatile(status_desc.addr as *mut u8, status);
+        if dma.write_val(status_desc.addr, status) {
+            total_written += 1; // status byte
         }
-        total_written += 1; // status byte
 
         queue.put_used(head, total_written);
     }
@@ -164,7 +242,17 @@ impl VirtioDevice for VirtioBlk {
     } // VIRTIO_ID_BLOCK
 
     fn device_features(&self) -> u64 {
-        VIRTIO_F_VERSION_1 | VIRTIO_BLK_F_BLK_SIZE | VIRTIO_BLK_F_SIZE_MAX | VIRTIO_BLK_F_SEG_MAX
+        let features = VIRTIO_F_VERSION_1
+            | VIRTIO_BLK_F_BLK_SIZE
+            | VIRTIO_BLK_F_SIZE_MAX
+            | VIRTIO_BLK_F_SEG_MAX
+            | VIRTIO_BLK_F_FLUSH
+            | VIRTIO_RING_F_INDIRECT_DESC;
+        if self.read_only {
+            features | VIRTIO_BLK_F_RO
+        } else {
+            features
+        }
     }
 
     fn config_read(&self, offset: u64, size: u8) -> u64 {
@@ -181,7 +269,7 @@ impl VirtioDevice for VirtioBlk {
             // size_max: 32-bit at offset 8
             (8, 4) => 0x0020_0000, // 2MB max segment
             // seg_max: 32-bit at offset 12
-            (12, 4) => 128,
+            (12, 4) => SEG_MAX as u64,
             // blk_size: 32-bit at offset 20
             (20, 4) => 512,
             _ => 0,
@@ -195,6 +283,11 @@ impl VirtioDevice for VirtioBlk {
     fn queue_notify(&mut self, _queue_idx: u16, queue: &mut Virtqueue) {
         // Process all available descriptor chains
         while let Some(chain) = queue.get_avail_desc() {
+            if chain.truncated {
+                // More segments than seg_max: fail it through its status byte
+                Self::fail_request(queue, chain.head, &chain.descs[chain.count - 1]);
+                continue;
+            }
             self.process_request(queue, chain.head, &chain.descs, chain.count);
         }
     }
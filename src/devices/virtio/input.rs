@@ -0,0 +1,194 @@
+//! Virtio input device backend.
+//!
+//! Implements a virtio-input keyboard with relative pointer axes
+//! (device ID 18).
+//! eventq (0): host → guest input events, injected via inject_event().
+//! statusq (1): guest → host status (LED state), consumed and ignored.
+
+use super::queue::Virtqueue;
+use super::VirtioDevice;
+
+// ── Feature bits ────────────────────────────────────────────────────
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+// ── Config select values (virtio spec 5.8.5) ───────────────────────
+const VIRTIO_INPUT_CFG_UNSET: u8 = 0x00;
+const VIRTIO_INPUT_CFG_ID_NAME: u8 = 0x01;
+const VIRTIO_INPUT_CFG_ID_SERIAL: u8 = 0x02;
+const VIRTIO_INPUT_CFG_ID_DEVIDS: u8 = 0x03;
+const VIRTIO_INPUT_CFG_EV_BITS: u8 = 0x11;
+
+/// Offset of the config payload union (after select/subsel/size/reserved[5])
+const CONFIG_PAYLOAD: u64 = 8;
+/// Size of the config payload union
+const CONFIG_PAYLOAD_SIZE: u64 = 128;
+
+// ── Linux input event types ────────────────────────────────────────
+pub const EV_SYN: u16 = 0x00;
+pub const EV_KEY: u16 = 0x01;
+pub const EV_REL: u16 = 0x02;
+
+// ── Linux relative axis codes ──────────────────────────────────────
+pub const REL_X: u16 = 0x00;
+pub const REL_Y: u16 = 0x01;
+pub const REL_WHEEL: u16 = 0x08;
+
+/// Number of key codes advertised in the EV_KEY bitmap (KEY_ESC..KEY_MAX/2)
+const KEY_BITMAP_BYTES: u8 = 32;
+/// EV_REL bitmap: REL_X, REL_Y and REL_WHEEL
+const REL_BITMAP: [u8; 2] = [(1 << REL_X) | (1 << REL_Y), 1 << (REL_WHEEL - 8)];
+
+const INPUT_NAME: &[u8] = b"hypervisor virtio keyboard";
+const INPUT_SERIAL: &[u8] = b"0";
+
+/// Device IDs: bustype=BUS_VIRTUAL, vendor=0x0627 (QEMU), product=1, version=1
+const INPUT_DEVIDS: [u16; 4] = [0x0006, 0x0627, 0x0001, 0x0001];
+
+/// Size of a virtio_input_event in the eventq.
+pub const VIRTIO_INPUT_EVENT_SIZE: usize = 8;
+
+/// A single input event (struct virtio_input_event, little-endian).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VirtioInputEvent {
+    pub ev_type: u16,
+    pub code: u16,
+    pub value: u32,
+}
+
+impl VirtioInputEvent {
+    pub const fn new(ev_type: u16, code: u16, value: u32) -> Self {
+        Self {
+            ev_type,
+            code,
+            value,
+        }
+    }
+
+    /// Encode the event as it appears in guest memory.
+    pub fn to_bytes(&self) -> [u8; VIRTIO_INPUT_EVENT_SIZE] {
+        let t = self.ev_type.to_le_bytes();
+        let c = self.code.to_le_bytes();
+        let v = self.value.to_le_bytes();
+        [t[0], t[1], c[0], c[1], v[0], v[1], v[2], v[3]]
+    }
+}
+
+/// Virtio-input keyboard backend.
+pub struct VirtioInput {
+    /// Config space selector (virtio_input_config.select)
+    select: u8,
+    /// Config space sub-selector (virtio_input_config.subsel)
+    subsel: u8,
+}
+
+impl VirtioInput {
+    pub fn new() -> Self {
+        Self {
+            select: VIRTIO_INPUT_CFG_UNSET,
+            subsel: 0,
+        }
+    }
+
+    /// Size of the payload selected by (select, subsel); 0 if unsupported.
+    fn payload_size(&self) -> u8 {
+        match (self.select, self.subsel) {
+            (VIRTIO_INPUT_CFG_ID_NAME, _) => INPUT_NAME.len() as u8,
+            (VIRTIO_INPUT_CFG_ID_SERIAL, _) => INPUT_SERIAL.len() as u8,
+            (VIRTIO_INPUT_CFG_ID_DEVIDS, _) => 8,
+            (VIRTIO_INPUT_CFG_EV_BITS, s) if s as u16 == EV_KEY => KEY_BITMAP_BYTES,
+            (VIRTIO_INPUT_CFG_EV_BITS, s) if s as u16 == EV_REL => REL_BITMAP.len() as u8,
+            _ => 0,
+        }
+    }
+
+    /// Byte `idx` of the selected payload.
+    fn payload_byte(&self, idx: usize) -> u8 {
+        if idx >= self.payload_size() as usize {
+            return 0;
+        }
+        match self.select {
+            VIRTIO_INPUT_CFG_ID_NAME => INPUT_NAME[idx],
+            VIRTIO_INPUT_CFG_ID_SERIAL => INPUT_SERIAL[idx],
+            VIRTIO_INPUT_CFG_ID_DEVIDS => INPUT_DEVIDS[idx / 2].to_le_bytes()[idx % 2],
+            VIRTIO_INPUT_CFG_EV_BITS if self.subsel as u16 == EV_REL => REL_BITMAP[idx],
+            // Every key code except KEY_RESERVED (0)
+            VIRTIO_INPUT_CFG_EV_BITS if idx == 0 => 0xFE,
+            VIRTIO_INPUT_CFG_EV_BITS => 0xFF,
+            _ => 0,
+        }
+    }
+
+    /// Byte at `offset` within virtio_input_config.
+    fn config_byte(&self, offset: u64) -> u8 {
+        match offset {
+            0 => self.select,
+            1 => self.subsel,
+            2 => self.payload_size(),
+            o if (CONFIG_PAYLOAD..CONFIG_PAYLOAD + CONFIG_PAYLOAD_SIZE).contains(&o) => {
+                self.payload_byte((o - CONFIG_PAYLOAD) as usize)
+            }
+            _ => 0,
+        }
+    }
+}
+
+impl Default for VirtioInput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtioDevice for VirtioInput {
+    fn device_id(&self) -> u32 {
+        18
+    } // VIRTIO_ID_INPUT
+
+    fn device_features(&self) -> u64 {
+        VIRTIO_F_VERSION_1
+    }
+
+    fn config_read(&self, offset: u64, size: u8) -> u64 {
+        // Config space layout:
+        //   0x00: select  0x01: subsel  0x02: size  0x03-0x07: reserved
+        //   0x08-0x87: payload union (string / bitmap / devids)
+        let mut val = 0u64;
+        for i in 0..(size.min(8) as u64) {
+            val |= (self.config_byte(offset + i) as u64) << (i * 8);
+        }
+        val
+    }
+
+    fn config_write(&mut self, offset: u64, value: u64, size: u8) {
+        // Only select and subsel are writable
+        for i in 0..(size.min(8) as u64) {
+            let byte = (value >> (i * 8)) as u8;
+            match offset + i {
+                0 => self.select = byte,
+                1 => self.subsel = byte,
+                _ => {}
+            }
+        }
+    }
+
+    fn queue_notify(&mut self, queue_idx: u16, queue: &mut Virtqueue) {
+        match queue_idx {
+            0 => {} // eventq — guest replenishing buffers, no action needed
+            1 => {
+                // statusq — LED updates are not emulated, just return buffers
+                while let Some(chain) = queue.get_avail_desc() {
+                    queue.put_used(chain.head, 0);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn num_queues(&self) -> u16 {
+        2
+    } // eventq=0, statusq=1
+
+    fn max_queue_size(&self) -> u16 {
+        64
+    }
+}
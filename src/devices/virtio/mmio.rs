@@ -6,10 +6,11 @@
 
 use super::queue::Virtqueue;
 use super::VirtioDevice;
+use crate::devices::dma::DmaMapper;
 use crate::devices::MmioDevice;
 
 /// Maximum number of virtqueues per device
-const MAX_QUEUES: usize = 2;
+const MAX_QUEUES: usize = 3;
 
 // ── Virtio-MMIO register offsets ────────────────────────────────────
 const MAGIC_VALUE: u64 = 0x000;
@@ -44,6 +45,44 @@ const VIRTIO_VENDOR_ID: u32 = 0x554D4551; // "QEMU"
 
 // ── Interrupt status bits ───────────────────────────────────────────
 const VIRTIO_INT_VRING: u32 = 1;
+const VIRTIO_INT_CONFIG: u32 = 2;
+
+/// Transport feature: `used_event`/`avail_event` notification suppression
+pub const VIRTIO_RING_F_EVENT_IDX: u64 = 1 << 29;
+
+/// Notification and interrupt counters of one transport.
+///
+/// Suppressed notifications are buffers the driver made available without
+/// a doorbell of their own (picked up while handling an earlier one);
+/// suppressed interrupts are completions whose used index did not cross
+/// the driver's `used_event`; coalesced ones arrived while InterruptStatus
+/// was still unacknowledged, so the SPI already raised covers them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VirtioStats {
+    /// QueueNotify writes received
+    pub notifications: u64,
+    /// Buffers consumed beyond the first per notification (EVENT_IDX)
+    pub notifications_suppressed: u64,
+    /// Used-buffer interrupts raised
+    pub interrupts: u64,
+    /// Used-buffer interrupts skipped due to `used_event` (EVENT_IDX)
+    pub interrupts_suppressed: u64,
+    /// Used-buffer notifications folded into a not yet ACKed interrupt
+    pub interrupts_coalesced: u64,
+}
+
+/// Snapshot of transport-level state (status, features, queue positions).
+///
+/// Backend-specific state (disk contents, config selectors) is not included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VirtioTransportState {
+    pub queues: [Virtqueue; MAX_QUEUES],
+    pub queue_sel: u32,
+    pub status: u32,
+    pub interrupt_status: u32,
+    pub driver_features: u64,
+    pub config_generation: u32,
+}
 
 /// Virtio-MMIO transport wrapping a device backend.
 pub struct VirtioMmioTransport<D: VirtioDevice> {
@@ -69,10 +108,14 @@ pub struct VirtioMmioTransport<D: VirtioDevice> {
     config_generation: u32,
     /// SPI INTID for this device (injected on completion)
     irq_intid: u32,
+    /// VM the SPI is raised in (`None` = whichever VM is current)
+    owner_vm: Option<usize>,
     /// Temporary storage for split 32-bit queue address writes
     queue_desc_high: u32,
     queue_driver_high: u32,
     queue_device_high: u32,
+    /// Notification/interrupt counters (kept across device reset)
+    stats: VirtioStats,
 }
 
 impl<D: VirtioDevice> VirtioMmioTransport<D> {
@@ -80,7 +123,7 @@ impl<D: VirtioDevice> VirtioMmioTransport<D> {
         Self {
             base,
             device,
-            queues: [Virtqueue::new(), Virtqueue::new()],
+            queues: [Virtqueue::new(); MAX_QUEUES],
             queue_sel: 0,
             status: 0,
             interrupt_status: 0,
@@ -89,9 +132,18 @@ impl<D: VirtioDevice> VirtioMmioTransport<D> {
             driver_features: 0,
             config_generation: 0,
             irq_intid,
+            owner_vm: None,
             queue_desc_high: 0,
             queue_driver_high: 0,
             queue_device_high: 0,
+            stats: VirtioStats::default(),
+        }
+    }
+
+    /// Bind all virtqueues to the owning VM's DMA domain.
+    pub fn set_dma(&mut self, dma: DmaMapper) {
+        for q in &mut self.queues {
+            q.set_dma(dma);
         }
     }
 
@@ -105,16 +157,112 @@ impl<D: VirtioDevice> VirtioMmioTransport<D> {
         }
     }
 
-    /// Signal interrupt to guest by queuing SPI via global mechanism.
-    fn signal_interrupt(&mut self) {
-        self.interrupt_status |= VIRTIO_INT_VRING;
-        crate::global::inject_spi(self.irq_intid);
+    /// Raise this device's SPI in `vm_id` rather than the current VM.
+    pub fn set_owner_vm(&mut self, vm_id: usize) {
+        self.owner_vm = Some(vm_id);
+    }
+
+    /// Set `cause` in InterruptStatus and raise the SPI line, unless an
+    /// earlier cause is still waiting for InterruptACK: the driver reads the
+    /// whole status in the handler that SPI runs, so a second one would only
+    /// find it already cleared. Returns whether the line went high.
+    ///
+    /// The line follows InterruptStatus != 0 (`update_irq_line`), so a
+    /// guest that configured the SPI level-sensitive sees it again after
+    /// EOI until it acknowledges every cause.
+    ///
+    /// Guest queue notifies and host-side completions (RX injection) both
+    /// run under this transport's device lock in multi-pCPU builds, so the
+    /// InterruptStatus check-and-set cannot interleave; `set_spi_level`
+    /// takes no device lock and queues the SPI with atomics.
+    fn signal_interrupt(&mut self, cause: u32) -> bool {
+        let pending = self.interrupt_status != 0;
+        self.interrupt_status |= cause;
+        self.update_irq_line();
+        !pending
+    }
+
+    /// Drive the SPI line from InterruptStatus.
+    fn update_irq_line(&self) {
+        let vm_id = self.owner_vm.unwrap_or_else(crate::global::current_vm_id);
+        crate::global::set_spi_level(vm_id, self.irq_intid, self.interrupt_status != 0);
+    }
+
+    /// Tell the driver the device configuration changed: bumps
+    /// ConfigGeneration and signals a config-change interrupt.
+    pub fn signal_config_change(&mut self) {
+        self.config_generation = self.config_generation.wrapping_add(1);
+        self.signal_interrupt(VIRTIO_INT_CONFIG);
+    }
+
+    /// Pending InterruptStatus bits (0=vring, 1=config change).
+    pub fn interrupt_status(&self) -> u32 {
+        self.interrupt_status
+    }
+
+    /// Whether the driver negotiated VIRTIO_RING_F_EVENT_IDX.
+    fn event_idx(&self) -> bool {
+        self.driver_features & VIRTIO_RING_F_EVENT_IDX != 0
+    }
+
+    /// Interrupt the guest for new used entries on `queue_idx`, unless
+    /// EVENT_IDX lets the driver do without one.
+    fn signal_used(&mut self, queue_idx: usize) {
+        let event_idx = self.event_idx();
+        match self.queues[queue_idx].used_signal(event_idx) {
+            Some(true) => {
+                if self.signal_interrupt(VIRTIO_INT_VRING) {
+                    self.stats.interrupts += 1;
+                } else {
+                    self.stats.interrupts_coalesced += 1;
+                }
+            }
+            Some(false) => self.stats.interrupts_suppressed += 1,
+            None => {}
+        }
+    }
+
+    /// SPI INTID raised on used-buffer notifications.
+    pub fn irq_intid(&self) -> u32 {
+        self.irq_intid
+    }
+
+    /// Notification and interrupt counters.
+    pub fn stats(&self) -> VirtioStats {
+        self.stats
+    }
+
+    /// Capture transport state for a VM checkpoint.
+    pub fn snapshot(&self) -> VirtioTransportState {
+        VirtioTransportState {
+            queues: self.queues,
+            queue_sel: self.queue_sel,
+            status: self.status,
+            interrupt_status: self.interrupt_status,
+            driver_features: self.driver_features,
+            config_generation: self.config_generation,
+        }
+    }
+
+    /// Restore transport state captured by `snapshot()`.
+    pub fn restore(&mut self, state: &VirtioTransportState) {
+        self.queues = state.queues;
+        self.queue_sel = state.queue_sel;
+        self.status = state.status;
+        self.interrupt_status = state.interrupt_status;
+        self.update_irq_line();
+        self.driver_features = state.driver_features;
+        self.config_generation = state.config_generation;
+        self.queue_desc_high = 0;
+        self.queue_driver_high = 0;
+        self.queue_device_high = 0;
     }
 
     /// Reset device to initial state.
     fn reset(&mut self) {
         self.status = 0;
         self.interrupt_status = 0;
+        self.update_irq_line();
         self.device_features_sel = 0;
         self.driver_features_sel = 0;
         self.driver_features = 0;
@@ -125,6 +273,16 @@ impl<D: VirtioDevice> VirtioMmioTransport<D> {
     }
 }
 
+impl<D: VirtioDevice> Drop for VirtioMmioTransport<D> {
+    /// A removed transport no longer drives its SPI line.
+    fn drop(&mut self) {
+        if self.interrupt_status != 0 {
+            self.interrupt_status = 0;
+            self.update_irq_line();
+        }
+    }
+}
+
 impl<D: VirtioDevice> MmioDevice for VirtioMmioTransport<D> {
     fn read(&mut self, offset: u64, size: u8) -> Option<u64> {
         // Config space reads can be 1/2/4 bytes
@@ -145,7 +303,7 @@ impl<D: VirtioDevice> MmioDevice for VirtioMmioTransport<D> {
             VENDOR_ID => VIRTIO_VENDOR_ID,
 
             DEVICE_FEATURES => {
-                let features = self.device.device_features();
+                let features = self.device.device_features() | VIRTIO_RING_F_EVENT_IDX;
                 if self.device_features_sel == 0 {
                     features as u32
                 } else {
@@ -206,6 +364,10 @@ impl<D: VirtioDevice> MmioDevice for VirtioMmioTransport<D> {
                     self.driver_features =
                         (self.driver_features & 0x0000_0000_FFFF_FFFF) | ((val as u64) << 32);
                 }
+                let event_idx = self.event_idx();
+                for q in &mut self.queues {
+                    q.set_event_idx(event_idx);
+                }
             }
 
             DRIVER_FEATURES_SEL => {
@@ -238,16 +400,37 @@ impl<D: VirtioDevice> MmioDevice for VirtioMmioTransport<D> {
                     && (queue_idx as usize) < self.device.num_queues() as usize
                     && self.queues[queue_idx as usize].ready
                 {
+                    self.stats.notifications += 1;
+                    let event_idx = self.event_idx();
                     // Split borrow: take queue out, call device, put back
                     let q = &mut self.queues[queue_idx as usize];
-                    self.device.queue_notify(queue_idx, q);
+                    let start = q.last_avail_idx();
+                    loop {
+                        let before = q.last_avail_idx();
+                        self.device.queue_notify(queue_idx, q);
+                        if !event_idx {
+                            break;
+                        }
+                        // avail_event already asks for the next doorbell;
+                        // re-check: a buffer added before the driver saw it
+                        // gets none
+                        core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+                        if q.last_avail_idx() == before || !q.has_avail() {
+                            break;
+                        }
+                    }
+                    let consumed = q.last_avail_idx().wrapping_sub(start) as u64;
+                    if event_idx && consumed > 1 {
+                        self.stats.notifications_suppressed += consumed - 1;
+                    }
                     // Signal interrupt after processing
-                    self.signal_interrupt();
+                    self.signal_used(queue_idx as usize);
                 }
             }
 
             INTERRUPT_ACK => {
                 self.interrupt_status &= !val;
+                self.update_irq_line();
             }
 
             STATUS => {
@@ -311,6 +494,36 @@ impl<D: VirtioDevice> MmioDevice for VirtioMmioTransport<D> {
     fn size(&self) -> u64 {
         0x200 // 512 bytes per virtio-mmio spec
     }
+
+    fn decode_offset(&self, offset: u64) -> &'static str {
+        match offset {
+            MAGIC_VALUE => "MagicValue",
+            VERSION => "Version",
+            DEVICE_ID => "DeviceID",
+            VENDOR_ID => "VendorID",
+            DEVICE_FEATURES => "DeviceFeatures",
+            DEVICE_FEATURES_SEL => "DeviceFeaturesSel",
+            DRIVER_FEATURES => "DriverFeatures",
+            DRIVER_FEATURES_SEL => "DriverFeaturesSel",
+            QUEUE_SEL => "QueueSel",
+            QUEUE_NUM_MAX => "QueueNumMax",
+            QUEUE_NUM => "QueueNum",
+            QUEUE_READY => "QueueReady",
+            QUEUE_NOTIFY => "QueueNotify",
+            INTERRUPT_STATUS => "InterruptStatus",
+            INTERRUPT_ACK => "InterruptACK",
+            STATUS => "Status",
+            QUEUE_DESC_LOW => "QueueDescLow",
+            QUEUE_DESC_HIGH => "QueueDescHigh",
+            QUEUE_DRIVER_LOW => "QueueDriverLow",
+            QUEUE_DRIVER_HIGH => "QueueDriverHigh",
+            QUEUE_DEVICE_LOW => "QueueDeviceLow",
+            QUEUE_DEVICE_HIGH => "QueueDeviceHigh",
+            CONFIG_GENERATION => "ConfigGeneration",
+            o if o >= CONFIG_SPACE => "Config",
+            _ => "unknown",
+        }
+    }
 }
 
 /// Specialized methods for VirtioNet transport (RX injection).
@@ -324,81 +537,128 @@ impl VirtioMmioTransport<super::net::VirtioNet> {
     /// Returns false if no RX descriptor is available (guest hasn't
     /// replenished its RX queue).
     pub fn inject_rx(&mut self, frame: &[u8]) -> bool {
-        let rx_queue = &mut self.queues[0];
-        let chain = match rx_queue.get_avail_desc() {
-            Some(c) => c,
-            None => return false, // No available RX buffer
-        };
-
-        if chain.count == 0 {
+        if !super::net::VirtioNet::fill_rx(&mut self.queues[0], frame) {
             return false;
         }
+        self.signal_used(0);
+        true
+    }
+
+    /// Deliver the frames the VSwitch queued for this device into posted
+    /// RX buffers, signalling an interrupt if any were used. Frames
+    /// without a buffer stay queued for the guest's next RX notify.
+    pub fn drain_rx(&mut self) {
+        if self.device.process_rx(&mut self.queues[0]) {
+            self.signal_used(0);
+        }
+    }
+}
 
-        // virtio_net_hdr_v1: 12 bytes, all zeroed except num_buffers=1
-        let hdr: [u8; 12] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0]; // num_buffers=1 (LE u16 at offset 10)
+/// Specialized methods for VirtioInput transport (event injection).
+impl VirtioMmioTransport<super::input::VirtioInput> {
+    /// Push an input event into the guest's eventq.
+    ///
+    /// Writes the 8-byte virtio_input_event into the first device-writable
+    /// descriptor of the next available chain, then signals an interrupt.
+    ///
+    /// Returns false if the guest hasn't posted any event buffers.
+    pub fn inject_event(&mut self, event: super::input::VirtioInputEvent) -> bool {
+        use super::input::VIRTIO_INPUT_EVENT_SIZE;
 
-        let combined_len = hdr.len() + frame.len();
+        let eventq = &mut self.queues[0];
+        let chain = match eventq.get_avail_desc() {
+            Some(c) => c,
+            None => return false, // No event buffer posted
+        };
 
-        // Check total writable capacity before writing
-        let mut total_cap = 0usize;
-        for i in 0..chain.count {
-            let desc = &chain.descs[i];
-            if desc.flags & super::queue::VIRTQ_DESC_F_WRITE != 0 {
-                total_cap += desc.len as usize;
+        let desc = chain.descs[..chain.count].iter().find(|d| {
+            d.flags & super::queue::VIRTQ_DESC_F_WRITE != 0
+                && d.len as usize >= VIRTIO_INPUT_EVENT_SIZE
+        });
+        let desc = match desc {
+            Some(d) => d,
+            None => {
+                // Buffer too small — return it with len=0 so guest can reuse
+                eventq.put_used(chain.head, 0);
+                return false;
             }
+        };
+
+        let bytes = event.to_bytes();
+        if !eventq.dma().write(desc.addr, &bytes) {
+            // Buffer outside guest memory — return it unused
+            eventq.put_used(chain.head, 0);
+            return false;
         }
-        if total_cap < combined_len {
-            // Descriptor chain too small — return it with len=0 so guest can reuse
-            rx_queue.put_used(chain.head, 0);
+
+        eventq.put_used(chain.head, VIRTIO_INPUT_EVENT_SIZE as u32);
+        self.signal_used(0);
+        true
+    }
+}
+
+/// Specialized methods for VirtioVsock transport (host side of the channel).
+impl VirtioMmioTransport<super::vsock::VirtioVsock> {
+    /// Send `data` to the guest from host port `port` and write it into a
+    /// posted RX buffer right away if there is one.
+    ///
+    /// Returns false if the message is too large or the device's outbox
+    /// is full.
+    pub fn vsock_send(&mut self, port: u32, data: &[u8]) -> bool {
+        if !self.device.vsock_send(port, data) {
             return false;
         }
+        self.drain_rx();
+        true
+    }
 
-        // Write header + frame into descriptor buffer(s) using bulk copies
-        let mut written = 0usize;
+    /// Oldest message the guest sent to the host, if any.
+    pub fn vsock_recv(&mut self) -> Option<super::vsock::VsockMessage> {
+        self.device.vsock_recv()
+    }
 
-        for i in 0..chain.count {
-            let desc = &chain.descs[i];
-            if desc.flags & super::queue::VIRTQ_DESC_F_WRITE == 0 {
-                continue;
-            }
-            let buf_addr = desc.addr as *mut u8;
-            let buf_cap = desc.len as usize;
-            let remaining = combined_len - written;
-            let to_write = if remaining < buf_cap {
-                remaining
-            } else {
-                buf_cap
-            };
-
-            unsafe {
-                let dst = buf_addr;
-                // Determine how much of header vs frame goes into this descriptor
-                if written < 12 {
-                    let hdr_remaining = 12 - written;
-                    let hdr_bytes = if hdr_remaining < to_write {
-                        hdr_remaining
-                    } else {
-                        to_write
-                    };
-                    core::ptr::copy_nonoverlapping(hdr.as_ptr().add(written), dst, hdr_bytes);
-                    if to_write > hdr_bytes {
-                        let frame_bytes = to_write - hdr_bytes;
-                        core::ptr::copy_nonoverlapping(
-                            frame.as_ptr(),
-                            dst.add(hdr_bytes),
-                            frame_bytes,
-                        );
-                    }
-                } else {
-                    let frame_offset = written - 12;
-                    core::ptr::copy_nonoverlapping(frame.as_ptr().add(frame_offset), dst, to_write);
-                }
-            }
-            written += to_write;
+    /// Write waiting host → guest packets (including replies to the
+    /// guest's control packets) into posted RX buffers, signalling an
+    /// interrupt if any were used.
+    pub fn drain_rx(&mut self) {
+        if self.device.rx_pending() && self.device.deliver_rx(&mut self.queues[0]) {
+            self.signal_used(0);
         }
+    }
+}
 
-        rx_queue.put_used(chain.head, written as u32);
-        self.signal_interrupt();
+/// Specialized methods for VirtioBalloon transport (stats requests).
+impl VirtioMmioTransport<super::balloon::VirtioBalloon> {
+    /// Ask the guest for fresh memory statistics.
+    ///
+    /// Returns the held stats buffer on the stats queue and signals an
+    /// interrupt; the guest refills it and notifies again.
+    ///
+    /// Returns false if the guest hasn't posted a stats buffer.
+    pub fn request_stats(&mut self) -> bool {
+        let head = match self.device.take_stats_head() {
+            Some(h) => h,
+            None => return false,
+        };
+        self.queues[super::balloon::STATSQ as usize].put_used(head, 0);
+        self.signal_used(super::balloon::STATSQ as usize);
         true
     }
+
+    /// Get the balloon backend (stats, target size).
+    pub fn balloon(&self) -> &super::balloon::VirtioBalloon {
+        &self.device
+    }
+
+    /// Get the balloon backend mutably.
+    pub fn balloon_mut(&mut self) -> &mut super::balloon::VirtioBalloon {
+        &mut self.device
+    }
+
+    /// Set the target balloon size (in 4KB pages) and signal a config
+    /// change so the guest inflates or deflates towards it.
+    pub fn set_target_pages(&mut self, pages: u32) {
+        self.device.set_target_pages(pages);
+        self.signal_config_change();
+    }
 }
@@ -3,10 +3,13 @@
 //! Implements the virtio-mmio transport layer and provides the `VirtioDevice`
 //! trait for concrete device backends (e.g., virtio-blk).
 
+pub mod balloon;
 pub mod blk;
+pub mod input;
 pub mod mmio;
 pub mod net;
 pub mod queue;
+pub mod vsock;
 
 use queue::Virtqueue;
 
@@ -2,10 +2,12 @@
 //!
 //! Implements a virtio-net device (device ID 1) for inter-VM networking.
 //! TX: strips virtio_net_hdr, forwards Ethernet frame via VSwitch.
-//! RX: inject_rx() writes virtio_net_hdr + frame into guest RX queue.
+//! RX: frames the VSwitch queued in `PORT_RX[port]` are written, behind a
+//! virtio_net_hdr, into buffers the guest posted on the RX queue.
 
-use super::queue::Virtqueue;
+use super::queue::{Virtqueue, VIRTQ_DESC_F_WRITE};
 use super::VirtioDevice;
+use crate::vswitch::{MAX_FRAME_SIZE, PORT_RX};
 
 // ── Feature bits ────────────────────────────────────────────────────
 const VIRTIO_NET_F_MAC: u64 = 1 << 5;
@@ -19,6 +21,16 @@ const VIRTIO_NET_S_LINK_UP: u16 = 1;
 /// Linux always uses this size for VERSION_1 devices.
 const VIRTIO_NET_HDR_SIZE: usize = 12;
 
+/// Offset of `num_buffers` (le16) in virtio_net_hdr_v1
+const VIRTIO_NET_HDR_NUM_BUFFERS: usize = 10;
+
+/// Smallest frame forwarded: an Ethernet header
+const ETH_HDR_SIZE: usize = 14;
+
+/// Queue indices
+const RX_QUEUE: u16 = 0;
+const TX_QUEUE: u16 = 1;
+
 /// Virtio-net device backend.
 pub struct VirtioNet {
     mac: [u8; 6],
@@ -43,58 +55,116 @@ impl VirtioNet {
     }
 
     /// Process TX queue: strip virtio_net_hdr, forward frames via VSwitch.
+    ///
+    /// Without CSUM/GSO negotiated the header's flags and gso_type are
+    /// always zero, and num_buffers is unused on TX, so it is skipped
+    /// whole. Frames longer than `MAX_FRAME_SIZE` are dropped rather than
+    /// truncated. TX buffers are device-readable, so each chain is
+    /// returned with a used length of 0.
     fn process_tx(&mut self, queue: &mut Virtqueue) {
+        let dma = queue.dma();
         while let Some(chain) = queue.get_avail_desc() {
             // Descriptor chain: [virtio_net_hdr] [frame data...]
             // Could be 1 descriptor (hdr + frame) or 2+ (hdr, then frame)
             let mut total_len = 0usize;
-            let mut frame_buf = [0u8; crate::vswitch::MAX_FRAME_SIZE];
+            let mut frame_buf = [0u8; MAX_FRAME_SIZE];
             let mut frame_len = 0usize;
+            let mut ok = true;
 
-            for i in 0..chain.count {
-                let desc = &chain.descs[i];
-                let buf_addr = desc.addr as *const u8;
+            for desc in &chain.descs[..chain.count] {
                 let buf_len = desc.len as usize;
-
-                if total_len < VIRTIO_NET_HDR_SIZE {
-                    // Still in the header region — skip header bytes
-                    let skip = core::cmp::min(VIRTIO_NET_HDR_SIZE - total_len, buf_len);
-                    let data_start = skip;
-                    let data_len = buf_len - skip;
-                    if data_len > 0 && frame_len + data_len <= frame_buf.len() {
-                        unsafe {
-                            core::ptr::copy_nonoverlapping(
-                                buf_addr.add(data_start),
-                                frame_buf.as_mut_ptr().add(frame_len),
-                                data_len,
-                            );
-                        }
-                        frame_len += data_len;
-                    }
-                } else {
-                    // Pure frame data
-                    if frame_len + buf_len <= frame_buf.len() {
-                        unsafe {
-                            core::ptr::copy_nonoverlapping(
-                                buf_addr,
-                                frame_buf.as_mut_ptr().add(frame_len),
-                                buf_len,
-                            );
-                        }
-                        frame_len += buf_len;
-                    }
-                }
+                // Skip whatever part of the header this buffer holds
+                let skip = VIRTIO_NET_HDR_SIZE.saturating_sub(total_len).min(buf_len);
+                let data_len = buf_len - skip;
                 total_len += buf_len;
+                if data_len == 0 {
+                    continue;
+                }
+                if frame_len + data_len > frame_buf.len() {
+                    ok = false;
+                    break;
+                }
+                let dst = &mut frame_buf[frame_len..frame_len + data_len];
+                ok &= dma.read(desc.addr + skip as u64, dst);
+                frame_len += data_len;
             }
 
-            // Forward the Ethernet frame through the VSwitch
-            if frame_len >= 14 {
+            // Forward the Ethernet frame through the VSwitch (dropped if
+            // oversized or any buffer was outside guest memory)
+            if ok && frame_len >= ETH_HDR_SIZE {
                 crate::vswitch::vswitch_forward(self.port_id, &frame_buf[..frame_len]);
             }
 
             queue.put_used(chain.head, 0);
         }
     }
+
+    /// Write `frame` behind a virtio_net_hdr_v1 into the next buffer the
+    /// guest posted on the RX `queue`.
+    ///
+    /// The header is zeroed (no checksum or GSO offload is negotiated)
+    /// except num_buffers = 1: without MRG_RXBUF every frame fits one
+    /// chain. The chain goes back on the used ring with the bytes written.
+    ///
+    /// Returns false if no RX buffer is posted, leaving the frame with the
+    /// caller. A chain too small for the frame, or outside guest memory,
+    /// is returned with a used length of 0 and the frame is dropped.
+    pub fn fill_rx(queue: &mut Virtqueue, frame: &[u8]) -> bool {
+        let chain = match queue.get_avail_desc() {
+            Some(c) => c,
+            None => return false, // No available RX buffer
+        };
+
+        // Header + frame, contiguous
+        let mut pkt = [0u8; VIRTIO_NET_HDR_SIZE + MAX_FRAME_SIZE];
+        let pkt_len = VIRTIO_NET_HDR_SIZE + frame.len();
+        let writable = || {
+            chain.descs[..chain.count]
+                .iter()
+                .filter(|d| d.flags & VIRTQ_DESC_F_WRITE != 0)
+        };
+        let total_cap: usize = writable().map(|d| d.len as usize).sum();
+        if frame.len() > MAX_FRAME_SIZE || total_cap < pkt_len {
+            queue.put_used(chain.head, 0);
+            return true;
+        }
+        pkt[VIRTIO_NET_HDR_NUM_BUFFERS..VIRTIO_NET_HDR_SIZE].copy_from_slice(&1u16.to_le_bytes());
+        pkt[VIRTIO_NET_HDR_SIZE..pkt_len].copy_from_slice(frame);
+
+        // Scatter across the chain's device-writable buffers
+        let dma = queue.dma();
+        let mut written = 0usize;
+        for desc in writable() {
+            if written == pkt_len {
+                break;
+            }
+            let to_write = (pkt_len - written).min(desc.len as usize);
+            if !dma.write(desc.addr, &pkt[written..written + to_write]) {
+                // Buffer outside guest memory — return the chain unused
+                queue.put_used(chain.head, 0);
+                return true;
+            }
+            written += to_write;
+        }
+
+        queue.put_used(chain.head, written as u32);
+        true
+    }
+
+    /// Move frames the VSwitch queued for this port into posted RX buffers.
+    ///
+    /// A frame stays queued until a buffer is available for it. Returns
+    /// true if any chain was placed on the used ring.
+    pub fn process_rx(&mut self, queue: &mut Virtqueue) -> bool {
+        let Some(ring) = PORT_RX.get(self.port_id) else {
+            return false;
+        };
+        let mut used = false;
+        while queue.has_avail() && ring.take_if(|frame| Self::fill_rx(queue, frame)) {
+            used = true;
+        }
+        used
+    }
 }
 
 impl VirtioDevice for VirtioNet {
@@ -135,8 +205,11 @@ impl VirtioDevice for VirtioNet {
 
     fn queue_notify(&mut self, queue_idx: u16, queue: &mut Virtqueue) {
         match queue_idx {
-            0 => {} // RX queue — guest replenishing buffers, no action needed
-            1 => self.process_tx(queue),
+            // Guest replenished RX buffers: deliver frames waiting for them
+            RX_QUEUE => {
+                self.process_rx(queue);
+            }
+            TX_QUEUE => self.process_tx(queue),
             _ => {}
         }
     }
@@ -1,8 +1,11 @@
 //! Split virtqueue implementation for virtio devices.
 //!
 //! The guest allocates descriptor table, available ring, and used ring in
-//! guest physical memory. Since we use identity mapping (GPA == HPA), the
-//! hypervisor can directly read/write these structures via volatile pointers.
+//! guest physical memory. All ring accesses go through the queue's
+//! `DmaMapper`, so a ring placed outside the owning VM's memory reads as
+//! empty instead of exposing hypervisor memory.
+
+use crate::devices::dma::DmaMapper;
 
 /// A single virtqueue descriptor.
 #[repr(C)]
@@ -21,22 +24,16 @@ pub struct VirtqDesc {
 /// Descriptor flags
 pub const VIRTQ_DESC_F_NEXT: u16 = 1;
 pub const VIRTQ_DESC_F_WRITE: u16 = 2;
+pub const VIRTQ_DESC_F_INDIRECT: u16 = 4;
 
-/// Available ring header (followed by ring[num] entries).
-#[repr(C)]
-struct VirtqAvail {
-    flags: u16,
-    idx: u16,
-    // ring: [u16; num] follows
-}
+/// Descriptors a `DescChain` holds: a virtio-blk request with the
+/// advertised seg_max data segments plus its header and status
+pub const MAX_CHAIN_DESCS: usize = 130;
 
-/// Used ring header (followed by ring[num] VirtqUsedElem entries).
-#[repr(C)]
-struct VirtqUsed {
-    flags: u16,
-    idx: u16,
-    // ring: [VirtqUsedElem; num] follows
-}
+/// Offset of `idx` in the available/used ring headers (after `flags: u16`)
+const RING_IDX_OFFSET: u64 = 2;
+/// Size of the ring headers; ring entries follow
+const RING_HDR_SIZE: u64 = 4;
 
 /// A single used ring element.
 #[repr(C)]
@@ -50,13 +47,18 @@ struct VirtqUsedElem {
 pub struct DescChain {
     /// Head descriptor index (needed for put_used)
     pub head: u16,
-    /// The descriptors in order
-    pub descs: [VirtqDesc; 4],
+    /// The descriptors in order, with any indirect table unrolled
+    pub descs: [VirtqDesc; MAX_CHAIN_DESCS],
     /// Number of valid descriptors
     pub count: usize,
+    /// The chain was longer than `MAX_CHAIN_DESCS`: the descriptors past
+    /// the buffer were dropped, except the final one, which is kept in the
+    /// last slot so the request can still be failed through it
+    pub truncated: bool,
 }
 
 /// Split virtqueue state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Virtqueue {
     /// Guest physical address of the descriptor table
     desc_addr: u64,
@@ -68,8 +70,15 @@ pub struct Virtqueue {
     pub num: u16,
     /// Last available index we processed
     last_avail_idx: u16,
+    /// Used index at the last interrupt decision (VIRTIO_RING_F_EVENT_IDX)
+    signalled_used: u16,
+    /// Driver negotiated VIRTIO_RING_F_EVENT_IDX: `avail_event` follows
+    /// every buffer consumed
+    event_idx: bool,
     /// Whether the queue has been set up by the driver
     pub ready: bool,
+    /// Validates ring and buffer addresses against the owning VM
+    dma: DmaMapper,
 }
 
 impl Virtqueue {
@@ -80,10 +89,28 @@ impl Virtqueue {
             used_addr: 0,
             num: 0,
             last_avail_idx: 0,
+            signalled_used: 0,
+            event_idx: false,
             ready: false,
+            dma: DmaMapper::new(),
         }
     }
 
+    /// Bind the queue to a DMA domain (kept across `reset()`).
+    pub fn set_dma(&mut self, dma: DmaMapper) {
+        self.dma = dma;
+    }
+
+    /// Record whether the driver negotiated VIRTIO_RING_F_EVENT_IDX.
+    pub fn set_event_idx(&mut self, on: bool) {
+        self.event_idx = on;
+    }
+
+    /// DMA mapper for accessing this queue's buffers.
+    pub fn dma(&self) -> DmaMapper {
+        self.dma
+    }
+
     pub fn set_desc_addr(&mut self, low: u32, high: u32) {
         self.desc_addr = (low as u64) | ((high as u64) << 32);
     }
@@ -108,6 +135,10 @@ impl Virtqueue {
     pub fn used_addr_low(&self) -> u32 {
         self.used_addr as u32
     }
+    /// Next available ring index the device will consume
+    pub fn last_avail_idx(&self) -> u16 {
+        self.last_avail_idx
+    }
 
     /// Reset the queue to initial state
     pub fn reset(&mut self) {
@@ -116,43 +147,50 @@ impl Virtqueue {
         self.used_addr = 0;
         self.num = 0;
         self.last_avail_idx = 0;
+        self.signalled_used = 0;
+        self.event_idx = false;
         self.ready = false;
     }
 
     /// Check if there are new available descriptors to process.
-    fn has_avail(&self) -> bool {
+    pub fn has_avail(&self) -> bool {
         if !self.ready || self.avail_addr == 0 {
             return false;
         }
-        let avail = self.avail_addr as *const VirtqAvail;
-        let avail_idx = unsafe { core::ptr::read_volatile(&(*avail).idx) };
-        avail_idx != self.last_avail_idx
+        let avail_idx = self.dma.read_val::<u16>(self.avail_addr + RING_IDX_OFFSET);
+        avail_idx.is_some_and(|idx| idx != self.last_avail_idx)
     }
 
     /// Get the next available descriptor chain from the guest.
     ///
     /// Returns `None` if no new descriptors are available.
-    /// The returned `DescChain` contains up to 4 chained descriptors.
+    /// The returned `DescChain` contains up to `MAX_CHAIN_DESCS` chained
+    /// descriptors (see `DescChain::truncated` for longer ones). A
+    /// descriptor with VIRTQ_DESC_F_INDIRECT is replaced by the chain in
+    /// the table it points to (VIRTIO_RING_F_INDIRECT_DESC). A chain that
+    /// cannot be walked to its end (bad index, unreadable descriptor,
+    /// nested table, longer than the queue) comes back with no descriptors.
     pub fn get_avail_desc(&mut self) -> Option<DescChain> {
         if !self.has_avail() {
             return None;
         }
 
-        // The ring array starts right after the VirtqAvail header (4 bytes)
-        let ring_base = (self.avail_addr + 4) as *const u16;
-
-        let ring_idx = (self.last_avail_idx % self.num) as usize;
-        let head = unsafe { core::ptr::read_volatile(ring_base.add(ring_idx)) };
+        // The ring array starts right after the avail header (4 bytes)
+        let ring_idx = (self.last_avail_idx % self.num) as u64;
+        let head = self
+            .dma
+            .read_val::<u16>(self.avail_addr + RING_HDR_SIZE + ring_idx * 2)?;
         self.last_avail_idx = self.last_avail_idx.wrapping_add(1);
+        // Every consumer (doorbell, RX injection, eventq) goes through here,
+        // so the driver always kicks for the first buffer not yet taken
+        if self.event_idx {
+            self.set_avail_event();
+        }
 
-        // Walk the descriptor chain.
-        //
-        // Safety: relies on identity mapping (GPA == HPA). The bounds check
-        // `idx >= self.num` prevents reading past the descriptor table, but
-        // does NOT validate that desc.addr fields point to valid guest memory.
-        // A malicious guest could set desc.addr to an arbitrary physical address.
-        // This is acceptable for an educational hypervisor on a single-guest system.
-        let desc_base = self.desc_addr as *const VirtqDesc;
+        // Walk the descriptor chain. The bounds check `idx >= table_len`
+        // keeps the walk inside the descriptor table (or the indirect table
+        // once in it); desc.addr itself is validated when the device
+        // accesses the buffer through `dma()`.
         let mut chain = DescChain {
             head,
             descs: [VirtqDesc {
@@ -160,25 +198,61 @@ impl Virtqueue {
                 len: 0,
                 flags: 0,
                 next: 0,
-            }; 4],
+            }; MAX_CHAIN_DESCS],
             count: 0,
+            truncated: false,
         };
 
+        let desc_size = core::mem::size_of::<VirtqDesc>() as u64;
+        let mut table = self.desc_addr;
+        let mut table_len = self.num as u64;
+        let mut indirect = false;
         let mut idx = head;
-        for _ in 0..4 {
-            if (idx as u16) >= self.num {
+        // A chain is no longer than the queue (virtio 1.1 2.6.5), plus one
+        // read for the descriptor pointing at an indirect table
+        for _ in 0..=self.num {
+            if idx as u64 >= table_len {
                 break;
             }
-            let desc = unsafe { core::ptr::read_volatile(desc_base.add(idx as usize)) };
-            chain.descs[chain.count] = desc;
-            chain.count += 1;
+            let desc = match self
+                .dma
+                .read_val::<VirtqDesc>(table + idx as u64 * desc_size)
+            {
+                Some(d) => d,
+                None => break,
+            };
+
+            if desc.flags & VIRTQ_DESC_F_INDIRECT != 0 {
+                // One table per chain, and it ends the chain: no nested
+                // tables, no NEXT alongside INDIRECT
+                if indirect || desc.flags & VIRTQ_DESC_F_NEXT != 0 {
+                    break;
+                }
+                indirect = true;
+                table = desc.addr;
+                table_len = desc.len as u64 / desc_size;
+                idx = 0;
+                continue;
+            }
+
+            if chain.count < MAX_CHAIN_DESCS {
+                chain.descs[chain.count] = desc;
+                chain.count += 1;
+            } else {
+                chain.truncated = true;
+                chain.descs[MAX_CHAIN_DESCS - 1] = desc;
+            }
 
             if desc.flags & VIRTQ_DESC_F_NEXT == 0 {
-                break;
+                return Some(chain);
             }
             idx = desc.next;
         }
 
+        // The walk stopped before the end of the chain: hand out no
+        // descriptors rather than a prefix whose last one is not the last
+        chain.count = 0;
+        chain.truncated = false;
         Some(chain)
     }
 
@@ -191,24 +265,71 @@ impl Virtqueue {
             return;
         }
 
-        let used = self.used_addr as *mut VirtqUsed;
-        let used_idx = unsafe { core::ptr::read_volatile(&(*used).idx) };
-        let ring_idx = (used_idx % self.num) as usize;
+        let idx_ipa = self.used_addr + RING_IDX_OFFSET;
+        let used_idx = match self.dma.read_val::<u16>(idx_ipa) {
+            Some(i) => i,
+            None => return,
+        };
+        let ring_idx = (used_idx % self.num) as u64;
 
-        // Used ring elements start after the VirtqUsed header (4 bytes)
-        let elem_base = (self.used_addr + 4) as *mut VirtqUsedElem;
-        unsafe {
-            let elem = VirtqUsedElem {
-                id: head as u32,
-                len,
-            };
-            core::ptr::write_volatile(elem_base.add(ring_idx), elem);
+        // Used ring elements start after the used header (4 bytes)
+        let elem_size = core::mem::size_of::<VirtqUsedElem>() as u64;
+        let elem = VirtqUsedElem {
+            id: head as u32,
+            len,
+        };
+        if !self
+            .dma
+            .write_val(self.used_addr + RING_HDR_SIZE + ring_idx * elem_size, elem)
+        {
+            return;
+        }
+
+        // Memory barrier before updating the index
+        core::sync::atomic::fence(core::sync::atomic::Ordering::Release);
+
+        // Advance the used index
+        self.dma.write_val(idx_ipa, used_idx.wrapping_add(1));
+    }
 
-            // Memory barrier before updating the index
-            core::sync::atomic::fence(core::sync::atomic::Ordering::Release);
+    /// Publish `avail_event` (after the used ring) so the driver notifies
+    /// again once it adds a buffer past the ones already consumed.
+    ///
+    /// Only meaningful with VIRTIO_RING_F_EVENT_IDX negotiated.
+    pub fn set_avail_event(&self) {
+        if self.used_addr == 0 || self.num == 0 {
+            return;
+        }
+        let elem_size = core::mem::size_of::<VirtqUsedElem>() as u64;
+        let ipa = self.used_addr + RING_HDR_SIZE + self.num as u64 * elem_size;
+        self.dma.write_val(ipa, self.last_avail_idx);
+    }
 
-            // Advance the used index
-            core::ptr::write_volatile(&mut (*used).idx, used_idx.wrapping_add(1));
+    /// Decide whether used-ring entries added since the last call need an
+    /// interrupt.
+    ///
+    /// Without EVENT_IDX every call asks for one. With it, returns `None`
+    /// if nothing was added to the used ring, otherwise whether the new
+    /// used index crossed the driver's `used_event` (after the avail ring).
+    pub fn used_signal(&mut self, event_idx: bool) -> Option<bool> {
+        if !event_idx {
+            return Some(true);
         }
+        let new = self.dma.read_val::<u16>(self.used_addr + RING_IDX_OFFSET)?;
+        let old = self.signalled_used;
+        if new == old {
+            return None;
+        }
+        self.signalled_used = new;
+
+        // The driver must see the used entries before we read used_event
+        core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+        let event_ipa = self.avail_addr + RING_HDR_SIZE + self.num as u64 * 2;
+        let used_event = match self.dma.read_val::<u16>(event_ipa) {
+            Some(e) => e,
+            None => return Some(true),
+        };
+        // vring_need_event(): did [old, new) step over used_event?
+        Some(new.wrapping_sub(used_event).wrapping_sub(1) < new.wrapping_sub(old))
     }
 }
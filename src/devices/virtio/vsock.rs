@@ -0,0 +1,461 @@
+//! Virtio socket device backend.
+//!
+//! Implements a virtio-vsock device (device ID 19) that gives a guest a
+//! control channel to the hypervisor without networking. The guest sits on
+//! a fixed CID (`guest_cid_for_vm`); the hypervisor answers as the host
+//! (CID 2).
+//!
+//! Messages are datagrams: every OP_RW packet the guest sends to the host
+//! lands in a bounded inbox drained with `vsock_recv()`, and `vsock_send()`
+//! queues an OP_RW packet for the guest. Connection requests to any host
+//! port are accepted and shutdowns answered with RST, which is all a guest
+//! stream socket needs to connect, exchange messages and close.
+//!
+//! Credit: buf_alloc is the inbox's byte capacity and fwd_cnt counts the
+//! bytes the host has taken out with `vsock_recv()`, so a guest honouring
+//! credit stalls while the inbox is full and resumes on the CREDIT_UPDATE
+//! sent once space frees up. A packet that still does not fit (more than
+//! the credit, more inbox slots than left, or longer than
+//! `VSOCK_MAX_PAYLOAD`) resets the connection with RST instead of being
+//! dropped silently.
+//!
+//! rxq (0): host → guest packets, written by `deliver_rx()`.
+//! txq (1): guest → host packets, consumed on notify.
+//! eventq (2): transport events; never raised (no migration).
+
+use super::queue::{Virtqueue, VIRTQ_DESC_F_WRITE};
+use super::VirtioDevice;
+
+// ── Feature bits ────────────────────────────────────────────────────
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+/// Well-known CID of the host
+pub const VMADDR_CID_HOST: u64 = 2;
+
+/// Size of struct virtio_vsock_hdr (packed, little-endian)
+pub const VSOCK_HDR_SIZE: usize = 44;
+/// Largest message payload carried in either direction
+pub const VSOCK_MAX_PAYLOAD: usize = 256;
+/// Packets buffered per direction
+const VSOCK_QUEUE_DEPTH: usize = 4;
+/// Host ports whose guest peer port is remembered
+const VSOCK_MAX_PEERS: usize = 4;
+
+pub const VIRTIO_VSOCK_TYPE_STREAM: u16 = 1;
+
+pub const VIRTIO_VSOCK_OP_REQUEST: u16 = 1;
+pub const VIRTIO_VSOCK_OP_RESPONSE: u16 = 2;
+pub const VIRTIO_VSOCK_OP_RST: u16 = 3;
+pub const VIRTIO_VSOCK_OP_SHUTDOWN: u16 = 4;
+pub const VIRTIO_VSOCK_OP_RW: u16 = 5;
+pub const VIRTIO_VSOCK_OP_CREDIT_UPDATE: u16 = 6;
+pub const VIRTIO_VSOCK_OP_CREDIT_REQUEST: u16 = 7;
+
+/// Receive space advertised to the guest (buf_alloc): the inbox's bytes
+const VSOCK_BUF_ALLOC: u32 = (VSOCK_MAX_PAYLOAD * VSOCK_QUEUE_DEPTH) as u32;
+
+/// Queue indices
+const RX_QUEUE: u16 = 0;
+const TX_QUEUE: u16 = 1;
+
+/// struct virtio_vsock_hdr
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VsockHdr {
+    pub src_cid: u64,
+    pub dst_cid: u64,
+    pub src_port: u32,
+    pub dst_port: u32,
+    pub len: u32,
+    pub type_: u16,
+    pub op: u16,
+    pub flags: u32,
+    pub buf_alloc: u32,
+    pub fwd_cnt: u32,
+}
+
+impl VsockHdr {
+    /// Encode the header as it appears in guest memory.
+    pub fn to_bytes(&self) -> [u8; VSOCK_HDR_SIZE] {
+        let mut b = [0u8; VSOCK_HDR_SIZE];
+        b[0..8].copy_from_slice(&self.src_cid.to_le_bytes());
+        b[8..16].copy_from_slice(&self.dst_cid.to_le_bytes());
+        b[16..20].copy_from_slice(&self.src_port.to_le_bytes());
+        b[20..24].copy_from_slice(&self.dst_port.to_le_bytes());
+        b[24..28].copy_from_slice(&self.len.to_le_bytes());
+        b[28..30].copy_from_slice(&self.type_.to_le_bytes());
+        b[30..32].copy_from_slice(&self.op.to_le_bytes());
+        b[32..36].copy_from_slice(&self.flags.to_le_bytes());
+        b[36..40].copy_from_slice(&self.buf_alloc.to_le_bytes());
+        b[40..44].copy_from_slice(&self.fwd_cnt.to_le_bytes());
+        b
+    }
+
+    /// Decode a header read from guest memory.
+    pub fn from_bytes(b: &[u8; VSOCK_HDR_SIZE]) -> Self {
+        let u16_at = |o: usize| u16::from_le_bytes([b[o], b[o + 1]]);
+        let u32_at = |o: usize| u32::from_le_bytes([b[o], b[o + 1], b[o + 2], b[o + 3]]);
+        let u64_at = |o: usize| u32_at(o) as u64 | (u32_at(o + 4) as u64) << 32;
+        Self {
+            src_cid: u64_at(0),
+            dst_cid: u64_at(8),
+            src_port: u32_at(16),
+            dst_port: u32_at(20),
+            len: u32_at(24),
+            type_: u16_at(28),
+            op: u16_at(30),
+            flags: u32_at(32),
+            buf_alloc: u32_at(36),
+            fwd_cnt: u32_at(40),
+        }
+    }
+}
+
+/// A message on one host port.
+#[derive(Clone, Copy)]
+pub struct VsockMessage {
+    /// Host-side port: the guest's destination port on receive, the
+    /// source port on send
+    pub port: u32,
+    pub len: usize,
+    pub data: [u8; VSOCK_MAX_PAYLOAD],
+}
+
+impl VsockMessage {
+    const fn empty() -> Self {
+        Self {
+            port: 0,
+            len: 0,
+            data: [0; VSOCK_MAX_PAYLOAD],
+        }
+    }
+
+    /// The message payload.
+    pub fn payload(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+/// A packet waiting for a guest RX buffer.
+#[derive(Clone, Copy)]
+struct PendingPacket {
+    hdr: VsockHdr,
+    data: [u8; VSOCK_MAX_PAYLOAD],
+}
+
+/// Bounded FIFO of `T`.
+struct Fifo<T: Copy> {
+    slots: [T; VSOCK_QUEUE_DEPTH],
+    head: usize,
+    count: usize,
+}
+
+impl<T: Copy> Fifo<T> {
+    fn new(fill: T) -> Self {
+        Self {
+            slots: [fill; VSOCK_QUEUE_DEPTH],
+            head: 0,
+            count: 0,
+        }
+    }
+
+    fn push(&mut self, item: T) -> bool {
+        if self.count == VSOCK_QUEUE_DEPTH {
+            return false;
+        }
+        self.slots[(self.head + self.count) % VSOCK_QUEUE_DEPTH] = item;
+        self.count += 1;
+        true
+    }
+
+    fn front(&self) -> Option<&T> {
+        (self.count > 0).then(|| &self.slots[self.head])
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        let item = *self.front()?;
+        self.head = (self.head + 1) % VSOCK_QUEUE_DEPTH;
+        self.count -= 1;
+        Some(item)
+    }
+}
+
+/// Virtio-vsock device backend.
+pub struct VirtioVsock {
+    guest_cid: u64,
+    /// Guest → host messages, oldest first
+    inbox: Fifo<VsockMessage>,
+    /// Host → guest packets not yet written to an RX buffer
+    outbox: Fifo<PendingPacket>,
+    /// (host port, guest port) of the guest's most recent packet per port
+    peers: [(u32, u32); VSOCK_MAX_PEERS],
+    /// Payload bytes waiting in `inbox`
+    inbox_bytes: u32,
+    /// Payload bytes the host has taken out of `inbox` (fwd_cnt credit)
+    fwd_cnt: u32,
+}
+
+impl VirtioVsock {
+    /// Create a vsock device for the given VM.
+    pub fn new(vm_id: usize) -> Self {
+        Self {
+            guest_cid: Self::guest_cid_for_vm(vm_id),
+            inbox: Fifo::new(VsockMessage::empty()),
+            outbox: Fifo::new(PendingPacket {
+                hdr: VsockHdr::default(),
+                data: [0; VSOCK_MAX_PAYLOAD],
+            }),
+            peers: [(0, 0); VSOCK_MAX_PEERS],
+            inbox_bytes: 0,
+            fwd_cnt: 0,
+        }
+    }
+
+    /// Fixed guest CID for a VM: VM 0 -> 3, VM 1 -> 4 (0-2 are reserved).
+    pub fn guest_cid_for_vm(vm_id: usize) -> u64 {
+        3 + vm_id as u64
+    }
+
+    /// The guest's CID.
+    pub fn guest_cid(&self) -> u64 {
+        self.guest_cid
+    }
+
+    /// Queue `data` for the guest as an OP_RW packet from host port `port`.
+    ///
+    /// It goes to the guest port that last talked to `port`, or to `port`
+    /// itself if none did. Returns false if `data` exceeds
+    /// `VSOCK_MAX_PAYLOAD` or the outbox is full.
+    pub fn vsock_send(&mut self, port: u32, data: &[u8]) -> bool {
+        if data.len() > VSOCK_MAX_PAYLOAD {
+            return false;
+        }
+        let dst_port = self.peer_port(port).unwrap_or(port);
+        self.queue_packet(VIRTIO_VSOCK_OP_RW, port, dst_port, data)
+    }
+
+    /// Oldest message the guest sent to the host, if any.
+    ///
+    /// Its bytes are credited back to the guest (fwd_cnt). If the guest
+    /// was short of credit for a full message, a CREDIT_UPDATE tells it it
+    /// may send again.
+    pub fn vsock_recv(&mut self) -> Option<VsockMessage> {
+        let stalled = self.inbox.count == VSOCK_QUEUE_DEPTH
+            || VSOCK_BUF_ALLOC - self.inbox_bytes < VSOCK_MAX_PAYLOAD as u32;
+        let msg = self.inbox.pop()?;
+        self.inbox_bytes -= msg.len as u32;
+        self.fwd_cnt = self.fwd_cnt.wrapping_add(msg.len as u32);
+        if let Some(guest_port) = self.peer_port(msg.port).filter(|_| stalled) {
+            self.queue_packet(VIRTIO_VSOCK_OP_CREDIT_UPDATE, msg.port, guest_port, &[]);
+        }
+        Some(msg)
+    }
+
+    /// Whether packets are waiting for guest RX buffers.
+    pub fn rx_pending(&self) -> bool {
+        self.outbox.count > 0
+    }
+
+    /// Write waiting packets into the buffers posted on the RX `queue`.
+    ///
+    /// Each packet takes one chain: header, then payload, used length
+    /// covering both. A packet stays queued until a buffer is available;
+    /// a chain too small or outside guest memory is returned with a used
+    /// length of 0 and its packet dropped. Returns true if any chain was
+    /// placed on the used ring.
+    pub fn deliver_rx(&mut self, queue: &mut Virtqueue) -> bool {
+        let dma = queue.dma();
+        let mut used = false;
+        while let Some(pkt) = self.outbox.front().copied() {
+            let chain = match queue.get_avail_desc() {
+                Some(c) => c,
+                None => break,
+            };
+            self.outbox.pop();
+            used = true;
+
+            let mut buf = [0u8; VSOCK_HDR_SIZE + VSOCK_MAX_PAYLOAD];
+            let len = VSOCK_HDR_SIZE + pkt.hdr.len as usize;
+            buf[..VSOCK_HDR_SIZE].copy_from_slice(&pkt.hdr.to_bytes());
+            buf[VSOCK_HDR_SIZE..len].copy_from_slice(&pkt.data[..pkt.hdr.len as usize]);
+
+            let mut written = 0usize;
+            for desc in chain.descs[..chain.count]
+                .iter()
+                .filter(|d| d.flags & VIRTQ_DESC_F_WRITE != 0)
+            {
+                if written == len {
+                    break;
+                }
+                let n = (len - written).min(desc.len as usize);
+                if !dma.write(desc.addr, &buf[written..written + n]) {
+                    break;
+                }
+                written += n;
+            }
+            queue.put_used(chain.head, if written == len { len as u32 } else { 0 });
+        }
+        used
+    }
+
+    /// Guest port last seen talking to host `port`.
+    fn peer_port(&self, port: u32) -> Option<u32> {
+        self.peers
+            .iter()
+            .find(|&&(host, guest)| host == port && guest != 0)
+            .map(|&(_, guest)| guest)
+    }
+
+    /// Remember `guest_port` as the peer of host `port`.
+    fn learn_peer(&mut self, port: u32, guest_port: u32) {
+        let slot = self
+            .peers
+            .iter()
+            .position(|&(host, guest)| host == port || guest == 0)
+            .unwrap_or(0);
+        self.peers[slot] = (port, guest_port);
+    }
+
+    /// Queue a host → guest packet.
+    fn queue_packet(&mut self, op: u16, src_port: u32, dst_port: u32, data: &[u8]) -> bool {
+        let mut pkt = PendingPacket {
+            hdr: VsockHdr {
+                src_cid: VMADDR_CID_HOST,
+                dst_cid: self.guest_cid,
+                src_port,
+                dst_port,
+                len: data.len() as u32,
+                type_: VIRTIO_VSOCK_TYPE_STREAM,
+                op,
+                flags: 0,
+                buf_alloc: VSOCK_BUF_ALLOC,
+                fwd_cnt: self.fwd_cnt,
+            },
+            data: [0; VSOCK_MAX_PAYLOAD],
+        };
+        pkt.data[..data.len()].copy_from_slice(data);
+        self.outbox.push(pkt)
+    }
+
+    /// Act on one guest → host packet.
+    fn handle_packet(&mut self, hdr: &VsockHdr, payload: &[u8]) {
+        if hdr.src_cid != self.guest_cid || hdr.dst_cid != VMADDR_CID_HOST {
+            return;
+        }
+        let (host_port, guest_port) = (hdr.dst_port, hdr.src_port);
+        match hdr.op {
+            VIRTIO_VSOCK_OP_REQUEST => {
+                self.learn_peer(host_port, guest_port);
+                self.queue_packet(VIRTIO_VSOCK_OP_RESPONSE, host_port, guest_port, &[]);
+            }
+            VIRTIO_VSOCK_OP_RW => {
+                self.learn_peer(host_port, guest_port);
+                let len = payload.len() as u32;
+                if len > VSOCK_BUF_ALLOC - self.inbox_bytes || self.inbox.count == VSOCK_QUEUE_DEPTH
+                {
+                    // Past its credit or out of inbox slots
+                    self.queue_packet(VIRTIO_VSOCK_OP_RST, host_port, guest_port, &[]);
+                    return;
+                }
+                let mut msg = VsockMessage::empty();
+                msg.port = host_port;
+                msg.len = payload.len();
+                msg.data[..payload.len()].copy_from_slice(payload);
+                self.inbox.push(msg);
+                self.inbox_bytes += len;
+            }
+            VIRTIO_VSOCK_OP_SHUTDOWN => {
+                self.queue_packet(VIRTIO_VSOCK_OP_RST, host_port, guest_port, &[]);
+            }
+            VIRTIO_VSOCK_OP_CREDIT_REQUEST => {
+                self.queue_packet(VIRTIO_VSOCK_OP_CREDIT_UPDATE, host_port, guest_port, &[]);
+            }
+            // RESPONSE, RST, CREDIT_UPDATE: nothing to track
+            _ => {}
+        }
+    }
+
+    /// Process TX queue: parse each packet and return its chain (used
+    /// length 0). Chains outside guest memory or shorter than their header
+    /// claims are dropped; a payload beyond `VSOCK_MAX_PAYLOAD` resets its
+    /// connection (RST).
+    fn process_tx(&mut self, queue: &mut Virtqueue) {
+        let dma = queue.dma();
+        while let Some(chain) = queue.get_avail_desc() {
+            let mut buf = [0u8; VSOCK_HDR_SIZE + VSOCK_MAX_PAYLOAD];
+            // Bytes in the chain; only the first `buf.len()` are read
+            let mut len = 0usize;
+            let mut ok = true;
+            for desc in &chain.descs[..chain.count] {
+                let start = len.min(buf.len());
+                let n = (desc.len as usize).min(buf.len() - start);
+                if !dma.read(desc.addr, &mut buf[start..start + n]) {
+                    ok = false;
+                    break;
+                }
+                len += desc.len as usize;
+            }
+            queue.put_used(chain.head, 0);
+            if !ok || len < VSOCK_HDR_SIZE {
+                continue;
+            }
+
+            let mut hdr_bytes = [0u8; VSOCK_HDR_SIZE];
+            hdr_bytes.copy_from_slice(&buf[..VSOCK_HDR_SIZE]);
+            let hdr = VsockHdr::from_bytes(&hdr_bytes);
+            let payload_len = hdr.len as usize;
+            if VSOCK_HDR_SIZE + payload_len > len {
+                continue;
+            }
+            if payload_len > VSOCK_MAX_PAYLOAD {
+                if hdr.src_cid == self.guest_cid && hdr.dst_cid == VMADDR_CID_HOST {
+                    self.queue_packet(VIRTIO_VSOCK_OP_RST, hdr.dst_port, hdr.src_port, &[]);
+                }
+                continue;
+            }
+            self.handle_packet(&hdr, &buf[VSOCK_HDR_SIZE..VSOCK_HDR_SIZE + payload_len]);
+        }
+    }
+}
+
+impl VirtioDevice for VirtioVsock {
+    fn device_id(&self) -> u32 {
+        19
+    } // VIRTIO_ID_VSOCK
+
+    fn device_features(&self) -> u64 {
+        VIRTIO_F_VERSION_1
+    }
+
+    fn config_read(&self, offset: u64, size: u8) -> u64 {
+        // Config space: guest_cid (le64) at 0x00
+        match (offset, size) {
+            (0, 8) => self.guest_cid,
+            (0, 4) => self.guest_cid & 0xFFFF_FFFF,
+            (4, 4) => self.guest_cid >> 32,
+            _ => 0,
+        }
+    }
+
+    fn config_write(&mut self, _offset: u64, _value: u64, _size: u8) {
+        // Config space is read-only for vsock
+    }
+
+    fn queue_notify(&mut self, queue_idx: u16, queue: &mut Virtqueue) {
+        match queue_idx {
+            // Guest posted RX buffers: deliver packets waiting for them
+            RX_QUEUE => {
+                self.deliver_rx(queue);
+            }
+            TX_QUEUE => self.process_tx(queue),
+            _ => {} // eventq: buffers are held, no events are raised
+        }
+    }
+
+    fn num_queues(&self) -> u16 {
+        3
+    } // RX=0, TX=1, EVENT=2
+
+    fn max_queue_size(&self) -> u16 {
+        256
+    }
+}
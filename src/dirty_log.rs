@@ -0,0 +1,194 @@
+//! Stage-2 dirty page tracking (groundwork for live migration).
+//!
+//! `start()` write-protects a range of guest RAM in a VM's Stage-2, tagging
+//! each protected leaf with `PTE_DIRTY_LOG`. The guest's first write to such
+//! a page takes a Stage-2 permission fault: `handle_write_fault()` records
+//! the page in the VM's dirty bitmap, restores write access and lets the
+//! store re-execute. `take()` hands the bitmap to the caller, clears it and
+//! write-protects the reported pages again, so each call returns the pages
+//! written since the previous one. Device writes through a `DmaMapper`
+//! land on protected pages too; `record_dma_write()` logs those the same
+//! way.
+//!
+//! The bitmap always has one bit per 4KB page. With a 64KB Stage-2 one
+//! leaf protects sixteen of them, so a write fault marks all sixteen.
+
+use crate::arch::aarch64::defs::*;
+use crate::ffa::stage2_walker::Stage2Walker;
+use crate::global::MAX_VMS;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Most pages one VM can track: all of the largest guest's RAM
+pub const DIRTY_LOG_MAX_PAGES: usize = (crate::platform::LINUX_MEM_SIZE / PAGE_SIZE_4KB) as usize;
+const DIRTY_LOG_WORDS: usize = DIRTY_LOG_MAX_PAGES / 64;
+
+/// ESR_EL2.ISS DFSC field and its Permission fault encoding (levels 0-3
+/// in bits [1:0])
+const ESR_DFSC_MASK: u64 = 0x3C;
+const DFSC_PERMISSION_FAULT: u64 = 0x0C;
+/// ESR_EL2.WnR (data aborts)
+const ESR_WNR: u64 = 1 << 6;
+
+/// One VM's tracked range and dirty bitmap (bit N = page `base + N * 4KB`)
+struct DirtyLog {
+    base: AtomicU64,
+    /// Pages tracked from `base` (0 = tracking off)
+    pages: AtomicU64,
+    bits: [AtomicU64; DIRTY_LOG_WORDS],
+}
+
+impl DirtyLog {
+    const fn new() -> Self {
+        Self {
+            base: AtomicU64::new(0),
+            pages: AtomicU64::new(0),
+            bits: [const { AtomicU64::new(0) }; DIRTY_LOG_WORDS],
+        }
+    }
+
+    /// Bitmap index of the page containing `ipa`, if it is tracked.
+    fn index(&self, ipa: u64) -> Option<usize> {
+        let page = ipa.checked_sub(self.base.load(Ordering::Acquire))? / PAGE_SIZE_4KB;
+        (page < self.pages.load(Ordering::Acquire)).then_some(page as usize)
+    }
+
+    /// Mark every tracked 4KB page of the `walker` leaf page holding `ipa`.
+    fn mark(&self, walker: &Stage2Walker, ipa: u64) {
+        let leaf = ipa & !(walker.page_size() - 1);
+        for page in (leaf..leaf + walker.page_size()).step_by(PAGE_SIZE_4KB as usize) {
+            if let Some(n) = self.index(page) {
+                self.bits[n / 64].fetch_or(1 << (n % 64), Ordering::AcqRel);
+            }
+        }
+    }
+}
+
+static DIRTY_LOGS: [DirtyLog; MAX_VMS] = [const { DirtyLog::new() }; MAX_VMS];
+
+/// Walker over `vm_id`'s Stage-2, looked up in `PER_VM_VTTBR`.
+fn walker(vm_id: usize) -> Stage2Walker {
+    Stage2Walker::for_vm(vm_id)
+}
+
+/// Start tracking writes to `[ipa, ipa + len)` in `vm_id`'s Stage-2,
+/// replacing any range tracked before. Holes in the range are skipped.
+/// The range must be aligned to the VM's Stage-2 page size.
+pub fn start(vm_id: usize, ipa: u64, len: u64) -> Result<(), &'static str> {
+    if vm_id >= MAX_VMS {
+        return Err("Invalid VM ID");
+    }
+    let walker = walker(vm_id);
+    if !walker.has_stage2() {
+        return Err("Stage-2 not initialized");
+    }
+    let page_mask = walker.page_size() - 1;
+    if ipa & page_mask != 0 || len & page_mask != 0 || len == 0 {
+        return Err("Region not page-aligned");
+    }
+    if len / PAGE_SIZE_4KB > DIRTY_LOG_MAX_PAGES as u64 {
+        return Err("Region too large");
+    }
+    stop(vm_id);
+    let log = &DIRTY_LOGS[vm_id];
+    for word in log.bits.iter() {
+        word.store(0, Ordering::Relaxed);
+    }
+    log.base.store(ipa, Ordering::Release);
+    log.pages.store(len / PAGE_SIZE_4KB, Ordering::Release);
+
+    let mut page = ipa;
+    while page < ipa + len {
+        if walker.translate(page).is_some() {
+            walker.dirty_log_protect(page)?;
+        }
+        page += walker.page_size();
+    }
+    Ok(())
+}
+
+/// Stop tracking for `vm_id`, giving write access back to every page still
+/// protected. The bitmap is kept until the next `start()`.
+pub fn stop(vm_id: usize) {
+    let Some(log) = DIRTY_LOGS.get(vm_id) else {
+        return;
+    };
+    let walker = walker(vm_id);
+    let base = log.base.load(Ordering::Acquire);
+    let len = log.pages.load(Ordering::Acquire) * PAGE_SIZE_4KB;
+    for page in (base..base + len).step_by(walker.page_size() as usize) {
+        walker.dirty_log_unprotect(page);
+    }
+    log.pages.store(0, Ordering::Release);
+}
+
+/// Move `vm_id`'s dirty bitmap into `bitmap` (bit N of word W = page
+/// `base + (W * 64 + N) * 4KB`) and write-protect those pages again.
+///
+/// Pages beyond `bitmap` stay recorded for a later call. Returns the
+/// number of dirty pages handed over.
+pub fn take(vm_id: usize, bitmap: &mut [u64]) -> usize {
+    let Some(log) = DIRTY_LOGS.get(vm_id) else {
+        return 0;
+    };
+    let walker = walker(vm_id);
+    let base = log.base.load(Ordering::Acquire);
+    let words = (log.pages.load(Ordering::Acquire) as usize).div_ceil(64);
+    let mut count = 0;
+    for (w, out) in bitmap.iter_mut().enumerate().take(words) {
+        // Clear before re-protecting: a write in between faults again and
+        // is reported next time
+        let dirty = log.bits[w].swap(0, Ordering::AcqRel);
+        let mut rest = dirty;
+        while rest != 0 {
+            let n = rest.trailing_zeros() as u64;
+            let _ = walker.dirty_log_protect(base + (w as u64 * 64 + n) * PAGE_SIZE_4KB);
+            rest &= rest - 1;
+        }
+        *out = dirty;
+        count += dirty.count_ones() as usize;
+    }
+    count
+}
+
+/// Handle a Stage-2 data abort at `ipa` for the current VM if it is a
+/// write to a page protected by dirty tracking: record the page and give
+/// write access back. The faulting store must then be re-executed (PC not
+/// advanced).
+///
+/// Returns false for anything else (translation faults on emulated MMIO,
+/// reads, untracked pages), which the caller handles as before.
+pub fn handle_write_fault(ipa: u64, esr: u64) -> bool {
+    let vm_id = crate::global::current_vm_id();
+    let log = &DIRTY_LOGS[vm_id];
+    if esr & ESR_DFSC_MASK != DFSC_PERMISSION_FAULT || esr & ESR_WNR == 0 {
+        return false;
+    }
+    if log.index(ipa).is_none() {
+        return false;
+    }
+    let walker = walker(vm_id);
+    // Another vCPU may have taken the same fault and unprotected the page
+    // first; the store just needs retrying then
+    let rw = (S2AP_RW >> S2AP_SHIFT) as u8;
+    if !walker.dirty_log_unprotect(ipa) && walker.read_s2ap(ipa) != Some(rw) {
+        return false;
+    }
+    log.mark(&walker, ipa);
+    true
+}
+
+/// Record a device write to the page at `ipa` in `vm_id`'s RAM, as
+/// `handle_write_fault()` does for a guest store: mark the page dirty and
+/// give write access back. Returns false if the page is not tracked.
+pub fn record_dma_write(vm_id: usize, ipa: u64) -> bool {
+    let Some(log) = DIRTY_LOGS.get(vm_id) else {
+        return false;
+    };
+    if log.index(ipa).is_none() {
+        return false;
+    }
+    let walker = walker(vm_id);
+    walker.dirty_log_unprotect(ipa);
+    log.mark(&walker, ipa);
+    true
+}
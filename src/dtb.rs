@@ -7,10 +7,33 @@
 //! The `fdt` crate does zero-copy parsing — no heap allocation needed.
 //! This module must be initialized before heap init since DTB may
 //! describe the memory layout.
+//!
+//! `set_chosen_prop`, `add_root_node` and `add_mem_reserve` also edit a
+//! guest DTB in place (e.g. the initrd range in `/chosen`, a framebuffer
+//! node), which the read-only `fdt` crate cannot do.
 
 use core::cell::UnsafeCell;
 use core::sync::atomic::{AtomicBool, Ordering};
 
+/// GICv3 redistributor frame stride (RD_base + SGI_base, 2 x 64KB) used
+/// when the DTB has no `redistributor-stride`.
+pub const GICR_DEFAULT_STRIDE: u64 = 0x20000;
+
+/// Redistributor regions (`#redistributor-regions`) tracked from the DTB.
+pub const MAX_GICR_REGIONS: usize = 4;
+
+/// Compatible strings of GICv2-class interrupt controllers.
+const GICV2_COMPATIBLE: &[&str] = &["arm,cortex-a15-gic", "arm,gic-400", "arm,cortex-a9-gic"];
+
+/// GIC architecture described by the host DTB.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GicVersion {
+    /// Memory-mapped CPU interface (GICC) and virtual interface (GICH)
+    V2,
+    /// System-register CPU interface (ICC_*/ICH_*) with redistributors
+    V3,
+}
+
 /// Runtime-discovered platform information from host DTB.
 ///
 /// Fields are initialized with QEMU virt defaults so everything works
@@ -18,12 +41,24 @@ use core::sync::atomic::{AtomicBool, Ordering};
 pub struct PlatformInfo {
     /// UART (PL011) base address
     pub uart_base: u64,
+    /// GIC architecture of the interrupt controller node
+    pub gic_version: GicVersion,
     /// GIC distributor base address
     pub gicd_base: u64,
+    /// GICv2 CPU interface (GICC) base address
+    pub gicc_base: u64,
+    /// GICv2 virtual interface control (GICH) base address
+    pub gich_base: u64,
     /// GIC redistributor base address (first frame)
     pub gicr_base: u64,
     /// GIC redistributor region size (total)
     pub gicr_size: u64,
+    /// Distance between consecutive redistributor frames
+    pub gicr_stride: u64,
+    /// Redistributor regions as (base, size); size 0 = unknown (unbounded)
+    pub gicr_regions: [(u64, u64); MAX_GICR_REGIONS],
+    /// Number of valid entries in `gicr_regions` (0 = only `gicr_base`)
+    pub gicr_region_count: usize,
     /// Number of CPUs discovered from /cpus node
     pub num_cpus: usize,
     /// RAM base address
@@ -44,9 +79,15 @@ unsafe impl Sync for PlatformInfoCell {}
 static PLATFORM_INFO: PlatformInfoCell = PlatformInfoCell {
     inner: UnsafeCell::new(PlatformInfo {
         uart_base: 0x0900_0000,
+        gic_version: GicVersion::V3,
         gicd_base: 0x0800_0000,
+        gicc_base: 0x0801_0000,
+        gich_base: 0x0803_0000,
         gicr_base: 0x080A_0000,
         gicr_size: 0,
+        gicr_stride: GICR_DEFAULT_STRIDE,
+        gicr_regions: [(0, 0); MAX_GICR_REGIONS],
+        gicr_region_count: 0,
         num_cpus: 4,
         ram_base: 0x4000_0000,
         ram_size: 0x4000_0000, // 1GB default
@@ -77,10 +118,26 @@ pub fn platform_info() -> &'static PlatformInfo {
     unsafe { &*PLATFORM_INFO.inner.get() }
 }
 
-/// Compute GICR RD base for a given CPU ID.
-/// GICv3 redistributor frames are 0x20000 (128KB) apart.
+impl PlatformInfo {
+    /// RD base of the `cpu_id`th redistributor frame: frames are
+    /// `gicr_stride` apart and fill each region in turn.
+    pub fn gicr_rd_base(&self, cpu_id: usize) -> u64 {
+        let mut frame = cpu_id as u64;
+        for &(base, size) in &self.gicr_regions[..self.gicr_region_count] {
+            let frames = size / self.gicr_stride;
+            if size == 0 || frame < frames {
+                return base + frame * self.gicr_stride;
+            }
+            frame -= frames;
+        }
+        self.gicr_base + (cpu_id as u64) * self.gicr_stride
+    }
+}
+
+/// Compute GICR RD base for a given CPU ID from the DTB's redistributor
+/// regions and stride (0x20000 apart by default).
 pub fn gicr_rd_base(cpu_id: usize) -> u64 {
-    platform_info().gicr_base + (cpu_id as u64) * 0x20000
+    platform_info().gicr_rd_base(cpu_id)
 }
 
 /// Compute GICR SGI frame base for a given CPU ID.
@@ -110,12 +167,29 @@ fn parse_host_dtb(dtb_addr: usize) -> Option<PlatformInfo> {
     }
 
     let fdt = unsafe { fdt::Fdt::from_ptr(dtb_addr as *const u8).ok()? };
+    Some(parse_fdt(&fdt))
+}
 
+/// Parse platform information from an in-memory DTB blob.
+///
+/// The blob must contain `/memory` and `/cpus` nodes.
+pub fn parse_dtb_blob(blob: &[u8]) -> Option<PlatformInfo> {
+    let fdt = fdt::Fdt::new(blob).ok()?;
+    Some(parse_fdt(&fdt))
+}
+
+fn parse_fdt(fdt: &fdt::Fdt) -> PlatformInfo {
     let mut info = PlatformInfo {
         uart_base: 0x0900_0000,
+        gic_version: GicVersion::V3,
         gicd_base: 0x0800_0000,
+        gicc_base: 0x0801_0000,
+        gich_base: 0x0803_0000,
         gicr_base: 0x080A_0000,
         gicr_size: 0,
+        gicr_stride: GICR_DEFAULT_STRIDE,
+        gicr_regions: [(0, 0); MAX_GICR_REGIONS],
+        gicr_region_count: 0,
         num_cpus: 4,
         ram_base: 0x4000_0000,
         ram_size: 0,
@@ -139,18 +213,48 @@ fn parse_host_dtb(dtb_addr: usize) -> Option<PlatformInfo> {
         }
     }
 
-    // 3. Parse GIC (arm,gic-v3)
-    // reg = <GICD_base GICD_size GICR_base GICR_size>
+    // 3. Parse GIC (arm,gic-v3, else a GICv2 compatible)
+    // reg = <GICD_base GICD_size GICR0_base GICR0_size [GICRn ...] [GICC ...]>
+    // with #redistributor-regions GICR entries (default 1)
     if let Some(gic_node) = fdt.find_compatible(&["arm,gic-v3"]) {
+        let region_count = gic_node
+            .property("#redistributor-regions")
+            .and_then(|p| p.as_usize())
+            .unwrap_or(1)
+            .clamp(1, MAX_GICR_REGIONS);
+        if let Some(stride) = gic_node
+            .property("redistributor-stride")
+            .and_then(|p| p.as_usize())
+            .filter(|&s| s != 0)
+        {
+            info.gicr_stride = stride as u64;
+        }
         if let Some(mut regs) = gic_node.reg() {
             if let Some(gicd_reg) = regs.next() {
                 info.gicd_base = gicd_reg.starting_address as u64;
             }
-            if let Some(gicr_reg) = regs.next() {
-                info.gicr_base = gicr_reg.starting_address as u64;
-                if let Some(size) = gicr_reg.size {
-                    info.gicr_size = size as u64;
-                }
+            for gicr_reg in regs.take(region_count) {
+                let size = gicr_reg.size.unwrap_or(0) as u64;
+                info.gicr_regions[info.gicr_region_count] =
+                    (gicr_reg.starting_address as u64, size);
+                info.gicr_region_count += 1;
+            }
+            if info.gicr_region_count > 0 {
+                (info.gicr_base, info.gicr_size) = info.gicr_regions[0];
+            }
+        }
+    } else if let Some(gic_node) = fdt.find_compatible(GICV2_COMPATIBLE) {
+        // reg = <GICD GICC [GICH GICV]>
+        info.gic_version = GicVersion::V2;
+        if let Some(mut regs) = gic_node.reg() {
+            if let Some(gicd_reg) = regs.next() {
+                info.gicd_base = gicd_reg.starting_address as u64;
+            }
+            if let Some(gicc_reg) = regs.next() {
+                info.gicc_base = gicc_reg.starting_address as u64;
+            }
+            if let Some(gich_reg) = regs.next() {
+                info.gich_base = gich_reg.starting_address as u64;
             }
         }
     }
@@ -161,5 +265,364 @@ fn parse_host_dtb(dtb_addr: usize) -> Option<PlatformInfo> {
         info.num_cpus = cpu_count;
     }
 
-    Some(info)
+    info
+}
+
+// ── Guest DTB patching ──────────────────────────────────────────────
+
+const FDT_MAGIC: u32 = 0xD00D_FEED;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+/// Header field offsets
+const FDT_TOTALSIZE: usize = 4;
+const FDT_OFF_STRUCT: usize = 8;
+const FDT_OFF_STRINGS: usize = 12;
+const FDT_OFF_RSVMAP: usize = 16;
+const FDT_SIZE_STRINGS: usize = 32;
+const FDT_SIZE_STRUCT: usize = 36;
+
+/// Longest property value `set_chosen_prop` inserts
+const MAX_PATCH_VALUE: usize = 16;
+/// Longest property name `set_chosen_prop` inserts (without the NUL)
+const MAX_PATCH_NAME: usize = 31;
+
+fn be32(blob: &[u8], off: usize) -> Option<u32> {
+    let bytes = blob.get(off..off + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn put_be32(blob: &mut [u8], off: usize, value: u32) {
+    blob[off..off + 4].copy_from_slice(&value.to_be_bytes());
+}
+
+const fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+/// Block offsets and sizes from a DTB header.
+struct Layout {
+    total: usize,
+    off_rsvmap: usize,
+    off_struct: usize,
+    size_struct: usize,
+    off_strings: usize,
+    size_strings: usize,
+}
+
+impl Layout {
+    /// Read the header of the DTB at the start of `blob`. The blocks must
+    /// be in dtc order (reservation map, structure, strings) and inside
+    /// `blob`.
+    fn parse(blob: &[u8]) -> Result<Self, &'static str> {
+        let header = |off| {
+            be32(blob, off)
+                .map(|v| v as usize)
+                .ok_or("DTB header truncated")
+        };
+        if header(0)? != FDT_MAGIC as usize {
+            return Err("bad DTB magic");
+        }
+        let layout = Self {
+            total: header(FDT_TOTALSIZE)?,
+            off_rsvmap: header(FDT_OFF_RSVMAP)?,
+            off_struct: header(FDT_OFF_STRUCT)?,
+            size_struct: header(FDT_SIZE_STRUCT)?,
+            off_strings: header(FDT_OFF_STRINGS)?,
+            size_strings: header(FDT_SIZE_STRINGS)?,
+        };
+        if layout.total > blob.len()
+            || layout.off_rsvmap > layout.off_struct
+            || layout.off_struct + layout.size_struct > layout.off_strings
+            || layout.off_strings + layout.size_strings > layout.total
+        {
+            return Err("unsupported DTB layout");
+        }
+        Ok(layout)
+    }
+}
+
+/// Where `/chosen` and a property in it sit in the structure block.
+#[derive(Default)]
+struct ChosenScan {
+    /// END_NODE token of the root node
+    root_end: Option<usize>,
+    /// END_NODE token of `/chosen`, if the node exists
+    chosen_end: Option<usize>,
+    /// (PROP token offset, value length) of the property being set
+    prop: Option<(usize, usize)>,
+}
+
+/// Walk the structure block looking for `/chosen` and its `name` property.
+fn scan_chosen(
+    blob: &[u8],
+    off_struct: usize,
+    off_strings: usize,
+    name: &str,
+) -> Option<ChosenScan> {
+    let mut scan = ChosenScan::default();
+    let mut pos = off_struct;
+    let mut depth = 0usize;
+    let mut in_chosen = false;
+    loop {
+        let token = be32(blob, pos)?;
+        match token {
+            FDT_BEGIN_NODE => {
+                let start = pos + 4;
+                let len = blob.get(start..)?.iter().position(|&b| b == 0)?;
+                if depth == 1 {
+                    in_chosen = &blob[start..start + len] == b"chosen";
+                }
+                depth += 1;
+                pos = start + align4(len + 1);
+            }
+            FDT_END_NODE => {
+                if depth == 2 && in_chosen {
+                    scan.chosen_end = Some(pos);
+                    in_chosen = false;
+                }
+                if depth == 1 {
+                    scan.root_end = Some(pos);
+                }
+                depth = depth.checked_sub(1)?;
+                pos += 4;
+            }
+            FDT_PROP => {
+                let len = be32(blob, pos + 4)? as usize;
+                let nameoff = off_strings + be32(blob, pos + 8)? as usize;
+                let is_name = blob
+                    .get(nameoff..nameoff + name.len() + 1)
+                    .is_some_and(|n| &n[..name.len()] == name.as_bytes() && n[name.len()] == 0);
+                if depth == 2 && in_chosen && is_name {
+                    scan.prop = Some((pos, len));
+                }
+                pos += 12 + align4(len);
+            }
+            FDT_NOP => pos += 4,
+            FDT_END => return Some(scan),
+            _ => return None,
+        }
+    }
+}
+
+/// Replace the `remove` bytes at `at` in `blob[..total]` with `bytes`,
+/// moving everything after them.
+fn splice(blob: &mut [u8], total: usize, at: usize, remove: usize, bytes: &[u8]) {
+    blob.copy_within(at + remove..total, at + bytes.len());
+    blob[at..at + bytes.len()].copy_from_slice(bytes);
+}
+
+/// Set property `name` of `/chosen` to `value` in the DTB at the start of
+/// `blob`, creating the property (and `/chosen`) if needed.
+///
+/// `blob` is the DTB followed by free space it may grow into. A value of
+/// a different size replaces the old property in place (no FDT_NOP
+/// padding, which the `fdt` crate cannot skip between properties); the
+/// rest of the structure block and the strings block move and the header
+/// is updated. The memory reservation map must precede the structure
+/// block (as dtc lays it out).
+pub fn set_chosen_prop(blob: &mut [u8], name: &str, value: &[u8]) -> Result<(), &'static str> {
+    let Layout {
+        total,
+        off_struct,
+        size_struct,
+        off_strings,
+        size_strings,
+        ..
+    } = Layout::parse(blob)?;
+    if value.len() > MAX_PATCH_VALUE || name.len() > MAX_PATCH_NAME {
+        return Err("unsupported DTB layout");
+    }
+    let scan =
+        scan_chosen(blob, off_struct, off_strings, name).ok_or("malformed DTB structure block")?;
+
+    // Same size: overwrite the value in place
+    if let Some((pos, len)) = scan.prop.filter(|&(_, len)| len == value.len()) {
+        blob[pos + 12..pos + 12 + len].copy_from_slice(value);
+        return Ok(());
+    }
+
+    // Property name: reuse an existing string or append one
+    let strings = &blob[off_strings..off_strings + size_strings];
+    let existing = strings
+        .windows(name.len() + 1)
+        .position(|w| &w[..name.len()] == name.as_bytes() && w[name.len()] == 0);
+    let new_string = if existing.is_some() {
+        0
+    } else {
+        name.len() + 1
+    };
+    let nameoff = existing.unwrap_or(size_strings);
+
+    // FDT_PROP, len, nameoff, value (padded): replacing the old property,
+    // appended to /chosen, or wrapped in a new BEGIN_NODE "chosen" ...
+    // END_NODE at the end of the root node
+    let mut prop = [0u8; 12 + 12 + MAX_PATCH_VALUE + 4];
+    let mut n = 0;
+    let (at, remove) = match (scan.prop, scan.chosen_end) {
+        (Some((pos, len)), _) => (pos, 12 + align4(len)),
+        (None, Some(end)) => (end, 0),
+        (None, None) => {
+            put_be32(&mut prop, 0, FDT_BEGIN_NODE);
+            prop[4..10].copy_from_slice(b"chosen");
+            n = 12;
+            (scan.root_end.ok_or("DTB has no root node")?, 0)
+        }
+    };
+    put_be32(&mut prop, n, FDT_PROP);
+    put_be32(&mut prop, n + 4, value.len() as u32);
+    put_be32(&mut prop, n + 8, nameoff as u32);
+    prop[n + 12..n + 12 + value.len()].copy_from_slice(value);
+    n += 12 + align4(value.len());
+    if scan.chosen_end.is_none() {
+        put_be32(&mut prop, n, FDT_END_NODE);
+        n += 4;
+    }
+
+    let new_total = total + n + new_string - remove;
+    if new_total > blob.len() {
+        return Err("no room to grow DTB");
+    }
+    splice(blob, total, at, remove, &prop[..n]);
+    let (total, off_strings) = (total + n - remove, off_strings + n - remove);
+    if new_string != 0 {
+        let mut s = [0u8; MAX_PATCH_NAME + 1];
+        s[..name.len()].copy_from_slice(name.as_bytes());
+        splice(blob, total, off_strings + size_strings, 0, &s[..new_string]);
+    }
+    put_be32(blob, FDT_TOTALSIZE, new_total as u32);
+    put_be32(blob, FDT_SIZE_STRUCT, (size_struct + n - remove) as u32);
+    put_be32(blob, FDT_OFF_STRINGS, off_strings as u32);
+    put_be32(blob, FDT_SIZE_STRINGS, (size_strings + new_string) as u32);
+    Ok(())
+}
+
+/// Most properties `add_root_node` writes into one node
+pub const MAX_NODE_PROPS: usize = 8;
+
+/// Append node `name` with `props` to the root node of the DTB at the
+/// start of `blob`, growing it into the free space that follows.
+///
+/// Property names already in the strings block are reused; the others are
+/// appended to it. Like `set_chosen_prop`, this needs the dtc block order.
+pub fn add_root_node(
+    blob: &mut [u8],
+    name: &str,
+    props: &[(&str, &[u8])],
+) -> Result<(), &'static str> {
+    let Layout {
+        total,
+        off_struct,
+        size_struct,
+        off_strings,
+        size_strings,
+        ..
+    } = Layout::parse(blob)?;
+    if props.len() > MAX_NODE_PROPS || name.contains('\0') {
+        return Err("unsupported DTB node");
+    }
+    // Only the root's END_NODE is needed from the walk
+    let scan =
+        scan_chosen(blob, off_struct, off_strings, "").ok_or("malformed DTB structure block")?;
+    let root_end = scan.root_end.ok_or("DTB has no root node")?;
+
+    // Name offsets: an existing string, or one appended after the block
+    let mut nameoffs = [0usize; MAX_NODE_PROPS];
+    let mut new_strings = 0;
+    for (i, (prop, _)) in props.iter().enumerate() {
+        let strings = &blob[off_strings..off_strings + size_strings];
+        let existing = strings
+            .windows(prop.len() + 1)
+            .position(|w| &w[..prop.len()] == prop.as_bytes() && w[prop.len()] == 0)
+            .filter(|&at| at == 0 || strings[at - 1] == 0);
+        let earlier = props[..i].iter().position(|(p, _)| p == prop);
+        nameoffs[i] = match (existing, earlier) {
+            (Some(at), _) => at,
+            (None, Some(j)) => nameoffs[j],
+            (None, None) => {
+                new_strings += prop.len() + 1;
+                size_strings + new_strings - prop.len() - 1
+            }
+        };
+    }
+    let node = 4
+        + align4(name.len() + 1)
+        + props
+            .iter()
+            .map(|(_, v)| 12 + align4(v.len()))
+            .sum::<usize>()
+        + 4;
+    let new_total = total + node + new_strings;
+    if new_total > blob.len() {
+        return Err("no room to grow DTB");
+    }
+
+    // Open a gap at the root's END_NODE, then write the node into it
+    blob.copy_within(root_end..total, root_end + node);
+    blob[root_end..root_end + node].fill(0);
+    let mut pos = root_end;
+    put_be32(blob, pos, FDT_BEGIN_NODE);
+    blob[pos + 4..pos + 4 + name.len()].copy_from_slice(name.as_bytes());
+    pos += 4 + align4(name.len() + 1);
+    for (i, (_, value)) in props.iter().enumerate() {
+        put_be32(blob, pos, FDT_PROP);
+        put_be32(blob, pos + 4, value.len() as u32);
+        put_be32(blob, pos + 8, nameoffs[i] as u32);
+        blob[pos + 12..pos + 12 + value.len()].copy_from_slice(value);
+        pos += 12 + align4(value.len());
+    }
+    put_be32(blob, pos, FDT_END_NODE);
+
+    // New property names, in first-use order
+    let mut at = total + node;
+    for (i, (prop, _)) in props.iter().enumerate() {
+        if nameoffs[i] >= size_strings && props[..i].iter().all(|(p, _)| p != prop) {
+            blob[at..at + prop.len()].copy_from_slice(prop.as_bytes());
+            blob[at + prop.len()] = 0;
+            at += prop.len() + 1;
+        }
+    }
+    put_be32(blob, FDT_TOTALSIZE, new_total as u32);
+    put_be32(blob, FDT_SIZE_STRUCT, (size_struct + node) as u32);
+    put_be32(blob, FDT_OFF_STRINGS, (off_strings + node) as u32);
+    put_be32(blob, FDT_SIZE_STRINGS, (size_strings + new_strings) as u32);
+    Ok(())
+}
+
+/// Add `[base, base + size)` to the memory reservation map of the DTB at
+/// the start of `blob`, so the guest never allocates it. The structure and
+/// strings blocks move up by one 16-byte entry.
+pub fn add_mem_reserve(blob: &mut [u8], base: u64, size: u64) -> Result<(), &'static str> {
+    let Layout {
+        total,
+        off_rsvmap,
+        off_struct,
+        off_strings,
+        ..
+    } = Layout::parse(blob)?;
+    // The map ends with an all-zero entry
+    let mut at = off_rsvmap;
+    loop {
+        if at + 16 > off_struct {
+            return Err("unterminated memory reservation map");
+        }
+        if blob[at..at + 16].iter().all(|&b| b == 0) {
+            break;
+        }
+        at += 16;
+    }
+    if total + 16 > blob.len() {
+        return Err("no room to grow DTB");
+    }
+    let mut entry = [0u8; 16];
+    entry[..8].copy_from_slice(&base.to_be_bytes());
+    entry[8..].copy_from_slice(&size.to_be_bytes());
+    splice(blob, total, at, 0, &entry);
+    put_be32(blob, FDT_TOTALSIZE, (total + 16) as u32);
+    put_be32(blob, FDT_OFF_STRUCT, (off_struct + 16) as u32);
+    put_be32(blob, FDT_OFF_STRINGS, (off_strings + 16) as u32);
+    Ok(())
 }
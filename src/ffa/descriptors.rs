@@ -7,6 +7,9 @@
 /// Maximum number of address ranges per parsed descriptor.
 pub const MAX_ADDR_RANGES: usize = 16;
 
+/// Maximum number of receivers (memory access descriptors) per share.
+pub const MAX_RECEIVERS: usize = 4;
+
 /// FF-A v1.1 Memory Region Descriptor (DEN0077A Table 5.19).
 ///
 /// Top-level structure placed in the TX buffer for MEM_SHARE/MEM_LEND.
@@ -84,7 +87,11 @@ pub struct FfaMemRegionAddrRange {
 /// Parsed result of a composite memory region descriptor.
 pub struct ParsedMemRegion {
     pub sender_id: u16,
-    pub receiver_id: u16,
+    /// (receiver_id, permissions) per memory access descriptor
+    pub receivers: [(u16, u8); MAX_RECEIVERS],
+    pub receiver_count: usize,
+    /// Memory region attributes (type / cacheability / shareability)
+    pub attributes: u16,
     pub flags: u32,
     pub ranges: [(u64, u32); MAX_ADDR_RANGES],
     pub range_count: usize,
@@ -92,10 +99,12 @@ pub struct ParsedMemRegion {
 }
 
 impl ParsedMemRegion {
-    const fn new() -> Self {
+    pub(crate) const fn new() -> Self {
         Self {
             sender_id: 0,
-            receiver_id: 0,
+            receivers: [(0, 0); MAX_RECEIVERS],
+            receiver_count: 0,
+            attributes: 0,
             flags: 0,
             ranges: [(0, 0); MAX_ADDR_RANGES],
             range_count: 0,
@@ -106,8 +115,11 @@ impl ParsedMemRegion {
 
 /// Parse the TX buffer contents as an FF-A v1.1 composite memory region descriptor.
 ///
-/// Validates structure sizes, bounds, and extracts address ranges.
-/// Does NOT support fragmented descriptors (requires total_length == fragment_length).
+/// Validates structure sizes, bounds, and extracts address ranges and up to
+/// `MAX_RECEIVERS` receivers. All receivers must describe the same composite
+/// region, with at most `MAX_ADDR_RANGES` address ranges (FFA_NO_MEMORY
+/// otherwise). Does NOT support fragmented descriptors (requires
+/// total_length == fragment_length).
 ///
 /// # Safety
 ///
@@ -128,27 +140,36 @@ pub unsafe fn parse_mem_region(
     // Read FfaMemRegion header (use read_unaligned for packed struct safety)
     let sender_id = core::ptr::read_unaligned(tx_ptr as *const u16);
     let attributes = core::ptr::read_unaligned(tx_ptr.add(2) as *const u16);
-    let _ = attributes; // reserved for future use
     let flags = core::ptr::read_unaligned(tx_ptr.add(8) as *const u32);
     let receiver_count = core::ptr::read_unaligned(tx_ptr.add(32) as *const u32);
     let receivers_offset = core::ptr::read_unaligned(tx_ptr.add(36) as *const u32);
 
-    // Only support single-receiver share for now
-    if receiver_count == 0 || receiver_count > 1 {
+    if receiver_count == 0 || receiver_count as usize > MAX_RECEIVERS {
         return Err(crate::ffa::FFA_INVALID_PARAMETERS);
     }
 
-    // Validate receiver descriptor bounds
+    // Validate receiver descriptor array bounds
+    let access_size = core::mem::size_of::<FfaMemAccessDesc>();
     let access_offset = receivers_offset as usize;
-    let access_end = access_offset + core::mem::size_of::<FfaMemAccessDesc>();
+    let access_end = access_offset + receiver_count as usize * access_size;
     if access_end > total {
         return Err(crate::ffa::FFA_INVALID_PARAMETERS);
     }
 
-    // Read FfaMemAccessDesc
-    let access_ptr = tx_ptr.add(access_offset);
-    let receiver_id = core::ptr::read_unaligned(access_ptr as *const u16);
-    let composite_offset = core::ptr::read_unaligned(access_ptr.add(4) as *const u32);
+    // Read the FfaMemAccessDesc array
+    let mut receivers = [(0u16, 0u8); MAX_RECEIVERS];
+    let mut composite_offset = 0;
+    for (i, receiver) in receivers[..receiver_count as usize].iter_mut().enumerate() {
+        let access_ptr = tx_ptr.add(access_offset + i * access_size);
+        let receiver_id = core::ptr::read_unaligned(access_ptr as *const u16);
+        let permissions = core::ptr::read_unaligned(access_ptr.add(2));
+        let offset = core::ptr::read_unaligned(access_ptr.add(4) as *const u32);
+        if i > 0 && offset != composite_offset {
+            return Err(crate::ffa::FFA_INVALID_PARAMETERS);
+        }
+        *receiver = (receiver_id, permissions);
+        composite_offset = offset;
+    }
 
     // Validate composite descriptor bounds
     let comp_offset = composite_offset as usize;
@@ -165,15 +186,22 @@ pub unsafe fn parse_mem_region(
     if address_range_count == 0 {
         return Err(crate::ffa::FFA_INVALID_PARAMETERS);
     }
+    // A share record holds at most MAX_ADDR_RANGES ranges; dropping the
+    // rest would share less than the sender described
+    if address_range_count as usize > MAX_ADDR_RANGES {
+        return Err(crate::ffa::FFA_NO_MEMORY);
+    }
 
     // Read address ranges
     let ranges_offset = comp_end;
     let range_size = core::mem::size_of::<FfaMemRegionAddrRange>();
-    let count = (address_range_count as usize).min(MAX_ADDR_RANGES);
+    let count = address_range_count as usize;
 
     let mut result = ParsedMemRegion::new();
     result.sender_id = sender_id;
-    result.receiver_id = receiver_id;
+    result.receivers = receivers;
+    result.receiver_count = receiver_count as usize;
+    result.attributes = attributes;
     result.flags = flags;
     result.total_page_count = total_page_count;
 
@@ -198,6 +226,72 @@ pub unsafe fn parse_mem_region(
     Ok(result)
 }
 
+/// Fields of an FFA_MEM_RETRIEVE_RESP descriptor, one receiver.
+pub struct RetrieveResp<'a> {
+    pub sender_id: u16,
+    pub receiver_id: u16,
+    /// Memory region attributes (Table 5.22)
+    pub attributes: u16,
+    /// Flags (transaction type in bits [4:3])
+    pub flags: u32,
+    pub handle: u64,
+    /// Memory access permissions (Table 5.23)
+    pub permissions: u8,
+    pub ranges: &'a [(u64, u32)],
+    pub total_page_count: u32,
+}
+
+/// Write an FF-A v1.1 retrieve-response descriptor (header, one access
+/// descriptor, composite region and address ranges) into an RX buffer.
+///
+/// Returns the total length, or None if it does not fit in `capacity` bytes.
+///
+/// # Safety
+///
+/// `buf` must point to at least `capacity` bytes of writable memory.
+pub unsafe fn write_retrieve_resp(
+    buf: *mut u8,
+    capacity: usize,
+    resp: &RetrieveResp,
+) -> Option<u32> {
+    let recv_off = core::mem::size_of::<FfaMemRegion>();
+    let comp_off = recv_off + core::mem::size_of::<FfaMemAccessDesc>();
+    let ranges_start = comp_off + core::mem::size_of::<FfaCompositeMemRegion>();
+    let total = ranges_start + resp.ranges.len() * core::mem::size_of::<FfaMemRegionAddrRange>();
+    if total > capacity {
+        return None;
+    }
+    core::ptr::write_bytes(buf, 0, total);
+
+    // FfaMemRegion header
+    core::ptr::write_unaligned(buf as *mut u16, resp.sender_id);
+    core::ptr::write_unaligned(buf.add(2) as *mut u16, resp.attributes);
+    core::ptr::write_unaligned(buf.add(8) as *mut u32, resp.flags);
+    core::ptr::write_unaligned(buf.add(16) as *mut u64, resp.handle);
+    core::ptr::write_unaligned(buf.add(32) as *mut u32, 1);
+    core::ptr::write_unaligned(buf.add(36) as *mut u32, recv_off as u32);
+
+    // FfaMemAccessDesc
+    let access_ptr = buf.add(recv_off);
+    core::ptr::write_unaligned(access_ptr as *mut u16, resp.receiver_id);
+    core::ptr::write_unaligned(access_ptr.add(2), resp.permissions);
+    core::ptr::write_unaligned(access_ptr.add(4) as *mut u32, comp_off as u32);
+
+    // FfaCompositeMemRegion
+    let comp_ptr = buf.add(comp_off);
+    core::ptr::write_unaligned(comp_ptr as *mut u32, resp.total_page_count);
+    core::ptr::write_unaligned(comp_ptr.add(4) as *mut u32, resp.ranges.len() as u32);
+
+    // FfaMemRegionAddrRange array
+    for (i, &(addr, count)) in resp.ranges.iter().enumerate() {
+        let range_ptr = buf.add(ranges_start + i * 16);
+        core::ptr::write_unaligned(range_ptr as *mut u64, addr);
+        core::ptr::write_unaligned(range_ptr.add(8) as *mut u32, count);
+    }
+
+    Some(total as u32)
+}
+
 /// Build a minimal FfaMemRegion descriptor in a buffer for testing.
 ///
 /// Returns the total descriptor length.
@@ -212,30 +306,50 @@ pub unsafe fn build_test_descriptor(
     ranges: &[(u64, u32)],
 ) -> u32 {
     core::ptr::write_bytes(buf, 0, 128);
+    build_test_descriptor_multi(buf, sender_id, &[(receiver_id, 0)], ranges)
+}
 
+/// Build an FfaMemRegion descriptor with one memory access descriptor per
+/// `(receiver_id, permissions)` entry, all sharing one composite region.
+///
+/// Returns the total descriptor length.
+///
+/// # Safety
+///
+/// `buf` must point to at least `64 + 16 * (receivers + ranges)` bytes of
+/// writable memory, zeroed.
+pub unsafe fn build_test_descriptor_multi(
+    buf: *mut u8,
+    sender_id: u16,
+    receivers: &[(u16, u8)],
+    ranges: &[(u64, u32)],
+) -> u32 {
     // FfaMemRegion header (48 bytes)
     // sender_id at offset 0
     core::ptr::write_unaligned(buf as *mut u16, sender_id);
     // receiver_count at offset 32
-    core::ptr::write_unaligned(buf.add(32) as *mut u32, 1);
+    core::ptr::write_unaligned(buf.add(32) as *mut u32, receivers.len() as u32);
     // receivers_offset at offset 36 (right after the 48-byte header)
     let recv_off: u32 = 48;
     core::ptr::write_unaligned(buf.add(36) as *mut u32, recv_off);
 
-    // FfaMemAccessDesc (16 bytes) at offset 48
-    let access_ptr = buf.add(recv_off as usize);
-    core::ptr::write_unaligned(access_ptr as *mut u16, receiver_id);
-    // composite_offset at +4 (from start of FfaMemRegion)
-    let comp_off: u32 = 48 + 16; // after access desc
-    core::ptr::write_unaligned(access_ptr.add(4) as *mut u32, comp_off);
+    // FfaMemAccessDesc array (16 bytes each) at offset 48; composite_offset
+    // at +4 (from start of FfaMemRegion) points past the whole array
+    let comp_off = recv_off + 16 * receivers.len() as u32;
+    for (i, &(receiver_id, permissions)) in receivers.iter().enumerate() {
+        let access_ptr = buf.add(recv_off as usize + i * 16);
+        core::ptr::write_unaligned(access_ptr as *mut u16, receiver_id);
+        core::ptr::write_unaligned(access_ptr.add(2), permissions);
+        core::ptr::write_unaligned(access_ptr.add(4) as *mut u32, comp_off);
+    }
 
-    // FfaCompositeMemRegion (16 bytes) at offset 64
+    // FfaCompositeMemRegion (16 bytes) after the access descriptors
     let comp_ptr = buf.add(comp_off as usize);
     let total_pages: u32 = ranges.iter().map(|(_, c)| *c).sum();
     core::ptr::write_unaligned(comp_ptr as *mut u32, total_pages);
     core::ptr::write_unaligned(comp_ptr.add(4) as *mut u32, ranges.len() as u32);
 
-    // FfaMemRegionAddrRange (16 bytes each) starting at offset 80
+    // FfaMemRegionAddrRange (16 bytes each) after the composite header
     let ranges_start = comp_off as usize + 16;
     for (i, &(addr, count)) in ranges.iter().enumerate() {
         let range_ptr = buf.add(ranges_start + i * 16);
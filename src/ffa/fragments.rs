@@ -0,0 +1,163 @@
+//! Fragmented MEM_SHARE / MEM_LEND reassembly (FFA_MEM_FRAG_TX / FRAG_RX).
+//!
+//! A sender whose descriptor does not fit its TX buffer passes
+//! `fragment_length < total_length` to FFA_MEM_SHARE/LEND. The proxy copies
+//! each fragment into a per-VM accumulator, answers FFA_MEM_FRAG_RX with
+//! the transaction handle and the bytes received so far, and takes the next
+//! fragment with FFA_MEM_FRAG_TX. Once `total_length` bytes are in, the
+//! assembled descriptor is parsed and validated like a single-fragment one.
+//!
+//! One transaction per sender VM may be in flight. The sender abandons it
+//! with FFA_MEM_RECLAIM on its handle; VM teardown drops it too.
+
+use crate::ffa::descriptors::{self, ParsedMemRegion};
+use crate::ffa::{FFA_BUSY, FFA_INVALID_PARAMETERS, FFA_MAX_VMS, FFA_NO_MEMORY};
+use core::cell::UnsafeCell;
+
+/// 4KB pages of reassembly buffer per sender VM.
+pub const MAX_FRAG_DESC_PAGES: usize = 4;
+
+/// Longest descriptor that can be reassembled. Several TX buffers' worth,
+/// so the access and composite descriptors may sit past the first page;
+/// more address ranges than `MAX_ADDR_RANGES` still fail in
+/// `parse_mem_region`.
+pub const MAX_FRAG_DESC_LEN: usize = MAX_FRAG_DESC_PAGES * 4096;
+
+/// One sender VM's in-flight fragmented transaction.
+struct FragState {
+    active: bool,
+    handle: u64,
+    is_lend: bool,
+    total_length: u32,
+    received: u32,
+    buf: [u8; MAX_FRAG_DESC_LEN],
+    /// The assembled descriptor, handed out by `FragProgress::Complete`
+    parsed: ParsedMemRegion,
+}
+
+impl FragState {
+    const fn new() -> Self {
+        Self {
+            active: false,
+            handle: 0,
+            is_lend: false,
+            total_length: 0,
+            received: 0,
+            buf: [0; MAX_FRAG_DESC_LEN],
+            parsed: ParsedMemRegion::new(),
+        }
+    }
+}
+
+/// Result of accepting a fragment.
+pub enum FragProgress {
+    /// More fragments expected; `received` bytes delivered so far.
+    Pending { handle: u64, received: u32 },
+    /// Descriptor complete and parsed (kept in the sender's accumulator
+    /// until its next transaction begins).
+    Complete {
+        handle: u64,
+        is_lend: bool,
+        parsed: &'static ParsedMemRegion,
+    },
+}
+
+/// Per-VM accumulators, keyed on the sender VM ID.
+///
+/// Access is safe: in single-pCPU modes only one exception handler runs at
+/// a time; in multi-pCPU mode each VM's entry is only touched from its own
+/// FF-A calls (and from its teardown, after it stopped running).
+struct FragArray(UnsafeCell<[FragState; FFA_MAX_VMS]>);
+unsafe impl Sync for FragArray {}
+
+static FRAGMENTS: FragArray = FragArray(UnsafeCell::new([const { FragState::new() }; FFA_MAX_VMS]));
+
+fn state(vm_id: usize) -> &'static mut FragState {
+    assert!(vm_id < FFA_MAX_VMS);
+    unsafe { &mut (*FRAGMENTS.0.get())[vm_id] }
+}
+
+/// Copy `fragment` into `vm_id`'s accumulator and parse it once complete.
+fn accept(vm_id: usize, fragment: &[u8]) -> Result<FragProgress, i32> {
+    let st = state(vm_id);
+    let start = st.received as usize;
+    let end = start + fragment.len();
+    if fragment.is_empty() || end > st.total_length as usize {
+        st.active = false;
+        return Err(FFA_INVALID_PARAMETERS);
+    }
+    st.buf[start..end].copy_from_slice(fragment);
+    st.received = end as u32;
+    if st.received < st.total_length {
+        return Ok(FragProgress::Pending {
+            handle: st.handle,
+            received: st.received,
+        });
+    }
+    st.active = false;
+    // SAFETY: buf holds total_length (<= MAX_FRAG_DESC_LEN) bytes
+    st.parsed = unsafe { descriptors::parse_mem_region(st.buf.as_ptr(), st.total_length)? };
+    Ok(FragProgress::Complete {
+        handle: st.handle,
+        is_lend: st.is_lend,
+        parsed: &st.parsed,
+    })
+}
+
+/// Start a fragmented transaction for `vm_id` with its first fragment.
+///
+/// Fails with FFA_BUSY if the VM already has one in flight, FFA_NO_MEMORY
+/// if `total_length` exceeds `MAX_FRAG_DESC_LEN`.
+pub fn begin(
+    vm_id: usize,
+    handle: u64,
+    is_lend: bool,
+    total_length: u32,
+    fragment: &[u8],
+) -> Result<FragProgress, i32> {
+    let st = state(vm_id);
+    if st.active {
+        return Err(FFA_BUSY);
+    }
+    if total_length as usize > MAX_FRAG_DESC_LEN {
+        return Err(FFA_NO_MEMORY);
+    }
+    st.active = true;
+    st.handle = handle;
+    st.is_lend = is_lend;
+    st.total_length = total_length;
+    st.received = 0;
+    accept(vm_id, fragment)
+}
+
+/// Append the next fragment (FFA_MEM_FRAG_TX) of `vm_id`'s transaction
+/// `handle`. A fragment overrunning `total_length` aborts the transaction.
+pub fn append(vm_id: usize, handle: u64, fragment: &[u8]) -> Result<FragProgress, i32> {
+    let st = state(vm_id);
+    if !st.active || st.handle != handle {
+        return Err(FFA_INVALID_PARAMETERS);
+    }
+    accept(vm_id, fragment)
+}
+
+/// Whether `vm_id` has transaction `handle` in flight.
+pub fn in_flight(vm_id: usize, handle: u64) -> bool {
+    let st = state(vm_id);
+    st.active && st.handle == handle
+}
+
+/// Abandon `vm_id`'s transaction `handle`. Returns true if one was dropped.
+pub fn abort(vm_id: usize, handle: u64) -> bool {
+    let dropped = in_flight(vm_id, handle);
+    if dropped {
+        state(vm_id).active = false;
+    }
+    dropped
+}
+
+/// Drop whatever transaction `vm_id` has in flight (VM teardown).
+pub fn abort_vm(vm_id: usize) {
+    if vm_id < FFA_MAX_VMS {
+        state(vm_id).active = false;
+    }
+}
@@ -30,3 +30,205 @@ pub fn validate_page_for_share(sw_bits: u8) -> Result<(), i32> {
         _ => Err(crate::ffa::FFA_DENIED),
     }
 }
+
+/// Translate FF-A memory region attributes into a Stage-2 MemAttr value.
+///
+/// "Not specified" maps to Normal Write-back, same as before attributes were
+/// honored. Device types map straight onto Device-nGnRnE..GRE.
+pub fn s2_memattr_from_ffa(attributes: u16) -> Result<u8, i32> {
+    use crate::arch::aarch64::defs::{S2_MEMATTR_NORMAL_NC, S2_MEMATTR_NORMAL_WB};
+    use crate::ffa::*;
+
+    let attr = ((attributes >> FFA_MEM_ATTR_SHIFT) & 0x3) as u8;
+    match (attributes >> FFA_MEM_TYPE_SHIFT) & 0x3 {
+        FFA_MEM_TYPE_NOT_SPECIFIED => Ok(S2_MEMATTR_NORMAL_WB),
+        // Stage-2 Device encodings are 0b00xx with the same xx as FF-A
+        FFA_MEM_TYPE_DEVICE => Ok(attr),
+        FFA_MEM_TYPE_NORMAL => match attr as u16 {
+            FFA_MEM_NORMAL_NON_CACHEABLE => Ok(S2_MEMATTR_NORMAL_NC),
+            FFA_MEM_NORMAL_WRITE_BACK => Ok(S2_MEMATTR_NORMAL_WB),
+            _ => Err(FFA_INVALID_PARAMETERS),
+        },
+        _ => Err(FFA_INVALID_PARAMETERS),
+    }
+}
+
+/// Inverse of `s2_memattr_from_ffa`: FF-A memory region attributes for a
+/// share recorded with the given Stage-2 MemAttr (used in retrieve responses).
+pub fn ffa_attrs_from_s2_memattr(mem_attr: u8) -> u16 {
+    use crate::arch::aarch64::defs::S2_MEMATTR_NORMAL_NC;
+    use crate::ffa::*;
+
+    if mem_attr & 0b1100 == 0 {
+        // Device-nGnRnE..GRE share the low two bits with FF-A
+        (FFA_MEM_TYPE_DEVICE << FFA_MEM_TYPE_SHIFT) | ((mem_attr as u16) << FFA_MEM_ATTR_SHIFT)
+    } else if mem_attr == S2_MEMATTR_NORMAL_NC {
+        (FFA_MEM_TYPE_NORMAL << FFA_MEM_TYPE_SHIFT)
+            | (FFA_MEM_NORMAL_NON_CACHEABLE << FFA_MEM_ATTR_SHIFT)
+    } else {
+        (FFA_MEM_TYPE_NORMAL << FFA_MEM_TYPE_SHIFT)
+            | (FFA_MEM_NORMAL_WRITE_BACK << FFA_MEM_ATTR_SHIFT)
+    }
+}
+
+/// Translate a receiver's FF-A memory access permissions into a Stage-2
+/// S2AP value: read-only maps RO; read-write or "not specified" maps RW.
+/// Reserved data or instruction access encodings are INVALID_PARAMETERS.
+pub fn s2ap_from_ffa_permissions(permissions: u8) -> Result<u8, i32> {
+    use crate::arch::aarch64::defs::{S2AP_RO, S2AP_RW, S2AP_SHIFT};
+    use crate::ffa::*;
+
+    if permissions & FFA_MEM_INST_ACCESS_MASK == FFA_MEM_INST_ACCESS_MASK {
+        return Err(FFA_INVALID_PARAMETERS);
+    }
+    match permissions & FFA_MEM_DATA_ACCESS_MASK {
+        FFA_MEM_DATA_ACCESS_RO => Ok((S2AP_RO >> S2AP_SHIFT) as u8),
+        0 | FFA_MEM_DATA_ACCESS_RW => Ok((S2AP_RW >> S2AP_SHIFT) as u8),
+        _ => Err(FFA_INVALID_PARAMETERS),
+    }
+}
+
+/// Whether every range (4KB FF-A pages) covers whole leaf pages of
+/// `walker`'s granule. A 64KB Stage-2 cannot share or map part of a page
+/// without exposing the rest of it.
+pub fn ranges_fit_granule(
+    walker: &crate::ffa::stage2_walker::Stage2Walker,
+    ranges: &[(u64, u32)],
+) -> bool {
+    use crate::arch::aarch64::defs::PAGE_SIZE_4KB;
+
+    let mask = walker.page_size() - 1;
+    ranges
+        .iter()
+        .all(|&(ipa, count)| ipa & mask == 0 && (count as u64 * PAGE_SIZE_4KB) & mask == 0)
+}
+
+/// IPAs of the leaf pages of `walker`'s granule covering a range of
+/// `page_count` 4KB FF-A pages at `base_ipa`.
+fn leaf_pages(
+    walker: &crate::ffa::stage2_walker::Stage2Walker,
+    base_ipa: u64,
+    page_count: u32,
+) -> impl Iterator<Item = u64> {
+    use crate::arch::aarch64::defs::PAGE_SIZE_4KB;
+
+    let end = base_ipa + page_count as u64 * PAGE_SIZE_4KB;
+    (base_ipa..end).step_by(walker.page_size() as usize)
+}
+
+/// Map shared ranges into a receiver's Stage-2 as SharedBorrowed with the
+/// given MemAttr and the S2AP its FF-A `permissions` grant. On failure,
+/// pages mapped so far are unmapped again.
+pub fn map_shared_ranges(
+    walker: &crate::ffa::stage2_walker::Stage2Walker,
+    ranges: &[(u64, u32)],
+    mem_attr: u8,
+    permissions: u8,
+) -> Result<(), i32> {
+    let s2ap = s2ap_from_ffa_permissions(permissions)?;
+    if !ranges_fit_granule(walker, ranges) {
+        return Err(crate::ffa::FFA_INVALID_PARAMETERS);
+    }
+    let sw = PageOwnership::SharedBorrowed as u8;
+    for (i, &(base_ipa, page_count)) in ranges.iter().enumerate() {
+        for ipa in leaf_pages(walker, base_ipa, page_count) {
+            if walker.map_page_with_attr(ipa, s2ap, sw, mem_attr).is_err() {
+                // Rollback (best effort -- ignore errors on rollback)
+                for (j, &(rb_ipa, rb_count)) in ranges[..=i].iter().enumerate() {
+                    for rb in leaf_pages(walker, rb_ipa, rb_count) {
+                        if j == i && rb == ipa {
+                            break;
+                        }
+                        let _ = walker.unmap_page(rb);
+                    }
+                }
+                return Err(crate::ffa::FFA_DENIED);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Unmap shared ranges from a receiver's Stage-2 (best effort).
+pub fn unmap_shared_ranges(
+    walker: &crate::ffa::stage2_walker::Stage2Walker,
+    ranges: &[(u64, u32)],
+) {
+    for &(base_ipa, page_count) in ranges {
+        for ipa in leaf_pages(walker, base_ipa, page_count) {
+            let _ = walker.unmap_page(ipa);
+        }
+    }
+}
+
+/// Return shared ranges to the sender: Owned + RW + write-back (best effort).
+pub fn restore_owned_ranges(
+    walker: &crate::ffa::stage2_walker::Stage2Walker,
+    ranges: &[(u64, u32)],
+) {
+    use crate::arch::aarch64::defs::{S2AP_RW, S2AP_SHIFT, S2_MEMATTR_NORMAL_WB};
+
+    let owned_sw = PageOwnership::Owned as u8;
+    let rw_s2ap = (S2AP_RW >> S2AP_SHIFT) as u8;
+    for &(base_ipa, page_count) in ranges {
+        for ipa in leaf_pages(walker, base_ipa, page_count) {
+            let _ = walker.write_sw_bits(ipa, owned_sw);
+            let _ = walker.set_s2ap(ipa, rw_s2ap);
+            let _ = walker.set_mem_attr(ipa, S2_MEMATTR_NORMAL_WB);
+        }
+    }
+}
+
+/// Stage-2 of the VM behind partition `part_id`, if it has one.
+fn partition_stage2(part_id: u16) -> Option<crate::ffa::stage2_walker::Stage2Walker> {
+    let vm_id = crate::ffa::partition_id_to_vm_id(part_id)?;
+    let walker = crate::ffa::stage2_walker::Stage2Walker::for_vm(vm_id);
+    walker.has_stage2().then_some(walker)
+}
+
+/// Roll back the outstanding shares of `vm_id` when it is stopped.
+///
+/// Shares the VM sent are force-reclaimed: every receiver that retrieved
+/// them loses its mapping, the VM's pages go back to Owned + RW +
+/// write-back and the record is removed. Shares the VM retrieved as a
+/// receiver are relinquished on its behalf (unmapped from its Stage-2);
+/// those records belong to the sender, which can reclaim them once the
+/// other receivers have relinquished too.
+///
+/// Returns the number of shares rolled back.
+pub fn reclaim_vm_shares(vm_id: usize) -> usize {
+    use crate::ffa::stub_spmc;
+
+    // A fragmented share still in flight was never recorded
+    crate::ffa::fragments::abort_vm(vm_id);
+
+    let part_id = crate::ffa::vm_id_to_partition_id(vm_id);
+    let mut handles = [0u64; stub_spmc::MAX_SHARES];
+    let count = stub_spmc::share_handles(part_id, &mut handles);
+    let mut rolled_back = 0;
+    for &handle in &handles[..count] {
+        let Some(info) = stub_spmc::lookup_share_full(handle) else {
+            continue;
+        };
+        let ranges = &info.ranges[..info.range_count];
+        if info.sender_id == part_id {
+            for receiver in info.receivers().iter().filter(|r| r.retrieved) {
+                if let Some(walker) = partition_stage2(receiver.id) {
+                    unmap_shared_ranges(&walker, ranges);
+                }
+            }
+            if let Some(walker) = partition_stage2(part_id) {
+                restore_owned_ranges(&walker, ranges);
+            }
+            stub_spmc::reclaim_share(handle);
+            rolled_back += 1;
+        } else if info.receiver(part_id).is_some_and(|r| r.retrieved) {
+            if let Some(walker) = partition_stage2(part_id) {
+                unmap_shared_ranges(&walker, ranges);
+            }
+            stub_spmc::mark_relinquished(handle, part_id);
+            rolled_back += 1;
+        }
+    }
+    rolled_back
+}
@@ -5,14 +5,20 @@
 //! a stub SPMC (replaceable with real Secure World later).
 
 pub mod descriptors;
+pub mod fragments;
 pub mod mailbox;
 pub mod memory;
 pub mod notifications;
 pub mod proxy;
+pub mod share_limit;
 pub mod smc_forward;
 pub mod stage2_walker;
 pub mod stub_spmc;
 
+pub use memory::reclaim_vm_shares;
+
+use crate::arch::aarch64::regs::VcpuContext;
+
 // ── FF-A Function IDs (SMC32) ─────────────────────────────────────
 pub const FFA_ERROR: u64 = 0x84000060;
 pub const FFA_SUCCESS_32: u64 = 0x84000061;
@@ -31,9 +37,7 @@ pub const FFA_MEM_RETRIEVE_REQ_32: u64 = 0x84000074;
 pub const FFA_MEM_RETRIEVE_RESP: u64 = 0x84000075;
 pub const FFA_MEM_RELINQUISH: u64 = 0x84000076;
 pub const FFA_MEM_RECLAIM: u64 = 0x84000077;
-#[allow(dead_code)]
 pub const FFA_MEM_FRAG_RX: u64 = 0x8400007A;
-#[allow(dead_code)]
 pub const FFA_MEM_FRAG_TX: u64 = 0x8400007B;
 pub const FFA_INTERRUPT: u64 = 0x84000062;
 pub const FFA_NOTIFICATION_BITMAP_CREATE: u64 = 0x8400007D;
@@ -47,6 +51,7 @@ pub const FFA_SPM_ID_GET: u64 = 0x84000085;
 pub const FFA_MSG_SEND2: u64 = 0x84000086;
 pub const FFA_MSG_WAIT: u64 = 0x8400006B;
 pub const FFA_RUN: u64 = 0x8400006D;
+pub const FFA_CONSOLE_LOG_32: u64 = 0x8400008A;
 
 // ── FF-A Function IDs (SMC64) ─────────────────────────────────────
 #[allow(dead_code)]
@@ -59,9 +64,13 @@ pub const FFA_MEM_LEND_64: u64 = 0xC4000072;
 pub const FFA_MEM_SHARE_64: u64 = 0xC4000073;
 pub const FFA_MEM_RETRIEVE_REQ_64: u64 = 0xC4000074;
 pub const FFA_NOTIFICATION_INFO_GET_64: u64 = 0xC4000083;
+pub const FFA_CONSOLE_LOG_64: u64 = 0xC400008A;
 
 // ── FF-A Version ──────────────────────────────────────────────────
+pub const FFA_VERSION_1_0: u32 = 0x00010000; // Major=1, Minor=0
 pub const FFA_VERSION_1_1: u32 = 0x00010001; // Major=1, Minor=1
+/// Bit 31 of FFA_VERSION x1 is MBZ; set means the request is malformed.
+pub const FFA_VERSION_MBZ_BIT: u64 = 1 << 31;
 
 // ── FF-A Error Codes (returned in x2 with FFA_ERROR in x0) ───────
 pub const FFA_NOT_SUPPORTED: i32 = -1;
@@ -74,6 +83,43 @@ pub const FFA_ABORTED: i32 = -7;
 #[allow(dead_code)]
 pub const FFA_NO_DATA: i32 = -8;
 
+// ── FFA_FEATURES(FFA_RXTX_MAP) properties (returned in x2) ───────
+/// Bits [1:0]: minimum buffer size and alignment. 0b00 = 4KB.
+pub const FFA_RXTX_MAP_MIN_BUF_4K: u64 = 0b00;
+/// Bits [31:16]: maximum buffer size in 4KB pages (0 = no limit).
+pub const FFA_RXTX_MAP_MAX_PAGES_SHIFT: u64 = 16;
+/// Largest RX/TX buffer accepted by FFA_RXTX_MAP, in 4KB pages.
+pub const FFA_RXTX_MAX_PAGES: u32 = 1;
+
+// ── Memory region attributes (DEN0077A Table 10.18) ───────────────
+/// Bits [5:4]: memory type.
+pub const FFA_MEM_TYPE_SHIFT: u16 = 4;
+pub const FFA_MEM_TYPE_NOT_SPECIFIED: u16 = 0b00;
+pub const FFA_MEM_TYPE_DEVICE: u16 = 0b01;
+pub const FFA_MEM_TYPE_NORMAL: u16 = 0b10;
+/// Bits [3:2]: cacheability (Normal) or device attributes (Device).
+pub const FFA_MEM_ATTR_SHIFT: u16 = 2;
+pub const FFA_MEM_NORMAL_NON_CACHEABLE: u16 = 0b01;
+pub const FFA_MEM_NORMAL_WRITE_BACK: u16 = 0b11;
+
+// ── Memory access permissions (DEN0077A Table 10.15) ──────────────
+/// Bits [1:0]: data access. 0b00 = not specified, 0b11 = reserved.
+pub const FFA_MEM_DATA_ACCESS_MASK: u8 = 0b11;
+/// Data access 0b01 = read-only.
+pub const FFA_MEM_DATA_ACCESS_RO: u8 = 0b01;
+/// Data access 0b10 = read-write.
+pub const FFA_MEM_DATA_ACCESS_RW: u8 = 0b10;
+/// Bits [3:2]: instruction access. 0b11 = reserved.
+pub const FFA_MEM_INST_ACCESS_MASK: u8 = 0b11 << 2;
+/// Instruction access 0b01 = not executable.
+pub const FFA_MEM_INST_ACCESS_NX: u8 = 0b01 << 2;
+
+// ── Memory transaction flags (DEN0077A Table 10.21) ───────────────
+/// Bits [4:3]: transaction type reported in a retrieve response.
+pub const FFA_MEM_FLAG_TYPE_SHIFT: u32 = 3;
+pub const FFA_MEM_FLAG_TYPE_SHARE: u32 = 0b01;
+pub const FFA_MEM_FLAG_TYPE_LEND: u32 = 0b10;
+
 // ── Partition IDs ─────────────────────────────────────────────────
 #[allow(dead_code)]
 pub const FFA_HOST_ID: u16 = 0x0000;
@@ -114,3 +160,33 @@ pub fn is_vm_partition(part_id: u16) -> bool {
 pub fn is_valid_receiver(part_id: u16) -> bool {
     is_vm_partition(part_id) || stub_spmc::is_valid_sp(part_id)
 }
+
+// ── Memory handle registers (DEN0077A) ────────────────────────────
+// The 64-bit handle travels as two 32-bit halves, low first. Calls that
+// take it (MEM_RECLAIM, MEM_RETRIEVE_REQ, MEM_RELINQUISH, MEM_FRAG_TX) and
+// FFA_MEM_FRAG_RX have it in w1/w2; FFA_SUCCESS for MEM_SHARE/LEND and
+// the register-based MEM_RETRIEVE_RESP return it in w2/w3. A caller passes
+// `mem_handle_from_result()` of the share to `set_mem_handle_args()`.
+
+/// Handle taken by a memory management call (x1 = low, x2 = high).
+pub fn mem_handle_from_args(context: &VcpuContext) -> u64 {
+    (context.gp_regs.x1 & 0xFFFF_FFFF) | ((context.gp_regs.x2 & 0xFFFF_FFFF) << 32)
+}
+
+/// Pass `handle` to a memory management call (x1 = low, x2 = high).
+pub fn set_mem_handle_args(context: &mut VcpuContext, handle: u64) {
+    context.gp_regs.x1 = handle & 0xFFFF_FFFF;
+    context.gp_regs.x2 = handle >> 32;
+}
+
+/// Handle returned by MEM_SHARE/LEND (x2 = low, x3 = high).
+pub fn mem_handle_from_result(context: &VcpuContext) -> u64 {
+    (context.gp_regs.x2 & 0xFFFF_FFFF) | ((context.gp_regs.x3 & 0xFFFF_FFFF) << 32)
+}
+
+/// Return `handle` from MEM_SHARE/LEND or a register-based
+/// MEM_RETRIEVE_RESP (x2 = low, x3 = high).
+pub fn set_mem_handle_result(context: &mut VcpuContext, handle: u64) {
+    context.gp_regs.x2 = handle & 0xFFFF_FFFF;
+    context.gp_regs.x3 = handle >> 32;
+}
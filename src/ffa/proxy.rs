@@ -4,7 +4,7 @@
 //! Validates page ownership via Stage-2 PTE SW bits before allowing
 //! memory sharing operations (pKVM-compatible).
 
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 
 #[cfg(feature = "linux_guest")]
 use crate::arch::aarch64::defs::*;
@@ -14,6 +14,17 @@ use crate::ffa::*;
 /// Whether a real SPMC was detected at EL3 during init.
 static SPMC_PRESENT: AtomicBool = AtomicBool::new(false);
 
+/// FF-A version each VM passed to FFA_VERSION (0 = never called).
+static NEGOTIATED_VERSION: [AtomicU32; FFA_MAX_VMS] = [const { AtomicU32::new(0) }; FFA_MAX_VMS];
+
+/// FF-A version the given VM requested via FFA_VERSION, or 0 if it has not
+/// called FFA_VERSION yet.
+pub fn negotiated_version(vm_id: usize) -> u32 {
+    NEGOTIATED_VERSION
+        .get(vm_id)
+        .map_or(0, |v| v.load(Ordering::Relaxed))
+}
+
 // ── Proxy RXTX buffers (registered with SPMD for PARTITION_INFO relay) ──
 
 /// 4KB-aligned page for proxy TX/RX buffers (separate from per-VM guest mailboxes).
@@ -80,60 +91,88 @@ pub fn spmc_present() -> bool {
     SPMC_PRESENT.load(Ordering::Relaxed)
 }
 
+/// Handler for one FF-A call; returns true to continue the guest.
+type FfaHandler = fn(&mut VcpuContext) -> bool;
+
+/// Function IDs the proxy handles itself, and their handlers.
+///
+/// Single source of truth for both `handle_ffa_call` dispatch and
+/// FFA_FEATURES: a call added here is reported as supported.
+const HANDLERS: &[(u64, FfaHandler)] = &[
+    // Always handled locally (proxy policy, same as pKVM)
+    (FFA_VERSION, handle_version),
+    (FFA_ID_GET, handle_id_get),
+    (FFA_FEATURES, handle_features),
+    (FFA_RXTX_MAP, handle_rxtx_map),
+    (FFA_RXTX_UNMAP, handle_rxtx_unmap),
+    (FFA_RX_RELEASE, handle_rx_release),
+    (FFA_PARTITION_INFO_GET, handle_partition_info_get),
+    // Direct messaging: forward to SPMC if present, else stub
+    (FFA_MSG_SEND_DIRECT_REQ_32, handle_msg_send_direct_req),
+    (FFA_MSG_SEND_DIRECT_REQ_64, handle_msg_send_direct_req),
+    // Memory operations: validate ownership, then stub SPMC or forward
+    (FFA_MEM_SHARE_32, handle_mem_share),
+    (FFA_MEM_SHARE_64, handle_mem_share),
+    (FFA_MEM_LEND_32, handle_mem_lend),
+    (FFA_MEM_LEND_64, handle_mem_lend),
+    (FFA_MEM_FRAG_TX, handle_mem_frag_tx),
+    (FFA_MEM_RECLAIM, handle_mem_reclaim),
+    (FFA_MEM_RETRIEVE_REQ_32, handle_mem_retrieve_req),
+    (FFA_MEM_RETRIEVE_REQ_64, handle_mem_retrieve_req),
+    (FFA_MEM_RELINQUISH, handle_mem_relinquish),
+    // Supplemental calls
+    (FFA_SPM_ID_GET, handle_spm_id_get),
+    (FFA_RUN, handle_run),
+    // Notifications
+    (
+        FFA_NOTIFICATION_BITMAP_CREATE,
+        handle_notification_bitmap_create,
+    ),
+    (
+        FFA_NOTIFICATION_BITMAP_DESTROY,
+        handle_notification_bitmap_destroy,
+    ),
+    (FFA_NOTIFICATION_BIND, handle_notification_bind),
+    (FFA_NOTIFICATION_UNBIND, handle_notification_unbind),
+    (FFA_NOTIFICATION_SET, handle_notification_set),
+    (FFA_NOTIFICATION_GET, handle_notification_get),
+    (FFA_NOTIFICATION_INFO_GET_32, handle_notification_info_get),
+    (FFA_NOTIFICATION_INFO_GET_64, handle_notification_info_get),
+    // Indirect messaging
+    (FFA_MSG_SEND2, handle_msg_send2),
+    (FFA_MSG_WAIT, handle_msg_wait),
+];
+
+fn local_handler(function_id: u64) -> Option<FfaHandler> {
+    HANDLERS
+        .iter()
+        .find(|&&(fid, _)| fid == function_id)
+        .map(|&(_, handler)| handler)
+}
+
+/// Function IDs handled locally, i.e. those FFA_FEATURES reports as
+/// supported.
+pub fn supported_functions() -> impl Iterator<Item = u64> {
+    HANDLERS.iter().map(|&(fid, _)| fid)
+}
+
 /// Handle an FF-A SMC call from guest.
 ///
 /// Called from handle_smc() when function_id is in FF-A range.
 /// Returns true to continue guest, false to exit.
 pub fn handle_ffa_call(context: &mut VcpuContext) -> bool {
     let function_id = context.gp_regs.x0;
+    if let Some(handler) = local_handler(function_id) {
+        return handler(context);
+    }
 
     match function_id {
-        // Always handled locally (proxy policy, same as pKVM)
-        FFA_VERSION => handle_version(context),
-        FFA_ID_GET => handle_id_get(context),
-        FFA_FEATURES => handle_features(context),
-        FFA_RXTX_MAP => handle_rxtx_map(context),
-        FFA_RXTX_UNMAP => handle_rxtx_unmap(context),
-        FFA_RX_RELEASE => handle_rx_release(context),
-        FFA_PARTITION_INFO_GET => handle_partition_info_get(context),
-
-        // Direct messaging: forward to SPMC if present, else stub
-        FFA_MSG_SEND_DIRECT_REQ_32 | FFA_MSG_SEND_DIRECT_REQ_64 => {
-            handle_msg_send_direct_req(context)
-        }
-
-        // Memory operations: validate ownership, then stub SPMC or forward
-        FFA_MEM_SHARE_32 | FFA_MEM_SHARE_64 => handle_mem_share(context),
-        FFA_MEM_LEND_32 | FFA_MEM_LEND_64 => handle_mem_lend(context),
-        FFA_MEM_RECLAIM => handle_mem_reclaim(context),
-        FFA_MEM_RETRIEVE_REQ_32 | FFA_MEM_RETRIEVE_REQ_64 => handle_mem_retrieve_req(context),
-        FFA_MEM_RELINQUISH => handle_mem_relinquish(context),
-
         // Blocked: FFA_MEM_DONATE (pKVM policy)
         FFA_MEM_DONATE_32 | FFA_MEM_DONATE_64 => {
             ffa_error(context, FFA_NOT_SUPPORTED);
             true
         }
 
-        // Supplemental calls
-        FFA_SPM_ID_GET => handle_spm_id_get(context),
-        FFA_RUN => handle_run(context),
-
-        // Notifications
-        FFA_NOTIFICATION_BITMAP_CREATE => handle_notification_bitmap_create(context),
-        FFA_NOTIFICATION_BITMAP_DESTROY => handle_notification_bitmap_destroy(context),
-        FFA_NOTIFICATION_BIND => handle_notification_bind(context),
-        FFA_NOTIFICATION_UNBIND => handle_notification_unbind(context),
-        FFA_NOTIFICATION_SET => handle_notification_set(context),
-        FFA_NOTIFICATION_GET => handle_notification_get(context),
-        FFA_NOTIFICATION_INFO_GET_32 | FFA_NOTIFICATION_INFO_GET_64 => {
-            handle_notification_info_get(context)
-        }
-
-        // Indirect messaging
-        FFA_MSG_SEND2 => handle_msg_send2(context),
-        FFA_MSG_WAIT => handle_msg_wait(context),
-
         // Unknown FF-A: forward to SPMC if present, else NOT_SUPPORTED
         _ => {
             if SPMC_PRESENT.load(Ordering::Relaxed) {
@@ -175,9 +214,15 @@ fn forward_ffa_to_spmc(context: &mut VcpuContext) -> bool {
 
 /// FFA_VERSION: Return supported FF-A version.
 ///
-/// Input:  x1 = caller's version (ignored for now)
+/// Input:  x1 = caller's version (recorded per VM, see `negotiated_version`)
 /// Output: x0 = FFA_VERSION_1_1 (0x00010001)
 fn handle_version(context: &mut VcpuContext) -> bool {
+    let requested = context.gp_regs.x1;
+    if requested & FFA_VERSION_MBZ_BIT == 0 {
+        if let Some(v) = NEGOTIATED_VERSION.get(crate::global::current_vm_id()) {
+            v.store(requested as u32, Ordering::Relaxed);
+        }
+    }
     context.gp_regs.x0 = FFA_VERSION_1_1 as u64;
     true
 }
@@ -199,42 +244,18 @@ fn handle_id_get(context: &mut VcpuContext) -> bool {
 /// Output: x0 = FFA_SUCCESS_32 if supported, FFA_ERROR + NOT_SUPPORTED if not
 fn handle_features(context: &mut VcpuContext) -> bool {
     let queried_fid = context.gp_regs.x1;
-    let supported = matches!(
-        queried_fid,
-        FFA_VERSION
-            | FFA_ID_GET
-            | FFA_FEATURES
-            | FFA_RXTX_MAP
-            | FFA_RXTX_UNMAP
-            | FFA_RX_RELEASE
-            | FFA_PARTITION_INFO_GET
-            | FFA_MSG_SEND_DIRECT_REQ_32
-            | FFA_MSG_SEND_DIRECT_REQ_64
-            | FFA_MEM_SHARE_32
-            | FFA_MEM_SHARE_64
-            | FFA_MEM_LEND_32
-            | FFA_MEM_LEND_64
-            | FFA_MEM_RECLAIM
-            | FFA_MEM_RETRIEVE_REQ_32
-            | FFA_MEM_RETRIEVE_REQ_64
-            | FFA_MEM_RELINQUISH
-            | FFA_SPM_ID_GET
-            | FFA_RUN
-            | FFA_NOTIFICATION_BITMAP_CREATE
-            | FFA_NOTIFICATION_BITMAP_DESTROY
-            | FFA_NOTIFICATION_BIND
-            | FFA_NOTIFICATION_UNBIND
-            | FFA_NOTIFICATION_SET
-            | FFA_NOTIFICATION_GET
-            | FFA_NOTIFICATION_INFO_GET_32
-            | FFA_NOTIFICATION_INFO_GET_64
-            | FFA_MSG_SEND2
-            | FFA_MSG_WAIT
-    );
+    let supported = local_handler(queried_fid).is_some();
 
     if supported {
         context.gp_regs.x0 = FFA_SUCCESS_32;
-        context.gp_regs.x2 = 0; // No additional feature properties
+        context.gp_regs.x2 = match queried_fid {
+            // Buffer size/alignment granularity and page-count limit
+            FFA_RXTX_MAP => {
+                FFA_RXTX_MAP_MIN_BUF_4K
+                    | ((FFA_RXTX_MAX_PAGES as u64) << FFA_RXTX_MAP_MAX_PAGES_SHIFT)
+            }
+            _ => 0, // No additional feature properties
+        };
     } else {
         ffa_error(context, FFA_NOT_SUPPORTED);
     }
@@ -253,7 +274,11 @@ fn handle_rxtx_map(context: &mut VcpuContext) -> bool {
     let page_count = context.gp_regs.x3 as u32;
 
     // Validate: page-aligned, non-zero, reasonable size
-    if tx_ipa & 0xFFF != 0 || rx_ipa & 0xFFF != 0 || page_count == 0 || page_count > 1 {
+    if tx_ipa & 0xFFF != 0
+        || rx_ipa & 0xFFF != 0
+        || page_count == 0
+        || page_count > FFA_RXTX_MAX_PAGES
+    {
         ffa_error(context, FFA_INVALID_PARAMETERS);
         return true;
     }
@@ -453,35 +478,50 @@ fn handle_msg_send_direct_req(context: &mut VcpuContext) -> bool {
         return forward_ffa_to_spmc(context);
     }
 
-    // Stub path: validate receiver is a known SP, echo x3-x7
-    if !stub_spmc::is_valid_sp(receiver) {
-        ffa_error(context, FFA_INVALID_PARAMETERS);
-        return true;
-    }
-
-    let x3 = context.gp_regs.x3;
-    let x4 = context.gp_regs.x4;
-    let x5 = context.gp_regs.x5;
-    let x6 = context.gp_regs.x6;
-    let x7 = context.gp_regs.x7;
-
-    // Return FFA_MSG_SEND_DIRECT_RESP
+    // Stub path: the stub SP (if Idle) answers with x3-x7 echoed back
     let is_64bit = context.gp_regs.x0 == FFA_MSG_SEND_DIRECT_REQ_64;
-    context.gp_regs.x0 = if is_64bit {
-        FFA_MSG_SEND_DIRECT_RESP_64
-    } else {
-        FFA_MSG_SEND_DIRECT_RESP_32
-    };
-    // x1 = [31:16] responder (SP), [15:0] receiver (VM)
-    context.gp_regs.x1 = ((receiver as u64) << 16) | (sender as u64);
-    context.gp_regs.x3 = x3;
-    context.gp_regs.x4 = x4;
-    context.gp_regs.x5 = x5;
-    context.gp_regs.x6 = x6;
-    context.gp_regs.x7 = x7;
+    let resp = [
+        if is_64bit {
+            FFA_MSG_SEND_DIRECT_RESP_64
+        } else {
+            FFA_MSG_SEND_DIRECT_RESP_32
+        },
+        // x1 = [31:16] responder (SP), [15:0] receiver (VM)
+        ((receiver as u64) << 16) | (sender as u64),
+        0,
+        context.gp_regs.x3,
+        context.gp_regs.x4,
+        context.gp_regs.x5,
+        context.gp_regs.x6,
+        context.gp_regs.x7,
+    ];
+    let exit = stub_spmc::direct_req(receiver, resp);
+    write_stub_exit(context, receiver, exit);
     true
 }
 
+/// Report to the calling VM how a stub SP gave up the CPU: its
+/// DIRECT_RESP, or FFA_MSG_WAIT/FFA_INTERRUPT with x1 = [31:16] SP ID,
+/// [15:0] vCPU 0.
+fn write_stub_exit(
+    context: &mut VcpuContext,
+    sp_id: u16,
+    exit: Result<stub_spmc::StubSpExit, i32>,
+) {
+    let x1 = (sp_id as u64) << 16;
+    let regs = match exit {
+        Ok(stub_spmc::StubSpExit::DirectResp(resp)) => resp,
+        Ok(stub_spmc::StubSpExit::MsgWait) => [FFA_MSG_WAIT, x1, 0, 0, 0, 0, 0, 0],
+        Ok(stub_spmc::StubSpExit::Interrupted) => [FFA_INTERRUPT, x1, 0, 0, 0, 0, 0, 0],
+        Err(code) => {
+            ffa_error(context, code);
+            return;
+        }
+    };
+    let gp = &mut context.gp_regs;
+    [gp.x0, gp.x1, gp.x2, gp.x3, gp.x4, gp.x5, gp.x6, gp.x7] = regs;
+}
+
 // ── Memory Sharing ───────────────────────────────────────────────────
 
 /// FFA_MEM_SHARE: Share memory pages with a secure partition.
@@ -489,12 +529,15 @@ fn handle_msg_send_direct_req(context: &mut VcpuContext) -> bool {
 /// Two interfaces supported:
 /// 1. **Descriptor-based** (FF-A v1.1 compliant): If RXTX mailbox is mapped,
 ///    reads composite memory region descriptor from TX buffer.
-///    x1 = total_length, x2 = fragment_length.
+///    x1 = total_length, x2 = fragment_length. The descriptor may name up
+///    to `descriptors::MAX_RECEIVERS` receivers.
 /// 2. **Register-based** (fallback for testing): If no mailbox,
-///    x3 = IPA, x4 = page_count, x5 = receiver_id.
+///    x3 = IPA, x4 = page_count, x5 = receiver_id, x6 = memory attributes.
 ///
 /// Validates page ownership via Stage-2 PTE SW bits and transitions
 /// pages from Owned → SharedOwned. Sets S2AP to RO for shared pages.
+/// The memory attributes (e.g. Normal Non-cacheable) are applied to the
+/// sender's pages here and to the receiver's on retrieve.
 fn handle_mem_share(context: &mut VcpuContext) -> bool {
     handle_mem_share_or_lend(context, false)
 }
@@ -510,15 +553,40 @@ fn handle_mem_lend(context: &mut VcpuContext) -> bool {
 ///
 /// - is_lend=false (SHARE): pages become S2AP_RO (guest retains read)
 /// - is_lend=true  (LEND):  pages become S2AP_NONE (guest loses access)
+///
+/// A descriptor with fragment_length < total_length starts a fragmented
+/// transaction: the first fragment is stashed and FFA_MEM_FRAG_RX returned.
+///
+/// Returns FFA_BUSY once the VM has used up its `share_limit` window.
 fn handle_mem_share_or_lend(context: &mut VcpuContext, is_lend: bool) -> bool {
     let vm_id = crate::global::current_vm_id();
+    if !share_limit::try_acquire(vm_id) {
+        ffa_error(context, FFA_BUSY);
+        return true;
+    }
     let mbox = mailbox::get_mailbox(vm_id);
 
     // Choose interface: descriptor-based (mailbox mapped) or register-based (fallback)
-    let (sender_id_from_desc, receiver_id, ranges, range_count, total_page_count) = if mbox.mapped {
+    let region = if mbox.mapped {
+        let total_length = context.gp_regs.x1 as u32;
+        let fragment_length = context.gp_regs.x2 as u32;
+        if fragment_length != 0 && fragment_length < total_length {
+            let Some(fragment) = tx_fragment(mbox, fragment_length) else {
+                ffa_error(context, FFA_INVALID_PARAMETERS);
+                return true;
+            };
+            let handle = stub_spmc::alloc_handle();
+            return match fragments::begin(vm_id, handle, is_lend, total_length, fragment) {
+                Ok(progress) => finish_fragment(context, vm_id, progress),
+                Err(code) => {
+                    ffa_error(context, code);
+                    true
+                }
+            };
+        }
         // FF-A v1.1 descriptor path: parse TX buffer
         match parse_share_descriptor(context, mbox) {
-            Ok(info) => info,
+            Ok(region) => region,
             Err(code) => {
                 ffa_error(context, code);
                 return true;
@@ -528,25 +596,65 @@ fn handle_mem_share_or_lend(context: &mut VcpuContext, is_lend: bool) -> bool {
         // Register-based fallback (for unit tests and simple use)
         let base_ipa = context.gp_regs.x3;
         let page_count = context.gp_regs.x4 as u32;
-        let receiver_id = context.gp_regs.x5 as u16;
         if page_count == 0 {
             ffa_error(context, FFA_INVALID_PARAMETERS);
             return true;
         }
         let mut ranges = [(0u64, 0u32); descriptors::MAX_ADDR_RANGES];
         ranges[0] = (base_ipa, page_count);
-        (0u16, receiver_id, ranges, 1usize, page_count)
+        let mut receivers = [(0u16, 0u8); descriptors::MAX_RECEIVERS];
+        receivers[0] = (context.gp_regs.x5 as u16, 0);
+        descriptors::ParsedMemRegion {
+            sender_id: 0,
+            receivers,
+            receiver_count: 1,
+            attributes: context.gp_regs.x6 as u16,
+            flags: 0,
+            ranges,
+            range_count: 1,
+            total_page_count: page_count,
+        }
     };
 
-    // Validate receiver is a known partition (VM or SP)
-    if !is_valid_receiver(receiver_id) {
-        ffa_error(context, FFA_INVALID_PARAMETERS);
-        return true;
+    complete_share(context, vm_id, is_lend, &region, None)
+}
+
+/// Validate a fully received share, transition the sender's pages and
+/// record it. `handle` is the one already handed out for a fragmented
+/// transaction, None to allocate a fresh one.
+fn complete_share(
+    context: &mut VcpuContext,
+    vm_id: usize,
+    is_lend: bool,
+    region: &descriptors::ParsedMemRegion,
+    handle: Option<u64>,
+) -> bool {
+    let receivers = &region.receivers[..region.receiver_count];
+    let ranges = &region.ranges[..region.range_count];
+
+    let mem_attr = match memory::s2_memattr_from_ffa(region.attributes) {
+        Ok(attr) => attr,
+        Err(code) => {
+            ffa_error(context, code);
+            return true;
+        }
+    };
+
+    // Validate receivers are known partitions (VM or SP), each listed once
+    // with a valid access permission encoding
+    for (i, &(receiver_id, permissions)) in receivers.iter().enumerate() {
+        if !is_valid_receiver(receiver_id)
+            || receivers[..i].iter().any(|&(id, _)| id == receiver_id)
+            || memory::s2ap_from_ffa_permissions(permissions).is_err()
+        {
+            ffa_error(context, FFA_INVALID_PARAMETERS);
+            return true;
+        }
     }
 
     // Validate sender matches caller (only for descriptor path where sender is explicit)
     let expected_sender = vm_id_to_partition_id(vm_id);
-    if sender_id_from_desc != 0 && sender_id_from_desc != expected_sender {
+    if region.sender_id != 0 && region.sender_id != expected_sender {
         ffa_error(context, FFA_INVALID_PARAMETERS);
         return true;
     }
@@ -558,9 +666,12 @@ fn handle_mem_share_or_lend(context: &mut VcpuContext, is_lend: bool) -> bool {
     {
         let walker = stage2_walker::Stage2Walker::from_vttbr();
         if walker.has_stage2() {
+            if !memory::ranges_fit_granule(&walker, ranges) {
+                ffa_error(context, FFA_INVALID_PARAMETERS);
+                return true;
+            }
             // Validate: all pages must be in Owned state
-            for i in 0..range_count {
-                let (base_ipa, page_count) = ranges[i];
+            for &(base_ipa, page_count) in ranges {
                 for p in 0..page_count as u64 {
                     let ipa = base_ipa + p * PAGE_SIZE_4KB;
                     match walker.read_sw_bits(ipa) {
@@ -585,12 +696,12 @@ fn handle_mem_share_or_lend(context: &mut VcpuContext, is_lend: bool) -> bool {
             } else {
                 (S2AP_RO >> S2AP_SHIFT) as u8
             };
-            for i in 0..range_count {
-                let (base_ipa, page_count) = ranges[i];
+            for &(base_ipa, page_count) in ranges {
                 for p in 0..page_count as u64 {
                     let ipa = base_ipa + p * PAGE_SIZE_4KB;
                     let _ = walker.write_sw_bits(ipa, new_sw);
                     let _ = walker.set_s2ap(ipa, new_s2ap);
+                    let _ = walker.set_mem_attr(ipa, mem_attr);
                 }
             }
         }
@@ -599,13 +710,26 @@ fn handle_mem_share_or_lend(context: &mut VcpuContext, is_lend: bool) -> bool {
     let sender_id = expected_sender;
 
     // Record the share in stub SPMC
-    let handle = match stub_spmc::record_share(
-        sender_id,
-        receiver_id,
-        &ranges[..range_count],
-        total_page_count,
-        is_lend,
-    ) {
+    let recorded = match handle {
+        Some(h) => stub_spmc::record_share_with_handle(
+            h,
+            sender_id,
+            receivers,
+            ranges,
+            region.total_page_count,
+            is_lend,
+            mem_attr,
+        ),
+        None => stub_spmc::record_share(
+            sender_id,
+            receivers,
+            ranges,
+            region.total_page_count,
+            is_lend,
+            mem_attr,
+        ),
+    };
+    let handle = match recorded {
         Some(h) => h,
         None => {
             ffa_error(context, FFA_NO_MEMORY);
@@ -616,31 +740,77 @@ fn handle_mem_share_or_lend(context: &mut VcpuContext, is_lend: bool) -> bool {
     // Return success with handle
     context.gp_regs.x0 = FFA_SUCCESS_32;
     // Handle is 64-bit, returned in x2 (low) and x3 (high)
-    context.gp_regs.x2 = handle & 0xFFFF_FFFF;
-    context.gp_regs.x3 = handle >> 32;
+    set_mem_handle_result(context, handle);
     true
 }
 
-/// Parse a FF-A v1.1 composite memory region descriptor from the TX buffer.
+/// FFA_MEM_FRAG_TX: Next fragment of a fragmented MEM_SHARE/MEM_LEND.
 ///
-/// Returns (sender_id, receiver_id, ranges, range_count, total_page_count).
+/// Input:  x1 = handle (low 32), x2 = handle (high 32), x3 = fragment length
+/// Output: x0 = FFA_MEM_FRAG_RX while fragments remain, then the
+///         FFA_MEM_SHARE/LEND result (FFA_SUCCESS_32 with handle in x2/x3)
+fn handle_mem_frag_tx(context: &mut VcpuContext) -> bool {
+    let vm_id = crate::global::current_vm_id();
+    let mbox = mailbox::get_mailbox(vm_id);
+    let handle = mem_handle_from_args(context);
+
+    let fragment = match tx_fragment(mbox, context.gp_regs.x3 as u32) {
+        Some(f) if mbox.mapped => f,
+        _ => {
+            ffa_error(context, FFA_INVALID_PARAMETERS);
+            return true;
+        }
+    };
+    match fragments::append(vm_id, handle, fragment) {
+        Ok(progress) => finish_fragment(context, vm_id, progress),
+        Err(code) => {
+            ffa_error(context, code);
+            true
+        }
+    }
+}
+
+/// Report an accepted fragment: FFA_MEM_FRAG_RX with x1/x2 = handle and
+/// x3 = bytes received so far, or complete the share once assembled.
+fn finish_fragment(
+    context: &mut VcpuContext,
+    vm_id: usize,
+    progress: fragments::FragProgress,
+) -> bool {
+    match progress {
+        fragments::FragProgress::Pending { handle, received } => {
+            context.gp_regs.x0 = FFA_MEM_FRAG_RX;
+            set_mem_handle_args(context, handle);
+            context.gp_regs.x3 = received as u64;
+            context.gp_regs.x4 = 0;
+            true
+        }
+        fragments::FragProgress::Complete {
+            handle,
+            is_lend,
+            parsed,
+        } => complete_share(context, vm_id, is_lend, parsed, Some(handle)),
+    }
+}
+
+/// The first `len` bytes of the VM's TX buffer, if they fit in it.
+fn tx_fragment(mbox: &mailbox::FfaMailbox, len: u32) -> Option<&'static [u8]> {
+    if len == 0 || len as u64 > mbox.page_count as u64 * 4096 {
+        return None;
+    }
+    // Identity mapping: IPA == PA, safe to read TX buffer directly at EL2
+    Some(unsafe { core::slice::from_raw_parts(mbox.tx_ipa as *const u8, len as usize) })
+}
+
+/// Parse a FF-A v1.1 composite memory region descriptor from the TX buffer.
 fn parse_share_descriptor(
     context: &VcpuContext,
     mbox: &mailbox::FfaMailbox,
-) -> Result<
-    (
-        u16,
-        u16,
-        [(u64, u32); descriptors::MAX_ADDR_RANGES],
-        usize,
-        u32,
-    ),
-    i32,
-> {
+) -> Result<descriptors::ParsedMemRegion, i32> {
     let total_length = context.gp_regs.x1 as u32;
     let fragment_length = context.gp_regs.x2 as u32;
 
-    // No fragmentation support: entire descriptor must fit in one TX buffer
+    // Single fragment: entire descriptor in one TX buffer
     if total_length != fragment_length || total_length == 0 {
         return Err(FFA_INVALID_PARAMETERS);
     }
@@ -648,15 +818,7 @@ fn parse_share_descriptor(
     // Identity mapping: IPA == PA, safe to read TX buffer directly at EL2
     let tx_ptr = mbox.tx_ipa as *const u8;
 
-    let parsed = unsafe { descriptors::parse_mem_region(tx_ptr, total_length)? };
-
-    Ok((
-        parsed.sender_id,
-        parsed.receiver_id,
-        parsed.ranges,
-        parsed.range_count,
-        parsed.total_page_count,
-    ))
+    unsafe { descriptors::parse_mem_region(tx_ptr, total_length) }
 }
 
 /// FFA_MEM_RECLAIM: Reclaim previously shared/lent memory.
@@ -664,9 +826,24 @@ fn parse_share_descriptor(
 /// Input: x1 = handle (low 32), x2 = handle (high 32), x3 = flags
 /// Output: x0 = FFA_SUCCESS_32 or FFA_ERROR
 ///
-/// Restores page ownership to Owned and S2AP to RW.
+/// Denied while any receiver still has the share retrieved.
+/// A handle of the caller's in-flight fragmented share aborts it.
+/// Counts against the VM's `share_limit` window like MEM_SHARE.
+/// Restores page ownership to Owned, S2AP to RW and MemAttr to Write-back.
 fn handle_mem_reclaim(context: &mut VcpuContext) -> bool {
-    let handle = (context.gp_regs.x1 & 0xFFFF_FFFF) | ((context.gp_regs.x2 & 0xFFFF_FFFF) << 32);
+    let handle = mem_handle_from_args(context);
+
+    // Abandon a fragmented share still being transmitted: nothing recorded
+    // or transitioned yet, just drop the partial descriptor
+    if fragments::abort(crate::global::current_vm_id(), handle) {
+        context.gp_regs.x0 = FFA_SUCCESS_32;
+        return true;
+    }
+
+    if !share_limit::try_acquire(crate::global::current_vm_id()) {
+        ffa_error(context, FFA_BUSY);
+        return true;
+    }
 
     // Look up share record (need IPA info for restoration + retrieved status)
     let info = match stub_spmc::lookup_share_full(handle) {
@@ -677,8 +854,8 @@ fn handle_mem_reclaim(context: &mut VcpuContext) -> bool {
         }
     };
 
-    // Block reclaim while share is still retrieved by receiver
-    if info.retrieved {
+    // Block reclaim until every receiver has relinquished
+    if info.any_retrieved() {
         ffa_error(context, FFA_DENIED);
         return true;
     }
@@ -689,16 +866,7 @@ fn handle_mem_reclaim(context: &mut VcpuContext) -> bool {
     {
         let walker = stage2_walker::Stage2Walker::from_vttbr();
         if walker.has_stage2() {
-            let owned_sw = memory::PageOwnership::Owned as u8;
-            let rw_s2ap = (S2AP_RW >> S2AP_SHIFT) as u8;
-            for i in 0..info.range_count {
-                let (base_ipa, page_count) = info.ranges[i];
-                for p in 0..page_count as u64 {
-                    let ipa = base_ipa + p * PAGE_SIZE_4KB;
-                    let _ = walker.write_sw_bits(ipa, owned_sw);
-                    let _ = walker.set_s2ap(ipa, rw_s2ap);
-                }
-            }
+            memory::restore_owned_ranges(&walker, &info.ranges[..info.range_count]);
         }
     }
 
@@ -713,10 +881,17 @@ fn handle_mem_reclaim(context: &mut VcpuContext) -> bool {
 /// Input: x1 = handle (low 32), x2 = handle (high 32)
 /// Output: x0 = FFA_MEM_RETRIEVE_RESP or FFA_ERROR
 ///
-/// For VM receivers: maps shared pages into receiver's Stage-2 via map_page().
+/// Each receiver of a multi-receiver share retrieves it independently.
+/// For VM receivers: maps shared pages into receiver's Stage-2 with the
+/// share's memory attributes and the receiver's access permissions via
+/// `memory::map_shared_ranges()`.
 /// For SP receivers: returns NOT_SUPPORTED (stub SPMC has no Stage-2).
+///
+/// A receiver that negotiated v1.1+ and has an RX buffer mapped gets the
+/// retrieve-response descriptor in RX, with x1/x2 = total/fragment length.
+/// Otherwise the response is register-only (x1 = 0).
 fn handle_mem_retrieve_req(context: &mut VcpuContext) -> bool {
-    let handle = (context.gp_regs.x1 & 0xFFFF_FFFF) | ((context.gp_regs.x2 & 0xFFFF_FFFF) << 32);
+    let handle = mem_handle_from_args(context);
 
     // Look up the share record
     let info = match stub_spmc::lookup_share_full(handle) {
@@ -727,63 +902,94 @@ fn handle_mem_retrieve_req(context: &mut VcpuContext) -> bool {
         }
     };
 
-    // Verify caller is the designated receiver
+    // Verify caller is one of the receivers and has not retrieved yet
     let vm_id = crate::global::current_vm_id();
     let caller_id = vm_id_to_partition_id(vm_id);
-    if caller_id != info.receiver_id {
-        ffa_error(context, FFA_DENIED);
-        return true;
-    }
+    let permissions = match info.receiver(caller_id) {
+        Some(r) if !r.retrieved => r.permissions,
+        _ => {
+            ffa_error(context, FFA_DENIED);
+            return true;
+        }
+    };
 
-    // Check not already retrieved
-    if info.retrieved {
-        ffa_error(context, FFA_DENIED);
+    // v1.1 receivers with a mailbox get the descriptor in RX; the buffer
+    // must be free before anything is mapped or marked retrieved.
+    let mbox = mailbox::get_mailbox(vm_id);
+    let rx_resp = negotiated_version(vm_id) >= FFA_VERSION_1_1 && mbox.mapped;
+    if rx_resp && !mbox.rx_held_by_proxy {
+        ffa_error(context, FFA_BUSY);
         return true;
     }
 
     // Only VM receivers get Stage-2 mapping; SP receivers are stub-only
-    if is_vm_partition(info.receiver_id) {
+    if is_vm_partition(caller_id) {
         #[cfg(feature = "linux_guest")]
         {
-            let recv_vm_id = partition_id_to_vm_id(info.receiver_id).unwrap();
-            let l0_pa =
-                crate::global::PER_VM_VTTBR[recv_vm_id].load(core::sync::atomic::Ordering::Acquire);
-            if l0_pa != 0 {
-                let walker = stage2_walker::Stage2Walker::new(l0_pa);
-                let s2ap = (S2AP_RW >> S2AP_SHIFT) as u8;
-                let sw = memory::PageOwnership::SharedBorrowed as u8;
-                for i in 0..info.range_count {
-                    let (base_ipa, page_count) = info.ranges[i];
-                    for p in 0..page_count as u64 {
-                        let ipa = base_ipa + p * PAGE_SIZE_4KB;
-                        if let Err(_) = walker.map_page(ipa, s2ap, sw) {
-                            // Rollback: unmap pages we already mapped
-                            // (best effort -- ignore errors on rollback)
-                            for j in 0..=i {
-                                let (rb_ipa, rb_count) = info.ranges[j];
-                                let end = if j == i { p } else { rb_count as u64 };
-                                for k in 0..end {
-                                    let _ = walker.unmap_page(rb_ipa + k * PAGE_SIZE_4KB);
-                                }
-                            }
-                            ffa_error(context, FFA_DENIED);
-                            return true;
-                        }
-                    }
+            let walker = stage2_walker::Stage2Walker::for_vm(vm_id);
+            if walker.has_stage2() {
+                let ranges = &info.ranges[..info.range_count];
+                if let Err(code) =
+                    memory::map_shared_ranges(&walker, ranges, info.mem_attr, permissions)
+                {
+                    ffa_error(context, code);
+                    return true;
                 }
             }
         }
     }
 
-    // Mark as retrieved
-    stub_spmc::mark_retrieved(handle);
+    let mut total_length = 0;
+    if rx_resp {
+        let trans_type = if info.is_lend {
+            FFA_MEM_FLAG_TYPE_LEND
+        } else {
+            FFA_MEM_FLAG_TYPE_SHARE
+        };
+        let resp = descriptors::RetrieveResp {
+            sender_id: info.sender_id,
+            receiver_id: caller_id,
+            attributes: memory::ffa_attrs_from_s2_memattr(info.mem_attr),
+            flags: trans_type << FFA_MEM_FLAG_TYPE_SHIFT,
+            handle,
+            // "Not specified" is mapped RW; report that, otherwise the
+            // permissions the sender granted
+            permissions: if permissions == 0 {
+                FFA_MEM_DATA_ACCESS_RW | FFA_MEM_INST_ACCESS_NX
+            } else {
+                permissions
+            },
+            ranges: &info.ranges[..info.range_count],
+            total_page_count: info.total_page_count,
+        };
+        // rx_ipa was validated in handle_rxtx_map() to be within guest RAM.
+        let capacity = mbox.page_count as usize * 4096;
+        match unsafe { descriptors::write_retrieve_resp(mbox.rx_ipa as *mut u8, capacity, &resp) } {
+            Some(len) => total_length = len,
+            None => {
+                ffa_error(context, FFA_NO_MEMORY);
+                return true;
+            }
+        }
+        // Transfer RX ownership to VM
+        mbox.rx_held_by_proxy = false;
+    }
+
+    // Mark as retrieved by this receiver
+    stub_spmc::mark_retrieved(handle, caller_id);
 
     // Return FFA_MEM_RETRIEVE_RESP
     context.gp_regs.x0 = FFA_MEM_RETRIEVE_RESP;
-    // x1 = total_length (0 for register-based), x2/x3 = handle
-    context.gp_regs.x1 = 0;
-    context.gp_regs.x2 = handle & 0xFFFF_FFFF;
-    context.gp_regs.x3 = handle >> 32;
+    if rx_resp {
+        // x1 = total_length, x2 = fragment_length (never fragmented)
+        context.gp_regs.x1 = total_length as u64;
+        context.gp_regs.x2 = total_length as u64;
+        context.gp_regs.x3 = 0;
+    } else {
+        // x1 = total_length (0 for register-based), x2/x3 = handle
+        context.gp_regs.x1 = 0;
+        set_mem_handle_result(context, handle);
+    }
     true
 }
 
@@ -792,9 +998,11 @@ fn handle_mem_retrieve_req(context: &mut VcpuContext) -> bool {
 /// Input: x1 = handle (low 32), x2 = handle (high 32)
 /// Output: x0 = FFA_SUCCESS_32 or FFA_ERROR
 ///
-/// For VM receivers: unmaps shared pages from receiver's Stage-2 via unmap_page().
+/// Only releases the caller's retrieve; other receivers keep theirs.
+/// For VM receivers: unmaps shared pages from receiver's Stage-2 via
+/// `memory::unmap_shared_ranges()`.
 fn handle_mem_relinquish(context: &mut VcpuContext) -> bool {
-    let handle = (context.gp_regs.x1 & 0xFFFF_FFFF) | ((context.gp_regs.x2 & 0xFFFF_FFFF) << 32);
+    let handle = mem_handle_from_args(context);
 
     // Look up the share record
     let info = match stub_spmc::lookup_share_full(handle) {
@@ -805,42 +1013,27 @@ fn handle_mem_relinquish(context: &mut VcpuContext) -> bool {
         }
     };
 
-    // Verify caller is the designated receiver
+    // Verify caller is one of the receivers and currently has it retrieved
     let vm_id = crate::global::current_vm_id();
     let caller_id = vm_id_to_partition_id(vm_id);
-    if caller_id != info.receiver_id {
-        ffa_error(context, FFA_DENIED);
-        return true;
-    }
-
-    // Must be currently retrieved
-    if !info.retrieved {
+    if !info.receiver(caller_id).is_some_and(|r| r.retrieved) {
         ffa_error(context, FFA_DENIED);
         return true;
     }
 
     // Unmap pages from receiver's Stage-2
-    if is_vm_partition(info.receiver_id) {
+    if is_vm_partition(caller_id) {
         #[cfg(feature = "linux_guest")]
         {
-            let recv_vm_id = partition_id_to_vm_id(info.receiver_id).unwrap();
-            let l0_pa =
-                crate::global::PER_VM_VTTBR[recv_vm_id].load(core::sync::atomic::Ordering::Acquire);
-            if l0_pa != 0 {
-                let walker = stage2_walker::Stage2Walker::new(l0_pa);
-                for i in 0..info.range_count {
-                    let (base_ipa, page_count) = info.ranges[i];
-                    for p in 0..page_count as u64 {
-                        let ipa = base_ipa + p * PAGE_SIZE_4KB;
-                        let _ = walker.unmap_page(ipa);
-                    }
-                }
+            let walker = stage2_walker::Stage2Walker::for_vm(vm_id);
+            if walker.has_stage2() {
+                memory::unmap_shared_ranges(&walker, &info.ranges[..info.range_count]);
             }
         }
     }
 
-    // Mark as relinquished
-    stub_spmc::mark_relinquished(handle);
+    // Mark as relinquished by this receiver
+    stub_spmc::mark_relinquished(handle, caller_id);
 
     context.gp_regs.x0 = FFA_SUCCESS_32;
     true
@@ -860,14 +1053,27 @@ fn handle_spm_id_get(context: &mut VcpuContext) -> bool {
 /// FFA_RUN: Resume execution of a Secure Partition.
 ///
 /// Input: x1[31:16] = target SP ID, x1[15:0] = vCPU ID
-/// In NS-EL2 stub mode: forward to SPMC if present, else NOT_SUPPORTED.
+/// Forwarded to the SPMC if present. Otherwise the stub SP runs: an Idle
+/// SP returns FFA_MSG_WAIT, a Preempted one resumes the caller's direct
+/// request (FFA_INTERRUPT again, or its DIRECT_RESP); Running/Blocked SPs
+/// get BUSY and Aborted ones ABORTED.
 fn handle_run(context: &mut VcpuContext) -> bool {
     if SPMC_PRESENT.load(Ordering::Relaxed) {
-        forward_ffa_to_spmc(context)
-    } else {
-        ffa_error(context, FFA_NOT_SUPPORTED);
-        true
+        return forward_ffa_to_spmc(context);
     }
+    let sp_id = ((context.gp_regs.x1 >> 16) & 0xFFFF) as u16;
+    let vcpu = (context.gp_regs.x1 & 0xFFFF) as u16;
+    let valid_vcpu = stub_spmc::STUB_PARTITIONS
+        .iter()
+        .any(|sp| sp.id == sp_id && vcpu < sp.exec_ctx_count);
+    if !valid_vcpu {
+        ffa_error(context, FFA_INVALID_PARAMETERS);
+        return true;
+    }
+    let caller = vm_id_to_partition_id(crate::global::current_vm_id());
+    let exit = stub_spmc::run(sp_id, caller);
+    write_stub_exit(context, sp_id, exit);
+    true
 }
 
 // ── Notifications ───────────────────────────────────────────────────
@@ -0,0 +1,77 @@
+//! Per-VM rate limit on FF-A memory operations.
+//!
+//! Every MEM_SHARE/MEM_LEND/MEM_RECLAIM rewrites Stage-2 PTEs and
+//! invalidates TLBs (with a cross-core quiesce under multi_pcpu), so a
+//! guest churning shares can slow down co-resident VMs. Each VM may issue
+//! at most `limit` such operations per `SHARE_WINDOW_NS` window; excess
+//! calls get FFA_BUSY and can be retried in the next window.
+
+use crate::ffa::FFA_MAX_VMS;
+use crate::sync::SpinLock;
+use crate::time;
+
+/// Length of one rate-limit window
+pub const SHARE_WINDOW_NS: u64 = 10_000_000; // 10ms
+
+/// Operations per window a VM gets unless `set_limit` says otherwise
+pub const DEFAULT_SHARE_OPS_PER_WINDOW: u32 = 256;
+
+struct ShareWindow {
+    /// Operations allowed per window (0 = unlimited)
+    limit: u32,
+    /// Counter value the current window started at
+    start: u64,
+    /// Operations accepted in the current window
+    count: u32,
+}
+
+impl ShareWindow {
+    const fn new() -> Self {
+        Self {
+            limit: DEFAULT_SHARE_OPS_PER_WINDOW,
+            start: 0,
+            count: 0,
+        }
+    }
+}
+
+static WINDOWS: [SpinLock<ShareWindow>; FFA_MAX_VMS] =
+    [const { SpinLock::new(ShareWindow::new()) }; FFA_MAX_VMS];
+
+/// Set the number of memory operations `vm_id` may issue per window
+/// (0 = unlimited). Starts a fresh window.
+pub fn set_limit(vm_id: usize, ops_per_window: u32) {
+    if let Some(w) = WINDOWS.get(vm_id) {
+        let mut w = w.lock();
+        w.limit = ops_per_window;
+        w.start = time::now_ticks();
+        w.count = 0;
+    }
+}
+
+/// Memory operations per window currently allowed for `vm_id`.
+pub fn limit(vm_id: usize) -> u32 {
+    WINDOWS.get(vm_id).map_or(0, |w| w.lock().limit)
+}
+
+/// Account one memory operation for `vm_id`. Returns false if the VM has
+/// used up its current window; the operation must then be refused.
+pub fn try_acquire(vm_id: usize) -> bool {
+    let Some(w) = WINDOWS.get(vm_id) else {
+        return true;
+    };
+    let mut w = w.lock();
+    if w.limit == 0 {
+        return true;
+    }
+    let now = time::now_ticks();
+    if now.wrapping_sub(w.start) >= time::ns_to_ticks(SHARE_WINDOW_NS) {
+        w.start = now;
+        w.count = 0;
+    }
+    if w.count >= w.limit {
+        return false;
+    }
+    w.count += 1;
+    true
+}
@@ -1,46 +1,103 @@
 //! Lightweight Stage-2 page table walker reconstructed from VTTBR_EL2.
 //!
-//! The `DynamicIdentityMapper` is leaked via `core::mem::forget()` in `vm.rs`,
+//! The `DynamicIdentityMapper` is kept alive via `core::mem::forget()` in `vm.rs`,
 //! so no global reference exists at SMC dispatch time. However, `walk_to_leaf_ptr()`
 //! only uses `self.l0_table` (the L0 page table physical address), which survives
 //! in `VTTBR_EL2` bits [47:1]. This module reconstructs a minimal walker from
 //! that register for page ownership validation during FF-A memory operations.
 
 use crate::arch::aarch64::defs::*;
+use crate::arch::aarch64::mm::mmu::Granule;
+
+/// VTTBR_EL2.VMID field position
+const VTTBR_VMID_SHIFT: u32 = 48;
+/// Largest VMID with 8-bit VMIDs (VTCR_EL2.VS = 0)
+const MAX_VMID: u64 = 0xFF;
 
 /// Lightweight Stage-2 page table walker.
 ///
-/// Does NOT own the page tables — they were leaked by `DynamicIdentityMapper`
-/// and survive for the VM's lifetime.
+/// Does NOT own the page tables — they were forgotten by (not dropped with)
+/// `DynamicIdentityMapper` and survive for the VM's lifetime. Tables this
+/// walker allocates are freed when an owning mapper is dropped.
 pub struct Stage2Walker {
+    /// Root table (L0 for 4KB, L1 for 64KB)
     l0_table: u64,
+    granule: Granule,
 }
 
 impl Stage2Walker {
     /// Reconstruct from current VTTBR_EL2.
     ///
     /// VTTBR_EL2: bits [47:1] = page table base (L0 PA), bits [63:48] = VMID.
-    /// Valid at SMC handling time since we are at EL2 and Stage-2 is active.
+    /// The granule comes from VTCR_EL2.TG0. Valid at SMC handling time since
+    /// we are at EL2 and Stage-2 is active.
+    ///
+    /// A VMID wider than 8 bits (VTCR_EL2.VS is never set) means VTTBR holds
+    /// garbage; the walker then reports `has_stage2() == false`.
     pub fn from_vttbr() -> Self {
         let vttbr: u64;
         unsafe {
             core::arch::asm!("mrs {}, vttbr_el2", out(reg) vttbr, options(nomem, nostack));
         }
-        Self {
-            l0_table: vttbr & PTE_ADDR_MASK,
+        if vttbr >> VTTBR_VMID_SHIFT > MAX_VMID {
+            return Self::new(0);
+        }
+        let vtcr: u64;
+        unsafe {
+            core::arch::asm!("mrs {}, vtcr_el2", out(reg) vtcr, options(nomem, nostack));
         }
+        Self::with_granule(vttbr & PTE_ADDR_MASK, Granule::from_vtcr(vtcr))
+    }
+
+    /// Walker over VM `vm_id`'s Stage-2, from `PER_VM_VTTBR` and the
+    /// granule in `PER_VM_VTCR`. Reports `has_stage2() == false` for an
+    /// unknown VM or one without tables yet.
+    pub fn for_vm(vm_id: usize) -> Self {
+        use core::sync::atomic::Ordering;
+        let (Some(vttbr), Some(vtcr)) = (
+            crate::global::PER_VM_VTTBR.get(vm_id),
+            crate::global::PER_VM_VTCR.get(vm_id),
+        ) else {
+            return Self::new(0);
+        };
+        Self::with_granule(
+            vttbr.load(Ordering::Acquire),
+            Granule::from_vtcr(vtcr.load(Ordering::Acquire)),
+        )
     }
 
     /// Create from an explicit L0 table address (for testing).
     pub fn new(l0_table: u64) -> Self {
-        Self { l0_table }
+        Self::with_granule(l0_table, Granule::Size4KB)
+    }
+
+    /// Create from a root table built with `granule` (see
+    /// `DynamicIdentityMapper::with_granule()`).
+    pub fn with_granule(l0_table: u64, granule: Granule) -> Self {
+        Self { l0_table, granule }
+    }
+
+    /// Granule the tables were built with.
+    pub fn granule(&self) -> Granule {
+        self.granule
+    }
+
+    /// Size of a leaf page (4KB or 64KB).
+    pub fn page_size(&self) -> u64 {
+        self.granule.page_size()
     }
 
     /// Check if a Stage-2 page table is configured.
     ///
-    /// Returns false if L0 table address is 0 (no Stage-2, e.g. unit test mode).
+    /// Returns false if L0 table address is 0 (no Stage-2, e.g. unit test
+    /// mode), or if it is not a granule-aligned table inside the heap that
+    /// page tables are allocated from, so a garbage VTTBR is never walked.
     pub fn has_stage2(&self) -> bool {
+        let table_size = self.granule.page_size();
         self.l0_table != 0
+            && self.l0_table & (table_size - 1) == 0
+            && crate::mm::heap::contains(self.l0_table)
+            && crate::mm::heap::contains(self.l0_table + table_size - 1)
     }
 
     /// Read SW bits [56:55] from the leaf PTE for a given IPA.
@@ -51,8 +108,8 @@ impl Stage2Walker {
 
     /// Write SW bits [56:55] on the leaf PTE for a given IPA.
     ///
-    /// If the IPA is mapped as a 2MB block, the block is split into 512 x 4KB
-    /// page entries first so that only the target page is modified.
+    /// If the IPA is mapped by a 1GB or 2MB block, the block is split into
+    /// 4KB page entries first so that only the target page is modified.
     ///
     /// No TLB invalidation needed — SW bits don't affect hardware translation.
     pub fn write_sw_bits(&self, ipa: u64, bits: u8) -> Result<(), &'static str> {
@@ -74,8 +131,8 @@ impl Stage2Walker {
 
     /// Write S2AP bits [7:6] on the leaf PTE + TLB invalidation.
     ///
-    /// If the IPA is mapped as a 2MB block, the block is split into 512 x 4KB
-    /// page entries first so that only the target page is modified.
+    /// If the IPA is mapped by a 1GB or 2MB block, the block is split into
+    /// 4KB page entries first so that only the target page is modified.
     ///
     /// Unlike SW bits, S2AP affects hardware translation and requires a
     /// TLB invalidation after modification.
@@ -91,6 +148,95 @@ impl Stage2Walker {
         Ok(())
     }
 
+    /// Write-protect the 4KB page at `ipa` for dirty logging.
+    ///
+    /// A writable leaf becomes read-only and is tagged `PTE_DIRTY_LOG`, so
+    /// the guest's next write to it takes a Stage-2 permission fault that
+    /// `dirty_log_unprotect()` resolves. Pages that are already read-only
+    /// are left alone. A 1GB or 2MB block is split first.
+    ///
+    /// Returns whether the page is now protected for dirty logging.
+    pub fn dirty_log_protect(&self, ipa: u64) -> Result<bool, &'static str> {
+        self.split_block_if_needed(ipa)?;
+        let leaf_ptr = self
+            .walk_to_l3_ptr(ipa)
+            .ok_or("IPA not mapped as 4KB page")?;
+        unsafe {
+            let pte = core::ptr::read_volatile(leaf_ptr);
+            if pte & S2AP_MASK != S2AP_RW {
+                return Ok(pte & PTE_DIRTY_LOG != 0);
+            }
+            core::ptr::write_volatile(leaf_ptr, (pte & !S2AP_MASK) | S2AP_RO | PTE_DIRTY_LOG);
+        }
+        Self::tlbi_ipa(ipa);
+        Ok(true)
+    }
+
+    /// Undo `dirty_log_protect()` for the page at `ipa`, restoring write
+    /// access. Returns false if the page was not protected for dirty logging.
+    pub fn dirty_log_unprotect(&self, ipa: u64) -> bool {
+        let Some(leaf_ptr) = self.walk_to_l3_ptr(ipa) else {
+            return false;
+        };
+        unsafe {
+            let pte = core::ptr::read_volatile(leaf_ptr);
+            if pte & PTE_DIRTY_LOG == 0 {
+                return false;
+            }
+            core::ptr::write_volatile(leaf_ptr, (pte & !(S2AP_MASK | PTE_DIRTY_LOG)) | S2AP_RW);
+        }
+        Self::tlbi_ipa(ipa);
+        true
+    }
+
+    /// Whether the page at `ipa` is write-protected by `dirty_log_protect()`.
+    pub fn is_dirty_logged(&self, ipa: u64) -> bool {
+        self.walk_to_leaf(ipa)
+            .is_some_and(|pte| pte & PTE_DIRTY_LOG != 0)
+    }
+
+    /// Read Stage-2 MemAttr bits [5:2] from the leaf PTE for a given IPA.
+    pub fn read_mem_attr(&self, ipa: u64) -> Option<u8> {
+        let pte = self.walk_to_leaf(ipa)?;
+        Some(((pte & S2_MEMATTR_MASK) >> S2_MEMATTR_SHIFT) as u8)
+    }
+
+    /// Write Stage-2 MemAttr bits [5:2] on the leaf PTE.
+    ///
+    /// If the IPA is mapped by a 1GB or 2MB block, the block is split first. A
+    /// memory-type change needs break-before-make, and when the page stops
+    /// being Write-back its dirty lines are cleaned to PoC first so the
+    /// uncached view sees current data.
+    pub fn set_mem_attr(&self, ipa: u64, mem_attr: u8) -> Result<(), &'static str> {
+        self.split_block_if_needed(ipa)?;
+        let leaf_ptr = self.walk_to_leaf_ptr(ipa).ok_or("IPA not mapped")?;
+        unsafe {
+            let pte = core::ptr::read_volatile(leaf_ptr);
+            let new = (pte & !S2_MEMATTR_MASK) | (((mem_attr as u64) & 0xF) << S2_MEMATTR_SHIFT);
+            if new == pte {
+                return Ok(());
+            }
+            if mem_attr != S2_MEMATTR_NORMAL_WB {
+                let page = self.granule.page_size();
+                Self::clean_inval_page(ipa & !(page - 1), page);
+            }
+            core::ptr::write_volatile(leaf_ptr, 0u64);
+            Self::tlbi_ipa(ipa);
+            core::ptr::write_volatile(leaf_ptr, new);
+        }
+        Self::tlbi_ipa(ipa);
+        Ok(())
+    }
+
+    /// Translate an IPA to the PA it maps to, or `None` if unmapped.
+    pub fn translate(&self, ipa: u64) -> Option<u64> {
+        let (leaf_ptr, level) = self.walk(ipa)?;
+        let pte = unsafe { core::ptr::read_volatile(leaf_ptr) };
+        // L3 page, L2 block or L1 block
+        let offset_mask = (1u64 << self.granule.level_shift(level)) - 1;
+        Some((pte & PTE_ADDR_MASK & !offset_mask) | (ipa & offset_mask))
+    }
+
     /// Walk page table to the leaf PTE value.
     fn walk_to_leaf(&self, ipa: u64) -> Option<u64> {
         let ptr = self.walk_to_leaf_ptr(ipa)?;
@@ -102,47 +248,31 @@ impl Stage2Walker {
     /// Duplicated from `DynamicIdentityMapper::walk_to_leaf_ptr()` (mmu.rs).
     /// The walk logic only uses `self.l0_table`, making this reconstruction safe.
     fn walk_to_leaf_ptr(&self, ipa: u64) -> Option<*mut u64> {
-        // L0
-        let l0_idx = ((ipa >> 39) & PT_INDEX_MASK) as usize;
-        let l0_entry = unsafe { *(self.l0_table as *const u64).add(l0_idx) };
-        if l0_entry & (PTE_VALID | PTE_TABLE) != (PTE_VALID | PTE_TABLE) {
-            return None;
-        }
-
-        // L1
-        let l1_table = l0_entry & PTE_ADDR_MASK;
-        let l1_idx = ((ipa >> 30) & PT_INDEX_MASK) as usize;
-        let l1_entry = unsafe { *(l1_table as *const u64).add(l1_idx) };
-        if l1_entry & PTE_VALID == 0 {
-            return None;
-        }
-        // L1 block (1GB)
-        if l1_entry & PTE_TABLE == 0 {
-            return Some(unsafe { (l1_table as *mut u64).add(l1_idx) });
-        }
+        self.walk(ipa).map(|(ptr, _)| ptr)
+    }
 
-        // L2
-        let l2_table = l1_entry & PTE_ADDR_MASK;
-        let l2_idx = ((ipa >> 21) & PT_INDEX_MASK) as usize;
-        let l2_ptr = unsafe { (l2_table as *mut u64).add(l2_idx) };
-        let l2_entry = unsafe { core::ptr::read_volatile(l2_ptr) };
-        if l2_entry & PTE_VALID == 0 {
+    /// Walk to the leaf entry for `ipa`, returning its pointer and level.
+    ///
+    /// The granule's start level only holds table descriptors; a leaf is an
+    /// L1/L2 block or an L3 page.
+    fn walk(&self, ipa: u64) -> Option<(*mut u64, usize)> {
+        if !self.has_stage2() {
             return None;
         }
-        // L2 block (2MB)
-        if l2_entry & PTE_TABLE == 0 {
-            return Some(l2_ptr);
-        }
-
-        // L3 (4KB page)
-        let l3_table = l2_entry & PTE_ADDR_MASK;
-        let l3_idx = ((ipa >> 12) & PT_INDEX_MASK) as usize;
-        let l3_ptr = unsafe { (l3_table as *mut u64).add(l3_idx) };
-        let l3_entry = unsafe { core::ptr::read_volatile(l3_ptr) };
-        if l3_entry & PTE_VALID == 0 {
-            return None;
+        let g = self.granule;
+        let mut table = self.l0_table;
+        for level in g.start_level()..=3 {
+            let ptr = unsafe { (table as *mut u64).add(g.index(ipa, level)) };
+            let entry = unsafe { core::ptr::read_volatile(ptr) };
+            if entry & PTE_VALID == 0 {
+                return None;
+            }
+            if level == 3 || entry & PTE_TABLE == 0 {
+                return (level != g.start_level()).then_some((ptr, level));
+            }
+            table = entry & PTE_ADDR_MASK;
         }
-        Some(l3_ptr)
+        None
     }
 
     /// Create a 4KB page mapping in this VM's Stage-2 at the given IPA.
@@ -165,26 +295,58 @@ impl Stage2Walker {
     /// - Heap allocation fails
     #[allow(dead_code)]
     pub fn map_page(&self, ipa: u64, s2ap: u8, sw_bits: u8) -> Result<(), &'static str> {
-        // L0: must be a valid table descriptor (L0->L1 link from DynamicIdentityMapper)
-        let l0_idx = ((ipa >> 39) & PT_INDEX_MASK) as usize;
-        let l0_entry =
-            unsafe { core::ptr::read_volatile((self.l0_table as *const u64).add(l0_idx)) };
-        if l0_entry & (PTE_VALID | PTE_TABLE) != (PTE_VALID | PTE_TABLE) {
-            return Err("L0 entry not a valid table");
+        self.map_page_with_attr(ipa, s2ap, sw_bits, S2_MEMATTR_NORMAL_WB)
+    }
+
+    /// Like `map_page()`, with an explicit Stage-2 MemAttr (e.g.
+    /// `S2_MEMATTR_NORMAL_NC` for an uncached FF-A shared buffer).
+    pub fn map_page_with_attr(
+        &self,
+        ipa: u64,
+        s2ap: u8,
+        sw_bits: u8,
+        mem_attr: u8,
+    ) -> Result<(), &'static str> {
+        let page_mask = self.granule.page_size() - 1;
+        self.map_page_to(ipa, ipa & !page_mask, s2ap, sw_bits, mem_attr)
+    }
+
+    /// Like `map_page_with_attr()`, but maps `ipa` to an arbitrary host `pa`
+    /// instead of identity (e.g. a hypervisor-owned shared buffer page).
+    pub fn map_page_to(
+        &self,
+        ipa: u64,
+        pa: u64,
+        s2ap: u8,
+        sw_bits: u8,
+        mem_attr: u8,
+    ) -> Result<(), &'static str> {
+        if !self.has_stage2() {
+            return Err("No valid Stage-2 table");
         }
-        let l1_table = l0_entry & PTE_ADDR_MASK;
+        let g = self.granule;
+        // L0: must be a valid table descriptor (L0->L1 link from DynamicIdentityMapper).
+        // The 64KB granule starts at L1, so the root is the L1 table.
+        let l1_table = if g.start_level() == 0 {
+            let l0_idx = g.index(ipa, 0);
+            let l0_entry =
+                unsafe { core::ptr::read_volatile((self.l0_table as *const u64).add(l0_idx)) };
+            if l0_entry & (PTE_VALID | PTE_TABLE) != (PTE_VALID | PTE_TABLE) {
+                return Err("L0 entry not a valid table");
+            }
+            l0_entry & PTE_ADDR_MASK
+        } else {
+            self.l0_table
+        };
 
         // L1: get or create L2 table
-        let l1_idx = ((ipa >> 30) & PT_INDEX_MASK) as usize;
+        let l1_idx = g.index(ipa, 1);
         let l1_ptr = unsafe { (l1_table as *mut u64).add(l1_idx) };
         let l1_entry = unsafe { core::ptr::read_volatile(l1_ptr) };
 
         let l2_table = if l1_entry & PTE_VALID == 0 {
             // L1 entry invalid: allocate a new L2 table
-            let l2 = crate::mm::heap::alloc_page().ok_or("Failed to allocate L2 table")?;
-            unsafe {
-                core::ptr::write_bytes(l2 as *mut u8, 0, PAGE_SIZE_4KB as usize);
-            }
+            let l2 = g.alloc_table().ok_or("Failed to allocate L2 table")?;
             let l1_desc = l2 | PTE_VALID | PTE_TABLE;
             unsafe {
                 core::ptr::write_volatile(l1_ptr, l1_desc);
@@ -199,16 +361,13 @@ impl Stage2Walker {
         };
 
         // L2: get or create L3 table
-        let l2_idx = ((ipa >> 21) & PT_INDEX_MASK) as usize;
+        let l2_idx = g.index(ipa, 2);
         let l2_ptr = unsafe { (l2_table as *mut u64).add(l2_idx) };
         let l2_entry = unsafe { core::ptr::read_volatile(l2_ptr) };
 
         let l3_table = if l2_entry & PTE_VALID == 0 {
             // L2 entry invalid: allocate a new L3 table
-            let l3 = crate::mm::heap::alloc_page().ok_or("Failed to allocate L3 table")?;
-            unsafe {
-                core::ptr::write_bytes(l3 as *mut u8, 0, PAGE_SIZE_4KB as usize);
-            }
+            let l3 = g.alloc_table().ok_or("Failed to allocate L3 table")?;
             let l2_desc = l3 | PTE_VALID | PTE_TABLE;
             unsafe {
                 core::ptr::write_volatile(l2_ptr, l2_desc);
@@ -223,7 +382,7 @@ impl Stage2Walker {
         };
 
         // L3: write page entry (must not already be mapped)
-        let l3_idx = ((ipa >> 12) & PT_INDEX_MASK) as usize;
+        let l3_idx = g.index(ipa, 3);
         let l3_ptr = unsafe { (l3_table as *mut u64).add(l3_idx) };
         let l3_entry = unsafe { core::ptr::read_volatile(l3_ptr) };
         if l3_entry & PTE_VALID != 0 {
@@ -231,13 +390,14 @@ impl Stage2Walker {
         }
 
         // Build the L3 page descriptor:
-        //   PA (identity-mapped) | MemAttrIndx=0b1111 | SH=Inner | AF=1 | S2AP | SW | Valid+Page
-        // Normal memory base attrs (without S2AP): MemAttrIndx[5:2]=0b1111, SH[9:8]=0b11, AF[10]=1
-        let normal_attrs: u64 = (0b1111 << 2) | (0b11 << 8) | (1 << 10);
+        //   PA | MemAttr | SH=Inner | AF=1 | S2AP | SW | Valid+Page
+        // Base attrs (without S2AP): MemAttr[5:2], SH[9:8]=0b11, AF[10]=1
+        let base_attrs: u64 =
+            (((mem_attr as u64) & 0xF) << S2_MEMATTR_SHIFT) | (0b11 << 8) | (1 << 10);
         let s2ap_bits = ((s2ap as u64) & 0x3) << S2AP_SHIFT;
         let sw = ((sw_bits as u64) & 0x3) << PTE_SW_SHIFT;
-        let pa = ipa & !PAGE_MASK_4KB;
-        let page_entry = pa | normal_attrs | s2ap_bits | sw | PTE_TABLE | PTE_VALID;
+        let pa = pa & !(g.page_size() - 1);
+        let page_entry = pa | base_attrs | s2ap_bits | sw | PTE_TABLE | PTE_VALID;
         unsafe {
             core::ptr::write_volatile(l3_ptr, page_entry);
         }
@@ -271,127 +431,114 @@ impl Stage2Walker {
         Ok(())
     }
 
+    /// Remove a 4KB page mapping, first splitting a 1GB or 2MB block that
+    /// covers `ipa` so the rest of the block stays mapped.
+    ///
+    /// # Errors
+    /// Returns an error if the IPA is unmapped.
+    pub fn unmap_4kb_page(&self, ipa: u64) -> Result<(), &'static str> {
+        self.split_block_if_needed(ipa)?;
+        self.unmap_page(ipa)
+    }
+
     /// Walk page table to the L3 PTE pointer for a given IPA.
     ///
     /// Unlike `walk_to_leaf_ptr()`, this only returns a pointer if the walk
     /// reaches a valid L3 page entry. Returns `None` for 2MB blocks, 1GB blocks,
     /// or unmapped IPAs.
     fn walk_to_l3_ptr(&self, ipa: u64) -> Option<*mut u64> {
-        // L0
-        let l0_idx = ((ipa >> 39) & PT_INDEX_MASK) as usize;
-        let l0_entry =
-            unsafe { core::ptr::read_volatile((self.l0_table as *const u64).add(l0_idx)) };
-        if l0_entry & (PTE_VALID | PTE_TABLE) != (PTE_VALID | PTE_TABLE) {
-            return None;
-        }
-
-        // L1: must be a table descriptor (not a 1GB block)
-        let l1_table = l0_entry & PTE_ADDR_MASK;
-        let l1_idx = ((ipa >> 30) & PT_INDEX_MASK) as usize;
-        let l1_entry = unsafe { core::ptr::read_volatile((l1_table as *const u64).add(l1_idx)) };
-        if l1_entry & (PTE_VALID | PTE_TABLE) != (PTE_VALID | PTE_TABLE) {
-            return None;
-        }
-
-        // L2: must be a table descriptor (not a 2MB block)
-        let l2_table = l1_entry & PTE_ADDR_MASK;
-        let l2_idx = ((ipa >> 21) & PT_INDEX_MASK) as usize;
-        let l2_entry = unsafe { core::ptr::read_volatile((l2_table as *const u64).add(l2_idx)) };
-        if l2_entry & (PTE_VALID | PTE_TABLE) != (PTE_VALID | PTE_TABLE) {
-            return None;
-        }
-
-        // L3: must be a valid page entry
-        let l3_table = l2_entry & PTE_ADDR_MASK;
-        let l3_idx = ((ipa >> 12) & PT_INDEX_MASK) as usize;
-        let l3_ptr = unsafe { (l3_table as *mut u64).add(l3_idx) };
-        let l3_entry = unsafe { core::ptr::read_volatile(l3_ptr) };
-        if l3_entry & PTE_VALID == 0 {
-            return None;
-        }
-
-        Some(l3_ptr)
+        self.walk(ipa)
+            .filter(|&(_, level)| level == 3)
+            .map(|(ptr, _)| ptr)
     }
 
-    /// If the IPA is mapped as a 2MB block at L2, split the block into
-    /// 512 x 4KB page entries so that individual pages can be modified.
+    /// If the IPA is mapped by a 1GB L1 block or a 2MB L2 block, split it
+    /// down to L3 page entries so that individual pages can be modified.
     ///
     /// No-op if the IPA is already mapped as a 4KB page or via an L3 table.
     fn split_block_if_needed(&self, ipa: u64) -> Result<(), &'static str> {
-        // Walk L0 → L1 → L2 to check the L2 entry
-        let l0_idx = ((ipa >> 39) & PT_INDEX_MASK) as usize;
-        let l0_entry =
-            unsafe { core::ptr::read_volatile((self.l0_table as *const u64).add(l0_idx)) };
-        if l0_entry & (PTE_VALID | PTE_TABLE) != (PTE_VALID | PTE_TABLE) {
-            return Ok(()); // Not mapped — walk_to_leaf_ptr will handle the error
-        }
-
-        let l1_table = l0_entry & PTE_ADDR_MASK;
-        let l1_idx = ((ipa >> 30) & PT_INDEX_MASK) as usize;
-        let l1_entry =
-            unsafe { core::ptr::read_volatile((l1_table as *const u64).add(l1_idx)) };
-        if l1_entry & PTE_VALID == 0 || l1_entry & PTE_TABLE == 0 {
-            return Ok(()); // Invalid or 1GB block — not our concern here
+        if !self.has_stage2() {
+            return Err("No valid Stage-2 table");
         }
-
-        let l2_table = l1_entry & PTE_ADDR_MASK;
-        let l2_idx = ((ipa >> 21) & PT_INDEX_MASK) as usize;
-        let l2_ptr = unsafe { (l2_table as *mut u64).add(l2_idx) };
-        let l2_entry = unsafe { core::ptr::read_volatile(l2_ptr) };
-
-        // Only split if L2 is a valid block (bit[0]=1, bit[1]=0)
-        if l2_entry & PTE_VALID != 0 && l2_entry & PTE_TABLE == 0 {
-            Self::split_2mb_block_at_l2(l2_ptr, l2_entry)?;
+        let g = self.granule;
+        let mut table = self.l0_table;
+        for level in g.start_level()..=2 {
+            let ptr = unsafe { (table as *mut u64).add(g.index(ipa, level)) };
+            let mut entry = unsafe { core::ptr::read_volatile(ptr) };
+            if entry & PTE_VALID == 0 {
+                // Not mapped — walk_to_leaf_ptr will handle it
+                return Ok(());
+            }
+            if entry & PTE_TABLE == 0 {
+                if level == g.start_level() {
+                    // No blocks at the root level
+                    return Ok(());
+                }
+                // Block (bit[0]=1, bit[1]=0): split one level down
+                entry = self.split_block_at(ptr, level, entry)?;
+            }
+            table = entry & PTE_ADDR_MASK;
         }
 
         Ok(())
     }
 
-    /// Split a 2MB block entry at L2 into 512 x 4KB page entries.
+    /// Split a block entry at `level` into a next-level table covering the
+    /// same range: a 1GB L1 block into 512 x 2MB L2 blocks, or an L2 block
+    /// into L3 pages (512 x 4KB, or 8192 x 64KB with the 64KB granule).
+    /// Returns the new table descriptor.
     ///
     /// Uses break-before-make protocol (required by ARM architecture):
-    /// invalidate L2 → TLB flush → write new table descriptor → TLB flush.
+    /// invalidate entry → TLB flush → write new table descriptor → TLB flush.
     ///
-    /// Based on `DynamicIdentityMapper::split_2mb_block()` (mmu.rs).
-    fn split_2mb_block_at_l2(l2_ptr: *mut u64, block_entry: u64) -> Result<(), &'static str> {
-        let block_pa = block_entry & !BLOCK_MASK_2MB;
+    /// Based on `DynamicIdentityMapper::split_block()` (mmu.rs).
+    fn split_block_at(
+        &self,
+        ptr: *mut u64,
+        level: usize,
+        block_entry: u64,
+    ) -> Result<u64, &'static str> {
+        let g = self.granule;
+        let block_mask = (1u64 << g.level_shift(level)) - 1;
+        let child_size = 1u64 << g.level_shift(level + 1);
+        let block_pa = block_entry & !block_mask;
         // Extract attribute bits from the block entry, stripping valid+type bits [1:0]
-        let block_attr_bits = block_entry & BLOCK_MASK_2MB & !0x3;
+        let block_attr_bits = block_entry & block_mask & !0x3;
         // Preserve SW bits [56:55] from the block entry
         let block_sw_bits = block_entry & PTE_SW_MASK;
+        // bit[1] = 1 marks an L3 page; at L2 it must be 0 (block)
+        let child_type = if level + 1 == 3 { PTE_TABLE } else { 0 };
 
-        // Allocate L3 table (4KB, holds 512 page entries)
-        let l3 =
-            crate::mm::heap::alloc_page().ok_or("Failed to allocate L3 table for block split")?;
-        unsafe {
-            core::ptr::write_bytes(l3 as *mut u8, 0, PAGE_SIZE_4KB as usize);
-        }
+        // Allocate the next-level table (one granule page of entries)
+        let next = g
+            .alloc_table()
+            .ok_or("Failed to allocate table for block split")?;
 
-        // Fill L3 with 512 page entries preserving original block attributes
-        // L3 page descriptor: [PA | SW bits | attrs | bit1=1(page) | bit0=1(valid)]
+        // Fill it with entries preserving original block attributes
+        // [PA | SW bits | attrs | bit1=type | bit0=1(valid)]
         unsafe {
-            let l3_ptr = l3 as *mut u64;
-            for i in 0..512u64 {
-                let pa = block_pa + i * PAGE_SIZE_4KB;
-                let page = pa | block_sw_bits | block_attr_bits | PTE_TABLE | PTE_VALID;
-                *l3_ptr.add(i as usize) = page;
+            let next_ptr = next as *mut u64;
+            for i in 0..1u64 << g.index_bits() {
+                let pa = block_pa + i * child_size;
+                let child = pa | block_sw_bits | block_attr_bits | child_type | PTE_VALID;
+                *next_ptr.add(i as usize) = child;
             }
         }
 
-        // Break-before-make: invalidate old L2 block entry
+        // Break-before-make: invalidate old block entry
         unsafe {
-            core::ptr::write_volatile(l2_ptr, 0u64);
+            core::ptr::write_volatile(ptr, 0u64);
         }
         Self::tlbi_all();
 
-        // Write new L2 table descriptor pointing to L3
-        let l2_desc = l3 | PTE_VALID | PTE_TABLE;
+        // Write new table descriptor
+        let desc = next | PTE_VALID | PTE_TABLE;
         unsafe {
-            core::ptr::write_volatile(l2_ptr, l2_desc);
+            core::ptr::write_volatile(ptr, desc);
         }
         Self::tlbi_all();
 
-        Ok(())
+        Ok(desc)
     }
 
     /// Invalidate all Stage-2 TLB entries (all VMIDs, all IPAs).
@@ -407,6 +554,26 @@ impl Stage2Walker {
         }
     }
 
+    /// Clean and invalidate one `size`-byte page to PoC by VA (identity-mapped at EL2).
+    fn clean_inval_page(pa: u64, size: u64) {
+        let ctr: u64;
+        unsafe {
+            core::arch::asm!("mrs {}, ctr_el0", out(reg) ctr, options(nomem, nostack));
+        }
+        // CTR_EL0.DminLine [19:16]: log2(words) of the smallest D-cache line
+        let line = 4u64 << ((ctr >> 16) & 0xF);
+        let mut addr = pa;
+        while addr < pa + size {
+            unsafe {
+                core::arch::asm!("dc civac, {}", in(reg) addr, options(nostack));
+            }
+            addr += line;
+        }
+        unsafe {
+            core::arch::asm!("dsb ish", options(nostack));
+        }
+    }
+
     /// Invalidate a single IPA from Stage-2 TLB.
     fn tlbi_ipa(ipa: u64) {
         let ipa_shifted = (ipa >> 12) & 0x0000_00FF_FFFF_FFFF;
@@ -4,6 +4,11 @@
 use core::cell::UnsafeCell;
 use core::sync::atomic::{AtomicU64, Ordering};
 
+use crate::arch::aarch64::defs::S2_MEMATTR_NORMAL_WB;
+use crate::ffa::{FFA_ABORTED, FFA_BUSY, FFA_DENIED, FFA_INVALID_PARAMETERS};
+use crate::sp_context::SpState;
+use crate::sync::SpinLock;
+
 /// Simulated secure partition info.
 pub struct StubPartition {
     pub id: u16,
@@ -32,14 +37,35 @@ pub static STUB_PARTITIONS: [StubPartition; 2] = [
 /// Handle count for memory sharing.
 static NEXT_HANDLE: AtomicU64 = AtomicU64::new(1);
 
-/// Maximum address ranges per share record.
-pub const MAX_SHARE_RANGES: usize = 4;
+/// Maximum address ranges per share record (a full parsed descriptor).
+pub const MAX_SHARE_RANGES: usize = crate::ffa::descriptors::MAX_ADDR_RANGES;
+
+/// Maximum receivers per share record (a full parsed descriptor).
+pub const MAX_SHARE_RECEIVERS: usize = crate::ffa::descriptors::MAX_RECEIVERS;
+
+/// One receiver of a share; each retrieves and relinquishes on its own.
+#[derive(Clone, Copy)]
+pub struct ShareReceiver {
+    pub id: u16,
+    /// Memory access permissions from the sender's descriptor (0 = not
+    /// specified)
+    pub permissions: u8,
+    /// Whether this receiver has called FFA_MEM_RETRIEVE_REQ.
+    pub retrieved: bool,
+}
+
+const NO_RECEIVER: ShareReceiver = ShareReceiver {
+    id: 0,
+    permissions: 0,
+    retrieved: false,
+};
 
 /// Memory share record.
 pub struct MemShareRecord {
     pub handle: u64,
     pub sender_id: u16,
-    pub receiver_id: u16,
+    pub receivers: [ShareReceiver; MAX_SHARE_RECEIVERS],
+    pub receiver_count: usize,
     /// Address ranges: (base_ipa, page_count) per range.
     pub ranges: [(u64, u32); MAX_SHARE_RANGES],
     pub range_count: usize,
@@ -47,16 +73,27 @@ pub struct MemShareRecord {
     pub active: bool,
     /// True for MEM_LEND (S2AP=NONE), false for MEM_SHARE (S2AP=RO).
     pub is_lend: bool,
-    /// Whether receiver has called FFA_MEM_RETRIEVE_REQ.
-    pub retrieved: bool,
+    /// Stage-2 MemAttr both sides map the region with.
+    pub mem_attr: u8,
+}
+
+impl MemShareRecord {
+    fn has_receiver(&self, part_id: u16) -> bool {
+        self.receivers[..self.receiver_count]
+            .iter()
+            .any(|r| r.id == part_id)
+    }
 }
 
+/// Maximum number of outstanding share records. A share beyond this fails
+/// with FFA_NO_MEMORY until an earlier one is reclaimed.
+pub const MAX_SHARES: usize = 16;
+
 /// Fixed-size array of share records (no alloc).
 ///
 /// Uses UnsafeCell for interior mutability. Access is safe: in single-pCPU modes,
 /// only one exception handler runs at a time. In multi-pCPU mode, share records
 /// are accessed under the FF-A proxy dispatch (one SMC at a time per VM).
-const MAX_SHARES: usize = 16;
 struct ShareRecordArray(UnsafeCell<[MemShareRecord; MAX_SHARES]>);
 unsafe impl Sync for ShareRecordArray {}
 
@@ -64,18 +101,16 @@ static SHARE_RECORDS: ShareRecordArray = ShareRecordArray(UnsafeCell::new({
     const EMPTY: MemShareRecord = MemShareRecord {
         handle: 0,
         sender_id: 0,
-        receiver_id: 0,
+        receivers: [NO_RECEIVER; MAX_SHARE_RECEIVERS],
+        receiver_count: 0,
         ranges: [(0, 0); MAX_SHARE_RANGES],
         range_count: 0,
         total_page_count: 0,
         active: false,
         is_lend: false,
-        retrieved: false,
+        mem_attr: S2_MEMATTR_NORMAL_WB,
     };
-    [
-        EMPTY, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY,
-        EMPTY, EMPTY, EMPTY,
-    ]
+    [EMPTY; MAX_SHARES]
 }));
 
 /// Allocate a new memory sharing handle.
@@ -83,33 +118,101 @@ pub fn alloc_handle() -> u64 {
     NEXT_HANDLE.fetch_add(1, Ordering::Relaxed)
 }
 
-/// Record a memory share and return the handle.
+/// Number of share records the stub SPMC can hold.
+pub fn share_capacity() -> usize {
+    MAX_SHARES
+}
+
+/// Number of share records currently outstanding.
+pub fn share_count() -> usize {
+    let records = unsafe { &*SHARE_RECORDS.0.get() };
+    records.iter().filter(|r| r.active).count()
+}
+
+/// Record a memory share with `receivers` given as (receiver_id,
+/// permissions) and return the handle.
+///
+/// Takes the first free slot, so slots released by `reclaim_share()` are
+/// reused. Returns None (no handle consumed) when the table is full.
 pub fn record_share(
     sender_id: u16,
-    receiver_id: u16,
+    receivers: &[(u16, u8)],
     ranges: &[(u64, u32)],
     total_page_count: u32,
     is_lend: bool,
+    mem_attr: u8,
+) -> Option<u64> {
+    insert_share(
+        alloc_handle,
+        sender_id,
+        receivers,
+        ranges,
+        total_page_count,
+        is_lend,
+        mem_attr,
+    )
+}
+
+/// Record a memory share under a handle allocated earlier with
+/// `alloc_handle()` (a fragmented share, handed out at its first fragment).
+pub fn record_share_with_handle(
+    handle: u64,
+    sender_id: u16,
+    receivers: &[(u16, u8)],
+    ranges: &[(u64, u32)],
+    total_page_count: u32,
+    is_lend: bool,
+    mem_attr: u8,
+) -> Option<u64> {
+    insert_share(
+        || handle,
+        sender_id,
+        receivers,
+        ranges,
+        total_page_count,
+        is_lend,
+        mem_attr,
+    )
+}
+
+fn insert_share(
+    handle: impl FnOnce() -> u64,
+    sender_id: u16,
+    receivers: &[(u16, u8)],
+    ranges: &[(u64, u32)],
+    total_page_count: u32,
+    is_lend: bool,
+    mem_attr: u8,
 ) -> Option<u64> {
-    let handle = alloc_handle();
     let records = unsafe { &mut *SHARE_RECORDS.0.get() };
     for record in records.iter_mut() {
         if !record.active {
+            let handle = handle();
             let mut stored_ranges = [(0u64, 0u32); MAX_SHARE_RANGES];
             let count = ranges.len().min(MAX_SHARE_RANGES);
             for (i, &r) in ranges.iter().take(count).enumerate() {
                 stored_ranges[i] = r;
             }
+            let mut stored_receivers = [NO_RECEIVER; MAX_SHARE_RECEIVERS];
+            let receiver_count = receivers.len().min(MAX_SHARE_RECEIVERS);
+            for (i, &(id, permissions)) in receivers.iter().take(receiver_count).enumerate() {
+                stored_receivers[i] = ShareReceiver {
+                    id,
+                    permissions,
+                    retrieved: false,
+                };
+            }
             *record = MemShareRecord {
                 handle,
                 sender_id,
-                receiver_id,
+                receivers: stored_receivers,
+                receiver_count,
                 ranges: stored_ranges,
                 range_count: count,
                 total_page_count,
                 active: true,
                 is_lend,
-                retrieved: false,
+                mem_attr,
             };
             return Some(handle);
         }
@@ -141,58 +244,85 @@ pub fn lookup_share(handle: u64) -> Option<ShareInfo> {
     None
 }
 
-/// Extended share record info (includes sender/receiver/retrieved state).
+/// Extended share record info (includes sender/receivers/retrieved state).
 pub struct ShareInfoFull {
     pub sender_id: u16,
-    pub receiver_id: u16,
+    pub receivers: [ShareReceiver; MAX_SHARE_RECEIVERS],
+    pub receiver_count: usize,
     pub ranges: [(u64, u32); MAX_SHARE_RANGES],
     pub range_count: usize,
     pub total_page_count: u32,
     pub is_lend: bool,
-    pub retrieved: bool,
+    pub mem_attr: u8,
 }
 
-/// Look up a share record by handle, returning full info including sender/receiver.
+impl ShareInfoFull {
+    /// The share's receivers.
+    pub fn receivers(&self) -> &[ShareReceiver] {
+        &self.receivers[..self.receiver_count]
+    }
+
+    /// Receiver entry of `part_id`, if it is one of the share's receivers.
+    pub fn receiver(&self, part_id: u16) -> Option<&ShareReceiver> {
+        self.receivers().iter().find(|r| r.id == part_id)
+    }
+
+    /// Whether any receiver still has the share retrieved.
+    pub fn any_retrieved(&self) -> bool {
+        self.receivers().iter().any(|r| r.retrieved)
+    }
+}
+
+/// Look up a share record by handle, returning full info including sender/receivers.
 pub fn lookup_share_full(handle: u64) -> Option<ShareInfoFull> {
     let records = unsafe { &*SHARE_RECORDS.0.get() };
     for record in records.iter() {
         if record.active && record.handle == handle {
             return Some(ShareInfoFull {
                 sender_id: record.sender_id,
-                receiver_id: record.receiver_id,
+                receivers: record.receivers,
+                receiver_count: record.receiver_count,
                 ranges: record.ranges,
                 range_count: record.range_count,
                 total_page_count: record.total_page_count,
                 is_lend: record.is_lend,
-                retrieved: record.retrieved,
+                mem_attr: record.mem_attr,
             });
         }
     }
     None
 }
 
-/// Mark a share as retrieved. Returns true if found and was not already retrieved.
-pub fn mark_retrieved(handle: u64) -> bool {
+/// Set the retrieved state of receiver `receiver_id` of share `handle`.
+/// Returns true if found and the state changed.
+fn set_retrieved(handle: u64, receiver_id: u16, retrieved: bool) -> bool {
     let records = unsafe { &mut *SHARE_RECORDS.0.get() };
-    for record in records.iter_mut() {
-        if record.active && record.handle == handle && !record.retrieved {
-            record.retrieved = true;
-            return true;
+    let Some(record) = records.iter_mut().find(|r| r.active && r.handle == handle) else {
+        return false;
+    };
+    let count = record.receiver_count;
+    match record.receivers[..count]
+        .iter_mut()
+        .find(|r| r.id == receiver_id)
+    {
+        Some(r) if r.retrieved != retrieved => {
+            r.retrieved = retrieved;
+            true
         }
+        _ => false,
     }
-    false
 }
 
-/// Mark a share as relinquished (not retrieved). Returns true if found and was retrieved.
-pub fn mark_relinquished(handle: u64) -> bool {
-    let records = unsafe { &mut *SHARE_RECORDS.0.get() };
-    for record in records.iter_mut() {
-        if record.active && record.handle == handle && record.retrieved {
-            record.retrieved = false;
-            return true;
-        }
-    }
-    false
+/// Mark a share as retrieved by `receiver_id`. Returns true if it is one of
+/// the share's receivers and had not already retrieved it.
+pub fn mark_retrieved(handle: u64, receiver_id: u16) -> bool {
+    set_retrieved(handle, receiver_id, true)
+}
+
+/// Mark a share as relinquished (not retrieved) by `receiver_id`. Returns
+/// true if it is one of the share's receivers and had retrieved it.
+pub fn mark_relinquished(handle: u64, receiver_id: u16) -> bool {
+    set_retrieved(handle, receiver_id, false)
 }
 
 /// Reclaim a memory share by handle. Returns true if found and removed.
@@ -207,6 +337,23 @@ pub fn reclaim_share(handle: u64) -> bool {
     false
 }
 
+/// Collect the handles of active shares `part_id` sent or received into
+/// `out`. Returns the number of handles written.
+pub fn share_handles(part_id: u16, out: &mut [u64]) -> usize {
+    let records = unsafe { &*SHARE_RECORDS.0.get() };
+    let mut n = 0;
+    for record in records.iter() {
+        if n < out.len()
+            && record.active
+            && (record.sender_id == part_id || record.has_receiver(part_id))
+        {
+            out[n] = record.handle;
+            n += 1;
+        }
+    }
+    n
+}
+
 /// Check if a partition ID is a known stub SP.
 pub fn is_valid_sp(part_id: u16) -> bool {
     STUB_PARTITIONS.iter().any(|sp| sp.id == part_id)
@@ -216,3 +363,133 @@ pub fn is_valid_sp(part_id: u16) -> bool {
 pub fn partition_count() -> usize {
     STUB_PARTITIONS.len()
 }
+
+// ── Stub SP execution state ─────────────────────────────────────────
+
+/// Direct request command (x3): the SP is preempted before it finishes,
+/// x4 times (at least once), so the caller must FFA_RUN it to completion.
+pub const STUB_CMD_PREEMPT: u64 = 0xFFA0_5EC0_0000_0001;
+/// Direct request command (x3): the SP aborts while handling the request.
+pub const STUB_CMD_ABORT: u64 = 0xFFA0_5EC0_0000_0002;
+
+/// What a stub SP did with the CPU time it was given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StubSpExit {
+    /// Finished its request: DIRECT_RESP registers x0-x7.
+    DirectResp([u64; 8]),
+    /// Nothing to do: the SP went back to FFA_MSG_WAIT.
+    MsgWait,
+    /// Preempted before finishing; the caller sees FFA_INTERRUPT and
+    /// resumes it with FFA_RUN.
+    Interrupted,
+}
+
+#[derive(Clone, Copy)]
+struct StubSp {
+    state: SpState,
+    /// Preemptions left before the pending request completes
+    preemptions: u64,
+    /// DIRECT_RESP for the request in progress
+    pending: Option<[u64; 8]>,
+}
+
+const STUB_SP_IDLE: StubSp = StubSp {
+    state: SpState::Idle,
+    preemptions: 0,
+    pending: None,
+};
+
+/// Per stub SP (indexed like `STUB_PARTITIONS`); stub SPs boot straight
+/// to Idle.
+static STUB_SPS: SpinLock<[StubSp; 2]> = SpinLock::new([STUB_SP_IDLE; 2]);
+
+fn sp_index(part_id: u16) -> Option<usize> {
+    STUB_PARTITIONS.iter().position(|sp| sp.id == part_id)
+}
+
+/// Move `sp` to `next`, following the `SpState` lifecycle.
+fn transition(sp: &mut StubSp, next: SpState) {
+    debug_assert!(sp.state.can_transition_to(next));
+    sp.state = next;
+}
+
+/// Error for an SP that cannot take CPU time in its current state.
+fn not_runnable(state: SpState) -> i32 {
+    match state {
+        SpState::Aborted => FFA_ABORTED,
+        SpState::Running | SpState::Blocked | SpState::Preempted => FFA_BUSY,
+        SpState::Reset | SpState::Idle => FFA_DENIED,
+    }
+}
+
+/// Let a Running `sp` execute until it completes or is preempted.
+fn run_slice(sp: &mut StubSp) -> StubSpExit {
+    if sp.preemptions > 0 {
+        sp.preemptions -= 1;
+        transition(sp, SpState::Preempted);
+        return StubSpExit::Interrupted;
+    }
+    transition(sp, SpState::Idle);
+    match sp.pending.take() {
+        Some(resp) => StubSpExit::DirectResp(resp),
+        None => StubSpExit::MsgWait,
+    }
+}
+
+/// Current lifecycle state of a stub SP.
+pub fn sp_state(part_id: u16) -> Option<SpState> {
+    sp_index(part_id).map(|i| STUB_SPS.lock()[i].state)
+}
+
+/// Deliver a direct request to an Idle stub SP. `resp` is the
+/// DIRECT_RESP it answers with (x3-x7 echoed from the request); x3 may
+/// instead carry a `STUB_CMD_*` command.
+pub fn direct_req(part_id: u16, resp: [u64; 8]) -> Result<StubSpExit, i32> {
+    let idx = sp_index(part_id).ok_or(FFA_INVALID_PARAMETERS)?;
+    let mut sps = STUB_SPS.lock();
+    let sp = &mut sps[idx];
+    if sp.state != SpState::Idle {
+        return Err(not_runnable(sp.state));
+    }
+    transition(sp, SpState::Running);
+    match resp[3] {
+        STUB_CMD_ABORT => {
+            transition(sp, SpState::Aborted);
+            return Err(FFA_ABORTED);
+        }
+        STUB_CMD_PREEMPT => sp.preemptions = resp[4].max(1),
+        _ => sp.preemptions = 0,
+    }
+    sp.pending = Some(resp);
+    Ok(run_slice(sp))
+}
+
+/// FFA_RUN: give a stub SP CPU time on behalf of partition `caller`.
+///
+/// An Idle SP has nothing to do and returns to FFA_MSG_WAIT; a Preempted
+/// one continues the request it was handling, which only its sender may
+/// resume. Running, Blocked and Aborted SPs are refused.
+pub fn run(part_id: u16, caller: u16) -> Result<StubSpExit, i32> {
+    let idx = sp_index(part_id).ok_or(FFA_INVALID_PARAMETERS)?;
+    let mut sps = STUB_SPS.lock();
+    let sp = &mut sps[idx];
+    match sp.state {
+        SpState::Idle => {}
+        SpState::Preempted => {
+            let sender = sp.pending.map_or(0, |resp| resp[1] as u16);
+            if sender != caller {
+                return Err(FFA_DENIED);
+            }
+        }
+        state => return Err(not_runnable(state)),
+    }
+    transition(sp, SpState::Running);
+    Ok(run_slice(sp))
+}
+
+/// Reload a stub SP: back to Idle with no request in progress.
+pub fn reload_sp(part_id: u16) {
+    if let Some(i) = sp_index(part_id) {
+        STUB_SPS.lock()[i] = STUB_SP_IDLE;
+    }
+}
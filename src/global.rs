@@ -1,3 +1,4 @@
+#[cfg(not(feature = "multi_pcpu"))]
 use crate::devices::DeviceManager;
 /// Global state for hypervisor
 ///
@@ -31,9 +32,10 @@ unsafe impl Sync for GlobalDeviceManager {}
 
 #[cfg(not(feature = "multi_pcpu"))]
 impl GlobalDeviceManager {
-    pub const fn new() -> Self {
+    /// Device manager owned by VM `vm_id`.
+    pub const fn new(vm_id: usize) -> Self {
         Self {
-            devices: UnsafeCell::new(DeviceManager::new()),
+            devices: UnsafeCell::new(DeviceManager::for_vm(vm_id)),
             initialized: AtomicBool::new(false),
         }
     }
@@ -51,26 +53,143 @@ impl GlobalDeviceManager {
         self.initialized.store(true, Ordering::Relaxed);
     }
 
+    /// Register `dev` unless its region overlaps a registered device.
+    pub fn try_register_device(&self, dev: crate::devices::Device) -> Result<(), &'static str> {
+        use crate::devices::MmioDevice;
+
+        let dm = unsafe { &mut *self.devices.get() };
+        if dm.overlaps(dev.base_address(), dev.size()) {
+            return Err("Device region overlaps a registered device");
+        }
+        dm.register_device(dev).ok_or("Device table full")?;
+        self.initialized.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
     pub fn attach_virtio_blk(&self, disk_base: u64, disk_size: u64) {
         unsafe {
             (*self.devices.get()).attach_virtio_blk(disk_base, disk_size);
         }
     }
 
+    pub fn attach_virtio_blk_at(
+        &self,
+        slot: usize,
+        disk_base: u64,
+        disk_size: u64,
+    ) -> Result<(), &'static str> {
+        unsafe { (*self.devices.get()).attach_virtio_blk_at(slot, disk_base, disk_size) }
+    }
+
+    pub fn attach_virtio_cdrom(&self, base: u64, size: u64) {
+        unsafe {
+            (*self.devices.get()).attach_virtio_cdrom(base, size);
+        }
+    }
+
     pub fn handle_mmio(&self, addr: u64, value: u64, size: u8, is_write: bool) -> Option<u64> {
         unsafe { (*self.devices.get()).handle_mmio(addr, value, size, is_write) }
     }
 
+    pub fn set_unmapped_mmio_policy(&self, policy: crate::devices::UnmappedMmioPolicy) {
+        unsafe { (*self.devices.get()).set_unmapped_policy(policy) }
+    }
+
+    pub fn is_mapped(&self, addr: u64) -> bool {
+        unsafe { (*self.devices.get()).is_mapped(addr) }
+    }
+
+    pub fn overlaps(&self, base: u64, size: u64) -> bool {
+        unsafe { (*self.devices.get()).overlaps(base, size) }
+    }
+
+    /// True if an access to `addr` must be reflected to the guest as an abort.
+    pub fn unmapped_mmio_faults(&self, addr: u64) -> bool {
+        let dm = unsafe { &*self.devices.get() };
+        dm.unmapped_policy() == crate::devices::UnmappedMmioPolicy::Strict && !dm.is_mapped(addr)
+    }
+
     pub fn route_spi(&self, intid: u32) -> usize {
         unsafe { (*self.devices.get()).route_spi(intid) }
     }
 
+    pub fn set_gic_trapped(&self, trapped: bool) {
+        unsafe { (*self.devices.get()).set_gic_trapped(trapped) }
+    }
+
+    pub fn set_dma(&self, dma: crate::devices::dma::DmaMapper) {
+        unsafe { (*self.devices.get()).set_dma(dma) }
+    }
+
+    pub fn virtio_stats(&self, base: u64) -> Option<crate::devices::virtio::mmio::VirtioStats> {
+        unsafe { (*self.devices.get()).virtio_stats(base) }
+    }
+
+    pub fn attach_sensor(&self) {
+        unsafe { (*self.devices.get()).attach_sensor() }
+    }
+
+    pub fn attach_sched_stats(&self) {
+        unsafe { (*self.devices.get()).attach_sched_stats() }
+    }
+
+    /// Drive the sensor's temperature; raises/clears its alarm SPI.
+    pub fn sensor_set_temp(&self, millicelsius: i32) {
+        if let Some(sensor) = unsafe { (*self.devices.get()).sensor_mut() } {
+            sensor.set_temp(millicelsius);
+        }
+    }
+
+    /// Evaluate the RTC alarm; returns whether its interrupt is asserted.
+    pub fn rtc_poll(&self) -> bool {
+        unsafe { (*self.devices.get()).pl031_mut() }.is_some_and(|rtc| rtc.poll())
+    }
+
+    /// Whether an enabled RTC alarm is waiting to fire.
+    pub fn rtc_alarm_armed(&self) -> bool {
+        unsafe { (*self.devices.get()).pl031_mut() }.is_some_and(|rtc| rtc.alarm_armed())
+    }
+
+    pub fn irq_enabled(&self, vcpu_id: usize, intid: u32) -> bool {
+        unsafe { (*self.devices.get()).irq_enabled(vcpu_id, intid) }
+    }
+
+    pub fn irq_group1(&self, vcpu_id: usize, intid: u32) -> bool {
+        unsafe { (*self.devices.get()).irq_group1(vcpu_id, intid) }
+    }
+
+    pub fn update_spi_active(&self, before: &[u64], after: &[u64]) -> u32 {
+        unsafe { (*self.devices.get()).update_spi_active(before, after) }
+    }
+
+    pub fn deactivate_spi(&self, intid: u32) -> bool {
+        unsafe { (*self.devices.get()).deactivate_spi(intid) }
+    }
+
     #[allow(clippy::mut_from_ref)]
     pub fn uart_mut(&self) -> Option<&mut crate::devices::pl011::VirtualUart> {
         unsafe { (*self.devices.get()).uart_mut() }
     }
 
+    /// Transmit `bytes` through the VM's UART, recording them in its TX log.
+    pub fn uart_transmit(&self, bytes: &[u8]) {
+        if let Some(uart) = self.uart_mut() {
+            for &ch in bytes {
+                uart.transmit(ch);
+            }
+        }
+    }
+
+    /// Most recent UART output, oldest first; returns the bytes copied.
+    pub fn uart_tx_log(&self, out: &mut [u8]) -> usize {
+        self.uart_mut().map_or(0, |uart| uart.tx_log(out))
+    }
+
     pub fn attach_virtio_net(&self, vm_id: usize) {
+        debug_assert!(
+            owns_devices(vm_id, self),
+            "attach_virtio_net: vm_id does not match device manager"
+        );
         unsafe {
             (*self.devices.get()).attach_virtio_net(vm_id);
         }
@@ -85,54 +204,340 @@ impl GlobalDeviceManager {
             }
         }
     }
+
+    pub fn drain_net_rx(&self) {
+        unsafe {
+            if let Some(transport) = (*self.devices.get()).virtio_net_mut() {
+                transport.drain_rx();
+            }
+        }
+    }
+
+    pub fn attach_virtio_input(&self) {
+        unsafe {
+            (*self.devices.get()).attach_virtio_input();
+        }
+    }
+
+    pub fn attach_virtio_vsock(&self, vm_id: usize) {
+        debug_assert!(
+            owns_devices(vm_id, self),
+            "attach_virtio_vsock: vm_id does not match device manager"
+        );
+        unsafe {
+            (*self.devices.get()).attach_virtio_vsock(vm_id);
+        }
+    }
+
+    pub fn vsock_send(&self, port: u32, data: &[u8]) -> bool {
+        unsafe {
+            if let Some(transport) = (*self.devices.get()).virtio_vsock_mut() {
+                transport.vsock_send(port, data)
+            } else {
+                false
+            }
+        }
+    }
+
+    pub fn vsock_recv(&self) -> Option<crate::devices::virtio::vsock::VsockMessage> {
+        unsafe {
+            (*self.devices.get())
+                .virtio_vsock_mut()
+                .and_then(|transport| transport.vsock_recv())
+        }
+    }
+
+    pub fn drain_vsock_rx(&self) {
+        unsafe {
+            if let Some(transport) = (*self.devices.get()).virtio_vsock_mut() {
+                transport.drain_rx();
+            }
+        }
+    }
+
+    pub fn attach_virtio_balloon(&self) {
+        unsafe {
+            (*self.devices.get()).attach_virtio_balloon();
+        }
+    }
+
+    /// Set the balloon's target size (4KB pages) and signal a config change.
+    pub fn balloon_set_target_pages(&self, pages: u32) -> bool {
+        unsafe {
+            if let Some(transport) = (*self.devices.get()).virtio_balloon_mut() {
+                transport.set_target_pages(pages);
+                true
+            } else {
+                false
+            }
+        }
+    }
+
+    /// Ask the guest for fresh balloon memory statistics.
+    pub fn balloon_request_stats(&self) -> bool {
+        unsafe {
+            (*self.devices.get())
+                .virtio_balloon_mut()
+                .is_some_and(|transport| transport.request_stats())
+        }
+    }
+
+    /// Latest balloon memory statistics reported by the guest.
+    pub fn balloon_stats(&self) -> Option<crate::devices::virtio::balloon::BalloonStats> {
+        unsafe {
+            (*self.devices.get())
+                .virtio_balloon_mut()
+                .map(|transport| transport.balloon().stats())
+        }
+    }
+
+    pub fn snapshot(&self) -> crate::devices::DeviceSnapshot {
+        unsafe { (*self.devices.get()).snapshot() }
+    }
+
+    pub fn restore(&self, snap: &crate::devices::DeviceSnapshot) {
+        unsafe {
+            (*self.devices.get()).restore(snap);
+        }
+    }
+
+    pub fn set_mmio_trace(&self, enabled: bool) {
+        unsafe {
+            (*self.devices.get()).set_trace(enabled);
+        }
+    }
+
+    pub fn dump_mmio_trace(&self) {
+        unsafe {
+            (*self.devices.get()).trace().dump();
+        }
+    }
+
+    pub fn inject_input_event(
+        &self,
+        event: crate::devices::virtio::input::VirtioInputEvent,
+    ) -> bool {
+        unsafe {
+            if let Some(transport) = (*self.devices.get()).virtio_input_mut() {
+                transport.inject_event(event)
+            } else {
+                false
+            }
+        }
+    }
 }
 
-// ── Multi-pCPU GlobalDeviceManager (SpinLock protected) ───────────
+// ── Multi-pCPU GlobalDeviceManager (per-device locks) ─────────────
 
 #[cfg(feature = "multi_pcpu")]
-use crate::sync::SpinLock;
+use crate::devices::{Device, LockedDeviceManager};
 
 #[cfg(feature = "multi_pcpu")]
 use crate::devices::MmioDevice;
 
+/// Locking: MMIO dispatch holds the target device's lock only, so pCPUs
+/// trapping on different devices do not contend. Host-side helpers lock
+/// only the device they touch (`with_device`); registration, reset and
+/// checkpoints lock every device (`with_all`). See `LockedDeviceManager`.
 #[cfg(feature = "multi_pcpu")]
 pub struct GlobalDeviceManager {
-    devices: SpinLock<DeviceManager>,
+    devices: LockedDeviceManager,
 }
 
 #[cfg(feature = "multi_pcpu")]
 impl GlobalDeviceManager {
-    pub const fn new() -> Self {
+    /// Device manager owned by VM `vm_id`.
+    pub const fn new(vm_id: usize) -> Self {
         Self {
-            devices: SpinLock::new(DeviceManager::new()),
+            devices: LockedDeviceManager::new(vm_id),
         }
     }
 
     pub fn reset(&self) {
-        self.devices.lock().reset();
+        self.devices.with_all(|dm| dm.reset());
     }
 
     pub fn register_device(&self, dev: crate::devices::Device) {
-        self.devices.lock().register_device(dev);
+        self.devices.with_all(|dm| dm.register_device(dev));
+    }
+
+    /// Register `dev` unless its region overlaps a registered device.
+    /// The check and the insertion happen under one lock acquisition, so a
+    /// concurrent MMIO dispatch sees either the old or the new device table.
+    pub fn try_register_device(&self, dev: crate::devices::Device) -> Result<(), &'static str> {
+        self.devices.with_all(|dm| {
+            if dm.overlaps(dev.base_address(), dev.size()) {
+                return Err("Device region overlaps a registered device");
+            }
+            dm.register_device(dev).ok_or("Device table full")?;
+            Ok(())
+        })
     }
 
     pub fn attach_virtio_blk(&self, disk_base: u64, disk_size: u64) {
-        self.devices.lock().attach_virtio_blk(disk_base, disk_size);
+        self.devices
+            .with_all(|dm| dm.attach_virtio_blk(disk_base, disk_size));
+    }
+
+    pub fn attach_virtio_blk_at(
+        &self,
+        slot: usize,
+        disk_base: u64,
+        disk_size: u64,
+    ) -> Result<(), &'static str> {
+        self.devices
+            .with_all(|dm| dm.attach_virtio_blk_at(slot, disk_base, disk_size))
+    }
+
+    pub fn attach_virtio_cdrom(&self, base: u64, size: u64) {
+        self.devices
+            .with_all(|dm| dm.attach_virtio_cdrom(base, size));
     }
 
     pub fn handle_mmio(&self, addr: u64, value: u64, size: u8, is_write: bool) -> Option<u64> {
-        self.devices.lock().handle_mmio(addr, value, size, is_write)
+        self.devices.handle_mmio(addr, value, size, is_write)
+    }
+
+    pub fn set_unmapped_mmio_policy(&self, policy: crate::devices::UnmappedMmioPolicy) {
+        self.devices.with_table(|dm| dm.set_unmapped_policy(policy));
+    }
+
+    pub fn is_mapped(&self, addr: u64) -> bool {
+        self.devices.is_mapped(addr)
+    }
+
+    pub fn overlaps(&self, base: u64, size: u64) -> bool {
+        self.devices.overlaps(base, size)
+    }
+
+    /// True if an access to `addr` must be reflected to the guest as an abort.
+    pub fn unmapped_mmio_faults(&self, addr: u64) -> bool {
+        self.devices.with_table(|dm| dm.unmapped_policy())
+            == crate::devices::UnmappedMmioPolicy::Strict
+            && !self.devices.is_mapped(addr)
     }
 
     pub fn route_spi(&self, intid: u32) -> usize {
-        self.devices.lock().route_spi(intid)
+        self.devices
+            .with_device(Device::gicd_mut, |gicd| gicd.route_spi(intid))
+            .unwrap_or(0)
     }
 
-    /// UART RX injection — acquires the device lock.
-    pub fn uart_push_rx(&self, ch: u8) {
-        if let Some(uart) = self.devices.lock().uart_mut() {
-            uart.push_rx(ch);
+    pub fn set_gic_trapped(&self, trapped: bool) {
+        self.devices.with_table(|dm| dm.set_gic_trapped(trapped));
+    }
+
+    pub fn set_dma(&self, dma: crate::devices::dma::DmaMapper) {
+        self.devices.with_table(|dm| dm.set_dma(dma));
+    }
+
+    pub fn virtio_stats(&self, base: u64) -> Option<crate::devices::virtio::mmio::VirtioStats> {
+        self.devices
+            .lock_device(base)
+            .filter(|dev| dev.base_address() == base)?
+            .virtio_stats()
+    }
+
+    pub fn attach_sensor(&self) {
+        self.devices.with_all(|dm| dm.attach_sensor());
+    }
+
+    pub fn attach_sched_stats(&self) {
+        self.devices.with_all(|dm| dm.attach_sched_stats());
+    }
+
+    /// Drive the sensor's temperature; raises/clears its alarm SPI.
+    ///
+    /// inject_spi() does not take the device lock in multi-pCPU builds.
+    pub fn sensor_set_temp(&self, millicelsius: i32) {
+        self.devices
+            .with_device(Device::sensor_mut, |sensor| sensor.set_temp(millicelsius));
+    }
+
+    /// Evaluate the RTC alarm; returns whether its interrupt is asserted.
+    pub fn rtc_poll(&self) -> bool {
+        self.devices
+            .with_device(Device::pl031_mut, |rtc| rtc.poll())
+            .unwrap_or(false)
+    }
+
+    /// Whether an enabled RTC alarm is waiting to fire.
+    pub fn rtc_alarm_armed(&self) -> bool {
+        self.devices
+            .with_device(Device::pl031_mut, |rtc| rtc.alarm_armed())
+            .unwrap_or(false)
+    }
+
+    /// Same lookup as `DeviceManager::irq_enabled`: the GICR shadow for
+    /// SGIs/PPIs, the GICD shadow for SPIs, true while the GIC is not trapped.
+    pub fn irq_enabled(&self, vcpu_id: usize, intid: u32) -> bool {
+        if !self.devices.with_table(|dm| dm.gic_trapped()) {
+            return true;
+        }
+        let enabled = if intid < 32 {
+            self.devices
+                .with_device(Device::gicr_mut, |gicr| gicr.is_enabled(vcpu_id, intid))
+        } else {
+            self.devices
+                .with_device(Device::gicd_mut, |gicd| gicd.is_enabled(intid))
+        };
+        enabled.unwrap_or(true)
+    }
+
+    /// Same lookup as `DeviceManager::irq_group1`.
+    pub fn irq_group1(&self, vcpu_id: usize, intid: u32) -> bool {
+        if !self.devices.with_table(|dm| dm.gic_trapped()) {
+            return true;
         }
+        let group1 = if intid < 32 {
+            self.devices
+                .with_device(Device::gicr_mut, |gicr| gicr.is_group1(vcpu_id, intid))
+        } else {
+            self.devices
+                .with_device(Device::gicd_mut, |gicd| gicd.is_group1(intid))
+        };
+        group1.unwrap_or(true)
+    }
+
+    pub fn update_spi_active(&self, before: &[u64], after: &[u64]) -> u32 {
+        self.devices
+            .with_device(Device::gicd_mut, |gicd| {
+                gicd.update_active_from_lrs(before, after)
+            })
+            .unwrap_or(0)
+    }
+
+    pub fn deactivate_spi(&self, intid: u32) -> bool {
+        self.devices
+            .with_device(Device::gicd_mut, |gicd| gicd.deactivate(intid))
+            .unwrap_or(false)
+    }
+
+    /// Run `f` on the VM's UART with its device lock held.
+    fn with_uart<R>(
+        &self,
+        f: impl FnOnce(&mut crate::devices::pl011::VirtualUart) -> R,
+    ) -> Option<R> {
+        self.devices.with_device(Device::uart_mut, f)
+    }
+
+    /// UART RX injection — acquires the UART's lock.
+    pub fn uart_push_rx(&self, ch: u8) {
+        self.with_uart(|uart| uart.push_rx(ch));
+    }
+
+    /// Transmit `bytes` through the VM's UART under one lock acquisition.
+    pub fn uart_transmit(&self, bytes: &[u8]) {
+        self.with_uart(|uart| {
+            for &ch in bytes {
+                uart.transmit(ch);
+            }
+        });
+    }
+
+    /// Most recent UART output, oldest first; returns the bytes copied.
+    pub fn uart_tx_log(&self, out: &mut [u8]) -> usize {
+        self.with_uart(|uart| uart.tx_log(out)).unwrap_or(0)
     }
 
     /// Drain UART RX ring buffer and inject SPI 33 if needed.
@@ -151,35 +556,147 @@ impl GlobalDeviceManager {
         if count == 0 {
             return;
         }
-        let mut guard = self.devices.lock();
-        if let Some(uart) = guard.uart_mut() {
-            for &ch in &buf[..count] {
-                uart.push_rx(ch);
-            }
-            if uart.pending_irq().is_some() {
-                drop(guard); // Release lock before inject_spi (may re-lock)
-                inject_spi(33);
-            }
+        let vm_id = self
+            .devices
+            .with_table(|dm| dm.owner_vm())
+            .unwrap_or_else(current_vm_id);
+        let irq_vm = self
+            .with_uart(|uart| {
+                for &ch in &buf[..count] {
+                    uart.push_rx(ch);
+                }
+                uart.pending_irq().map(|_| vm_id)
+            })
+            .flatten();
+        // Lock released before inject_spi
+        if let Some(vm_id) = irq_vm {
+            inject_spi(vm_id, 33);
         }
     }
 
     pub fn attach_virtio_net(&self, vm_id: usize) {
-        self.devices.lock().attach_virtio_net(vm_id);
+        debug_assert!(
+            owns_devices(vm_id, self),
+            "attach_virtio_net: vm_id does not match device manager"
+        );
+        self.devices.with_all(|dm| dm.attach_virtio_net(vm_id));
     }
 
     pub fn inject_net_rx(&self, frame: &[u8]) -> bool {
-        if let Some(transport) = self.devices.lock().virtio_net_mut() {
-            transport.inject_rx(frame)
-        } else {
-            false
-        }
+        // The transport's device lock serializes host-side RX completions
+        // with the guest's queue notifies
+        self.devices
+            .with_device(Device::virtio_net_mut, |transport| {
+                transport.inject_rx(frame)
+            })
+            .unwrap_or(false)
+    }
+
+    pub fn drain_net_rx(&self) {
+        self.devices
+            .with_device(Device::virtio_net_mut, |transport| transport.drain_rx());
+    }
+
+    pub fn attach_virtio_input(&self) {
+        self.devices.with_all(|dm| dm.attach_virtio_input());
+    }
+
+    pub fn attach_virtio_vsock(&self, vm_id: usize) {
+        debug_assert!(
+            owns_devices(vm_id, self),
+            "attach_virtio_vsock: vm_id does not match device manager"
+        );
+        self.devices.with_all(|dm| dm.attach_virtio_vsock(vm_id));
+    }
+
+    pub fn vsock_send(&self, port: u32, data: &[u8]) -> bool {
+        self.devices
+            .with_device(Device::virtio_vsock_mut, |transport| {
+                transport.vsock_send(port, data)
+            })
+            .unwrap_or(false)
+    }
+
+    pub fn vsock_recv(&self) -> Option<crate::devices::virtio::vsock::VsockMessage> {
+        self.devices
+            .with_device(Device::virtio_vsock_mut, |transport| transport.vsock_recv())
+            .flatten()
+    }
+
+    pub fn drain_vsock_rx(&self) {
+        self.devices
+            .with_device(Device::virtio_vsock_mut, |transport| transport.drain_rx());
+    }
+
+    pub fn attach_virtio_balloon(&self) {
+        self.devices.with_all(|dm| dm.attach_virtio_balloon());
+    }
+
+    /// Set the balloon's target size (4KB pages) and signal a config change.
+    pub fn balloon_set_target_pages(&self, pages: u32) -> bool {
+        self.devices
+            .with_device(Device::virtio_balloon_mut, |transport| {
+                transport.set_target_pages(pages)
+            })
+            .is_some()
+    }
+
+    /// Ask the guest for fresh balloon memory statistics.
+    pub fn balloon_request_stats(&self) -> bool {
+        self.devices
+            .with_device(Device::virtio_balloon_mut, |transport| {
+                transport.request_stats()
+            })
+            .unwrap_or(false)
+    }
+
+    /// Latest balloon memory statistics reported by the guest.
+    pub fn balloon_stats(&self) -> Option<crate::devices::virtio::balloon::BalloonStats> {
+        self.devices
+            .with_device(Device::virtio_balloon_mut, |transport| {
+                transport.balloon().stats()
+            })
+    }
+
+    pub fn snapshot(&self) -> crate::devices::DeviceSnapshot {
+        self.devices.with_all(|dm| dm.snapshot())
+    }
+
+    pub fn restore(&self, snap: &crate::devices::DeviceSnapshot) {
+        self.devices.with_all(|dm| dm.restore(snap));
+    }
+
+    pub fn set_mmio_trace(&self, enabled: bool) {
+        self.devices.with_table(|dm| dm.set_trace(enabled));
+    }
+
+    pub fn dump_mmio_trace(&self) {
+        self.devices.with_table(|dm| dm.trace().dump());
+    }
+
+    pub fn inject_input_event(
+        &self,
+        event: crate::devices::virtio::input::VirtioInputEvent,
+    ) -> bool {
+        self.devices
+            .with_device(Device::virtio_input_mut, |transport| {
+                transport.inject_event(event)
+            })
+            .unwrap_or(false)
     }
 }
 
 /// Per-VM device managers.
 /// Exception handler indexes by CURRENT_VM_ID.
 pub static DEVICES: [GlobalDeviceManager; MAX_VMS] =
-    [GlobalDeviceManager::new(), GlobalDeviceManager::new()];
+    [GlobalDeviceManager::new(0), GlobalDeviceManager::new(1)];
+
+/// True if `devices` is `DEVICES[vm_id]`.
+pub fn owns_devices(vm_id: usize, devices: &GlobalDeviceManager) -> bool {
+    DEVICES
+        .get(vm_id)
+        .is_some_and(|d| core::ptr::eq(d, devices))
+}
 
 /// Get the current VM's device manager.
 #[inline]
@@ -187,6 +704,37 @@ pub fn current_devices() -> &'static GlobalDeviceManager {
     &DEVICES[CURRENT_VM_ID.load(Ordering::Relaxed)]
 }
 
+/// Push an input event into a VM's virtio-input eventq.
+///
+/// Returns false if `vm_id` is out of range, the VM has no virtio-input
+/// device, or the guest hasn't posted an event buffer.
+pub fn inject_input_event(
+    vm_id: usize,
+    event: crate::devices::virtio::input::VirtioInputEvent,
+) -> bool {
+    if vm_id >= MAX_VMS {
+        return false;
+    }
+    DEVICES[vm_id].inject_input_event(event)
+}
+
+/// Send a host message to a VM's guest over virtio-vsock, from host port
+/// `port`.
+///
+/// Returns false if `vm_id` is out of range, the VM has no vsock device,
+/// or the message could not be queued.
+pub fn vsock_send(vm_id: usize, port: u32, data: &[u8]) -> bool {
+    if vm_id >= MAX_VMS {
+        return false;
+    }
+    DEVICES[vm_id].vsock_send(port, data)
+}
+
+/// Oldest message a VM's guest sent to the host over virtio-vsock.
+pub fn vsock_recv(vm_id: usize) -> Option<crate::devices::virtio::vsock::VsockMessage> {
+    DEVICES.get(vm_id)?.vsock_recv()
+}
+
 // ── Per-VM Global State ──────────────────────────────────────────────
 
 /// Per-VM global state — exception handler indexes by CURRENT_VM_ID.
@@ -206,8 +754,26 @@ pub struct VmGlobalState {
     pub current_vcpu_id: AtomicUsize,
     /// Pending PSCI CPU_ON for this VM (single-pCPU mode)
     pub pending_cpu_on: PendingCpuOn,
+    /// PSCI SYSTEM_SUSPEND state for this VM (single-pCPU mode)
+    pub system_suspend: SystemSuspend,
     /// Flag set by IRQ handler to signal preemptive vCPU exit
     pub preemption_exit: AtomicBool,
+    /// Whole-VM stop requested (exception storm in multi-VM mode)
+    pub vm_terminated: AtomicBool,
+    /// Number of PSCI SYSTEM_RESETs by this VM; survives `Vm::new()`
+    pub reboot_count: AtomicU32,
+    /// CNTVOFF_EL2 shared by this VM's vCPUs (physical count at VM creation)
+    pub counter_offset: AtomicU64,
+    /// How long a vCPU may idle in WFI at one PC before it is forced out to
+    /// the scheduler, in ns (0 = only the WFI iteration cap applies)
+    pub wfi_timeout_ns: AtomicU64,
+    /// vCPUs per Aff1 cluster in the guest-visible MPIDR layout (see
+    /// `vcpu_affinity`)
+    pub vcpus_per_cluster: AtomicU32,
+    /// Base IPA of the guest RAM window mapped by `Vm::init_memory`
+    pub ram_base: AtomicU64,
+    /// Size of that window in bytes (0 = memory not initialized yet)
+    pub ram_size: AtomicU64,
 }
 
 impl VmGlobalState {
@@ -246,7 +812,15 @@ impl VmGlobalState {
             vcpu_online_mask: AtomicU64::new(0),
             current_vcpu_id: AtomicUsize::new(0),
             pending_cpu_on: PendingCpuOn::new(),
+            system_suspend: SystemSuspend::new(),
             preemption_exit: AtomicBool::new(false),
+            vm_terminated: AtomicBool::new(false),
+            reboot_count: AtomicU32::new(0),
+            counter_offset: AtomicU64::new(0),
+            wfi_timeout_ns: AtomicU64::new(0),
+            vcpus_per_cluster: AtomicU32::new(DEFAULT_VCPUS_PER_CLUSTER),
+            ram_base: AtomicU64::new(0),
+            ram_size: AtomicU64::new(0),
         }
     }
 }
@@ -267,6 +841,33 @@ pub fn vm_state(vm_id: usize) -> &'static VmGlobalState {
     &VM_STATE[vm_id]
 }
 
+/// Default vCPUs per Aff1 cluster: the 16 PEs one ICC_SGI1R_EL1
+/// TargetList can address, so every vCPU has Aff0 = vCPU ID
+pub const DEFAULT_VCPUS_PER_CLUSTER: u32 = 16;
+
+/// MPIDR_EL1 affinity fields: Aff3 [39:32], Aff2 [23:16], Aff1 [15:8],
+/// Aff0 [7:0]
+pub const MPIDR_AFFINITY_MASK: u64 = 0xFF_00FF_FFFF;
+
+/// MPIDR affinity of vCPU `vcpu_id` of VM `vm_id`, as programmed into
+/// VMPIDR_EL2: Aff1 = ID / vcpus_per_cluster, Aff0 = ID % vcpus_per_cluster,
+/// Aff2 = Aff3 = 0.
+pub fn vcpu_affinity(vm_id: usize, vcpu_id: usize) -> u64 {
+    let per_cluster = VM_STATE[vm_id]
+        .vcpus_per_cluster
+        .load(Ordering::Relaxed)
+        .max(1) as u64;
+    let id = vcpu_id as u64;
+    ((id / per_cluster) << 8) | (id % per_cluster)
+}
+
+/// vCPU of VM `vm_id` whose MPIDR affinity fields match `mpidr` (other
+/// MPIDR bits are ignored), if any.
+pub fn vcpu_at_affinity(vm_id: usize, mpidr: u64) -> Option<usize> {
+    let affinity = mpidr & MPIDR_AFFINITY_MASK;
+    (0..MAX_VCPUS).find(|&id| vcpu_affinity(vm_id, id) == affinity)
+}
+
 /// Get the current vCPU ID.
 /// - Single-pCPU: reads current_vm_state().current_vcpu_id.
 /// - Multi-pCPU: reads MPIDR_EL1.Aff0 (1:1 affinity, vCPU N = pCPU N).
@@ -289,11 +890,49 @@ pub fn current_vm_id() -> usize {
     CURRENT_VM_ID.load(Ordering::Relaxed)
 }
 
+/// VMID field of VTTBR_EL2 (bits [63:48]); VM `n` runs with VMID `n`.
+const VTTBR_VMID_SHIFT: u32 = 48;
+
+/// Check that `CURRENT_VM_ID` names the VM whose Stage-2 `vttbr` installs.
+///
+/// Device models use `current_vm_id()` while handling a trapped access
+/// (e.g. to route an SPI), so a `CURRENT_VM_ID` left stale by a VM switch
+/// would act on the wrong VM. Returns the VM the VMID belongs to on a
+/// mismatch.
+pub fn check_current_vm(vttbr: u64) -> Result<(), usize> {
+    let active = (vttbr >> VTTBR_VMID_SHIFT) as usize;
+    if active == current_vm_id() {
+        Ok(())
+    } else {
+        Err(active)
+    }
+}
+
+/// Debug builds: panic if `CURRENT_VM_ID` disagrees with the VMID in the
+/// live VTTBR_EL2. See `check_current_vm()`.
+#[inline]
+pub fn debug_assert_current_vm() {
+    if cfg!(debug_assertions) {
+        let vttbr: u64;
+        unsafe {
+            core::arch::asm!("mrs {}, vttbr_el2", out(reg) vttbr, options(nomem, nostack));
+        }
+        if let Err(active) = check_current_vm(vttbr) {
+            panic!(
+                "CURRENT_VM_ID {} but VM {}'s Stage-2 is active",
+                current_vm_id(),
+                active
+            );
+        }
+    }
+}
+
 // ── PSCI CPU_ON ──────────────────────────────────────────────────────
 
 /// Pending PSCI CPU_ON request from exception handler to run loop
 pub struct PendingCpuOn {
     pub requested: AtomicBool,
+    /// Linear vCPU ID, resolved from the guest's MPIDR by `handle_psci`
     pub target_cpu: AtomicU64,
     pub entry_point: AtomicU64,
     pub context_id: AtomicU64,
@@ -336,6 +975,83 @@ impl PendingCpuOn {
     }
 }
 
+// ── PSCI SYSTEM_SUSPEND ──────────────────────────────────────────────
+
+/// Wakeup source: PL031 RTC alarm (match interrupt)
+pub const WAKE_SRC_RTC: u32 = 1 << 0;
+
+/// A VM suspended by PSCI SYSTEM_SUSPEND, waiting for a wakeup source.
+///
+/// The exception handler records the calling vCPU's resume entry point and
+/// context ID together with the wakeup sources armed at suspend time; the
+/// run loop polls those sources and resumes the vCPU once one fires.
+pub struct SystemSuspend {
+    pub suspended: AtomicBool,
+    pub vcpu_id: AtomicUsize,
+    pub entry_point: AtomicU64,
+    pub context_id: AtomicU64,
+    /// Bitmask of `WAKE_SRC_*` registered at suspend time
+    pub wake_sources: AtomicU32,
+}
+
+impl SystemSuspend {
+    pub const fn new() -> Self {
+        Self {
+            suspended: AtomicBool::new(false),
+            vcpu_id: AtomicUsize::new(0),
+            entry_point: AtomicU64::new(0),
+            context_id: AtomicU64::new(0),
+            wake_sources: AtomicU32::new(0),
+        }
+    }
+
+    /// Record a suspend request (called from exception handler)
+    pub fn suspend(&self, vcpu_id: usize, entry: u64, ctx: u64, wake_sources: u32) {
+        self.vcpu_id.store(vcpu_id, Ordering::Relaxed);
+        self.entry_point.store(entry, Ordering::Relaxed);
+        self.context_id.store(ctx, Ordering::Relaxed);
+        self.wake_sources.store(wake_sources, Ordering::Relaxed);
+        self.suspended.store(true, Ordering::Release);
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.suspended.load(Ordering::Acquire)
+    }
+
+    /// Wakeup sources registered by the current suspend
+    pub fn wake_sources(&self) -> u32 {
+        self.wake_sources.load(Ordering::Relaxed)
+    }
+
+    /// End the suspend, returning (vcpu_id, entry, ctx) to resume at
+    pub fn take(&self) -> Option<(usize, u64, u64)> {
+        if self
+            .suspended
+            .compare_exchange(true, false, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            let vcpu_id = self.vcpu_id.load(Ordering::Relaxed);
+            let entry = self.entry_point.load(Ordering::Relaxed);
+            let ctx = self.context_id.load(Ordering::Relaxed);
+            Some((vcpu_id, entry, ctx))
+        } else {
+            None
+        }
+    }
+
+    /// Drop any suspend state (VM reset)
+    pub fn cancel(&self) {
+        self.suspended.store(false, Ordering::Release);
+        self.wake_sources.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Default for SystemSuspend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Per-vCPU PSCI CPU_ON request (multi-pCPU mode).
 /// Index = target vCPU ID. Each pCPU checks its own slot.
 #[cfg(feature = "multi_pcpu")]
@@ -402,18 +1118,22 @@ pub static SHARED_VTCR: AtomicU64 = AtomicU64::new(0);
 /// Stage2Walker for any VM's page tables.
 pub static PER_VM_VTTBR: [AtomicU64; MAX_VMS] = [AtomicU64::new(0), AtomicU64::new(0)];
 
-/// Inject an SPI to the correct vCPU based on GICD_IROUTER.
+/// Per-VM VTCR_EL2, stored next to `PER_VM_VTTBR` so a walker over another
+/// VM's tables uses the granule (TG0) they were built with.
+pub static PER_VM_VTCR: [AtomicU64; MAX_VMS] = [AtomicU64::new(0), AtomicU64::new(0)];
+
+/// Inject an SPI into VM `vm_id`, to the vCPU selected by its GICD_IROUTER.
 ///
-/// Called from exception handler or device completion path.
-/// Routes via the current VM's device manager and pending SPI state.
+/// Called from exception handler or device completion path. Devices pass
+/// the VM that owns them, which need not be the current VM: an MMIO
+/// handler may complete another VM's request after a context switch.
 ///
 /// Only supports INTIDs 32-63 (first 32 SPIs).
-pub fn inject_spi(intid: u32) {
-    if intid < 32 || intid > 63 {
+pub fn inject_spi(vm_id: usize, intid: u32) {
+    if !(32..=63).contains(&intid) || vm_id >= MAX_VMS {
         return;
     }
     let bit = intid - 32;
-    let vm_id = CURRENT_VM_ID.load(Ordering::Relaxed);
     let vs = &VM_STATE[vm_id];
 
     // Read IROUTER to find target vCPU.
@@ -425,11 +1145,12 @@ pub fn inject_spi(intid: u32) {
         let gicd_irouter_base = crate::dtb::platform_info().gicd_base + 0x6100;
         let irouter_addr = gicd_irouter_base + (intid as u64 - 32) * 8;
         let irouter = unsafe { core::ptr::read_volatile(irouter_addr as *const u64) };
-        (irouter & 0xFF) as usize // Aff0 = vCPU ID
+        vcpu_at_affinity(vm_id, irouter).unwrap_or(0)
     };
     #[cfg(not(feature = "multi_pcpu"))]
     let target = DEVICES[vm_id].route_spi(intid);
     if target < MAX_VCPUS {
+        note_spi_queued(vm_id, intid);
         vs.pending_spis[target].fetch_or(1 << bit, Ordering::Release);
 
         // Multi-pCPU: if target is a remote pCPU, send physical SGI to wake it.
@@ -452,6 +1173,339 @@ pub fn inject_spi(intid: u32) {
     }
 }
 
+/// Inject an SPI into the current VM (see `inject_spi`).
+pub fn inject_spi_current(intid: u32) {
+    inject_spi(current_vm_id(), intid);
+}
+
+/// Device line level of each SPI (INTID 32-63), per VM: bit N = INTID N+32.
+static SPI_LINE_LEVEL: [AtomicU32; MAX_VMS] = [const { AtomicU32::new(0) }; MAX_VMS];
+
+/// Drive the line of a level-triggered SPI in VM `vm_id`.
+///
+/// A rising edge queues the SPI (`inject_spi`), a falling one withdraws it
+/// if not yet delivered (`clear_spi`). While the line stays high and the
+/// guest configured the SPI level-sensitive, every deactivation queues it
+/// again (`requeue_level_spis`). Lock-free: device models call it from
+/// their MMIO handlers. Only INTIDs 32-63 are tracked.
+pub fn set_spi_level(vm_id: usize, intid: u32, high: bool) {
+    if !(32..=63).contains(&intid) || vm_id >= MAX_VMS {
+        return;
+    }
+    let bit = 1u32 << (intid - 32);
+    let prev = if high {
+        SPI_LINE_LEVEL[vm_id].fetch_or(bit, Ordering::AcqRel)
+    } else {
+        SPI_LINE_LEVEL[vm_id].fetch_and(!bit, Ordering::AcqRel)
+    };
+    let was_high = prev & bit != 0;
+    if high && !was_high {
+        inject_spi(vm_id, intid);
+    } else if !high && was_high {
+        clear_spi(vm_id, intid);
+    }
+}
+
+/// Whether the device line of SPI `intid` in VM `vm_id` is high.
+pub fn spi_line_level(vm_id: usize, intid: u32) -> bool {
+    (32..=63).contains(&intid)
+        && vm_id < MAX_VMS
+        && SPI_LINE_LEVEL[vm_id].load(Ordering::Acquire) & (1 << (intid - 32)) != 0
+}
+
+/// Drop every SPI line of VM `vm_id` (its devices are recreated).
+pub fn reset_spi_lines(vm_id: usize) {
+    if vm_id < MAX_VMS {
+        SPI_LINE_LEVEL[vm_id].store(0, Ordering::Release);
+    }
+}
+
+/// Queue again the deactivated level-triggered SPIs in `deactivated` (a
+/// `PENDING_SPIS` bitmap) whose line is still high in VM `vm_id`.
+pub fn requeue_level_spis(vm_id: usize, deactivated: u32) {
+    if vm_id >= MAX_VMS {
+        return;
+    }
+    let high = deactivated & SPI_LINE_LEVEL[vm_id].load(Ordering::Acquire);
+    for bit in (0..32).filter(|bit| high & (1 << bit) != 0) {
+        inject_spi(vm_id, 32 + bit);
+    }
+}
+
+/// Track SPI active state across a guest run of one vCPU of VM `vm_id`
+/// from its LRs at entry (`before`) and exit (`after`); SPIs gone from the
+/// LRs were deactivated, and level-triggered ones whose line is still high
+/// are queued again.
+pub fn sync_spi_active(vm_id: usize, before: &[u64], after: &[u64]) {
+    if vm_id >= MAX_VMS {
+        return;
+    }
+    let deactivated = DEVICES[vm_id].update_spi_active(before, after);
+    requeue_level_spis(vm_id, deactivated);
+}
+
+/// The guest deactivated `intid` through a trapped ICC_EOIR1_EL1 or
+/// ICC_DIR_EL1 write: clear its shadow active bit and, for a
+/// level-triggered SPI whose line is still high, queue it again.
+pub fn spi_deactivated(vm_id: usize, intid: u32) {
+    if !(32..=63).contains(&intid) || vm_id >= MAX_VMS {
+        return;
+    }
+    if DEVICES[vm_id].deactivate_spi(intid) {
+        requeue_level_spis(vm_id, 1 << (intid - 32));
+    }
+}
+
+/// Move a queued SPI from one vCPU's pending bitmap to another's.
+///
+/// Called when the guest rewrites GICD_IROUTER for an SPI while it is still
+/// pending for the old target. Operates on VM `vm_id`'s state, which need
+/// not be the current VM. Only INTIDs 32-63 are tracked in `pending_spis`;
+/// others are ignored.
+pub fn migrate_pending_spi(vm_id: usize, intid: u32, old_target: usize, new_target: usize) {
+    if !(32..=63).contains(&intid)
+        || vm_id >= MAX_VMS
+        || old_target >= MAX_VCPUS
+        || new_target >= MAX_VCPUS
+    {
+        return;
+    }
+    let bit = 1u32 << (intid - 32);
+    let vs = &VM_STATE[vm_id];
+    let prev = vs.pending_spis[old_target].fetch_and(!bit, Ordering::AcqRel);
+    if prev & bit != 0 {
+        vs.pending_spis[new_target].fetch_or(bit, Ordering::Release);
+    }
+}
+
+/// Withdraw a queued, not yet delivered SPI from every vCPU of VM `vm_id`.
+///
+/// Used by level-triggered device interrupts when the line de-asserts
+/// before the guest took it. Only INTIDs 32-63 are tracked.
+pub fn clear_spi(vm_id: usize, intid: u32) {
+    if !(32..=63).contains(&intid) || vm_id >= MAX_VMS {
+        return;
+    }
+    let bit = 1u32 << (intid - 32);
+    for pending in VM_STATE[vm_id].pending_spis.iter() {
+        pending.fetch_and(!bit, Ordering::AcqRel);
+    }
+    SPI_QUEUED_AT[vm_id][bit.trailing_zeros() as usize].store(0, Ordering::Relaxed);
+}
+
+/// Withdraw a queued SPI from the current VM (see `clear_spi`).
+pub fn clear_spi_current(intid: u32) {
+    clear_spi(current_vm_id(), intid);
+}
+
+// ── SPI injection latency ───────────────────────────────────────────
+
+/// Number of latency histogram bins. Bin 0 counts deliveries under 1us;
+/// each following bin's upper bound is 4x the previous one, and the last
+/// bin collects everything above 4ms.
+pub const IRQ_LATENCY_BINS: usize = 8;
+
+/// Physical counter value when each SPI (INTID 32-63) was queued, per VM.
+/// 0 = not queued.
+static SPI_QUEUED_AT: [[AtomicU64; 32]; MAX_VMS] =
+    [const { [const { AtomicU64::new(0) }; 32] }; MAX_VMS];
+
+/// Queue-to-LR latency histogram for SPIs, all VMs.
+static IRQ_LATENCY_HIST: [AtomicU32; IRQ_LATENCY_BINS] =
+    [const { AtomicU32::new(0) }; IRQ_LATENCY_BINS];
+
+/// Timestamp an SPI when it is queued. A re-queue keeps the original time so
+/// waiting for a free LR or a descheduled vCPU counts toward the latency.
+fn note_spi_queued(vm_id: usize, intid: u32) {
+    let now = crate::time::now_ticks().max(1);
+    let _ = SPI_QUEUED_AT[vm_id][(intid - 32) as usize].compare_exchange(
+        0,
+        now,
+        Ordering::Relaxed,
+        Ordering::Relaxed,
+    );
+}
+
+/// Record that a queued SPI of the current VM was placed in a List Register.
+pub fn record_spi_delivered(intid: u32) {
+    if !(32..=63).contains(&intid) {
+        return;
+    }
+    let vm_id = CURRENT_VM_ID.load(Ordering::Relaxed);
+    let queued_at = SPI_QUEUED_AT[vm_id][(intid - 32) as usize].swap(0, Ordering::Relaxed);
+    if queued_at == 0 {
+        return;
+    }
+    let latency_ns = crate::time::ticks_to_ns(crate::time::now_ticks().saturating_sub(queued_at));
+    let mut bin = 0;
+    let mut bound = 1_000u64; // 1us
+    while bin < IRQ_LATENCY_BINS - 1 && latency_ns >= bound {
+        bin += 1;
+        bound *= 4;
+    }
+    IRQ_LATENCY_HIST[bin].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Snapshot of the SPI queue-to-LR latency histogram.
+pub fn irq_latency_histogram() -> [u32; IRQ_LATENCY_BINS] {
+    core::array::from_fn(|i| IRQ_LATENCY_HIST[i].load(Ordering::Relaxed))
+}
+
+/// Zero the latency histogram and forget outstanding queue timestamps.
+pub fn reset_irq_latency_histogram() {
+    for bin in IRQ_LATENCY_HIST.iter() {
+        bin.store(0, Ordering::Relaxed);
+    }
+    for vm in SPI_QUEUED_AT.iter() {
+        for t in vm.iter() {
+            t.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+// ── Physical → virtual interrupt passthrough ────────────────────────
+
+/// Maximum number of physical interrupts assigned to guests
+pub const MAX_PASSTHROUGH_IRQS: usize = 16;
+
+/// One physical INTID assigned to a VM (phys == 0 means the slot is free)
+pub struct PassthroughIrq {
+    pub phys_intid: AtomicU32,
+    pub virt_intid: AtomicU32,
+    pub vm_id: AtomicUsize,
+}
+
+impl PassthroughIrq {
+    pub const fn new() -> Self {
+        Self {
+            phys_intid: AtomicU32::new(0),
+            virt_intid: AtomicU32::new(0),
+            vm_id: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Default for PassthroughIrq {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Passthrough table, consulted by handle_irq_exception() for unknown INTIDs.
+pub static PASSTHROUGH_IRQS: [PassthroughIrq; MAX_PASSTHROUGH_IRQS] =
+    [const { PassthroughIrq::new() }; MAX_PASSTHROUGH_IRQS];
+
+/// Record a phys → virt interrupt mapping for a VM.
+///
+/// Re-registering the same physical INTID for the same VM updates its
+/// mapping in place; one owned by another VM is refused.
+pub fn register_passthrough_irq(
+    vm_id: usize,
+    phys_intid: u32,
+    virt_intid: u32,
+) -> Result<(), &'static str> {
+    if phys_intid == 0 {
+        return Err("Invalid physical INTID");
+    }
+    let existing = PASSTHROUGH_IRQS
+        .iter()
+        .find(|e| e.phys_intid.load(Ordering::Acquire) == phys_intid);
+    if existing.is_some_and(|e| e.vm_id.load(Ordering::Relaxed) != vm_id) {
+        return Err("Physical INTID assigned to another VM");
+    }
+    let slot = existing
+        .or_else(|| {
+            PASSTHROUGH_IRQS
+                .iter()
+                .find(|e| e.phys_intid.load(Ordering::Acquire) == 0)
+        })
+        .ok_or("Passthrough IRQ table full")?;
+    slot.virt_intid.store(virt_intid, Ordering::Relaxed);
+    slot.vm_id.store(vm_id, Ordering::Relaxed);
+    // Release: virt/vm must be visible before the slot becomes live
+    slot.phys_intid.store(phys_intid, Ordering::Release);
+    Ok(())
+}
+
+/// Look up a physical INTID. Returns `(vm_id, virt_intid)` if assigned.
+pub fn lookup_passthrough_irq(phys_intid: u32) -> Option<(usize, u32)> {
+    PASSTHROUGH_IRQS
+        .iter()
+        .find(|e| e.phys_intid.load(Ordering::Acquire) == phys_intid)
+        .map(|e| {
+            (
+                e.vm_id.load(Ordering::Relaxed),
+                e.virt_intid.load(Ordering::Relaxed),
+            )
+        })
+}
+
+/// Physical INTID VM `vm_id` receives as `virt_intid`, if any.
+pub fn passthrough_phys_intid(vm_id: usize, virt_intid: u32) -> Option<u32> {
+    PASSTHROUGH_IRQS
+        .iter()
+        .find(|e| {
+            e.phys_intid.load(Ordering::Acquire) != 0
+                && e.vm_id.load(Ordering::Relaxed) == vm_id
+                && e.virt_intid.load(Ordering::Relaxed) == virt_intid
+        })
+        .map(|e| e.phys_intid.load(Ordering::Relaxed))
+}
+
+/// Set bit `intid` of the physical GICD write-1 bitmap at `off`.
+fn write_gicd_bit(off: u64, intid: u32) {
+    let gicd = crate::dtb::platform_info().gicd_base;
+    let reg = (intid / 32) as u64 * 4;
+    // SAFETY: EL2 accesses to the physical GICD bypass Stage-2.
+    unsafe {
+        core::ptr::write_volatile((gicd + off + reg) as *mut u32, 1 << (intid % 32));
+    }
+}
+
+/// Mask a passthrough INTID whose delivery was deferred to a queued SPI.
+///
+/// A level-triggered device keeps its line asserted until the guest
+/// services it; left enabled, the deactivated physical INTID would fire
+/// again at once and re-queue the SPI until the owner VM runs.
+pub fn mask_passthrough_irq(phys_intid: u32) {
+    write_gicd_bit(crate::platform::GICD_ICENABLER_OFF, phys_intid);
+}
+
+/// Unmask a passthrough INTID as its queued SPI enters a List Register
+/// with HW=1.
+///
+/// The physical INTID is made active first, so an asserted line cannot
+/// fire again before the guest's EOI deactivates it through the HW link.
+pub fn unmask_passthrough_irq(phys_intid: u32) {
+    write_gicd_bit(crate::platform::GICD_ISACTIVER_OFF, phys_intid);
+    write_gicd_bit(crate::platform::GICD_ISENABLER_OFF, phys_intid);
+}
+
+/// Drop the passthrough mapping of `phys_intid`, if any.
+pub fn unregister_passthrough_irq(phys_intid: u32) {
+    if let Some(e) = PASSTHROUGH_IRQS
+        .iter()
+        .find(|e| e.phys_intid.load(Ordering::Acquire) == phys_intid)
+    {
+        e.phys_intid.store(0, Ordering::Release);
+    }
+}
+
+/// Drop all passthrough mappings owned by a VM and disable their physical
+/// SPIs at the GICD, so a torn-down VM's device stops interrupting. An
+/// INTID left active by an HW=1 injection the guest never EOIed is
+/// deactivated too.
+pub fn clear_passthrough_irqs(vm_id: usize) {
+    for e in PASSTHROUGH_IRQS.iter() {
+        let phys = e.phys_intid.load(Ordering::Acquire);
+        if phys != 0 && e.vm_id.load(Ordering::Relaxed) == vm_id {
+            write_gicd_bit(crate::platform::GICD_ICENABLER_OFF, phys);
+            write_gicd_bit(crate::platform::GICD_ICACTIVER_OFF, phys);
+            e.phys_intid.store(0, Ordering::Release);
+        }
+    }
+}
+
 // ── UART RX pending ring buffer ─────────────────────────────────────
 // Filled by handle_irq_exception (INTID 33), drained by run loop.
 
@@ -501,3 +1555,105 @@ impl UartRxRing {
 }
 
 pub static UART_RX: UartRxRing = UartRxRing::new();
+
+// ── VM lifecycle event channel ──────────────────────────────────────
+// Pushed from VM state transitions and the shutdown/crash paths (any pCPU),
+// drained by a host-side consumer instead of polling VM state.
+
+/// VM state reported on the lifecycle channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LifecycleState {
+    /// `Vm::new()` (re)created the VM
+    Created,
+    /// Configured and runnable, or returned from a run loop
+    Ready,
+    /// Entered a run loop
+    Running,
+    /// Paused (explicitly or for a checkpoint)
+    Paused,
+    /// Stopped by the host (`Vm::stop()`)
+    Stopped,
+    /// Guest requested PSCI SYSTEM_OFF
+    ShutDown,
+    /// Terminated by the hypervisor after an exception storm
+    Crashed,
+}
+
+/// One lifecycle transition of VM `vm_id`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LifecycleEvent {
+    pub vm_id: usize,
+    pub state: LifecycleState,
+}
+
+const LIFECYCLE_RING_SIZE: usize = 32;
+
+struct LifecycleRing {
+    buf: [LifecycleEvent; LIFECYCLE_RING_SIZE],
+    head: usize,
+    len: usize,
+}
+
+/// MPSC ring of `LifecycleEvent`s.
+///
+/// Producers may run on any pCPU, so the ring sits behind a spinlock. When
+/// it is full the oldest event is overwritten: a slow consumer loses
+/// history, never the latest state. Overwritten events are counted.
+pub struct LifecycleChannel {
+    ring: crate::sync::SpinLock<LifecycleRing>,
+    dropped: AtomicU32,
+}
+
+impl LifecycleChannel {
+    pub const fn new() -> Self {
+        Self {
+            ring: crate::sync::SpinLock::new(LifecycleRing {
+                buf: [LifecycleEvent {
+                    vm_id: 0,
+                    state: LifecycleState::Created,
+                }; LIFECYCLE_RING_SIZE],
+                head: 0,
+                len: 0,
+            }),
+            dropped: AtomicU32::new(0),
+        }
+    }
+
+    /// Record that VM `vm_id` entered `state`.
+    pub fn push(&self, vm_id: usize, state: LifecycleState) {
+        let mut ring = self.ring.lock();
+        if ring.len == LIFECYCLE_RING_SIZE {
+            ring.head = (ring.head + 1) % LIFECYCLE_RING_SIZE;
+            ring.len -= 1;
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        let tail = (ring.head + ring.len) % LIFECYCLE_RING_SIZE;
+        ring.buf[tail] = LifecycleEvent { vm_id, state };
+        ring.len += 1;
+    }
+
+    /// Take the oldest pending event.
+    pub fn pop(&self) -> Option<LifecycleEvent> {
+        let mut ring = self.ring.lock();
+        if ring.len == 0 {
+            return None;
+        }
+        let event = ring.buf[ring.head];
+        ring.head = (ring.head + 1) % LIFECYCLE_RING_SIZE;
+        ring.len -= 1;
+        Some(event)
+    }
+
+    /// Events overwritten before they were drained.
+    pub fn dropped(&self) -> u32 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for LifecycleChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub static LIFECYCLE: LifecycleChannel = LifecycleChannel::new();
@@ -4,6 +4,7 @@
 //! real ELF binaries as guests.
 
 use crate::arch::aarch64::defs::*;
+use crate::arch::aarch64::VcpuContext;
 use crate::platform;
 use crate::uart_put_hex;
 use crate::uart_puts;
@@ -18,6 +19,101 @@ pub enum GuestType {
     Linux,
 }
 
+/// Register convention for handing control to the guest at its entry point
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BootProtocol {
+    /// Linux arm64 boot protocol (Documentation/arch/arm64/booting.rst):
+    /// x0 = DTB physical address, x1-x3 = 0 (reserved)
+    #[default]
+    LinuxArm64,
+    /// No register contract; the guest only relies on PC/SP (Zephyr ELF)
+    Bare,
+}
+
+impl BootProtocol {
+    /// Set the entry registers in `ctx` according to this convention.
+    ///
+    /// PC, SP and SPSR are not touched; callers set those separately.
+    pub fn setup_registers(&self, ctx: &mut VcpuContext, config: &GuestConfig) {
+        match self {
+            BootProtocol::LinuxArm64 => {
+                ctx.gp_regs.x0 = config.dtb_addr;
+                ctx.gp_regs.x1 = 0;
+                ctx.gp_regs.x2 = 0;
+                ctx.gp_regs.x3 = 0;
+            }
+            BootProtocol::Bare => {}
+        }
+    }
+}
+
+/// ARM64 Image magic at offset 0x38 ("ARM\x64" little-endian)
+pub const ARM64_IMAGE_MAGIC: u32 = 0x644d_5241;
+
+/// Largest text_offset honoured when computing the entry point; anything
+/// else is treated as a zero offset
+const MAX_TEXT_OFFSET: u64 = 0x10_0000;
+
+/// Fields of the 64-byte ARM64 Image header
+/// (Documentation/arch/arm64/booting.rst)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arm64ImageHeader {
+    /// Magic at offset 0x38, always `ARM64_IMAGE_MAGIC` once parsed
+    pub magic: u32,
+    /// Image load offset from a 2MB aligned base (offset 0x08)
+    pub text_offset: u64,
+    /// Effective image size from the load offset; 0 on pre-3.17 kernels
+    /// (offset 0x10)
+    pub image_size: u64,
+    /// Kernel flags: endianness, page size, placement (offset 0x18)
+    pub flags: u64,
+}
+
+impl Arm64ImageHeader {
+    /// Read the header of an Image loaded at `addr`.
+    ///
+    /// Returns None if the magic does not match.
+    pub fn parse(addr: u64) -> Option<Self> {
+        // SAFETY: the caller's kernel image is identity-mapped at `addr`
+        // and at least one header (64 bytes) long
+        let (magic, text_offset, image_size, flags) = unsafe {
+            (
+                core::ptr::read_volatile((addr + 0x38) as *const u32),
+                core::ptr::read_volatile((addr + 0x08) as *const u64),
+                core::ptr::read_volatile((addr + 0x10) as *const u64),
+                core::ptr::read_volatile((addr + 0x18) as *const u64),
+            )
+        };
+        if magic != ARM64_IMAGE_MAGIC {
+            return None;
+        }
+        Some(Self {
+            magic,
+            text_offset,
+            image_size,
+            flags,
+        })
+    }
+
+    /// Offset of the entry point from the load address.
+    pub fn entry_offset(&self) -> u64 {
+        if self.text_offset != 0 && self.text_offset < MAX_TEXT_OFFSET {
+            self.text_offset
+        } else {
+            0
+        }
+    }
+
+    /// Whether the image loaded at `addr` ends at or below `ram_end`.
+    /// An unknown (zero) image_size always fits.
+    pub fn fits(&self, addr: u64, ram_end: u64) -> bool {
+        self.image_size == 0
+            || (addr + self.entry_offset())
+                .checked_add(self.image_size)
+                .is_some_and(|end| end <= ram_end)
+    }
+}
+
 /// Guest configuration
 ///
 /// Defines memory layout and entry point for a guest VM.
@@ -32,6 +128,8 @@ pub struct GuestConfig {
     pub entry_point: u64,
     /// DTB (device tree blob) address for Linux
     pub dtb_addr: u64,
+    /// Entry register convention
+    pub boot_protocol: BootProtocol,
 }
 
 impl GuestConfig {
@@ -102,6 +200,7 @@ impl GuestConfig {
             mem_size: platform::ZEPHYR_MEM_SIZE,
             entry_point,
             dtb_addr: 0, // Zephyr doesn't need DTB
+            boot_protocol: BootProtocol::Bare,
         }
     }
 
@@ -111,43 +210,45 @@ impl GuestConfig {
         let mem_start = platform::GUEST_RAM_BASE;
         let kernel_addr = platform::GUEST_LOAD_ADDR;
 
-        // Parse ARM64 Image header to find entry point
-        let entry_point = unsafe {
-            let header = kernel_addr as *const u8;
-
-            // Debug: print header
-            uart_puts(b"[LINUX] First 64 bytes of Image header:\n");
-            for row in 0..4 {
-                uart_puts(b"  ");
-                for col in 0..16 {
-                    let byte = *header.add(row * 16 + col);
-                    let hex_chars = b"0123456789abcdef";
-                    uart_puts(&[
-                        hex_chars[(byte >> 4) as usize],
-                        hex_chars[(byte & 0xf) as usize],
-                        b' ',
-                    ]);
-                }
-                uart_puts(b"\n");
+        // Stage-2 mapping must cover from GUEST_RAM_BASE through the end of
+        // the DTB-declared memory region (GUEST_LOAD_ADDR + LINUX_MEM_SIZE).
+        // The DTB says memory starts at GUEST_LOAD_ADDR (0x48000000), but the
+        // Stage-2 mapping starts from GUEST_RAM_BASE (0x40000000) to also cover
+        // the DTB itself (at 0x47000000).
+        let stage2_size = (kernel_addr - mem_start) + platform::LINUX_MEM_SIZE;
+
+        // Debug: print header
+        uart_puts(b"[LINUX] First 64 bytes of Image header:\n");
+        let header = kernel_addr as *const u8;
+        for row in 0..4 {
+            uart_puts(b"  ");
+            for col in 0..16 {
+                let byte = unsafe { *header.add(row * 16 + col) };
+                let hex_chars = b"0123456789abcdef";
+                uart_puts(&[
+                    hex_chars[(byte >> 4) as usize],
+                    hex_chars[(byte & 0xf) as usize],
+                    b' ',
+                ]);
             }
+            uart_puts(b"\n");
+        }
 
-            // Check for ARM64 magic at offset 0x38
-            let magic = core::ptr::read_volatile((kernel_addr + 0x38) as *const u32);
-            if magic == 0x644d5241 {
-                // "ARMd" little-endian
+        // Parse ARM64 Image header to find entry point
+        let entry_point = match Arm64ImageHeader::parse(kernel_addr) {
+            Some(image) => {
                 uart_puts(b"[LINUX] ARM64 Image format detected\n");
-
-                let text_offset = core::ptr::read_volatile((kernel_addr + 0x08) as *const u64);
                 uart_puts(b"[LINUX] text_offset = 0x");
-                uart_put_hex(text_offset);
+                uart_put_hex(image.text_offset);
+                uart_puts(b", image_size = 0x");
+                uart_put_hex(image.image_size);
                 uart_puts(b"\n");
-
-                if text_offset != 0 && text_offset < 0x100000 {
-                    kernel_addr + text_offset
-                } else {
-                    kernel_addr
+                if !image.fits(kernel_addr, mem_start + stage2_size) {
+                    uart_puts(b"[LINUX] WARNING: image_size exceeds mapped guest RAM\n");
                 }
-            } else {
+                kernel_addr + image.entry_offset()
+            }
+            None => {
                 uart_puts(b"[LINUX] WARNING: No ARM64 magic, using kernel address\n");
                 kernel_addr
             }
@@ -157,19 +258,13 @@ impl GuestConfig {
         uart_put_hex(entry_point);
         uart_puts(b"\n");
 
-        // Stage-2 mapping must cover from GUEST_RAM_BASE through the end of
-        // the DTB-declared memory region (GUEST_LOAD_ADDR + LINUX_MEM_SIZE).
-        // The DTB says memory starts at GUEST_LOAD_ADDR (0x48000000), but the
-        // Stage-2 mapping starts from GUEST_RAM_BASE (0x40000000) to also cover
-        // the DTB itself (at 0x47000000).
-        let stage2_size = (kernel_addr - mem_start) + platform::LINUX_MEM_SIZE;
-
         Self {
             guest_type: GuestType::Linux,
             load_addr: mem_start,
             mem_size: stage2_size,
             entry_point,
             dtb_addr,
+            boot_protocol: BootProtocol::LinuxArm64,
         }
     }
 
@@ -183,19 +278,8 @@ impl GuestConfig {
         let kernel_addr = platform::VM1_GUEST_LOAD_ADDR;
 
         // Parse ARM64 Image header to find entry point
-        let entry_point = unsafe {
-            let magic = core::ptr::read_volatile((kernel_addr + 0x38) as *const u32);
-            if magic == 0x644d5241 {
-                let text_offset = core::ptr::read_volatile((kernel_addr + 0x08) as *const u64);
-                if text_offset != 0 && text_offset < 0x100000 {
-                    kernel_addr + text_offset
-                } else {
-                    kernel_addr
-                }
-            } else {
-                kernel_addr
-            }
-        };
+        let entry_point = Arm64ImageHeader::parse(kernel_addr)
+            .map_or(kernel_addr, |image| kernel_addr + image.entry_offset());
 
         // Stage-2 size: from mem_start through kernel + VM1 mem size
         let stage2_size = (kernel_addr - mem_start) + platform::VM1_LINUX_MEM_SIZE;
@@ -206,8 +290,130 @@ impl GuestConfig {
             mem_size: stage2_size,
             entry_point,
             dtb_addr,
+            boot_protocol: BootProtocol::LinuxArm64,
+        }
+    }
+}
+
+/// Free bytes that must follow a guest DTB for `set_initrd` to add a
+/// `/chosen` node and both initrd properties to it
+pub const INITRD_DTB_ROOM: usize = 128;
+
+/// Largest guest DTB accepted (Documentation/arch/arm64/booting.rst)
+const MAX_GUEST_DTB_SIZE: usize = 2 * 1024 * 1024;
+
+/// Point the guest at an initrd of `initrd_size` bytes placed at
+/// `initrd_ipa`: set `linux,initrd-start`/`linux,initrd-end` (64-bit, end
+/// exclusive) in `/chosen` of the guest DTB at `dtb_addr`, creating them
+/// if missing.
+///
+/// The DTB may grow by up to `INITRD_DTB_ROOM` bytes.
+pub fn set_initrd(dtb_addr: u64, initrd_ipa: u64, initrd_size: u64) -> Result<(), &'static str> {
+    let end = initrd_ipa
+        .checked_add(initrd_size)
+        .ok_or("initrd range overflows")?;
+    let blob = guest_dtb_mut(dtb_addr, INITRD_DTB_ROOM)?;
+    crate::dtb::set_chosen_prop(blob, "linux,initrd-start", &initrd_ipa.to_be_bytes())?;
+    crate::dtb::set_chosen_prop(blob, "linux,initrd-end", &end.to_be_bytes())
+}
+
+/// Free bytes that must follow a guest DTB for `set_framebuffer` to add
+/// the framebuffer node, its property names and a memory reservation
+pub const FRAMEBUFFER_DTB_ROOM: usize = 256;
+
+/// Describe `fb` to the guest: add a `simple-framebuffer` node and a
+/// memory reservation for it to the guest DTB at `dtb_addr`.
+///
+/// The DTB may grow by up to `FRAMEBUFFER_DTB_ROOM` bytes.
+pub fn set_framebuffer(
+    dtb_addr: u64,
+    fb: &crate::devices::framebuffer::VirtualFramebuffer,
+) -> Result<(), &'static str> {
+    fb.add_to_dtb(guest_dtb_mut(dtb_addr, FRAMEBUFFER_DTB_ROOM)?)
+}
+
+/// Give `vm` the boot framebuffer (`FRAMEBUFFER_WIDTH` x
+/// `FRAMEBUFFER_HEIGHT`, x8r8g8b8) at `base` and describe it in its DTB at
+/// `dtb_addr`.
+pub fn attach_boot_framebuffer(vm: &mut Vm, dtb_addr: u64, base: u64) -> Result<(), &'static str> {
+    let fb = crate::devices::framebuffer::VirtualFramebuffer::new(
+        base,
+        platform::FRAMEBUFFER_WIDTH,
+        platform::FRAMEBUFFER_HEIGHT,
+        crate::devices::framebuffer::PixelFormat::X8R8G8B8,
+    )?;
+    vm.attach_framebuffer(fb)?;
+    set_framebuffer(dtb_addr, &fb)
+}
+
+/// The guest DTB at `dtb_addr` plus `room` free bytes after it.
+fn guest_dtb_mut(dtb_addr: u64, room: usize) -> Result<&'static mut [u8], &'static str> {
+    // SAFETY: the guest DTB is identity-mapped at `dtb_addr`, and (once the
+    // magic matches) followed by `room` bytes it may grow into
+    unsafe {
+        let header = dtb_addr as *const u32;
+        let magic = u32::from_be(core::ptr::read_volatile(header));
+        let total = u32::from_be(core::ptr::read_volatile(header.add(1))) as usize;
+        if magic != 0xD00D_FEED || total > MAX_GUEST_DTB_SIZE {
+            return Err("no valid DTB at dtb_addr");
         }
+        Ok(core::slice::from_raw_parts_mut(
+            dtb_addr as *mut u8,
+            total + room,
+        ))
+    }
+}
+
+/// Start `vm`'s virtual counter at zero: take the physical count now as the
+/// CNTVOFF_EL2 of its existing vCPUs and record it in the VM's global state,
+/// where vCPUs brought up later by PSCI CPU_ON pick it up. Returns the offset.
+pub fn start_guest_counter(vm: &mut Vm) -> u64 {
+    let offset = crate::time::now_ticks();
+    crate::global::vm_state(vm.id())
+        .counter_offset
+        .store(offset, core::sync::atomic::Ordering::Release);
+    for id in 0..crate::vm::MAX_VCPUS {
+        if let Some(vcpu) = vm.vcpu_mut(id) {
+            vcpu.set_counter_offset(offset);
+        }
+    }
+    offset
+}
+
+/// Attach virtio-blk (disk image at `disk_base`), virtio-net,
+/// virtio-input, the read-only CD-ROM (image at `cdrom_base`), the
+/// data-partition disk (image at `data_base`, slot `VIRTIO_SLOT_DATA`),
+/// virtio-vsock and virtio-balloon to `vm`.
+///
+/// All go to `DEVICES[vm.id()]`, so a device can never land in another
+/// VM's manager.
+pub fn attach_virtio_devices(vm: &Vm, disk_base: u64, cdrom_base: u64, data_base: u64) {
+    let vm_id = vm.id();
+    let devices = &crate::global::DEVICES[vm_id];
+    devices.attach_virtio_blk(disk_base, platform::VIRTIO_DISK_SIZE);
+    devices.attach_virtio_net(vm_id);
+    devices.attach_virtio_input();
+    devices.attach_virtio_cdrom(cdrom_base, platform::VIRTIO_CDROM_SIZE);
+    if let Err(e) = devices.attach_virtio_blk_at(
+        platform::VIRTIO_SLOT_DATA,
+        data_base,
+        platform::VIRTIO_DATA_DISK_SIZE,
+    ) {
+        uart_puts(b"[GUEST] Data disk not attached: ");
+        uart_puts(e.as_bytes());
+        uart_puts(b"\n");
     }
+    devices.attach_virtio_vsock(vm_id);
+    devices.attach_virtio_balloon();
+}
+
+/// Attach the emulated non-virtio devices the guest DTBs describe (the
+/// temperature/voltage sensor and the scheduler-statistics bank) to
+/// `DEVICES[vm.id()]`.
+pub fn attach_platform_devices(vm: &Vm) {
+    let devices = &crate::global::DEVICES[vm.id()];
+    devices.attach_sensor();
+    devices.attach_sched_stats();
 }
 
 /// Boot a guest VM with the given configuration
@@ -249,21 +455,16 @@ pub fn run_guest(config: &GuestConfig) -> Result<(), &'static str> {
             vcpu.context_mut().pc = config.entry_point;
             vcpu.context_mut().sp = guest_sp;
 
-            // Set up Linux boot protocol if this is a Linux guest
-            if config.guest_type == GuestType::Linux {
+            // Set up entry registers for the guest's boot protocol
+            if config.boot_protocol == BootProtocol::LinuxArm64 {
                 uart_puts(b"[GUEST] Setting up Linux boot protocol...\n");
                 uart_puts(b"[GUEST] x0 (DTB) = 0x");
                 uart_put_hex(config.dtb_addr);
                 uart_puts(b"\n");
-
-                // Linux ARM64 boot protocol:
-                // x0 = physical address of device tree blob (DTB)
-                // x1-x3 = 0 (reserved)
-                vcpu.context_mut().gp_regs.x0 = config.dtb_addr;
-                vcpu.context_mut().gp_regs.x1 = 0;
-                vcpu.context_mut().gp_regs.x2 = 0;
-                vcpu.context_mut().gp_regs.x3 = 0;
             }
+            config
+                .boot_protocol
+                .setup_registers(vcpu.context_mut(), config);
         }
         Err(e) => {
             uart_puts(b"[GUEST] Failed to create vCPU: ");
@@ -272,6 +473,7 @@ pub fn run_guest(config: &GuestConfig) -> Result<(), &'static str> {
             return Err(e);
         }
     }
+    start_guest_counter(&mut vm);
 
     // Initialize guest timer access
     uart_puts(b"[GUEST] Configuring virtual timer for guest...\n");
@@ -347,15 +549,22 @@ pub fn run_guest(config: &GuestConfig) -> Result<(), &'static str> {
         }
     }
 
-    // Attach virtio-blk device (backed by in-memory disk image loaded by QEMU)
-    if config.guest_type == GuestType::Linux {
-        crate::global::DEVICES[0]
-            .attach_virtio_blk(platform::VIRTIO_DISK_ADDR, platform::VIRTIO_DISK_SIZE);
-    }
-
-    // Attach virtio-net device
+    // Attach virtio-blk (backed by in-memory disk image loaded by QEMU) + virtio-net
     if config.guest_type == GuestType::Linux {
-        crate::global::DEVICES[0].attach_virtio_net(0);
+        attach_virtio_devices(
+            &vm,
+            platform::VIRTIO_DISK_ADDR,
+            platform::VIRTIO_CDROM_ADDR,
+            platform::VIRTIO_DATA_DISK_ADDR,
+        );
+        attach_platform_devices(&vm);
+        if let Err(e) =
+            attach_boot_framebuffer(&mut vm, config.dtb_addr, platform::FRAMEBUFFER_ADDR)
+        {
+            uart_puts(b"[GUEST] Framebuffer not attached: ");
+            uart_puts(e.as_bytes());
+            uart_puts(b"\n");
+        }
     }
 
     // Enable physical UART RX interrupt (INTID 33) so the hypervisor
@@ -547,7 +756,9 @@ pub fn run_multi_vm_guests() -> Result<(), &'static str> {
         Ok(vcpu) => {
             vcpu.context_mut().pc = config0.entry_point;
             vcpu.context_mut().sp = guest_sp0;
-            vcpu.context_mut().gp_regs.x0 = config0.dtb_addr;
+            config0
+                .boot_protocol
+                .setup_registers(vcpu.context_mut(), &config0);
             vcpu.context_mut().spsr_el2 = SPSR_EL1H_DAIF_MASKED;
         }
         Err(e) => return Err(e),
@@ -556,11 +767,17 @@ pub fn run_multi_vm_guests() -> Result<(), &'static str> {
         vcpu.arch_state_mut().sctlr_el1 = 0x30D0_0800;
         vcpu.arch_state_mut().cpacr_el1 = 3 << 20;
     }
-
-    // Attach virtio-blk to VM 0
-    crate::global::DEVICES[0]
-        .attach_virtio_blk(platform::VIRTIO_DISK_ADDR, platform::VIRTIO_DISK_SIZE);
-    crate::global::DEVICES[0].attach_virtio_net(0);
+    start_guest_counter(&mut vm0);
+
+    // Attach virtio-blk + virtio-net to VM 0
+    attach_virtio_devices(
+        &vm0,
+        platform::VIRTIO_DISK_ADDR,
+        platform::VIRTIO_CDROM_ADDR,
+        platform::VIRTIO_DATA_DISK_ADDR,
+    );
+    attach_platform_devices(&vm0);
+    attach_boot_framebuffer(&mut vm0, config0.dtb_addr, platform::FRAMEBUFFER_ADDR)?;
 
     // --- VM 1 setup ---
     let config1 = GuestConfig::linux_vm1();
@@ -581,7 +798,9 @@ pub fn run_multi_vm_guests() -> Result<(), &'static str> {
         Ok(vcpu) => {
             vcpu.context_mut().pc = config1.entry_point;
             vcpu.context_mut().sp = guest_sp1;
-            vcpu.context_mut().gp_regs.x0 = config1.dtb_addr;
+            config1
+                .boot_protocol
+                .setup_registers(vcpu.context_mut(), &config1);
             vcpu.context_mut().spsr_el2 = SPSR_EL1H_DAIF_MASKED;
         }
         Err(e) => return Err(e),
@@ -590,11 +809,17 @@ pub fn run_multi_vm_guests() -> Result<(), &'static str> {
         vcpu.arch_state_mut().sctlr_el1 = 0x30D0_0800;
         vcpu.arch_state_mut().cpacr_el1 = 3 << 20;
     }
-
-    // Attach virtio-blk to VM 1 (different disk image address)
-    crate::global::DEVICES[1]
-        .attach_virtio_blk(platform::VM1_VIRTIO_DISK_ADDR, platform::VIRTIO_DISK_SIZE);
-    crate::global::DEVICES[1].attach_virtio_net(1);
+    start_guest_counter(&mut vm1);
+
+    // Attach virtio-blk (different disk image address) + virtio-net to VM 1
+    attach_virtio_devices(
+        &vm1,
+        platform::VM1_VIRTIO_DISK_ADDR,
+        platform::VM1_VIRTIO_CDROM_ADDR,
+        platform::VM1_VIRTIO_DATA_DISK_ADDR,
+    );
+    attach_platform_devices(&vm1);
+    attach_boot_framebuffer(&mut vm1, config1.dtb_addr, platform::VM1_FRAMEBUFFER_ADDR)?;
 
     // Restore VM 0's Stage-2 as active (run_multi_vm will switch as needed)
     unsafe {
@@ -707,7 +932,7 @@ fn test_ffa_vm_to_vm_integration(vm0_vttbr: u64) {
     ctx.gp_regs.x4 = 1; // 1 page
     ctx.gp_regs.x5 = 2; // receiver = VM1 (partition ID 2)
     let cont = ffa::proxy::handle_ffa_call(&mut ctx);
-    let handle = ctx.gp_regs.x2 | (ctx.gp_regs.x3 << 32);
+    let handle = ffa::mem_handle_from_result(&ctx);
 
     if cont && ctx.gp_regs.x0 == ffa::FFA_SUCCESS_32 && handle > 0 {
         uart_puts(b"  [PASS] 1: MEM_SHARE VM0->VM1 handle=0x");
@@ -776,8 +1001,7 @@ fn test_ffa_vm_to_vm_integration(vm0_vttbr: u64) {
     {
         let mut ctx = VcpuContext::default();
         ctx.gp_regs.x0 = ffa::FFA_MEM_RETRIEVE_REQ_32;
-        ctx.gp_regs.x1 = handle & 0xFFFF_FFFF;
-        ctx.gp_regs.x2 = handle >> 32;
+        ffa::set_mem_handle_args(&mut ctx, handle);
         let cont = ffa::proxy::handle_ffa_call(&mut ctx);
         if cont && ctx.gp_regs.x0 == ffa::FFA_MEM_RETRIEVE_RESP {
             uart_puts(b"  [PASS] 4: MEM_RETRIEVE_REQ by VM1\n");
@@ -792,7 +1016,7 @@ fn test_ffa_vm_to_vm_integration(vm0_vttbr: u64) {
 
     // ── Test 5: Verify receiver PTE SW bits = SharedBorrowed ────────
     {
-        let walker = Stage2Walker::new(vm1_l0_pa);
+        let walker = Stage2Walker::for_vm(1);
         let sw = walker.read_sw_bits(TEST_IPA);
         if sw == Some(PageOwnership::SharedBorrowed as u8) {
             uart_puts(b"  [PASS] 5: Receiver PTE SW = SharedBorrowed\n");
@@ -807,7 +1031,7 @@ fn test_ffa_vm_to_vm_integration(vm0_vttbr: u64) {
 
     // ── Test 6: Verify receiver S2AP = RW ───────────────────────────
     {
-        let walker = Stage2Walker::new(vm1_l0_pa);
+        let walker = Stage2Walker::for_vm(1);
         let s2ap = walker.read_s2ap(TEST_IPA);
         let expected = (S2AP_RW >> S2AP_SHIFT) as u8;
         if s2ap == Some(expected) {
@@ -825,8 +1049,7 @@ fn test_ffa_vm_to_vm_integration(vm0_vttbr: u64) {
     {
         let mut ctx = VcpuContext::default();
         ctx.gp_regs.x0 = ffa::FFA_MEM_RELINQUISH;
-        ctx.gp_regs.x1 = handle & 0xFFFF_FFFF;
-        ctx.gp_regs.x2 = handle >> 32;
+        ffa::set_mem_handle_args(&mut ctx, handle);
         let cont = ffa::proxy::handle_ffa_call(&mut ctx);
         if cont && ctx.gp_regs.x0 == ffa::FFA_SUCCESS_32 {
             uart_puts(b"  [PASS] 7: MEM_RELINQUISH by VM1\n");
@@ -841,7 +1064,7 @@ fn test_ffa_vm_to_vm_integration(vm0_vttbr: u64) {
 
     // ── Test 8: Verify receiver page unmapped ───────────────────────
     {
-        let walker = Stage2Walker::new(vm1_l0_pa);
+        let walker = Stage2Walker::for_vm(1);
         let sw = walker.read_sw_bits(TEST_IPA);
         if sw.is_none() {
             uart_puts(b"  [PASS] 8: Receiver page unmapped after RELINQUISH\n");
@@ -869,8 +1092,7 @@ fn test_ffa_vm_to_vm_integration(vm0_vttbr: u64) {
     {
         let mut ctx = VcpuContext::default();
         ctx.gp_regs.x0 = ffa::FFA_MEM_RECLAIM;
-        ctx.gp_regs.x1 = handle & 0xFFFF_FFFF;
-        ctx.gp_regs.x2 = handle >> 32;
+        ffa::set_mem_handle_args(&mut ctx, handle);
         let cont = ffa::proxy::handle_ffa_call(&mut ctx);
         if cont && ctx.gp_regs.x0 == ffa::FFA_SUCCESS_32 {
             uart_puts(b"  [PASS] 9: MEM_RECLAIM by VM0\n");
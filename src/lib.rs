@@ -1,20 +1,26 @@
 #![no_std]
 
 pub mod arch;
+pub mod cache_maint;
 pub mod devices;
+pub mod dirty_log;
 pub mod dtb;
 pub mod ffa;
 pub mod global;
 pub mod guest_loader;
 pub mod manifest;
 pub mod mm;
+pub mod pcpu_pin;
 pub mod spmc_handler;
 pub mod sp_context;
 pub mod secure_stage2;
+pub mod shared_buffer;
 pub mod percpu;
 pub mod platform;
+pub mod pv_console;
 pub mod scheduler;
 pub mod sync;
+pub mod time;
 pub mod uart;
 pub mod vcpu;
 pub mod vcpu_interrupt;
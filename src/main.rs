@@ -57,7 +57,7 @@ pub extern "C" fn rust_main(dtb_addr: usize) -> ! {
     exception::init();
     uart_puts_local(b"[INIT] Exception handling initialized\n");
 
-    // Initialize GIC - try GICv3 first, fall back to GICv2
+    // Initialize GIC - GICv3 unless the DTB describes a GICv2 (or ICC_* is absent)
     hypervisor::arch::aarch64::peripherals::gicv3::init();
 
     // Initialize FF-A proxy (probe for real SPMC at EL3)
@@ -111,6 +111,33 @@ pub extern "C" fn rust_main(dtb_addr: usize) -> ! {
     // Run the VM scheduler integration test
     tests::run_vm_scheduler_test();
 
+    // Run the multi-VM epoch scheduler test
+    tests::run_epoch_scheduler_test();
+
+    // Run the scheduler-stats device test
+    tests::run_sched_stats_test();
+
+    // Run the PSCI CPU_OFF test
+    tests::run_psci_cpu_off_test();
+    tests::run_psci_cpu_on_test();
+
+    // Run the reboot counter test
+    tests::run_reboot_counter_test();
+
+    // Run the RTC alarm wake (SYSTEM_SUSPEND) test
+    #[cfg(not(feature = "multi_pcpu"))]
+    tests::run_rtc_wake_test();
+
+    // Run the preemption timer disarm test
+    #[cfg(not(feature = "multi_pcpu"))]
+    tests::run_preemption_disarm_test();
+
+    // Run the exclusive pCPU (hypercall 15) test
+    tests::run_exclusive_pcpu_test();
+
+    // Run the VM lifecycle event channel test
+    tests::run_lifecycle_test();
+
     // Run the MMIO device emulation test
     tests::run_mmio_test();
 
@@ -129,6 +156,15 @@ pub extern "C" fn rust_main(dtb_addr: usize) -> ! {
     // Run the simple guest test
     tests::run_simple_guest_test();
 
+    // Run the per-vCPU counter offset test
+    tests::run_counter_offset_test();
+
+    // Run the fresh-vCPU FP/SIMD zeroing test
+    tests::run_fp_reset_test();
+
+    // Run the per-vCPU FP/SIMD switch test
+    tests::run_fp_switch_test();
+
     // Run the MMIO instruction decode test
     tests::run_decode_test();
 
@@ -138,6 +174,9 @@ pub extern "C" fn rust_main(dtb_addr: usize) -> ! {
     // Run the GICR emulation test
     tests::run_gicr_test();
 
+    // Run the GICR wake timeout test
+    tests::run_gicr_wake_test();
+
     // Run the global state test
     tests::run_global_test();
 
@@ -146,12 +185,78 @@ pub extern "C" fn rust_main(dtb_addr: usize) -> ! {
 
     // Run the device manager routing test
     tests::run_device_routing_test();
+    tests::run_device_locking_test();
+
+    // Run the MMIO dispatch fuzz test
+    tests::run_mmio_fuzz_test();
+
+    // Run the MMIO trace decode test
+    tests::run_mmio_trace_test();
+
+    // Run the strict unmapped-MMIO policy test
+    tests::run_mmio_strict_test();
+
+    // Run the LDP/STP RAM/device straddle test
+    tests::run_mmio_pair_test();
+
+    // Run the unbacked IPA abort injection test
+    #[cfg(feature = "linux_guest")]
+    tests::run_unbacked_abort_test();
+
+    // Run the timebase test
+    tests::run_time_test();
+
+    // Run the trapped sysreg emulation test
+    tests::run_sysreg_trap_test();
+
+    // Run the virtual SError / DISR_EL1 test
+    tests::run_serror_disr_test();
+
+    // Run the ID register override test
+    tests::run_id_override_test();
+
+    // Run the WFI periodic tick test
+    tests::run_wfi_tick_test();
+
+    // Run the WFI idle timeout test
+    tests::run_wfi_timeout_test();
+
+    // Run the WFI guest IRQ mask test
+    tests::run_wfi_irq_mask_test();
+
+    // Run the emulated guest physical timer test
+    tests::run_ptimer_test();
+
+    // Run the interrupt enable gating test
+    tests::run_irq_enable_gate_test();
+
+    // Run the interrupt group (IGROUPR) test
+    tests::run_irq_group_test();
+
+    // Run the List Register free-slot selection test
+    tests::run_lr_free_slot_test();
+
+    // Run the SPI latency histogram test
+    tests::run_irq_latency_test();
+
+    // Run the SGI wake ordering test
+    tests::run_sgi_wake_test();
+
+    // Run the pending virtual IRQ inspection test
+    tests::run_pending_irqs_test();
+
+    // Run the WFI idle poll test
+    tests::run_idle_poll_test();
 
     // Run multi-VM tests
     tests::run_vm_state_isolation_test();
     tests::run_vmid_vttbr_test();
+    tests::run_vm_context_guard_test();
     tests::run_multi_vm_devices_test();
     tests::run_vm_activate_test();
+    tests::run_vm_checkpoint_test();
+    tests::run_hot_attach_test();
+    tests::run_passthrough_test();
 
     // Run the NetRxRing test
     tests::run_net_rx_ring_test();
@@ -162,15 +267,102 @@ pub extern "C" fn rust_main(dtb_addr: usize) -> ! {
     // Run the VirtioNet device test
     tests::run_virtio_net_test();
 
+    // Run the VirtioInput device test
+    tests::run_virtio_input_test();
+
+    // Run the VirtioVsock device test
+    tests::run_virtio_vsock_test();
+
+    // Run the VirtioBalloon device test
+    tests::run_virtio_balloon_test();
+
+    // Run the read-only virtio-blk (CD-ROM) test
+    tests::run_virtio_cdrom_test();
+
+    // Run the virtio-blk write/flush test
+    tests::run_virtio_blk_test();
+
+    // Run the multiple virtio-blk instance test
+    tests::run_virtio_multi_blk_test();
+
+    // Run the virtio indirect descriptor test
+    tests::run_virtio_indirect_test();
+
+    // Run the virtio INTID slot-table test
+    tests::run_virtio_intid_test();
+
+    // Run the DMA mapper test
+    tests::run_dma_mapper_test();
+
+    // Run the virtio EVENT_IDX statistics test
+    tests::run_virtio_event_idx_test();
+
+    // Run the virtio InterruptStatus/ACK test
+    tests::run_virtio_isr_test();
+
+    // Run the level-triggered SPI line test
+    tests::run_spi_level_test();
+
     // Run the page ownership test
     tests::run_page_ownership_test();
 
+    // Run the Stage-2 walker VTTBR validation test
+    tests::run_stage2_walker_test();
+
+    // Run the shared buffer hypercall test
+    tests::run_shared_buffer_test();
+
+    // Run the paravirtual console ring test
+    tests::run_pv_console_test();
+
+    // Run the DMA cache maintenance hypercall test
+    tests::run_cache_maint_test();
+
+    // Run the guest RAM attribute test
+    tests::run_ram_attrs_test();
+
+    // Run the Stage-2 dirty tracking test
+    tests::run_dirty_tracking_test();
+
+    // Run the linear framebuffer test
+    tests::run_framebuffer_test();
+
+    // Run the 64KB Stage-2 granule test
+    tests::run_granule_64k_test();
+
+    // Run the PL011 FIFO and interrupt test
+    tests::run_pl011_fifo_test();
+
     // Run the PL031 RTC test
     tests::run_pl031_test();
 
+    // Run the virtual sensor test
+    tests::run_sensor_test();
+
+    // Run the cross-VM SPI routing test
+    tests::run_spi_vm_routing_test();
+
     // Run the FF-A proxy test
     tests::run_ffa_test();
 
+    // Run the FF-A v1.1 retrieve-response descriptor test
+    tests::run_ffa_retrieve_resp_test();
+
+    // Run the FF-A multi-receiver share test
+    tests::run_ffa_multi_receiver_test();
+
+    // Run the FFA_RUN stub SP test
+    tests::run_ffa_run_test();
+
+    // Run the FF-A reclaim-on-shutdown test
+    tests::run_ffa_vm_shutdown_test();
+
+    // Run the FF-A share table capacity test
+    tests::run_ffa_share_capacity_test();
+
+    // Run the FF-A share rate limit test
+    tests::run_ffa_share_limit_test();
+
     // Run the SPMC handler dispatch test
     tests::run_spmc_handler_test();
 
@@ -180,6 +372,9 @@ pub extern "C" fn rust_main(dtb_addr: usize) -> ! {
     // Run the Secure Stage-2 config test
     tests::run_secure_stage2_test();
 
+    // Run the EL2 exception handler guard test
+    tests::run_exception_test();
+
     // Run the guest interrupt injection test (LAST before guest boot — blocks forever)
     // Skip when booting guests since it never returns.
     #[cfg(not(any(feature = "linux_guest", feature = "guest")))]
@@ -530,20 +725,13 @@ fn secondary_enter_guest(cpu_id: usize, entry: u64, ctx_id: u64) {
     use hypervisor::platform;
     use hypervisor::vcpu::Vcpu;
 
-    // Wake this CPU's GICR
+    // Wake this CPU's GICR; a GICR that never wakes fails this secondary boot
     if cpu_id < platform::num_cpus() {
+        use hypervisor::arch::aarch64::peripherals::gicv3;
         let rd_base = hypervisor::dtb::gicr_rd_base(cpu_id);
-        let waker_addr = (rd_base + platform::GICR_WAKER_OFF) as *mut u32;
-        unsafe {
-            let mut waker = core::ptr::read_volatile(waker_addr);
-            waker &= !(1 << 1); // Clear ProcessorSleep
-            core::ptr::write_volatile(waker_addr, waker);
-            loop {
-                let w = core::ptr::read_volatile(waker_addr);
-                if w & (1 << 2) == 0 {
-                    break;
-                }
-            }
+        if !gicv3::wake_redistributor(rd_base, gicv3::GICR_WAKE_SPIN_LIMIT) {
+            hypervisor::uart_puts(b"[SMP] Secondary boot aborted\n");
+            return;
         }
     }
 
@@ -552,8 +740,16 @@ fn secondary_enter_guest(cpu_id: usize, entry: u64, ctx_id: u64) {
     vcpu.context_mut().gp_regs.x0 = ctx_id;
     vcpu.context_mut().spsr_el2 = SPSR_EL1H_DAIF_MASKED;
     vcpu.arch_state_mut().sctlr_el1 = 0x30D0_0800;
+    // FP/SIMD enabled; the first guest use loads a zeroed FpState
     vcpu.arch_state_mut().cpacr_el1 = 3 << 20;
     vcpu.arch_state_mut().init_for_vcpu(cpu_id);
+    vcpu.set_counter_offset(
+        hypervisor::global::vm_state(0)
+            .counter_offset
+            .load(Ordering::Acquire),
+    );
+    hypervisor::arch::aarch64::hypervisor::exception::reset_guest_exit_count(0, cpu_id);
+    hypervisor::arch::aarch64::hypervisor::serror::reset(0, cpu_id);
 
     // Mark vCPU online (current_vcpu_id() uses MPIDR in multi_pcpu mode)
     hypervisor::global::vm_state(0)
@@ -599,8 +795,14 @@ fn secondary_enter_guest(cpu_id: usize, entry: u64, ctx_id: u64) {
                 // Normal exit — loop back, re-enter guest
             }
             Err("WFI") => {
-                // WFI: execute real WFI — pCPU idles until next interrupt
-                unsafe { core::arch::asm!("wfi") };
+                // WFI: execute real WFI — pCPU idles until next interrupt.
+                // Poll the queue briefly first: an SGI queued after the
+                // injection above (its wake IPI possibly already taken in
+                // the guest, or still in flight) must not be slept through.
+                // The loop re-injects on wake.
+                if !hypervisor::vm::idle_poll(cpu_id) {
+                    unsafe { core::arch::asm!("wfi") };
+                }
             }
             Err(_) => {
                 // Other exit — loop back
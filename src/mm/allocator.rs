@@ -1,6 +1,7 @@
 //! Bump allocator with free-list page recycling for hypervisor heap
 
 pub struct BumpAllocator {
+    start: u64,
     next: u64,
     end: u64,
     allocated: u64,
@@ -12,6 +13,7 @@ pub struct BumpAllocator {
 impl BumpAllocator {
     pub const unsafe fn new(start: u64, size: u64) -> Self {
         Self {
+            start,
             next: start,
             end: start + size,
             allocated: 0,
@@ -51,6 +53,11 @@ impl BumpAllocator {
         self.allocated -= 4096;
     }
 
+    /// True if `addr` lies in the part of the region handed out so far.
+    pub fn contains(&self, addr: u64) -> bool {
+        (self.start..self.next).contains(&addr)
+    }
+
     pub fn alloc_aligned(&mut self, size: u64, align: u64) -> Option<u64> {
         let aligned = (self.next + align - 1) & !(align - 1);
         let new_next = aligned + size;
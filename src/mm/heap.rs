@@ -56,7 +56,36 @@ pub fn alloc(size: u64) -> Option<u64> {
 /// Caller must ensure `addr` was previously allocated via `alloc_page()`,
 /// is 4KB-aligned, and is no longer in use.
 pub unsafe fn free_page(addr: u64) {
-    (*HEAP.allocator.get()).as_mut().map(|a| a.free_page(addr));
+    if let Some(a) = (*HEAP.allocator.get()).as_mut() {
+        a.free_page(addr);
+    }
+}
+
+/// Return a `size`-byte block from `alloc_aligned()` to the free-list.
+///
+/// The free-list only holds 4KB pages, so the block comes back as
+/// `size / 4096` pages for `alloc_page()`; a later `alloc_aligned()` of
+/// the same size does not reuse it.
+///
+/// # Safety
+/// Caller must ensure `addr` was previously allocated via
+/// `alloc_aligned(size, align)` with `size` and `align` multiples of 4KB,
+/// and that the block is no longer in use.
+pub unsafe fn free_aligned(addr: u64, size: u64) {
+    let mut page = addr;
+    while page < addr + size {
+        free_page(page);
+        page += 4096;
+    }
+}
+
+/// True if `addr` lies in heap memory handed out so far (e.g. a page table).
+pub fn contains(addr: u64) -> bool {
+    unsafe {
+        (*HEAP.allocator.get())
+            .as_ref()
+            .is_some_and(|a| a.contains(addr))
+    }
 }
 
 /// Get remaining heap space
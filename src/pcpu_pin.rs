@@ -0,0 +1,111 @@
+//! Guest-requested exclusive pCPUs.
+//!
+//! A latency-sensitive vCPU can ask (hypercall 15) to own its physical CPU
+//! outright. While a pCPU is exclusive:
+//!
+//! - the CNTHP preemption watchdog is not armed on it, so the vCPU is never
+//!   forced out to the scheduler, and
+//! - it is no longer a PSCI CPU_ON candidate, so no other vCPU is brought
+//!   up on it.
+//!
+//! Which pCPU a vCPU runs on follows the fixed vCPU-to-pCPU affinity: vCPU N
+//! on pCPU N with `multi_pcpu`, every vCPU on pCPU 0 otherwise. On a single
+//! pCPU the claim is therefore only granted to a VM's sole online vCPU, and
+//! blocks CPU_ON for all others until released.
+//!
+//! Claims are kept per VM: one VM's claim never affects another VM's vCPUs
+//! on the same pCPU, and all of a VM's claims are dropped when it is
+//! created or terminated (`release_vm`).
+
+use crate::arch::aarch64::regs::VcpuContext;
+use crate::global::MAX_VMS;
+use crate::shared_buffer::HC_SUCCESS;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Hypercall number (x0): x1 = 1 to claim the caller's pCPU, 0 to release it.
+pub const HC_EXCLUSIVE_PCPU: u64 = 15;
+
+/// x1: claim the pCPU
+pub const EXCLUSIVE_CLAIM: u64 = 1;
+/// x1: give the pCPU back
+pub const EXCLUSIVE_RELEASE: u64 = 0;
+
+/// Other vCPUs already share the caller's pCPU
+pub const HC_DENIED: u64 = -3i64 as u64;
+/// x1 is neither `EXCLUSIVE_CLAIM` nor `EXCLUSIVE_RELEASE`
+pub const HC_INVALID_ARG: u64 = crate::cache_maint::HC_INVALID_ARG;
+
+/// Per VM, bit N set: pCPU N is held exclusively by that VM's vCPU on it
+static EXCLUSIVE_PCPUS: [AtomicU64; MAX_VMS] = [const { AtomicU64::new(0) }; MAX_VMS];
+
+/// pCPU that `vcpu_id` runs on under the fixed vCPU-to-pCPU affinity.
+pub fn pcpu_of_vcpu(vcpu_id: usize) -> usize {
+    if cfg!(feature = "multi_pcpu") {
+        vcpu_id
+    } else {
+        0
+    }
+}
+
+/// Mark `pcpu` exclusive for `vm_id`.
+pub fn claim(vm_id: usize, pcpu: usize) {
+    if let (Some(mask), true) = (EXCLUSIVE_PCPUS.get(vm_id), pcpu < 64) {
+        mask.fetch_or(1 << pcpu, Ordering::AcqRel);
+    }
+}
+
+/// Return `vm_id`'s claim on `pcpu` to the shared pool.
+pub fn release(vm_id: usize, pcpu: usize) {
+    if let (Some(mask), true) = (EXCLUSIVE_PCPUS.get(vm_id), pcpu < 64) {
+        mask.fetch_and(!(1 << pcpu), Ordering::AcqRel);
+    }
+}
+
+/// Drop every claim `vm_id` holds (VM creation and termination).
+pub fn release_vm(vm_id: usize) {
+    if let Some(mask) = EXCLUSIVE_PCPUS.get(vm_id) {
+        mask.store(0, Ordering::Release);
+    }
+}
+
+/// Whether `pcpu` is held exclusively by `vm_id`.
+pub fn is_exclusive(vm_id: usize, pcpu: usize) -> bool {
+    pcpu < 64
+        && EXCLUSIVE_PCPUS
+            .get(vm_id)
+            .is_some_and(|mask| mask.load(Ordering::Acquire) & (1 << pcpu) != 0)
+}
+
+/// Whether CPU_ON in `vm_id` may bring up `target_vcpu`: its pCPU must
+/// exist and must not be held exclusively by that VM.
+pub fn cpu_on_candidate(vm_id: usize, target_vcpu: usize) -> bool {
+    let pcpu = pcpu_of_vcpu(target_vcpu);
+    pcpu < crate::platform::num_cpus() && !is_exclusive(vm_id, pcpu)
+}
+
+/// Handle `HC_EXCLUSIVE_PCPU` for the current vCPU: x0 = `HC_SUCCESS`,
+/// `HC_DENIED` or `HC_INVALID_ARG`.
+pub fn handle_exclusive_hypercall(context: &mut VcpuContext) {
+    let vm_id = crate::global::current_vm_id();
+    let vcpu_id = crate::global::current_vcpu_id();
+    let pcpu = pcpu_of_vcpu(vcpu_id);
+    context.gp_regs.x0 = match context.gp_regs.x1 {
+        EXCLUSIVE_CLAIM => {
+            let online = crate::global::current_vm_state()
+                .vcpu_online_mask
+                .load(Ordering::Acquire);
+            if !cfg!(feature = "multi_pcpu") && online & !(1 << vcpu_id) != 0 {
+                HC_DENIED
+            } else {
+                claim(vm_id, pcpu);
+                crate::arch::aarch64::peripherals::timer::disarm_preemption_timer();
+                HC_SUCCESS
+            }
+        }
+        EXCLUSIVE_RELEASE => {
+            release(vm_id, pcpu);
+            HC_SUCCESS
+        }
+        _ => HC_INVALID_ARG,
+    };
+}
@@ -4,6 +4,11 @@ use core::cell::UnsafeCell;
 pub struct PerCpuContext {
     pub vcpu_id: usize,
     pub exception_count: u32,
+    /// Set while handle_exception() runs (nested EL2 fault detection)
+    pub in_exception: bool,
+    /// ESR_EL2/FAR_EL2 of the exception currently being handled
+    pub outer_esr: u64,
+    pub outer_far: u64,
 }
 
 /// Wrapper for per-CPU array with interior mutability.
@@ -16,6 +21,9 @@ static PER_CPU: PerCpuArray = PerCpuArray(UnsafeCell::new({
     const INIT: PerCpuContext = PerCpuContext {
         vcpu_id: 0,
         exception_count: 0,
+        in_exception: false,
+        outer_esr: 0,
+        outer_far: 0,
     };
     [INIT; MAX_SMP_CPUS]
 }));
@@ -30,18 +30,58 @@ pub const GUEST_STACK_RESERVE: u64 = 0x1000;
 pub const VIRTIO_DISK_ADDR: u64 = 0x5800_0000;
 /// Disk image size (2MB default — overridden if image is smaller/larger)
 pub const VIRTIO_DISK_SIZE: u64 = 2 * 1024 * 1024;
+/// Read-only CD-ROM image load address (`LINUX_CDROM` in the Makefile;
+/// reads as an empty disc when nothing is loaded there)
+pub const VIRTIO_CDROM_ADDR: u64 = 0x5a00_0000;
+/// CD-ROM image size
+pub const VIRTIO_CDROM_SIZE: u64 = 2 * 1024 * 1024;
+/// Data-partition disk image load address (`LINUX_DATA_DISK` in the
+/// Makefile; starts zeroed when nothing is loaded there)
+pub const VIRTIO_DATA_DISK_ADDR: u64 = 0x5e00_0000;
+/// Data-partition disk image size
+pub const VIRTIO_DATA_DISK_SIZE: u64 = 2 * 1024 * 1024;
+
+// ── Linear framebuffer ──────────────────────────────────────────────
+/// Framebuffer in VM 0's RAM, described to the guest by a
+/// `simple-framebuffer` DTB node and reserved from its allocator
+pub const FRAMEBUFFER_ADDR: u64 = 0x5c00_0000;
+pub const FRAMEBUFFER_WIDTH: u32 = 800;
+pub const FRAMEBUFFER_HEIGHT: u32 = 600;
 
 // ── Virtio-MMIO slot layout ───────────────────────────────────────
 /// Base address of the first virtio-mmio transport (QEMU virt convention)
 pub const VIRTIO_MMIO_BASE: u64 = 0x0a00_0000;
 /// Stride between virtio-mmio transports
 pub const VIRTIO_MMIO_STRIDE: u64 = 0x200;
+/// Physical PL011 RX interrupt, taken by the hypervisor (SPI 1)
+pub const UART_SPI_INTID: u32 = 33;
 /// First SPI INTID for virtio devices (SPI 16 = INTID 48)
 pub const VIRTIO_SPI_BASE: u32 = 48;
+/// Usable virtio-mmio slots: their INTIDs (48-63) must fit the 32-bit
+/// per-VM pending SPI bitmap
+pub const VIRTIO_MAX_SLOTS: usize = 16;
 
-/// Compute (base_addr, intid) for virtio-mmio slot N.
-/// Slot 0: virtio-blk (0x0a000000, INTID 48)
+// Virtio-mmio slot table. The guest DTBs (guest/linux/guest*.dts) list
+// the same slots: `virtio_mmio@<base>` with `interrupts = <0 (intid - 32) 1>`.
+/// Slot 0: virtio-blk root disk (0x0a000000, INTID 48)
+pub const VIRTIO_SLOT_BLK: usize = 0;
 /// Slot 1: virtio-net (0x0a000200, INTID 49)
+pub const VIRTIO_SLOT_NET: usize = 1;
+/// Slot 2: virtio-input (0x0a000400, INTID 50)
+pub const VIRTIO_SLOT_INPUT: usize = 2;
+/// Slot 3: read-only virtio-blk "CD-ROM" (0x0a000600, INTID 51)
+pub const VIRTIO_SLOT_CDROM: usize = 3;
+/// Slot 4: data-partition virtio-blk (0x0a000800, INTID 52)
+pub const VIRTIO_SLOT_DATA: usize = 4;
+/// Slot 5: virtio-vsock host control channel (0x0a000a00, INTID 53)
+pub const VIRTIO_SLOT_VSOCK: usize = 5;
+/// Slot 6: virtio-balloon (0x0a000c00, INTID 54)
+pub const VIRTIO_SLOT_BALLOON: usize = 6;
+
+/// Compute (base_addr, intid) for virtio-mmio slot N.
+///
+/// Every virtio transport takes its INTID from here when attached, so
+/// completions are raised on the SPI the guest DTB advertises for the slot.
 pub const fn virtio_slot(n: usize) -> (u64, u32) {
     (
         VIRTIO_MMIO_BASE + (n as u64) * VIRTIO_MMIO_STRIDE,
@@ -74,11 +114,36 @@ pub const GICR_ISPENDR0_OFF: u64 = 0x200;
 /// GICR_ICPENDR0 offset within SGI frame
 pub const GICR_ICPENDR0_OFF: u64 = 0x280;
 
+// ── GICD distributor offsets (physical programming at EL2) ──────────
+/// GICD_IGROUPR<n> base (1 bit per INTID)
+pub const GICD_IGROUPR_OFF: u64 = 0x080;
+/// GICD_ISENABLER<n> base (write-1-to-enable, 1 bit per INTID)
+pub const GICD_ISENABLER_OFF: u64 = 0x100;
+/// GICD_ICENABLER<n> base (write-1-to-disable, 1 bit per INTID)
+pub const GICD_ICENABLER_OFF: u64 = 0x180;
+/// GICD_ISPENDR<n> base (write-1-to-set-pending, 1 bit per INTID)
+pub const GICD_ISPENDR_OFF: u64 = 0x200;
+/// GICD_ICPENDR<n> base (write-1-to-clear-pending, 1 bit per INTID)
+pub const GICD_ICPENDR_OFF: u64 = 0x280;
+/// GICD_ISACTIVER<n> base (write-1-to-activate, 1 bit per INTID)
+pub const GICD_ISACTIVER_OFF: u64 = 0x300;
+/// GICD_ICACTIVER<n> base (write-1-to-deactivate, 1 bit per INTID)
+pub const GICD_ICACTIVER_OFF: u64 = 0x380;
+/// GICD_IPRIORITYR<n> base (1 byte per INTID)
+pub const GICD_IPRIORITYR_OFF: u64 = 0x400;
+/// GICD_ICFGR<n> base (2 bits per INTID, 0b10 = edge, 0b00 = level)
+pub const GICD_ICFGR_OFF: u64 = 0xC00;
+/// GICD_IROUTER<n> base for SPI 32 (8 bytes per SPI)
+pub const GICD_IROUTER_OFF: u64 = 0x6100;
+
 // ── VM 1 memory layout (multi-VM mode) ──────────────────────────────
 pub const VM1_GUEST_LOAD_ADDR: u64 = 0x6800_0000;
 pub const VM1_LINUX_DTB_ADDR: u64 = 0x6700_0000;
 pub const VM1_LINUX_MEM_SIZE: u64 = 256 * 1024 * 1024;
 pub const VM1_VIRTIO_DISK_ADDR: u64 = 0x7800_0000;
+pub const VM1_VIRTIO_CDROM_ADDR: u64 = 0x7a00_0000;
+pub const VM1_VIRTIO_DATA_DISK_ADDR: u64 = 0x7c00_0000;
+pub const VM1_FRAMEBUFFER_ADDR: u64 = 0x7700_0000;
 
 // ── Heap ─────────────────────────────────────────────────────────────
 pub const HEAP_START: u64 = 0x4100_0000;
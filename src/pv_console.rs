@@ -0,0 +1,152 @@
+//! Paravirtual console ring.
+//!
+//! A guest registers (hypercall 9) one page of its RAM as a console ring,
+//! writes log bytes into it without trapping, and rings a doorbell
+//! (hypercall 10) when it wants them flushed. On the doorbell the
+//! hypervisor drains everything between its consumer index and the guest's
+//! producer index into the VM's virtual UART.
+//!
+//! Ring layout (4KB, page-aligned, writable guest RAM):
+//!
+//! | Offset  | Size  | Field                                    |
+//! |---------|-------|------------------------------------------|
+//! | 0x000   | 4     | producer index (guest writes)            |
+//! | 0x004   | 4     | consumer index (hypervisor writes)       |
+//! | 0x800   | 0x800 | data, byte `i` at `0x800 + i % 0x800`    |
+//!
+//! Both indices are free-running u32 counters. Every access goes through
+//! the VM's `DmaMapper`, so a ring only works in memory the VM itself can
+//! write through its Stage-2.
+
+use crate::arch::aarch64::defs::*;
+use crate::arch::aarch64::regs::VcpuContext;
+use crate::devices::dma::DmaMapper;
+use crate::global::MAX_VMS;
+use crate::shared_buffer::{HC_INVALID_IPA, HC_SUCCESS};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Hypercall number (x0): x1 = IPA of the console ring page.
+pub const HC_CONSOLE_REGISTER: u64 = 9;
+/// Hypercall number (x0): drain the ring; returns the bytes drained in x0.
+pub const HC_CONSOLE_DOORBELL: u64 = 10;
+
+/// Doorbell rung before a ring was registered
+pub const HC_NOT_REGISTERED: u64 = -3i64 as u64;
+
+/// Ring header: producer index (guest-owned)
+pub const RING_PROD_OFFSET: u64 = 0x000;
+/// Ring header: consumer index (hypervisor-owned)
+pub const RING_CONS_OFFSET: u64 = 0x004;
+/// Start of the data area
+pub const RING_DATA_OFFSET: u64 = 0x800;
+/// Size of the data area (power of two)
+pub const RING_DATA_SIZE: u32 = 0x800;
+
+/// IPA of each VM's console ring (0 = not registered)
+static RINGS: [AtomicU64; MAX_VMS] = [const { AtomicU64::new(0) }; MAX_VMS];
+
+/// Register the console ring of `vm_id` at `ipa`, resetting its consumer
+/// index to the current producer index (bytes already in the ring are
+/// skipped). The whole page must be writable Normal memory in the VM's
+/// Stage-2.
+pub fn register_ring(vm_id: usize, ipa: u64) -> Result<(), u64> {
+    if vm_id >= MAX_VMS || ipa & PAGE_MASK_4KB != 0 {
+        return Err(HC_INVALID_IPA);
+    }
+    let dma = DmaMapper::for_vm(vm_id);
+    dma.translate(ipa, PAGE_SIZE_4KB, true)
+        .ok_or(HC_INVALID_IPA)?;
+    let prod = dma
+        .read_val::<u32>(ipa + RING_PROD_OFFSET)
+        .ok_or(HC_INVALID_IPA)?;
+    if !dma.write_val(ipa + RING_CONS_OFFSET, prod) {
+        return Err(HC_INVALID_IPA);
+    }
+    RINGS[vm_id].store(ipa, Ordering::Release);
+    Ok(())
+}
+
+/// Forget `vm_id`'s console ring (call when the VM is torn down).
+pub fn unregister_ring(vm_id: usize) {
+    if let Some(slot) = RINGS.get(vm_id) {
+        slot.store(0, Ordering::Release);
+    }
+}
+
+/// IPA of `vm_id`'s registered console ring, if any.
+pub fn ring_ipa(vm_id: usize) -> Option<u64> {
+    match RINGS.get(vm_id)?.load(Ordering::Acquire) {
+        0 => None,
+        ipa => Some(ipa),
+    }
+}
+
+/// Drain `vm_id`'s console ring into its UART.
+///
+/// Returns the number of bytes drained. If the guest overran the ring, the
+/// bytes it overwrote are lost and draining resumes at the oldest byte
+/// still present.
+pub fn drain_ring(vm_id: usize) -> Result<u32, u64> {
+    let ipa = ring_ipa(vm_id).ok_or(HC_NOT_REGISTERED)?;
+    let devs = &crate::global::DEVICES[vm_id];
+    let dma = DmaMapper::for_vm(vm_id);
+    let data = ipa + RING_DATA_OFFSET;
+
+    let prod = dma
+        .read_val::<u32>(ipa + RING_PROD_OFFSET)
+        .ok_or(HC_INVALID_IPA)?;
+    // Order the data reads after the producer index read
+    unsafe {
+        core::arch::asm!("dmb ishld", options(nostack, preserves_flags));
+    }
+    let mut cons = dma
+        .read_val::<u32>(ipa + RING_CONS_OFFSET)
+        .ok_or(HC_INVALID_IPA)?;
+    if prod.wrapping_sub(cons) > RING_DATA_SIZE {
+        cons = prod.wrapping_sub(RING_DATA_SIZE);
+    }
+
+    let mut drained = 0;
+    let mut chunk = [0u8; 64];
+    while cons != prod {
+        // Up to a chunk, stopping at the end of the data area
+        let off = cons % RING_DATA_SIZE;
+        let n = (chunk.len() as u32)
+            .min(prod.wrapping_sub(cons))
+            .min(RING_DATA_SIZE - off) as usize;
+        if !dma.read(data + off as u64, &mut chunk[..n]) {
+            return Err(HC_INVALID_IPA);
+        }
+        devs.uart_transmit(&chunk[..n]);
+        cons = cons.wrapping_add(n as u32);
+        drained += n as u32;
+    }
+    if !dma.write_val(ipa + RING_CONS_OFFSET, cons) {
+        return Err(HC_INVALID_IPA);
+    }
+    Ok(drained)
+}
+
+/// Handle hypercall 9 for the current VM: x1 = ring IPA, result in x0.
+pub fn handle_register_hypercall(context: &mut VcpuContext) {
+    let vm_id = crate::global::current_vm_id();
+    let ipa = context.gp_regs.x1;
+    context.gp_regs.x0 = match register_ring(vm_id, ipa) {
+        Ok(()) => HC_SUCCESS,
+        Err(e) => {
+            crate::uart_puts(b"[PVCON] Ring rejected at IPA 0x");
+            crate::uart_put_hex(ipa);
+            crate::uart_puts(b"\n");
+            e
+        }
+    };
+}
+
+/// Handle hypercall 10 for the current VM: bytes drained (or error) in x0.
+pub fn handle_doorbell_hypercall(context: &mut VcpuContext) {
+    let vm_id = crate::global::current_vm_id();
+    context.gp_regs.x0 = match drain_ring(vm_id) {
+        Ok(n) => n as u64,
+        Err(e) => e,
+    };
+}
@@ -1,5 +1,9 @@
-//! Simple round-robin vCPU scheduler
+//! Weighted round-robin vCPU scheduler, and the epoch scheduler that shares
+//! the pCPU between VMs in multi-VM mode
 
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::global::MAX_VMS;
 use crate::vm::MAX_VCPUS;
 
 /// Run state for a vCPU in the scheduler
@@ -15,7 +19,13 @@ pub enum RunState {
     Blocked,
 }
 
-/// Simple round-robin scheduler for vCPUs
+/// Weighted (deficit) round-robin scheduler for vCPUs
+///
+/// Each vCPU gets `weight` picks per round: a pick spends one credit, and
+/// a vCPU out of credits is passed over while another ready vCPU still has
+/// some. When none has, every vCPU's credits are reset to its weight and
+/// a new round starts. With all weights 1 (the default) this is plain
+/// round-robin.
 pub struct Scheduler {
     /// Run state for each vCPU slot
     states: [RunState; MAX_VCPUS],
@@ -23,6 +33,14 @@ pub struct Scheduler {
     current: Option<usize>,
     /// Next index to check in round-robin
     next_idx: usize,
+    /// Calls to `pick_next()`
+    iterations: u64,
+    /// Times each vCPU was taken off the ready queue
+    run_counts: [u64; MAX_VCPUS],
+    /// Picks per round for each vCPU
+    weights: [u32; MAX_VCPUS],
+    /// Picks left in the current round
+    credits: [u32; MAX_VCPUS],
 }
 
 impl Scheduler {
@@ -32,16 +50,39 @@ impl Scheduler {
             states: [RunState::None; MAX_VCPUS],
             current: None,
             next_idx: 0,
+            iterations: 0,
+            run_counts: [0; MAX_VCPUS],
+            weights: [1; MAX_VCPUS],
+            credits: [1; MAX_VCPUS],
         }
     }
 
-    /// Add a vCPU to the scheduler
+    /// Add a vCPU to the scheduler (weight 1)
     pub fn add_vcpu(&mut self, vcpu_id: usize) {
         if vcpu_id < MAX_VCPUS {
             self.states[vcpu_id] = RunState::Ready;
+            self.weights[vcpu_id] = 1;
+            self.credits[vcpu_id] = 1;
+        }
+    }
+
+    /// Set how many picks per round a vCPU gets (0 is treated as 1).
+    ///
+    /// Credits already above the new weight are trimmed; a higher weight
+    /// takes effect from the next round.
+    pub fn set_weight(&mut self, vcpu_id: usize, weight: u32) {
+        if vcpu_id < MAX_VCPUS {
+            let weight = weight.max(1);
+            self.weights[vcpu_id] = weight;
+            self.credits[vcpu_id] = self.credits[vcpu_id].min(weight);
         }
     }
 
+    /// Picks per round of a vCPU
+    pub fn weight(&self, vcpu_id: usize) -> u32 {
+        self.weights.get(vcpu_id).copied().unwrap_or(0)
+    }
+
     /// Remove a vCPU from the scheduler
     pub fn remove_vcpu(&mut self, vcpu_id: usize) {
         if vcpu_id < MAX_VCPUS {
@@ -52,11 +93,14 @@ impl Scheduler {
         }
     }
 
-    /// Pick the next vCPU to run (round-robin)
+    /// Pick the next vCPU to run (weighted round-robin)
     ///
     /// If a vCPU is already running, returns it.
-    /// Otherwise, finds the next ready vCPU starting from next_idx.
+    /// Otherwise, finds the next ready vCPU with credits left starting from
+    /// next_idx, starting a new round if no ready vCPU has any.
     pub fn pick_next(&mut self) -> Option<usize> {
+        self.iterations += 1;
+
         // If current is still running, return it
         if let Some(id) = self.current {
             if self.states[id] == RunState::Running {
@@ -64,17 +108,26 @@ impl Scheduler {
             }
         }
 
-        // Find next ready vCPU
-        for i in 0..MAX_VCPUS {
-            let idx = (self.next_idx + i) % MAX_VCPUS;
-            if self.states[idx] == RunState::Ready {
-                self.current = Some(idx);
-                self.states[idx] = RunState::Running;
-                return Some(idx);
+        // Find next ready vCPU with credits; refill once if none has any
+        let idx = match self.next_with_credit() {
+            Some(idx) => idx,
+            None => {
+                self.credits = self.weights;
+                self.next_with_credit()?
             }
-        }
+        };
+        self.current = Some(idx);
+        self.states[idx] = RunState::Running;
+        self.credits[idx] -= 1;
+        self.run_counts[idx] += 1;
+        Some(idx)
+    }
 
-        None
+    /// First ready vCPU with credits left, searching from next_idx
+    fn next_with_credit(&self) -> Option<usize> {
+        (0..MAX_VCPUS)
+            .map(|i| (self.next_idx + i) % MAX_VCPUS)
+            .find(|&idx| self.states[idx] == RunState::Ready && self.credits[idx] > 0)
     }
 
     /// Yield the current vCPU (put back in ready queue)
@@ -115,6 +168,16 @@ impl Scheduler {
             RunState::None
         }
     }
+
+    /// Number of scheduling decisions (`pick_next()` calls) made
+    pub fn iterations(&self) -> u64 {
+        self.iterations
+    }
+
+    /// Number of times a vCPU was picked from the ready queue
+    pub fn run_count(&self, vcpu_id: usize) -> u64 {
+        self.run_counts.get(vcpu_id).copied().unwrap_or(0)
+    }
 }
 
 impl Default for Scheduler {
@@ -122,3 +185,211 @@ impl Default for Scheduler {
         Self::new()
     }
 }
+
+/// Scheduler statistics of one VM, published for the guest-visible
+/// sched-stats device.
+///
+/// Iteration and run counts and the picked vCPU are copied from the VM's
+/// `Scheduler` after each scheduling decision; preemptions and slice times are recorded when a
+/// vCPU exits. Relaxed atomics: readers only need eventually-consistent
+/// counters.
+pub struct SchedStats {
+    iterations: AtomicU64,
+    run_counts: [AtomicU64; MAX_VCPUS],
+    /// Slices ended by the CNTHP preemption watchdog
+    preemptions: AtomicU64,
+    /// Accumulated time each vCPU spent in the guest, in counter ticks
+    slice_ticks: [AtomicU64; MAX_VCPUS],
+    /// Counter value when the VM was (re)created
+    start_ticks: AtomicU64,
+    /// vCPU picked by the last scheduling decision (`NO_VCPU` = none)
+    current_vcpu: AtomicU64,
+}
+
+/// `SchedStats::current_vcpu` value when no vCPU is scheduled
+const NO_VCPU: u64 = u64::MAX;
+
+impl SchedStats {
+    const fn new() -> Self {
+        Self {
+            iterations: AtomicU64::new(0),
+            run_counts: [const { AtomicU64::new(0) }; MAX_VCPUS],
+            preemptions: AtomicU64::new(0),
+            slice_ticks: [const { AtomicU64::new(0) }; MAX_VCPUS],
+            start_ticks: AtomicU64::new(0),
+            current_vcpu: AtomicU64::new(NO_VCPU),
+        }
+    }
+
+    /// Zero all counters and restart the uptime clock.
+    pub fn reset(&self) {
+        self.iterations.store(0, Ordering::Relaxed);
+        self.preemptions.store(0, Ordering::Relaxed);
+        for (runs, ticks) in self.run_counts.iter().zip(self.slice_ticks.iter()) {
+            runs.store(0, Ordering::Relaxed);
+            ticks.store(0, Ordering::Relaxed);
+        }
+        self.start_ticks
+            .store(crate::time::now_ticks(), Ordering::Relaxed);
+        self.current_vcpu.store(NO_VCPU, Ordering::Relaxed);
+    }
+
+    /// Copy the iteration and per-vCPU run counters and the current vCPU
+    /// from `sched`.
+    pub fn publish(&self, sched: &Scheduler) {
+        self.iterations.store(sched.iterations(), Ordering::Relaxed);
+        let current = sched.current().map_or(NO_VCPU, |id| id as u64);
+        self.current_vcpu.store(current, Ordering::Relaxed);
+        for (id, runs) in self.run_counts.iter().enumerate() {
+            runs.store(sched.run_count(id), Ordering::Relaxed);
+        }
+    }
+
+    /// Account a finished slice of `ticks` for a vCPU.
+    pub fn record_slice(&self, vcpu_id: usize, ticks: u64, preempted: bool) {
+        if let Some(total) = self.slice_ticks.get(vcpu_id) {
+            total.fetch_add(ticks, Ordering::Relaxed);
+        }
+        if preempted {
+            self.preemptions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn iterations(&self) -> u64 {
+        self.iterations.load(Ordering::Relaxed)
+    }
+
+    pub fn run_count(&self, vcpu_id: usize) -> u64 {
+        self.run_counts
+            .get(vcpu_id)
+            .map_or(0, |c| c.load(Ordering::Relaxed))
+    }
+
+    /// vCPU picked by the last scheduling decision
+    pub fn current_vcpu(&self) -> Option<usize> {
+        match self.current_vcpu.load(Ordering::Relaxed) {
+            NO_VCPU => None,
+            id => Some(id as usize),
+        }
+    }
+
+    pub fn preemptions(&self) -> u64 {
+        self.preemptions.load(Ordering::Relaxed)
+    }
+
+    /// Accumulated guest time of a vCPU in counter ticks
+    pub fn slice_ticks(&self, vcpu_id: usize) -> u64 {
+        self.slice_ticks
+            .get(vcpu_id)
+            .map_or(0, |c| c.load(Ordering::Relaxed))
+    }
+
+    /// Counter ticks since the VM was created
+    pub fn uptime_ticks(&self) -> u64 {
+        crate::time::now_ticks().wrapping_sub(self.start_ticks.load(Ordering::Relaxed))
+    }
+}
+
+static SCHED_STATS: [SchedStats; MAX_VMS] = [const { SchedStats::new() }; MAX_VMS];
+
+/// Scheduler statistics of VM `vm_id`.
+pub fn sched_stats(vm_id: usize) -> &'static SchedStats {
+    &SCHED_STATS[vm_id]
+}
+
+/// How far (in ns of accumulated runtime) a VM may get ahead of the
+/// least-served runnable VM before it sits out epochs
+pub const MAX_RUNTIME_LEAD_NS: u64 = 1_000_000;
+
+/// Multi-VM epoch scheduler
+///
+/// Each epoch gives every unfinished VM at most one slice, starting with
+/// VM `epoch % num_vms` so no index is always first. Slices are timed and
+/// summed per VM; a VM more than `MAX_RUNTIME_LEAD_NS` ahead of the
+/// least-served runnable VM skips the epoch, so VMs with longer slices do
+/// not take more than their share. A VM that cannot run (suspended) does
+/// not hold the others back, and its runtime is brought up to the
+/// least-served runnable VM's so it does not monopolize the pCPU on wakeup.
+pub struct EpochScheduler {
+    /// Epochs completed
+    epoch: u64,
+    /// Accumulated slice time per VM, in counter ticks
+    runtime: [u64; MAX_VMS],
+    /// VMs whose slice reported completion
+    finished: [bool; MAX_VMS],
+}
+
+impl EpochScheduler {
+    pub const fn new() -> Self {
+        Self {
+            epoch: 0,
+            runtime: [0; MAX_VMS],
+            finished: [false; MAX_VMS],
+        }
+    }
+
+    /// Epochs completed so far
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Accumulated runtime of VM `vm_id` in counter ticks
+    pub fn runtime(&self, vm_id: usize) -> u64 {
+        self.runtime.get(vm_id).copied().unwrap_or(0)
+    }
+
+    pub fn is_finished(&self, vm_id: usize) -> bool {
+        self.finished.get(vm_id).copied().unwrap_or(true)
+    }
+
+    /// Run one epoch over VMs `0..num_vms`.
+    ///
+    /// `runnable(vm_id)` tells whether the VM has work to do this epoch;
+    /// only runnable VMs count for the lead check, but every unfinished VM
+    /// still gets its slice (to poll its wakeup sources). `run_slice(vm_id)`
+    /// runs one slice of the VM and returns true once it has finished; it
+    /// is not called again for that VM. Returns true when every VM has
+    /// finished.
+    pub fn run_epoch<R, F>(&mut self, num_vms: usize, runnable: R, mut run_slice: F) -> bool
+    where
+        R: Fn(usize) -> bool,
+        F: FnMut(usize) -> bool,
+    {
+        let num_vms = num_vms.min(MAX_VMS);
+        if self.finished[..num_vms].iter().all(|&f| f) {
+            return true;
+        }
+        let least = (0..num_vms)
+            .filter(|&id| !self.finished[id] && runnable(id))
+            .map(|id| self.runtime[id])
+            .min();
+        let limit = least.map_or(u64::MAX, |least| {
+            least.saturating_add(crate::time::ns_to_ticks(MAX_RUNTIME_LEAD_NS))
+        });
+        let start = (self.epoch % num_vms as u64) as usize;
+        for i in 0..num_vms {
+            let id = (start + i) % num_vms;
+            if self.finished[id] {
+                continue;
+            }
+            if !runnable(id) {
+                if let Some(least) = least {
+                    self.runtime[id] = self.runtime[id].max(least);
+                }
+            } else if self.runtime[id] > limit {
+                continue;
+            }
+            let t0 = crate::time::now_ticks();
+            self.finished[id] = run_slice(id);
+            self.runtime[id] += crate::time::now_ticks().wrapping_sub(t0);
+        }
+        self.epoch += 1;
+        self.finished[..num_vms].iter().all(|&f| f)
+    }
+}
+
+impl Default for EpochScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
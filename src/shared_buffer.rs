@@ -0,0 +1,96 @@
+//! Host/guest shared buffer pages.
+//!
+//! Each VM can ask (hypercall 12) for one hypervisor-owned page (4KB, or
+//! 64KB with a 64KB Stage-2) to be mapped RW into its Stage-2 at an IPA of
+//! its choosing, for zero-copy
+//! communication with the host. The page is allocated from the hypervisor
+//! heap on first use and reused for later requests; the guest only chooses
+//! where it appears, and the IPA must lie in a hole of its address space.
+
+use crate::arch::aarch64::defs::*;
+use crate::arch::aarch64::regs::VcpuContext;
+use crate::ffa::memory::PageOwnership;
+use crate::ffa::stage2_walker::Stage2Walker;
+use crate::global::MAX_VMS;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Hypercall number (x0): x1 = IPA to map the shared page at.
+pub const HC_MAP_SHARED_BUFFER: u64 = 12;
+
+/// Hypercall return values (x0)
+pub const HC_SUCCESS: u64 = 0;
+/// IPA misaligned or overlapping RAM, a device, or an existing mapping
+pub const HC_INVALID_IPA: u64 = -1i64 as u64;
+/// Shared page allocation or the Stage-2 update failed
+pub const HC_MAP_FAILED: u64 = -2i64 as u64;
+
+/// Host PA of each VM's shared page (0 = not allocated yet)
+static SHARED_PAGES: [AtomicU64; MAX_VMS] = [const { AtomicU64::new(0) }; MAX_VMS];
+
+/// Host PA of `vm_id`'s shared page, allocating and zeroing it on first use
+/// with the size of one page of `vm_id`'s Stage-2 granule.
+pub fn shared_page(vm_id: usize) -> Option<u64> {
+    let slot = SHARED_PAGES.get(vm_id)?;
+    let pa = slot.load(Ordering::Acquire);
+    if pa != 0 {
+        return Some(pa);
+    }
+    let page = Stage2Walker::for_vm(vm_id).granule().alloc_table()?;
+    slot.store(page, Ordering::Release);
+    Some(page)
+}
+
+/// Whether `ipa` is a page-aligned hole in `vm_id`'s address space:
+/// outside the RAM window `Vm::init_memory` mapped for it (heap gap
+/// included), not claimed by an emulated device, and not already mapped
+/// in Stage-2. Always false without Stage-2 tables.
+pub fn is_unused_ipa(walker: &Stage2Walker, vm_id: usize, ipa: u64) -> bool {
+    // 48-bit IPA space (VTCR_EL2.T0SZ = 16)
+    let page_size = walker.page_size();
+    if vm_id >= MAX_VMS || !walker.has_stage2() || ipa & (page_size - 1) != 0 || ipa >> 48 != 0 {
+        return false;
+    }
+    let state = crate::global::vm_state(vm_id);
+    let ram_base = state.ram_base.load(Ordering::Relaxed);
+    let ram_size = state.ram_size.load(Ordering::Relaxed);
+    if ram_size != 0 && ipa < ram_base + ram_size && ipa + page_size > ram_base {
+        return false;
+    }
+    if crate::global::DEVICES[vm_id].overlaps(ipa, page_size) {
+        return false;
+    }
+    walker.translate(ipa).is_none()
+}
+
+/// Map `vm_id`'s shared page RW at `ipa` in the Stage-2 tables of `walker`.
+///
+/// The page is tagged SharedBorrowed: it is hypervisor memory at a
+/// non-identity IPA, so FF-A MEM_SHARE/LEND must refuse it (the FF-A
+/// paths treat IPA as PA). Returns the host PA backing the mapping.
+pub fn map_shared_buffer(walker: &Stage2Walker, vm_id: usize, ipa: u64) -> Result<u64, u64> {
+    if !is_unused_ipa(walker, vm_id, ipa) {
+        return Err(HC_INVALID_IPA);
+    }
+    let pa = shared_page(vm_id).ok_or(HC_MAP_FAILED)?;
+    let s2ap = (S2AP_RW >> S2AP_SHIFT) as u8;
+    let sw = PageOwnership::SharedBorrowed as u8;
+    walker
+        .map_page_to(ipa, pa, s2ap, sw, S2_MEMATTR_NORMAL_WB)
+        .map_err(|_| HC_MAP_FAILED)?;
+    Ok(pa)
+}
+
+/// Handle hypercall 12 for the current VM: x1 = IPA, result in x0.
+pub fn handle_map_hypercall(context: &mut VcpuContext, walker: &Stage2Walker) {
+    let vm_id = crate::global::current_vm_id();
+    let ipa = context.gp_regs.x1;
+    context.gp_regs.x0 = match map_shared_buffer(walker, vm_id, ipa) {
+        Ok(_) => HC_SUCCESS,
+        Err(e) => {
+            crate::uart_puts(b"[SHM] Map rejected at IPA 0x");
+            crate::uart_put_hex(ipa);
+            crate::uart_puts(b"\n");
+            e
+        }
+    };
+}
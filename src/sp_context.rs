@@ -20,6 +20,25 @@ pub enum SpState {
     Blocked,
     /// SP was preempted by NS interrupt, resume via FFA_RUN.
     Preempted,
+    /// SP hit a fatal error and is never scheduled again.
+    Aborted,
+}
+
+impl SpState {
+    /// Whether the SP lifecycle allows moving from `self` to `next`.
+    pub fn can_transition_to(self, next: SpState) -> bool {
+        matches!(
+            (self, next),
+            (SpState::Reset, SpState::Idle)
+                | (SpState::Idle, SpState::Running)
+                | (SpState::Running, SpState::Idle)
+                | (SpState::Running, SpState::Blocked)
+                | (SpState::Blocked, SpState::Running)
+                | (SpState::Running, SpState::Preempted)
+                | (SpState::Preempted, SpState::Running)
+                | (SpState::Running, SpState::Aborted)
+        )
+    }
 }
 
 /// Per-SP context: register state + metadata.
@@ -93,17 +112,7 @@ impl SpContext {
 
     /// Validate and perform a state transition.
     pub fn transition_to(&mut self, new_state: SpState) -> Result<(), &'static str> {
-        let valid = match (self.state, new_state) {
-            (SpState::Reset, SpState::Idle) => true,
-            (SpState::Idle, SpState::Running) => true,
-            (SpState::Running, SpState::Idle) => true,
-            (SpState::Running, SpState::Blocked) => true,
-            (SpState::Blocked, SpState::Running) => true,
-            (SpState::Running, SpState::Preempted) => true,
-            (SpState::Preempted, SpState::Running) => true,
-            _ => false,
-        };
-        if valid {
+        if self.state.can_transition_to(new_state) {
             self.state = new_state;
             Ok(())
         } else {
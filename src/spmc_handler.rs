@@ -220,132 +220,174 @@ fn resume_preempted_sp(sp_id: u16) -> SmcResult8 {
     }
 }
 
+/// Handler for one FF-A call from SPMD.
+type SpmcHandler = fn(&SmcResult8) -> SmcResult8;
+
+/// Function IDs the SPMC handles, and their handlers.
+///
+/// Single source of truth for both `dispatch_ffa` and FFA_FEATURES: a call
+/// added here is reported as supported. RXTX_MAP/UNMAP and RX_RELEASE are
+/// listed because SPMD forwards them from NWd to SPMC.
+const HANDLERS: &[(u64, SpmcHandler)] = &[
+    (ffa::FFA_VERSION, handle_version),
+    (ffa::FFA_ID_GET, handle_id_get),
+    (ffa::FFA_SPM_ID_GET, handle_id_get),
+    (ffa::FFA_FEATURES, handle_features),
+    (ffa::FFA_RUN, handle_run),
+    (ffa::FFA_RXTX_MAP, handle_rxtx_map),
+    (ffa::FFA_RXTX_UNMAP, handle_rxtx_unmap),
+    (ffa::FFA_RX_RELEASE, handle_rx_release),
+    (ffa::FFA_PARTITION_INFO_GET, handle_partition_info_get),
+    (ffa::FFA_CONSOLE_LOG_32, handle_console_log),
+    (ffa::FFA_CONSOLE_LOG_64, handle_console_log),
+    (ffa::FFA_MSG_SEND_DIRECT_REQ_32, handle_direct_req_32),
+    (ffa::FFA_MSG_SEND_DIRECT_REQ_64, handle_direct_req_64),
+];
+
+/// Function IDs `dispatch_ffa` handles, i.e. those FFA_FEATURES reports as
+/// supported.
+pub fn supported_functions() -> impl Iterator<Item = u64> {
+    HANDLERS.iter().map(|&(fid, _)| fid)
+}
+
 /// Dispatch an FF-A request and return the appropriate response.
 ///
-/// Pure function: matches on the FF-A function ID in req.x0 and builds
-/// a response SmcResult8. Not gated by feature flags so it can be unit
-/// tested on the host.
+/// Pure function: looks up the FF-A function ID in req.x0 in `HANDLERS`
+/// and builds a response SmcResult8. Not gated by feature flags so it can
+/// be unit tested on the host.
 pub fn dispatch_ffa(req: &SmcResult8) -> SmcResult8 {
-    match req.x0 {
-        ffa::FFA_VERSION => {
-            // Return FF-A v1.1
-            SmcResult8 {
-                x0: ffa::FFA_VERSION_1_1 as u64,
-                x1: 0,
-                x2: 0,
-                x3: 0,
-                x4: 0,
-                x5: 0,
-                x6: 0,
-                x7: 0,
-            }
-        }
+    match HANDLERS.iter().find(|&&(fid, _)| fid == req.x0) {
+        Some(&(_, handler)) => handler(req),
+        None => make_error(ffa::FFA_NOT_SUPPORTED as u64),
+    }
+}
 
-        ffa::FFA_ID_GET => {
-            // SPMC partition ID = 0x8000
-            SmcResult8 {
-                x0: ffa::FFA_SUCCESS_32,
-                x1: 0,
-                x2: ffa::FFA_SPMC_ID as u64,
-                x3: 0,
-                x4: 0,
-                x5: 0,
-                x6: 0,
-                x7: 0,
-            }
-        }
+/// Handle FFA_VERSION — report FF-A v1.1.
+fn handle_version(_req: &SmcResult8) -> SmcResult8 {
+    SmcResult8 {
+        x0: ffa::FFA_VERSION_1_1 as u64,
+        x1: 0,
+        x2: 0,
+        x3: 0,
+        x4: 0,
+        x5: 0,
+        x6: 0,
+        x7: 0,
+    }
+}
 
-        ffa::FFA_SPM_ID_GET => {
-            // SPMC partition ID = 0x8000
-            SmcResult8 {
-                x0: ffa::FFA_SUCCESS_32,
-                x1: 0,
-                x2: ffa::FFA_SPMC_ID as u64,
-                x3: 0,
-                x4: 0,
-                x5: 0,
-                x6: 0,
-                x7: 0,
-            }
-        }
+/// Handle FFA_ID_GET and FFA_SPM_ID_GET — SPMC partition ID = 0x8000.
+fn handle_id_get(_req: &SmcResult8) -> SmcResult8 {
+    SmcResult8 {
+        x0: ffa::FFA_SUCCESS_32,
+        x1: 0,
+        x2: ffa::FFA_SPMC_ID as u64,
+        x3: 0,
+        x4: 0,
+        x5: 0,
+        x6: 0,
+        x7: 0,
+    }
+}
 
-        ffa::FFA_FEATURES => {
-            // Check if the queried function ID (in x1) is supported
-            let queried_fid = req.x1;
-            // RXTX_MAP is listed because SPMD forwards it from NWd to SPMC.
-            let supported = matches!(
-                queried_fid,
-                ffa::FFA_VERSION
-                    | ffa::FFA_ID_GET
-                    | ffa::FFA_FEATURES
-                    | ffa::FFA_SPM_ID_GET
-                    | ffa::FFA_PARTITION_INFO_GET
-                    | ffa::FFA_MSG_SEND_DIRECT_REQ_32
-                    | ffa::FFA_MSG_SEND_DIRECT_REQ_64
-                    | ffa::FFA_RXTX_MAP
-                    | ffa::FFA_RX_RELEASE
-                    | ffa::FFA_RUN
-            );
-            if supported {
-                SmcResult8 {
-                    x0: ffa::FFA_SUCCESS_32,
-                    x1: 0,
-                    x2: 0,
-                    x3: 0,
-                    x4: 0,
-                    x5: 0,
-                    x6: 0,
-                    x7: 0,
-                }
-            } else {
-                make_error(ffa::FFA_NOT_SUPPORTED as u64)
-            }
+/// Handle FFA_FEATURES — the queried function ID (x1) is supported if
+/// `HANDLERS` routes it.
+fn handle_features(req: &SmcResult8) -> SmcResult8 {
+    if supported_functions().any(|fid| fid == req.x1) {
+        SmcResult8 {
+            x0: ffa::FFA_SUCCESS_32,
+            x1: 0,
+            x2: 0,
+            x3: 0,
+            x4: 0,
+            x5: 0,
+            x6: 0,
+            x7: 0,
         }
+    } else {
+        make_error(ffa::FFA_NOT_SUPPORTED as u64)
+    }
+}
 
-        ffa::FFA_RUN => {
-            // FFA_RUN: x1[31:16] = target SP ID
-            let sp_id = ((req.x1 >> 16) & 0xFFFF) as u16;
-            if !crate::sp_context::is_registered_sp(sp_id) {
-                return make_error(ffa::FFA_INVALID_PARAMETERS as u64);
-            }
-            let sp = crate::sp_context::get_sp_mut(sp_id).unwrap();
-            if sp.state() != crate::sp_context::SpState::Preempted {
-                return make_error(ffa::FFA_DENIED as u64);
-            }
-            // In sel2 mode, dispatch_request() handles this before we get here.
-            // In unit tests (no sel2), just validate the state.
-            make_error(ffa::FFA_NOT_SUPPORTED as u64)
-        }
+/// Handle FFA_RUN: x1[31:16] = target SP ID.
+///
+/// In sel2 mode, dispatch_request() resumes the SP before we get here.
+/// In unit tests (no sel2), just validate the state.
+fn handle_run(req: &SmcResult8) -> SmcResult8 {
+    let sp_id = ((req.x1 >> 16) & 0xFFFF) as u16;
+    if !crate::sp_context::is_registered_sp(sp_id) {
+        return make_error(ffa::FFA_INVALID_PARAMETERS as u64);
+    }
+    let sp = crate::sp_context::get_sp_mut(sp_id).unwrap();
+    if sp.state() != crate::sp_context::SpState::Preempted {
+        return make_error(ffa::FFA_DENIED as u64);
+    }
+    make_error(ffa::FFA_NOT_SUPPORTED as u64)
+}
 
-        ffa::FFA_RXTX_MAP => handle_rxtx_map(req),
-        ffa::FFA_RXTX_UNMAP => handle_rxtx_unmap(),
-        ffa::FFA_RX_RELEASE => handle_rx_release(),
+/// Handle DIRECT_REQ_64 — echo x3-x7 back, swap source/dest in x1.
+fn handle_direct_req_64(req: &SmcResult8) -> SmcResult8 {
+    let source = (req.x1 >> 16) & 0xFFFF;
+    let dest = req.x1 & 0xFFFF;
+    SmcResult8 {
+        x0: ffa::FFA_MSG_SEND_DIRECT_RESP_64,
+        x1: (dest << 16) | source,
+        x2: 0,
+        x3: req.x3,
+        x4: req.x4,
+        x5: req.x5,
+        x6: req.x6,
+        x7: req.x7,
+    }
+}
 
-        ffa::FFA_PARTITION_INFO_GET => {
-            handle_partition_info_get()
-        }
+/// Largest FFA_CONSOLE_LOG payload: 6 registers of 8 characters (SMC64).
+pub const CONSOLE_LOG_MAX_CHARS: usize = 48;
 
-        ffa::FFA_MSG_SEND_DIRECT_REQ_32 => {
-            handle_direct_req_32(req)
-        }
+/// Unpack the characters of an FFA_CONSOLE_LOG request into `buf`.
+///
+/// x1 holds the character count; x2-x7 hold the characters packed
+/// little-endian, 4 per register for the SMC32 call and 8 for SMC64.
+/// Returns the number of characters, or `None` if the count is 0 or more
+/// than the registers can carry.
+pub fn unpack_console_log(
+    req: &SmcResult8,
+    buf: &mut [u8; CONSOLE_LOG_MAX_CHARS],
+) -> Option<usize> {
+    let per_reg = if req.x0 == ffa::FFA_CONSOLE_LOG_64 {
+        8
+    } else {
+        4
+    };
+    let count = (req.x1 & 0xFFFF_FFFF) as usize;
+    if count == 0 || count > 6 * per_reg {
+        return None;
+    }
+    let regs = [req.x2, req.x3, req.x4, req.x5, req.x6, req.x7];
+    for (i, byte) in buf[..count].iter_mut().enumerate() {
+        *byte = (regs[i / per_reg] >> (8 * (i % per_reg))) as u8;
+    }
+    Some(count)
+}
 
-        ffa::FFA_MSG_SEND_DIRECT_REQ_64 => {
-            // Echo x3-x7 back, swap source/dest in x1
-            let source = (req.x1 >> 16) & 0xFFFF;
-            let dest = req.x1 & 0xFFFF;
-            SmcResult8 {
-                x0: ffa::FFA_MSG_SEND_DIRECT_RESP_64,
-                x1: (dest << 16) | source,
-                x2: 0,
-                x3: req.x3,
-                x4: req.x4,
-                x5: req.x5,
-                x6: req.x6,
-                x7: req.x7,
-            }
-        }
+/// Handle FFA_CONSOLE_LOG — print an SP's debug characters on the SPMC
+/// console, so SPs can log without a UART of their own.
+fn handle_console_log(req: &SmcResult8) -> SmcResult8 {
+    let mut buf = [0u8; CONSOLE_LOG_MAX_CHARS];
+    let Some(count) = unpack_console_log(req, &mut buf) else {
+        return make_error(ffa::FFA_INVALID_PARAMETERS as u64);
+    };
+    crate::uart_puts(&buf[..count]);
 
-        _ => make_error(ffa::FFA_NOT_SUPPORTED as u64),
+    SmcResult8 {
+        x0: ffa::FFA_SUCCESS_32,
+        x1: 0,
+        x2: 0,
+        x3: 0,
+        x4: 0,
+        x5: 0,
+        x6: 0,
+        x7: 0,
     }
 }
 
@@ -386,7 +428,7 @@ fn handle_rxtx_map(req: &SmcResult8) -> SmcResult8 {
 }
 
 /// Handle FFA_RXTX_UNMAP — clear NWd's RXTX registration.
-fn handle_rxtx_unmap() -> SmcResult8 {
+fn handle_rxtx_unmap(_req: &SmcResult8) -> SmcResult8 {
     unsafe {
         if !NWD_RXTX.mapped {
             return make_error(ffa::FFA_DENIED as u64);
@@ -410,7 +452,7 @@ fn handle_rxtx_unmap() -> SmcResult8 {
 }
 
 /// Handle FFA_RX_RELEASE — acknowledge NWd has consumed the RX buffer.
-fn handle_rx_release() -> SmcResult8 {
+fn handle_rx_release(_req: &SmcResult8) -> SmcResult8 {
     unsafe {
         if !NWD_RXTX.mapped {
             return make_error(ffa::FFA_DENIED as u64);
@@ -439,7 +481,7 @@ fn handle_rx_release() -> SmcResult8 {
 ///
 /// If NWd has registered RXTX, writes descriptors to NWd's RX PA.
 /// If no RXTX registered, returns count only (FF-A "count query" mode).
-fn handle_partition_info_get() -> SmcResult8 {
+fn handle_partition_info_get(_req: &SmcResult8) -> SmcResult8 {
     let mut count = 0u64;
 
     // Write descriptors to NWd's RX buffer (sel2 mode) or just count (unit tests).
@@ -31,6 +31,20 @@ impl<T> SpinLock<T> {
         }
         SpinLockGuard { lock: self, ticket }
     }
+
+    /// Take the lock only if no one holds or is waiting for it.
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        let ticket = self.now_serving.load(Ordering::Acquire);
+        self.next_ticket
+            .compare_exchange(
+                ticket,
+                ticket.wrapping_add(1),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .ok()
+            .map(|_| SpinLockGuard { lock: self, ticket })
+    }
 }
 
 impl<T> core::ops::Deref for SpinLockGuard<'_, T> {
@@ -0,0 +1,92 @@
+//! Monotonic timebase for the hypervisor.
+//!
+//! All hypervisor-side time reads go through this module instead of reading
+//! the counter inline. The source is the physical counter (CNTPCT_EL0) scaled
+//! by CNTFRQ_EL0, so guest CNTVOFF changes never affect hypervisor deadlines.
+//!
+//! Tests can install a fake clock (`install_fake_clock()`) and advance it
+//! manually; while installed, `now_ticks()` and `frequency()` return the fake
+//! values. The fake clock must not be installed while hardware timers are
+//! being armed from it.
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Nanoseconds per second
+pub const NS_PER_SEC: u64 = 1_000_000_000;
+
+// ── Fake clock (test injection) ────────────────────────────────────
+
+static FAKE_CLOCK_ACTIVE: AtomicBool = AtomicBool::new(false);
+static FAKE_TICKS: AtomicU64 = AtomicU64::new(0);
+static FAKE_FREQ: AtomicU64 = AtomicU64::new(0);
+
+/// Replace the hardware counter with a fake clock at `freq` Hz, starting
+/// at `start_ticks`.
+pub fn install_fake_clock(freq: u64, start_ticks: u64) {
+    FAKE_TICKS.store(start_ticks, Ordering::Relaxed);
+    FAKE_FREQ.store(freq, Ordering::Relaxed);
+    FAKE_CLOCK_ACTIVE.store(true, Ordering::Release);
+}
+
+/// Advance the fake clock by `ticks`. No-op on the hardware clock.
+pub fn advance_fake_clock(ticks: u64) {
+    FAKE_TICKS.fetch_add(ticks, Ordering::Relaxed);
+}
+
+/// Restore the hardware counter as the clock source.
+pub fn remove_fake_clock() {
+    FAKE_CLOCK_ACTIVE.store(false, Ordering::Release);
+}
+
+// ── Clock accessors ────────────────────────────────────────────────
+
+/// Current counter value in ticks.
+#[inline]
+pub fn now_ticks() -> u64 {
+    if FAKE_CLOCK_ACTIVE.load(Ordering::Acquire) {
+        return FAKE_TICKS.load(Ordering::Relaxed);
+    }
+    let count: u64;
+    unsafe {
+        asm!("isb", "mrs {}, cntpct_el0", out(reg) count, options(nostack, nomem));
+    }
+    count
+}
+
+/// Counter frequency in Hz (CNTFRQ_EL0, as configured by firmware).
+#[inline]
+pub fn frequency() -> u64 {
+    if FAKE_CLOCK_ACTIVE.load(Ordering::Acquire) {
+        return FAKE_FREQ.load(Ordering::Relaxed);
+    }
+    let freq: u64;
+    unsafe {
+        asm!("mrs {}, cntfrq_el0", out(reg) freq, options(nostack, nomem));
+    }
+    freq
+}
+
+/// Current time in nanoseconds since the counter started.
+pub fn now_ns() -> u64 {
+    ticks_to_ns(now_ticks())
+}
+
+/// Convert counter ticks to nanoseconds. Returns 0 if the frequency is unset.
+pub fn ticks_to_ns(ticks: u64) -> u64 {
+    let freq = frequency();
+    if freq == 0 {
+        return 0;
+    }
+    (ticks as u128 * NS_PER_SEC as u128 / freq as u128) as u64
+}
+
+/// Convert nanoseconds to counter ticks. Returns 0 if the frequency is unset.
+pub fn ns_to_ticks(ns: u64) -> u64 {
+    (ns as u128 * frequency() as u128 / NS_PER_SEC as u128) as u64
+}
+
+/// Absolute counter value `ns` nanoseconds from now.
+pub fn deadline_after_ns(ns: u64) -> u64 {
+    now_ticks().wrapping_add(ns_to_ticks(ns))
+}
@@ -72,7 +72,11 @@
 //! as pending and will be delivered when the guest resumes execution with
 //! interrupts enabled.
 
-use crate::arch::aarch64::vcpu_arch_state::VcpuArchState;
+use core::sync::atomic::Ordering;
+
+use crate::arch::aarch64::hypervisor::{id_regs, serror};
+use crate::arch::aarch64::peripherals::gicv3::GicV3VirtualInterface;
+use crate::arch::aarch64::vcpu_arch_state::{FpState, VcpuArchState, NUM_LRS};
 use crate::arch::aarch64::{enter_guest, VcpuContext};
 use crate::vcpu_interrupt::VirtualInterruptState;
 
@@ -104,6 +108,44 @@ pub enum VcpuState {
     Stopped,
 }
 
+/// Point-in-time copy of a vCPU's register, interrupt and arch state.
+#[derive(Clone, Copy)]
+pub struct VcpuSnapshot {
+    pub id: usize,
+    pub state: VcpuState,
+    pub context: VcpuContext,
+    pub virt_irq: VirtualInterruptState,
+    pub arch_state: VcpuArchState,
+}
+
+/// Virtual interrupts waiting for a vCPU, from `Vcpu::pending_virtual_irqs()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PendingIrqs {
+    /// SGIs queued for injection (bit N = INTID N)
+    pub sgis: u32,
+    /// SPIs queued for injection (bit N = INTID N + 32)
+    pub spis: u32,
+    /// INTID held by each saved List Register that is not Invalid
+    pub lrs: [Option<u32>; NUM_LRS],
+}
+
+impl PendingIrqs {
+    /// True if nothing is queued and every List Register is free
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// True if `intid` is queued or held in a List Register
+    pub fn contains(&self, intid: u32) -> bool {
+        let queued = match intid {
+            0..=15 => self.sgis & (1 << intid) != 0,
+            32..=63 => self.spis & (1 << (intid - 32)) != 0,
+            _ => false,
+        };
+        queued || self.lrs.contains(&Some(intid))
+    }
+}
+
 /// Virtual CPU (vCPU)
 ///
 /// Represents a single virtual processor that can execute guest code at EL1.
@@ -185,6 +227,37 @@ impl Vcpu {
         &mut self.arch_state
     }
 
+    /// Get reference to architectural state
+    pub fn arch_state(&self) -> &VcpuArchState {
+        &self.arch_state
+    }
+
+    /// Set the CNTVOFF_EL2 value loaded on guest entry: the guest's virtual
+    /// counter reads `CNTPCT - offset`. All vCPUs of a VM must share one
+    /// offset, or the guest sees time jump as it migrates between them.
+    pub fn set_counter_offset(&mut self, offset: u64) {
+        self.arch_state.cntvoff = offset;
+    }
+
+    /// Capture the vCPU's full state. Only meaningful while it is not running.
+    pub fn snapshot(&self) -> VcpuSnapshot {
+        VcpuSnapshot {
+            id: self.id,
+            state: self.state,
+            context: self.context,
+            virt_irq: self.virt_irq,
+            arch_state: self.arch_state,
+        }
+    }
+
+    /// Overwrite the vCPU's state with a snapshot (the vCPU ID is kept).
+    pub fn restore_snapshot(&mut self, snap: &VcpuSnapshot) {
+        self.state = snap.state;
+        self.context = snap.context;
+        self.virt_irq = snap.virt_irq;
+        self.arch_state = snap.arch_state;
+    }
+
     /// Run the vCPU
     ///
     /// This will enter the guest and execute code until an exit occurs.
@@ -203,19 +276,36 @@ impl Vcpu {
         // Restore per-vCPU architectural state (GIC LRs, timer, EL1 sysregs)
         self.arch_state.restore();
 
-        // Apply virtual interrupt state to HCR_EL2 before entering guest
-        unsafe {
+        // Apply virtual interrupt, virtual SError and ID override state to
+        // HCR_EL2 (and VPIDR_EL2) before entering guest
+        let vm_id = crate::global::current_vm_id();
+        let entry_hcr = unsafe {
             use crate::vcpu_interrupt::{get_hcr_el2, set_hcr_el2};
             let hcr = get_hcr_el2();
             let hcr_with_vi = self.virt_irq.apply_to_hcr(hcr);
-            set_hcr_el2(hcr_with_vi);
-        }
+            let hcr = serror::enter(vm_id, self.id, hcr_with_vi);
+            let hcr = id_regs::enter(vm_id, hcr);
+            set_hcr_el2(hcr);
+            hcr
+        };
+
+        // FP/SIMD is switched lazily: the first guest use traps and
+        // exception.S loads it from (and later saves it to) arch_state.fp
+        self.context.fp_state = &mut self.arch_state.fp as *mut FpState as u64;
+        self.context.fp_live = 0;
 
         // Enter the guest
+        let entry_lrs = self.arch_state.ich_lr;
         let result = unsafe { enter_guest(&mut self.context as *mut VcpuContext) };
 
         // Save per-vCPU architectural state
         self.arch_state.save();
+        // Reflect SPIs the guest acknowledged or deactivated in ISACTIVER;
+        // a deactivated level-triggered SPI whose line is still high fires again
+        crate::global::sync_spi_active(vm_id, &entry_lrs, &self.arch_state.ich_lr);
+        serror::exit(vm_id, self.id, entry_hcr, unsafe {
+            crate::vcpu_interrupt::get_hcr_el2()
+        });
 
         self.state = VcpuState::Ready;
 
@@ -241,6 +331,7 @@ impl Vcpu {
     pub fn reset(&mut self, entry_point: u64, stack_pointer: u64) {
         self.context = VcpuContext::new(entry_point, stack_pointer);
         self.state = VcpuState::Ready;
+        self.arch_state.fp = FpState::new();
     }
 
     /// Inject a virtual IRQ into the guest
@@ -270,6 +361,36 @@ impl Vcpu {
     pub fn virt_irq_mut(&mut self) -> &mut VirtualInterruptState {
         &mut self.virt_irq
     }
+
+    /// SGIs/SPIs queued for this vCPU in the current VM, plus the
+    /// interrupts already placed in its saved List Registers
+    pub fn pending_virtual_irqs(&self) -> PendingIrqs {
+        let vs = crate::global::current_vm_state();
+        let queued = |q: Option<&core::sync::atomic::AtomicU32>| {
+            q.map_or(0, |bits| bits.load(Ordering::Acquire))
+        };
+        PendingIrqs {
+            sgis: queued(vs.pending_sgis.get(self.id)),
+            spis: queued(vs.pending_spis.get(self.id)),
+            lrs: self.arch_state.ich_lr.map(|lr| {
+                (!GicV3VirtualInterface::lr_is_free(lr))
+                    .then(|| GicV3VirtualInterface::get_lr_intid(lr))
+            }),
+        }
+    }
+
+    /// Drop everything `pending_virtual_irqs()` reports: the SGI/SPI queues
+    /// for this vCPU in the current VM and all saved List Registers
+    pub fn clear_pending_irqs(&mut self) {
+        let vs = crate::global::current_vm_state();
+        if let Some(sgis) = vs.pending_sgis.get(self.id) {
+            sgis.store(0, Ordering::Release);
+        }
+        if let Some(spis) = vs.pending_spis.get(self.id) {
+            spis.store(0, Ordering::Release);
+        }
+        self.arch_state.ich_lr = [0; NUM_LRS];
+    }
 }
 
 impl core::fmt::Debug for Vcpu {
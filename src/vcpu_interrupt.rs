@@ -5,6 +5,7 @@
 //! ## GICv3/v4 List Register Mechanism
 //! GICv3+ uses List Registers (LRs) instead of HCR_EL2.VI for interrupt injection.
 //! Each LR can hold one pending/active virtual interrupt with its state, priority, etc.
+//! A GICv2 host has the same mechanism in the memory-mapped GICH_LR registers.
 //!
 //! ## Interrupt Injection Flow
 //! 1. Hypervisor receives physical interrupt (e.g., timer)
@@ -26,7 +27,8 @@ pub struct VirtualInterruptState {
     /// IRQ number that is pending (if any)
     pub pending_irq_num: Option<u32>,
 
-    /// Use GICv3 List Registers (true) or HCR_EL2.VI (false)
+    /// Use GICv3 List Registers (true) or, with false, GICH_LR on a GICv2
+    /// host and HCR_EL2.VI otherwise
     pub use_gicv3: bool,
 }
 
@@ -36,7 +38,7 @@ impl Default for VirtualInterruptState {
             irq_pending: false,
             fiq_pending: false,
             pending_irq_num: None,
-            use_gicv3: true, // Default to GICv3
+            use_gicv3: !crate::arch::aarch64::peripherals::gic::is_active(),
         }
     }
 }
@@ -68,7 +70,13 @@ impl VirtualInterruptState {
                 }
             }
         } else {
-            // Use legacy HCR_EL2.VI mechanism
+            // GICv2: GICH_LR injection; otherwise the legacy HCR_EL2.VI
+            // mechanism picks up irq_pending in apply_to_hcr()
+            use crate::arch::aarch64::peripherals::gic;
+
+            if gic::is_active() {
+                let _ = gic::gich().inject_interrupt(irq_num, 0xA0);
+            }
             self.irq_pending = true;
             self.pending_irq_num = Some(irq_num);
         }
@@ -88,6 +96,10 @@ impl VirtualInterruptState {
                 use crate::arch::aarch64::peripherals::gicv3::GicV3VirtualInterface;
                 GicV3VirtualInterface::clear_interrupt(irq_num);
             }
+        } else if crate::arch::aarch64::peripherals::gic::is_active() {
+            if let Some(irq_num) = self.pending_irq_num {
+                crate::arch::aarch64::peripherals::gic::gich().clear_interrupt(irq_num);
+            }
         }
         self.irq_pending = false;
         self.pending_irq_num = None;
@@ -105,8 +117,8 @@ impl VirtualInterruptState {
 
     /// Apply interrupt state to HCR_EL2
     ///
-    /// This is only used for legacy mode (GICv2 or fallback).
-    /// GICv3+ uses List Registers instead.
+    /// This is only used for legacy mode (no List Registers).
+    /// GICv3+ and GICv2 (GICH_LR) use List Registers instead.
     ///
     /// # Arguments
     /// * `hcr` - Current HCR_EL2 value
@@ -114,8 +126,8 @@ impl VirtualInterruptState {
     /// # Returns
     /// Updated HCR_EL2 value with VI/VF bits set
     pub fn apply_to_hcr(&self, hcr: u64) -> u64 {
-        if self.use_gicv3 {
-            // GICv3 mode: don't use HCR_EL2.VI
+        if self.use_gicv3 || crate::arch::aarch64::peripherals::gic::is_active() {
+            // List Register mode: don't use HCR_EL2.VI
             // Just return HCR unchanged
             hcr
         } else {
@@ -7,11 +7,11 @@ use crate::arch::aarch64::defs::*;
 use crate::arch::aarch64::peripherals::gicv3::GicV3VirtualInterface;
 #[cfg(not(feature = "linux_guest"))]
 use crate::arch::aarch64::{init_stage2, MemoryAttributes};
-#[cfg(not(feature = "multi_pcpu"))]
 use crate::devices::MmioDevice;
+use crate::global::LifecycleState;
 use crate::platform;
 use crate::scheduler::Scheduler;
-use crate::vcpu::Vcpu;
+use crate::vcpu::{Vcpu, VcpuSnapshot};
 use core::sync::atomic::Ordering;
 
 /// Maximum number of vCPUs per VM
@@ -36,6 +36,18 @@ pub enum VmState {
     Stopped,
 }
 
+impl From<VmState> for LifecycleState {
+    fn from(state: VmState) -> Self {
+        match state {
+            VmState::Uninitialized => LifecycleState::Created,
+            VmState::Ready => LifecycleState::Ready,
+            VmState::Running => LifecycleState::Running,
+            VmState::Paused => LifecycleState::Paused,
+            VmState::Stopped => LifecycleState::Stopped,
+        }
+    }
+}
+
 /// Virtual Machine
 pub struct Vm {
     /// Unique identifier for this VM
@@ -61,6 +73,50 @@ pub struct Vm {
 
     /// Saved VTCR_EL2
     vtcr: u64,
+
+    /// Stage-2 translation granule, fixed once memory is initialized
+    granule: crate::arch::aarch64::mm::mmu::Granule,
+
+    /// Linear framebuffer in guest RAM, if attached
+    framebuffer: Option<crate::devices::framebuffer::VirtualFramebuffer>,
+}
+
+/// Stage-2 translation summary recorded in a checkpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stage2Summary {
+    /// VTTBR_EL2 (VMID + root table address)
+    pub vttbr: u64,
+    /// VTCR_EL2 (granule, T0SZ, start level)
+    pub vtcr: u64,
+    /// Whether guest memory had been mapped
+    pub memory_initialized: bool,
+}
+
+/// Whole-VM checkpoint produced by [`Vm::checkpoint`].
+///
+/// Guest RAM is not copied; Stage-2 is recorded as a summary only, so a
+/// checkpoint can be restored on the same host (or a host with identical
+/// guest memory contents at the same IPA layout).
+#[derive(Clone)]
+pub struct VmCheckpoint {
+    /// VM the checkpoint was taken from
+    pub vm_id: usize,
+    /// VM lifecycle state after quiescing
+    pub state: VmState,
+    /// Per-vCPU snapshots (None for empty slots)
+    pub vcpus: [Option<VcpuSnapshot>; MAX_VCPUS],
+    /// Number of vCPUs
+    pub vcpu_count: usize,
+    /// Per-vCPU pending SGI bitmasks from `VM_STATE`
+    pub pending_sgis: [u32; MAX_VCPUS],
+    /// Per-vCPU pending SPI bitmasks from `VM_STATE`
+    pub pending_spis: [u32; MAX_VCPUS],
+    /// Online-vCPU bitmask from `VM_STATE`
+    pub vcpu_online_mask: u64,
+    /// Emulated device state (UART FIFO, GIC, virtio queues)
+    pub devices: crate::devices::DeviceSnapshot,
+    /// Stage-2 mapping summary
+    pub stage2: Stage2Summary,
 }
 
 impl Vm {
@@ -71,12 +127,14 @@ impl Vm {
         // GlobalDeviceManager uses a static DeviceManager to avoid stack overflow
         // (VirtualGicd alone is ~10KB due to irouter[988]).
         crate::global::DEVICES[id].reset();
+        // Virtio buffers are validated against this VM's Stage-2 once built
+        crate::global::DEVICES[id].set_dma(crate::devices::dma::DmaMapper::for_vm(id));
         crate::global::DEVICES[id].register_device(crate::devices::Device::Uart(
             crate::devices::pl011::VirtualUart::new(),
         ));
-        crate::global::DEVICES[id].register_device(crate::devices::Device::Gicd(
-            crate::devices::gic::VirtualGicd::new(),
-        ));
+        let mut gicd = crate::devices::gic::VirtualGicd::new();
+        gicd.set_owner_vm(id);
+        crate::global::DEVICES[id].register_device(crate::devices::Device::Gicd(gicd));
         #[cfg(feature = "linux_guest")]
         crate::global::DEVICES[id].register_device(crate::devices::Device::Gicr(
             crate::devices::gic::VirtualGicr::new(platform::num_cpus()),
@@ -84,6 +142,27 @@ impl Vm {
         crate::global::DEVICES[id].register_device(crate::devices::Device::Pl031(
             crate::devices::pl031::VirtualPl031::new(),
         ));
+        crate::global::vm_state(id)
+            .vm_terminated
+            .store(false, Ordering::Release);
+        crate::global::vm_state(id)
+            .vcpus_per_cluster
+            .store(crate::global::DEFAULT_VCPUS_PER_CLUSTER, Ordering::Relaxed);
+        // A rebooted guest must register its console ring again
+        crate::pv_console::unregister_ring(id);
+        crate::pcpu_pin::release_vm(id);
+        crate::global::clear_passthrough_irqs(id);
+        crate::global::reset_spi_lines(id);
+        crate::scheduler::sched_stats(id).reset();
+        crate::global::vm_state(id).system_suspend.cancel();
+        crate::global::vm_state(id)
+            .wfi_timeout_ns
+            .store(0, Ordering::Relaxed);
+        crate::global::vm_state(id)
+            .ram_size
+            .store(0, Ordering::Relaxed);
+        crate::arch::aarch64::hypervisor::id_regs::clear_overrides(id);
+        crate::global::LIFECYCLE.push(id, LifecycleState::Created);
 
         Self {
             id,
@@ -94,6 +173,8 @@ impl Vm {
             scheduler: Scheduler::new(),
             vttbr: 0,
             vtcr: 0,
+            granule: crate::arch::aarch64::mm::mmu::Granule::Size4KB,
+            framebuffer: None,
         }
     }
 
@@ -107,6 +188,56 @@ impl Vm {
         self.state
     }
 
+    /// Move to `state`, reporting the change on the lifecycle channel.
+    fn set_state(&mut self, state: VmState) {
+        if self.state != state {
+            self.state = state;
+            crate::global::LIFECYCLE.push(self.id, state.into());
+        }
+    }
+
+    /// Let a vCPU idle in WFI with nothing to inject for at most
+    /// `timeout_ns` before it exits to the scheduler (0 = no timeout, only
+    /// the WFI iteration cap applies).
+    pub fn set_wfi_timeout_ns(&self, timeout_ns: u64) {
+        crate::global::vm_state(self.id)
+            .wfi_timeout_ns
+            .store(timeout_ns, Ordering::Relaxed);
+    }
+
+    /// WFI idle timeout set by `set_wfi_timeout_ns` (0 = none)
+    pub fn wfi_timeout_ns(&self) -> u64 {
+        crate::global::vm_state(self.id)
+            .wfi_timeout_ns
+            .load(Ordering::Relaxed)
+    }
+
+    /// Present bits `[field_shift, field_shift + width)` of ID register
+    /// `register` to this VM's guest as `value` instead of the hardware's,
+    /// e.g. ID_AA64ISAR0_EL1.Atomics = 0 to force non-LSE code paths, or a
+    /// MIDR_EL1 field (via VPIDR_EL2) to trigger an erratum workaround.
+    /// Takes effect on the next vCPU entry.
+    pub fn override_id_field(
+        &self,
+        register: crate::arch::aarch64::hypervisor::id_regs::IdReg,
+        field_shift: u32,
+        width: u32,
+        value: u64,
+    ) -> Result<(), &'static str> {
+        crate::arch::aarch64::hypervisor::id_regs::set_override(
+            self.id,
+            register,
+            field_shift,
+            width,
+            value,
+        )
+    }
+
+    /// Drop all ID register overrides of this VM.
+    pub fn clear_id_overrides(&self) {
+        crate::arch::aarch64::hypervisor::id_regs::clear_overrides(self.id)
+    }
+
     /// Get saved VTTBR_EL2 value (includes VMID)
     pub fn vttbr(&self) -> u64 {
         self.vttbr
@@ -117,6 +248,26 @@ impl Vm {
         self.vtcr
     }
 
+    /// Lay out vCPU MPIDRs with `per_cluster` vCPUs per Aff1 cluster
+    /// (1-16): vCPU n gets Aff1 = n / per_cluster, Aff0 = n % per_cluster.
+    /// Existing vCPUs' VMPIDR is reprogrammed; SGI routing, PSCI and the
+    /// GICR_TYPER affinity follow the same layout.
+    pub fn set_vcpus_per_cluster(&mut self, per_cluster: u32) -> Result<(), &'static str> {
+        if !(1..=crate::global::DEFAULT_VCPUS_PER_CLUSTER).contains(&per_cluster) {
+            return Err("vCPUs per cluster must be 1-16");
+        }
+        crate::global::vm_state(self.id)
+            .vcpus_per_cluster
+            .store(per_cluster, Ordering::Relaxed);
+        for (id, slot) in self.vcpus.iter_mut().enumerate() {
+            if let Some(vcpu) = slot {
+                vcpu.arch_state_mut()
+                    .set_affinity(crate::global::vcpu_affinity(self.id, id));
+            }
+        }
+        Ok(())
+    }
+
     /// Activate this VM's Stage-2 page tables by writing VTTBR_EL2.
     ///
     /// With distinct VMIDs per VM, TLB entries are tagged and no flush is needed.
@@ -136,6 +287,35 @@ impl Vm {
         self.vcpu_count
     }
 
+    /// Stage-2 translation granule of this VM
+    pub fn granule(&self) -> crate::arch::aarch64::mm::mmu::Granule {
+        self.granule
+    }
+
+    /// Select the Stage-2 granule for `init_memory()` (4KB by default).
+    ///
+    /// Fails once memory is mapped, if the PE does not support the granule
+    /// at Stage-2 (ID_AA64MMFR0_EL1.TGranX_2), or for 64KB without the
+    /// dynamic mapper (`linux_guest`).
+    pub fn set_granule(
+        &mut self,
+        granule: crate::arch::aarch64::mm::mmu::Granule,
+    ) -> Result<(), &'static str> {
+        use crate::arch::aarch64::mm::mmu::Granule;
+
+        if self.memory_initialized {
+            return Err("Memory already initialized");
+        }
+        if !granule.stage2_supported() {
+            return Err("Granule not supported at Stage-2");
+        }
+        if cfg!(not(feature = "linux_guest")) && granule != Granule::Size4KB {
+            return Err("Granule needs the dynamic mapper");
+        }
+        self.granule = granule;
+        Ok(())
+    }
+
     /// Initialize memory for the VM
     pub fn init_memory(&mut self, guest_mem_start: u64, guest_mem_size: u64) {
         use crate::uart_put_hex;
@@ -166,6 +346,9 @@ impl Vm {
         self.init_memory_static(start_aligned, size_aligned);
 
         self.memory_initialized = true;
+        let state = crate::global::vm_state(self.id);
+        state.ram_base.store(start_aligned, Ordering::Relaxed);
+        state.ram_size.store(size_aligned, Ordering::Relaxed);
         uart_puts(b"[VM] Memory mapping complete\n");
     }
 
@@ -202,7 +385,7 @@ impl Vm {
         };
         use crate::uart_puts;
 
-        let mut mapper = DynamicIdentityMapper::new();
+        let mut mapper = DynamicIdentityMapper::with_granule(self.granule);
 
         // Map guest memory in two regions, SKIPPING the hypervisor heap.
         // The heap (HEAP_START .. HEAP_START+HEAP_SIZE) lies within the guest's
@@ -276,40 +459,46 @@ impl Vm {
             )
             .expect("Failed to map GIC region");
 
-        // Unmap GICD (64KB = 16 × 4KB pages) for full trap-and-emulate.
-        // Guest GICD accesses trap as Data Aborts → VirtualGicd.
-        // The hypervisor still accesses physical GICD at EL2 (bypasses Stage-2).
-        for page in 0..16u64 {
-            let addr = crate::dtb::platform_info().gicd_base + page * PAGE_SIZE_4KB;
+        // Unmap GICD (64KB = 16 × 4KB or 1 × 64KB pages) for full
+        // trap-and-emulate. Guest GICD accesses trap as Data Aborts →
+        // VirtualGicd. The hypervisor still accesses physical GICD at EL2
+        // (bypasses Stage-2).
+        let page_size = self.granule.page_size();
+        for page in 0..platform::GICD_SIZE / page_size {
+            let addr = crate::dtb::platform_info().gicd_base + page * page_size;
             mapper
                 .unmap_4kb_page(addr)
                 .expect("Failed to unmap GICD page");
         }
         uart_puts(b"[VM] GICD unmapped (trap to EL2 via VirtualGicd)\n");
 
-        // Unmap all GICR frames (each = 128KB = 32 × 4KB pages)
+        // Unmap all GICR frames (each = 128KB = 32 × 4KB or 2 × 64KB pages)
         for cpu in 0..platform::num_cpus() {
             let base = crate::dtb::gicr_rd_base(cpu);
-            for page in 0..32u64 {
-                let addr = base + page * PAGE_SIZE_4KB;
+            for page in 0..32 * PAGE_SIZE_4KB / page_size {
+                let addr = base + page * page_size;
                 mapper
                     .unmap_4kb_page(addr)
                     .expect("Failed to unmap GICR page");
             }
         }
         uart_puts(b"[VM] All GICRs unmapped (trap to EL2 via VirtualGicr)\n");
+        crate::global::DEVICES[self.id].set_gic_trapped(true);
 
         // UART (0x09000000) is NOT mapped — all accesses trap to VirtualUart
 
         // Install Stage-2 translation with VMID
-        let config = crate::arch::aarch64::mm::mmu::Stage2Config::new_with_vmid(
+        let config = crate::arch::aarch64::mm::mmu::Stage2Config::new_with_granule(
             mapper.vttbr(),
             self.id as u16,
+            self.granule,
         );
         self.vttbr = config.vttbr;
         self.vtcr = config.vtcr;
 
-        // Store L0 table PA for cross-VM Stage-2 access (FF-A memory sharing)
+        // Store L0 table PA and granule for cross-VM Stage-2 access (FF-A
+        // memory sharing)
+        crate::global::PER_VM_VTCR[self.id].store(config.vtcr, Ordering::Release);
         crate::global::PER_VM_VTTBR[self.id].store(
             config.vttbr & crate::arch::aarch64::defs::PTE_ADDR_MASK,
             core::sync::atomic::Ordering::Release,
@@ -330,12 +519,215 @@ impl Vm {
             crate::global::SHARED_VTCR.store(vtcr, Ordering::Release);
         }
 
-        // Keep mapper alive — page tables are heap-allocated and must not be freed.
-        // Leak it intentionally (we only create one per boot).
+        // Keep the tables alive: dropping the mapper would free them while
+        // VTTBR_EL2 (and PER_VM_VTTBR) still point at them. There is no VM
+        // teardown yet, so they live for the rest of the boot.
         core::mem::forget(mapper);
     }
 
-    /// Create a vCPU with specified ID
+    /// Identity-map `[ipa, ipa + len)` in this VM's Stage-2 with memory
+    /// attribute `attr`, changing the type of pages that are already mapped
+    /// (e.g. a Normal Non-Cacheable window in guest RAM to exercise
+    /// non-coherent DMA). Blocks are split into pages of the VM's granule
+    /// as needed; the region must be aligned to that page size.
+    ///
+    /// Requires the heap-allocated Stage-2 built by `init_memory_dynamic()`.
+    pub fn map_region_attr(
+        &mut self,
+        ipa: u64,
+        len: u64,
+        attr: crate::arch::aarch64::mm::mmu::MemoryAttribute,
+    ) -> Result<(), &'static str> {
+        let walker = crate::ffa::stage2_walker::Stage2Walker::for_vm(self.id);
+        if !walker.has_stage2() {
+            return Err("Stage-2 not initialized");
+        }
+        let page_mask = walker.page_size() - 1;
+        if ipa & page_mask != 0 || len & page_mask != 0 {
+            return Err("Region not page-aligned");
+        }
+        let end = ipa.checked_add(len).ok_or("Region out of range")?;
+        let (mem_attr, s2ap) = (attr.s2_mem_attr(), attr.s2ap());
+        let mut page = ipa;
+        while page < end {
+            if walker.translate(page).is_some() {
+                walker.set_mem_attr(page, mem_attr)?;
+                walker.set_s2ap(page, s2ap)?;
+            } else {
+                walker.map_page_with_attr(page, s2ap, 0, mem_attr)?;
+            }
+            page += walker.page_size();
+        }
+        Ok(())
+    }
+
+    /// Start tracking guest writes to `[ipa, ipa + len)`: the range is
+    /// write-protected in Stage-2 and each page the guest then writes is
+    /// recorded for `take_dirty_bitmap()`. Replaces any range tracked before.
+    ///
+    /// Requires the heap-allocated Stage-2 built by `init_memory_dynamic()`.
+    pub fn start_dirty_tracking(&mut self, ipa: u64, len: u64) -> Result<(), &'static str> {
+        crate::dirty_log::start(self.id, ipa, len)
+    }
+
+    /// Move the pages dirtied since tracking started (or since the last
+    /// call) into `bitmap`, bit N of word W standing for the 4KB page at
+    /// `ipa + (W * 64 + N) * 4KB`, and write-protect them again. Returns the
+    /// number of dirty pages.
+    pub fn take_dirty_bitmap(&mut self, bitmap: &mut [u64]) -> usize {
+        crate::dirty_log::take(self.id, bitmap)
+    }
+
+    /// Stop dirty tracking and give the guest write access back.
+    pub fn stop_dirty_tracking(&mut self) {
+        crate::dirty_log::stop(self.id)
+    }
+
+    /// Give the guest a linear framebuffer in its RAM, replacing any
+    /// earlier one. The guest learns of it from the DTB
+    /// (`guest_loader::set_framebuffer`).
+    pub fn attach_framebuffer(
+        &mut self,
+        fb: crate::devices::framebuffer::VirtualFramebuffer,
+    ) -> Result<(), &'static str> {
+        if crate::global::DEVICES[self.id].overlaps(fb.base(), fb.size()) {
+            return Err("Framebuffer overlaps an MMIO device");
+        }
+        self.framebuffer = Some(fb);
+        Ok(())
+    }
+
+    pub fn framebuffer(&self) -> Option<&crate::devices::framebuffer::VirtualFramebuffer> {
+        self.framebuffer.as_ref()
+    }
+
+    /// Copy what the guest rendered into `out`, reading through this VM's
+    /// Stage-2; returns the bytes copied (0 without a framebuffer).
+    pub fn framebuffer_snapshot(&self, out: &mut [u8]) -> usize {
+        let dma = crate::devices::dma::DmaMapper::for_vm(self.id);
+        self.framebuffer.map_or(0, |fb| fb.snapshot(&dma, out))
+    }
+
+    /// Attach an emulated device to this VM while it is running (hotplug).
+    ///
+    /// The device is registered in the VM's device manager, then any pages
+    /// of its region still mapped in Stage-2 (e.g. a passthrough window) are
+    /// unmapped so guest accesses trap to the emulation. Registering first
+    /// means a vCPU that faults on the region after the unmap always finds
+    /// the new device; the device lock serializes it against MMIO dispatch
+    /// on other pCPUs.
+    pub fn hot_attach_device(&mut self, dev: crate::devices::Device) -> Result<(), &'static str> {
+        if !matches!(self.state, VmState::Running | VmState::Paused) {
+            return Err("VM is not running");
+        }
+        let (base, size) = (dev.base_address(), dev.size());
+        let walker = crate::ffa::stage2_walker::Stage2Walker::for_vm(self.id);
+        let page_mask = walker.page_size() - 1;
+        if base & page_mask != 0 || size & page_mask != 0 {
+            return Err("Device region not page-aligned");
+        }
+        crate::global::DEVICES[self.id].try_register_device(dev)?;
+
+        if walker.has_stage2() {
+            let mut page = base;
+            while page < base + size {
+                if walker.translate(page).is_some() {
+                    walker.unmap_4kb_page(page)?;
+                }
+                page += walker.page_size();
+            }
+        }
+        Ok(())
+    }
+
+    /// Assign a platform device's interrupts to this VM.
+    ///
+    /// `phys_intids[i]` is forwarded to the guest as `guest_intids[i]`. Each
+    /// physical SPI is enabled at the physical GICD as Group 1 with default
+    /// priority and routed to the calling PE; `handle_irq_exception` then
+    /// injects the mapped virtual INTID with HW=1, so the guest's EOI
+    /// deactivates the physical interrupt.
+    ///
+    /// SGIs, PPIs and the hypervisor's own UART SPI cannot be assigned, and
+    /// guest INTIDs must be 32-63 so an interrupt arriving while the VM is
+    /// not running can be queued for it. On error no mapping is left behind
+    /// and the GICD is untouched; the mappings are dropped again when the VM
+    /// is torn down (`global::clear_passthrough_irqs`).
+    pub fn assign_device(
+        &mut self,
+        phys_intids: &[u32],
+        guest_intids: &[u32],
+    ) -> Result<(), &'static str> {
+        if phys_intids.len() != guest_intids.len() {
+            return Err("INTID arrays differ in length");
+        }
+        if phys_intids.len() > crate::global::MAX_PASSTHROUGH_IRQS {
+            return Err("Passthrough IRQ table full");
+        }
+        for (&phys, &virt) in phys_intids.iter().zip(guest_intids.iter()) {
+            if !(32..GIC_SPURIOUS_INTID).contains(&phys) {
+                return Err("Passthrough INTID must be an SPI");
+            }
+            // Deferred delivery (global::inject_spi) tracks INTIDs 32-63 only
+            if !(32..=63).contains(&virt) {
+                return Err("Guest INTID must be 32-63");
+            }
+            if phys == platform::UART_SPI_INTID {
+                return Err("Physical INTID owned by the hypervisor");
+            }
+        }
+
+        // Register every mapping before touching the GICD; on failure drop
+        // the ones this call added (bit i = phys_intids[i] was new)
+        let mut added = 0u32;
+        for (i, (&phys, &virt)) in phys_intids.iter().zip(guest_intids.iter()).enumerate() {
+            let new = crate::global::lookup_passthrough_irq(phys).is_none();
+            if let Err(e) = crate::global::register_passthrough_irq(self.id, phys, virt) {
+                for (j, &phys) in phys_intids[..i].iter().enumerate() {
+                    if added & (1 << j) != 0 {
+                        crate::global::unregister_passthrough_irq(phys);
+                    }
+                }
+                return Err(e);
+            }
+            if new {
+                added |= 1 << i;
+            }
+        }
+
+        let mpidr: u64;
+        unsafe {
+            core::arch::asm!("mrs {}, mpidr_el1", out(reg) mpidr, options(nostack, nomem));
+        }
+        // IROUTER.Aff3 lives at [39:32], Aff2..Aff0 at [23:0]
+        let affinity = (mpidr & 0x00FF_FFFF) | ((mpidr >> 32) & 0xFF) << 32;
+        let gicd = crate::dtb::platform_info().gicd_base;
+
+        for &phys in phys_intids {
+            let reg = (phys / 32) as u64 * 4;
+            let bit = 1u32 << (phys % 32);
+            // SAFETY: EL2 accesses to the physical GICD bypass Stage-2.
+            unsafe {
+                let igroupr = (gicd + platform::GICD_IGROUPR_OFF + reg) as *mut u32;
+                core::ptr::write_volatile(igroupr, core::ptr::read_volatile(igroupr) | bit);
+                core::ptr::write_volatile(
+                    (gicd + platform::GICD_IPRIORITYR_OFF + phys as u64) as *mut u8,
+                    IRQ_DEFAULT_PRIORITY,
+                );
+                core::ptr::write_volatile(
+                    (gicd + platform::GICD_IROUTER_OFF + (phys as u64 - 32) * 8) as *mut u64,
+                    affinity,
+                );
+                core::ptr::write_volatile(
+                    (gicd + platform::GICD_ISENABLER_OFF + reg) as *mut u32,
+                    bit,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a vCPU with specified ID (scheduler weight 1)
     pub fn create_vcpu(&mut self, vcpu_id: usize) -> Result<&mut Vcpu, &'static str> {
         if vcpu_id >= MAX_VCPUS {
             return Err("vCPU ID out of range");
@@ -344,13 +736,17 @@ impl Vm {
             return Err("vCPU already exists");
         }
 
-        let vcpu = Vcpu::new(vcpu_id, 0, 0);
+        let mut vcpu = Vcpu::new(vcpu_id, 0, 0);
+        vcpu.arch_state_mut()
+            .set_affinity(crate::global::vcpu_affinity(self.id, vcpu_id));
         self.vcpus[vcpu_id] = Some(vcpu);
         self.vcpu_count += 1;
         self.scheduler.add_vcpu(vcpu_id);
+        crate::arch::aarch64::hypervisor::exception::reset_guest_exit_count(self.id, vcpu_id);
+        crate::arch::aarch64::hypervisor::serror::reset(self.id, vcpu_id);
 
         if self.state == VmState::Uninitialized {
-            self.state = VmState::Ready;
+            self.set_state(VmState::Ready);
         }
 
         Ok(self.vcpus[vcpu_id].as_mut().unwrap())
@@ -371,9 +767,11 @@ impl Vm {
 
         self.vcpus[vcpu_id] = Some(vcpu);
         self.vcpu_count += 1;
+        crate::arch::aarch64::hypervisor::exception::reset_guest_exit_count(self.id, vcpu_id);
+        crate::arch::aarch64::hypervisor::serror::reset(self.id, vcpu_id);
 
         if self.state == VmState::Uninitialized {
-            self.state = VmState::Ready;
+            self.set_state(VmState::Ready);
         }
 
         Ok(vcpu_id)
@@ -407,16 +805,16 @@ impl Vm {
             return Err("No vCPUs configured");
         }
 
-        self.state = VmState::Running;
+        self.set_state(VmState::Running);
 
         if let Some(vcpu) = self.vcpu_mut(0) {
             let result = vcpu.run();
 
-            self.state = VmState::Ready;
+            self.set_state(VmState::Ready);
 
             result
         } else {
-            self.state = VmState::Ready;
+            self.set_state(VmState::Ready);
             Err("vCPU 0 not found")
         }
     }
@@ -440,7 +838,7 @@ impl Vm {
         let vcpu = self.vcpus[vcpu_id].as_mut().ok_or("vCPU not found")?;
         let _ = vcpu; // drop borrow — re-borrow in loop
 
-        self.state = VmState::Running;
+        self.set_state(VmState::Running);
         let vs = crate::global::vm_state(self.id);
         vs.current_vcpu_id.store(vcpu_id, Ordering::Release);
         vs.vcpu_online_mask
@@ -455,6 +853,9 @@ impl Vm {
             // Drain pending network RX frames
             drain_net_rx(self.id);
 
+            // Deliver host vsock messages and control replies
+            crate::global::DEVICES[self.id].drain_vsock_rx();
+
             // Ensure PPI 27 (virtual timer) is enabled at the physical GICR.
             // Guest's GICR writes are trapped → shadow only → physical stays disabled.
             ensure_vtimer_enabled(vcpu_id);
@@ -481,7 +882,12 @@ impl Vm {
                 Err("WFI") => {
                     // WFI: execute real WFI on the physical CPU.
                     // pCPU idles until next interrupt (SGI, SPI, timer).
-                    unsafe { core::arch::asm!("wfi") };
+                    // Skip it if something was queued since the injection
+                    // above or shows up during a short poll; either way the
+                    // loop re-injects after waking.
+                    if !idle_poll(vcpu_id) {
+                        unsafe { core::arch::asm!("wfi") };
+                    }
                 }
                 Err(_) => {
                     // Other exit — loop back
@@ -489,10 +895,62 @@ impl Vm {
             }
         }
 
-        self.state = VmState::Ready;
+        self.set_state(VmState::Ready);
         Ok(())
     }
 
+    /// If the VM was terminated (`vm_terminated`, set on an exception storm
+    /// in multi-VM mode), remove every vCPU from the scheduler, mark them
+    /// offline and roll back its FF-A shares as `stop()` does. Returns true
+    /// if the VM is terminated.
+    pub fn retire_if_terminated(&mut self) -> bool {
+        let vs = crate::global::vm_state(self.id);
+        if !vs.vm_terminated.load(Ordering::Acquire) {
+            return false;
+        }
+        for id in 0..MAX_VCPUS {
+            self.scheduler.remove_vcpu(id);
+        }
+        vs.vcpu_online_mask.store(0, Ordering::Release);
+        crate::pcpu_pin::release_vm(self.id);
+        crate::global::clear_passthrough_irqs(self.id);
+        crate::ffa::reclaim_vm_shares(self.id);
+        true
+    }
+
+    /// Retire `vcpu_id` after a terminal PSCI exit (CPU_OFF, SYSTEM_OFF,
+    /// SYSTEM_RESET): remove it from the scheduler, mark it offline and
+    /// drop its queued SGIs. Other vCPUs keep running.
+    ///
+    /// Returns true if no vCPU is left online (the VM is done); its FF-A
+    /// shares are then rolled back as in `stop()`.
+    pub fn retire_vcpu(&mut self, vcpu_id: usize) -> bool {
+        let vs = crate::global::vm_state(self.id);
+        self.scheduler.remove_vcpu(vcpu_id);
+        let online = vs
+            .vcpu_online_mask
+            .fetch_and(!(1 << vcpu_id), Ordering::AcqRel)
+            & !(1 << vcpu_id);
+        if let Some(sgis) = vs.pending_sgis.get(vcpu_id) {
+            sgis.store(0, Ordering::Relaxed);
+        }
+        if online == 0 {
+            crate::pcpu_pin::release_vm(self.id);
+            crate::global::clear_passthrough_irqs(self.id);
+            crate::ffa::reclaim_vm_shares(self.id);
+        }
+        online == 0
+    }
+
+    /// Whether the VM has guest work to do: Running and not suspended in
+    /// PSCI SYSTEM_SUSPEND.
+    pub fn is_runnable(&self) -> bool {
+        self.state == VmState::Running
+            && !crate::global::vm_state(self.id)
+                .system_suspend
+                .is_suspended()
+    }
+
     /// Run one iteration of the VM scheduler: pick a vCPU, run it, handle exit.
     ///
     /// Returns `true` if the VM has no runnable vCPUs (all done or blocked).
@@ -501,10 +959,25 @@ impl Vm {
     pub fn run_one_iteration(&mut self) -> bool {
         let vs = crate::global::vm_state(self.id);
 
+        // Whole-VM termination (exception storm)
+        if self.retire_if_terminated() {
+            return true;
+        }
+
+        // A suspended VM runs nothing until a wakeup source fires
+        if self.poll_system_suspend() {
+            return false;
+        }
+
         // Check for pending PSCI CPU_ON requests
         if let Some((target, entry, ctx_id)) = vs.pending_cpu_on.take() {
-            let vcpu_id = (target & 0xFF) as usize;
-            if vcpu_id < MAX_VCPUS && self.vcpus[vcpu_id].is_none() {
+            // handle_psci already resolved the target MPIDR to a vCPU ID
+            let vcpu_id = target as usize;
+            // Never booted, or taken offline by CPU_OFF
+            let bootable = vcpu_id < MAX_VCPUS
+                && (self.vcpus[vcpu_id].is_none()
+                    || vs.vcpu_online_mask.load(Ordering::Acquire) & (1 << vcpu_id) == 0);
+            if bootable {
                 crate::uart_puts(b"[VM] Booting secondary vCPU ");
                 crate::uart_put_hex(vcpu_id as u64);
                 crate::uart_puts(b" at entry=0x");
@@ -546,28 +1019,42 @@ impl Vm {
         }
         if let Some(uart) = crate::global::DEVICES[self.id].uart_mut() {
             if uart.pending_irq().is_some() {
-                crate::global::inject_spi(33);
+                crate::global::inject_spi(self.id, 33);
             }
         }
 
         // Drain pending network RX frames
         drain_net_rx(self.id);
 
+        // Deliver host vsock messages and control replies
+        crate::global::DEVICES[self.id].drain_vsock_rx();
+
         // Inject pending SGIs and SPIs into this vCPU's arch_state before run
         inject_pending_sgis(self.vcpus[vcpu_id].as_mut().unwrap());
         inject_pending_spis(self.vcpus[vcpu_id].as_mut().unwrap());
 
-        // Arm CNTHP preemption watchdog (10ms) in SMP mode
+        // Arm CNTHP preemption watchdog (10ms) in SMP mode, unless the
+        // vCPU has claimed the pCPU exclusively (hypercall 15)
         let online = vs.vcpu_online_mask.load(Ordering::Relaxed);
         let multi_vcpu = online != 0 && (online & (online - 1)) != 0;
-        if multi_vcpu {
+        let pcpu = crate::pcpu_pin::pcpu_of_vcpu(vcpu_id);
+        if multi_vcpu && !crate::pcpu_pin::is_exclusive(self.id, pcpu) {
             ensure_cnthp_enabled();
             crate::arch::aarch64::peripherals::timer::arm_preemption_timer();
         }
 
         // Run it
         let vcpu = self.vcpus[vcpu_id].as_mut().unwrap();
+        let slice_start = crate::time::now_ticks();
         let result = vcpu.run();
+        // Only a preemption exit disarms CNTHP in the IRQ handler; whatever
+        // the exit, the slice is over and must not leak into the next one
+        crate::arch::aarch64::peripherals::timer::disarm_preemption_timer();
+        crate::scheduler::sched_stats(self.id).record_slice(
+            vcpu_id,
+            crate::time::now_ticks().wrapping_sub(slice_start),
+            vs.preemption_exit.load(Ordering::Acquire),
+        );
 
         match result {
             Ok(()) => {
@@ -576,7 +1063,10 @@ impl Vm {
                     .compare_exchange(true, false, Ordering::Acquire, Ordering::Relaxed)
                     .is_ok()
                 {
-                    self.scheduler.remove_vcpu(vcpu_id);
+                    // Only this vCPU stops; the VM is done when none is left
+                    if self.retire_vcpu(vcpu_id) {
+                        return true;
+                    }
                 } else if vs.pending_cpu_on.requested.load(Ordering::Relaxed) {
                     self.scheduler.yield_current();
                 } else if vs
@@ -611,7 +1101,7 @@ impl Vm {
             return Err("No vCPUs configured");
         }
 
-        self.state = VmState::Running;
+        self.set_state(VmState::Running);
         crate::global::CURRENT_VM_ID.store(self.id, Ordering::Release);
         // Mark vCPU 0 as online (main branch had VCPU_ONLINE_MASK init'd to 1)
         crate::global::vm_state(self.id)
@@ -624,10 +1114,38 @@ impl Vm {
             }
         }
 
-        self.state = VmState::Ready;
+        self.set_state(VmState::Ready);
         Ok(())
     }
 
+    /// Check a PSCI SYSTEM_SUSPEND for wakeup.
+    ///
+    /// Polls the wakeup sources registered at suspend time; once one fires
+    /// (or immediately, if none was registered) the suspending vCPU is
+    /// re-entered at its resume entry point with the context ID in x0, as
+    /// for CPU_ON. Returns `true` while the VM stays suspended.
+    #[cfg(not(feature = "multi_pcpu"))]
+    pub fn poll_system_suspend(&mut self) -> bool {
+        let suspend = &crate::global::vm_state(self.id).system_suspend;
+        if !suspend.is_suspended() {
+            return false;
+        }
+        let sources = suspend.wake_sources();
+        let woken = sources == 0
+            || (sources & crate::global::WAKE_SRC_RTC != 0
+                && crate::global::DEVICES[self.id].rtc_poll());
+        if !woken {
+            return true;
+        }
+        if let Some((vcpu_id, entry, ctx_id)) = suspend.take() {
+            crate::uart_puts(b"[VM] Resuming from SYSTEM_SUSPEND at entry=0x");
+            crate::uart_put_hex(entry);
+            crate::uart_puts(b"\n");
+            self.boot_secondary_vcpu(vcpu_id, entry, ctx_id);
+        }
+        false
+    }
+
     /// Boot a secondary vCPU via PSCI CPU_ON (single-pCPU mode only)
     #[cfg(not(feature = "multi_pcpu"))]
     fn boot_secondary_vcpu(&mut self, id: usize, entry: u64, ctx_id: u64) {
@@ -637,6 +1155,9 @@ impl Vm {
         if id > 0 && id < platform::num_cpus() {
             wake_gicr(crate::dtb::gicr_rd_base(id));
         }
+        if self.vcpus[id].is_none() {
+            self.vcpu_count += 1;
+        }
         let mut vcpu = Vcpu::new(id, entry, 0);
         // PSCI CPU_ON: x0 = context_id, booting into EL1h with DAIF masked
         vcpu.context_mut().gp_regs.x0 = ctx_id;
@@ -646,14 +1167,22 @@ impl Vm {
         // Enable FP/SIMD access (CPACR_EL1.FPEN = 0b11)
         vcpu.arch_state_mut().cpacr_el1 = 3 << 20;
         vcpu.arch_state_mut().init_for_vcpu(id);
+        vcpu.arch_state_mut()
+            .set_affinity(crate::global::vcpu_affinity(self.id, id));
+        vcpu.set_counter_offset(
+            crate::global::vm_state(self.id)
+                .counter_offset
+                .load(Ordering::Acquire),
+        );
         self.vcpus[id] = Some(vcpu);
-        self.vcpu_count += 1;
         self.scheduler.add_vcpu(id);
         crate::global::vm_state(self.id)
             .vcpu_online_mask
             .fetch_or(1 << id, Ordering::Release);
         // Reset exception counters so the new vCPU gets a clean slate
         crate::arch::aarch64::hypervisor::exception::reset_exception_counters();
+        crate::arch::aarch64::hypervisor::exception::reset_guest_exit_count(self.id, id);
+        crate::arch::aarch64::hypervisor::serror::reset(self.id, id);
     }
 
     /// Pause the VM
@@ -662,7 +1191,7 @@ impl Vm {
             return Err("VM is not running");
         }
 
-        self.state = VmState::Paused;
+        self.set_state(VmState::Paused);
         Ok(())
     }
 
@@ -672,24 +1201,116 @@ impl Vm {
             return Err("VM is not paused");
         }
 
-        self.state = VmState::Running;
+        self.set_state(VmState::Running);
+        Ok(())
+    }
+
+    /// Quiesce the VM and capture a whole-VM checkpoint.
+    ///
+    /// A running VM is paused first and stays paused; call `resume()` to
+    /// continue. Must be called with no vCPU of this VM inside the guest.
+    pub fn checkpoint(&mut self) -> VmCheckpoint {
+        if self.state == VmState::Running {
+            self.set_state(VmState::Paused);
+        }
+
+        let mut vcpus = [None; MAX_VCPUS];
+        for (dst, vcpu) in vcpus.iter_mut().zip(self.vcpus.iter()) {
+            *dst = vcpu.as_ref().map(|v| v.snapshot());
+        }
+
+        let vs = crate::global::vm_state(self.id);
+        let pending_sgis = core::array::from_fn(|i| vs.pending_sgis[i].load(Ordering::Acquire));
+        let pending_spis = core::array::from_fn(|i| vs.pending_spis[i].load(Ordering::Acquire));
+        let vcpu_online_mask = vs.vcpu_online_mask.load(Ordering::Acquire);
+
+        VmCheckpoint {
+            vm_id: self.id,
+            state: self.state,
+            vcpus,
+            vcpu_count: self.vcpu_count,
+            pending_sgis,
+            pending_spis,
+            vcpu_online_mask,
+            devices: crate::global::DEVICES[self.id].snapshot(),
+            stage2: Stage2Summary {
+                vttbr: self.vttbr,
+                vtcr: self.vtcr,
+                memory_initialized: self.memory_initialized,
+            },
+        }
+    }
+
+    /// Rebuild VM state from a checkpoint taken by `checkpoint()`.
+    ///
+    /// vCPUs missing from this VM are recreated, extra vCPUs are removed.
+    /// Devices must already be registered (as by `Vm::new()` plus any
+    /// `attach_*` calls); their state is overwritten in place.
+    pub fn restore_checkpoint(&mut self, cp: &VmCheckpoint) -> Result<(), &'static str> {
+        if cp.vm_id != self.id {
+            return Err("Checkpoint belongs to a different VM");
+        }
+        if self.state == VmState::Running {
+            return Err("VM must be paused before restore");
+        }
+
+        for (id, (slot, snap)) in self.vcpus.iter_mut().zip(cp.vcpus.iter()).enumerate() {
+            match (slot.as_mut(), snap) {
+                (Some(vcpu), Some(snap)) => vcpu.restore_snapshot(snap),
+                (None, Some(snap)) => {
+                    let mut vcpu = Vcpu::new(id, 0, 0);
+                    vcpu.restore_snapshot(snap);
+                    *slot = Some(vcpu);
+                    self.scheduler.add_vcpu(id);
+                }
+                (Some(_), None) => {
+                    *slot = None;
+                    self.scheduler.remove_vcpu(id);
+                }
+                (None, None) => {}
+            }
+        }
+        self.vcpu_count = cp.vcpu_count;
+
+        let vs = crate::global::vm_state(self.id);
+        for (dst, &bits) in vs.pending_sgis.iter().zip(cp.pending_sgis.iter()) {
+            dst.store(bits, Ordering::Release);
+        }
+        for (dst, &bits) in vs.pending_spis.iter().zip(cp.pending_spis.iter()) {
+            dst.store(bits, Ordering::Release);
+        }
+        vs.vcpu_online_mask
+            .store(cp.vcpu_online_mask, Ordering::Release);
+
+        crate::global::DEVICES[self.id].restore(&cp.devices);
+
+        self.vttbr = cp.stage2.vttbr;
+        self.vtcr = cp.stage2.vtcr;
+        self.memory_initialized = cp.stage2.memory_initialized;
+        self.set_state(cp.state);
         Ok(())
     }
 
     /// Stop the VM
+    ///
+    /// Outstanding FF-A shares are rolled back so no Stage-2 page is left
+    /// restricted or borrowed by a VM that will not run again.
     pub fn stop(&mut self) {
         for vcpu in self.vcpus.iter_mut().flatten() {
             vcpu.stop();
         }
+        crate::ffa::reclaim_vm_shares(self.id);
 
-        self.state = VmState::Stopped;
+        self.set_state(VmState::Stopped);
     }
 
     // ========== Scheduler Integration ==========
 
     /// Schedule the next vCPU to run
     pub fn schedule(&mut self) -> Option<usize> {
-        self.scheduler.pick_next()
+        let next = self.scheduler.pick_next();
+        crate::scheduler::sched_stats(self.id).publish(&self.scheduler);
+        next
     }
 
     /// Run the currently scheduled vCPU
@@ -721,16 +1342,22 @@ impl Vm {
         self.scheduler.unblock(vcpu_id);
     }
 
+    /// Give a vCPU `weight` run opportunities per scheduling round
+    pub fn set_vcpu_weight(&mut self, vcpu_id: usize, weight: u32) {
+        self.scheduler.set_weight(vcpu_id, weight);
+    }
+
     /// Get the currently scheduled vCPU ID
     pub fn current_vcpu(&self) -> Option<usize> {
         self.scheduler.current()
     }
 }
 
-/// Run multiple VMs time-sliced on a single pCPU (round-robin).
+/// Run multiple VMs time-sliced on a single pCPU.
 ///
-/// Outer loop round-robins between VMs, inner loop runs one vCPU iteration
-/// per VM. Each VM gets its Stage-2 activated before running.
+/// An `EpochScheduler` gives each VM one vCPU iteration per epoch, rotating
+/// which VM goes first and holding back a VM that got ahead in runtime.
+/// Each VM gets its Stage-2 activated before running.
 /// UART RX is only delivered to VM 0. VM 1 has TX-only virtual UART.
 #[cfg(not(feature = "multi_pcpu"))]
 pub fn run_multi_vm(vms: &mut [Vm]) {
@@ -742,20 +1369,24 @@ pub fn run_multi_vm(vms: &mut [Vm]) {
             uart_puts(b"[MULTI-VM] VM not ready, skipping\n");
             continue;
         }
-        vm.state = VmState::Running;
+        vm.set_state(VmState::Running);
         crate::global::vm_state(vm.id)
             .vcpu_online_mask
             .fetch_or(1, Ordering::Release);
     }
 
-    let mut done = [false; crate::global::MAX_VMS];
+    let mut epochs = crate::scheduler::EpochScheduler::new();
     loop {
-        let mut all_done = true;
-        for vm in vms.iter_mut() {
-            if done[vm.id] {
-                continue;
+        let mut runnable = [false; crate::global::MAX_VMS];
+        for (slot, vm) in vms.iter().enumerate().take(crate::global::MAX_VMS) {
+            runnable[slot] = vm.is_runnable();
+        }
+        let is_runnable = |slot: usize| runnable[slot];
+        let all_done = epochs.run_epoch(vms.len(), is_runnable, |slot| {
+            let vm = &mut vms[slot];
+            if vm.state != VmState::Running {
+                return true;
             }
-            all_done = false;
 
             // Switch to this VM's context
             crate::global::CURRENT_VM_ID.store(vm.id, Ordering::Release);
@@ -764,13 +1395,14 @@ pub fn run_multi_vm(vms: &mut [Vm]) {
             // Run one iteration (pick vCPU, run, handle exit)
             // Note: drain_net_rx is called inside run_one_iteration()
             if vm.run_one_iteration() {
-                done[vm.id] = true;
-                vm.state = VmState::Ready;
+                vm.set_state(VmState::Ready);
                 uart_puts(b"[MULTI-VM] VM ");
                 crate::uart_put_hex(vm.id as u64);
                 uart_puts(b" finished\n");
+                return true;
             }
-        }
+            false
+        });
         if all_done {
             break;
         }
@@ -781,31 +1413,22 @@ pub fn run_multi_vm(vms: &mut [Vm]) {
 ///
 /// Only used in single-pCPU mode (secondary vCPUs booted via PSCI CPU_ON).
 /// In multi-pCPU mode, each pCPU wakes its own GICR in secondary_enter_guest().
+/// A GICR that never clears ChildrenAsleep is logged and the boot continues.
 #[cfg(not(feature = "multi_pcpu"))]
 fn wake_gicr(rd_base: u64) {
-    let waker_addr = (rd_base + platform::GICR_WAKER_OFF) as *mut u32;
-    unsafe {
-        let mut waker = core::ptr::read_volatile(waker_addr);
-        // Clear ProcessorSleep (bit 1)
-        waker &= !(1 << 1);
-        core::ptr::write_volatile(waker_addr, waker);
-        // Wait for ChildrenAsleep (bit 2) to clear
-        loop {
-            let w = core::ptr::read_volatile(waker_addr);
-            if w & (1 << 2) == 0 {
-                break;
-            }
-        }
-    }
+    use crate::arch::aarch64::peripherals::gicv3;
+    gicv3::wake_redistributor(rd_base, gicv3::GICR_WAKE_SPIN_LIMIT);
 }
 
-/// Ensure SGIs (0-15) and PPI 27 (virtual timer) are enabled and Group 1
-/// at the physical GICR for the given pCPU.
+/// Ensure SGIs (0-15), PPI 27 (virtual timer) and PPI 30 (emulated
+/// physical timer) are enabled and Group 1 at the physical GICR for the
+/// given pCPU.
 ///
 /// In multi-pCPU mode, the guest's GICR writes are trapped and only update
 /// shadow state (VirtualGicr). The physical GICR never sees the guest's
 /// ISENABLER0 write, so PPIs stay disabled. Without PPI 27, the virtual
-/// timer can't generate a physical IRQ (WFI never wakes). Without SGIs
+/// timer can't generate a physical IRQ (WFI never wakes); the same holds
+/// for PPI 30 and a guest waiting on CNTP. Without SGIs
 /// 0-15, physical IPIs between pCPUs don't fire.
 ///
 /// This function programs the **physical** GICR SGI frame at EL2
@@ -814,11 +1437,12 @@ fn wake_gicr(rd_base: u64) {
 #[inline]
 pub fn ensure_vtimer_enabled(cpu_id: usize) {
     // Bits to enable: SGIs 0-15 (for physical IPIs) + PPI 27 (vtimer)
-    const ENABLE_MASK: u32 = 0xFFFF | (1 << 27); // bits 0-15 + bit 27
+    // + PPI 30 (emulated guest physical timer)
+    const ENABLE_MASK: u32 = 0xFFFF | (1 << 27) | (1 << 30);
 
     let sgi_base = crate::dtb::gicr_sgi_base(cpu_id);
     unsafe {
-        // IGROUPR0: ensure Group 1 for SGIs + PPI 27
+        // IGROUPR0: ensure Group 1 for SGIs + PPI 27/30
         let igroupr0 =
             core::ptr::read_volatile((sgi_base + platform::GICR_IGROUPR0_OFF) as *const u32);
         if igroupr0 & ENABLE_MASK != ENABLE_MASK {
@@ -863,23 +1487,73 @@ fn ensure_cnthp_enabled() {
 /// Only used in single-pCPU mode (scheduler-based scheduling).
 #[cfg(not(feature = "multi_pcpu"))]
 fn wake_pending_vcpus(scheduler: &mut Scheduler, vcpus: &[Option<Vcpu>; MAX_VCPUS], vm_id: usize) {
+    for (id, vcpu) in vcpus.iter().enumerate() {
+        if vcpu.is_some() && has_deliverable_irqs(vm_id, id) {
+            scheduler.unblock(id);
+        }
+    }
+}
+
+/// True if `vm_id` has SGIs or SPIs queued for `vcpu_id` that its guest
+/// has enabled in the GICD/GICR shadow.
+///
+/// Disabled ones stay queued until the guest enables them (the
+/// ISENABLER write kicks the vCPU then), so they must not wake it: it
+/// would find nothing to inject and spin.
+fn has_deliverable_irqs(vm_id: usize, vcpu_id: usize) -> bool {
     let vs = crate::global::vm_state(vm_id);
-    for id in 0..MAX_VCPUS {
-        if vcpus[id].is_none() {
-            continue;
+    let (Some(sgis), Some(spis)) = (vs.pending_sgis.get(vcpu_id), vs.pending_spis.get(vcpu_id))
+    else {
+        return false;
+    };
+    let devices = &crate::global::DEVICES[vm_id];
+    let any_enabled = |mut bits: u32, base: u32| {
+        while bits != 0 {
+            let n = bits.trailing_zeros();
+            if devices.irq_enabled(vcpu_id, base + n) {
+                return true;
+            }
+            bits &= bits - 1;
         }
-        if vs.pending_sgis[id].load(Ordering::Relaxed) != 0
-            || vs.pending_spis[id].load(Ordering::Relaxed) != 0
-        {
-            scheduler.unblock(id);
+        false
+    };
+    any_enabled(sgis.load(Ordering::Acquire), 0) || any_enabled(spis.load(Ordering::Acquire), 32)
+}
+
+/// True if SGIs or SPIs the guest has enabled are queued for `vcpu_id` in
+/// the current VM.
+///
+/// Checked right before a pCPU executes WFI for an idle vCPU: an interrupt
+/// queued after the last injection must not be slept through.
+pub fn has_pending_irqs(vcpu_id: usize) -> bool {
+    has_deliverable_irqs(crate::global::current_vm_id(), vcpu_id)
+}
+
+/// Pending-bitmap checks `idle_poll()` makes before giving up
+pub const IDLE_POLL_SPINS: u32 = 256;
+
+/// Poll briefly for work queued to `vcpu_id` before the pCPU commits to WFI.
+///
+/// Another pCPU may have set a PENDING_SGIS/SPIS bit whose wake IPI has not
+/// arrived yet; re-entering the guest right away beats sleeping until it
+/// does. Bounded by `IDLE_POLL_SPINS` so an idle pCPU does not busy-wait.
+/// Returns true if work appeared (skip the WFI).
+pub fn idle_poll(vcpu_id: usize) -> bool {
+    for _ in 0..IDLE_POLL_SPINS {
+        if has_pending_irqs(vcpu_id) {
+            return true;
         }
+        core::hint::spin_loop();
     }
+    false
 }
 
 /// Inject pending SGIs into a vCPU's saved arch_state LRs before running.
 ///
 /// SGIs are queued in PENDING_SGIS by the TALL1 trap handler (handle_sgi_trap)
-/// when the guest writes ICC_SGI1R_EL1.
+/// when the guest writes ICC_SGI1R_EL1. SGIs the guest has disabled in its
+/// GICR shadow stay queued until it enables them; SGIs it placed in Group 0
+/// are injected as Group 0 (vFIQ).
 ///
 /// Critical: must write to `arch_state.ich_lr[]` (not hardware LRs), because
 /// `vcpu.run()` calls `arch_state.restore()` which overwrites hardware LRs.
@@ -892,27 +1566,35 @@ pub fn inject_pending_sgis(vcpu: &mut Vcpu) {
         return;
     }
 
+    let devices = crate::global::current_devices();
     let arch = vcpu.arch_state_mut();
     for sgi in 0..16u32 {
         if all & (1 << sgi) == 0 {
             continue;
         }
-        // Find a free LR slot in saved state
-        let mut injected = false;
-        for lr in arch.ich_lr.iter_mut() {
-            if (*lr >> LR_STATE_SHIFT) & LR_STATE_MASK == 0 {
-                // LR is free — write pending SGI
-                *lr = (GicV3VirtualInterface::LR_STATE_PENDING << LR_STATE_SHIFT)
-                    | LR_GROUP1_BIT
+        if !devices.irq_enabled(vcpu_id, sgi) {
+            // Disabled by the guest — keep it pending, like a real GICR
+            vs.pending_sgis[vcpu_id].fetch_or(1 << sgi, Ordering::Relaxed);
+            continue;
+        }
+        let group = if devices.irq_group1(vcpu_id, sgi) {
+            LR_GROUP1_BIT
+        } else {
+            0
+        };
+        // Find a free (Invalid) LR slot in saved state and overwrite all
+        // of it, so no stale INTID/HW/pINTID bits survive
+        match GicV3VirtualInterface::free_lr_index(&arch.ich_lr) {
+            Some(i) => {
+                arch.ich_lr[i] = (GicV3VirtualInterface::LR_STATE_PENDING << LR_STATE_SHIFT)
+                    | group
                     | ((IRQ_DEFAULT_PRIORITY as u64) << LR_PRIORITY_SHIFT)
                     | (sgi as u64);
-                injected = true;
-                break;
             }
-        }
-        if !injected {
-            // No free LR — re-queue for next entry
-            vs.pending_sgis[vcpu_id].fetch_or(1 << sgi, Ordering::Relaxed);
+            None => {
+                // No free LR — re-queue for next entry
+                vs.pending_sgis[vcpu_id].fetch_or(1 << sgi, Ordering::Relaxed);
+            }
         }
     }
 }
@@ -920,7 +1602,10 @@ pub fn inject_pending_sgis(vcpu: &mut Vcpu) {
 /// Inject pending SPIs into a vCPU's saved arch_state LRs before running.
 ///
 /// SPIs are queued in PENDING_SPIS by `global::inject_spi()`.
-/// Bit N = SPI with INTID (N + 32).
+/// Bit N = SPI with INTID (N + 32). SPIs the guest has disabled in its GICD
+/// shadow stay queued until it enables them; SPIs it placed in Group 0 via
+/// GICD_IGROUPR are injected as Group 0 (vFIQ). A queued passthrough SPI is
+/// injected with HW=1 and its masked physical INTID handed back.
 pub fn inject_pending_spis(vcpu: &mut Vcpu) {
     let vcpu_id = vcpu.id();
     let vs = crate::global::current_vm_state();
@@ -930,43 +1615,57 @@ pub fn inject_pending_spis(vcpu: &mut Vcpu) {
         return;
     }
 
+    let devices = crate::global::current_devices();
     let arch = vcpu.arch_state_mut();
     for bit in 0..32u32 {
         if all & (1 << bit) == 0 {
             continue;
         }
         let intid = bit + 32; // SPI INTIDs start at 32
-        let mut injected = false;
-        for lr in arch.ich_lr.iter_mut() {
-            if (*lr >> LR_STATE_SHIFT) & LR_STATE_MASK == 0 {
-                *lr = (GicV3VirtualInterface::LR_STATE_PENDING << LR_STATE_SHIFT)
-                    | LR_GROUP1_BIT
+        if !devices.irq_enabled(vcpu_id, intid) {
+            vs.pending_spis[vcpu_id].fetch_or(1 << bit, Ordering::Relaxed);
+            continue;
+        }
+        let group = if devices.irq_group1(vcpu_id, intid) {
+            LR_GROUP1_BIT
+        } else {
+            0
+        };
+        let phys = crate::global::passthrough_phys_intid(crate::global::current_vm_id(), intid);
+        let hw = phys.map_or(0, |p| {
+            LR_HW_BIT | (((p as u64) & LR_PINTID_MASK) << LR_PINTID_SHIFT)
+        });
+        match GicV3VirtualInterface::free_lr_index(&arch.ich_lr) {
+            Some(i) => {
+                arch.ich_lr[i] = (GicV3VirtualInterface::LR_STATE_PENDING << LR_STATE_SHIFT)
+                    | hw
+                    | group
                     | ((IRQ_DEFAULT_PRIORITY as u64) << LR_PRIORITY_SHIFT)
                     | (intid as u64);
-                injected = true;
-                break;
+                if let Some(p) = phys {
+                    crate::global::unmask_passthrough_irq(p);
+                }
+                crate::global::record_spi_delivered(intid);
+            }
+            None => {
+                vs.pending_spis[vcpu_id].fetch_or(1 << bit, Ordering::Relaxed);
             }
-        }
-        if !injected {
-            vs.pending_spis[vcpu_id].fetch_or(1 << bit, Ordering::Relaxed);
         }
     }
 }
 
 /// Drain pending network RX frames from PORT_RX into the guest's
-/// virtio-net RX queue via DEVICES[vm_id].inject_net_rx().
+/// virtio-net RX queue via DEVICES[vm_id].drain_net_rx(). Frames with no
+/// posted RX buffer stay in PORT_RX until the guest replenishes the queue.
 ///
-/// Precondition: CURRENT_VM_ID must be set (inject_net_rx -> inject_spi
+/// Precondition: CURRENT_VM_ID must be set (drain_net_rx -> inject_spi
 /// reads it to route the SPI to the correct VM).
 fn drain_net_rx(vm_id: usize) {
-    use crate::vswitch::{MAX_FRAME_SIZE, PORT_RX};
+    use crate::vswitch::PORT_RX;
     if PORT_RX[vm_id].is_empty() {
         return; // fast path
     }
-    let mut buf = [0u8; MAX_FRAME_SIZE];
-    while let Some(len) = PORT_RX[vm_id].take(&mut buf) {
-        crate::global::DEVICES[vm_id].inject_net_rx(&buf[..len]);
-    }
+    crate::global::DEVICES[vm_id].drain_net_rx();
 }
 
 impl core::fmt::Debug for Vm {
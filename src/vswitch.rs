@@ -2,7 +2,8 @@
 //!
 //! PORT_RX[port_id] is a per-port SPSC ring buffer.
 //! Producer: VSwitch::forward() (inside DEVICES lock during TX)
-//! Consumer: run loop drain_net_rx() (outside DEVICES lock)
+//! Consumer: VirtioNet::process_rx() (inside DEVICES lock), from the run
+//! loop's drain_net_rx() or the guest's RX queue notify
 
 use core::cell::UnsafeCell;
 use core::sync::atomic::{AtomicUsize, Ordering};
@@ -31,8 +32,9 @@ impl FrameSlot {
 
 /// Per-port SPSC ring buffer for async frame delivery.
 ///
-/// Single producer (VSwitch::forward, inside DEVICES lock) and
-/// single consumer (drain_net_rx, in run loop). Uses atomic
+/// Single producer (VSwitch::forward, inside the sender's DEVICES lock)
+/// and single consumer (VirtioNet::process_rx, inside the receiver's
+/// DEVICES lock). Uses atomic
 /// head/tail indices for lock-free synchronization.
 pub struct NetRxRing {
     frames: UnsafeCell<[FrameSlot; NET_RX_RING_SIZE]>,
@@ -41,7 +43,7 @@ pub struct NetRxRing {
 }
 
 // SAFETY: SPSC — single producer (VSwitch in DEVICES lock),
-// single consumer (run loop). Atomic indices provide ordering.
+// single consumer (process_rx in DEVICES lock). Atomic indices provide ordering.
 unsafe impl Sync for NetRxRing {}
 
 impl NetRxRing {
@@ -103,6 +105,27 @@ impl NetRxRing {
         Some(len)
     }
 
+    /// Hand the oldest frame to `deliver` in place (consumer side). The
+    /// frame is consumed only if `deliver` returns true, so one that
+    /// cannot be delivered yet stays at the head of the ring.
+    ///
+    /// Returns false if the ring is empty or the frame was kept.
+    pub fn take_if(&self, deliver: impl FnOnce(&[u8]) -> bool) -> bool {
+        let head = self.head.load(Ordering::Relaxed);
+        if head == self.tail.load(Ordering::Acquire) {
+            return false; // empty
+        }
+        let consumed = unsafe {
+            let slot = &(*self.frames.get())[head];
+            deliver(&slot.buf[..slot.len as usize])
+        };
+        if consumed {
+            self.head
+                .store((head + 1) % NET_RX_RING_SIZE, Ordering::Release);
+        }
+        consumed
+    }
+
     /// Check if the ring is empty (for fast-path skip in run loop).
     #[inline]
     pub fn is_empty(&self) -> bool {
@@ -0,0 +1,23 @@
+//! Shared test teardown guard
+//!
+//! Tests that patch global state (hooks, device managers, per-VM VTTBRs)
+//! wrap the restore in a `Cleanup` so every early `return` on failure runs
+//! it; `drop(cleanup)` runs it early where a later check needs the state
+//! already restored.
+
+/// Runs the wrapped closure once, when dropped.
+pub struct Cleanup<F: FnOnce()>(Option<F>);
+
+impl<F: FnOnce()> Cleanup<F> {
+    pub fn new(undo: F) -> Self {
+        Self(Some(undo))
+    }
+}
+
+impl<F: FnOnce()> Drop for Cleanup<F> {
+    fn drop(&mut self) {
+        if let Some(undo) = self.0.take() {
+            undo();
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! Shared FF-A test fixture
+//!
+//! FFA_MEM_SHARE of one page to the stub SP and FFA_MEM_RECLAIM of the
+//! returned handle, issued through the proxy as VM 0 would.
+
+use hypervisor::arch::aarch64::regs::VcpuContext;
+use hypervisor::ffa;
+
+/// Partition ID of the stub SPMC's SP
+pub const SP_ID: u64 = 0x8001;
+
+/// FFA_MEM_SHARE one page at `ipa` to the SP. Returns the handle or the
+/// FF-A error code.
+pub fn share(ipa: u64) -> Result<u64, i32> {
+    let mut ctx = VcpuContext::default();
+    ctx.gp_regs.x0 = ffa::FFA_MEM_SHARE_32;
+    ctx.gp_regs.x3 = ipa;
+    ctx.gp_regs.x4 = 1;
+    ctx.gp_regs.x5 = SP_ID;
+    ffa::proxy::handle_ffa_call(&mut ctx);
+    if ctx.gp_regs.x0 == ffa::FFA_SUCCESS_32 {
+        Ok(ffa::mem_handle_from_result(&ctx))
+    } else {
+        Err(ctx.gp_regs.x2 as u32 as i32)
+    }
+}
+
+/// FFA_MEM_RECLAIM `handle`. Returns the FF-A error code on failure.
+pub fn reclaim(handle: u64) -> Result<(), i32> {
+    let mut ctx = VcpuContext::default();
+    ctx.gp_regs.x0 = ffa::FFA_MEM_RECLAIM;
+    ffa::set_mem_handle_args(&mut ctx, handle);
+    ffa::proxy::handle_ffa_call(&mut ctx);
+    if ctx.gp_regs.x0 == ffa::FFA_SUCCESS_32 {
+        Ok(())
+    } else {
+        Err(ctx.gp_regs.x2 as u32 as i32)
+    }
+}
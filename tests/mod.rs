@@ -1,14 +1,33 @@
 pub mod test_allocator;
+pub mod test_cache_maint;
 pub mod test_complete_interrupt;
+pub mod test_counter_offset;
 pub mod test_decode;
+pub mod test_device_locking;
 pub mod test_device_routing;
+pub mod test_dirty_tracking;
+pub mod test_dma_mapper;
 pub mod test_dtb;
 pub mod test_dynamic_pagetable;
+pub mod test_epoch_scheduler;
+pub mod test_exception;
+pub mod test_exclusive_pcpu;
 pub mod test_ffa;
+pub mod test_ffa_multi_receiver;
+pub mod test_ffa_retrieve_resp;
+pub mod test_ffa_run;
+pub mod test_ffa_share_capacity;
+pub mod test_ffa_share_limit;
+pub mod test_ffa_vm_shutdown;
+pub mod test_fp_reset;
+pub mod test_fp_switch;
+pub mod test_framebuffer;
 pub mod test_gicd;
 pub mod test_gicr;
+pub mod test_gicr_wake;
 pub mod test_gicv3_virt;
 pub mod test_global;
+pub mod test_granule_64k;
 ///! Test module for hypervisor
 ///!
 ///! This module contains various integration tests for the hypervisor.
@@ -17,17 +36,65 @@ pub mod test_guest_interrupt;
 pub mod test_guest_irq;
 pub mod test_guest_loader;
 pub mod test_heap;
+pub mod test_hot_attach;
+pub mod test_id_override;
+pub mod test_idle_poll;
+pub mod test_irq_enable_gate;
+pub mod test_irq_group;
+pub mod test_irq_latency;
+pub mod test_lifecycle;
+pub mod test_lr_free_slot;
 pub mod test_mmio;
+pub mod test_mmio_fuzz;
+pub mod test_mmio_pair;
+pub mod test_mmio_strict;
+pub mod test_mmio_trace;
 pub mod test_multi_vcpu;
 pub mod test_multi_vm_devices;
 pub mod test_net_rx_ring;
 pub mod test_page_ownership;
+pub mod test_passthrough;
+pub mod test_pending_irqs;
+pub mod test_pl011_fifo;
 pub mod test_pl031;
+#[cfg(not(feature = "multi_pcpu"))]
+pub mod test_preemption_disarm;
+pub mod test_psci_cpu_off;
+pub mod test_psci_cpu_on;
+pub mod test_pv_console;
+pub mod test_ram_attrs;
+pub mod test_reboot_counter;
+#[cfg(not(feature = "multi_pcpu"))]
+pub mod test_rtc_wake;
+pub mod test_sched_stats;
 pub mod test_scheduler;
+pub mod test_sensor;
+pub mod test_serror_disr;
+pub mod test_sgi_wake;
+pub mod test_shared_buffer;
 pub mod test_simple_guest;
+pub mod test_spi_level;
+pub mod test_spi_vm_routing;
+pub mod test_stage2_walker;
+pub mod test_sysreg_trap;
+pub mod test_time;
 pub mod test_timer;
+#[cfg(feature = "linux_guest")]
+pub mod test_unbacked_abort;
+pub mod test_virtio_balloon;
+pub mod test_virtio_blk;
+pub mod test_virtio_cdrom;
+pub mod test_virtio_event_idx;
+pub mod test_virtio_indirect;
+pub mod test_virtio_input;
+pub mod test_virtio_intid;
+pub mod test_virtio_isr;
+pub mod test_virtio_multi_blk;
 pub mod test_virtio_net;
+pub mod test_virtio_vsock;
 pub mod test_vm_activate;
+pub mod test_vm_checkpoint;
+pub mod test_vm_context_guard;
 pub mod test_vm_scheduler;
 pub mod test_vm_state_isolation;
 pub mod test_vmid_vttbr;
@@ -35,40 +102,116 @@ pub mod test_spmc_handler;
 pub mod test_sp_context;
 pub mod test_secure_stage2;
 pub mod test_vswitch;
+pub mod test_wfi_irq_mask;
+pub mod test_wfi_tick;
+pub mod test_wfi_timeout;
+pub mod cleanup;
+pub mod ffa_fixture;
+pub mod virtio_fixture;
 
 // Re-export test functions for easy access
 pub use test_allocator::run_allocator_test;
+pub use test_cache_maint::run_cache_maint_test;
 pub use test_complete_interrupt::run_complete_interrupt_test;
+pub use test_counter_offset::run_counter_offset_test;
 pub use test_decode::run_decode_test;
+pub use test_device_locking::run_device_locking_test;
 pub use test_device_routing::run_device_routing_test;
+pub use test_dirty_tracking::run_dirty_tracking_test;
+pub use test_dma_mapper::run_dma_mapper_test;
 pub use test_dtb::run_dtb_test;
 pub use test_dynamic_pagetable::run_dynamic_pt_test;
+pub use test_epoch_scheduler::run_epoch_scheduler_test;
+pub use test_exception::run_exception_test;
+pub use test_exclusive_pcpu::run_exclusive_pcpu_test;
 pub use test_ffa::run_ffa_test;
+pub use test_ffa_multi_receiver::run_ffa_multi_receiver_test;
+pub use test_ffa_retrieve_resp::run_ffa_retrieve_resp_test;
+pub use test_ffa_run::run_ffa_run_test;
+pub use test_ffa_share_capacity::run_ffa_share_capacity_test;
+pub use test_ffa_share_limit::run_ffa_share_limit_test;
+pub use test_ffa_vm_shutdown::run_ffa_vm_shutdown_test;
+pub use test_fp_reset::run_fp_reset_test;
+pub use test_fp_switch::run_fp_switch_test;
+pub use test_framebuffer::run_framebuffer_test;
 pub use test_gicd::run_gicd_test;
 pub use test_gicr::run_gicr_test;
+pub use test_gicr_wake::run_gicr_wake_test;
 pub use test_gicv3_virt::run_gicv3_virt_test;
 pub use test_global::run_global_test;
+pub use test_granule_64k::run_granule_64k_test;
 pub use test_guest::run_test as run_guest_test;
 pub use test_guest_interrupt::run_guest_interrupt_test;
 pub use test_guest_irq::run_irq_test;
 pub use test_guest_loader::run_test as run_guest_loader_test;
 pub use test_heap::run_heap_test;
+pub use test_hot_attach::run_hot_attach_test;
+pub use test_id_override::run_id_override_test;
+pub use test_idle_poll::run_idle_poll_test;
+pub use test_irq_enable_gate::run_irq_enable_gate_test;
+pub use test_irq_group::run_irq_group_test;
+pub use test_irq_latency::run_irq_latency_test;
+pub use test_lifecycle::run_lifecycle_test;
+pub use test_lr_free_slot::run_lr_free_slot_test;
 pub use test_mmio::run_mmio_test;
+pub use test_mmio_fuzz::run_mmio_fuzz_test;
+pub use test_mmio_pair::run_mmio_pair_test;
+pub use test_mmio_strict::run_mmio_strict_test;
+pub use test_mmio_trace::run_mmio_trace_test;
 pub use test_multi_vcpu::run_multi_vcpu_test;
 pub use test_multi_vm_devices::run_multi_vm_devices_test;
 pub use test_net_rx_ring::run_net_rx_ring_test;
 pub use test_page_ownership::run_page_ownership_test;
+pub use test_passthrough::run_passthrough_test;
+pub use test_pending_irqs::run_pending_irqs_test;
+pub use test_pl011_fifo::run_pl011_fifo_test;
 pub use test_pl031::run_pl031_test;
+#[cfg(not(feature = "multi_pcpu"))]
+pub use test_preemption_disarm::run_preemption_disarm_test;
+pub use test_psci_cpu_off::run_psci_cpu_off_test;
+pub use test_psci_cpu_on::run_psci_cpu_on_test;
+pub use test_pv_console::run_pv_console_test;
+pub use test_ram_attrs::run_ram_attrs_test;
+pub use test_reboot_counter::run_reboot_counter_test;
+#[cfg(not(feature = "multi_pcpu"))]
+pub use test_rtc_wake::run_rtc_wake_test;
+pub use test_sched_stats::run_sched_stats_test;
 pub use test_scheduler::run_scheduler_test;
+pub use test_sensor::run_sensor_test;
+pub use test_serror_disr::run_serror_disr_test;
+pub use test_sgi_wake::run_sgi_wake_test;
+pub use test_shared_buffer::run_shared_buffer_test;
 pub use test_spmc_handler::run_tests as run_spmc_handler_test;
 pub use test_sp_context::run_tests as run_sp_context_test;
 pub use test_secure_stage2::run_tests as run_secure_stage2_test;
 pub use test_simple_guest::run_test as run_simple_guest_test;
+pub use test_spi_level::run_spi_level_test;
+pub use test_spi_vm_routing::run_spi_vm_routing_test;
+pub use test_stage2_walker::run_stage2_walker_test;
+pub use test_sysreg_trap::run_sysreg_trap_test;
+pub use test_time::run_time_test;
 #[allow(unused_imports)]
-pub use test_timer::run_timer_test;
+pub use test_timer::{run_ptimer_test, run_timer_test};
+#[cfg(feature = "linux_guest")]
+pub use test_unbacked_abort::run_unbacked_abort_test;
+pub use test_virtio_balloon::run_virtio_balloon_test;
+pub use test_virtio_blk::run_virtio_blk_test;
+pub use test_virtio_cdrom::run_virtio_cdrom_test;
+pub use test_virtio_event_idx::run_virtio_event_idx_test;
+pub use test_virtio_indirect::run_virtio_indirect_test;
+pub use test_virtio_input::run_virtio_input_test;
+pub use test_virtio_intid::run_virtio_intid_test;
+pub use test_virtio_isr::run_virtio_isr_test;
+pub use test_virtio_multi_blk::run_virtio_multi_blk_test;
 pub use test_virtio_net::run_virtio_net_test;
+pub use test_virtio_vsock::run_virtio_vsock_test;
 pub use test_vm_activate::run_vm_activate_test;
+pub use test_vm_checkpoint::run_vm_checkpoint_test;
+pub use test_vm_context_guard::run_vm_context_guard_test;
 pub use test_vm_scheduler::run_vm_scheduler_test;
 pub use test_vm_state_isolation::run_vm_state_isolation_test;
 pub use test_vmid_vttbr::run_vmid_vttbr_test;
 pub use test_vswitch::run_vswitch_test;
+pub use test_wfi_irq_mask::run_wfi_irq_mask_test;
+pub use test_wfi_tick::run_wfi_tick_test;
+pub use test_wfi_timeout::run_wfi_timeout_test;
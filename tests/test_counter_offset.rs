@@ -0,0 +1,122 @@
+//! Per-vCPU counter offset (CNTVOFF_EL2) tests
+//!
+//! Runs a guest stub that reads CNTVCT_EL0 into x19 and again into x20 and
+//! exits: a fresh vCPU's virtual counter starts near zero and runs forward,
+//! and one entered with an explicit `set_counter_offset` reads the physical
+//! count minus it.
+
+use core::arch::asm;
+use hypervisor::time;
+use hypervisor::uart_puts;
+use hypervisor::vcpu::Vcpu;
+use hypervisor::vm::Vm;
+
+/// Virtual count the guest should see with an offset of `now - BIAS`
+const BIAS: u64 = 1 << 40;
+
+#[repr(C, align(4096))]
+struct CounterGuest {
+    code: [u32; 8],
+}
+
+static COUNTER_GUEST: CounterGuest = CounterGuest {
+    code: [
+        0xd53be053, // mrs x19, cntvct_el0
+        0xd5033fdf, // isb
+        0xd53be054, // mrs x20, cntvct_el0
+        0xd2800020, // mov x0, #1 (exit hypercall)
+        0xd4000002, // hvc #0
+        0x14000000, // b .
+        0x00000000, // padding
+        0x00000000, // padding
+    ],
+};
+
+fn physical_count() -> u64 {
+    let count: u64;
+    unsafe { asm!("isb", "mrs {}, cntpct_el0", out(reg) count, options(nostack, nomem)) };
+    count
+}
+
+/// Enter vCPU 0 of `vm` at the stub; returns the two CNTVCT values it read.
+fn read_guest_counters(vm: &mut Vm, entry: u64) -> Option<(u64, u64)> {
+    let vcpu = vm.vcpu_mut(0)?;
+    vcpu.reset(entry, entry + 0x10000);
+    vcpu.context_mut().gp_regs.x19 = u64::MAX;
+    vcpu.context_mut().gp_regs.x20 = u64::MAX;
+    vm.run().ok()?;
+    vm.vcpu(0)
+        .map(|v| (v.context().gp_regs.x19, v.context().gp_regs.x20))
+}
+
+/// Enter vCPU 0 of `vm` at the stub; returns the first CNTVCT it read.
+fn read_guest_counter(vm: &mut Vm, entry: u64) -> Option<u64> {
+    read_guest_counters(vm, entry).map(|(first, _)| first)
+}
+
+pub fn run_counter_offset_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Counter Offset (CNTVOFF) Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let entry = &COUNTER_GUEST.code as *const _ as u64;
+    // One second of counter ticks: generous for the few instructions
+    // between vCPU creation and the guest's read
+    let threshold = time::frequency();
+
+    // Test 1: init_for_vcpu takes the current physical count as offset
+    uart_puts(b"[CNTVOFF] Test 1: new vCPU offset is the physical count...\n");
+    let before = physical_count();
+    let vcpu = Vcpu::new(0, entry, 0);
+    let after = physical_count();
+    let offset = vcpu.arch_state().cntvoff;
+    if offset < before || offset > after {
+        uart_puts(b"[CNTVOFF] FAILED: offset not captured at vCPU creation\n");
+        return;
+    }
+    uart_puts(b"[CNTVOFF] Test 1 PASSED\n\n");
+
+    let mut vm = Vm::new(0);
+    vm.init_memory(entry & !(2 * 1024 * 1024 - 1), 4 * 1024 * 1024);
+    if vm.create_vcpu(0).is_err() {
+        uart_puts(b"[CNTVOFF] FAILED: create_vcpu\n");
+        return;
+    }
+
+    // Test 2: the guest's first CNTVCT read is near zero
+    uart_puts(b"[CNTVOFF] Test 2: guest counter starts near zero...\n");
+    let first = read_guest_counter(&mut vm, entry);
+    if !first.is_some_and(|c| c < threshold) {
+        unsafe { asm!("msr cntvoff_el2, xzr", "isb", options(nostack, nomem)) };
+        uart_puts(b"[CNTVOFF] FAILED: guest CNTVCT not below threshold\n");
+        return;
+    }
+    uart_puts(b"[CNTVOFF] Test 2 PASSED\n\n");
+
+    // Test 3: two back-to-back reads run forward from near zero
+    uart_puts(b"[CNTVOFF] Test 3: guest counter monotonic from near zero...\n");
+    let pair = read_guest_counters(&mut vm, entry);
+    if !pair.is_some_and(|(a, b)| a <= b && b < threshold) {
+        unsafe { asm!("msr cntvoff_el2, xzr", "isb", options(nostack, nomem)) };
+        uart_puts(b"[CNTVOFF] FAILED: guest CNTVCT not monotonic from zero\n");
+        return;
+    }
+    uart_puts(b"[CNTVOFF] Test 3 PASSED\n\n");
+
+    // Test 4: set_counter_offset() is what the guest runs with
+    uart_puts(b"[CNTVOFF] Test 4: explicit offset applied on entry...\n");
+    if let Some(vcpu) = vm.vcpu_mut(0) {
+        vcpu.set_counter_offset(physical_count().wrapping_sub(BIAS));
+    }
+    let biased = read_guest_counter(&mut vm, entry);
+    unsafe { asm!("msr cntvoff_el2, xzr", "isb", options(nostack, nomem)) };
+    if !biased.is_some_and(|c| c >= BIAS && c - BIAS < threshold) {
+        uart_puts(b"[CNTVOFF] FAILED: guest CNTVCT ignores the set offset\n");
+        return;
+    }
+    uart_puts(b"[CNTVOFF] Test 4 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Counter Offset (CNTVOFF) Test PASSED (4 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
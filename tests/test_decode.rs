@@ -1,8 +1,11 @@
 //! MMIO instruction decode tests
 //!
-//! Tests MmioAccess::decode() for ISS-based and instruction-based paths.
+//! Tests MmioAccess::decode() for ISS-based and instruction-based paths,
+//! sign/zero extension of loaded values to the destination width, and
+//! register 31 as SP (base) versus XZR (data), and LDP/STP pair decode.
 
-use hypervisor::arch::aarch64::hypervisor::decode::MmioAccess;
+use hypervisor::arch::aarch64::hypervisor::decode::{MmioAccess, PairAccess, PairIndex};
+use hypervisor::arch::aarch64::regs::VcpuContext;
 use hypervisor::uart_puts;
 
 pub fn run_decode_test() {
@@ -72,8 +75,138 @@ pub fn run_decode_test() {
     }
     uart_puts(b"[DECODE] Test 9 PASSED\n\n");
 
+    // ISS load with SSE (bit 21) set; SF (bit 15) selects Xt over Wt
+    let iss_signed_load =
+        |sas: u32, sf: u32| -> u32 { (1 << 24) | (sas << 22) | (1 << 21) | (7 << 16) | (sf << 15) };
+
+    // Test 10: LDRSB Wt — 0x80 becomes 0xFFFFFF80, upper half clear
+    uart_puts(b"[DECODE] Test 10: ISS signed byte into Wt...\n");
+    let access = MmioAccess::decode(0, iss_signed_load(0, 0)).expect("decode failed");
+    if access.reg() != 7 || access.extend(0x80) != 0xFFFF_FF80 || access.extend(0x7F) != 0x7F {
+        uart_puts(b"[DECODE] FAILED: LDRSB Wt not sign-extended to 32 bits\n");
+        return;
+    }
+    uart_puts(b"[DECODE] Test 10 PASSED\n\n");
+
+    // Test 11: LDRSH Xt — 0x8000 becomes 0xFFFF_FFFF_FFFF_8000
+    uart_puts(b"[DECODE] Test 11: ISS signed half into Xt...\n");
+    let access = MmioAccess::decode(0, iss_signed_load(1, 1)).expect("decode failed");
+    if access.extend(0x8000) != 0xFFFF_FFFF_FFFF_8000 || access.extend(0x1234) != 0x1234 {
+        uart_puts(b"[DECODE] FAILED: LDRSH Xt not sign-extended to 64 bits\n");
+        return;
+    }
+    uart_puts(b"[DECODE] Test 11 PASSED\n\n");
+
+    // Test 12: LDRSW Xt — via ISS and via raw decode (ISV=0), plus an
+    // unsigned LDR W that must stay zero-extended
+    uart_puts(b"[DECODE] Test 12: signed word into Xt (ISS and insn)...\n");
+    let iss_ldrsw = MmioAccess::decode(0, iss_signed_load(2, 1)).expect("decode failed");
+    let insn_ldrsw: u32 = 0xb9800262; // LDRSW X2, [X19, #0]
+    let raw_ldrsw = MmioAccess::decode(insn_ldrsw, iss_no_isv).expect("decode failed");
+    let insn_ldrsb_w: u32 = 0x39c00263; // LDRSB W3, [X19, #0]
+    let raw_ldrsb = MmioAccess::decode(insn_ldrsb_w, iss_no_isv).expect("decode failed");
+    let raw_ldr = MmioAccess::decode(insn_ldr_w2, iss_no_isv).expect("decode failed");
+    if iss_ldrsw.extend(0x8000_0000) != 0xFFFF_FFFF_8000_0000
+        || !raw_ldrsw.is_load()
+        || raw_ldrsw.reg() != 2
+        || raw_ldrsw.extend(0xFFFF_FFFE) != 0xFFFF_FFFF_FFFF_FFFE
+        || raw_ldrsb.extend(0xFF) != 0xFFFF_FFFF
+        || raw_ldr.extend(0x8000_0000) != 0x8000_0000
+    {
+        uart_puts(b"[DECODE] FAILED: LDRSW/LDRSB/LDR extension wrong\n");
+        return;
+    }
+    uart_puts(b"[DECODE] Test 12 PASSED\n\n");
+
+    // Test 13: LDR W2, [SP, #8] — Rn=31 is the guest SP_EL1 (EL1h), not
+    // XZR and not the hypervisor SP; STR WZR, [SP] stores zero
+    uart_puts(b"[DECODE] Test 13: SP as base register...\n");
+    let mut ctx = VcpuContext::default(); // SPSR_EL2 = EL1h
+    ctx.sys_regs.sp_el1 = 0x0900_0000;
+    ctx.sp = 0x4100_0000;
+    ctx.gp_regs.x30 = 0xDEAD_BEEF;
+    let insn_ldr_sp: u32 = 0xb9400be2; // LDR W2, [SP, #8]
+    let insn_str_wzr: u32 = 0xb90003ff; // STR WZR, [SP, #0]
+    let ldr_sp = MmioAccess::decode(insn_ldr_sp, iss_no_isv).expect("decode failed");
+    let str_wzr = MmioAccess::decode(insn_str_wzr, iss_no_isv).expect("decode failed");
+    if ldr_sp.reg() != 2
+        || MmioAccess::effective_address(insn_ldr_sp, &ctx) != Some(0x0900_0008)
+        || MmioAccess::effective_address(insn_str_wzr, &ctx) != Some(0x0900_0000)
+        || !str_wzr.is_store()
+        || ctx.get_gpr(str_wzr.reg()) != 0
+        || ctx.get_base_reg(30) != 0xDEAD_BEEF
+    {
+        uart_puts(b"[DECODE] FAILED: register 31 not SP as base / XZR as data\n");
+        return;
+    }
+    uart_puts(b"[DECODE] Test 13 PASSED\n\n");
+
+    // Test 14: STP W1, W2, [X0] and LDPSW X7, X8, [X2, #8] — both
+    // registers, element size, sign extension, no writeback
+    uart_puts(b"[DECODE] Test 14: Instruction STP / LDPSW...\n");
+    let stp = PairAccess::decode(0x29000801, iss_no_isv).expect("decode failed");
+    let ldpsw = PairAccess::decode(0x69412047, iss_no_isv).expect("decode failed");
+    if stp.is_load()
+        || stp.first.reg() != 1
+        || stp.second.reg() != 2
+        || stp.size() != 4
+        || stp.base != 0
+        || stp.address(0x1000) != 0x1000
+        || stp.writeback(0x1000).is_some()
+        || !ldpsw.is_load()
+        || ldpsw.second.reg() != 8
+        || ldpsw.address(0x2000) != 0x2008
+        || ldpsw.first.extend(0x8000_0000) != 0xFFFF_FFFF_8000_0000
+    {
+        uart_puts(b"[DECODE] FAILED: STP/LDPSW fields wrong\n");
+        return;
+    }
+    uart_puts(b"[DECODE] Test 14 PASSED\n\n");
+
+    // Test 15: LDP X3, X4, [SP, #16]! and LDP W5, W6, [X1], #-8 — address
+    // and base writeback, SP written back to SP_EL1
+    uart_puts(b"[DECODE] Test 15: Instruction LDP pre/post-index...\n");
+    let pre = PairAccess::decode(0xa9c113e3, iss_no_isv).expect("decode failed");
+    let post = PairAccess::decode(0x28ff1825, iss_no_isv).expect("decode failed");
+    let sp = ctx.get_base_reg(pre.base);
+    if let Some(new_sp) = pre.writeback(sp) {
+        ctx.set_base_reg(pre.base, new_sp);
+    }
+    if pre.index != PairIndex::PreIndex
+        || pre.size() != 8
+        || pre.address(sp) != 0x0900_0010
+        || ctx.sys_regs.sp_el1 != 0x0900_0010
+        || post.index != PairIndex::PostIndex
+        || post.first.reg() != 5
+        || post.address(0x1000) != 0x1000
+        || post.writeback(0x1000) != Some(0xff8)
+    {
+        uart_puts(b"[DECODE] FAILED: LDP pre/post-index address or writeback\n");
+        return;
+    }
+    uart_puts(b"[DECODE] Test 15 PASSED\n\n");
+
+    // Test 16: ISS path — a valid syndrome is never a pair, the single
+    // decoder rejects pairs, STGP is not a pair load/store; and the faulting
+    // element (FAR) locates the pair's first IPA
+    uart_puts(b"[DECODE] Test 16: pair vs ISS decode, faulting element...\n");
+    let iss_single = MmioAccess::decode(0xa9c113e3, iss_store_w5).expect("decode failed");
+    if PairAccess::decode(0xa9c113e3, iss_store_w5).is_some()
+        || iss_single.reg() != 5
+        || !iss_single.is_store()
+        || MmioAccess::decode(0xa9c113e3, iss_no_isv).is_some()
+        || PairAccess::decode(0x69000801, iss_no_isv).is_some()
+        || stp.first_ipa(0x0a00_0000, 0x1000, 0x1000) != Some(0x0a00_0000)
+        || stp.first_ipa(0x0a00_0004, 0x1004, 0x1000) != Some(0x0a00_0000)
+        || stp.first_ipa(0x0a00_0000, 0x2000, 0x1000).is_some()
+    {
+        uart_puts(b"[DECODE] FAILED: pair/ISS decode or first element IPA\n");
+        return;
+    }
+    uart_puts(b"[DECODE] Test 16 PASSED\n\n");
+
     uart_puts(b"========================================\n");
-    uart_puts(b"  MMIO Instruction Decode Test PASSED (9 assertions)\n");
+    uart_puts(b"  MMIO Instruction Decode Test PASSED (16 assertions)\n");
     uart_puts(b"========================================\n\n");
 }
 
@@ -0,0 +1,147 @@
+//! Per-device locking tests
+//!
+//! Single-pCPU approximation of two pCPUs trapping on MMIO at once: a held
+//! `DeviceGuard` (or a host-side `with_device` call in progress) stands for
+//! one pCPU's access while the other pCPU's accesses go through the same
+//! `LockedDeviceManager`. Whatever the other pCPU needs besides the held
+//! device — table lookups, other devices, manager-wide state — must not
+//! wait on it, or a real second pCPU would spin forever.
+
+use super::cleanup::Cleanup;
+use hypervisor::devices::sensor::SENSOR_BASE;
+use hypervisor::devices::{Device, LockedDeviceManager, MmioDevice};
+use hypervisor::global::clear_spi;
+use hypervisor::platform::{virtio_slot, VIRTIO_SLOT_BLK};
+use hypervisor::uart_puts;
+
+const VM_ID: usize = 0;
+const DISK_SIZE: usize = 4096;
+const NOTIFIES_PER_CPU: u64 = 64;
+
+const MAGIC_VALUE: u64 = 0x000;
+const VIRTIO_MAGIC: u64 = 0x7472_6976;
+const QUEUE_SEL: u64 = 0x030;
+const QUEUE_NUM: u64 = 0x038;
+const QUEUE_READY: u64 = 0x044;
+const QUEUE_NOTIFY: u64 = 0x050;
+
+const SENSOR_ID: u64 = 0x000;
+const SENSOR_TEMP: u64 = 0x004;
+const SENSOR_ID_VALUE: u64 = 0x5345_4E53;
+const TEMP_MC: i32 = 71_500;
+
+#[repr(C, align(4096))]
+struct Disk([u8; DISK_SIZE]);
+
+static mut DISK_IMAGE: Disk = Disk([0; DISK_SIZE]);
+
+static DEVICES: LockedDeviceManager = LockedDeviceManager::new(VM_ID);
+
+pub fn run_device_locking_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Per-Device Locking Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let (blk, blk_intid) = virtio_slot(VIRTIO_SLOT_BLK);
+    let disk = &raw mut DISK_IMAGE as u64;
+    DEVICES.with_all(|dm| {
+        dm.reset();
+        dm.attach_virtio_blk(disk, DISK_SIZE as u64);
+        dm.attach_sensor();
+    });
+    // Queue 0 ready with no rings: every QueueNotify counts, none consumes
+    DEVICES.handle_mmio(blk + QUEUE_SEL, 0, 4, true);
+    DEVICES.handle_mmio(blk + QUEUE_NUM, 8, 4, true);
+    DEVICES.handle_mmio(blk + QUEUE_READY, 1, 4, true);
+    let cleanup = Cleanup::new(|| {
+        DEVICES.with_all(|dm| dm.reset());
+        clear_spi(VM_ID, blk_intid);
+    });
+
+    // Test 1: another device is reachable while one is held; the held one is not
+    uart_puts(b"[DEV-LOCK] Test 1: different devices in parallel...\n");
+    let Some(held) = DEVICES.lock_device(blk) else {
+        uart_puts(b"[DEV-LOCK] FAILED: virtio-blk not found\n");
+        return;
+    };
+    let other_free = DEVICES.try_lock_device(SENSOR_BASE).is_some();
+    let sensor_id = DEVICES.handle_mmio(SENSOR_BASE + SENSOR_ID, 0, 4, false);
+    let same_busy = DEVICES.try_lock_device(blk).is_none();
+    drop(held);
+    let released = DEVICES.try_lock_device(blk).is_some();
+    if !other_free || sensor_id != Some(SENSOR_ID_VALUE) || !same_busy || !released {
+        uart_puts(b"[DEV-LOCK] FAILED: per-device lock scope wrong\n");
+        return;
+    }
+    uart_puts(b"[DEV-LOCK] Test 1 PASSED\n\n");
+
+    // Test 2: pCPU 0 is mid-notify whenever pCPU 1 notifies the same queue;
+    // pCPU 1 finds the device busy, then retries — no notify is lost and
+    // the completion path raises the SPI once
+    uart_puts(b"[DEV-LOCK] Test 2: concurrent notifies, no lost updates...\n");
+    let mut busy = 0;
+    for _ in 0..NOTIFIES_PER_CPU {
+        let Some(mut cpu0) = DEVICES.lock_device(blk) else {
+            break;
+        };
+        if DEVICES.try_lock_device(blk).is_none() {
+            busy += 1;
+        }
+        cpu0.write(QUEUE_NOTIFY, 0, 4);
+        drop(cpu0);
+        DEVICES.handle_mmio(blk + QUEUE_NOTIFY, 0, 4, true);
+    }
+    let stats = DEVICES.lock_device(blk).and_then(|dev| dev.virtio_stats());
+    let Some(stats) = stats else {
+        uart_puts(b"[DEV-LOCK] FAILED: no virtio-blk stats\n");
+        return;
+    };
+    if busy != NOTIFIES_PER_CPU
+        || stats.notifications != 2 * NOTIFIES_PER_CPU
+        || stats.interrupts != 1
+        || stats.interrupts_coalesced != 2 * NOTIFIES_PER_CPU - 1
+    {
+        uart_puts(b"[DEV-LOCK] FAILED: notify counter lost updates\n");
+        return;
+    }
+    uart_puts(b"[DEV-LOCK] Test 2 PASSED\n\n");
+
+    // Test 3: a host-side helper holds only its own device: MMIO to other
+    // devices, table lookups and manager-wide state stay reachable from
+    // inside it, and what it writes is what MMIO reads afterwards
+    uart_puts(b"[DEV-LOCK] Test 3: with_device holds only its slot...\n");
+    let inside = DEVICES.with_device(Device::sensor_mut, |sensor| {
+        sensor.set_temp(TEMP_MC);
+        let other_mmio = DEVICES.handle_mmio(blk + MAGIC_VALUE, 0, 4, false) == Some(VIRTIO_MAGIC)
+            && DEVICES.try_lock_device(blk).is_some();
+        let table = DEVICES.is_mapped(SENSOR_BASE)
+            && DEVICES.overlaps(blk, 4)
+            && DEVICES.with_table(|dm| dm.owner_vm()) == Some(VM_ID);
+        let own_busy = DEVICES.try_lock_device(SENSOR_BASE).is_none();
+        other_mmio && table && own_busy
+    });
+    let temp = DEVICES.handle_mmio(SENSOR_BASE + SENSOR_TEMP, 0, 4, false);
+    if inside != Some(true) || temp != Some(TEMP_MC as u64) {
+        uart_puts(b"[DEV-LOCK] FAILED: with_device blocked other paths or lost state\n");
+        return;
+    }
+    uart_puts(b"[DEV-LOCK] Test 3 PASSED\n\n");
+
+    // Test 4: after a reset the old slot is gone for every lookup path
+    uart_puts(b"[DEV-LOCK] Test 4: reset drops devices...\n");
+    drop(cleanup);
+    let gone = DEVICES.lock_device(blk).is_none()
+        && DEVICES.try_lock_device(SENSOR_BASE).is_none()
+        && DEVICES.with_device(Device::sensor_mut, |_| ()).is_none()
+        && !DEVICES.is_mapped(blk);
+    let unmapped = DEVICES.handle_mmio(SENSOR_BASE + SENSOR_ID, 0, 4, false);
+    if !gone || unmapped != Some(0) {
+        uart_puts(b"[DEV-LOCK] FAILED: reset left a device reachable\n");
+        return;
+    }
+    uart_puts(b"[DEV-LOCK] Test 4 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Per-Device Locking Test PASSED (4 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,150 @@
+//! DMA address validation tests
+//!
+//! Checks that `DmaMapper` only hands out guest memory the owning VM can
+//! reach: the RAM window when no Stage-2 is bound, and Normal pages with the
+//! needed S2AP permission otherwise. A virtio descriptor pointing outside
+//! guest RAM must be rejected instead of being read.
+
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::mm::mmu::{DynamicIdentityMapper, MemoryAttribute};
+use hypervisor::devices::dma::DmaMapper;
+use hypervisor::devices::virtio::balloon::{
+    VirtioBalloon, STATSQ, VIRTIO_BALLOON_STAT_SIZE, VIRTIO_BALLOON_S_MEMFREE,
+};
+use hypervisor::devices::virtio::mmio::VirtioMmioTransport;
+use hypervisor::devices::MmioDevice;
+use hypervisor::global::current_vm_state;
+use hypervisor::platform::{GUEST_RAM_BASE, LINUX_MEM_SIZE};
+use hypervisor::uart_puts;
+
+const QUEUE_SIZE: usize = 8;
+/// Virtio-mmio slot 3 layout
+const BALLOON_BASE: u64 = 0x0a00_0600;
+const BALLOON_INTID: u32 = 51;
+/// Flash, below guest RAM (readable by the hypervisor, not by the guest)
+const OUTSIDE_RAM: u64 = 0x0000_1000;
+/// Test Stage-2 layout: RW RAM block, RO block, device block, hole
+const RW_IPA: u64 = 0x4800_0000;
+const RO_IPA: u64 = 0x4820_0000;
+const DEV_IPA: u64 = 0x0900_0000;
+const HOLE_IPA: u64 = 0x4840_0000;
+
+/// Descriptor table + avail ring + used ring for the balloon stats queue.
+#[repr(C, align(4096))]
+struct QueueMem {
+    desc: [[u8; 16]; QUEUE_SIZE],
+    avail: [u16; 2 + QUEUE_SIZE],
+    _pad: [u16; 2],
+    used: [u32; 1 + 2 * QUEUE_SIZE],
+}
+
+static mut QUEUE_MEM: QueueMem = QueueMem {
+    desc: [[0; 16]; QUEUE_SIZE],
+    avail: [0; 2 + QUEUE_SIZE],
+    _pad: [0; 2],
+    used: [0; 1 + 2 * QUEUE_SIZE],
+};
+
+pub fn run_dma_mapper_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  DMA Mapper Test\n");
+    uart_puts(b"========================================\n\n");
+
+    // Test 1: unbound mapper is limited to the guest RAM window
+    uart_puts(b"[DMA] Test 1: RAM window check...\n");
+    let dma = DmaMapper::new();
+    let ram_end = GUEST_RAM_BASE + LINUX_MEM_SIZE;
+    if dma.translate(GUEST_RAM_BASE, 16, false) != Some(GUEST_RAM_BASE)
+        || dma.translate(OUTSIDE_RAM, 16, false).is_some()
+        || dma.translate(ram_end - 8, 16, true).is_some()
+        || dma.translate(u64::MAX - 4, 16, false).is_some()
+    {
+        uart_puts(b"[DMA] FAILED: RAM window not enforced\n");
+        return;
+    }
+    uart_puts(b"[DMA] Test 1 PASSED\n\n");
+
+    // Test 2: descriptor outside guest RAM is not read
+    uart_puts(b"[DMA] Test 2: out-of-RAM descriptor rejected...\n");
+    let mut transport = VirtioMmioTransport::new(BALLOON_BASE, VirtioBalloon::new(), BALLOON_INTID);
+    let mem = &raw mut QUEUE_MEM;
+    let (desc_addr, avail_addr, used_addr) = unsafe {
+        (
+            (*mem).desc.as_ptr() as u64,
+            (*mem).avail.as_ptr() as u64,
+            (*mem).used.as_ptr() as u64,
+        )
+    };
+    unsafe {
+        // desc[0] = { addr: OUTSIDE_RAM, len: one stat entry }
+        let d = &mut (*mem).desc[0];
+        d[0..8].copy_from_slice(&OUTSIDE_RAM.to_le_bytes());
+        d[8..12].copy_from_slice(&(VIRTIO_BALLOON_STAT_SIZE as u32).to_le_bytes());
+        (*mem).avail[2] = 0;
+        (*mem).avail[1] = 1;
+    }
+    transport.write(0x030, STATSQ as u64, 4); // QueueSel = statsq
+    transport.write(0x038, QUEUE_SIZE as u64, 4);
+    transport.write(0x080, desc_addr & 0xFFFF_FFFF, 4);
+    transport.write(0x084, desc_addr >> 32, 4);
+    transport.write(0x090, avail_addr & 0xFFFF_FFFF, 4);
+    transport.write(0x094, avail_addr >> 32, 4);
+    transport.write(0x0A0, used_addr & 0xFFFF_FFFF, 4);
+    transport.write(0x0A4, used_addr >> 32, 4);
+    transport.write(0x044, 1, 4); // QueueReady
+    transport.write(0x050, STATSQ as u64, 4); // QueueNotify
+    let spi_bit = 1u32 << (BALLOON_INTID - 32);
+    for spis in current_vm_state().pending_spis.iter() {
+        spis.fetch_and(!spi_bit, Ordering::Relaxed);
+    }
+    let stats = transport.balloon().stats();
+    if stats.free_memory != 0 || stats.total_memory != 0 {
+        uart_puts(b"[DMA] FAILED: descriptor outside guest RAM was read\n");
+        return;
+    }
+    uart_puts(b"[DMA] Test 2 PASSED\n\n");
+
+    // Test 3: Stage-2 bound mapper checks mapping, memory type and S2AP
+    uart_puts(b"[DMA] Test 3: Stage-2 permissions...\n");
+    let mut mapper = DynamicIdentityMapper::new();
+    let mapped = mapper
+        .map_region(RW_IPA, 0x0020_0000, MemoryAttribute::Normal)
+        .and(mapper.map_region(RO_IPA, 0x0020_0000, MemoryAttribute::ReadOnly))
+        .and(mapper.map_region(DEV_IPA, 0x0020_0000, MemoryAttribute::Device));
+    let dma = DmaMapper::with_stage2(mapper.vttbr());
+    let ok = mapped.is_ok()
+        && dma.translate(RW_IPA + 0x100, 64, true) == Some(RW_IPA + 0x100)
+        && dma.translate(RO_IPA, 64, false) == Some(RO_IPA)
+        && dma.translate(RO_IPA, 64, true).is_none()
+        && dma.translate(DEV_IPA, 4, false).is_none()
+        && dma.translate(HOLE_IPA, 4, false).is_none()
+        && dma.translate(RO_IPA + 0x0020_0000 - 8, 16, false).is_none();
+    core::mem::forget(mapper);
+    if !ok {
+        uart_puts(b"[DMA] FAILED: Stage-2 permissions not enforced\n");
+        return;
+    }
+    uart_puts(b"[DMA] Test 3 PASSED\n\n");
+
+    // Test 4: accessors copy only when the range is accepted
+    uart_puts(b"[DMA] Test 4: read/write accessors...\n");
+    let dma = DmaMapper::new();
+    let src = [0x5Au8; VIRTIO_BALLOON_STAT_SIZE];
+    let mut dst = [0u8; VIRTIO_BALLOON_STAT_SIZE];
+    let good = dma.write(desc_addr, &src)
+        && dma.read(desc_addr, &mut dst)
+        && dma.read_val::<u16>(desc_addr) == Some(0x5A5A);
+    let mut rejected = [0xEEu8; 4];
+    let bad = dma.read(OUTSIDE_RAM, &mut rejected)
+        || dma.write_val(OUTSIDE_RAM, VIRTIO_BALLOON_S_MEMFREE)
+        || dma.read_val::<u32>(OUTSIDE_RAM).is_some();
+    if !good || dst != src || bad || rejected != [0xEE; 4] {
+        uart_puts(b"[DMA] FAILED: accessor did not honour validation\n");
+        return;
+    }
+    uart_puts(b"[DMA] Test 4 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  DMA Mapper Test PASSED (4 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
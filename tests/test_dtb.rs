@@ -1,9 +1,253 @@
 //! DTB parsing tests
 //!
 //! Verifies that the host DTB was successfully parsed and the discovered
-//! platform values match expected QEMU virt machine configuration.
+//! platform values match expected QEMU virt machine configuration,
+//! that GIC redistributor regions and stride are taken from a built DTB,
+//! that a GICv2 node selects the GICv2 driver, that `set_initrd`
+//! patches `/chosen` of a guest DTB, and that `set_framebuffer` adds a
+//! `simple-framebuffer` node (also at boot, via `attach_boot_framebuffer`).
 
+use hypervisor::arch::aarch64::peripherals::gic::select_driver;
+use hypervisor::devices::framebuffer::{PixelFormat, VirtualFramebuffer};
+use hypervisor::dtb::GicVersion;
+use hypervisor::guest_loader::{attach_boot_framebuffer, set_framebuffer, set_initrd};
+use hypervisor::platform;
 use hypervisor::uart_puts;
+use hypervisor::vm::Vm;
+
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_END: u32 = 9;
+
+/// Minimal flattened device tree writer (structure + strings blocks).
+struct FdtBuilder {
+    structs: [u8; 1024],
+    slen: usize,
+    strings: [u8; 256],
+    strlen: usize,
+    out: [u8; 2048],
+}
+
+impl FdtBuilder {
+    fn new() -> Self {
+        Self {
+            structs: [0; 1024],
+            slen: 0,
+            strings: [0; 256],
+            strlen: 0,
+            out: [0; 2048],
+        }
+    }
+
+    fn put(&mut self, bytes: &[u8]) {
+        self.structs[self.slen..self.slen + bytes.len()].copy_from_slice(bytes);
+        self.slen = (self.slen + bytes.len() + 3) & !3;
+    }
+
+    fn begin_node(&mut self, name: &str) {
+        self.put(&FDT_BEGIN_NODE.to_be_bytes());
+        self.structs[self.slen..self.slen + name.len()].copy_from_slice(name.as_bytes());
+        // NUL-terminated, padded to a word
+        self.slen = (self.slen + name.len() + 1 + 3) & !3;
+    }
+
+    fn end_node(&mut self) {
+        self.put(&FDT_END_NODE.to_be_bytes());
+    }
+
+    fn prop(&mut self, name: &str, value: &[u8]) {
+        let nameoff = self.strlen as u32;
+        self.strings[self.strlen..self.strlen + name.len()].copy_from_slice(name.as_bytes());
+        self.strlen += name.len() + 1;
+        self.put(&FDT_PROP.to_be_bytes());
+        self.put(&(value.len() as u32).to_be_bytes());
+        self.put(&nameoff.to_be_bytes());
+        if !value.is_empty() {
+            self.put(value);
+        }
+    }
+
+    fn prop_cells(&mut self, name: &str, cells: &[u32]) {
+        let mut value = [0u8; 64];
+        for (i, c) in cells.iter().enumerate() {
+            value[i * 4..i * 4 + 4].copy_from_slice(&c.to_be_bytes());
+        }
+        self.prop(name, &value[..cells.len() * 4]);
+    }
+
+    /// Header, empty memory reservation map, structure and strings blocks.
+    fn finish(&mut self) -> &[u8] {
+        self.put(&FDT_END.to_be_bytes());
+        let off_rsv = 40;
+        let off_struct = off_rsv + 16;
+        let off_strings = off_struct + self.slen;
+        let total = off_strings + self.strlen;
+        let header = [
+            0xD00D_FEED,
+            total as u32,
+            off_struct as u32,
+            off_strings as u32,
+            off_rsv as u32,
+            17, // version
+            16, // last_comp_version
+            0,  // boot_cpuid_phys
+            self.strlen as u32,
+            self.slen as u32,
+        ];
+        self.out = [0; 2048];
+        for (i, w) in header.iter().enumerate() {
+            self.out[i * 4..i * 4 + 4].copy_from_slice(&w.to_be_bytes());
+        }
+        self.out[off_struct..off_strings].copy_from_slice(&self.structs[..self.slen]);
+        self.out[off_strings..total].copy_from_slice(&self.strings[..self.strlen]);
+        &self.out[..total]
+    }
+}
+
+/// Writable guest DTB with room to grow
+#[repr(C, align(8))]
+struct DtbBuf([u8; 2048]);
+
+static mut GUEST_DTB: DtbBuf = DtbBuf([0; 2048]);
+
+/// Guest-like DTB: memory node, plus `/chosen` with `bootargs` and the
+/// given initrd properties if `chosen` is set.
+fn guest_dtb(b: &mut FdtBuilder, chosen: Option<(&[u8], &[u8])>) {
+    b.begin_node("");
+    b.prop_cells("#address-cells", &[2]);
+    b.prop_cells("#size-cells", &[2]);
+    if let Some((start, end)) = chosen {
+        b.begin_node("chosen");
+        b.prop("bootargs", b"console=ttyAMA0\0");
+        b.prop("linux,initrd-start", start);
+        b.prop("linux,initrd-end", end);
+        b.end_node();
+    }
+    b.begin_node("memory@48000000");
+    b.prop("device_type", b"memory\0");
+    b.prop_cells("reg", &[0, 0x4800_0000, 0, 0x1000_0000]);
+    b.end_node();
+    b.end_node();
+}
+
+/// Copy `dtb` into `GUEST_DTB`, apply `set_initrd`, and return the
+/// patched (initrd-start, initrd-end, bootargs intact, memory reg intact).
+fn patch_initrd(dtb: &[u8], ipa: u64, size: u64) -> Option<(usize, usize, bool, bool)> {
+    let buf = &raw mut GUEST_DTB;
+    unsafe {
+        (*buf).0 = [0; 2048];
+        (&mut (*buf).0)[..dtb.len()].copy_from_slice(dtb);
+    }
+    set_initrd(buf as u64, ipa, size).ok()?;
+    let fdt = unsafe { fdt::Fdt::new(&(*buf).0).ok()? };
+    let chosen = fdt.find_node("/chosen")?;
+    let prop = |name| chosen.property(name).and_then(|p| p.as_usize());
+    let bootargs = chosen.property("bootargs").and_then(|p| p.as_str());
+    let memory = fdt.memory().regions().next()?;
+    Some((
+        prop("linux,initrd-start")?,
+        prop("linux,initrd-end")?,
+        bootargs.is_none_or(|a| a == "console=ttyAMA0"),
+        memory.starting_address as usize == 0x4800_0000 && memory.size == Some(0x1000_0000),
+    ))
+}
+
+/// Copy `dtb` into `GUEST_DTB`, describe `fb` in it, and return whether
+/// the framebuffer node, its memory reservation, and the memory node and
+/// `/chosen` bootargs all parse back as expected.
+fn patch_framebuffer(dtb: &[u8], fb: &VirtualFramebuffer) -> Option<bool> {
+    let buf = &raw mut GUEST_DTB;
+    unsafe {
+        (*buf).0 = [0; 2048];
+        (&mut (*buf).0)[..dtb.len()].copy_from_slice(dtb);
+    }
+    set_framebuffer(buf as u64, fb).ok()?;
+    let fdt = unsafe { fdt::Fdt::new(&(*buf).0).ok()? };
+    let node = fdt.find_compatible(&["simple-framebuffer"])?;
+    let cell = |name| node.property(name).and_then(|p| p.as_usize());
+    let reg = node.reg()?.next()?;
+    let reserved = fdt.memory_reservations().next()?;
+    let memory = fdt.memory().regions().next()?;
+    let bootargs = fdt
+        .find_node("/chosen")
+        .and_then(|c| c.property("bootargs"))
+        .and_then(|p| p.as_str());
+    Some(
+        node.name == "framebuffer@48100000"
+            && reg.starting_address as u64 == fb.base()
+            && reg.size == Some(fb.size() as usize)
+            && cell("width") == Some(fb.width() as usize)
+            && cell("height") == Some(fb.height() as usize)
+            && cell("stride") == Some(fb.stride() as usize)
+            && node.property("format").and_then(|p| p.as_str()) == Some("r5g6b5")
+            && reserved.address() as u64 == fb.base()
+            && reserved.size() as u64 == fb.size()
+            && memory.starting_address as usize == 0x4800_0000
+            && bootargs == Some("console=ttyAMA0"),
+    )
+}
+
+/// Host-like DTB with a GICv3 whose redistributors are described by
+/// `gicr` (base, size) regions and an optional `redistributor-stride`.
+fn gic_dtb(b: &mut FdtBuilder, gicr: &[(u32, u32)], stride: Option<u32>) {
+    b.begin_node("");
+    b.prop_cells("#address-cells", &[2]);
+    b.prop_cells("#size-cells", &[2]);
+    b.begin_node("cpus");
+    b.prop_cells("#address-cells", &[1]);
+    b.prop_cells("#size-cells", &[0]);
+    for (name, id) in [("cpu@0", 0), ("cpu@1", 1)] {
+        b.begin_node(name);
+        b.prop("device_type", b"cpu\0");
+        b.prop_cells("reg", &[id]);
+        b.end_node();
+    }
+    b.end_node();
+    b.begin_node("memory@40000000");
+    b.prop("device_type", b"memory\0");
+    b.prop_cells("reg", &[0, 0x4000_0000, 0, 0x1000_0000]);
+    b.end_node();
+    b.begin_node("intc@8000000");
+    b.prop("compatible", b"arm,gic-v3\0");
+    b.prop_cells("#redistributor-regions", &[gicr.len() as u32]);
+    if let Some(stride) = stride {
+        b.prop_cells("redistributor-stride", &[0, stride]);
+    }
+    let mut reg = [0u32; 16];
+    reg[..4].copy_from_slice(&[0, 0x0800_0000, 0, 0x1_0000]);
+    for (i, &(base, size)) in gicr.iter().enumerate() {
+        reg[4 + i * 4..8 + i * 4].copy_from_slice(&[0, base, 0, size]);
+    }
+    b.prop_cells("reg", &reg[..4 + gicr.len() * 4]);
+    b.end_node();
+    b.end_node();
+}
+
+/// Host-like DTB with a GICv2 (`arm,cortex-a15-gic`): GICD, GICC, GICH
+/// and GICV regions as on QEMU virt with `gic-version=2`.
+fn gicv2_dtb(b: &mut FdtBuilder) {
+    b.begin_node("");
+    b.prop_cells("#address-cells", &[2]);
+    b.prop_cells("#size-cells", &[2]);
+    b.begin_node("memory@40000000");
+    b.prop("device_type", b"memory\0");
+    b.prop_cells("reg", &[0, 0x4000_0000, 0, 0x1000_0000]);
+    b.end_node();
+    b.begin_node("intc@8000000");
+    b.prop("compatible", b"arm,cortex-a15-gic\0");
+    // GICD, GICC, GICH, GICV
+    let mut reg = [0u32; 16];
+    for (i, base) in [0x0800_0000, 0x0801_0000, 0x0803_0000, 0x0804_0000]
+        .into_iter()
+        .enumerate()
+    {
+        reg[i * 4..i * 4 + 4].copy_from_slice(&[0, base, 0, 0x1_0000]);
+    }
+    b.prop_cells("reg", &reg);
+    b.end_node();
+    b.end_node();
+}
 
 pub fn run_dtb_test() {
     uart_puts(b"\n========================================\n");
@@ -89,5 +333,155 @@ pub fn run_dtb_test() {
     }
     uart_puts(b"[DTB] Test 8 PASSED\n\n");
 
-    uart_puts(b"=== DTB Parsing: All 8 tests PASSED ===\n");
+    // Test 9: redistributor-stride of 256KB (GICv4 frames)
+    uart_puts(b"[DTB] Test 9: 256KB redistributor stride...\n");
+    let mut b = FdtBuilder::new();
+    gic_dtb(&mut b, &[(0x080A_0000, 0x40_0000)], Some(0x40000));
+    let Some(info) = hypervisor::dtb::parse_dtb_blob(b.finish()) else {
+        uart_puts(b"[DTB] FAILED: built DTB did not parse\n");
+        return;
+    };
+    if info.gicr_stride != 0x40000
+        || info.gicr_rd_base(0) != 0x080A_0000
+        || info.gicr_rd_base(1) != 0x080A_0000 + 0x40000
+        || info.num_cpus != 2
+    {
+        uart_puts(b"[DTB] FAILED: gicr_rd_base(1) != base + 0x40000\n");
+        return;
+    }
+    uart_puts(b"[DTB] Test 9 PASSED\n\n");
+
+    // Test 10: two redistributor regions, default stride; frames past the
+    // first region continue in the second
+    uart_puts(b"[DTB] Test 10: multiple redistributor regions...\n");
+    let mut b = FdtBuilder::new();
+    gic_dtb(
+        &mut b,
+        &[(0x080A_0000, 0x4_0000), (0x0900_0000, 0x4_0000)],
+        None,
+    );
+    let Some(info) = hypervisor::dtb::parse_dtb_blob(b.finish()) else {
+        uart_puts(b"[DTB] FAILED: built DTB did not parse\n");
+        return;
+    };
+    if info.gicr_region_count != 2
+        || info.gicr_stride != hypervisor::dtb::GICR_DEFAULT_STRIDE
+        || info.gicr_rd_base(1) != 0x080A_0000 + 0x20000
+        || info.gicr_rd_base(2) != 0x0900_0000
+        || info.gicr_rd_base(3) != 0x0900_0000 + 0x20000
+    {
+        uart_puts(b"[DTB] FAILED: frames not laid out across regions\n");
+        return;
+    }
+    uart_puts(b"[DTB] Test 10 PASSED\n\n");
+
+    // Test 11: set_initrd creates /chosen and both initrd properties in a
+    // DTB without them; the rest of the tree still parses
+    uart_puts(b"[DTB] Test 11: set_initrd creates /chosen...\n");
+    let mut b = FdtBuilder::new();
+    guest_dtb(&mut b, None);
+    if patch_initrd(b.finish(), 0x5400_0000, 0x20_0000)
+        != Some((0x5400_0000, 0x5420_0000, true, true))
+    {
+        uart_puts(b"[DTB] FAILED: initrd properties not created\n");
+        return;
+    }
+    uart_puts(b"[DTB] Test 11 PASSED\n\n");
+
+    // Test 12: existing properties are replaced, whether the old value is
+    // 64-bit (in place) or 32-bit (re-added as 64-bit)
+    uart_puts(b"[DTB] Test 12: set_initrd updates existing properties...\n");
+    let mut b = FdtBuilder::new();
+    let old_start = 0x4400_0000u32.to_be_bytes();
+    let old_end = 0x4410_0000u64.to_be_bytes();
+    guest_dtb(&mut b, Some((&old_start, &old_end)));
+    if patch_initrd(b.finish(), 0x5600_0000, 0x12_3000)
+        != Some((0x5600_0000, 0x5612_3000, true, true))
+    {
+        uart_puts(b"[DTB] FAILED: initrd properties not updated\n");
+        return;
+    }
+    uart_puts(b"[DTB] Test 12 PASSED\n\n");
+
+    // Test 13: a GICv2 compatible selects the GICv2 driver with its GICC
+    // and GICH bases; a GICv3 node selects GICv3 unless ICC_* is absent
+    uart_puts(b"[DTB] Test 13: GIC driver detection...\n");
+    let mut b = FdtBuilder::new();
+    gicv2_dtb(&mut b);
+    let Some(v2) = hypervisor::dtb::parse_dtb_blob(b.finish()) else {
+        uart_puts(b"[DTB] FAILED: built GICv2 DTB did not parse\n");
+        return;
+    };
+    if v2.gic_version != GicVersion::V2
+        || v2.gicd_base != 0x0800_0000
+        || v2.gicc_base != 0x0801_0000
+        || v2.gich_base != 0x0803_0000
+        || select_driver(&v2, true) != GicVersion::V2
+    {
+        uart_puts(b"[DTB] FAILED: GICv2 node not detected\n");
+        return;
+    }
+    let mut b = FdtBuilder::new();
+    gic_dtb(&mut b, &[(0x080A_0000, 0x4_0000)], None);
+    let Some(v3) = hypervisor::dtb::parse_dtb_blob(b.finish()) else {
+        uart_puts(b"[DTB] FAILED: built DTB did not parse\n");
+        return;
+    };
+    if v3.gic_version != GicVersion::V3
+        || select_driver(&v3, true) != GicVersion::V3
+        || select_driver(&v3, false) != GicVersion::V2
+    {
+        uart_puts(b"[DTB] FAILED: GICv3 node selected the wrong driver\n");
+        return;
+    }
+    uart_puts(b"[DTB] Test 13 PASSED\n\n");
+
+    // Test 14: set_framebuffer adds a simple-framebuffer root node with
+    // reg/width/height/stride/format and reserves its memory
+    uart_puts(b"[DTB] Test 14: set_framebuffer adds simplefb node...\n");
+    let mut b = FdtBuilder::new();
+    guest_dtb(&mut b, Some((&[0u8; 8][..], &[0u8; 8][..])));
+    let described = VirtualFramebuffer::new(0x4810_0000, 640, 480, PixelFormat::R5G6B5)
+        .ok()
+        .and_then(|fb| patch_framebuffer(b.finish(), &fb));
+    if described != Some(true) {
+        uart_puts(b"[DTB] FAILED: framebuffer node not described\n");
+        return;
+    }
+    uart_puts(b"[DTB] Test 14 PASSED\n\n");
+
+    // Test 15: the boot path attaches the platform framebuffer to the VM
+    // and describes the same one in its DTB
+    uart_puts(b"[DTB] Test 15: boot framebuffer attached and described...\n");
+    let mut b = FdtBuilder::new();
+    guest_dtb(&mut b, None);
+    let dtb = b.finish();
+    let buf = &raw mut GUEST_DTB;
+    unsafe {
+        (*buf).0 = [0; 2048];
+        (&mut (*buf).0)[..dtb.len()].copy_from_slice(dtb);
+    }
+    let mut vm = Vm::new(1);
+    let attached = attach_boot_framebuffer(&mut vm, buf as u64, platform::FRAMEBUFFER_ADDR).is_ok();
+    let fb = vm.framebuffer().copied();
+    let node = unsafe { fdt::Fdt::new(&(*buf).0).ok() }.and_then(|fdt| {
+        let node = fdt.find_compatible(&["simple-framebuffer"])?;
+        let reg = node.reg()?.next()?;
+        let format = node.property("format").and_then(|p| p.as_str());
+        Some((reg.starting_address as u64, format == Some("x8r8g8b8")))
+    });
+    let _fresh = Vm::new(1);
+    let described = fb.is_some_and(|fb| {
+        fb.base() == platform::FRAMEBUFFER_ADDR
+            && fb.width() == platform::FRAMEBUFFER_WIDTH
+            && fb.height() == platform::FRAMEBUFFER_HEIGHT
+            && node == Some((fb.base(), true))
+    });
+    if !attached || !described {
+        uart_puts(b"[DTB] FAILED: boot framebuffer not attached or described\n");
+        return;
+    }
+    uart_puts(b"[DTB] Test 15 PASSED\n\n");
+
+    uart_puts(b"=== DTB Parsing: All 15 tests PASSED ===\n");
 }
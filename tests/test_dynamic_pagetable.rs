@@ -1,6 +1,8 @@
 //! Dynamic page table allocation tests
 
+use hypervisor::arch::aarch64::defs::{PTE_ADDR_MASK, PTE_AF, PTE_TABLE, PTE_VALID};
 use hypervisor::arch::aarch64::mm::mmu::{DynamicIdentityMapper, MemoryAttribute};
+use hypervisor::ffa::stage2_walker::Stage2Walker;
 use hypervisor::uart_puts;
 
 pub fn run_dynamic_pt_test() {
@@ -73,6 +75,114 @@ pub fn run_dynamic_pt_test() {
     }
     uart_puts(b"[DYN PT] Test 6 PASSED\n\n");
 
+    // Test 7: Map 2MB-block ranges in six more 1GB L1 slots (seven L2
+    // tables in all) and check both ends of every range translate
+    uart_puts(b"[DYN PT] Test 7: Map ranges across six more L2 tables...\n");
+    const GB: u64 = 0x4000_0000;
+    const MB2: u64 = 0x20_0000;
+    let base = 4 * GB;
+    for gb in 0..6 {
+        let start = base + gb * GB + MB2;
+        if mapper
+            .map_region(start, GB - MB2, MemoryAttribute::Normal)
+            .is_err()
+        {
+            uart_puts(b"[DYN PT] ERROR: Failed to map range in a new L1 slot\n");
+            return;
+        }
+    }
+    let walker = Stage2Walker::new(mapper.l0_addr());
+    for gb in 0..6 {
+        let start = base + gb * GB;
+        let first = start + MB2;
+        let last = start + GB - 0x1000;
+        if walker.translate(first) != Some(first)
+            || walker.translate(last) != Some(last)
+            || walker.translate(start).is_some()
+        {
+            uart_puts(b"[DYN PT] ERROR: 1GB slot does not resolve\n");
+            return;
+        }
+    }
+    uart_puts(b"[DYN PT] Test 7 PASSED\n\n");
+
+    // Test 8: a 1GB-aligned 4GB Normal mapping is four L1 block
+    // descriptors (AF set, output = IPA); mid-block addresses translate
+    uart_puts(b"[DYN PT] Test 8: 4GB mapping uses 1GB L1 blocks...\n");
+    let big = 16 * GB;
+    if mapper
+        .map_region(big, 4 * GB, MemoryAttribute::Normal)
+        .is_err()
+    {
+        uart_puts(b"[DYN PT] ERROR: Failed to map 4GB region\n");
+        return;
+    }
+    let l1 = unsafe { *(mapper.l0_addr() as *const u64) & PTE_ADDR_MASK } as *const u64;
+    // L1 index = IPA bits [38:30], i.e. the GB number
+    let l1_entry = |gb: u64| unsafe { *l1.add(gb as usize) };
+    for gb in 16..20 {
+        let e = l1_entry(gb);
+        let mid = gb * GB + 0x1234_5678;
+        if e & (PTE_VALID | PTE_TABLE) != PTE_VALID
+            || e & PTE_AF == 0
+            || e & PTE_ADDR_MASK != gb * GB
+            || walker.translate(mid) != Some(mid)
+        {
+            uart_puts(b"[DYN PT] ERROR: 1GB L1 block missing or wrong\n");
+            return;
+        }
+    }
+    uart_puts(b"[DYN PT] Test 8 PASSED\n\n");
+
+    // Test 9: page-level changes split a 1GB block on demand, through the
+    // mapper and through the Stage-2 walker, leaving the rest mapped
+    uart_puts(b"[DYN PT] Test 9: 1GB blocks split on demand...\n");
+    if mapper.unmap_4kb_page(big + 0x1000).is_err()
+        || walker.set_s2ap(big + GB + 0x5000, 0b01).is_err()
+    {
+        uart_puts(b"[DYN PT] ERROR: page change inside a 1GB block failed\n");
+        return;
+    }
+    let split = |gb: u64| l1_entry(gb) & (PTE_VALID | PTE_TABLE) == PTE_VALID | PTE_TABLE;
+    if !split(16)
+        || !split(17)
+        || walker.translate(big + 0x1000).is_some()
+        || walker.translate(big + 0x2000) != Some(big + 0x2000)
+        || walker.translate(big + 0x3000_0000) != Some(big + 0x3000_0000)
+        || walker.read_s2ap(big + GB + 0x5000) != Some(0b01)
+        || walker.read_s2ap(big + GB + 0x6000) != Some(0b11)
+        || walker.translate(big + GB + 0x2000_0000) != Some(big + GB + 0x2000_0000)
+    {
+        uart_puts(b"[DYN PT] ERROR: split 1GB block lost its mapping\n");
+        return;
+    }
+    uart_puts(b"[DYN PT] Test 9 PASSED\n\n");
+
+    // Test 10: a 2GB region plus a 2MB tail is two L1 blocks and one L2
+    // table holding the tail as a 2MB block
+    uart_puts(b"[DYN PT] Test 10: 2GB region with a 2MB tail...\n");
+    let two = 24 * GB;
+    if mapper
+        .map_region(two, 2 * GB + MB2, MemoryAttribute::Normal)
+        .is_err()
+    {
+        uart_puts(b"[DYN PT] ERROR: Failed to map 2GB region\n");
+        return;
+    }
+    let is_block = |gb: u64| l1_entry(gb) & (PTE_VALID | PTE_TABLE) == PTE_VALID;
+    let tail = two + 2 * GB;
+    if !is_block(24)
+        || !is_block(25)
+        || !split(26)
+        || walker.translate(two + GB + 0x7654_3000) != Some(two + GB + 0x7654_3000)
+        || walker.translate(tail + MB2 - 0x1000) != Some(tail + MB2 - 0x1000)
+        || walker.translate(tail + MB2).is_some()
+    {
+        uart_puts(b"[DYN PT] ERROR: 2GB region not mapped with L1 blocks\n");
+        return;
+    }
+    uart_puts(b"[DYN PT] Test 10 PASSED\n\n");
+
     // Clear VTTBR_EL2 so subsequent tests (e.g. FF-A MEM_SHARE) don't see stale
     // page tables and attempt Stage-2 walks on pages that were never mapped.
     unsafe {
@@ -80,6 +190,6 @@ pub fn run_dynamic_pt_test() {
     }
 
     uart_puts(b"========================================\n");
-    uart_puts(b"  Dynamic Page Table Test PASSED (6 assertions)\n");
+    uart_puts(b"  Dynamic Page Table Test PASSED (10 assertions)\n");
     uart_puts(b"========================================\n\n");
 }
@@ -0,0 +1,118 @@
+//! Multi-VM epoch scheduler tests
+//!
+//! Drives `EpochScheduler` with fake VMs whose slices advance a fake clock:
+//! the first VM of each epoch rotates, two never-finishing VMs with slices
+//! of 3:1 length still get balanced runtime, finished VMs drop out, and a
+//! suspended VM neither holds back a busy one nor lags behind it on wakeup.
+
+use hypervisor::scheduler::{EpochScheduler, MAX_RUNTIME_LEAD_NS};
+use hypervisor::time::{advance_fake_clock, install_fake_clock, ns_to_ticks, remove_fake_clock};
+use hypervisor::uart_puts;
+
+/// 1 tick = 1 ns
+const FREQ: u64 = 1_000_000_000;
+const SLICE_NS: u64 = 100_000;
+const EPOCHS: usize = 1000;
+/// Every VM runnable
+const ALL: fn(usize) -> bool = |_| true;
+/// VM 1 suspended
+const ONLY_VM0: fn(usize) -> bool = |id| id == 0;
+
+pub fn run_epoch_scheduler_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Multi-VM Epoch Scheduler Test\n");
+    uart_puts(b"========================================\n\n");
+
+    install_fake_clock(FREQ, 1000);
+    let slice = ns_to_ticks(SLICE_NS);
+
+    // Test 1: the VM that runs first rotates every epoch
+    uart_puts(b"[EPOCH] Test 1: starting VM rotates...\n");
+    let mut sched = EpochScheduler::new();
+    let mut firsts = [usize::MAX; 4];
+    for first in firsts.iter_mut() {
+        sched.run_epoch(2, ALL, |id| {
+            if *first == usize::MAX {
+                *first = id;
+            }
+            advance_fake_clock(slice);
+            false
+        });
+    }
+    if firsts != [0, 1, 0, 1] || sched.epoch() != 4 {
+        remove_fake_clock();
+        uart_puts(b"[EPOCH] FAILED: starting VM does not rotate\n");
+        return;
+    }
+    uart_puts(b"[EPOCH] Test 1 PASSED\n\n");
+
+    // Test 2: VM 0's slices are 3x VM 1's, yet runtimes stay balanced
+    uart_puts(b"[EPOCH] Test 2: runtime balanced over many epochs...\n");
+    let mut sched = EpochScheduler::new();
+    for _ in 0..EPOCHS {
+        sched.run_epoch(2, ALL, |id| {
+            advance_fake_clock(if id == 0 { 3 * slice } else { slice });
+            false
+        });
+    }
+    let (rt0, rt1) = (sched.runtime(0), sched.runtime(1));
+    let (hi, lo) = (rt0.max(rt1), rt0.min(rt1));
+    let lead = ns_to_ticks(MAX_RUNTIME_LEAD_NS) + 3 * slice;
+    // Within 10% of each other, and never further apart than one lead
+    if lo == 0 || hi * 10 > lo * 11 || hi - lo > lead {
+        remove_fake_clock();
+        uart_puts(b"[EPOCH] FAILED: runtimes unbalanced\n");
+        return;
+    }
+    uart_puts(b"[EPOCH] Test 2 PASSED\n\n");
+
+    // Test 3: a finished VM is not run again; the epoch after the last one
+    // finishes reports completion
+    uart_puts(b"[EPOCH] Test 3: finished VMs drop out...\n");
+    let mut sched = EpochScheduler::new();
+    let mut runs = [0u32; 2];
+    let mut all_done = false;
+    for _ in 0..5 {
+        all_done = sched.run_epoch(2, ALL, |id| {
+            runs[id] += 1;
+            advance_fake_clock(slice);
+            id == 0 || runs[1] == 3
+        });
+        if all_done {
+            break;
+        }
+    }
+    remove_fake_clock();
+    if !all_done || runs != [1, 3] || !sched.is_finished(0) || !sched.is_finished(1) {
+        uart_puts(b"[EPOCH] FAILED: finished VM scheduled again\n");
+        return;
+    }
+    uart_puts(b"[EPOCH] Test 3 PASSED\n\n");
+
+    // Test 4: a suspended VM whose slices take no time does not hold back
+    // a busy one, and resumes level with it instead of far behind
+    uart_puts(b"[EPOCH] Test 4: non-runnable VM excluded from lead check...\n");
+    install_fake_clock(FREQ, 1000);
+    let mut sched = EpochScheduler::new();
+    let mut busy_runs = 0u32;
+    for _ in 0..EPOCHS {
+        sched.run_epoch(2, ONLY_VM0, |id| {
+            if id == 0 {
+                busy_runs += 1;
+                advance_fake_clock(slice);
+            }
+            false
+        });
+    }
+    let level = sched.runtime(1) >= sched.runtime(0) - slice;
+    remove_fake_clock();
+    if busy_runs != EPOCHS as u32 || !level {
+        uart_puts(b"[EPOCH] FAILED: suspended VM held back the busy one\n");
+        return;
+    }
+    uart_puts(b"[EPOCH] Test 4 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Multi-VM Epoch Scheduler Test PASSED (4 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,125 @@
+//! EL2 exception handler infrastructure tests
+//!
+//! Tests the handle_exception() re-entrancy guard without taking a real
+//! nested fault (which would halt the system), the early-crash
+//! diagnostic for guests that fault before installing VBAR_EL1, and
+//! per-VM termination after an exception storm.
+
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::hypervisor::exception;
+use hypervisor::global::{vm_state, CURRENT_VM_ID};
+use hypervisor::uart_puts;
+use hypervisor::vm::Vm;
+
+pub fn run_exception_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  EL2 Exception Handler Test\n");
+    uart_puts(b"========================================\n\n");
+
+    // Test 1: First entry succeeds
+    uart_puts(b"[EXC] Test 1: Outer entry accepted...\n");
+    if exception::enter_exception_guard(0x9600_0045, 0x0900_0000).is_err() {
+        uart_puts(b"[EXC] FAILED: outer entry rejected\n");
+        return;
+    }
+    uart_puts(b"[EXC] Test 1 PASSED\n\n");
+
+    // Test 2: Nested entry is detected and reports the outer ESR/FAR
+    uart_puts(b"[EXC] Test 2: Nested entry detected...\n");
+    match exception::enter_exception_guard(0x9600_0010, 0xDEAD_0000) {
+        Err((outer_esr, outer_far)) => {
+            if outer_esr != 0x9600_0045 || outer_far != 0x0900_0000 {
+                exception::exit_exception_guard();
+                uart_puts(b"[EXC] FAILED: wrong outer ESR/FAR\n");
+                return;
+            }
+            exception::report_nested_fault((outer_esr, outer_far), 0x9600_0010, 0xDEAD_0000);
+        }
+        Ok(()) => {
+            exception::exit_exception_guard();
+            uart_puts(b"[EXC] FAILED: nested entry not detected\n");
+            return;
+        }
+    }
+    uart_puts(b"[EXC] Test 2 PASSED\n\n");
+
+    // Test 3: After exit, a fresh entry is accepted again
+    uart_puts(b"[EXC] Test 3: Guard cleared on exit...\n");
+    exception::exit_exception_guard();
+    if exception::enter_exception_guard(0, 0).is_err() {
+        uart_puts(b"[EXC] FAILED: guard still set after exit\n");
+        return;
+    }
+    exception::exit_exception_guard();
+    uart_puts(b"[EXC] Test 3 PASSED\n\n");
+
+    // Test 4: Abort on the first exit with VBAR_EL1==0 emits the diagnostic
+    uart_puts(b"[EXC] Test 4: Early crash diagnostic...\n");
+    if !exception::check_early_crash(0, 1) {
+        uart_puts(b"[EXC] FAILED: early crash not reported\n");
+        return;
+    }
+    uart_puts(b"[EXC] Test 4 PASSED\n\n");
+
+    // Test 5: Installed vectors or a long-running vCPU are not "early"
+    uart_puts(b"[EXC] Test 5: Later crash not flagged...\n");
+    if exception::check_early_crash(0x4008_0800, 1)
+        || exception::check_early_crash(0, exception::EARLY_CRASH_MAX_EXITS + 1)
+    {
+        uart_puts(b"[EXC] FAILED: later crash flagged as early\n");
+        return;
+    }
+    uart_puts(b"[EXC] Test 5 PASSED\n\n");
+
+    // Test 6: Diagnostic can be disabled
+    uart_puts(b"[EXC] Test 6: Early crash check disabled...\n");
+    exception::set_early_crash_check(false);
+    let emitted = exception::check_early_crash(0, 1);
+    exception::set_early_crash_check(true);
+    if emitted {
+        uart_puts(b"[EXC] FAILED: diagnostic emitted while disabled\n");
+        return;
+    }
+    uart_puts(b"[EXC] Test 6 PASSED\n\n");
+
+    // Test 7: exception storm termination hits only the current VM
+    uart_puts(b"[EXC] Test 7: Storm terminates current VM only...\n");
+    let mut vm1 = Vm::new(1);
+    if vm1.create_vcpu(0).is_err() {
+        uart_puts(b"[EXC] FAILED: create_vcpu\n");
+        return;
+    }
+    vm_state(1).vcpu_online_mask.store(1, Ordering::Release);
+    let prev_vm = CURRENT_VM_ID.swap(1, Ordering::Relaxed);
+    let vcpu_id = hypervisor::global::current_vcpu_id();
+    exception::terminate_current_vm();
+    CURRENT_VM_ID.store(prev_vm, Ordering::Relaxed);
+    let vm1_marked = vm_state(1).vm_terminated.load(Ordering::Acquire)
+        && vm_state(1).terminal_exit[vcpu_id].swap(false, Ordering::AcqRel);
+    let vm0_clean = !vm_state(0).vm_terminated.load(Ordering::Acquire)
+        && !vm_state(0)
+            .terminal_exit
+            .iter()
+            .any(|t| t.load(Ordering::Acquire));
+    if !vm1_marked || !vm0_clean || exception::consecutive_exception_count() != 0 {
+        uart_puts(b"[EXC] FAILED: termination not scoped to current VM\n");
+        return;
+    }
+    uart_puts(b"[EXC] Test 7 PASSED\n\n");
+
+    // Test 8: the terminated VM retires its vCPUs; a fresh VM starts clean
+    uart_puts(b"[EXC] Test 8: Terminated VM retired...\n");
+    let retired = vm1.retire_if_terminated();
+    let no_vcpus = vm1.schedule().is_none();
+    let offline = vm_state(1).vcpu_online_mask.load(Ordering::Acquire) == 0;
+    let _fresh = Vm::new(1);
+    if !retired || !no_vcpus || !offline || vm_state(1).vm_terminated.load(Ordering::Acquire) {
+        uart_puts(b"[EXC] FAILED: terminated VM not retired / reset\n");
+        return;
+    }
+    uart_puts(b"[EXC] Test 8 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  EL2 Exception Handler Test PASSED (8 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,159 @@
+//! Exclusive pCPU tests
+//!
+//! Issues hypercall 15 as VM 1 and checks that the claimed pCPU has its
+//! CNTHP preemption watchdog disarmed and drops out of the PSCI CPU_ON
+//! candidate set until it is released. Claims are scoped to VM 1 and are
+//! dropped when VM 1 is terminated or recreated.
+
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::hypervisor::exception::{
+    handle_hypercall_with_imm, handle_psci, terminate_current_vm,
+};
+use hypervisor::arch::aarch64::peripherals::timer;
+use hypervisor::arch::aarch64::regs::VcpuContext;
+use hypervisor::global::{vm_state, CURRENT_VM_ID};
+use hypervisor::pcpu_pin::{
+    cpu_on_candidate, is_exclusive, pcpu_of_vcpu, EXCLUSIVE_CLAIM, EXCLUSIVE_RELEASE, HC_DENIED,
+    HC_EXCLUSIVE_PCPU,
+};
+use hypervisor::uart_puts;
+use hypervisor::vm::Vm;
+
+const PSCI_CPU_ON_64: u64 = 0xC400_0003;
+const PSCI_INVALID_PARAMETERS: u64 = 0xFFFF_FFFE;
+const VM_ID: usize = 1;
+
+/// Issue hypercall 15 with `op` in x1 as VM_ID; returns (continue, x0).
+fn exclusive_as_vm(op: u64) -> (bool, u64) {
+    let prev_vm = CURRENT_VM_ID.swap(VM_ID, Ordering::Relaxed);
+    let mut ctx = VcpuContext::default();
+    ctx.gp_regs.x0 = HC_EXCLUSIVE_PCPU;
+    ctx.gp_regs.x1 = op;
+    let cont = handle_hypercall_with_imm(&mut ctx, 0);
+    CURRENT_VM_ID.store(prev_vm, Ordering::Relaxed);
+    (cont, ctx.gp_regs.x0)
+}
+
+/// PSCI CPU_ON for `target` as VM_ID; returns (continue, x0).
+fn cpu_on_as_vm(target: u64) -> (bool, u64) {
+    let prev_vm = CURRENT_VM_ID.swap(VM_ID, Ordering::Relaxed);
+    let mut ctx = VcpuContext::default();
+    ctx.gp_regs.x0 = PSCI_CPU_ON_64;
+    ctx.gp_regs.x1 = target;
+    ctx.gp_regs.x2 = 0x4800_0000;
+    let cont = handle_psci(&mut ctx, PSCI_CPU_ON_64);
+    CURRENT_VM_ID.store(prev_vm, Ordering::Relaxed);
+    (cont, ctx.gp_regs.x0)
+}
+
+fn cnthp_ctl() -> u64 {
+    let ctl: u64;
+    unsafe { core::arch::asm!("mrs {}, cnthp_ctl_el2", out(reg) ctl) };
+    ctl
+}
+
+pub fn run_exclusive_pcpu_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Exclusive pCPU Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let vs = vm_state(VM_ID);
+    let prev_online = vs.vcpu_online_mask.load(Ordering::Relaxed);
+    let prev_vcpu = vs.current_vcpu_id.load(Ordering::Relaxed);
+    vs.current_vcpu_id.store(0, Ordering::Relaxed);
+    let pcpu = pcpu_of_vcpu(hypervisor::global::current_vcpu_id());
+    // A CPU_ON target that would be placed on the claimed pCPU
+    let target = if cfg!(feature = "multi_pcpu") {
+        pcpu
+    } else {
+        1
+    };
+
+    // Test 1: claim disarms the preemption watchdog on this pCPU
+    uart_puts(b"[EXCL-PCPU] Test 1: claim disarms CNTHP...\n");
+    vs.vcpu_online_mask.store(1, Ordering::Relaxed);
+    timer::arm_preemption_timer();
+    if exclusive_as_vm(EXCLUSIVE_CLAIM) != (true, 0) {
+        uart_puts(b"[EXCL-PCPU] FAILED: claim not granted\n");
+        timer::disarm_preemption_timer();
+        return;
+    }
+    if cnthp_ctl() & 1 != 0 || !is_exclusive(VM_ID, pcpu) {
+        uart_puts(b"[EXCL-PCPU] FAILED: watchdog still armed or pCPU not exclusive\n");
+        timer::disarm_preemption_timer();
+        return;
+    }
+    uart_puts(b"[EXCL-PCPU] Test 1 PASSED\n\n");
+
+    // Test 2: the claimed pCPU is no CPU_ON candidate; CPU_ON is refused
+    uart_puts(b"[EXCL-PCPU] Test 2: CPU_ON candidate set...\n");
+    if cpu_on_candidate(VM_ID, target)
+        || cpu_on_as_vm(target as u64) != (true, PSCI_INVALID_PARAMETERS)
+    {
+        uart_puts(b"[EXCL-PCPU] FAILED: exclusive pCPU still a CPU_ON target\n");
+        exclusive_as_vm(EXCLUSIVE_RELEASE);
+        return;
+    }
+    exclusive_as_vm(EXCLUSIVE_RELEASE);
+    if is_exclusive(VM_ID, pcpu) || !cpu_on_candidate(VM_ID, target) {
+        uart_puts(b"[EXCL-PCPU] FAILED: release did not restore the pCPU\n");
+        return;
+    }
+    uart_puts(b"[EXCL-PCPU] Test 2 PASSED\n\n");
+
+    // Test 3: on a shared pCPU the claim needs the caller to be alone
+    uart_puts(b"[EXCL-PCPU] Test 3: claim with sibling vCPUs online...\n");
+    vs.vcpu_online_mask.store(0b11, Ordering::Relaxed);
+    let (cont, ret) = exclusive_as_vm(EXCLUSIVE_CLAIM);
+    let expected = if cfg!(feature = "multi_pcpu") {
+        0
+    } else {
+        HC_DENIED
+    };
+    exclusive_as_vm(EXCLUSIVE_RELEASE);
+    if !cont || ret != expected || is_exclusive(VM_ID, pcpu) {
+        uart_puts(b"[EXCL-PCPU] FAILED: unexpected claim result with siblings\n");
+        vs.vcpu_online_mask.store(prev_online, Ordering::Relaxed);
+        vs.current_vcpu_id.store(prev_vcpu, Ordering::Relaxed);
+        return;
+    }
+    uart_puts(b"[EXCL-PCPU] Test 3 PASSED\n\n");
+
+    // Test 4: the claim belongs to VM 1 only; VM 0 still sees a shared pCPU
+    uart_puts(b"[EXCL-PCPU] Test 4: claim scoped to the claiming VM...\n");
+    vs.vcpu_online_mask.store(1, Ordering::Relaxed);
+    let granted = exclusive_as_vm(EXCLUSIVE_CLAIM) == (true, 0);
+    let scoped = is_exclusive(VM_ID, pcpu) && !is_exclusive(0, pcpu) && cpu_on_candidate(0, target);
+    if !granted || !scoped {
+        uart_puts(b"[EXCL-PCPU] FAILED: claim leaked to another VM\n");
+        exclusive_as_vm(EXCLUSIVE_RELEASE);
+        vs.vcpu_online_mask.store(prev_online, Ordering::Relaxed);
+        vs.current_vcpu_id.store(prev_vcpu, Ordering::Relaxed);
+        return;
+    }
+    uart_puts(b"[EXCL-PCPU] Test 4 PASSED\n\n");
+
+    // Test 5: terminating VM 1 drops its claim; so does recreating it
+    uart_puts(b"[EXCL-PCPU] Test 5: terminate / Vm::new drop claims...\n");
+    let prev_vm = CURRENT_VM_ID.swap(VM_ID, Ordering::Relaxed);
+    terminate_current_vm();
+    CURRENT_VM_ID.store(prev_vm, Ordering::Relaxed);
+    let dropped_on_terminate = !is_exclusive(VM_ID, pcpu);
+    vs.vm_terminated.store(false, Ordering::Release);
+    vs.terminal_exit[0].store(false, Ordering::Release);
+    exclusive_as_vm(EXCLUSIVE_CLAIM);
+    let _fresh = Vm::new(VM_ID);
+    let dropped_on_new = !is_exclusive(VM_ID, pcpu);
+    vs.vcpu_online_mask.store(prev_online, Ordering::Relaxed);
+    vs.current_vcpu_id.store(prev_vcpu, Ordering::Relaxed);
+    if !dropped_on_terminate || !dropped_on_new {
+        uart_puts(b"[EXCL-PCPU] FAILED: stale claim survived VM teardown\n");
+        exclusive_as_vm(EXCLUSIVE_RELEASE);
+        return;
+    }
+    uart_puts(b"[EXCL-PCPU] Test 5 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Exclusive pCPU Test PASSED (5 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
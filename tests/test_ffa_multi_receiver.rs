@@ -0,0 +1,195 @@
+//! FF-A multi-receiver memory share tests
+//!
+//! VM0 shares a range with VM1 and VM2 in one descriptor. Each receiver
+//! retrieves and relinquishes on its own, and VM0's reclaim is denied for
+//! as long as either of them still has the share retrieved.
+
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::regs::VcpuContext;
+use hypervisor::ffa;
+use hypervisor::ffa::descriptors::{build_test_descriptor_multi, MAX_RECEIVERS};
+use hypervisor::ffa::stub_spmc::lookup_share_full;
+use hypervisor::global::CURRENT_VM_ID;
+use hypervisor::uart_puts;
+
+#[repr(C, align(4096))]
+struct PageBuf([u8; 4096]);
+
+static mut VM0_TX: PageBuf = PageBuf([0; 4096]);
+static mut VM0_RX: PageBuf = PageBuf([0; 4096]);
+
+const RANGES: [(u64, u32); 1] = [(0x5A20_0000, 2)];
+/// Partition IDs of VM1 and VM2
+const VM1: u16 = 2;
+const VM2: u16 = 3;
+const RW: u8 = ffa::FFA_MEM_DATA_ACCESS_RW | ffa::FFA_MEM_INST_ACCESS_NX;
+/// Read-only, not executable
+const RO: u8 = 0b01 | ffa::FFA_MEM_INST_ACCESS_NX;
+
+/// Issue the call set up in `ctx` as `vm_id`.
+fn issue(vm_id: usize, mut ctx: VcpuContext) -> VcpuContext {
+    CURRENT_VM_ID.store(vm_id, Ordering::Relaxed);
+    ffa::proxy::handle_ffa_call(&mut ctx);
+    CURRENT_VM_ID.store(0, Ordering::Relaxed);
+    ctx
+}
+
+fn call(vm_id: usize, x: [u64; 4]) -> VcpuContext {
+    let mut ctx = VcpuContext::default();
+    ctx.gp_regs.x0 = x[0];
+    ctx.gp_regs.x1 = x[1];
+    ctx.gp_regs.x2 = x[2];
+    ctx.gp_regs.x3 = x[3];
+    issue(vm_id, ctx)
+}
+
+/// MEM_SHARE `RANGES` from VM0 to `receivers` via VM0's TX buffer.
+/// Returns the handle or the FF-A error code.
+fn share(receivers: &[(u16, u8)]) -> Result<u64, i32> {
+    let tx = (&raw mut VM0_TX).cast::<u8>();
+    let len = unsafe {
+        core::ptr::write_bytes(tx, 0, 4096);
+        build_test_descriptor_multi(tx, 1, receivers, &RANGES)
+    };
+    let ctx = call(0, [ffa::FFA_MEM_SHARE_32, len as u64, len as u64, 0]);
+    if ctx.gp_regs.x0 == ffa::FFA_SUCCESS_32 {
+        Ok(ffa::mem_handle_from_result(&ctx))
+    } else {
+        Err(ctx.gp_regs.x2 as i32)
+    }
+}
+
+/// Issue `func` for `handle` as `vm_id`; returns x0 (or the error code).
+fn handle_call(vm_id: usize, func: u64, handle: u64) -> u64 {
+    let mut ctx = VcpuContext::default();
+    ctx.gp_regs.x0 = func;
+    ffa::set_mem_handle_args(&mut ctx, handle);
+    let ctx = issue(vm_id, ctx);
+    if ctx.gp_regs.x0 == ffa::FFA_ERROR {
+        ctx.gp_regs.x2
+    } else {
+        ctx.gp_regs.x0
+    }
+}
+
+fn retrieve(vm_id: usize, handle: u64) -> u64 {
+    handle_call(vm_id, ffa::FFA_MEM_RETRIEVE_REQ_32, handle)
+}
+
+fn relinquish(vm_id: usize, handle: u64) -> u64 {
+    handle_call(vm_id, ffa::FFA_MEM_RELINQUISH, handle)
+}
+
+fn reclaim(handle: u64) -> u64 {
+    handle_call(0, ffa::FFA_MEM_RECLAIM, handle)
+}
+
+/// Relinquish as both receivers, then reclaim as VM0.
+fn release(handle: u64) {
+    relinquish(1, handle);
+    relinquish(2, handle);
+    reclaim(handle);
+}
+
+pub fn run_ffa_multi_receiver_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  FF-A Multi-Receiver Share Test\n");
+    uart_puts(b"========================================\n\n");
+
+    // is_guest_ram() rejects RXTX buffers in hypervisor memory
+    if cfg!(feature = "linux_guest") {
+        uart_puts(b"[FFA-MULTI] Skipped: RXTX buffers not in guest RAM\n\n");
+        return;
+    }
+
+    let (tx, rx) = ((&raw const VM0_TX) as u64, (&raw const VM0_RX) as u64);
+    if call(0, [ffa::FFA_RXTX_MAP, tx, rx, 1]).gp_regs.x0 != ffa::FFA_SUCCESS_32 {
+        uart_puts(b"[FFA-MULTI] FAILED: RXTX_MAP\n");
+        return;
+    }
+    let denied = ffa::FFA_DENIED as u32 as u64;
+    let invalid = ffa::FFA_INVALID_PARAMETERS;
+
+    // Test 1: one share, two receivers with their own permissions
+    uart_puts(b"[FFA-MULTI] Test 1: share recorded with both receivers...\n");
+    let Ok(handle) = share(&[(VM1, RW), (VM2, RO)]) else {
+        call(0, [ffa::FFA_RXTX_UNMAP, 0, 0, 0]);
+        uart_puts(b"[FFA-MULTI] FAILED: two-receiver MEM_SHARE\n");
+        return;
+    };
+    let recorded = lookup_share_full(handle).is_some_and(|i| {
+        i.receivers().len() == 2
+            && i.receiver(VM1).is_some_and(|r| r.permissions == RW)
+            && i.receiver(VM2).is_some_and(|r| r.permissions == RO)
+            && !i.any_retrieved()
+    });
+    if !recorded {
+        release(handle);
+        call(0, [ffa::FFA_RXTX_UNMAP, 0, 0, 0]);
+        uart_puts(b"[FFA-MULTI] FAILED: receivers not recorded\n");
+        return;
+    }
+    uart_puts(b"[FFA-MULTI] Test 1 PASSED\n\n");
+
+    // Test 2: VM1 retrieves, VM2 has not; reclaim denied, VM3 (not a
+    // receiver) and a second VM1 retrieve denied
+    uart_puts(b"[FFA-MULTI] Test 2: one receiver retrieved, reclaim denied...\n");
+    let vm1_retrieve = retrieve(1, handle);
+    let vm1_again = retrieve(1, handle);
+    let stranger = retrieve(3, handle);
+    let early_reclaim = reclaim(handle);
+    let vm2_state = lookup_share_full(handle).and_then(|i| i.receiver(VM2).map(|r| r.retrieved));
+    if vm1_retrieve != ffa::FFA_MEM_RETRIEVE_RESP
+        || vm1_again != denied
+        || stranger != denied
+        || early_reclaim != denied
+        || vm2_state != Some(false)
+    {
+        release(handle);
+        call(0, [ffa::FFA_RXTX_UNMAP, 0, 0, 0]);
+        uart_puts(b"[FFA-MULTI] FAILED: partial retrieve not enforced\n");
+        return;
+    }
+    uart_puts(b"[FFA-MULTI] Test 2 PASSED\n\n");
+
+    // Test 3: reclaim stays denied until both receivers have relinquished
+    uart_puts(b"[FFA-MULTI] Test 3: reclaim after both relinquish...\n");
+    let vm2_retrieve = retrieve(2, handle);
+    let vm1_relinquish = relinquish(1, handle);
+    let vm1_twice = relinquish(1, handle);
+    let still_held = reclaim(handle);
+    let vm2_relinquish = relinquish(2, handle);
+    let reclaimed = reclaim(handle);
+    if vm2_retrieve != ffa::FFA_MEM_RETRIEVE_RESP
+        || vm1_relinquish != ffa::FFA_SUCCESS_32
+        || vm1_twice != denied
+        || still_held != denied
+        || vm2_relinquish != ffa::FFA_SUCCESS_32
+        || reclaimed != ffa::FFA_SUCCESS_32
+        || lookup_share_full(handle).is_some()
+    {
+        release(handle);
+        call(0, [ffa::FFA_RXTX_UNMAP, 0, 0, 0]);
+        uart_puts(b"[FFA-MULTI] FAILED: reclaim not gated on every receiver\n");
+        return;
+    }
+    uart_puts(b"[FFA-MULTI] Test 3 PASSED\n\n");
+
+    // Test 4: duplicate receivers or more than MAX_RECEIVERS are rejected
+    uart_puts(b"[FFA-MULTI] Test 4: malformed receiver lists rejected...\n");
+    let duplicate = share(&[(VM1, RW), (VM1, RO)]);
+    let too_many = share(&[(VM1, RW); MAX_RECEIVERS + 1]);
+    call(0, [ffa::FFA_RXTX_UNMAP, 0, 0, 0]);
+    if duplicate != Err(invalid) || too_many != Err(invalid) {
+        for h in [duplicate, too_many].into_iter().flatten() {
+            reclaim(h);
+        }
+        uart_puts(b"[FFA-MULTI] FAILED: bad receiver list accepted\n");
+        return;
+    }
+    uart_puts(b"[FFA-MULTI] Test 4 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  FF-A Multi-Receiver Share Test PASSED (4 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,180 @@
+//! FF-A v1.1 retrieve-response descriptor tests
+//!
+//! VM0 shares two ranges with VM1 through its TX buffer. A VM1 that
+//! negotiated v1.1 gets the retrieve-response descriptor in its RX buffer
+//! with the total length in x1; a v1.0 VM1 gets the register-only response.
+
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::regs::VcpuContext;
+use hypervisor::ffa;
+use hypervisor::ffa::descriptors::{build_test_descriptor, parse_mem_region};
+use hypervisor::global::CURRENT_VM_ID;
+use hypervisor::uart_puts;
+
+#[repr(C, align(4096))]
+struct PageBuf([u8; 4096]);
+
+static mut VM0_TX: PageBuf = PageBuf([0; 4096]);
+static mut VM0_RX: PageBuf = PageBuf([0; 4096]);
+static mut VM1_TX: PageBuf = PageBuf([0; 4096]);
+static mut VM1_RX: PageBuf = PageBuf([0; 4096]);
+
+const RANGES: [(u64, u32); 2] = [(0x5A00_0000, 2), (0x5A10_0000, 1)];
+
+/// Issue the call set up in `ctx` as `vm_id`.
+fn issue(vm_id: usize, mut ctx: VcpuContext) -> VcpuContext {
+    CURRENT_VM_ID.store(vm_id, Ordering::Relaxed);
+    ffa::proxy::handle_ffa_call(&mut ctx);
+    CURRENT_VM_ID.store(0, Ordering::Relaxed);
+    ctx
+}
+
+fn call(vm_id: usize, x: [u64; 4]) -> VcpuContext {
+    let mut ctx = VcpuContext::default();
+    ctx.gp_regs.x0 = x[0];
+    ctx.gp_regs.x1 = x[1];
+    ctx.gp_regs.x2 = x[2];
+    ctx.gp_regs.x3 = x[3];
+    issue(vm_id, ctx)
+}
+
+/// Issue `func` on `handle` as `vm_id`.
+fn handle_call(vm_id: usize, func: u64, handle: u64) -> VcpuContext {
+    let mut ctx = VcpuContext::default();
+    ctx.gp_regs.x0 = func;
+    ffa::set_mem_handle_args(&mut ctx, handle);
+    issue(vm_id, ctx)
+}
+
+/// Share `RANGES` from VM0 to VM1 via VM0's TX buffer. Returns the handle.
+fn share_to_vm1() -> Option<u64> {
+    let len = unsafe { build_test_descriptor((&raw mut VM0_TX).cast(), 1, 2, &RANGES) };
+    let ctx = call(0, [ffa::FFA_MEM_SHARE_32, len as u64, len as u64, 0]);
+    if ctx.gp_regs.x0 != ffa::FFA_SUCCESS_32 {
+        return None;
+    }
+    Some(ffa::mem_handle_from_result(&ctx))
+}
+
+/// Relinquish as VM1 and reclaim as VM0.
+fn release(handle: u64) {
+    handle_call(1, ffa::FFA_MEM_RELINQUISH, handle);
+    handle_call(0, ffa::FFA_MEM_RECLAIM, handle);
+}
+
+fn retrieve(handle: u64) -> VcpuContext {
+    handle_call(1, ffa::FFA_MEM_RETRIEVE_REQ_32, handle)
+}
+
+pub fn run_ffa_retrieve_resp_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  FF-A Retrieve Response Test\n");
+    uart_puts(b"========================================\n\n");
+
+    // is_guest_ram() rejects RXTX buffers in hypervisor memory
+    if cfg!(feature = "linux_guest") {
+        uart_puts(b"[FFA-RETR] Skipped: RXTX buffers not in guest RAM\n\n");
+        return;
+    }
+
+    let map = |vm_id: usize, tx: u64, rx: u64| {
+        call(vm_id, [ffa::FFA_RXTX_MAP, tx, rx, 1]).gp_regs.x0 == ffa::FFA_SUCCESS_32
+    };
+    let mapped = map(0, (&raw const VM0_TX) as u64, (&raw const VM0_RX) as u64)
+        && map(1, (&raw const VM1_TX) as u64, (&raw const VM1_RX) as u64);
+    if !mapped {
+        uart_puts(b"[FFA-RETR] FAILED: RXTX_MAP\n");
+        return;
+    }
+
+    // Test 1: v1.0 receiver keeps the register-only response
+    uart_puts(b"[FFA-RETR] Test 1: v1.0 register-only response...\n");
+    call(1, [ffa::FFA_VERSION, ffa::FFA_VERSION_1_0 as u64, 0, 0]);
+    let handle = share_to_vm1();
+    let ctx = handle.map(retrieve);
+    let ok = match (handle, ctx) {
+        (Some(h), Some(c)) => {
+            c.gp_regs.x0 == ffa::FFA_MEM_RETRIEVE_RESP
+                && c.gp_regs.x1 == 0
+                && ffa::mem_handle_from_result(&c) == h
+        }
+        _ => false,
+    };
+    if let Some(h) = handle {
+        release(h);
+    }
+    if !ok || ffa::proxy::negotiated_version(1) != ffa::FFA_VERSION_1_0 {
+        uart_puts(b"[FFA-RETR] FAILED: v1.0 response not register-only\n");
+        return;
+    }
+    uart_puts(b"[FFA-RETR] Test 1 PASSED\n\n");
+
+    // Test 2: v1.1 receiver gets a well-formed descriptor in RX
+    uart_puts(b"[FFA-RETR] Test 2: v1.1 descriptor in RX buffer...\n");
+    call(1, [ffa::FFA_VERSION, ffa::FFA_VERSION_1_1 as u64, 0, 0]);
+    let Some(handle) = share_to_vm1() else {
+        uart_puts(b"[FFA-RETR] FAILED: MEM_SHARE\n");
+        return;
+    };
+    let ctx = retrieve(handle);
+    let total = ctx.gp_regs.x1 as u32;
+    let rx = (&raw const VM1_RX) as *const u8;
+    let parsed = unsafe { parse_mem_region(rx, total) };
+    let (rx_handle, flags, perms) = unsafe {
+        (
+            core::ptr::read_unaligned(rx.add(16) as *const u64),
+            core::ptr::read_unaligned(rx.add(8) as *const u32),
+            core::ptr::read(rx.add(48 + 2)),
+        )
+    };
+    let ok = ctx.gp_regs.x0 == ffa::FFA_MEM_RETRIEVE_RESP
+        && total == 48 + 16 + 16 + 2 * 16
+        && ctx.gp_regs.x2 == total as u64
+        && rx_handle == handle
+        && (flags >> ffa::FFA_MEM_FLAG_TYPE_SHIFT) & 0b11 == ffa::FFA_MEM_FLAG_TYPE_SHARE
+        && perms == ffa::FFA_MEM_DATA_ACCESS_RW | ffa::FFA_MEM_INST_ACCESS_NX
+        && match parsed {
+            Ok(p) => {
+                p.sender_id == 1
+                    && p.receivers[..p.receiver_count] == [(2, perms)]
+                    && p.total_page_count == 3
+                    && p.ranges[..p.range_count] == RANGES
+                    && (p.attributes >> ffa::FFA_MEM_TYPE_SHIFT) & 0b11 == ffa::FFA_MEM_TYPE_NORMAL
+            }
+            Err(_) => false,
+        };
+    release(handle);
+    if !ok {
+        uart_puts(b"[FFA-RETR] FAILED: malformed retrieve-response descriptor\n");
+        return;
+    }
+    uart_puts(b"[FFA-RETR] Test 2 PASSED\n\n");
+
+    // Test 3: RX still owned by VM1 -> BUSY, share stays unretrieved
+    uart_puts(b"[FFA-RETR] Test 3: busy RX buffer...\n");
+    let Some(handle) = share_to_vm1() else {
+        uart_puts(b"[FFA-RETR] FAILED: MEM_SHARE\n");
+        return;
+    };
+    let busy = retrieve(handle);
+    call(1, [ffa::FFA_RX_RELEASE, 0, 0, 0]);
+    let after = retrieve(handle);
+    release(handle);
+    call(1, [ffa::FFA_RX_RELEASE, 0, 0, 0]);
+    if busy.gp_regs.x0 != ffa::FFA_ERROR
+        || busy.gp_regs.x2 as i32 != ffa::FFA_BUSY
+        || after.gp_regs.x0 != ffa::FFA_MEM_RETRIEVE_RESP
+    {
+        uart_puts(b"[FFA-RETR] FAILED: busy RX not reported\n");
+        return;
+    }
+    uart_puts(b"[FFA-RETR] Test 3 PASSED\n\n");
+
+    call(1, [ffa::FFA_VERSION, 0, 0, 0]);
+    call(1, [ffa::FFA_RXTX_UNMAP, 0, 0, 0]);
+    call(0, [ffa::FFA_RXTX_UNMAP, 0, 0, 0]);
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  FF-A Retrieve Response Test PASSED (3 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,139 @@
+//! FF-A FFA_RUN stub SP tests
+//!
+//! Without a real SPMC, FFA_RUN drives the stub SP state machine: an idle
+//! SP goes straight back to FFA_MSG_WAIT, a direct request that gets
+//! preempted leaves the SP Preempted until its sender FFA_RUNs it to the
+//! DIRECT_RESP, and an aborted SP is never run again.
+
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::regs::VcpuContext;
+use hypervisor::ffa;
+use hypervisor::ffa::stub_spmc::{self, STUB_CMD_ABORT, STUB_CMD_PREEMPT};
+use hypervisor::global::CURRENT_VM_ID;
+use hypervisor::sp_context::SpState;
+use hypervisor::uart_puts;
+
+const SP1: u16 = 0x8001;
+/// Partition ID of VM 0 (the sender)
+const VM0: u64 = 1;
+
+fn call(vm_id: usize, x: [u64; 8]) -> VcpuContext {
+    CURRENT_VM_ID.store(vm_id, Ordering::Relaxed);
+    let mut ctx = VcpuContext::default();
+    let gp = &mut ctx.gp_regs;
+    [gp.x0, gp.x1, gp.x2, gp.x3, gp.x4, gp.x5, gp.x6, gp.x7] = x;
+    ffa::proxy::handle_ffa_call(&mut ctx);
+    CURRENT_VM_ID.store(0, Ordering::Relaxed);
+    ctx
+}
+
+/// DIRECT_REQ from VM 0 to SP1 carrying `payload` in x3-x7.
+fn direct_req(payload: [u64; 5]) -> VcpuContext {
+    let [x3, x4, x5, x6, x7] = payload;
+    let x1 = (VM0 << 16) | SP1 as u64;
+    call(
+        0,
+        [ffa::FFA_MSG_SEND_DIRECT_REQ_32, x1, 0, x3, x4, x5, x6, x7],
+    )
+}
+
+/// FFA_RUN SP1 vCPU `vcpu` as `vm_id`.
+fn run(vm_id: usize, vcpu: u64) -> VcpuContext {
+    call(
+        vm_id,
+        [ffa::FFA_RUN, ((SP1 as u64) << 16) | vcpu, 0, 0, 0, 0, 0, 0],
+    )
+}
+
+fn error_code(ctx: &VcpuContext) -> Option<i32> {
+    (ctx.gp_regs.x0 == ffa::FFA_ERROR).then_some(ctx.gp_regs.x2 as u32 as i32)
+}
+
+pub fn run_ffa_run_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  FF-A FFA_RUN Stub SP Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let sp1_x1 = (SP1 as u64) << 16;
+
+    // Test 1: FFA_RUN on an idle SP returns FFA_MSG_WAIT and leaves it Idle
+    uart_puts(b"[FFA-RUN] Test 1: idle SP returns to MSG_WAIT...\n");
+    let ctx = run(0, 0);
+    if ctx.gp_regs.x0 != ffa::FFA_MSG_WAIT
+        || ctx.gp_regs.x1 != sp1_x1
+        || stub_spmc::sp_state(SP1) != Some(SpState::Idle)
+    {
+        uart_puts(b"[FFA-RUN] FAILED: idle SP not run to MSG_WAIT\n");
+        return;
+    }
+    uart_puts(b"[FFA-RUN] Test 1 PASSED\n\n");
+
+    // Test 2: a direct request preempted twice leaves the SP Preempted
+    // and busy; only the sender may resume it
+    uart_puts(b"[FFA-RUN] Test 2: preempted request leaves SP busy...\n");
+    let ctx = direct_req([STUB_CMD_PREEMPT, 2, 0xAA, 0xBB, 0xCC]);
+    let interrupted = ctx.gp_regs.x0 == ffa::FFA_INTERRUPT && ctx.gp_regs.x1 == sp1_x1;
+    let preempted = stub_spmc::sp_state(SP1) == Some(SpState::Preempted);
+    let busy = error_code(&direct_req([1, 2, 3, 4, 5]));
+    let stranger = error_code(&run(1, 0));
+    if !interrupted
+        || !preempted
+        || busy != Some(ffa::FFA_BUSY)
+        || stranger != Some(ffa::FFA_DENIED)
+    {
+        stub_spmc::reload_sp(SP1);
+        uart_puts(b"[FFA-RUN] FAILED: preempted request not tracked\n");
+        return;
+    }
+    uart_puts(b"[FFA-RUN] Test 2 PASSED\n\n");
+
+    // Test 3: FFA_RUN resumes it (Preempted -> Running -> Preempted),
+    // then runs it to the DIRECT_RESP (-> Idle)
+    uart_puts(b"[FFA-RUN] Test 3: FFA_RUN resumes to DIRECT_RESP...\n");
+    let again = run(0, 0).gp_regs.x0 == ffa::FFA_INTERRUPT
+        && stub_spmc::sp_state(SP1) == Some(SpState::Preempted);
+    let done = run(0, 0);
+    let resp = &done.gp_regs;
+    let completed = resp.x0 == ffa::FFA_MSG_SEND_DIRECT_RESP_32
+        && resp.x1 == sp1_x1 | VM0
+        && (resp.x5, resp.x6, resp.x7) == (0xAA, 0xBB, 0xCC)
+        && stub_spmc::sp_state(SP1) == Some(SpState::Idle);
+    if !again || !completed {
+        stub_spmc::reload_sp(SP1);
+        uart_puts(b"[FFA-RUN] FAILED: resume did not complete the request\n");
+        return;
+    }
+    uart_puts(b"[FFA-RUN] Test 3 PASSED\n\n");
+
+    // Test 4: an aborted SP refuses FFA_RUN and further requests
+    uart_puts(b"[FFA-RUN] Test 4: aborted SP rejected...\n");
+    let aborted = error_code(&direct_req([STUB_CMD_ABORT, 0, 0, 0, 0]));
+    let state = stub_spmc::sp_state(SP1);
+    let run_aborted = error_code(&run(0, 0));
+    let req_aborted = error_code(&direct_req([1, 2, 3, 4, 5]));
+    stub_spmc::reload_sp(SP1);
+    if aborted != Some(ffa::FFA_ABORTED)
+        || state != Some(SpState::Aborted)
+        || run_aborted != Some(ffa::FFA_ABORTED)
+        || req_aborted != Some(ffa::FFA_ABORTED)
+    {
+        uart_puts(b"[FFA-RUN] FAILED: aborted SP still scheduled\n");
+        return;
+    }
+    uart_puts(b"[FFA-RUN] Test 4 PASSED\n\n");
+
+    // Test 5: unknown SP or vCPU beyond the SP's execution contexts
+    uart_puts(b"[FFA-RUN] Test 5: bad target rejected...\n");
+    let invalid = Some(ffa::FFA_INVALID_PARAMETERS);
+    let bad_vcpu = error_code(&run(0, 1));
+    let bad_sp = error_code(&call(0, [ffa::FFA_RUN, 0x8009 << 16, 0, 0, 0, 0, 0, 0]));
+    if bad_vcpu != invalid || bad_sp != invalid {
+        uart_puts(b"[FFA-RUN] FAILED: bad FFA_RUN target accepted\n");
+        return;
+    }
+    uart_puts(b"[FFA-RUN] Test 5 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  FF-A FFA_RUN Stub SP Test PASSED (5 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,82 @@
+//! FF-A share table capacity tests
+//!
+//! Fills the stub SPMC share table through FFA_MEM_SHARE, checks that the
+//! next share fails with FFA_NO_MEMORY, then reclaims a few and checks that
+//! new shares reuse the freed slots.
+
+use super::cleanup::Cleanup;
+use super::ffa_fixture::{reclaim, share};
+use core::cell::Cell;
+use hypervisor::ffa;
+use hypervisor::ffa::stub_spmc::{share_capacity, share_count, MAX_SHARES};
+use hypervisor::uart_puts;
+
+const BASE_IPA: u64 = 0x5C00_0000;
+
+pub fn run_ffa_share_capacity_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  FF-A Share Capacity Test\n");
+    uart_puts(b"========================================\n\n");
+
+    // Handles start at 1; reclaiming an unused 0 slot is a no-op
+    let handles = [const { Cell::new(0u64) }; MAX_SHARES];
+    let already = share_count();
+    let free = share_capacity() - already;
+    let cleanup = Cleanup::new(|| {
+        for h in &handles {
+            let _ = reclaim(h.get());
+        }
+    });
+
+    // Test 1: every free slot can be filled
+    uart_puts(b"[FFA-CAP] Test 1: fill share table to capacity...\n");
+    for (i, slot) in handles[..free].iter().enumerate() {
+        match share(BASE_IPA + i as u64 * 0x1000) {
+            Ok(h) => slot.set(h),
+            Err(_) => {
+                uart_puts(b"[FFA-CAP] FAILED: share below capacity rejected\n");
+                return;
+            }
+        }
+    }
+    if share_count() != share_capacity() {
+        uart_puts(b"[FFA-CAP] FAILED: share_count does not reach capacity\n");
+        return;
+    }
+    uart_puts(b"[FFA-CAP] Test 1 PASSED\n\n");
+
+    // Test 2: one more share fails with FFA_NO_MEMORY
+    uart_puts(b"[FFA-CAP] Test 2: overflow returns FFA_NO_MEMORY...\n");
+    if share(BASE_IPA + 0x10_0000) != Err(ffa::FFA_NO_MEMORY) {
+        uart_puts(b"[FFA-CAP] FAILED: overflow share not rejected\n");
+        return;
+    }
+    uart_puts(b"[FFA-CAP] Test 2 PASSED\n\n");
+
+    // Test 3: reclaimed slots are reused by new shares
+    uart_puts(b"[FFA-CAP] Test 3: freed slots reused...\n");
+    let freed = free.min(3);
+    let mut reused = true;
+    for h in &handles[free - freed..free] {
+        reused &= reclaim(h.get()).is_ok();
+    }
+    reused &= share_count() == share_capacity() - freed;
+    for (i, slot) in handles[free - freed..free].iter().enumerate() {
+        match share(BASE_IPA + 0x20_0000 + i as u64 * 0x1000) {
+            Ok(h) => slot.set(h),
+            Err(_) => reused = false,
+        }
+    }
+    reused &=
+        share_count() == share_capacity() && share(BASE_IPA + 0x30_0000) == Err(ffa::FFA_NO_MEMORY);
+    drop(cleanup);
+    if !reused || share_count() != already {
+        uart_puts(b"[FFA-CAP] FAILED: freed slots not reused\n");
+        return;
+    }
+    uart_puts(b"[FFA-CAP] Test 3 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  FF-A Share Capacity Test PASSED (3 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
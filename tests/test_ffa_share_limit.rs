@@ -0,0 +1,106 @@
+//! FF-A share rate limit tests
+//!
+//! Under a fake clock, lowers VM 0's memory-operation limit, issues
+//! FFA_MEM_SHARE/RECLAIM faster than it allows and checks the excess calls
+//! get FFA_BUSY, then that they succeed once the window has elapsed.
+
+use super::ffa_fixture::{reclaim, share};
+use hypervisor::ffa;
+use hypervisor::ffa::share_limit::{self, DEFAULT_SHARE_OPS_PER_WINDOW, SHARE_WINDOW_NS};
+use hypervisor::ffa::stub_spmc::share_count;
+use hypervisor::time;
+use hypervisor::uart_puts;
+
+const VM_ID: usize = 0;
+const LIMIT: u32 = 4;
+const BASE_IPA: u64 = 0x5C40_0000;
+
+/// Move the fake clock past the current window.
+fn next_window() {
+    time::advance_fake_clock(time::ns_to_ticks(SHARE_WINDOW_NS));
+}
+
+/// Reclaim every handle, one window at a time, and restore the defaults.
+fn cleanup(handles: &[u64]) {
+    for &h in handles {
+        if reclaim(h) == Err(ffa::FFA_BUSY) {
+            next_window();
+            let _ = reclaim(h);
+        }
+    }
+    share_limit::set_limit(VM_ID, DEFAULT_SHARE_OPS_PER_WINDOW);
+    time::remove_fake_clock();
+}
+
+pub fn run_ffa_share_limit_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  FF-A Share Rate Limit Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let already = share_count();
+    let mut handles = [0u64; LIMIT as usize + 1];
+    time::install_fake_clock(1_000_000_000, 1 << 32);
+    share_limit::set_limit(VM_ID, LIMIT);
+
+    // Test 1: shares beyond the limit within one window get FFA_BUSY,
+    // and so does a reclaim
+    uart_puts(b"[FFA-LIMIT] Test 1: excess shares in a window get FFA_BUSY...\n");
+    for (i, slot) in handles[..LIMIT as usize].iter_mut().enumerate() {
+        match share(BASE_IPA + i as u64 * 0x1000) {
+            Ok(h) => *slot = h,
+            Err(_) => {
+                cleanup(&handles[..i]);
+                uart_puts(b"[FFA-LIMIT] FAILED: share within the limit rejected\n");
+                return;
+            }
+        }
+    }
+    let excess = share(BASE_IPA + LIMIT as u64 * 0x1000);
+    let busy_reclaim = reclaim(handles[0]);
+    if excess != Err(ffa::FFA_BUSY)
+        || busy_reclaim != Err(ffa::FFA_BUSY)
+        || share_count() != already + LIMIT as usize
+    {
+        if let Ok(h) = excess {
+            handles[LIMIT as usize] = h;
+        }
+        cleanup(&handles);
+        uart_puts(b"[FFA-LIMIT] FAILED: excess operations not refused with FFA_BUSY\n");
+        return;
+    }
+    uart_puts(b"[FFA-LIMIT] Test 1 PASSED\n\n");
+
+    // Test 2: once the window elapses the refused share and reclaim succeed
+    uart_puts(b"[FFA-LIMIT] Test 2: operations succeed after the window...\n");
+    next_window();
+    let retried = share(BASE_IPA + LIMIT as u64 * 0x1000);
+    if let Ok(h) = retried {
+        handles[LIMIT as usize] = h;
+    }
+    let reclaimed = reclaim(handles[0]);
+    if retried.is_err() || reclaimed.is_err() {
+        cleanup(&handles);
+        uart_puts(b"[FFA-LIMIT] FAILED: operations still refused in a new window\n");
+        return;
+    }
+    uart_puts(b"[FFA-LIMIT] Test 2 PASSED\n\n");
+
+    // Test 3: a limit of 0 disables throttling
+    uart_puts(b"[FFA-LIMIT] Test 3: limit 0 is unlimited...\n");
+    share_limit::set_limit(VM_ID, 0);
+    let mut unthrottled = share_limit::limit(VM_ID) == 0;
+    for &h in &handles[1..] {
+        unthrottled &= reclaim(h).is_ok();
+    }
+    share_limit::set_limit(VM_ID, DEFAULT_SHARE_OPS_PER_WINDOW);
+    time::remove_fake_clock();
+    if !unthrottled || share_count() != already {
+        uart_puts(b"[FFA-LIMIT] FAILED: limit 0 still throttles\n");
+        return;
+    }
+    uart_puts(b"[FFA-LIMIT] Test 3 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  FF-A Share Rate Limit Test PASSED (3 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,199 @@
+//! FF-A reclaim-on-shutdown tests
+//!
+//! Gives VM 1 outstanding shares over test Stage-2 tables (one lent to an
+//! SP, one retrieved by VM 0, and one it retrieved from VM 0), stops it,
+//! and checks that its pages are Owned + RW again, VM 0 lost its mapping of
+//! the page VM 1 shared, and only the share VM 0 still owns keeps its record.
+//! A VM retired by the run loop (exception storm, last vCPU off) rolls back
+//! its shares the same way.
+
+use super::cleanup::Cleanup;
+use core::mem::ManuallyDrop;
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::defs::S2_MEMATTR_NORMAL_WB;
+use hypervisor::arch::aarch64::mm::mmu::{DynamicIdentityMapper, MemoryAttribute};
+use hypervisor::ffa::memory::{map_shared_ranges, PageOwnership};
+use hypervisor::ffa::stage2_walker::Stage2Walker;
+use hypervisor::ffa::stub_spmc::{lookup_share_full, mark_retrieved, reclaim_share, record_share};
+use hypervisor::ffa::vm_id_to_partition_id;
+use hypervisor::global::{vm_state, PER_VM_VTTBR};
+use hypervisor::uart_puts;
+use hypervisor::vm::Vm;
+
+const VM_ID: usize = 1;
+const PEER_VM_ID: usize = 0;
+const SP_ID: u16 = 0x8001;
+/// VM 1 RAM (2MB block); pages lent to the SP and shared with VM 0
+const SENDER_RAM: u64 = 0x4860_0000;
+const LENT_IPA: u64 = SENDER_RAM;
+const SHARED_IPA: u64 = SENDER_RAM + 0x1_0000;
+/// VM 0 page that VM 1 retrieved
+const BORROWED_IPA: u64 = 0x4880_0000;
+
+/// Mark `ipa` as shared out by its owner: SharedOwned with S2AP `s2ap`.
+fn mark_shared_owned(walker: &Stage2Walker, ipa: u64, s2ap: u8) -> bool {
+    walker
+        .write_sw_bits(ipa, PageOwnership::SharedOwned as u8)
+        .and(walker.set_s2ap(ipa, s2ap))
+        .is_ok()
+}
+
+/// VM 1 lends `LENT_IPA` to the SP again.
+fn lend_page(walker: &Stage2Walker) -> Option<u64> {
+    let handle = record_share(
+        vm_id_to_partition_id(VM_ID),
+        &[(SP_ID, 0)],
+        &[(LENT_IPA, 1)],
+        1,
+        true,
+        S2_MEMATTR_NORMAL_WB,
+    )?;
+    if !mark_shared_owned(walker, LENT_IPA, 0b00) {
+        reclaim_share(handle);
+        return None;
+    }
+    Some(handle)
+}
+
+fn owned_rw(walker: &Stage2Walker, ipa: u64) -> bool {
+    walker.read_sw_bits(ipa) == Some(PageOwnership::Owned as u8)
+        && walker.read_s2ap(ipa) == Some(0b11)
+        && walker.read_mem_attr(ipa) == Some(S2_MEMATTR_NORMAL_WB)
+}
+
+pub fn run_ffa_vm_shutdown_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  FF-A Reclaim on VM Shutdown Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let mut vm1_s2 = ManuallyDrop::new(DynamicIdentityMapper::new());
+    let mut vm0_s2 = ManuallyDrop::new(DynamicIdentityMapper::new());
+    let mapped = vm1_s2
+        .map_region(SENDER_RAM, 0x0020_0000, MemoryAttribute::Normal)
+        .and(vm0_s2.map_region(BORROWED_IPA, 0x0020_0000, MemoryAttribute::Normal));
+    let vm1_walker = Stage2Walker::new(vm1_s2.vttbr());
+    let vm0_walker = Stage2Walker::new(vm0_s2.vttbr());
+    let saved1 = PER_VM_VTTBR[VM_ID].swap(vm1_s2.vttbr(), Ordering::AcqRel);
+    let saved0 = PER_VM_VTTBR[PEER_VM_ID].swap(vm0_s2.vttbr(), Ordering::AcqRel);
+    let (me, peer) = (
+        vm_id_to_partition_id(VM_ID),
+        vm_id_to_partition_id(PEER_VM_ID),
+    );
+
+    // VM 1 lends a page to the SP and shares one with VM 0, which retrieves it
+    let lent = record_share(
+        me,
+        &[(SP_ID, 0)],
+        &[(LENT_IPA, 1)],
+        1,
+        true,
+        S2_MEMATTR_NORMAL_WB,
+    );
+    let shared = record_share(
+        me,
+        &[(peer, 0)],
+        &[(SHARED_IPA, 1)],
+        1,
+        false,
+        S2_MEMATTR_NORMAL_WB,
+    );
+    // VM 1 retrieved a page shared by VM 0
+    let borrowed = record_share(
+        peer,
+        &[(me, 0)],
+        &[(BORROWED_IPA, 1)],
+        1,
+        false,
+        S2_MEMATTR_NORMAL_WB,
+    );
+    let setup = mapped.is_ok()
+        && mark_shared_owned(&vm1_walker, LENT_IPA, 0b00)
+        && mark_shared_owned(&vm1_walker, SHARED_IPA, 0b01)
+        && mark_shared_owned(&vm0_walker, BORROWED_IPA, 0b01)
+        && map_shared_ranges(&vm0_walker, &[(SHARED_IPA, 1)], S2_MEMATTR_NORMAL_WB, 0).is_ok()
+        && map_shared_ranges(&vm1_walker, &[(BORROWED_IPA, 1)], S2_MEMATTR_NORMAL_WB, 0).is_ok()
+        && shared.is_some_and(|h| mark_retrieved(h, peer))
+        && borrowed.is_some_and(|h| mark_retrieved(h, me));
+
+    let mut vm = Vm::new(VM_ID);
+    vm.stop();
+    let cleanup = Cleanup::new(|| {
+        for handle in [lent, shared, borrowed].into_iter().flatten() {
+            reclaim_share(handle);
+        }
+        PER_VM_VTTBR[VM_ID].store(saved1, Ordering::Release);
+        PER_VM_VTTBR[PEER_VM_ID].store(saved0, Ordering::Release);
+    });
+
+    // Test 1: shares VM 1 sent are reclaimed: pages Owned + RW, records gone
+    uart_puts(b"[FFA-STOP] Test 1: sender pages restored, records removed...\n");
+    if !setup
+        || !owned_rw(&vm1_walker, LENT_IPA)
+        || !owned_rw(&vm1_walker, SHARED_IPA)
+        || lent.and_then(lookup_share_full).is_some()
+        || shared.and_then(lookup_share_full).is_some()
+    {
+        uart_puts(b"[FFA-STOP] FAILED: sender share not reclaimed\n");
+        return;
+    }
+    uart_puts(b"[FFA-STOP] Test 1 PASSED\n\n");
+
+    // Test 2: the receiver of a force-reclaimed share loses its mapping
+    uart_puts(b"[FFA-STOP] Test 2: receiver mapping removed...\n");
+    if vm0_walker.translate(SHARED_IPA).is_some() {
+        uart_puts(b"[FFA-STOP] FAILED: VM 0 still maps reclaimed page\n");
+        return;
+    }
+    uart_puts(b"[FFA-STOP] Test 2 PASSED\n\n");
+
+    // Test 3: a share VM 1 retrieved is relinquished; VM 0 keeps the record
+    uart_puts(b"[FFA-STOP] Test 3: borrowed share relinquished...\n");
+    let info = borrowed.and_then(lookup_share_full);
+    let relinquished = info.is_some_and(|i| !i.any_retrieved() && i.sender_id == peer)
+        && vm1_walker.translate(BORROWED_IPA).is_none()
+        && vm0_walker.read_sw_bits(BORROWED_IPA) == Some(PageOwnership::SharedOwned as u8);
+    if !relinquished {
+        uart_puts(b"[FFA-STOP] FAILED: borrowed share not relinquished\n");
+        return;
+    }
+    uart_puts(b"[FFA-STOP] Test 3 PASSED\n\n");
+
+    // Test 4: a VM terminated after an exception storm, retired by the run
+    // loop, rolls back its shares too
+    uart_puts(b"[FFA-STOP] Test 4: terminated VM reclaims shares...\n");
+    let mut vm = Vm::new(VM_ID);
+    let storm = lend_page(&vm1_walker);
+    vm_state(VM_ID).vm_terminated.store(true, Ordering::Release);
+    let retired = vm.retire_if_terminated();
+    let reclaimed = storm.is_some() && storm.and_then(lookup_share_full).is_none();
+    if !retired || !reclaimed || !owned_rw(&vm1_walker, LENT_IPA) {
+        if let Some(h) = storm {
+            reclaim_share(h);
+        }
+        uart_puts(b"[FFA-STOP] FAILED: terminated VM kept its share\n");
+        return;
+    }
+    uart_puts(b"[FFA-STOP] Test 4 PASSED\n\n");
+
+    // Test 5: so does a VM whose last vCPU retires (CPU_OFF/SYSTEM_OFF)
+    uart_puts(b"[FFA-STOP] Test 5: last vCPU retired reclaims shares...\n");
+    let mut vm = Vm::new(VM_ID);
+    let off = lend_page(&vm1_walker);
+    vm_state(VM_ID).vcpu_online_mask.store(1, Ordering::Release);
+    let done = vm.retire_vcpu(0);
+    let reclaimed = off.is_some() && off.and_then(lookup_share_full).is_none();
+    let restored = owned_rw(&vm1_walker, LENT_IPA);
+    if let Some(h) = off {
+        reclaim_share(h);
+    }
+    drop(cleanup);
+    if !done || !reclaimed || !restored {
+        uart_puts(b"[FFA-STOP] FAILED: retired VM kept its share\n");
+        return;
+    }
+    uart_puts(b"[FFA-STOP] Test 5 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  FF-A Reclaim on VM Shutdown Test PASSED (5 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,134 @@
+//! Fresh-vCPU FP/SIMD state tests
+//!
+//! Fills V0-V31, FPSR and FPCR with junk (standing in for whatever the
+//! hypervisor or a previous guest left on the pCPU), then runs a guest
+//! stub that reads D0, V31.D[1], FPSR and FPCR into x19-x22. A newly
+//! created vCPU, and one brought back with `reset()`, must see zeros.
+
+use core::arch::asm;
+use hypervisor::uart_puts;
+use hypervisor::vm::Vm;
+
+/// Host-side junk loaded into every V register before entry
+const JUNK: u64 = 0xDEAD_BEEF_CAFE_F00D;
+/// FPSR.QC
+const FPSR_JUNK: u64 = 1 << 27;
+/// FPCR.RMode = round towards zero
+const FPCR_JUNK: u64 = 0b11 << 22;
+
+#[repr(C, align(4096))]
+struct FpGuest {
+    code: [u32; 8],
+}
+
+static FP_GUEST: FpGuest = FpGuest {
+    code: [
+        0x9e660013, // fmov x19, d0
+        0x9eae03f4, // fmov x20, v31.d[1]
+        0xd53b4435, // mrs x21, fpsr
+        0xd53b4416, // mrs x22, fpcr
+        0xd2800020, // mov x0, #1 (exit hypercall)
+        0xd4000002, // hvc #0
+        0x14000000, // b .
+        0x00000000, // padding
+    ],
+};
+
+/// Load `JUNK` into both halves of V0 and V31 and set FPSR/FPCR bits.
+fn dirty_fp_state() {
+    unsafe {
+        asm!(
+            "dup v0.2d, {junk}",
+            "dup v31.2d, {junk}",
+            "msr fpsr, {fpsr}",
+            "msr fpcr, {fpcr}",
+            junk = in(reg) JUNK,
+            fpsr = in(reg) FPSR_JUNK,
+            fpcr = in(reg) FPCR_JUNK,
+            out("v0") _,
+            out("v31") _,
+            options(nostack, nomem),
+        );
+    }
+}
+
+/// Put FPSR/FPCR back to the hypervisor's defaults after a failure.
+fn clear_fp_control() {
+    unsafe { asm!("msr fpsr, xzr", "msr fpcr, xzr", options(nostack, nomem)) };
+}
+
+/// Dirty the FP state, enter vCPU 0 at the stub and return what it read
+/// from D0, V31.D[1], FPSR and FPCR.
+fn guest_fp_state(vm: &mut Vm) -> Option<[u64; 4]> {
+    dirty_fp_state();
+    vm.run().ok()?;
+    let regs = &vm.vcpu(0)?.context().gp_regs;
+    Some([regs.x19, regs.x20, regs.x21, regs.x22])
+}
+
+/// Rewind vCPU 0 to the stub with FP enabled and x19-x22 poisoned.
+fn prepare(vm: &mut Vm, entry: u64, reset: bool) -> bool {
+    let Some(vcpu) = vm.vcpu_mut(0) else {
+        return false;
+    };
+    if reset {
+        vcpu.reset(entry, entry + 0x10000);
+    } else {
+        vcpu.context_mut().pc = entry;
+        vcpu.context_mut().sp = entry + 0x10000;
+    }
+    vcpu.arch_state_mut().cpacr_el1 = 3 << 20; // FPEN: no FP/SIMD traps
+    let regs = &mut vcpu.context_mut().gp_regs;
+    regs.x19 = u64::MAX;
+    regs.x20 = u64::MAX;
+    regs.x21 = u64::MAX;
+    regs.x22 = u64::MAX;
+    true
+}
+
+pub fn run_fp_reset_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Fresh vCPU FP/SIMD State Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let entry = &FP_GUEST.code as *const _ as u64;
+    let mut vm = Vm::new(0);
+    vm.init_memory(entry & !(2 * 1024 * 1024 - 1), 4 * 1024 * 1024);
+    if vm.create_vcpu(0).is_err() {
+        uart_puts(b"[FP-RESET] FAILED: create_vcpu\n");
+        return;
+    }
+
+    // Test 1: a newly created vCPU's first entry sees zeroed V registers,
+    // FPSR and FPCR regardless of what the pCPU held
+    uart_puts(b"[FP-RESET] Test 1: new vCPU starts with zeroed FP state...\n");
+    let fresh = if prepare(&mut vm, entry, false) {
+        guest_fp_state(&mut vm)
+    } else {
+        None
+    };
+    if fresh != Some([0; 4]) {
+        clear_fp_control();
+        uart_puts(b"[FP-RESET] FAILED: stale FP/SIMD state visible to new vCPU\n");
+        return;
+    }
+    uart_puts(b"[FP-RESET] Test 1 PASSED\n\n");
+
+    // Test 2: reset() zeroes the saved FP state again
+    uart_puts(b"[FP-RESET] Test 2: reset vCPU starts with zeroed FP state...\n");
+    let after_reset = if prepare(&mut vm, entry, true) {
+        guest_fp_state(&mut vm)
+    } else {
+        None
+    };
+    if after_reset != Some([0; 4]) {
+        clear_fp_control();
+        uart_puts(b"[FP-RESET] FAILED: stale FP/SIMD state visible after reset\n");
+        return;
+    }
+    uart_puts(b"[FP-RESET] Test 2 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Fresh vCPU FP/SIMD State Test PASSED (2 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
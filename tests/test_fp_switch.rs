@@ -0,0 +1,127 @@
+//! Per-vCPU FP/SIMD switching tests
+//!
+//! Two vCPUs of one VM each write their own value to Q0 and exit; with
+//! the host scribbling over V0 in between, each must read back its own
+//! value on the next entry, and its saved `FpState` must hold it. An entry
+//! that never touches FP/SIMD must leave the saved registers alone.
+
+use core::arch::asm;
+use hypervisor::uart_puts;
+use hypervisor::vm::Vm;
+
+/// Host-side junk loaded into V0 between guest entries
+const JUNK: u64 = 0xDEAD_BEEF_CAFE_F00D;
+/// Q0 value of each vCPU
+const VALUES: [u64; 2] = [0x1111_2222_3333_4444, 0x5555_6666_7777_8888];
+
+/// Offsets of the three stubs in `FP_GUEST`
+const WRITE_Q0: u64 = 0x00;
+const READ_Q0: u64 = 0x10;
+const NO_FP: u64 = 0x20;
+
+#[repr(C, align(4096))]
+struct FpGuest {
+    code: [u32; 12],
+}
+
+static FP_GUEST: FpGuest = FpGuest {
+    code: [
+        // WRITE_Q0
+        0x9e670260, // fmov d0, x19
+        0xd2800020, // mov x0, #1 (exit hypercall)
+        0xd4000002, // hvc #0
+        0x14000000, // b .
+        // READ_Q0
+        0x9e660014, // fmov x20, d0
+        0xd2800020, // mov x0, #1 (exit hypercall)
+        0xd4000002, // hvc #0
+        0x14000000, // b .
+        // NO_FP
+        0xd2800020, // mov x0, #1 (exit hypercall)
+        0xd4000002, // hvc #0
+        0x14000000, // b .
+        0x00000000, // padding
+    ],
+};
+
+/// Load `JUNK` into both halves of V0.
+fn dirty_v0() {
+    unsafe {
+        asm!(
+            "dup v0.2d, {junk}",
+            junk = in(reg) JUNK,
+            out("v0") _,
+            options(nostack, nomem),
+        );
+    }
+}
+
+/// Enter vCPU `id` at the stub at `offset` with x19 = `x19`, after
+/// dirtying V0 on the host. Returns the guest's x20.
+fn enter(vm: &mut Vm, id: usize, offset: u64, x19: u64) -> Option<u64> {
+    let entry = &FP_GUEST.code as *const _ as u64;
+    let vcpu = vm.vcpu_mut(id)?;
+    vcpu.context_mut().pc = entry + offset;
+    vcpu.context_mut().sp = entry + 0x10000;
+    vcpu.arch_state_mut().cpacr_el1 = 3 << 20; // FPEN: no FP/SIMD traps
+    let regs = &mut vcpu.context_mut().gp_regs;
+    regs.x19 = x19;
+    regs.x20 = u64::MAX;
+    dirty_v0();
+    vcpu.run().ok()?;
+    Some(vcpu.context().gp_regs.x20)
+}
+
+/// Q0 as saved in vCPU `id`'s `FpState`.
+fn saved_q0(vm: &Vm, id: usize) -> Option<u128> {
+    Some(vm.vcpu(id)?.arch_state().fp.q[0])
+}
+
+pub fn run_fp_switch_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Per-vCPU FP/SIMD Switch Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let entry = &FP_GUEST.code as *const _ as u64;
+    let mut vm = Vm::new(0);
+    vm.init_memory(entry & !(2 * 1024 * 1024 - 1), 4 * 1024 * 1024);
+    if vm.create_vcpu(0).is_err() || vm.create_vcpu(1).is_err() {
+        uart_puts(b"[FP-SWITCH] FAILED: create_vcpu\n");
+        return;
+    }
+    let expected = VALUES.map(|v| Some(v as u128));
+
+    // Test 1: a Q0 write is saved to the writing vCPU's FpState on exit
+    uart_puts(b"[FP-SWITCH] Test 1: guest Q0 saved per vCPU...\n");
+    let wrote = (0..2).all(|id| enter(&mut vm, id, WRITE_Q0, VALUES[id]).is_some());
+    let saved = [saved_q0(&vm, 0), saved_q0(&vm, 1)];
+    if !wrote || saved != expected {
+        uart_puts(b"[FP-SWITCH] FAILED: Q0 not saved on guest exit\n");
+        return;
+    }
+    uart_puts(b"[FP-SWITCH] Test 1 PASSED\n\n");
+
+    // Test 2: interleaved entries each reload their own Q0, not the
+    // other vCPU's or the host's
+    uart_puts(b"[FP-SWITCH] Test 2: Q0 reloaded per vCPU...\n");
+    let read1 = enter(&mut vm, 1, READ_Q0, 0);
+    let read0 = enter(&mut vm, 0, READ_Q0, 0);
+    if [read0, read1] != VALUES.map(Some) {
+        uart_puts(b"[FP-SWITCH] FAILED: Q0 bled between vCPUs\n");
+        return;
+    }
+    uart_puts(b"[FP-SWITCH] Test 2 PASSED\n\n");
+
+    // Test 3: an entry without FP/SIMD use leaves the saved state alone
+    uart_puts(b"[FP-SWITCH] Test 3: FP-idle entry keeps saved Q0...\n");
+    let ran = enter(&mut vm, 0, NO_FP, 0).is_some();
+    if !ran || saved_q0(&vm, 0) != expected[0] {
+        uart_puts(b"[FP-SWITCH] FAILED: host V0 saved over an idle vCPU\n");
+        return;
+    }
+    uart_puts(b"[FP-SWITCH] Test 3 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Per-vCPU FP/SIMD Switch Test PASSED (3 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
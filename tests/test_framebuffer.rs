@@ -0,0 +1,165 @@
+//! Linear framebuffer tests
+//!
+//! Attaches a `VirtualFramebuffer` to a VM, lets a stub guest fill it with
+//! a pixel pattern and checks that `Vm::framebuffer_snapshot()` returns
+//! exactly what the guest rendered. Also covers geometry validation,
+//! snapshot clipping and reading through a Stage-2 that does not map the
+//! framebuffer IPA == PA. The DTB node is tested in `test_dtb`.
+
+use hypervisor::arch::aarch64::defs::{
+    BLOCK_MASK_2MB, BLOCK_SIZE_2MB, PAGE_SIZE_4KB, S2AP_RW, S2AP_SHIFT, S2_MEMATTR_NORMAL_WB,
+};
+use hypervisor::arch::aarch64::mm::mmu::{DynamicIdentityMapper, MemoryAttribute};
+use hypervisor::devices::dma::DmaMapper;
+use hypervisor::devices::framebuffer::{PixelFormat, VirtualFramebuffer};
+use hypervisor::ffa::stage2_walker::Stage2Walker;
+use hypervisor::uart_puts;
+use hypervisor::vm::Vm;
+
+const WIDTH: u32 = 16;
+const HEIGHT: u32 = 8;
+const PIXELS: usize = (WIDTH * HEIGHT) as usize;
+const FB_BYTES: usize = PIXELS * 4;
+/// PL011 window of every VM's device manager
+const UART_BASE: u64 = 0x0900_0000;
+/// Framebuffer IPA remapped onto `FB_MEMORY` in Test 4
+const REMAP_IPA: u64 = 0x6000_0000;
+
+#[repr(C, align(4096))]
+struct FbGuest {
+    code: [u32; 12],
+}
+
+/// Writes pixel i = 0xFF00_0000 | i for x2 pixels from x1.
+static FB_GUEST: FbGuest = FbGuest {
+    code: [
+        0x52800003, // mov w3, #0
+        0x32081c64, // 1: orr w4, w3, #0xff000000
+        0xb8004424, // str w4, [x1], #4
+        0x11000463, // add w3, w3, #1
+        0xf1000442, // subs x2, x2, #1
+        0x54ffff81, // b.ne 1b
+        0xd2800020, // mov x0, #1 (exit hypercall)
+        0xd4000002, // hvc #0
+        0x14000000, // b .
+        0x00000000, // padding
+        0x00000000, // padding
+        0x00000000, // padding
+    ],
+};
+
+#[repr(C, align(4096))]
+struct FbMemory([u8; 4096]);
+
+static mut FB_MEMORY: FbMemory = FbMemory([0; 4096]);
+
+fn pixel(out: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([out[i * 4], out[i * 4 + 1], out[i * 4 + 2], out[i * 4 + 3]])
+}
+
+pub fn run_framebuffer_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Linear Framebuffer Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let entry = &FB_GUEST.code as *const _ as u64;
+    let base = &raw mut FB_MEMORY as u64;
+
+    // Test 1: geometry, and rejected layouts
+    uart_puts(b"[FB] Test 1: framebuffer geometry...\n");
+    let fb = VirtualFramebuffer::new(base, WIDTH, HEIGHT, PixelFormat::A8R8G8B8);
+    let rejected = VirtualFramebuffer::new(base + 4, WIDTH, HEIGHT, PixelFormat::A8R8G8B8).is_err()
+        && VirtualFramebuffer::new(base, 0, HEIGHT, PixelFormat::R5G6B5).is_err()
+        && VirtualFramebuffer::new(base, 1 << 20, 1 << 20, PixelFormat::X8R8G8B8).is_err();
+    let Ok(fb) = fb else {
+        uart_puts(b"[FB] FAILED: valid framebuffer rejected\n");
+        return;
+    };
+    if fb.stride() != WIDTH * 4 || fb.size() != FB_BYTES as u64 || !rejected {
+        uart_puts(b"[FB] FAILED: wrong geometry or bad layout accepted\n");
+        return;
+    }
+    uart_puts(b"[FB] Test 1 PASSED\n\n");
+
+    // Test 2: the host snapshot shows the guest's pattern
+    uart_puts(b"[FB] Test 2: snapshot reflects guest rendering...\n");
+    let code_block = entry & !BLOCK_MASK_2MB;
+    let fb_block = base & !BLOCK_MASK_2MB;
+    let start = code_block.min(fb_block);
+    let mut vm = Vm::new(0);
+    vm.init_memory(start, code_block.max(fb_block) + BLOCK_SIZE_2MB - start);
+    let attached = vm.attach_framebuffer(fb).is_ok();
+    let ran = match vm.create_vcpu(0) {
+        Ok(vcpu) => {
+            vcpu.context_mut().pc = entry;
+            vcpu.context_mut().gp_regs.x1 = base;
+            vcpu.context_mut().gp_regs.x2 = PIXELS as u64;
+            vcpu.run().is_ok()
+        }
+        Err(_) => false,
+    };
+    let mut out = [0u8; FB_BYTES];
+    let copied = vm.framebuffer_snapshot(&mut out);
+    let rendered = (0..PIXELS).all(|i| pixel(&out, i) == 0xFF00_0000 | i as u32);
+    if !attached || !ran || copied != FB_BYTES || !rendered {
+        uart_puts(b"[FB] FAILED: snapshot does not match guest pattern\n");
+        return;
+    }
+    uart_puts(b"[FB] Test 2 PASSED\n\n");
+
+    // Test 3: a short buffer gets the first rows only; no framebuffer, or
+    // one over an MMIO window, gives nothing
+    uart_puts(b"[FB] Test 3: clipping and rejection...\n");
+    let mut row = [0u8; (WIDTH * 4) as usize];
+    let clipped = vm.framebuffer_snapshot(&mut row) == row.len()
+        && (0..WIDTH as usize).all(|i| pixel(&row, i) == 0xFF00_0000 | i as u32);
+    let over_uart = VirtualFramebuffer::new(UART_BASE, WIDTH, HEIGHT, PixelFormat::R5G6B5)
+        .is_ok_and(|fb| Vm::new(1).attach_framebuffer(fb).is_err());
+    let none = Vm::new(1).framebuffer_snapshot(&mut row) == 0;
+    let _fresh = Vm::new(0);
+    if !clipped || !over_uart || !none {
+        uart_puts(b"[FB] FAILED: clipping or overlap check\n");
+        return;
+    }
+    uart_puts(b"[FB] Test 3 PASSED\n\n");
+
+    // Test 4: the snapshot follows Stage-2, not IPA == PA: a two-page
+    // framebuffer whose first page maps onto FB_MEMORY reads that page,
+    // and stops at the unmapped second one
+    uart_puts(b"[FB] Test 4: snapshot through Stage-2...\n");
+    let mem = &raw mut FB_MEMORY;
+    unsafe {
+        for (i, byte) in (*mem).0.iter_mut().enumerate() {
+            *byte = (i % 251) as u8;
+        }
+    }
+    let mut mapper = DynamicIdentityMapper::new();
+    // An identity page after the framebuffer gives its 2MB region an L3 table
+    let mapped = mapper
+        .map_4kb_page(REMAP_IPA + 2 * PAGE_SIZE_4KB, MemoryAttribute::Normal)
+        .is_ok()
+        && Stage2Walker::new(mapper.vttbr())
+            .map_page_to(
+                REMAP_IPA,
+                base,
+                (S2AP_RW >> S2AP_SHIFT) as u8,
+                0,
+                S2_MEMATTR_NORMAL_WB,
+            )
+            .is_ok();
+    let dma = DmaMapper::with_stage2(mapper.vttbr());
+    let remapped = VirtualFramebuffer::new(REMAP_IPA, WIDTH, 128, PixelFormat::A8R8G8B8);
+    let mut pages = [0u8; 2 * PAGE_SIZE_4KB as usize];
+    let copied = remapped.map_or(0, |fb| fb.snapshot(&dma, &mut pages));
+    let through_s2 = (0..PAGE_SIZE_4KB as usize).all(|i| pages[i] == (i % 251) as u8);
+    core::mem::forget(mapper);
+    if !mapped || copied != PAGE_SIZE_4KB as usize || !through_s2 {
+        uart_puts(b"[FB] FAILED: snapshot did not read through Stage-2\n");
+        return;
+    }
+    uart_puts(b"[FB] Test 4 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Linear Framebuffer Test PASSED (4 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
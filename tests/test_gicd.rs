@@ -1,12 +1,23 @@
 //! Virtual GICD emulation tests
 //!
-//! Tests VirtualGicd shadow state read/write semantics. Write-through to
+//! Tests VirtualGicd shadow state read/write semantics, including the
+//! single-security-state view (CTLR.DS, RAZ/WI NSACR), IPRIORITYR/ICFGR
+//! readback at every access width, SPI active state tracked from list
+//! registers and level-triggered re-assertion. Write-through to
 //! physical GICD occurs but is harmless at EL2.
 
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::defs::{LR_GROUP1_BIT, LR_STATE_SHIFT};
 use hypervisor::devices::gic::VirtualGicd;
 use hypervisor::devices::MmioDevice;
+use hypervisor::global::{current_vm_id, vm_state, DEFAULT_VCPUS_PER_CLUSTER};
 use hypervisor::uart_puts;
 
+/// ICH_LR<n>_EL2.State values
+const LR_PENDING: u64 = 0b01;
+const LR_ACTIVE: u64 = 0b10;
+const LR_PENDING_ACTIVE: u64 = 0b11;
+
 pub fn run_gicd_test() {
     uart_puts(b"\n========================================\n");
     uart_puts(b"  Virtual GICD Emulation Test\n");
@@ -27,9 +38,9 @@ pub fn run_gicd_test() {
     uart_puts(b"[GICD] Test 2: CTLR write preserves ARE_NS...\n");
     gicd.write(0x000, 0x01, 4); // EnableGrp1NS only
     let ctlr = gicd.read(0x000, 4).unwrap();
-    if ctlr != 0x11 {
-        // EnableGrp1NS | ARE_NS
-        uart_puts(b"[GICD] FAILED: CTLR should be 0x11, got 0x");
+    if ctlr != 0x51 {
+        // EnableGrp1NS | ARE_NS | DS
+        uart_puts(b"[GICD] FAILED: CTLR should be 0x51, got 0x");
         hypervisor::uart_put_hex(ctlr);
         uart_puts(b"\n");
         return;
@@ -95,8 +106,8 @@ pub fn run_gicd_test() {
     // Test 7: PIDR2 reports GICv3
     uart_puts(b"[GICD] Test 7: PIDR2...\n");
     let pidr2 = gicd.read(0xFFE8, 4).unwrap();
-    if pidr2 != 0x30 {
-        uart_puts(b"[GICD] FAILED: PIDR2 should be 0x30\n");
+    if (pidr2 >> 4) & 0xF != 0x3 {
+        uart_puts(b"[GICD] FAILED: PIDR2.ArchRev should be 3\n");
         return;
     }
     uart_puts(b"[GICD] Test 7 PASSED\n\n");
@@ -110,7 +121,210 @@ pub fn run_gicd_test() {
     }
     uart_puts(b"[GICD] Test 8 PASSED\n\n");
 
+    // Test 9: IROUTER retarget migrates a queued SPI to the new vCPU
+    uart_puts(b"[GICD] Test 9: IROUTER retarget migrates pending SPI...\n");
+    // SPI 40 -> IROUTER index = 8, offset = 0x6100 + 8*8 = 0x6140 (initially vCPU 0)
+    let vs = hypervisor::global::vm_state(0);
+    let bit = 1u32 << (40 - 32);
+    vs.pending_spis[0].fetch_or(bit, Ordering::Relaxed);
+    gicd.write(0x6140, 0x01, 8); // Retarget to vCPU 1
+    let old_pending = vs.pending_spis[0].load(Ordering::Relaxed) & bit;
+    let new_pending = vs.pending_spis[1].load(Ordering::Relaxed) & bit;
+    vs.pending_spis[1].fetch_and(!bit, Ordering::Relaxed);
+    if old_pending != 0 || new_pending == 0 {
+        uart_puts(b"[GICD] FAILED: pending bit did not move from vCPU 0 to vCPU 1\n");
+        return;
+    }
+    uart_puts(b"[GICD] Test 9 PASSED\n\n");
+
+    // Test 10: NSACR and IGRPMODR are RAZ/WI (single security state)
+    uart_puts(b"[GICD] Test 10: NSACR/IGRPMODR RAZ/WI...\n");
+    // NSACR[2] at 0xE08 covers INTIDs 32-47, IGRPMODR[1] at 0xD04 INTIDs 32-63
+    let before = gicd.read(0xE08, 4).unwrap();
+    gicd.write(0xE08, 0xFFFF_FFFF, 4);
+    gicd.write(0xD04, 0xFFFF_FFFF, 4);
+    let nsacr = gicd.read(0xE08, 4).unwrap();
+    let igrpmodr = gicd.read(0xD04, 4).unwrap();
+    if before != 0 || nsacr != 0 || igrpmodr != 0 {
+        uart_puts(b"[GICD] FAILED: NSACR/IGRPMODR not RAZ/WI\n");
+        return;
+    }
+    uart_puts(b"[GICD] Test 10 PASSED\n\n");
+
+    // Test 11: CTLR keeps the single-security-state view: DS and ARE_NS
+    // read as one whatever is written, reserved bits are not stored
+    uart_puts(b"[GICD] Test 11: CTLR DS read-as-one...\n");
+    // EnableGrp0 | EnableGrp1 | reserved bit 5, DS and ARE_NS clear
+    gicd.write(0x000, 0x23, 4);
+    let ctlr = gicd.read(0x000, 4).unwrap();
+    if ctlr != 0x53 {
+        uart_puts(b"[GICD] FAILED: CTLR should be 0x53, got 0x");
+        hypervisor::uart_put_hex(ctlr);
+        uart_puts(b"\n");
+        return;
+    }
+    uart_puts(b"[GICD] Test 11 PASSED\n\n");
+
+    // Test 12: CIDR/PIDR identify an ARM GICv3 component
+    uart_puts(b"[GICD] Test 12: CIDR/PIDR component ID...\n");
+    let mut cidr = [0u64; 4];
+    for (i, byte) in cidr.iter_mut().enumerate() {
+        *byte = gicd.read(0xFFF0 + i as u64 * 4, 4).unwrap();
+    }
+    if cidr != [0x0D, 0xF0, 0x05, 0xB1] {
+        uart_puts(b"[GICD] FAILED: CIDR0-3 should be 0D F0 05 B1\n");
+        return;
+    }
+    let pidr0 = gicd.read(0xFFE0, 4).unwrap();
+    let pidr1 = gicd.read(0xFFE4, 4).unwrap();
+    let pidr2 = gicd.read(0xFFE8, 4).unwrap();
+    let pidr4 = gicd.read(0xFFD0, 4).unwrap();
+    let part = pidr0 | ((pidr1 & 0xF) << 8);
+    let jep106 = (pidr1 >> 4) | ((pidr2 & 0x7) << 4);
+    if pidr2 != 0x3B || part != 0x492 || jep106 != 0x3B || pidr4 & 0xF != 0x4 {
+        uart_puts(b"[GICD] FAILED: PIDR should identify ARM GIC-500 (ArchRev 3)\n");
+        return;
+    }
+    gicd.write(0xFFF0, 0, 4);
+    if gicd.read(0xFFF0, 4) != Some(0x0D) {
+        uart_puts(b"[GICD] FAILED: CIDR0 should be read-only\n");
+        return;
+    }
+    uart_puts(b"[GICD] Test 12 PASSED\n\n");
+
+    // Test 13: IPRIORITYR reads back at byte, halfword and word width;
+    // SGI/PPI priorities (IPRIORITYR0-7) are RAZ/WI
+    uart_puts(b"[GICD] Test 13: IPRIORITYR byte/halfword/word access...\n");
+    // IPRIORITYR8 (0x420) covers INTIDs 32-35
+    gicd.write(0x420, 0xA0B0_C0D0, 4);
+    gicd.write(0x421, 0x80, 1); // INTID 33
+    gicd.write(0x426, 0x6070, 2); // INTIDs 38-39
+    let word = gicd.read(0x420, 4);
+    let bytes = [gicd.read(0x421, 1), gicd.read(0x423, 1)];
+    let half = gicd.read(0x426, 2);
+    gicd.write(0x41C, 0xFFFF_FFFF, 4); // INTIDs 28-31
+    gicd.write(0x400, 0xFF, 1); // INTID 0
+    if word != Some(0xA0B0_80D0)
+        || bytes != [Some(0x80), Some(0xA0)]
+        || half != Some(0x6070)
+        || gicd.read(0x424, 4) != Some(0x6070_0000)
+        || gicd.read(0x41C, 4) != Some(0)
+        || gicd.read(0x400, 1) != Some(0)
+    {
+        uart_puts(b"[GICD] FAILED: IPRIORITYR readback\n");
+        return;
+    }
+    uart_puts(b"[GICD] Test 13 PASSED\n\n");
+
+    // Test 14: ICFGR keeps the edge bit of each SPI (Int_config[0] is
+    // RES0), at word and byte width; ICFGR0/1 are RAZ/WI
+    uart_puts(b"[GICD] Test 14: ICFGR edge/level config...\n");
+    // ICFGR2 (0xC08) covers INTIDs 32-47
+    gicd.write(0xC08, 0xFFFF_0003, 4);
+    let word = gicd.read(0xC08, 4);
+    gicd.write(0xC09, 0x08, 1); // INTID 37 edge
+    let byte = gicd.read(0xC09, 1);
+    let half = gicd.read(0xC0A, 2);
+    gicd.write(0xC04, 0xAAAA_AAAA, 4);
+    if word != Some(0xAAAA_0002)
+        || byte != Some(0x08)
+        || half != Some(0xAAAA)
+        || gicd.read(0xC08, 4) != Some(0xAAAA_0802)
+        || gicd.read(0xC04, 4) != Some(0)
+    {
+        uart_puts(b"[GICD] FAILED: ICFGR readback\n");
+        return;
+    }
+    uart_puts(b"[GICD] Test 14 PASSED\n\n");
+
+    // Test 15: ISACTIVER sets and ICACTIVER clears the active bits; both
+    // read back the active state
+    uart_puts(b"[GICD] Test 15: ISACTIVER/ICACTIVER readback...\n");
+    // ISACTIVER1 (0x304) covers INTIDs 32-63
+    gicd.write(0x304, 0x0000_0305, 4);
+    gicd.write(0x384, 0x0000_0004, 4);
+    if gicd.read(0x304, 4) != Some(0x0301)
+        || gicd.read(0x384, 4) != Some(0x0301)
+        || !gicd.is_active(32)
+        || gicd.is_active(34)
+    {
+        uart_puts(b"[GICD] FAILED: active-state readback\n");
+        return;
+    }
+    gicd.write(0x384, 0xFFFF_FFFF, 4);
+    uart_puts(b"[GICD] Test 15 PASSED\n\n");
+
+    // Test 16: SPI active state follows the list registers: injected
+    // (Pending) is not active, acknowledged (Active) is, and dropping out
+    // of the LRs (deactivated) clears it
+    uart_puts(b"[GICD] Test 16: SPI active state tracks injection...\n");
+    let lr = |state: u64, intid: u64| (state << LR_STATE_SHIFT) | LR_GROUP1_BIT | intid;
+    let injected = [lr(LR_PENDING, 40), lr(LR_PENDING, 41), 0, 0];
+    gicd.update_active_from_lrs(&[0; 4], &injected);
+    let pending_only = !gicd.is_active(40) && !gicd.is_active(41);
+    // The guest acknowledged both; SGI 1 in an LR is not the GICD's
+    let acked = [
+        lr(LR_ACTIVE, 40),
+        lr(LR_PENDING_ACTIVE, 41),
+        lr(LR_ACTIVE, 1),
+        0,
+    ];
+    gicd.update_active_from_lrs(&injected, &acked);
+    let active = gicd.is_active(40) && gicd.is_active(41) && !gicd.is_active(1);
+    let isactiver1 = gicd.read(0x304, 4);
+    // INTID 40 deactivated (LR freed), 41 still Active
+    let eoied = [0, lr(LR_ACTIVE, 41), 0, 0];
+    gicd.update_active_from_lrs(&acked, &eoied);
+    let deactivated = !gicd.is_active(40) && gicd.is_active(41);
+    gicd.update_active_from_lrs(&eoied, &[0; 4]);
+    if !pending_only || !active || isactiver1 != Some(0x0300) || !deactivated || gicd.is_active(41)
+    {
+        uart_puts(b"[GICD] FAILED: SPI active state does not track LRs\n");
+        return;
+    }
+    uart_puts(b"[GICD] Test 16 PASSED\n\n");
+
+    // Test 17: deactivating a level-triggered SPI reports it back, both
+    // when it leaves the LRs and on a trapped DIR (`deactivate`); not while
+    // it is still Active, nor when the guest configured it edge-triggered
+    uart_puts(b"[GICD] Test 17: deactivated level SPI reported...\n");
+    let taken = [lr(LR_ACTIVE, 45), 0, 0, 0];
+    gicd.write(0xC08, 0, 4); // INTIDs 32-47 level-sensitive
+    let level = gicd.is_level_triggered(45);
+    let refire = gicd.update_active_from_lrs(&taken, &[0; 4]);
+    // Still Active: not deactivated yet, nothing to re-queue
+    let held = gicd.update_active_from_lrs(&taken, &taken);
+    let dir_level = gicd.deactivate(45);
+    // ICFGR2 (0xC08) field 13 (INTID 45): Int_config[1] set = edge
+    gicd.write(0xC08, 0b10 << 26, 4);
+    let edge = !gicd.is_level_triggered(45)
+        && gicd.update_active_from_lrs(&taken, &[0; 4]) == 0
+        && !gicd.deactivate(45);
+    if !level || refire != 1 << (45 - 32) || held != 0 || !dir_level || !edge {
+        uart_puts(b"[GICD] FAILED: level-triggered deactivation\n");
+        return;
+    }
+    uart_puts(b"[GICD] Test 17 PASSED\n\n");
+
+    // Test 18: IROUTER names a vCPU by its full MPIDR affinity, not Aff0;
+    // one naming no vCPU routes to vCPU 0
+    uart_puts(b"[GICD] Test 18: IROUTER routed by affinity...\n");
+    let vs = vm_state(current_vm_id());
+    vs.vcpus_per_cluster.store(2, Ordering::Relaxed);
+    gicd.write(0x6180, 0x100, 8); // SPI 48 -> Aff1=1, Aff0=0
+    let cluster = gicd.route_spi(48);
+    gicd.write(0x6180, 0x02, 8); // Aff0=2: beyond a 2-vCPU cluster
+    let unmatched = gicd.route_spi(48);
+    vs.vcpus_per_cluster
+        .store(DEFAULT_VCPUS_PER_CLUSTER, Ordering::Relaxed);
+    let flat = gicd.route_spi(48);
+    if cluster != 2 || unmatched != 0 || flat != 2 {
+        uart_puts(b"[GICD] FAILED: IROUTER affinity routing\n");
+        return;
+    }
+    uart_puts(b"[GICD] Test 18 PASSED\n\n");
+
     uart_puts(b"========================================\n");
-    uart_puts(b"  Virtual GICD Emulation Test PASSED (8 assertions)\n");
+    uart_puts(b"  Virtual GICD Emulation Test PASSED (18 assertions)\n");
     uart_puts(b"========================================\n\n");
 }
@@ -1,8 +1,11 @@
 //! GICv3 Virtual Interface Tests
 //!
-//! Tests for the GICv3 List Register management and virtual interrupt injection.
+//! Tests for the GICv3 List Register management and virtual interrupt injection,
+//! and that the host GIC facade (`peripherals::gic`) routes to it on a GICv3 host.
 
+use hypervisor::arch::aarch64::peripherals::gic;
 use hypervisor::arch::aarch64::peripherals::gicv3::GicV3VirtualInterface;
+use hypervisor::arch::aarch64::vcpu_arch_state::VcpuArchState;
 use hypervisor::uart_puts;
 
 /// Test GICv3 virtual interface functionality
@@ -145,6 +148,35 @@ pub fn run_gicv3_virt_test() {
     }
     uart_puts(b"[GICv3 VIRT] Test 6 PASSED (informational)\n\n");
 
+    // Test 7: the host GIC facade picked GICv3 at boot: IAR values are
+    // plain INTIDs, injection lands in an ICH LR and vCPU save captures
+    // it in ich_lr (GICH is left alone)
+    uart_puts(b"[GICv3 VIRT] Test 7: host GIC facade routes to GICv3...\n");
+    if gic::is_active() || gic::iar_intid(27) != 27 {
+        uart_puts(b"[GICv3 VIRT] ERROR: GICv2 driver selected on a GICv3 host\n");
+        return;
+    }
+    if gic::inject_interrupt(27, 0xA0).is_err()
+        || gic::pending_count() != 1
+        || GicV3VirtualInterface::pending_count() != 1
+    {
+        uart_puts(b"[GICv3 VIRT] ERROR: facade injection missed the ICH LRs\n");
+        GicV3VirtualInterface::clear_interrupt(27);
+        return;
+    }
+    let mut arch = VcpuArchState::new();
+    arch.save();
+    GicV3VirtualInterface::clear_interrupt(27);
+    let saved = arch
+        .ich_lr
+        .iter()
+        .any(|&lr| GicV3VirtualInterface::get_lr_intid(lr) == 27);
+    if !saved || arch.gich_lr != [0; 4] {
+        uart_puts(b"[GICv3 VIRT] ERROR: save() did not use ICH_LR*\n");
+        return;
+    }
+    uart_puts(b"[GICv3 VIRT] Test 7 PASSED\n\n");
+
     uart_puts(b"========================================\n");
     uart_puts(b"  GICv3 Virtual Interface Test PASSED\n");
     uart_puts(b"========================================\n\n");
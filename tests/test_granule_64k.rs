@@ -0,0 +1,143 @@
+//! 64KB Stage-2 granule tests
+//!
+//! Builds Stage-2 tables with `Granule::Size64KB` and checks the VTCR_EL2
+//! encoding, the per-level index shifts, and that mappings created by
+//! `DynamicIdentityMapper` round-trip through `Stage2Walker`, including a
+//! 512MB block split into 64KB pages. Also checks the ID_AA64MMFR0_EL1
+//! support decode and that per-VM walkers pick the granule up from
+//! `PER_VM_VTCR`.
+
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::defs::S2_MEMATTR_NORMAL_WB;
+use hypervisor::arch::aarch64::mm::mmu::{
+    DynamicIdentityMapper, Granule, MemoryAttribute, Stage2Config,
+};
+use hypervisor::devices::dma::DmaMapper;
+use hypervisor::ffa::memory::ranges_fit_granule;
+use hypervisor::ffa::stage2_walker::Stage2Walker;
+use hypervisor::global::{PER_VM_VTCR, PER_VM_VTTBR};
+use hypervisor::uart_puts;
+
+const SZ_64K: u64 = 0x1_0000;
+/// One 512MB L2 block, a 128KB page-mapped range, and a hole
+const BLOCK_IPA: u64 = 0x4000_0000;
+const PAGES_IPA: u64 = 0x6000_0000;
+const HOLE_IPA: u64 = 0x7000_0000;
+
+pub fn run_granule_64k_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  64KB Stage-2 Granule Test\n");
+    uart_puts(b"========================================\n\n");
+
+    // Test 1: VTCR fields and level shifts
+    uart_puts(b"[GRANULE] Test 1: VTCR TG0/SL0 and index shifts...\n");
+    let g = Granule::Size64KB;
+    let vtcr = Stage2Config::new_with_granule(0x4100_0000, 1, g).vtcr;
+    let k4 = Granule::Size4KB;
+    if (vtcr >> 14) & 0b11 != 0b01
+        || (vtcr >> 6) & 0b11 != 0b10
+        || vtcr & 0x3F != 16
+        || g.start_level() != 1
+        || (g.level_shift(1), g.level_shift(2), g.level_shift(3)) != (42, 29, 16)
+        || g.block_size() != 0x2000_0000
+        || (vtcr >> 14) & 0b11 == (Stage2Config::new(0).vtcr >> 14) & 0b11
+        || (k4.level_shift(0), k4.level_shift(2), k4.level_shift(3)) != (39, 21, 12)
+    {
+        uart_puts(b"[GRANULE] FAILED: wrong 64KB VTCR or shifts\n");
+        return;
+    }
+    uart_puts(b"[GRANULE] Test 1 PASSED\n\n");
+
+    // Test 2: block and page mappings round-trip through the walker
+    uart_puts(b"[GRANULE] Test 2: map/translate round-trip...\n");
+    let mut mapper = DynamicIdentityMapper::with_granule(g);
+    let mapped = mapper
+        .map_region(BLOCK_IPA, g.block_size(), MemoryAttribute::Normal)
+        .and(mapper.map_region(PAGES_IPA, 2 * SZ_64K, MemoryAttribute::Normal));
+    let walker = Stage2Walker::with_granule(mapper.vttbr(), g);
+    let ok = mapped.is_ok()
+        && mapper.vttbr().is_multiple_of(SZ_64K)
+        && mapper.granule() == g
+        && walker.translate(BLOCK_IPA + 0x123_4567) == Some(BLOCK_IPA + 0x123_4567)
+        && walker.translate(PAGES_IPA + SZ_64K + 0x10) == Some(PAGES_IPA + SZ_64K + 0x10)
+        && walker.translate(PAGES_IPA + 2 * SZ_64K).is_none()
+        && walker.read_mem_attr(PAGES_IPA) == Some(S2_MEMATTR_NORMAL_WB)
+        && mapper.write_sw_bits(PAGES_IPA, 0b10).is_ok()
+        && mapper.read_sw_bits(PAGES_IPA) == Some(0b10)
+        && mapper.read_sw_bits(PAGES_IPA + SZ_64K) == Some(0);
+    if !ok {
+        core::mem::forget(mapper);
+        uart_puts(b"[GRANULE] FAILED: 64KB mapping did not round-trip\n");
+        return;
+    }
+    uart_puts(b"[GRANULE] Test 2 PASSED\n\n");
+
+    // Test 3: block split and page map/unmap use 64KB pages
+    uart_puts(b"[GRANULE] Test 3: 64KB split and map_page...\n");
+    let ro = 0b01; // S2AP read-only
+    let split = walker.set_s2ap(BLOCK_IPA + SZ_64K, ro).is_ok()
+        && walker.read_s2ap(BLOCK_IPA + SZ_64K + 0xFFF0) == Some(ro)
+        && walker.read_s2ap(BLOCK_IPA) == Some(0b11)
+        && walker.read_s2ap(BLOCK_IPA + 2 * SZ_64K) == Some(0b11)
+        && walker.translate(BLOCK_IPA + SZ_64K + 0x1234) == Some(BLOCK_IPA + SZ_64K + 0x1234);
+    let mapped = walker.map_page(HOLE_IPA + 0x8000, 0b11, 0).is_ok()
+        && walker.translate(HOLE_IPA + 0xFFFC) == Some(HOLE_IPA + 0xFFFC)
+        && walker.translate(HOLE_IPA + SZ_64K).is_none();
+    let unmapped = walker.unmap_page(HOLE_IPA).is_ok() && walker.translate(HOLE_IPA).is_none();
+    let root = mapper.vttbr();
+    core::mem::forget(mapper);
+    if !split || !mapped || !unmapped {
+        uart_puts(b"[GRANULE] FAILED: 64KB page operations\n");
+        return;
+    }
+    uart_puts(b"[GRANULE] Test 3 PASSED\n\n");
+
+    // Test 4: TGran64_2 / TGran4_2 decode and VTCR.TG0 round-trip
+    uart_puts(b"[GRANULE] Test 4: ID_AA64MMFR0_EL1 Stage-2 support...\n");
+    let tgran64_2 = |v: u64| v << 32;
+    let tgran64 = |v: u64| v << 24;
+    let ok = g.stage2_supported_by(tgran64_2(0b0010) | tgran64(0xF))
+        && !g.stage2_supported_by(tgran64_2(0b0001))
+        && g.stage2_supported_by(tgran64_2(0b0000) | tgran64(0b0000))
+        && !g.stage2_supported_by(tgran64_2(0b0000) | tgran64(0xF))
+        && k4.stage2_supported_by(0)
+        && !k4.stage2_supported_by(1 << 40)
+        && Granule::from_vtcr(g.vtcr()) == g
+        && Granule::from_vtcr(k4.vtcr()) == k4
+        && Granule::from_vtcr(0) == k4;
+    if !ok {
+        uart_puts(b"[GRANULE] FAILED: wrong Stage-2 granule support decode\n");
+        return;
+    }
+    uart_puts(b"[GRANULE] Test 4 PASSED\n\n");
+
+    // Test 5: per-VM walkers (FF-A, DMA) use the VM's granule
+    uart_puts(b"[GRANULE] Test 5: per-VM walker granule...\n");
+    let saved = (
+        PER_VM_VTTBR[1].load(Ordering::Acquire),
+        PER_VM_VTCR[1].load(Ordering::Acquire),
+    );
+    PER_VM_VTTBR[1].store(root, Ordering::Release);
+    PER_VM_VTCR[1].store(g.vtcr(), Ordering::Release);
+    let vm_walker = Stage2Walker::for_vm(1);
+    let sub_page = 0x1000;
+    let ok = vm_walker.granule() == g
+        && vm_walker.translate(PAGES_IPA + SZ_64K + 0x10) == Some(PAGES_IPA + SZ_64K + 0x10)
+        && ranges_fit_granule(&vm_walker, &[(PAGES_IPA, 16)])
+        && !ranges_fit_granule(&vm_walker, &[(PAGES_IPA, 1)])
+        && !ranges_fit_granule(&vm_walker, &[(PAGES_IPA + sub_page, 16)])
+        && DmaMapper::for_vm(1).translate(PAGES_IPA + SZ_64K + 0x10, 4, false)
+            == Some(PAGES_IPA + SZ_64K + 0x10)
+        && Stage2Walker::for_vm(99).granule() == k4;
+    PER_VM_VTTBR[1].store(saved.0, Ordering::Release);
+    PER_VM_VTCR[1].store(saved.1, Ordering::Release);
+    if !ok {
+        uart_puts(b"[GRANULE] FAILED: per-VM walker ignored the 64KB granule\n");
+        return;
+    }
+    uart_puts(b"[GRANULE] Test 5 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  64KB Stage-2 Granule Test PASSED (5 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -1,9 +1,27 @@
 //! Test for guest_loader module
 //!
-//! Verifies GuestConfig creation and default values.
+//! Verifies GuestConfig creation and default values, the entry
+//! register setup done by BootProtocol, ARM64 Image header parsing, and
+//! that virtio and platform devices attach to the configured VM's device
+//! manager.
 
-use hypervisor::guest_loader::GuestConfig;
+use hypervisor::arch::aarch64::VcpuContext;
+use hypervisor::devices::sched_stats::SCHED_STATS_BASE;
+use hypervisor::devices::sensor::SENSOR_BASE;
+use hypervisor::global::DEVICES;
+use hypervisor::guest_loader::{
+    attach_platform_devices, attach_virtio_devices, Arm64ImageHeader, BootProtocol, GuestConfig,
+    GuestType, ARM64_IMAGE_MAGIC,
+};
+use hypervisor::platform;
 use hypervisor::uart_puts;
+use hypervisor::vm::Vm;
+
+/// Fabricated 64-byte ARM64 Image header
+#[repr(C, align(8))]
+struct ImageHeader([u8; 64]);
+
+static mut IMAGE: ImageHeader = ImageHeader([0; 64]);
 
 /// Test GuestConfig default values
 pub fn run_test() {
@@ -40,5 +58,123 @@ pub fn run_test() {
         return;
     }
 
+    // Verify LinuxArm64 boot protocol: x0 = DTB, x1-x3 = 0
+    uart_puts(b"[TEST] Checking LinuxArm64 setup_registers... ");
+    let linux = GuestConfig {
+        guest_type: GuestType::Linux,
+        load_addr: 0x4000_0000,
+        mem_size: 0x1000_0000,
+        entry_point: 0x4820_0000,
+        dtb_addr: 0x4700_0000,
+        boot_protocol: BootProtocol::default(),
+    };
+    let mut ctx = VcpuContext::default();
+    ctx.gp_regs.x1 = 0xdead;
+    ctx.gp_regs.x2 = 0xdead;
+    ctx.gp_regs.x3 = 0xdead;
+    linux.boot_protocol.setup_registers(&mut ctx, &linux);
+    if linux.boot_protocol == BootProtocol::LinuxArm64
+        && ctx.gp_regs.x0 == 0x4700_0000
+        && ctx.gp_regs.x1 == 0
+        && ctx.gp_regs.x2 == 0
+        && ctx.gp_regs.x3 == 0
+    {
+        uart_puts(b"PASS\n");
+    } else {
+        uart_puts(b"FAIL\n");
+        return;
+    }
+
+    // Verify Arm64ImageHeader::parse fields, fits() and bad magic
+    uart_puts(b"[TEST] Checking Arm64ImageHeader::parse... ");
+    let image = &raw mut IMAGE;
+    let addr = image as u64;
+    unsafe {
+        let h = &mut (*image).0;
+        h[0x08..0x10].copy_from_slice(&0x8_0000u64.to_le_bytes());
+        h[0x10..0x18].copy_from_slice(&0x20_0000u64.to_le_bytes());
+        h[0x18..0x20].copy_from_slice(&0xAu64.to_le_bytes());
+        h[0x38..0x3C].copy_from_slice(&ARM64_IMAGE_MAGIC.to_le_bytes());
+    }
+    let parsed = Arm64ImageHeader::parse(addr);
+    let expected = Arm64ImageHeader {
+        magic: ARM64_IMAGE_MAGIC,
+        text_offset: 0x8_0000,
+        image_size: 0x20_0000,
+        flags: 0xA,
+    };
+    // Load base 0x4800_0000: image spans 0x4808_0000..0x4828_0000
+    let fits = parsed.is_some_and(|h| {
+        h.entry_offset() == 0x8_0000
+            && h.fits(0x4800_0000, 0x4828_0000)
+            && !h.fits(0x4800_0000, 0x4827_F000)
+    });
+    unsafe { (*image).0[0x38] = 0 };
+    let bad_magic = Arm64ImageHeader::parse(addr);
+    if parsed == Some(expected) && fits && bad_magic.is_none() {
+        uart_puts(b"PASS\n");
+    } else {
+        uart_puts(b"FAIL\n");
+        return;
+    }
+
+    // Verify virtio devices for VM 1 land in DEVICES[1], not DEVICES[0]
+    uart_puts(b"[TEST] Checking attach_virtio_devices uses vm.id()... ");
+    let vm = Vm::new(1);
+    let vm0_virtio = DEVICES[0].snapshot().virtio.iter().flatten().count();
+    attach_virtio_devices(
+        &vm,
+        platform::VM1_VIRTIO_DISK_ADDR,
+        platform::VM1_VIRTIO_CDROM_ADDR,
+        platform::VM1_VIRTIO_DATA_DISK_ADDR,
+    );
+    let (blk_base, _) = platform::virtio_slot(0);
+    let (net_base, _) = platform::virtio_slot(1);
+    let (input_base, _) = platform::virtio_slot(platform::VIRTIO_SLOT_INPUT);
+    let (cdrom_base, _) = platform::virtio_slot(platform::VIRTIO_SLOT_CDROM);
+    let blk_id = DEVICES[1].handle_mmio(blk_base + 0x008, 0, 4, false);
+    let net_id = DEVICES[1].handle_mmio(net_base + 0x008, 0, 4, false);
+    let input_id = DEVICES[1].handle_mmio(input_base + 0x008, 0, 4, false);
+    let cdrom_id = DEVICES[1].handle_mmio(cdrom_base + 0x008, 0, 4, false);
+    let (data_base, _) = platform::virtio_slot(platform::VIRTIO_SLOT_DATA);
+    let data_id = DEVICES[1].handle_mmio(data_base + 0x008, 0, 4, false);
+    let (vsock_base, _) = platform::virtio_slot(platform::VIRTIO_SLOT_VSOCK);
+    let vsock_id = DEVICES[1].handle_mmio(vsock_base + 0x008, 0, 4, false);
+    let (balloon_base, _) = platform::virtio_slot(platform::VIRTIO_SLOT_BALLOON);
+    let balloon_id = DEVICES[1].handle_mmio(balloon_base + 0x008, 0, 4, false);
+    let vm0_after = DEVICES[0].snapshot().virtio.iter().flatten().count();
+    DEVICES[1].reset();
+    hypervisor::vswitch::vswitch_reset();
+    if blk_id == Some(2)
+        && net_id == Some(1)
+        && input_id == Some(18)
+        && cdrom_id == Some(2)
+        && data_id == Some(2)
+        && vsock_id == Some(19)
+        && balloon_id == Some(5)
+        && vm0_after == vm0_virtio
+    {
+        uart_puts(b"PASS\n");
+    } else {
+        uart_puts(b"FAIL\n");
+        return;
+    }
+
+    // Verify the platform devices for VM 1 land in DEVICES[1]
+    uart_puts(b"[TEST] Checking attach_platform_devices uses vm.id()... ");
+    let vm = Vm::new(1);
+    attach_platform_devices(&vm);
+    let sensor_id = DEVICES[1].handle_mmio(SENSOR_BASE, 0, 4, false);
+    let stats_id = DEVICES[1].handle_mmio(SCHED_STATS_BASE, 0, 4, false);
+    DEVICES[1].reset();
+    if sensor_id == Some(u32::from_be_bytes(*b"SENS") as u64)
+        && stats_id == Some(u32::from_be_bytes(*b"SCHD") as u64)
+    {
+        uart_puts(b"PASS\n");
+    } else {
+        uart_puts(b"FAIL\n");
+        return;
+    }
+
     uart_puts(b"[TEST] Guest Loader Test PASSED\n\n");
 }
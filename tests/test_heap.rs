@@ -1,7 +1,13 @@
 //! Global heap tests
 
+use hypervisor::arch::aarch64::mm::mmu::{DynamicIdentityMapper, MemoryAttribute};
+use hypervisor::mm::heap;
 use hypervisor::uart_puts;
 
+/// Alloc/free rounds in the churn test; 4 pages per round is 128MB in
+/// total, far more than the 16MB heap
+const CHURN_ROUNDS: usize = 8192;
+
 pub fn run_heap_test() {
     uart_puts(b"\n========================================\n");
     uart_puts(b"  Global Heap Test\n");
@@ -55,7 +61,65 @@ pub fn run_heap_test() {
     }
     uart_puts(b"[HEAP] Test 4 PASSED\n\n");
 
+    // Test 5: Freed pages are recycled, so alloc/free churn never runs out
+    uart_puts(b"[HEAP] Test 5: Alloc/free churn...\n");
+    let remaining_before = heap::remaining();
+    let allocated_before = heap::allocated();
+    for round in 0..CHURN_ROUNDS {
+        let mut pages = [0u64; 4];
+        for page in pages.iter_mut() {
+            match heap::alloc_page() {
+                Some(p) => *page = p,
+                None => {
+                    uart_puts(b"[HEAP] ERROR: Heap exhausted in round 0x");
+                    hypervisor::uart_put_hex(round as u64);
+                    uart_puts(b"\n");
+                    return;
+                }
+            }
+        }
+        // Recycled pages come back zeroed
+        if unsafe { core::ptr::read_volatile(pages[0] as *const u64) } != 0 {
+            uart_puts(b"[HEAP] ERROR: Recycled page not zeroed\n");
+            return;
+        }
+        for &page in pages.iter() {
+            unsafe {
+                core::ptr::write_volatile(page as *mut u64, 0xDEAD_BEEF);
+                heap::free_page(page);
+            }
+        }
+    }
+    if heap::allocated() != allocated_before || remaining_before - heap::remaining() > 4 * 4096 {
+        uart_puts(b"[HEAP] ERROR: Freed pages not recycled\n");
+        return;
+    }
+    uart_puts(b"[HEAP] Test 5 PASSED\n\n");
+
+    // Test 6: Dropping a DynamicIdentityMapper frees all its tables,
+    // including L3 tables from page mappings and block splits
+    uart_puts(b"[HEAP] Test 6: DynamicIdentityMapper drop frees tables...\n");
+    let allocated_before = heap::allocated();
+    let remaining_before = heap::remaining();
+    for _ in 0..64 {
+        let mut mapper = DynamicIdentityMapper::new();
+        let mapped = mapper
+            .map_region(0x4000_0000, 0x40_0000, MemoryAttribute::Normal)
+            .and(mapper.map_region(0x8000_0000, 0x20_0000, MemoryAttribute::Device))
+            .and(mapper.map_page(0x9000_0000, MemoryAttribute::Normal))
+            .and(mapper.unmap_4kb_page(0x4000_1000));
+        if mapped.is_err() || heap::allocated() == allocated_before {
+            uart_puts(b"[HEAP] ERROR: Mapper setup failed\n");
+            return;
+        }
+    }
+    if heap::allocated() != allocated_before || heap::remaining() < remaining_before - 8 * 4096 {
+        uart_puts(b"[HEAP] ERROR: Mapper tables leaked\n");
+        return;
+    }
+    uart_puts(b"[HEAP] Test 6 PASSED\n\n");
+
     uart_puts(b"========================================\n");
-    uart_puts(b"  Global Heap Test PASSED\n");
+    uart_puts(b"  Global Heap Test PASSED (6 assertions)\n");
     uart_puts(b"========================================\n\n");
 }
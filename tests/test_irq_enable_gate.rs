@@ -0,0 +1,134 @@
+//! Interrupt enable gating tests
+//!
+//! Queues SPIs/SGIs for a vCPU whose guest has them disabled in the
+//! VirtualGicd/VirtualGicr shadows and checks that inject_pending_spis/sgis
+//! keep them queued instead of placing them in a List Register, then
+//! delivers them once the shadow enable bit is set. A disabled queued SPI
+//! does not count as pending work for the idle check, and the guest's
+//! ISENABLER write that enables it kicks the target vCPU.
+
+use super::cleanup::Cleanup;
+use core::sync::atomic::{AtomicU32, Ordering};
+use hypervisor::arch::aarch64::hypervisor::exception::set_sgi_wake_hook;
+use hypervisor::arch::aarch64::peripherals::gicv3::GicV3VirtualInterface;
+use hypervisor::devices::gic::{VirtualGicd, VirtualGicr};
+use hypervisor::devices::Device;
+use hypervisor::global::{current_devices, current_vm_state};
+use hypervisor::platform::GICD_BASE;
+use hypervisor::uart_puts;
+use hypervisor::vcpu::Vcpu;
+use hypervisor::vm::{has_pending_irqs, inject_pending_sgis, inject_pending_spis};
+
+/// virtio-blk SPI
+const SPI_INTID: u32 = 48;
+const SGI_INTID: u32 = 1;
+/// vCPU the kicked SPI is queued for (not the current one)
+const REMOTE_VCPU: usize = 1;
+const GICD_ISENABLER1: u64 = 0x104;
+const GICD_ICENABLER1: u64 = 0x184;
+
+/// Targets of the last wake IPI
+static WAKE_TARGETS: AtomicU32 = AtomicU32::new(0);
+
+fn record_wake(targets: u16) {
+    WAKE_TARGETS.store(targets as u32, Ordering::Relaxed);
+}
+
+static RECORD_WAKE: fn(u16) = record_wake;
+
+fn lr_holds(vcpu: &Vcpu, intid: u32) -> bool {
+    vcpu.arch_state().ich_lr.iter().any(|&lr| {
+        GicV3VirtualInterface::get_lr_state(lr) != 0
+            && GicV3VirtualInterface::get_lr_intid(lr) == intid
+    })
+}
+
+pub fn run_irq_enable_gate_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  IRQ Enable Gating Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let devs = current_devices();
+    let vs = current_vm_state();
+    devs.reset();
+    devs.register_device(Device::Gicd(VirtualGicd::new()));
+    devs.register_device(Device::Gicr(VirtualGicr::new(1)));
+    devs.set_gic_trapped(true);
+    let mut vcpu = Vcpu::new(0, 0x4000_0000, 0);
+    let spi_bit = 1u32 << (SPI_INTID - 32);
+    let cleanup = Cleanup::new(|| {
+        vs.pending_spis[0].fetch_and(!spi_bit, Ordering::Relaxed);
+        vs.pending_sgis[0].fetch_and(!(1 << SGI_INTID), Ordering::Relaxed);
+        devs.reset();
+    });
+
+    // Test 1: SPI disabled in the GICD shadow stays queued
+    uart_puts(b"[IRQ-GATE] Test 1: disabled SPI not placed in LR...\n");
+    vs.pending_spis[0].fetch_or(spi_bit, Ordering::Relaxed);
+    inject_pending_spis(&mut vcpu);
+    if lr_holds(&vcpu, SPI_INTID) || vs.pending_spis[0].load(Ordering::Relaxed) & spi_bit == 0 {
+        uart_puts(b"[IRQ-GATE] FAILED: disabled SPI injected or dropped\n");
+        return;
+    }
+    uart_puts(b"[IRQ-GATE] Test 1 PASSED\n\n");
+
+    // Test 2: enabling INTID 48 (shadow only) delivers the queued SPI
+    uart_puts(b"[IRQ-GATE] Test 2: SPI delivered once enabled...\n");
+    let mut snap = devs.snapshot();
+    if let Some(gicd) = snap.gicd.as_mut() {
+        gicd.enabled[(SPI_INTID / 32) as usize] |= 1 << (SPI_INTID % 32);
+    }
+    devs.restore(&snap);
+    inject_pending_spis(&mut vcpu);
+    if !lr_holds(&vcpu, SPI_INTID) || vs.pending_spis[0].load(Ordering::Relaxed) & spi_bit != 0 {
+        uart_puts(b"[IRQ-GATE] FAILED: enabled SPI not placed in LR\n");
+        return;
+    }
+    uart_puts(b"[IRQ-GATE] Test 2 PASSED\n\n");
+
+    // Test 3: SGI gated by the vCPU's GICR_ISENABLER0 shadow
+    uart_puts(b"[IRQ-GATE] Test 3: SGI gated by GICR shadow...\n");
+    vs.pending_sgis[0].fetch_or(1 << SGI_INTID, Ordering::Relaxed);
+    inject_pending_sgis(&mut vcpu);
+    let held = !lr_holds(&vcpu, SGI_INTID)
+        && vs.pending_sgis[0].load(Ordering::Relaxed) & (1 << SGI_INTID) != 0;
+    let mut snap = devs.snapshot();
+    if let Some(gicr) = snap.gicr.as_mut() {
+        gicr[0].enabled |= 1 << SGI_INTID;
+    }
+    devs.restore(&snap);
+    inject_pending_sgis(&mut vcpu);
+    let delivered = lr_holds(&vcpu, SGI_INTID);
+    drop(cleanup);
+    if !held || !delivered {
+        uart_puts(b"[IRQ-GATE] FAILED: SGI not gated by GICR enable\n");
+        return;
+    }
+    uart_puts(b"[IRQ-GATE] Test 3 PASSED\n\n");
+
+    // Test 4: a disabled queued SPI is not work for the idle check; the
+    // guest's ISENABLER write kicks the vCPU it is queued for
+    uart_puts(b"[IRQ-GATE] Test 4: ISENABLER write kicks the target...\n");
+    devs.register_device(Device::Gicd(VirtualGicd::new()));
+    devs.set_gic_trapped(true);
+    vs.pending_spis[REMOTE_VCPU].fetch_or(spi_bit, Ordering::Relaxed);
+    WAKE_TARGETS.store(0, Ordering::Relaxed);
+    set_sgi_wake_hook(Some(&RECORD_WAKE));
+    let idle_while_disabled = !has_pending_irqs(REMOTE_VCPU);
+    devs.handle_mmio(GICD_BASE + GICD_ISENABLER1, spi_bit as u64, 4, true);
+    let kicked = WAKE_TARGETS.load(Ordering::Relaxed) == 1 << REMOTE_VCPU;
+    let pending_once_enabled = has_pending_irqs(REMOTE_VCPU);
+    devs.handle_mmio(GICD_BASE + GICD_ICENABLER1, spi_bit as u64, 4, true);
+    set_sgi_wake_hook(None);
+    vs.pending_spis[REMOTE_VCPU].fetch_and(!spi_bit, Ordering::Relaxed);
+    devs.reset();
+    if !idle_while_disabled || !kicked || !pending_once_enabled {
+        uart_puts(b"[IRQ-GATE] FAILED: unmasking a queued SPI did not wake its vCPU\n");
+        return;
+    }
+    uart_puts(b"[IRQ-GATE] Test 4 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  IRQ Enable Gating Test PASSED (4 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
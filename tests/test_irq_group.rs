@@ -0,0 +1,123 @@
+//! Interrupt group tests
+//!
+//! Configures interrupt groups through trapped GICD_IGROUPR / GICR_IGROUPR0
+//! writes and checks that inject_pending_spis/sgis set the List Register
+//! group bit only for Group 1 interrupts, so Group 0 ones reach the guest
+//! as vFIQs.
+
+use super::cleanup::Cleanup;
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::defs::LR_GROUP1_BIT;
+use hypervisor::arch::aarch64::peripherals::gicv3::GicV3VirtualInterface;
+use hypervisor::devices::gic::{VirtualGicd, VirtualGicr};
+use hypervisor::devices::Device;
+use hypervisor::dtb::{gicr_sgi_base, platform_info};
+use hypervisor::global::{current_devices, current_vm_state};
+use hypervisor::uart_puts;
+use hypervisor::vcpu::Vcpu;
+use hypervisor::vm::{inject_pending_sgis, inject_pending_spis};
+
+/// virtio-blk SPI
+const SPI_INTID: u32 = 48;
+const SGI_INTID: u32 = 1;
+const GICD_IGROUPR: u64 = 0x080;
+const GICD_ISENABLER: u64 = 0x100;
+const GICR_IGROUPR0: u64 = 0x080;
+const GICR_ISENABLER0: u64 = 0x100;
+
+/// List Register holding `intid`, if any.
+fn lr_for(vcpu: &Vcpu, intid: u32) -> Option<u64> {
+    vcpu.arch_state().ich_lr.iter().copied().find(|&lr| {
+        GicV3VirtualInterface::get_lr_state(lr) != 0
+            && GicV3VirtualInterface::get_lr_intid(lr) == intid
+    })
+}
+
+/// Queue SPI_INTID on a fresh vCPU 0 and return the LR it was placed in.
+fn inject_spi_lr() -> Option<u64> {
+    let mut vcpu = Vcpu::new(0, 0x4000_0000, 0);
+    current_vm_state().pending_spis[0].fetch_or(1 << (SPI_INTID - 32), Ordering::Relaxed);
+    inject_pending_spis(&mut vcpu);
+    lr_for(&vcpu, SPI_INTID)
+}
+
+pub fn run_irq_group_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  IRQ Group Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let devs = current_devices();
+    let vs = current_vm_state();
+    devs.reset();
+    devs.register_device(Device::Gicd(VirtualGicd::new()));
+    devs.register_device(Device::Gicr(VirtualGicr::new(1)));
+    devs.set_gic_trapped(true);
+    let gicd = platform_info().gicd_base;
+    let spi_reg = 4 * (SPI_INTID / 32) as u64;
+    let spi_mask = 1u64 << (SPI_INTID % 32);
+    devs.handle_mmio(gicd + GICD_ISENABLER + spi_reg, spi_mask, 4, true);
+    let cleanup = Cleanup::new(|| {
+        vs.pending_spis[0].fetch_and(!(1 << (SPI_INTID - 32)), Ordering::Relaxed);
+        vs.pending_sgis[0].fetch_and(!(1 << SGI_INTID), Ordering::Relaxed);
+        devs.reset();
+    });
+
+    // Test 1: an SPI left in Group 0 is injected with the group bit clear
+    uart_puts(b"[IRQ-GROUP] Test 1: Group 0 SPI injected as Group 0...\n");
+    devs.handle_mmio(
+        gicd + GICD_IGROUPR + spi_reg,
+        !spi_mask & 0xFFFF_FFFF,
+        4,
+        true,
+    );
+    match inject_spi_lr() {
+        Some(lr) if lr & LR_GROUP1_BIT == 0 => {}
+        _ => {
+            uart_puts(b"[IRQ-GROUP] FAILED: Group 0 SPI has LR group bit set\n");
+            return;
+        }
+    }
+    uart_puts(b"[IRQ-GROUP] Test 1 PASSED\n\n");
+
+    // Test 2: moving it to Group 1 sets the group bit
+    uart_puts(b"[IRQ-GROUP] Test 2: Group 1 SPI injected as Group 1...\n");
+    devs.handle_mmio(gicd + GICD_IGROUPR + spi_reg, 0xFFFF_FFFF, 4, true);
+    match inject_spi_lr() {
+        Some(lr) if lr & LR_GROUP1_BIT != 0 => {}
+        _ => {
+            uart_puts(b"[IRQ-GROUP] FAILED: Group 1 SPI has LR group bit clear\n");
+            return;
+        }
+    }
+    uart_puts(b"[IRQ-GROUP] Test 2 PASSED\n\n");
+
+    // Test 3: SGIs follow the vCPU's GICR_IGROUPR0
+    uart_puts(b"[IRQ-GROUP] Test 3: SGI group from GICR_IGROUPR0...\n");
+    let sgi_base = gicr_sgi_base(0);
+    devs.handle_mmio(sgi_base + GICR_ISENABLER0, 1 << SGI_INTID, 4, true);
+    let mut lrs = [None; 2];
+    for (group1, lr) in lrs.iter_mut().enumerate() {
+        let mut vcpu = Vcpu::new(0, 0x4000_0000, 0);
+        devs.handle_mmio(
+            sgi_base + GICR_IGROUPR0,
+            (group1 as u64) << SGI_INTID,
+            4,
+            true,
+        );
+        vs.pending_sgis[0].fetch_or(1 << SGI_INTID, Ordering::Relaxed);
+        inject_pending_sgis(&mut vcpu);
+        *lr = lr_for(&vcpu, SGI_INTID);
+    }
+    drop(cleanup);
+    let group0_ok = lrs[0].is_some_and(|lr| lr & LR_GROUP1_BIT == 0);
+    let group1_ok = lrs[1].is_some_and(|lr| lr & LR_GROUP1_BIT != 0);
+    if !group0_ok || !group1_ok {
+        uart_puts(b"[IRQ-GROUP] FAILED: SGI LR group bit does not follow IGROUPR0\n");
+        return;
+    }
+    uart_puts(b"[IRQ-GROUP] Test 3 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  IRQ Group Test PASSED (3 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,121 @@
+//! VM lifecycle event channel tests
+//!
+//! Drives VM 1 through its state transitions (including one real guest
+//! entry), the PSCI SYSTEM_OFF path and an exception-storm termination,
+//! and checks `LIFECYCLE` yields the matching events in order. Also checks
+//! that a full channel drops its oldest events.
+
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::hypervisor::exception::{handle_psci, terminate_current_vm};
+use hypervisor::arch::aarch64::regs::VcpuContext;
+use hypervisor::global::{vm_state, LifecycleEvent, LifecycleState, CURRENT_VM_ID, LIFECYCLE};
+use hypervisor::uart_puts;
+use hypervisor::vm::Vm;
+
+const VM_ID: usize = 1;
+const PSCI_SYSTEM_OFF: u64 = 0x8400_0008;
+
+#[repr(C, align(4096))]
+struct ExitGuest {
+    code: [u32; 4],
+}
+
+static EXIT_GUEST: ExitGuest = ExitGuest {
+    code: [
+        0xd2800020, // mov x0, #1 (exit hypercall)
+        0xd4000002, // hvc #0
+        0x14000000, // b .
+        0x00000000, // padding
+    ],
+};
+
+fn drain() {
+    while LIFECYCLE.pop().is_some() {}
+}
+
+/// Pop the next event and compare it to (`VM_ID`, `state`).
+fn expect(state: LifecycleState) -> bool {
+    LIFECYCLE.pop()
+        == Some(LifecycleEvent {
+            vm_id: VM_ID,
+            state,
+        })
+}
+
+pub fn run_lifecycle_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  VM Lifecycle Channel Test\n");
+    uart_puts(b"========================================\n\n");
+
+    // Test 1: Created -> Ready -> Running -> Ready -> Stopped, in order,
+    // all tagged with the VM's ID
+    uart_puts(b"[LIFECYCLE] Test 1: state transitions reported in order...\n");
+    drain();
+    let entry = &EXIT_GUEST.code as *const _ as u64;
+    let mut vm = Vm::new(VM_ID);
+    vm.init_memory(entry & !(2 * 1024 * 1024 - 1), 4 * 1024 * 1024);
+    let created = vm.create_vcpu(0).map(|vcpu| {
+        vcpu.context_mut().pc = entry;
+        vcpu.context_mut().sp = entry + 0x10000;
+    });
+    let ran = created.is_ok() && vm.run().is_ok();
+    vm.stop();
+    if !ran
+        || !expect(LifecycleState::Created)
+        || !expect(LifecycleState::Ready)
+        || !expect(LifecycleState::Running)
+        || !expect(LifecycleState::Ready)
+        || !expect(LifecycleState::Stopped)
+        || LIFECYCLE.pop().is_some()
+    {
+        drain();
+        uart_puts(b"[LIFECYCLE] FAILED: transitions missing or out of order\n");
+        return;
+    }
+    uart_puts(b"[LIFECYCLE] Test 1 PASSED\n\n");
+
+    // Test 2: SYSTEM_OFF reports ShutDown, an exception-storm termination
+    // reports Crashed, both for the VM that was current
+    uart_puts(b"[LIFECYCLE] Test 2: shutdown and crash paths...\n");
+    let vs = vm_state(VM_ID);
+    let prev_vm = CURRENT_VM_ID.swap(VM_ID, Ordering::Relaxed);
+    let prev_vcpu = vs.current_vcpu_id.swap(0, Ordering::Relaxed);
+    let mut ctx = VcpuContext::default();
+    ctx.gp_regs.x0 = PSCI_SYSTEM_OFF;
+    handle_psci(&mut ctx, PSCI_SYSTEM_OFF);
+    terminate_current_vm();
+    CURRENT_VM_ID.store(prev_vm, Ordering::Relaxed);
+    vs.current_vcpu_id.store(prev_vcpu, Ordering::Relaxed);
+    let ok = expect(LifecycleState::ShutDown) && expect(LifecycleState::Crashed);
+    let _fresh = Vm::new(VM_ID);
+    for flag in vs.terminal_exit.iter() {
+        flag.store(false, Ordering::Relaxed);
+    }
+    drain();
+    if !ok {
+        uart_puts(b"[LIFECYCLE] FAILED: ShutDown/Crashed not reported\n");
+        return;
+    }
+    uart_puts(b"[LIFECYCLE] Test 2 PASSED\n\n");
+
+    // Test 3: overflowing the ring drops the oldest events and counts them
+    uart_puts(b"[LIFECYCLE] Test 3: full channel drops oldest...\n");
+    let dropped_before = LIFECYCLE.dropped();
+    for vm_id in 0..40 {
+        LIFECYCLE.push(vm_id, LifecycleState::Ready);
+    }
+    let first = LIFECYCLE.pop().map(|e| e.vm_id);
+    let mut remaining = 0;
+    while LIFECYCLE.pop().is_some() {
+        remaining += 1;
+    }
+    if first != Some(8) || remaining != 31 || LIFECYCLE.dropped() - dropped_before != 8 {
+        uart_puts(b"[LIFECYCLE] FAILED: overflow did not drop the oldest events\n");
+        return;
+    }
+    uart_puts(b"[LIFECYCLE] Test 3 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  VM Lifecycle Channel Test PASSED (3 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
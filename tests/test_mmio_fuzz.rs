@@ -0,0 +1,117 @@
+//! MMIO dispatch fuzz test
+//!
+//! Drives DeviceManager::handle_mmio with pseudo-random (addr, value, size,
+//! is_write) tuples and checks every call returns a well-formed result
+//! instead of panicking or touching unmapped memory.
+
+use hypervisor::arch::aarch64::hypervisor::exception::is_fetchable_guest_pc;
+use hypervisor::devices::gic::VirtualGicr;
+use hypervisor::devices::pl031::VirtualPl031;
+use hypervisor::devices::{Device, DeviceManager, MmioDevice};
+use hypervisor::platform::{GUEST_RAM_BASE, LINUX_MEM_SIZE};
+use hypervisor::uart_puts;
+
+const FUZZ_ITERATIONS: usize = 4096;
+
+/// Minimal xorshift64 PRNG — deterministic so failures are reproducible.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+}
+
+pub fn run_mmio_fuzz_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  MMIO Dispatch Fuzz Test\n");
+    uart_puts(b"========================================\n\n");
+
+    // Only devices without physical write-through side effects are fuzzed
+    // (GICD forwards writes to the real distributor, UART prints).
+    let rtc = VirtualPl031::new();
+    let gicr = VirtualGicr::new(4);
+    let bases = [rtc.base_address(), gicr.base_address(), 0, !0xFFF];
+    let mut dm = DeviceManager::new();
+    dm.register_device(Device::Pl031(rtc));
+    dm.register_device(Device::Gicr(gicr));
+
+    // Test 1: Invalid widths and misaligned accesses are rejected
+    uart_puts(b"[FUZZ] Test 1: Invalid size / alignment rejected...\n");
+    for &size in &[0u8, 3, 5, 7, 16, 255] {
+        if dm.handle_mmio(bases[0], 0, size, false).is_some() {
+            uart_puts(b"[FUZZ] FAILED: invalid size read returned Some\n");
+            return;
+        }
+    }
+    if dm.handle_mmio(bases[0] + 2, 0, 4, false).is_some() {
+        uart_puts(b"[FUZZ] FAILED: misaligned read returned Some\n");
+        return;
+    }
+    uart_puts(b"[FUZZ] Test 1 PASSED\n\n");
+
+    // Test 2: Accesses at the top of the address space don't overflow
+    uart_puts(b"[FUZZ] Test 2: Address-space edges...\n");
+    if dm.handle_mmio(!0x7, 0, 8, false) != Some(0) {
+        uart_puts(b"[FUZZ] FAILED: top-of-space read should miss with Some(0)\n");
+        return;
+    }
+    if dm.handle_mmio(0, 0, 1, false) != Some(0) {
+        uart_puts(b"[FUZZ] FAILED: address 0 read should miss with Some(0)\n");
+        return;
+    }
+    uart_puts(b"[FUZZ] Test 2 PASSED\n\n");
+
+    // Test 3: Random accesses return well-formed results
+    uart_puts(b"[FUZZ] Test 3: Random accesses...\n");
+    let mut rng = XorShift(0x9E37_79B9_7F4A_7C15);
+    for _ in 0..FUZZ_ITERATIONS {
+        let r = rng.next();
+        // Bias addresses into device windows so handlers are exercised
+        let base = bases[(r & 0x3) as usize];
+        let addr = if (r >> 2) & 0x7 == 0 {
+            rng.next()
+        } else {
+            base.wrapping_add(rng.next() & 0x3_FFFF)
+        };
+        let size = [1u8, 2, 4, 8, 0, 3, 16, 6][((r >> 5) & 0x7) as usize];
+        let value = rng.next();
+        let is_write = (r >> 8) & 1 == 1;
+
+        let valid = matches!(size, 1 | 2 | 4 | 8) && addr % size as u64 == 0;
+        let result = dm.handle_mmio(addr, value, size, is_write);
+        let ok = match (is_write, valid, result) {
+            (true, _, None) => true,
+            (false, false, None) => true,
+            (false, true, Some(v)) => size == 8 || v >> (size as u32 * 8) == 0,
+            _ => false,
+        };
+        if !ok {
+            uart_puts(b"[FUZZ] FAILED: malformed result\n");
+            return;
+        }
+    }
+    uart_puts(b"[FUZZ] Test 3 PASSED\n\n");
+
+    // Test 4: Instruction fetch guard rejects unsafe PCs
+    uart_puts(b"[FUZZ] Test 4: Instruction fetch guard...\n");
+    if !is_fetchable_guest_pc(GUEST_RAM_BASE)
+        || is_fetchable_guest_pc(GUEST_RAM_BASE + 2)
+        || is_fetchable_guest_pc(GUEST_RAM_BASE + LINUX_MEM_SIZE)
+        || is_fetchable_guest_pc(0x0900_0000)
+        || is_fetchable_guest_pc(0xFFFF_8000_0000_0000)
+    {
+        uart_puts(b"[FUZZ] FAILED: fetch guard accepted unsafe PC\n");
+        return;
+    }
+    uart_puts(b"[FUZZ] Test 4 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  MMIO Dispatch Fuzz Test PASSED (4 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,107 @@
+//! Strict unmapped-MMIO policy tests
+//!
+//! Switches the current VM's device manager between the lenient and strict
+//! policies and checks that, under strict mode, an access to an address no
+//! device claims is reflected to the guest as a synchronous external abort
+//! instead of reading back as zero.
+
+use hypervisor::arch::aarch64::defs::{EC_DABT_SAME, ESR_EC_MASK, ESR_EC_SHIFT, SPSR_EL1H};
+use hypervisor::arch::aarch64::hypervisor::exception::reflect_unmapped_mmio;
+use hypervisor::arch::aarch64::regs::{SystemRegs, VcpuContext};
+use hypervisor::devices::UnmappedMmioPolicy;
+use hypervisor::global::current_devices;
+use hypervisor::uart_puts;
+
+/// No device lives here
+const UNMAPPED_ADDR: u64 = 0x1234_0000;
+const GUEST_PC: u64 = 0x4008_0000;
+/// ESR_EL2 for a 4-byte load data abort (ISV=0)
+const DABT_READ_ESR: u64 = 0x24 << 26;
+
+fn read_el1_fault_regs() -> (u64, u64, u64) {
+    let (esr, far, vbar): (u64, u64, u64);
+    unsafe {
+        core::arch::asm!("mrs {}, esr_el1", out(reg) esr);
+        core::arch::asm!("mrs {}, far_el1", out(reg) far);
+        core::arch::asm!("mrs {}, vbar_el1", out(reg) vbar);
+    }
+    (esr, far, vbar)
+}
+
+fn write_el1_fault_regs(esr: u64, far: u64) {
+    unsafe {
+        core::arch::asm!("msr esr_el1, {}", in(reg) esr);
+        core::arch::asm!("msr far_el1, {}", in(reg) far);
+    }
+}
+
+fn guest_ctx() -> VcpuContext {
+    VcpuContext {
+        pc: GUEST_PC,
+        spsr_el2: SPSR_EL1H,
+        sys_regs: SystemRegs {
+            far_el2: UNMAPPED_ADDR,
+            esr_el2: DABT_READ_ESR,
+            ..SystemRegs::default()
+        },
+        ..VcpuContext::default()
+    }
+}
+
+pub fn run_mmio_strict_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Strict Unmapped MMIO Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let devs = current_devices();
+    let (saved_esr, saved_far, _) = read_el1_fault_regs();
+
+    // Test 1: lenient (default) reads unmapped addresses as zero
+    uart_puts(b"[STRICT] Test 1: lenient reads zero...\n");
+    let mut ctx = guest_ctx();
+    if devs.handle_mmio(UNMAPPED_ADDR, 0, 4, false) != Some(0)
+        || reflect_unmapped_mmio(&mut ctx, UNMAPPED_ADDR, DABT_READ_ESR)
+        || ctx.pc != GUEST_PC
+    {
+        uart_puts(b"[STRICT] FAILED: lenient policy did not read zero\n");
+        return;
+    }
+    uart_puts(b"[STRICT] Test 1 PASSED\n\n");
+
+    // Test 2: strict read of an unmapped address fails
+    uart_puts(b"[STRICT] Test 2: strict read is not a silent zero...\n");
+    devs.set_unmapped_mmio_policy(UnmappedMmioPolicy::Strict);
+    let read = devs.handle_mmio(UNMAPPED_ADDR, 0, 4, false);
+    let faults = devs.unmapped_mmio_faults(UNMAPPED_ADDR);
+    if read.is_some() || !faults {
+        devs.set_unmapped_mmio_policy(UnmappedMmioPolicy::Lenient);
+        uart_puts(b"[STRICT] FAILED: strict read returned a value\n");
+        return;
+    }
+    uart_puts(b"[STRICT] Test 2 PASSED\n\n");
+
+    // Test 3: the abort is reflected to the guest's EL1 sync vector
+    uart_puts(b"[STRICT] Test 3: external abort reflected...\n");
+    let mut ctx = guest_ctx();
+    let injected = reflect_unmapped_mmio(&mut ctx, UNMAPPED_ADDR, DABT_READ_ESR);
+    let (esr_el1, far_el1, vbar) = read_el1_fault_regs();
+    write_el1_fault_regs(saved_esr, saved_far);
+    devs.set_unmapped_mmio_policy(UnmappedMmioPolicy::Lenient);
+    if !injected
+        || ctx.pc != vbar + 0x200
+        || ctx.sys_regs.elr_el1 != GUEST_PC
+        || ctx.sys_regs.spsr_el1 != SPSR_EL1H
+        || (esr_el1 >> ESR_EC_SHIFT) & ESR_EC_MASK != EC_DABT_SAME
+        || esr_el1 & 0x3F != 0x10 // DFSC: synchronous external abort
+        || esr_el1 & (1 << 6) != 0 // WnR: read
+        || far_el1 != UNMAPPED_ADDR
+    {
+        uart_puts(b"[STRICT] FAILED: external abort not reflected\n");
+        return;
+    }
+    uart_puts(b"[STRICT] Test 3 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Strict Unmapped MMIO Test PASSED (3 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,89 @@
+//! MMIO trace tests
+//!
+//! Routes GICD and virtio-mmio accesses through a local DeviceManager with
+//! tracing enabled and checks the decoded register names in the trace.
+
+use hypervisor::devices::gic::VirtualGicd;
+use hypervisor::devices::trace::MMIO_TRACE_LEN;
+use hypervisor::devices::virtio::input::VirtioInput;
+use hypervisor::devices::virtio::mmio::VirtioMmioTransport;
+use hypervisor::devices::{Device, DeviceManager};
+use hypervisor::uart_puts;
+
+const VIRTIO_BASE: u64 = 0x0a00_0400;
+
+pub fn run_mmio_trace_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  MMIO Trace Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let gicd_base = hypervisor::dtb::platform_info().gicd_base;
+    let mut dm = DeviceManager::new();
+    dm.register_device(Device::Gicd(VirtualGicd::new()));
+    dm.register_device(Device::VirtioInput(VirtioMmioTransport::new(
+        VIRTIO_BASE,
+        VirtioInput::new(),
+        50,
+    )));
+
+    // Test 1: nothing recorded while disabled
+    uart_puts(b"[TRACE] Test 1: disabled by default...\n");
+    dm.handle_mmio(gicd_base, 0, 4, false);
+    if !dm.trace().is_empty() {
+        uart_puts(b"[TRACE] FAILED: trace recorded while disabled\n");
+        return;
+    }
+    uart_puts(b"[TRACE] Test 1 PASSED\n\n");
+
+    // Test 2: GICD register names (reads only: GICD writes reach hardware)
+    uart_puts(b"[TRACE] Test 2: GICD decode...\n");
+    dm.set_trace(true);
+    dm.handle_mmio(gicd_base + 0x100, 0, 4, false); // ISENABLER0
+    dm.handle_mmio(gicd_base + 0x6108, 0, 8, false); // IROUTER[1]
+    let (e0, e1) = match (dm.trace().get(0), dm.trace().get(1)) {
+        (Some(a), Some(b)) => (*a, *b),
+        _ => {
+            uart_puts(b"[TRACE] FAILED: GICD accesses not recorded\n");
+            return;
+        }
+    };
+    if e0.reg != "ISENABLER" || e1.reg != "IROUTER" || e1.offset != 0x6108 || e1.is_write {
+        uart_puts(b"[TRACE] FAILED: GICD register names wrong\n");
+        return;
+    }
+    uart_puts(b"[TRACE] Test 2 PASSED\n\n");
+
+    // Test 3: virtio field names
+    uart_puts(b"[TRACE] Test 3: virtio decode...\n");
+    dm.set_trace(true); // restart
+    dm.handle_mmio(VIRTIO_BASE + 0x030, 1, 4, true); // QueueSel
+    dm.handle_mmio(VIRTIO_BASE + 0x050, 0, 4, true); // QueueNotify (queue not ready)
+    dm.handle_mmio(VIRTIO_BASE + 0x070, 0, 4, false); // Status
+    dm.handle_mmio(0x1234_0000, 0, 4, false); // no device
+    let names: [&str; 4] = core::array::from_fn(|i| dm.trace().get(i).map(|e| e.reg).unwrap_or(""));
+    if names != ["QueueSel", "QueueNotify", "Status", "unmapped"] {
+        uart_puts(b"[TRACE] FAILED: virtio field names wrong\n");
+        return;
+    }
+    dm.trace().dump();
+    uart_puts(b"[TRACE] Test 3 PASSED\n\n");
+
+    // Test 4: ring keeps the newest MMIO_TRACE_LEN entries
+    uart_puts(b"[TRACE] Test 4: ring wrap...\n");
+    for _ in 0..MMIO_TRACE_LEN {
+        dm.handle_mmio(gicd_base + 0x100, 0, 4, false);
+    }
+    dm.handle_mmio(VIRTIO_BASE + 0x070, 0, 4, false);
+    let last = dm.trace().get(MMIO_TRACE_LEN - 1).map(|e| e.reg);
+    let first = dm.trace().get(0).map(|e| e.reg);
+    if dm.trace().len() != MMIO_TRACE_LEN || last != Some("Status") || first != Some("ISENABLER") {
+        uart_puts(b"[TRACE] FAILED: ring did not keep newest entries\n");
+        return;
+    }
+    dm.set_trace(false);
+    uart_puts(b"[TRACE] Test 4 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  MMIO Trace Test PASSED (4 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
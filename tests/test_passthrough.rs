@@ -0,0 +1,289 @@
+//! Device interrupt passthrough tests
+//!
+//! Tests Vm::assign_device() phys→virt INTID mapping used by
+//! handle_irq_exception() for HW=1 injection, the physical GICD programming
+//! behind it, delivery of an assigned INTID, and teardown. Uses SPIs with no
+//! device behind them on QEMU virt, so delivery is driven through
+//! deliver_passthrough_irq(); every SPI enabled here is disabled again
+//! before the test returns. A level-triggered line held high is emulated by
+//! setting the SPI pending only while it is masked or active.
+
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::defs::{
+    IRQ_DEFAULT_PRIORITY, LR_HW_BIT, LR_PINTID_MASK, LR_PINTID_SHIFT, LR_STATE_SHIFT,
+};
+use hypervisor::arch::aarch64::hypervisor::exception::deliver_passthrough_irq;
+use hypervisor::arch::aarch64::peripherals::gicv3::GicV3VirtualInterface;
+use hypervisor::global::{
+    clear_passthrough_irqs, current_devices, current_vm_state, lookup_passthrough_irq,
+    register_passthrough_irq, MAX_PASSTHROUGH_IRQS,
+};
+use hypervisor::platform::{
+    GICD_BASE, GICD_ICFGR_OFF, GICD_ICPENDR_OFF, GICD_IGROUPR_OFF, GICD_IPRIORITYR_OFF,
+    GICD_IROUTER_OFF, GICD_ISACTIVER_OFF, GICD_ISENABLER_OFF, GICD_ISPENDR_OFF, UART_SPI_INTID,
+};
+use hypervisor::uart_puts;
+use hypervisor::vcpu::Vcpu;
+use hypervisor::vm::{inject_pending_spis, Vm};
+
+/// First physical SPI used to fill the table in Test 6
+const FILL_BASE: u32 = 150;
+const NUM_LRS: u32 = 4;
+
+/// Bit `intid` of the physical GICD bitmap at `off`.
+fn gicd_bit(off: u64, intid: u32) -> bool {
+    let reg = (GICD_BASE + off + (intid / 32) as u64 * 4) as *const u32;
+    unsafe { core::ptr::read_volatile(reg) & (1 << (intid % 32)) != 0 }
+}
+
+/// Set bit `intid` of the physical GICD write-1 bitmap at `off`.
+fn set_gicd_bit(off: u64, intid: u32) {
+    let reg = (GICD_BASE + off + (intid / 32) as u64 * 4) as *mut u32;
+    unsafe { core::ptr::write_volatile(reg, 1 << (intid % 32)) }
+}
+
+/// Configure physical SPI `intid` as level-sensitive (GICD_ICFGR 0b00).
+fn set_level_triggered(intid: u32) {
+    let reg = (GICD_BASE + GICD_ICFGR_OFF + (intid / 16) as u64 * 4) as *mut u32;
+    let edge = 0b10 << ((intid % 16) * 2);
+    unsafe { core::ptr::write_volatile(reg, core::ptr::read_volatile(reg) & !edge) }
+}
+
+fn fill_lrs() {
+    for i in 0..NUM_LRS {
+        GicV3VirtualInterface::write_lr(
+            i,
+            (GicV3VirtualInterface::LR_STATE_PENDING << LR_STATE_SHIFT) | (40 + i) as u64,
+        );
+    }
+}
+
+fn gicd_priority(intid: u32) -> u8 {
+    unsafe {
+        core::ptr::read_volatile((GICD_BASE + GICD_IPRIORITYR_OFF + intid as u64) as *const u8)
+    }
+}
+
+fn gicd_irouter(intid: u32) -> u64 {
+    let reg = (GICD_BASE + GICD_IROUTER_OFF + (intid as u64 - 32) * 8) as *const u64;
+    unsafe { core::ptr::read_volatile(reg) }
+}
+
+/// Take SPI `intid` off every vCPU's pending queue; true if it was queued.
+fn take_pending_spi(intid: u32) -> bool {
+    let bit = 1u32 << (intid - 32);
+    let mut queued = false;
+    for spis in current_vm_state().pending_spis.iter() {
+        queued |= spis.fetch_and(!bit, Ordering::AcqRel) & bit != 0;
+    }
+    queued
+}
+
+fn mpidr() -> u64 {
+    let val: u64;
+    unsafe {
+        core::arch::asm!("mrs {}, mpidr_el1", out(reg) val, options(nostack, nomem));
+    }
+    val
+}
+
+pub fn run_passthrough_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Interrupt Passthrough Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let mut vm = Vm::new(0);
+
+    // Test 1: Mismatched array lengths are rejected
+    uart_puts(b"[PT] Test 1: Length mismatch rejected...\n");
+    if vm.assign_device(&[140, 141], &[52]).is_ok() {
+        uart_puts(b"[PT] FAILED: mismatched arrays accepted\n");
+        return;
+    }
+    uart_puts(b"[PT] Test 1 PASSED\n\n");
+
+    // Test 2: Non-SPI and hypervisor-owned INTIDs are rejected
+    uart_puts(b"[PT] Test 2: SGI/PPI/UART SPI rejected...\n");
+    if vm.assign_device(&[27], &[27]).is_ok()
+        || vm.assign_device(&[UART_SPI_INTID], &[52]).is_ok()
+        || vm.assign_device(&[140], &[64]).is_ok()
+        || lookup_passthrough_irq(UART_SPI_INTID).is_some()
+    {
+        uart_puts(b"[PT] FAILED: hypervisor INTID accepted for passthrough\n");
+        clear_passthrough_irqs(0);
+        return;
+    }
+    uart_puts(b"[PT] Test 2 PASSED\n\n");
+
+    // Test 3: Both assigned INTIDs map to their virtual INTIDs
+    uart_puts(b"[PT] Test 3: Two INTIDs mapped...\n");
+    if vm.assign_device(&[140, 141], &[52, 53]).is_err() {
+        uart_puts(b"[PT] FAILED: assign_device returned error\n");
+        clear_passthrough_irqs(0);
+        return;
+    }
+    if lookup_passthrough_irq(140) != Some((0, 52)) || lookup_passthrough_irq(141) != Some((0, 53))
+    {
+        uart_puts(b"[PT] FAILED: phys INTID not mapped to guest INTID\n");
+        clear_passthrough_irqs(0);
+        return;
+    }
+    uart_puts(b"[PT] Test 3 PASSED\n\n");
+
+    // Test 4: the physical SPIs are enabled as Group 1, default priority,
+    // routed to this PE
+    uart_puts(b"[PT] Test 4: physical GICD programmed...\n");
+    let affinity = (mpidr() & 0x00FF_FFFF) | ((mpidr() >> 32) & 0xFF) << 32;
+    let programmed = [140, 141].iter().all(|&intid| {
+        gicd_bit(GICD_ISENABLER_OFF, intid)
+            && gicd_bit(GICD_IGROUPR_OFF, intid)
+            && gicd_priority(intid) == IRQ_DEFAULT_PRIORITY
+            && gicd_irouter(intid) == affinity
+    });
+    if !programmed {
+        uart_puts(b"[PT] FAILED: physical SPI not enabled/grouped/routed\n");
+        clear_passthrough_irqs(0);
+        return;
+    }
+    uart_puts(b"[PT] Test 4 PASSED\n\n");
+
+    // Test 5: another VM cannot take an assigned physical INTID
+    uart_puts(b"[PT] Test 5: INTID owned by another VM...\n");
+    if register_passthrough_irq(1, 140, 60).is_ok() || lookup_passthrough_irq(140) != Some((0, 52))
+    {
+        uart_puts(b"[PT] FAILED: VM 1 took VM 0's INTID\n");
+        clear_passthrough_irqs(0);
+        clear_passthrough_irqs(1);
+        return;
+    }
+    uart_puts(b"[PT] Test 5 PASSED\n\n");
+
+    // Test 6: a partial fill is rolled back
+    uart_puts(b"[PT] Test 6: full table rolls back...\n");
+    let mut filled = true;
+    for i in 0..(MAX_PASSTHROUGH_IRQS as u32 - 3) {
+        filled &= register_passthrough_irq(0, FILL_BASE + i, 54).is_ok();
+    }
+    // One free slot left: 142 fits, 143 does not
+    let full = vm.assign_device(&[142, 143], &[54, 55]).is_err();
+    let rolled_back = lookup_passthrough_irq(142).is_none()
+        && lookup_passthrough_irq(143).is_none()
+        && !gicd_bit(GICD_ISENABLER_OFF, 142);
+    if !filled || !full || !rolled_back {
+        uart_puts(b"[PT] FAILED: partial assignment left behind\n");
+        clear_passthrough_irqs(0);
+        return;
+    }
+    uart_puts(b"[PT] Test 6 PASSED\n\n");
+
+    // Test 7: Clearing the VM's mappings removes them and disables the
+    // physical SPIs
+    uart_puts(b"[PT] Test 7: clear_passthrough_irqs...\n");
+    clear_passthrough_irqs(0);
+    if lookup_passthrough_irq(140).is_some()
+        || lookup_passthrough_irq(141).is_some()
+        || lookup_passthrough_irq(FILL_BASE).is_some()
+        || gicd_bit(GICD_ISENABLER_OFF, 140)
+        || gicd_bit(GICD_ISENABLER_OFF, 141)
+    {
+        uart_puts(b"[PT] FAILED: mapping or enable survived clear\n");
+        return;
+    }
+    uart_puts(b"[PT] Test 7 PASSED\n\n");
+
+    // Test 8: VM teardown (Vm::new) drops the mappings too
+    uart_puts(b"[PT] Test 8: teardown drops mappings...\n");
+    if vm.assign_device(&[140], &[52]).is_err() {
+        uart_puts(b"[PT] FAILED: re-assign after clear\n");
+        clear_passthrough_irqs(0);
+        return;
+    }
+    let _vm = Vm::new(0);
+    if lookup_passthrough_irq(140).is_some() || gicd_bit(GICD_ISENABLER_OFF, 140) {
+        uart_puts(b"[PT] FAILED: mapping survived VM teardown\n");
+        clear_passthrough_irqs(0);
+        return;
+    }
+    uart_puts(b"[PT] Test 8 PASSED\n\n");
+
+    // Test 9: an assigned interrupt reaches the running VM as its guest
+    // INTID with HW=1; with every List Register busy it is queued instead
+    uart_puts(b"[PT] Test 9: interrupt delivered as guest INTID...\n");
+    let saved_lrs: [u64; NUM_LRS as usize] =
+        core::array::from_fn(|i| GicV3VirtualInterface::read_lr(i as u32));
+    for i in 0..NUM_LRS {
+        GicV3VirtualInterface::write_lr(i, 0);
+    }
+    take_pending_spi(52);
+    let assigned = vm.assign_device(&[140], &[52]).is_ok();
+    let (owner, virt) = lookup_passthrough_irq(140).unwrap_or((usize::MAX, 0));
+    let hw = deliver_passthrough_irq(owner, virt, 140);
+    let lr = GicV3VirtualInterface::read_lr(0);
+    let in_lr = GicV3VirtualInterface::get_lr_state(lr) == GicV3VirtualInterface::LR_STATE_PENDING
+        && GicV3VirtualInterface::get_lr_intid(lr) == 52
+        && lr & LR_HW_BIT != 0
+        && (lr >> LR_PINTID_SHIFT) & LR_PINTID_MASK == 140;
+    fill_lrs();
+    let full_hw = deliver_passthrough_irq(owner, virt, 140);
+    let queued = take_pending_spi(52);
+    for (i, lr) in saved_lrs.iter().enumerate() {
+        GicV3VirtualInterface::write_lr(i as u32, *lr);
+    }
+    clear_passthrough_irqs(0);
+    if !assigned || !hw || !in_lr {
+        uart_puts(b"[PT] FAILED: guest INTID not injected with HW=1\n");
+        return;
+    }
+    if full_hw || !queued {
+        uart_puts(b"[PT] FAILED: interrupt lost with List Registers full\n");
+        return;
+    }
+    uart_puts(b"[PT] Test 9 PASSED\n\n");
+
+    // Test 10: a deferred level-triggered INTID is masked, so the line
+    // still held high cannot fire again, until the owner's entry injects
+    // the queued SPI with HW=1 and hands the INTID back active + enabled
+    uart_puts(b"[PT] Test 10: level-triggered INTID parked until injected...\n");
+    let devs = current_devices();
+    let saved_gicd = devs.snapshot();
+    let assigned = vm.assign_device(&[140], &[52]).is_ok();
+    set_level_triggered(140);
+    fill_lrs();
+    take_pending_spi(52);
+    let hw = deliver_passthrough_irq(0, 52, 140);
+    let masked = !gicd_bit(GICD_ISENABLER_OFF, 140);
+    set_gicd_bit(GICD_ISPENDR_OFF, 140); // device keeps the line asserted
+    for (i, lr) in saved_lrs.iter().enumerate() {
+        GicV3VirtualInterface::write_lr(i as u32, *lr);
+    }
+    let mut snap = devs.snapshot();
+    if let Some(gicd) = snap.gicd.as_mut() {
+        gicd.enabled[1] |= 1 << (52 - 32);
+    }
+    devs.restore(&snap);
+    let mut vcpu = Vcpu::new(0, 0x4000_0000, 0);
+    inject_pending_spis(&mut vcpu);
+    let lr = vcpu.arch_state().ich_lr[0];
+    let linked = GicV3VirtualInterface::get_lr_intid(lr) == 52
+        && lr & LR_HW_BIT != 0
+        && (lr >> LR_PINTID_SHIFT) & LR_PINTID_MASK == 140;
+    let handed_back = gicd_bit(GICD_ISENABLER_OFF, 140) && gicd_bit(GICD_ISACTIVER_OFF, 140);
+    clear_passthrough_irqs(0);
+    set_gicd_bit(GICD_ICPENDR_OFF, 140);
+    let deactivated = !gicd_bit(GICD_ISACTIVER_OFF, 140);
+    take_pending_spi(52);
+    devs.restore(&saved_gicd);
+    if !assigned || hw || !masked {
+        uart_puts(b"[PT] FAILED: deferred INTID left unmasked\n");
+        return;
+    }
+    if !linked || !handed_back || !deactivated {
+        uart_puts(b"[PT] FAILED: queued SPI not injected with HW=1 and unmasked\n");
+        return;
+    }
+    uart_puts(b"[PT] Test 10 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Interrupt Passthrough Test PASSED (10 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,87 @@
+//! Pending virtual interrupt inspection tests
+//!
+//! Queues an SGI (through the ICC_SGI1R_EL1 trap handler) and an SPI for a
+//! vCPU other than the current one and leaves another interrupt in one of
+//! its saved List Registers, then checks that `Vcpu::pending_virtual_irqs()`
+//! reports all three and that `Vcpu::clear_pending_irqs()` drops them.
+
+use super::cleanup::Cleanup;
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::defs::LR_STATE_SHIFT;
+use hypervisor::arch::aarch64::hypervisor::exception::{handle_sgi_trap, set_sgi_wake_hook};
+use hypervisor::arch::aarch64::peripherals::gicv3::GicV3VirtualInterface;
+use hypervisor::global::{current_vcpu_id, current_vm_state};
+use hypervisor::uart_puts;
+use hypervisor::vcpu::Vcpu;
+
+const SGI_INTID: u32 = 3;
+/// virtio-blk SPI
+const SPI_INTID: u32 = 48;
+/// Virtual timer PPI, left in LR 1
+const LR_INTID: u32 = 27;
+
+/// Stands in for the wake SGI; the target vCPU is not running anywhere
+fn ignore_wake(_targets: u16) {}
+static IGNORE_WAKE: fn(u16) = ignore_wake;
+
+pub fn run_pending_irqs_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Pending Virtual IRQ Inspection Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let vs = current_vm_state();
+    let target = if current_vcpu_id() == 0 { 1 } else { 0 };
+    let spi_bit = 1u32 << (SPI_INTID - 32);
+    let mut vcpu = Vcpu::new(target, 0x4000_0000, 0);
+    let clear_queues = || {
+        vs.pending_sgis[target].fetch_and(!(1 << SGI_INTID), Ordering::Relaxed);
+        vs.pending_spis[target].fetch_and(!spi_bit, Ordering::Relaxed);
+    };
+    clear_queues();
+    let cleanup = Cleanup::new(clear_queues);
+
+    // Test 1: a vCPU with nothing queued reports nothing
+    uart_puts(b"[PENDING-IRQ] Test 1: idle vCPU has nothing pending...\n");
+    if !vcpu.pending_virtual_irqs().is_empty() {
+        uart_puts(b"[PENDING-IRQ] FAILED: idle vCPU reports pending IRQs\n");
+        return;
+    }
+    uart_puts(b"[PENDING-IRQ] Test 1 PASSED\n\n");
+
+    // Test 2: queued SGI/SPI and an LR-resident interrupt all reported
+    uart_puts(b"[PENDING-IRQ] Test 2: queued and LR interrupts reported...\n");
+    set_sgi_wake_hook(Some(&IGNORE_WAKE));
+    handle_sgi_trap(((SGI_INTID as u64) << 24) | (1 << target));
+    set_sgi_wake_hook(None);
+    vs.pending_spis[target].fetch_or(spi_bit, Ordering::Release);
+    vcpu.arch_state_mut().ich_lr[1] =
+        (GicV3VirtualInterface::LR_STATE_PENDING << LR_STATE_SHIFT) | LR_INTID as u64;
+    let pending = vcpu.pending_virtual_irqs();
+    let reported = pending.sgis == 1 << SGI_INTID
+        && pending.spis == spi_bit
+        && pending.lrs == [None, Some(LR_INTID), None, None]
+        && [SGI_INTID, SPI_INTID, LR_INTID]
+            .iter()
+            .all(|&intid| pending.contains(intid))
+        && !pending.contains(SGI_INTID + 1);
+    if !reported {
+        uart_puts(b"[PENDING-IRQ] FAILED: pending IRQs not reported\n");
+        return;
+    }
+    uart_puts(b"[PENDING-IRQ] Test 2 PASSED\n\n");
+
+    // Test 3: clear_pending_irqs() drops the queues and the LR
+    uart_puts(b"[PENDING-IRQ] Test 3: clear_pending_irqs() drops all...\n");
+    vcpu.clear_pending_irqs();
+    let cleared = vcpu.pending_virtual_irqs().is_empty();
+    drop(cleanup);
+    if !cleared {
+        uart_puts(b"[PENDING-IRQ] FAILED: pending IRQs survived the clear\n");
+        return;
+    }
+    uart_puts(b"[PENDING-IRQ] Test 3 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Pending Virtual IRQ Inspection Test PASSED (3 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
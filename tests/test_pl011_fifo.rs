@@ -0,0 +1,137 @@
+//! PL011 RX FIFO and interrupt tests
+//!
+//! Fills the emulated UART's RX FIFO and reads UARTFR to observe RXFF
+//! (and an overrun past it), checks that the RX interrupt asserts at the
+//! UARTIFLS watermark with the timeout interrupt below it, that reading
+//! UARTDR drains the FIFO in order down to RXFE, and that UARTICR clears
+//! raw status.
+
+use hypervisor::devices::pl011::{VirtualUart, RX_FIFO_DEPTH};
+use hypervisor::devices::MmioDevice;
+use hypervisor::global::{clear_spi, current_vm_id, current_vm_state};
+use hypervisor::uart_puts;
+
+const UARTDR: u64 = 0x000;
+const UARTRSR: u64 = 0x004;
+const UARTFR: u64 = 0x018;
+const UARTLCR_H: u64 = 0x02C;
+const UARTIMSC: u64 = 0x038;
+const UARTRIS: u64 = 0x03C;
+const UARTMIS: u64 = 0x040;
+const UARTICR: u64 = 0x044;
+
+const FR_RXFF: u64 = 1 << 6;
+const FR_RXFE: u64 = 1 << 4;
+const LCR_H_FEN: u64 = 1 << 4;
+const RSR_OE: u64 = 1 << 3;
+const INT_RX: u64 = 1 << 4;
+const INT_RT: u64 = 1 << 6;
+const INT_OE: u64 = 1 << 10;
+/// Default UARTIFLS: RX interrupt at half full
+const RX_WATERMARK: usize = RX_FIFO_DEPTH / 2;
+const UART_INTID: u32 = 33;
+
+/// UART with 8N1 framing and the FIFOs enabled.
+fn fifo_uart() -> VirtualUart {
+    let mut uart = VirtualUart::new();
+    uart.write(UARTLCR_H, 0x60 | LCR_H_FEN, 4);
+    uart
+}
+
+fn reg(uart: &mut VirtualUart, offset: u64) -> u64 {
+    uart.read(offset, 4).unwrap_or(0)
+}
+
+/// Whether SPI 33 is queued on any vCPU of the current VM.
+fn uart_spi_queued() -> bool {
+    let bit = 1u32 << (UART_INTID - 32);
+    current_vm_state()
+        .pending_spis
+        .iter()
+        .any(|p| p.load(core::sync::atomic::Ordering::Acquire) & bit != 0)
+}
+
+pub fn run_pl011_fifo_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  PL011 FIFO and Interrupt Test\n");
+    uart_puts(b"========================================\n\n");
+
+    clear_spi(current_vm_id(), UART_INTID);
+
+    // Test 1: 32 bytes fill the FIFO (RXFF); one more is an overrun. With
+    // FIFOs disabled a single byte fills the holding register.
+    uart_puts(b"[PL011-FIFO] Test 1: full FIFO reports RXFF...\n");
+    let mut uart = fifo_uart();
+    let empty = reg(&mut uart, UARTFR);
+    for i in 0..RX_FIFO_DEPTH {
+        uart.push_rx(i as u8);
+    }
+    let full = reg(&mut uart, UARTFR);
+    uart.push_rx(0xFF);
+    let overrun = reg(&mut uart, UARTRSR) & RSR_OE != 0 && reg(&mut uart, UARTRIS) & INT_OE != 0;
+    let mut holding = VirtualUart::new();
+    holding.push_rx(b'a');
+    let holding_full = reg(&mut holding, UARTFR) & FR_RXFF != 0;
+    if empty & (FR_RXFE | FR_RXFF) != FR_RXFE
+        || full & (FR_RXFE | FR_RXFF) != FR_RXFF
+        || uart.rx_level() != RX_FIFO_DEPTH
+        || !overrun
+        || !holding_full
+    {
+        uart_puts(b"[PL011-FIFO] FAILED: RXFF/overrun not reported\n");
+        return;
+    }
+    uart_puts(b"[PL011-FIFO] Test 1 PASSED\n\n");
+
+    // Test 2: draining returns the bytes in order and ends at RXFE, with
+    // the RX and timeout interrupts withdrawn on the way down
+    uart_puts(b"[PL011-FIFO] Test 2: drain to RXFE...\n");
+    let in_order = (0..RX_FIFO_DEPTH).all(|i| reg(&mut uart, UARTDR) == i as u64);
+    let drained = reg(&mut uart, UARTFR) & (FR_RXFE | FR_RXFF) == FR_RXFE
+        && reg(&mut uart, UARTRIS) & (INT_RX | INT_RT) == 0;
+    uart.write(UARTRSR, 0, 4);
+    if !in_order || !drained || reg(&mut uart, UARTRSR) != 0 {
+        uart_puts(b"[PL011-FIFO] FAILED: FIFO not drained to RXFE\n");
+        return;
+    }
+    uart_puts(b"[PL011-FIFO] Test 2 PASSED\n\n");
+
+    // Test 3: below the watermark only the timeout interrupt is raised;
+    // reaching it raises RX and, unmasked, the UART SPI
+    uart_puts(b"[PL011-FIFO] Test 3: RX interrupt at watermark...\n");
+    let mut uart = fifo_uart();
+    uart.write(UARTIMSC, INT_RX | INT_RT, 4);
+    for i in 0..RX_WATERMARK - 1 {
+        uart.push_rx(i as u8);
+    }
+    let below = reg(&mut uart, UARTRIS) & (INT_RX | INT_RT);
+    uart.push_rx(0);
+    let at = reg(&mut uart, UARTMIS) & (INT_RX | INT_RT);
+    let raised = uart.pending_irq() == Some(UART_INTID) && uart_spi_queued();
+    if below != INT_RT || at != INT_RX | INT_RT || !raised {
+        clear_spi(current_vm_id(), UART_INTID);
+        uart_puts(b"[PL011-FIFO] FAILED: RX interrupt not at watermark\n");
+        return;
+    }
+    uart_puts(b"[PL011-FIFO] Test 3 PASSED\n\n");
+
+    // Test 4: UARTICR clears raw status and withdraws the SPI; reading
+    // below the watermark then raises nothing new
+    uart_puts(b"[PL011-FIFO] Test 4: UARTICR clears status...\n");
+    uart.write(UARTICR, INT_RX | INT_RT, 4);
+    let cleared = reg(&mut uart, UARTRIS) & (INT_RX | INT_RT) == 0
+        && uart.pending_irq().is_none()
+        && !uart_spi_queued();
+    reg(&mut uart, UARTDR);
+    let quiet = reg(&mut uart, UARTMIS) == 0 && uart.rx_level() == RX_WATERMARK - 1;
+    clear_spi(current_vm_id(), UART_INTID);
+    if !cleared || !quiet {
+        uart_puts(b"[PL011-FIFO] FAILED: UARTICR did not clear status\n");
+        return;
+    }
+    uart_puts(b"[PL011-FIFO] Test 4 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  PL011 FIFO and Interrupt Test PASSED (4 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,108 @@
+//! Preemption timer disarm tests
+//!
+//! Runs scheduler iterations of a two-vCPU VM whose stub guest leaves
+//! through the exit hypercall instead of being preempted: once
+//! `run_one_iteration()` returns, the CNTHP watchdog armed for the slice
+//! must be off, so it cannot preempt the next, unrelated vCPU.
+
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::defs::{BLOCK_MASK_2MB, BLOCK_SIZE_2MB};
+use hypervisor::arch::aarch64::peripherals::timer;
+use hypervisor::global::vm_state;
+use hypervisor::uart_puts;
+use hypervisor::vm::Vm;
+
+const VM_ID: usize = 0;
+
+#[repr(C, align(4096))]
+struct ExitGuest {
+    code: [u32; 4],
+}
+
+static EXIT_GUEST: ExitGuest = ExitGuest {
+    code: [
+        0xd2800020, // mov x0, #1 (exit hypercall)
+        0xd4000002, // hvc #0
+        0x14000000, // b .
+        0x00000000, // padding
+    ],
+};
+
+pub fn run_preemption_disarm_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Preemption Timer Disarm Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let entry = &EXIT_GUEST.code as *const _ as u64;
+    let vs = vm_state(VM_ID);
+    let prev_online = vs.vcpu_online_mask.load(Ordering::Relaxed);
+    let prev_vcpu = vs.current_vcpu_id.load(Ordering::Relaxed);
+    let restore = || {
+        timer::disarm_preemption_timer();
+        vs.vcpu_online_mask.store(prev_online, Ordering::Relaxed);
+        vs.current_vcpu_id.store(prev_vcpu, Ordering::Relaxed);
+    };
+
+    let mut vm = Vm::new(VM_ID);
+    vm.init_memory(entry & !BLOCK_MASK_2MB, BLOCK_SIZE_2MB);
+    for id in 0..2 {
+        if vm.create_vcpu(id).is_err() {
+            restore();
+            uart_puts(b"[PREEMPT-DISARM] FAILED: create_vcpu\n");
+            return;
+        }
+    }
+    let reset = |vm: &mut Vm| {
+        for id in 0..2 {
+            if let Some(vcpu) = vm.vcpu_mut(id) {
+                vcpu.context_mut().pc = entry;
+            }
+        }
+    };
+
+    // Test 1: with two vCPUs online the slice is armed, and an exit
+    // hypercall (no preemption) still leaves CNTHP disarmed
+    uart_puts(b"[PREEMPT-DISARM] Test 1: hypercall exit disarms CNTHP...\n");
+    vs.vcpu_online_mask.store(0b11, Ordering::Release);
+    reset(&mut vm);
+    let done = vm.run_one_iteration();
+    if done || timer::preemption_timer_armed() || vs.preemption_exit.load(Ordering::Acquire) {
+        restore();
+        uart_puts(b"[PREEMPT-DISARM] FAILED: watchdog left armed after hypercall exit\n");
+        return;
+    }
+    uart_puts(b"[PREEMPT-DISARM] Test 1 PASSED\n\n");
+
+    // Test 2: the next iteration runs the other vCPU; its slice is armed
+    // afresh and again disarmed on exit
+    uart_puts(b"[PREEMPT-DISARM] Test 2: next vCPU's slice disarmed too...\n");
+    let first = vs.current_vcpu_id.load(Ordering::Acquire);
+    reset(&mut vm);
+    let done = vm.run_one_iteration();
+    let second = vs.current_vcpu_id.load(Ordering::Acquire);
+    if done || second == first || timer::preemption_timer_armed() {
+        restore();
+        uart_puts(b"[PREEMPT-DISARM] FAILED: second slice left watchdog armed\n");
+        return;
+    }
+    uart_puts(b"[PREEMPT-DISARM] Test 2 PASSED\n\n");
+
+    // Test 3: a single online vCPU runs unarmed, and a stale watchdog from
+    // an earlier slice is cleared by its exit
+    uart_puts(b"[PREEMPT-DISARM] Test 3: stale watchdog cleared...\n");
+    vs.vcpu_online_mask.store(0b01, Ordering::Release);
+    timer::arm_preemption_timer();
+    reset(&mut vm);
+    let done = vm.run_one_iteration();
+    let armed = timer::preemption_timer_armed();
+    restore();
+    if done || armed {
+        uart_puts(b"[PREEMPT-DISARM] FAILED: stale watchdog survived the iteration\n");
+        return;
+    }
+    uart_puts(b"[PREEMPT-DISARM] Test 3 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Preemption Timer Disarm Test PASSED (3 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
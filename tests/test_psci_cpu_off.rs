@@ -0,0 +1,103 @@
+//! PSCI CPU_OFF tests
+//!
+//! Builds a two-vCPU VM, has one vCPU call CPU_OFF through the PSCI handler
+//! and retires it the way the scheduler loop does, then checks that the
+//! other vCPU stays online and schedulable and the VM is only done once
+//! both vCPUs are off.
+
+use super::cleanup::Cleanup;
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::hypervisor::exception::handle_psci;
+use hypervisor::arch::aarch64::regs::VcpuContext;
+use hypervisor::global::{current_vcpu_id, vm_state, CURRENT_VM_ID};
+use hypervisor::uart_puts;
+use hypervisor::vm::Vm;
+
+const PSCI_CPU_OFF: u64 = 0x8400_0002;
+const PSCI_AFFINITY_INFO_64: u64 = 0xC400_0004;
+const VM_ID: usize = 1;
+
+/// Issue a PSCI call as vCPU `vcpu_id` of VM_ID; returns (continue, x0).
+fn psci_as(vcpu_id: usize, function_id: u64, x1: u64) -> (bool, u64) {
+    let vs = vm_state(VM_ID);
+    let prev_vcpu = vs.current_vcpu_id.swap(vcpu_id, Ordering::Relaxed);
+    let prev_vm = CURRENT_VM_ID.swap(VM_ID, Ordering::Relaxed);
+    let mut ctx = VcpuContext::default();
+    ctx.gp_regs.x0 = function_id;
+    ctx.gp_regs.x1 = x1;
+    let cont = handle_psci(&mut ctx, function_id);
+    CURRENT_VM_ID.store(prev_vm, Ordering::Relaxed);
+    vs.current_vcpu_id.store(prev_vcpu, Ordering::Relaxed);
+    (cont, ctx.gp_regs.x0)
+}
+
+pub fn run_psci_cpu_off_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  PSCI CPU_OFF Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let vs = vm_state(VM_ID);
+    let mut vm = Vm::new(VM_ID);
+    if vm.create_vcpu(0).is_err() || vm.create_vcpu(1).is_err() {
+        uart_puts(b"[CPU-OFF] FAILED: create_vcpu\n");
+        return;
+    }
+    vs.vcpu_online_mask.store(0b11, Ordering::Release);
+    // Multi-pCPU builds derive the caller from MPIDR, others from VM state
+    let prev_vm = CURRENT_VM_ID.swap(VM_ID, Ordering::Relaxed);
+    vs.current_vcpu_id.store(1, Ordering::Relaxed);
+    let caller = current_vcpu_id();
+    vs.current_vcpu_id.store(0, Ordering::Relaxed);
+    CURRENT_VM_ID.store(prev_vm, Ordering::Relaxed);
+    let other = 1 - caller;
+    let cleanup = Cleanup::new(|| {
+        for t in vs.terminal_exit.iter() {
+            t.store(false, Ordering::Relaxed);
+        }
+        let _fresh = Vm::new(VM_ID);
+        vs.vcpu_online_mask.store(0, Ordering::Relaxed);
+    });
+
+    // Test 1: CPU_OFF takes only the caller offline
+    uart_puts(b"[CPU-OFF] Test 1: caller offline, VM not stopped...\n");
+    let (cont, ret) = psci_as(caller, PSCI_CPU_OFF, 0);
+    if cont
+        || ret != 0
+        || vs.vcpu_online_mask.load(Ordering::Acquire) != 1 << other
+        || !vs.terminal_exit[caller].load(Ordering::Acquire)
+        || vs.vm_terminated.load(Ordering::Acquire)
+    {
+        uart_puts(b"[CPU-OFF] FAILED: CPU_OFF not scoped to caller\n");
+        return;
+    }
+    uart_puts(b"[CPU-OFF] Test 1 PASSED\n\n");
+
+    // Test 2: retiring the caller keeps the other vCPU running
+    uart_puts(b"[CPU-OFF] Test 2: other vCPU keeps running...\n");
+    vs.terminal_exit[caller].store(false, Ordering::Relaxed);
+    let done = vm.retire_vcpu(caller);
+    let next = vm.schedule();
+    let (_, caller_state) = psci_as(other, PSCI_AFFINITY_INFO_64, caller as u64);
+    let (_, other_state) = psci_as(other, PSCI_AFFINITY_INFO_64, other as u64);
+    if done || next != Some(other) || caller_state != 1 || other_state != 0 {
+        uart_puts(b"[CPU-OFF] FAILED: VM stopped with a vCPU online\n");
+        return;
+    }
+    uart_puts(b"[CPU-OFF] Test 2 PASSED\n\n");
+
+    // Test 3: the VM is done once the last vCPU is off
+    uart_puts(b"[CPU-OFF] Test 3: last CPU_OFF ends the VM...\n");
+    let done = vm.retire_vcpu(other);
+    let idle = vm.schedule().is_none();
+    let offline = vs.vcpu_online_mask.load(Ordering::Acquire) == 0;
+    drop(cleanup);
+    if !done || !idle || !offline {
+        uart_puts(b"[CPU-OFF] FAILED: VM not done after last CPU_OFF\n");
+        return;
+    }
+    uart_puts(b"[CPU-OFF] Test 3 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  PSCI CPU_OFF Test PASSED (3 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,76 @@
+//! PSCI CPU_ON target resolution tests
+//!
+//! Lays a VM out with two vCPUs per Aff1 cluster and checks that CPU_ON
+//! resolves the guest's full MPIDR to the linear vCPU ID it queues, and
+//! refuses an MPIDR that names no vCPU.
+
+use super::cleanup::Cleanup;
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::hypervisor::exception::handle_psci;
+use hypervisor::arch::aarch64::regs::VcpuContext;
+use hypervisor::global::{vm_state, CURRENT_VM_ID};
+use hypervisor::uart_puts;
+use hypervisor::vm::Vm;
+
+const PSCI_CPU_ON_64: u64 = 0xC400_0003;
+const PSCI_INVALID_PARAMETERS: u64 = 0xFFFF_FFFE;
+const VM_ID: usize = 1;
+const ENTRY: u64 = 0x4800_0000;
+/// Aff1 = 1, Aff0 = 0: vCPU 2 with two vCPUs per cluster
+const MPIDR_VCPU2: u64 = 0x100;
+/// Aff2 = 1: no vCPU lives there
+const MPIDR_UNMAPPED: u64 = 0x1_0000;
+
+/// PSCI CPU_ON for `mpidr` as VM_ID; returns (continue, x0).
+fn cpu_on_as_vm(mpidr: u64) -> (bool, u64) {
+    let prev_vm = CURRENT_VM_ID.swap(VM_ID, Ordering::Relaxed);
+    let mut ctx = VcpuContext::default();
+    ctx.gp_regs.x0 = PSCI_CPU_ON_64;
+    ctx.gp_regs.x1 = mpidr;
+    ctx.gp_regs.x2 = ENTRY;
+    let cont = handle_psci(&mut ctx, PSCI_CPU_ON_64);
+    CURRENT_VM_ID.store(prev_vm, Ordering::Relaxed);
+    (cont, ctx.gp_regs.x0)
+}
+
+pub fn run_psci_cpu_on_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  PSCI CPU_ON Target Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let vs = vm_state(VM_ID);
+    let mut vm = Vm::new(VM_ID);
+    if vm.set_vcpus_per_cluster(2).is_err() {
+        uart_puts(b"[CPU-ON] FAILED: set_vcpus_per_cluster\n");
+        return;
+    }
+    let cleanup = Cleanup::new(|| {
+        let _ = vs.pending_cpu_on.take();
+        let _fresh = Vm::new(VM_ID);
+    });
+
+    // Test 1: Aff1=1, Aff0=0 queues vCPU 2
+    uart_puts(b"[CPU-ON] Test 1: full MPIDR resolves to vCPU 2...\n");
+    let (cont, ret) = cpu_on_as_vm(MPIDR_VCPU2);
+    let request = vs.pending_cpu_on.take();
+    if cont || ret != 0 || request != Some((2, ENTRY, 0)) {
+        uart_puts(b"[CPU-ON] FAILED: CPU_ON did not target vCPU 2\n");
+        return;
+    }
+    uart_puts(b"[CPU-ON] Test 1 PASSED\n\n");
+
+    // Test 2: an MPIDR no vCPU has is refused, nothing queued
+    uart_puts(b"[CPU-ON] Test 2: unmapped MPIDR rejected...\n");
+    let (cont, ret) = cpu_on_as_vm(MPIDR_UNMAPPED);
+    let request = vs.pending_cpu_on.take();
+    drop(cleanup);
+    if !cont || ret != PSCI_INVALID_PARAMETERS || request.is_some() {
+        uart_puts(b"[CPU-ON] FAILED: unmapped MPIDR not INVALID_PARAMETERS\n");
+        return;
+    }
+    uart_puts(b"[CPU-ON] Test 2 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  PSCI CPU_ON Target Test PASSED (2 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,135 @@
+//! Paravirtual console ring tests
+//!
+//! Registers a ring page with hypercall 9, writes log bytes straight into
+//! it the way a guest would, rings the doorbell (hypercall 10) and checks
+//! that the bytes reach the VM's UART TX log in order. Ring IPAs outside
+//! guest RAM or misaligned are rejected, and so is a page the VM's
+//! Stage-2 maps read-only.
+
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::hypervisor::exception::handle_hypercall_with_imm;
+use hypervisor::arch::aarch64::mm::mmu::{DynamicIdentityMapper, MemoryAttribute};
+use hypervisor::arch::aarch64::regs::VcpuContext;
+use hypervisor::devices::pl011::VirtualUart;
+use hypervisor::devices::Device;
+use hypervisor::global::{current_vm_id, DEVICES, PER_VM_VTTBR};
+use hypervisor::pv_console::{
+    register_ring, unregister_ring, HC_CONSOLE_DOORBELL, HC_CONSOLE_REGISTER, HC_NOT_REGISTERED,
+    RING_DATA_OFFSET, RING_DATA_SIZE, RING_PROD_OFFSET,
+};
+use hypervisor::shared_buffer::{HC_INVALID_IPA, HC_SUCCESS};
+use hypervisor::uart_puts;
+
+const MESSAGE: &[u8] = b"pvcon: hello\n";
+/// VM whose Stage-2 Test 4 installs
+const STAGE2_VM: usize = 1;
+
+/// Ring page; the hypervisor image lies in guest RAM (identity-mapped)
+#[repr(C, align(4096))]
+struct RingPage([u8; 4096]);
+
+static mut RING: RingPage = RingPage([0; 4096]);
+
+/// Issue hypercall `num` with x1 = `arg`; returns x0.
+fn hypercall(num: u64, arg: u64) -> u64 {
+    let mut ctx = VcpuContext::default();
+    ctx.gp_regs.x0 = num;
+    ctx.gp_regs.x1 = arg;
+    handle_hypercall_with_imm(&mut ctx, 0);
+    ctx.gp_regs.x0
+}
+
+/// Append `bytes` to the ring as the guest producer, without trapping.
+fn guest_write(ring: u64, bytes: &[u8]) {
+    unsafe {
+        let prod_ptr = (ring + RING_PROD_OFFSET) as *mut u32;
+        let mut prod = core::ptr::read_volatile(prod_ptr);
+        for &b in bytes {
+            let off = (prod % RING_DATA_SIZE) as u64;
+            core::ptr::write_volatile((ring + RING_DATA_OFFSET + off) as *mut u8, b);
+            prod = prod.wrapping_add(1);
+        }
+        core::ptr::write_volatile(prod_ptr, prod);
+    }
+}
+
+pub fn run_pv_console_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  PV Console Ring Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let vm_id = current_vm_id();
+    let ring = &raw mut RING as u64;
+    DEVICES[vm_id].reset();
+    DEVICES[vm_id].register_device(Device::Uart(VirtualUart::new()));
+    unregister_ring(vm_id);
+
+    // Test 1: IPAs outside guest RAM, misaligned, or before registration
+    uart_puts(b"[PVCON] Test 1: invalid ring IPA rejected...\n");
+    let outside = hypercall(HC_CONSOLE_REGISTER, 0x0900_0000);
+    let misaligned = hypercall(HC_CONSOLE_REGISTER, ring + 8);
+    let early = hypercall(HC_CONSOLE_DOORBELL, 0);
+    if outside != HC_INVALID_IPA || misaligned != HC_INVALID_IPA || early != HC_NOT_REGISTERED {
+        uart_puts(b"[PVCON] FAILED: invalid ring accepted\n");
+        return;
+    }
+    uart_puts(b"[PVCON] Test 1 PASSED\n\n");
+
+    // Test 2: bytes written into the ring reach the UART in order
+    uart_puts(b"[PVCON] Test 2: doorbell drains ring to UART...\n");
+    if hypercall(HC_CONSOLE_REGISTER, ring) != HC_SUCCESS {
+        uart_puts(b"[PVCON] FAILED: ring in guest RAM rejected\n");
+        return;
+    }
+    guest_write(ring, MESSAGE);
+    let drained = hypercall(HC_CONSOLE_DOORBELL, 0);
+    let mut log = [0u8; 64];
+    let n = DEVICES[vm_id].uart_tx_log(&mut log);
+    if drained != MESSAGE.len() as u64 || &log[..n] != MESSAGE {
+        unregister_ring(vm_id);
+        uart_puts(b"[PVCON] FAILED: UART log does not match ring contents\n");
+        return;
+    }
+    uart_puts(b"[PVCON] Test 2 PASSED\n\n");
+
+    // Test 3: re-registering skips stale bytes; output wrapping the data
+    // area and the u32 index is drained once, in order
+    uart_puts(b"[PVCON] Test 3: wrapped ring drained in order...\n");
+    guest_write(ring, b"stale");
+    unsafe {
+        core::ptr::write_volatile((ring + RING_PROD_OFFSET) as *mut u32, u32::MAX - 3);
+    }
+    hypercall(HC_CONSOLE_REGISTER, ring);
+    guest_write(ring, MESSAGE);
+    let drained = hypercall(HC_CONSOLE_DOORBELL, 0);
+    let again = hypercall(HC_CONSOLE_DOORBELL, 0);
+    let n = DEVICES[vm_id].uart_tx_log(&mut log[..MESSAGE.len()]);
+    unregister_ring(vm_id);
+    if drained != MESSAGE.len() as u64 || again != 0 || &log[..n] != MESSAGE {
+        uart_puts(b"[PVCON] FAILED: wrapped ring drained out of order\n");
+        return;
+    }
+    uart_puts(b"[PVCON] Test 3 PASSED\n\n");
+
+    // Test 4: the ring must be writable in the VM's own Stage-2
+    uart_puts(b"[PVCON] Test 4: read-only ring page rejected...\n");
+    let block = ring & !0x1F_FFFF;
+    let mut mapper = DynamicIdentityMapper::new();
+    let ro = mapper.map_region(block, 0x20_0000, MemoryAttribute::ReadOnly);
+    let saved = PER_VM_VTTBR[STAGE2_VM].swap(mapper.vttbr(), Ordering::AcqRel);
+    let read_only = register_ring(STAGE2_VM, ring);
+    let rw = mapper.map_region(block, 0x20_0000, MemoryAttribute::Normal);
+    let writable = register_ring(STAGE2_VM, ring);
+    unregister_ring(STAGE2_VM);
+    PER_VM_VTTBR[STAGE2_VM].store(saved, Ordering::Release);
+    core::mem::forget(mapper);
+    if ro.is_err() || rw.is_err() || read_only != Err(HC_INVALID_IPA) || writable.is_err() {
+        uart_puts(b"[PVCON] FAILED: ring not checked against the VM's Stage-2\n");
+        return;
+    }
+    uart_puts(b"[PVCON] Test 4 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  PV Console Ring Test PASSED (4 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
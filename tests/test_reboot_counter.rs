@@ -0,0 +1,84 @@
+//! Reboot counter tests
+//!
+//! Drives PSCI SYSTEM_RESET for a VM and recreates it the way a reboot
+//! does, then checks that hypercall 13 reports the number of reboots and
+//! that the count survives `Vm::new()` and is kept per VM.
+
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::hypervisor::exception::{
+    handle_hypercall_with_imm, handle_psci, HC_REBOOT_COUNT,
+};
+use hypervisor::arch::aarch64::regs::VcpuContext;
+use hypervisor::global::{vm_state, CURRENT_VM_ID};
+use hypervisor::uart_puts;
+use hypervisor::vm::Vm;
+
+const PSCI_SYSTEM_RESET: u64 = 0x8400_0009;
+const VM_ID: usize = 1;
+
+/// Issue hypercall 13 as `vm_id`; returns (continue, x0).
+fn reboot_count_as(vm_id: usize) -> (bool, u64) {
+    let prev_vm = CURRENT_VM_ID.swap(vm_id, Ordering::Relaxed);
+    let mut ctx = VcpuContext::default();
+    ctx.gp_regs.x0 = HC_REBOOT_COUNT;
+    let cont = handle_hypercall_with_imm(&mut ctx, 0);
+    CURRENT_VM_ID.store(prev_vm, Ordering::Relaxed);
+    (cont, ctx.gp_regs.x0)
+}
+
+/// SYSTEM_RESET from VM_ID, then recreate the VM.
+fn reboot() -> bool {
+    let prev_vm = CURRENT_VM_ID.swap(VM_ID, Ordering::Relaxed);
+    let mut ctx = VcpuContext::default();
+    ctx.gp_regs.x0 = PSCI_SYSTEM_RESET;
+    let cont = handle_psci(&mut ctx, PSCI_SYSTEM_RESET);
+    CURRENT_VM_ID.store(prev_vm, Ordering::Relaxed);
+    for t in vm_state(VM_ID).terminal_exit.iter() {
+        t.store(false, Ordering::Relaxed);
+    }
+    let _fresh = Vm::new(VM_ID);
+    !cont
+}
+
+pub fn run_reboot_counter_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Reboot Counter Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let vs = vm_state(VM_ID);
+    let prev_vm0 = vm_state(0).reboot_count.load(Ordering::Relaxed);
+    vs.reboot_count.store(0, Ordering::Relaxed);
+
+    // Test 1: fresh VM reports zero reboots
+    uart_puts(b"[REBOOT] Test 1: initial count...\n");
+    if reboot_count_as(VM_ID) != (true, 0) {
+        uart_puts(b"[REBOOT] FAILED: initial reboot count not 0\n");
+        return;
+    }
+    uart_puts(b"[REBOOT] Test 1 PASSED\n\n");
+
+    // Test 2: two resets through SYSTEM_RESET + Vm::new -> 2
+    uart_puts(b"[REBOOT] Test 2: count survives VM resets...\n");
+    let reset = reboot() && reboot();
+    let count = reboot_count_as(VM_ID);
+    if !reset || count != (true, 2) {
+        vs.reboot_count.store(0, Ordering::Relaxed);
+        uart_puts(b"[REBOOT] FAILED: reboot count after two resets\n");
+        return;
+    }
+    uart_puts(b"[REBOOT] Test 2 PASSED\n\n");
+
+    // Test 3: other VMs keep their own count
+    uart_puts(b"[REBOOT] Test 3: per-VM count...\n");
+    let other = reboot_count_as(0);
+    vs.reboot_count.store(0, Ordering::Relaxed);
+    if other != (true, prev_vm0 as u64) {
+        uart_puts(b"[REBOOT] FAILED: VM 0 count changed\n");
+        return;
+    }
+    uart_puts(b"[REBOOT] Test 3 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Reboot Counter Test PASSED (3 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
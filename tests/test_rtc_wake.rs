@@ -0,0 +1,137 @@
+//! RTC alarm wake from PSCI SYSTEM_SUSPEND tests
+//!
+//! Programs a PL031 alarm in VM 1, suspends the VM through the PSCI
+//! handler, and advances a fake clock: the VM must stay suspended until the
+//! alarm time, then resume the calling vCPU at its SYSTEM_SUSPEND entry
+//! point with the context ID in x0 and the RTC interrupt pending.
+
+use super::cleanup::Cleanup;
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::hypervisor::exception::handle_psci;
+use hypervisor::arch::aarch64::regs::VcpuContext;
+use hypervisor::devices::pl031::{PL031_BASE, PL031_INTID};
+use hypervisor::global::{clear_spi, vm_state, CURRENT_VM_ID, DEVICES, WAKE_SRC_RTC};
+use hypervisor::time::{advance_fake_clock, install_fake_clock, remove_fake_clock};
+use hypervisor::uart_puts;
+use hypervisor::vm::Vm;
+
+const PSCI_SYSTEM_SUSPEND_64: u64 = 0xC400_000E;
+const PSCI_DENIED: u64 = 0xFFFF_FFFD;
+const PSCI_INVALID_ADDRESS: u64 = 0xFFFF_FFF7;
+const VM_ID: usize = 1;
+/// 1 tick = 1 us
+const FREQ: u64 = 1_000_000;
+const RTCMR: u64 = 0x004;
+const RTCLR: u64 = 0x008;
+const RTCIMSC: u64 = 0x010;
+const EPOCH: u64 = 1000;
+const ALARM_SECS: u64 = 5;
+const ENTRY: u64 = 0x4008_0000;
+const CONTEXT_ID: u64 = 0xC0DE;
+
+/// Issue SYSTEM_SUSPEND as vCPU 0 of VM_ID; returns (continue, x0).
+fn system_suspend(entry: u64, ctx_id: u64) -> (bool, u64) {
+    let vs = vm_state(VM_ID);
+    let prev_vcpu = vs.current_vcpu_id.swap(0, Ordering::Relaxed);
+    let prev_vm = CURRENT_VM_ID.swap(VM_ID, Ordering::Relaxed);
+    let mut ctx = VcpuContext::default();
+    ctx.gp_regs.x0 = PSCI_SYSTEM_SUSPEND_64;
+    ctx.gp_regs.x1 = entry;
+    ctx.gp_regs.x2 = ctx_id;
+    let cont = handle_psci(&mut ctx, PSCI_SYSTEM_SUSPEND_64);
+    CURRENT_VM_ID.store(prev_vm, Ordering::Relaxed);
+    vs.current_vcpu_id.store(prev_vcpu, Ordering::Relaxed);
+    (cont, ctx.gp_regs.x0)
+}
+
+/// Whether the RTC alarm SPI is queued for any vCPU of VM_ID.
+fn rtc_irq_pending() -> bool {
+    let bit = 1u32 << (PL031_INTID - 32);
+    vm_state(VM_ID)
+        .pending_spis
+        .iter()
+        .any(|s| s.load(Ordering::Acquire) & bit != 0)
+}
+
+pub fn run_rtc_wake_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  RTC Alarm Wake Test\n");
+    uart_puts(b"========================================\n\n");
+
+    install_fake_clock(FREQ, 1000);
+    let vs = vm_state(VM_ID);
+    let mut vm = Vm::new(VM_ID);
+    if vm.create_vcpu(0).is_err() {
+        remove_fake_clock();
+        uart_puts(b"[RTC-WAKE] FAILED: create_vcpu\n");
+        return;
+    }
+    vs.vcpu_online_mask.store(1, Ordering::Release);
+    let cleanup = Cleanup::new(|| {
+        remove_fake_clock();
+        clear_spi(VM_ID, PL031_INTID);
+        let _fresh = Vm::new(VM_ID);
+        vs.vcpu_online_mask.store(0, Ordering::Relaxed);
+    });
+
+    // Test 1: suspending with an armed alarm registers the RTC; the VM
+    // stays suspended until the alarm time
+    uart_puts(b"[RTC-WAKE] Test 1: suspend registers RTC wakeup...\n");
+    let rtc = &DEVICES[VM_ID];
+    rtc.handle_mmio(PL031_BASE + RTCLR, EPOCH, 4, true);
+    rtc.handle_mmio(PL031_BASE + RTCIMSC, 1, 4, true);
+    rtc.handle_mmio(PL031_BASE + RTCMR, EPOCH + ALARM_SECS, 4, true);
+    let (cont, _) = system_suspend(ENTRY, CONTEXT_ID);
+    advance_fake_clock((ALARM_SECS - 1) * FREQ);
+    if cont
+        || !vs.system_suspend.is_suspended()
+        || vs.system_suspend.wake_sources() != WAKE_SRC_RTC
+        || !vm.poll_system_suspend()
+        || rtc_irq_pending()
+    {
+        uart_puts(b"[RTC-WAKE] FAILED: VM not held suspended before the alarm\n");
+        return;
+    }
+    uart_puts(b"[RTC-WAKE] Test 1 PASSED\n\n");
+
+    // Test 2: passing the alarm time resumes vCPU 0 at the entry point
+    uart_puts(b"[RTC-WAKE] Test 2: alarm resumes at entry point...\n");
+    advance_fake_clock(2 * FREQ);
+    let still_suspended = vm.poll_system_suspend();
+    let resumed = vm
+        .vcpu(0)
+        .is_some_and(|v| v.context().pc == ENTRY && v.context().gp_regs.x0 == CONTEXT_ID);
+    if still_suspended
+        || vs.system_suspend.is_suspended()
+        || !resumed
+        || !rtc_irq_pending()
+        || vs.vcpu_online_mask.load(Ordering::Acquire) != 1
+    {
+        uart_puts(b"[RTC-WAKE] FAILED: VM did not resume on the RTC alarm\n");
+        return;
+    }
+    uart_puts(b"[RTC-WAKE] Test 2 PASSED\n\n");
+
+    // Test 3: refused while another vCPU is online or for a bad entry point
+    uart_puts(b"[RTC-WAKE] Test 3: invalid suspend requests rejected...\n");
+    vs.vcpu_online_mask.store(0b11, Ordering::Release);
+    let (denied_cont, denied) = system_suspend(ENTRY, CONTEXT_ID);
+    vs.vcpu_online_mask.store(1, Ordering::Release);
+    let (bad_cont, bad_entry) = system_suspend(0x1000, CONTEXT_ID);
+    let suspended = vs.system_suspend.is_suspended();
+    drop(cleanup);
+    if !denied_cont
+        || denied != PSCI_DENIED
+        || !bad_cont
+        || bad_entry != PSCI_INVALID_ADDRESS
+        || suspended
+    {
+        uart_puts(b"[RTC-WAKE] FAILED: invalid SYSTEM_SUSPEND accepted\n");
+        return;
+    }
+    uart_puts(b"[RTC-WAKE] Test 3 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  RTC Alarm Wake Test PASSED (3 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -1,6 +1,6 @@
 //! Scheduler tests
 
-use hypervisor::scheduler::Scheduler;
+use hypervisor::scheduler::{RunState, Scheduler};
 use hypervisor::uart_puts;
 
 pub fn run_scheduler_test() {
@@ -70,6 +70,42 @@ pub fn run_scheduler_test() {
     }
     uart_puts(b"[SCHED] Test 4 PASSED\n\n");
 
+    // Test 5: weights 3:1 give run counts 3:1; a blocked heavy vCPU
+    // does not stall the light one
+    uart_puts(b"[SCHED] Test 5: Weighted round-robin...\n");
+    let mut sched = Scheduler::new();
+    sched.add_vcpu(0);
+    sched.add_vcpu(1);
+    sched.set_weight(0, 3);
+    for _ in 0..400 {
+        sched.pick_next();
+        sched.yield_current();
+    }
+    let (heavy, light) = (sched.run_count(0), sched.run_count(1));
+    if heavy + light != 400 || heavy.abs_diff(3 * light) > 4 {
+        uart_puts(b"[SCHED] ERROR: Run counts not 3:1\n");
+        return;
+    }
+    for _ in 0..4 {
+        if sched.pick_next() == Some(0) {
+            sched.block_current();
+            break;
+        }
+        sched.yield_current();
+    }
+    let blocked = sched.current().is_none() && sched.state(0) == RunState::Blocked;
+    let light_runs = (0..8).all(|_| {
+        let id = sched.pick_next();
+        sched.yield_current();
+        id == Some(1)
+    });
+    sched.unblock(0);
+    if !blocked || !light_runs || sched.pick_next() != Some(0) {
+        uart_puts(b"[SCHED] ERROR: Blocking broke weighted picks\n");
+        return;
+    }
+    uart_puts(b"[SCHED] Test 5 PASSED\n\n");
+
     uart_puts(b"========================================\n");
     uart_puts(b"  Scheduler Test PASSED\n");
     uart_puts(b"========================================\n\n");
@@ -0,0 +1,130 @@
+//! SGI wake ordering tests
+//!
+//! Single-thread approximation of the multi-pCPU SGI wake path: a hook
+//! installed in place of the physical wake SGI records whether the target's
+//! PENDING_SGIS bit was already visible when the IPI would have been sent.
+
+use super::cleanup::Cleanup;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use hypervisor::arch::aarch64::hypervisor::exception::{handle_sgi_trap, set_sgi_wake_hook};
+use hypervisor::arch::aarch64::vcpu_arch_state::VcpuArchState;
+use hypervisor::global::{
+    current_vcpu_id, current_vm_id, current_vm_state, vcpu_affinity, vcpu_at_affinity,
+    DEFAULT_VCPUS_PER_CLUSTER,
+};
+use hypervisor::uart_puts;
+use hypervisor::vm::has_pending_irqs;
+
+const SGI_UNICAST: u32 = 3;
+const SGI_BROADCAST: u32 = 5;
+const SGI_CLUSTER: u32 = 7;
+/// With 4 vCPUs per cluster, vCPU 5 sits at Aff1=1, Aff0=1
+const CLUSTER_VCPU: usize = 5;
+
+/// Target vCPU and SGI the hook checks, and what it observed
+static EXPECT_VCPU: AtomicU32 = AtomicU32::new(0);
+static EXPECT_SGI: AtomicU32 = AtomicU32::new(0);
+static WAKE_TARGETS: AtomicU32 = AtomicU32::new(0);
+static SAW_PENDING: AtomicBool = AtomicBool::new(false);
+
+fn record_wake(targets: u16) {
+    let vcpu = EXPECT_VCPU.load(Ordering::Relaxed) as usize;
+    let sgi = EXPECT_SGI.load(Ordering::Relaxed);
+    let pending = current_vm_state().pending_sgis[vcpu].load(Ordering::Acquire);
+    SAW_PENDING.store(pending & (1 << sgi) != 0, Ordering::Relaxed);
+    WAKE_TARGETS.store(targets as u32, Ordering::Relaxed);
+}
+
+static RECORD_WAKE: fn(u16) = record_wake;
+
+fn expect(vcpu: usize, sgi: u32) {
+    EXPECT_VCPU.store(vcpu as u32, Ordering::Relaxed);
+    EXPECT_SGI.store(sgi, Ordering::Relaxed);
+    WAKE_TARGETS.store(0, Ordering::Relaxed);
+    SAW_PENDING.store(false, Ordering::Relaxed);
+}
+
+pub fn run_sgi_wake_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  SGI Wake Ordering Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let vs = current_vm_state();
+    let self_id = current_vcpu_id();
+    let target = if self_id == 0 { 1 } else { 0 };
+    let saved_online = vs.vcpu_online_mask.load(Ordering::Relaxed);
+    vs.vcpu_online_mask
+        .store((1 << self_id) | (1 << target), Ordering::Relaxed);
+    set_sgi_wake_hook(Some(&RECORD_WAKE));
+    let cleanup = Cleanup::new(|| {
+        set_sgi_wake_hook(None);
+        vs.pending_sgis[target].store(0, Ordering::Relaxed);
+        vs.vcpu_online_mask.store(saved_online, Ordering::Relaxed);
+    });
+
+    // Test 1: IRM=0 unicast — bit queued before the wake IPI
+    uart_puts(b"[SGI-WAKE] Test 1: unicast pending before wake...\n");
+    expect(target, SGI_UNICAST);
+    handle_sgi_trap(((SGI_UNICAST as u64) << 24) | (1 << target));
+    if WAKE_TARGETS.load(Ordering::Relaxed) != 1 << target || !SAW_PENDING.load(Ordering::Relaxed) {
+        uart_puts(b"[SGI-WAKE] FAILED: wake sent before SGI was queued\n");
+        return;
+    }
+    uart_puts(b"[SGI-WAKE] Test 1 PASSED\n\n");
+
+    // Test 2: IRM=1 broadcast — every other online vCPU queued, then woken
+    uart_puts(b"[SGI-WAKE] Test 2: broadcast pending before wake...\n");
+    vs.pending_sgis[target].store(0, Ordering::Relaxed);
+    expect(target, SGI_BROADCAST);
+    handle_sgi_trap((1 << 40) | ((SGI_BROADCAST as u64) << 24));
+    if WAKE_TARGETS.load(Ordering::Relaxed) != 1 << target || !SAW_PENDING.load(Ordering::Relaxed) {
+        uart_puts(b"[SGI-WAKE] FAILED: broadcast wake before SGI was queued\n");
+        return;
+    }
+    uart_puts(b"[SGI-WAKE] Test 2 PASSED\n\n");
+
+    // Test 3: the idle path sees the queued SGI instead of sleeping
+    uart_puts(b"[SGI-WAKE] Test 3: WFI re-check sees queued SGI...\n");
+    let queued = has_pending_irqs(target);
+    vs.pending_sgis[target].store(0, Ordering::Relaxed);
+    let drained = !has_pending_irqs(target);
+    drop(cleanup);
+    if !queued || !drained {
+        uart_puts(b"[SGI-WAKE] FAILED: pending re-check wrong\n");
+        return;
+    }
+    uart_puts(b"[SGI-WAKE] Test 3 PASSED\n\n");
+
+    // Test 4: Aff1 selects the cluster — Aff1=1, TargetList bit 1 = vCPU 5
+    uart_puts(b"[SGI-WAKE] Test 4: SGI routed by Aff1...\n");
+    let vm_id = current_vm_id();
+    vs.vcpus_per_cluster.store(4, Ordering::Relaxed);
+    vs.vcpu_online_mask
+        .store((1 << self_id) | (1 << CLUSTER_VCPU), Ordering::Relaxed);
+    set_sgi_wake_hook(Some(&RECORD_WAKE));
+    let mut arch = VcpuArchState::new();
+    arch.init_for_vcpu(CLUSTER_VCPU);
+    arch.set_affinity(vcpu_affinity(vm_id, CLUSTER_VCPU));
+    expect(CLUSTER_VCPU, SGI_CLUSTER);
+    let before_aff0 = vs.pending_sgis[1].load(Ordering::Relaxed);
+    handle_sgi_trap(((SGI_CLUSTER as u64) << 24) | (1 << 16) | (1 << 1));
+    let routed = WAKE_TARGETS.load(Ordering::Relaxed) == 1 << CLUSTER_VCPU
+        && SAW_PENDING.load(Ordering::Relaxed)
+        && vs.pending_sgis[1].load(Ordering::Relaxed) == before_aff0;
+    let lookup =
+        vcpu_at_affinity(vm_id, arch.vmpidr) == Some(CLUSTER_VCPU) && arch.vmpidr & 0xFFFF == 0x101;
+    set_sgi_wake_hook(None);
+    vs.pending_sgis[CLUSTER_VCPU].store(0, Ordering::Relaxed);
+    vs.vcpus_per_cluster
+        .store(DEFAULT_VCPUS_PER_CLUSTER, Ordering::Relaxed);
+    vs.vcpu_online_mask.store(saved_online, Ordering::Relaxed);
+    if !routed || !lookup {
+        uart_puts(b"[SGI-WAKE] FAILED: SGI not routed by Aff1\n");
+        return;
+    }
+    uart_puts(b"[SGI-WAKE] Test 4 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  SGI Wake Ordering Test PASSED (4 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,94 @@
+//! Shared buffer hypercall tests
+//!
+//! Issues hypercall 12 against a test Stage-2 table and checks that the
+//! VM's shared page shows up RW at the requested IPA, that FF-A sharing
+//! refuses it, and that IPAs overlapping guest RAM or an existing mapping
+//! are rejected.
+
+use hypervisor::arch::aarch64::mm::mmu::{DynamicIdentityMapper, MemoryAttribute};
+use hypervisor::arch::aarch64::regs::VcpuContext;
+use hypervisor::ffa::memory::{validate_page_for_share, PageOwnership};
+use hypervisor::ffa::stage2_walker::Stage2Walker;
+use hypervisor::global::current_vm_id;
+use hypervisor::shared_buffer::{
+    handle_map_hypercall, shared_page, HC_INVALID_IPA, HC_MAP_SHARED_BUFFER, HC_SUCCESS,
+};
+use hypervisor::uart_puts;
+
+/// Guest RAM, mapped as a 2MB block
+const RAM_IPA: u64 = 0x4800_0000;
+/// Hole above guest RAM
+const HOLE_IPA: u64 = 0x9000_0000;
+
+fn hypercall(walker: &Stage2Walker, ipa: u64) -> u64 {
+    let mut ctx = VcpuContext::default();
+    ctx.gp_regs.x0 = HC_MAP_SHARED_BUFFER;
+    ctx.gp_regs.x1 = ipa;
+    handle_map_hypercall(&mut ctx, walker);
+    ctx.gp_regs.x0
+}
+
+pub fn run_shared_buffer_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Shared Buffer Hypercall Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let mut mapper = DynamicIdentityMapper::new();
+    mapper
+        .map_region(RAM_IPA, 0x0020_0000, MemoryAttribute::Normal)
+        .unwrap();
+    let walker = Stage2Walker::new(mapper.vttbr());
+
+    // Test 1: valid hole -> shared page mapped at the IPA
+    uart_puts(b"[SHM] Test 1: map at unused IPA...\n");
+    let ret = hypercall(&walker, HOLE_IPA);
+    let page = shared_page(current_vm_id());
+    if ret != HC_SUCCESS || page.is_none() || walker.translate(HOLE_IPA) != page {
+        core::mem::forget(mapper);
+        uart_puts(b"[SHM] FAILED: shared page not mapped at IPA\n");
+        return;
+    }
+    uart_puts(b"[SHM] Test 1 PASSED\n\n");
+
+    // Test 2: mapping is RW and reaches the shared page
+    uart_puts(b"[SHM] Test 2: readable/writable through Stage-2...\n");
+    let pa = walker.translate(HOLE_IPA + 0x10).unwrap();
+    unsafe {
+        core::ptr::write_volatile(pa as *mut u64, 0xFEED_F00D);
+    }
+    let readback = unsafe { core::ptr::read_volatile((page.unwrap() + 0x10) as *const u64) };
+    if walker.read_s2ap(HOLE_IPA) != Some(0b11) || readback != 0xFEED_F00D {
+        core::mem::forget(mapper);
+        uart_puts(b"[SHM] FAILED: mapping not RW\n");
+        return;
+    }
+    uart_puts(b"[SHM] Test 2 PASSED\n\n");
+
+    // Test 3: the page is not guest-owned, so MEM_SHARE/LEND refuse it
+    uart_puts(b"[SHM] Test 3: not shareable over FF-A...\n");
+    let sw = walker.read_sw_bits(HOLE_IPA);
+    if sw != Some(PageOwnership::SharedBorrowed as u8)
+        || validate_page_for_share(sw.unwrap_or(0)).is_ok()
+    {
+        core::mem::forget(mapper);
+        uart_puts(b"[SHM] FAILED: shared page marked Owned\n");
+        return;
+    }
+    uart_puts(b"[SHM] Test 3 PASSED\n\n");
+
+    // Test 4: IPAs overlapping RAM or an existing mapping are rejected
+    uart_puts(b"[SHM] Test 4: invalid IPAs rejected...\n");
+    let ram = hypercall(&walker, RAM_IPA);
+    let again = hypercall(&walker, HOLE_IPA);
+    let ram_pa = walker.translate(RAM_IPA);
+    core::mem::forget(mapper);
+    if ram != HC_INVALID_IPA || again != HC_INVALID_IPA || ram_pa != Some(RAM_IPA) {
+        uart_puts(b"[SHM] FAILED: overlapping IPA accepted\n");
+        return;
+    }
+    uart_puts(b"[SHM] Test 4 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Shared Buffer Hypercall Test PASSED (4 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
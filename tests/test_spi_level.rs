@@ -0,0 +1,197 @@
+//! Level-triggered SPI line tests
+//!
+//! Drives the SPI lines of a virtio-mmio transport, the PL011 and the
+//! sensor, configured level-sensitive in the current VM's GICD, and checks
+//! that a deactivation while a line is still high queues the SPI again:
+//! both when it leaves the LRs (`global::sync_spi_active`) and on a
+//! trapped ICC_DIR_EL1 write. Once the device drops the line (InterruptACK,
+//! UARTICR, alarm clear) deactivation queues nothing.
+
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::defs::ESR_EC_SHIFT;
+use hypervisor::arch::aarch64::hypervisor::exception::handle_msr_mrs_trap;
+use hypervisor::arch::aarch64::regs::VcpuContext;
+use hypervisor::devices::pl011::VirtualUart;
+use hypervisor::devices::sensor::{VirtualSensor, SENSOR_INTID};
+use hypervisor::devices::virtio::balloon::VirtioBalloon;
+use hypervisor::devices::virtio::mmio::VirtioMmioTransport;
+use hypervisor::devices::MmioDevice;
+use hypervisor::global::{
+    clear_spi, current_devices, current_vm_id, current_vm_state, spi_line_level, sync_spi_active,
+};
+use hypervisor::platform::GICD_BASE;
+use hypervisor::uart_puts;
+
+/// Virtio-mmio slot 3 layout
+const BALLOON_BASE: u64 = 0x0a00_0600;
+const BALLOON_INTID: u32 = 51;
+const UART_INTID: u32 = 33;
+
+/// GICD_ICFGR2/3: INTIDs 32-47 and 48-63
+const GICD_ICFGR2: u64 = 0xC08;
+const GICD_ICFGR3: u64 = 0xC0C;
+
+const INTERRUPT_ACK: u64 = 0x064;
+const VIRTIO_INT_CONFIG: u64 = 1 << 1;
+const UARTIMSC: u64 = 0x038;
+const UARTICR: u64 = 0x044;
+/// UART receive timeout interrupt
+const INT_RT: u64 = 1 << 6;
+const SENSOR_THRESHOLD: u64 = 0x00C;
+const SENSOR_CTRL: u64 = 0x010;
+
+fn spi_pending(intid: u32) -> bool {
+    let bit = 1u32 << (intid - 32);
+    current_vm_state()
+        .pending_spis
+        .iter()
+        .any(|s| s.load(Ordering::Acquire) & bit != 0)
+}
+
+/// Active list register holding `intid`.
+fn active_lr(intid: u32) -> u64 {
+    (0b10u64 << 62) | intid as u64
+}
+
+/// The guest runs with `intid` active and deactivates it before the exit.
+fn leave_lrs(intid: u32) {
+    sync_spi_active(current_vm_id(), &[active_lr(intid), 0, 0, 0], &[0; 4]);
+}
+
+/// The guest deactivates `intid` through a trapped MSR ICC_DIR_EL1, x1.
+fn trapped_dir(intid: u32) {
+    // ISS: Op0=3, Op2=1, Op1=0, CRn=12, Rt=1, CRm=11, write
+    let iss: u32 = (3 << 20) | (1 << 17) | (12 << 10) | (1 << 5) | (11 << 1);
+    let mut ctx = VcpuContext::default();
+    ctx.gp_regs.x1 = intid as u64;
+    handle_msr_mrs_trap(&mut ctx, (0x18u64 << ESR_EC_SHIFT) | iss as u64);
+}
+
+pub fn run_spi_level_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Level-Triggered SPI Line Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let vm_id = current_vm_id();
+    let devs = current_devices();
+    let icfgr2 = devs
+        .handle_mmio(GICD_BASE + GICD_ICFGR2, 0, 4, false)
+        .unwrap_or(0);
+    let icfgr3 = devs
+        .handle_mmio(GICD_BASE + GICD_ICFGR3, 0, 4, false)
+        .unwrap_or(0);
+    devs.handle_mmio(GICD_BASE + GICD_ICFGR2, 0, 4, true);
+    devs.handle_mmio(GICD_BASE + GICD_ICFGR3, 0, 4, true);
+    let restore = || {
+        devs.handle_mmio(GICD_BASE + GICD_ICFGR2, icfgr2, 4, true);
+        devs.handle_mmio(GICD_BASE + GICD_ICFGR3, icfgr3, 4, true);
+    };
+
+    // Test 1: a virtio interrupt raises the line and queues the SPI
+    uart_puts(b"[SPI LEVEL] Test 1: virtio InterruptStatus drives the line...\n");
+    let mut t = VirtioMmioTransport::new(BALLOON_BASE, VirtioBalloon::new(), BALLOON_INTID);
+    t.set_owner_vm(vm_id);
+    t.signal_config_change();
+    if !spi_line_level(vm_id, BALLOON_INTID) || !spi_pending(BALLOON_INTID) {
+        uart_puts(b"[SPI LEVEL] FAILED: config change did not raise the line\n");
+        drop(t);
+        restore();
+        return;
+    }
+    uart_puts(b"[SPI LEVEL] Test 1 PASSED\n\n");
+
+    // Test 2: deactivated without InterruptACK, the SPI is queued again,
+    // both when it leaves the LRs and on a trapped DIR
+    uart_puts(b"[SPI LEVEL] Test 2: re-queued on deactivation while high...\n");
+    clear_spi(vm_id, BALLOON_INTID); // delivered
+    leave_lrs(BALLOON_INTID);
+    let lr_requeue = spi_pending(BALLOON_INTID);
+    clear_spi(vm_id, BALLOON_INTID);
+    trapped_dir(BALLOON_INTID);
+    let dir_requeue = spi_pending(BALLOON_INTID);
+    clear_spi(vm_id, BALLOON_INTID);
+    if !lr_requeue || !dir_requeue {
+        uart_puts(b"[SPI LEVEL] FAILED: level SPI not re-queued\n");
+        drop(t);
+        restore();
+        return;
+    }
+    uart_puts(b"[SPI LEVEL] Test 2 PASSED\n\n");
+
+    // Test 3: after InterruptACK the line is low and nothing is re-queued
+    uart_puts(b"[SPI LEVEL] Test 3: InterruptACK drops the line...\n");
+    t.write(INTERRUPT_ACK, VIRTIO_INT_CONFIG, 4);
+    let low = !spi_line_level(vm_id, BALLOON_INTID);
+    leave_lrs(BALLOON_INTID);
+    trapped_dir(BALLOON_INTID);
+    if !low || spi_pending(BALLOON_INTID) {
+        uart_puts(b"[SPI LEVEL] FAILED: acknowledged SPI re-queued\n");
+        drop(t);
+        restore();
+        return;
+    }
+    uart_puts(b"[SPI LEVEL] Test 3 PASSED\n\n");
+
+    // Test 4: an edge-triggered SPI is never re-queued
+    uart_puts(b"[SPI LEVEL] Test 4: edge-triggered SPI not re-queued...\n");
+    // ICFGR3 field 3 (INTID 51): Int_config[1] set = edge
+    devs.handle_mmio(GICD_BASE + GICD_ICFGR3, 0b10 << 6, 4, true);
+    t.signal_config_change();
+    clear_spi(vm_id, BALLOON_INTID);
+    leave_lrs(BALLOON_INTID);
+    let edge = spi_pending(BALLOON_INTID);
+    t.write(INTERRUPT_ACK, VIRTIO_INT_CONFIG, 4);
+    devs.handle_mmio(GICD_BASE + GICD_ICFGR3, 0, 4, true);
+    drop(t);
+    if edge {
+        uart_puts(b"[SPI LEVEL] FAILED: edge-triggered SPI re-queued\n");
+        restore();
+        return;
+    }
+    uart_puts(b"[SPI LEVEL] Test 4 PASSED\n\n");
+
+    // Test 5: the PL011 line follows UARTMIS
+    uart_puts(b"[SPI LEVEL] Test 5: UART line follows UARTMIS...\n");
+    let mut uart = VirtualUart::new();
+    uart.set_owner_vm(vm_id);
+    uart.write(UARTIMSC, INT_RT, 4);
+    uart.push_rx(b'x');
+    let raised = spi_line_level(vm_id, UART_INTID) && spi_pending(UART_INTID);
+    clear_spi(vm_id, UART_INTID);
+    trapped_dir(UART_INTID);
+    let requeued = spi_pending(UART_INTID);
+    clear_spi(vm_id, UART_INTID);
+    uart.write(UARTICR, INT_RT, 4);
+    let dropped = !spi_line_level(vm_id, UART_INTID);
+    trapped_dir(UART_INTID);
+    if !raised || !requeued || !dropped || spi_pending(UART_INTID) {
+        uart_puts(b"[SPI LEVEL] FAILED: UART line\n");
+        restore();
+        return;
+    }
+    uart_puts(b"[SPI LEVEL] Test 5 PASSED\n\n");
+
+    // Test 6: the sensor alarm line, lowered when the sensor is removed
+    uart_puts(b"[SPI LEVEL] Test 6: sensor alarm line...\n");
+    let mut sensor = VirtualSensor::new();
+    sensor.set_owner_vm(vm_id);
+    sensor.write(SENSOR_THRESHOLD, 70_000, 4);
+    sensor.write(SENSOR_CTRL, 1, 4);
+    sensor.set_temp(75_000);
+    let raised = spi_line_level(vm_id, SENSOR_INTID);
+    clear_spi(vm_id, SENSOR_INTID);
+    leave_lrs(SENSOR_INTID);
+    let requeued = spi_pending(SENSOR_INTID);
+    drop(sensor);
+    let removed = !spi_line_level(vm_id, SENSOR_INTID) && !spi_pending(SENSOR_INTID);
+    restore();
+    if !raised || !requeued || !removed {
+        uart_puts(b"[SPI LEVEL] FAILED: sensor alarm line\n");
+        return;
+    }
+    uart_puts(b"[SPI LEVEL] Test 6 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Level-Triggered SPI Line Test PASSED (6 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
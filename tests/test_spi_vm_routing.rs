@@ -0,0 +1,144 @@
+//! Cross-VM SPI routing tests
+//!
+//! Raises each VM's UART SPI while the other VM is current, and a sensor
+//! alarm owned by VM 1 from VM 0's context, and checks that every SPI lands
+//! only in the owning VM's pending bitmap. VM 1's distributor, written
+//! from VM 0's context, must migrate and wake VM 1's queued SPIs.
+
+use super::cleanup::Cleanup;
+use core::sync::atomic::{AtomicU32, Ordering};
+use hypervisor::arch::aarch64::hypervisor::exception::set_sgi_wake_hook;
+use hypervisor::devices::gic::VirtualGicd;
+use hypervisor::devices::sensor::{SENSOR_BASE, SENSOR_INTID};
+use hypervisor::devices::MmioDevice;
+use hypervisor::global::{
+    clear_spi, current_vcpu_id, inject_spi, inject_spi_current, vcpu_affinity, vm_state,
+    CURRENT_VM_ID, DEVICES, MAX_VMS,
+};
+use hypervisor::uart_puts;
+
+/// PL011 UART SPI
+const UART_INTID: u32 = 33;
+const SENSOR_THRESHOLD: u64 = 0x00C;
+const SENSOR_CTRL: u64 = 0x010;
+/// SPI retargeted and enabled through VM 1's distributor in Test 4
+const SPI_INTID: u32 = 40;
+const GICD_ISENABLER1: u64 = 0x104;
+const GICD_IROUTER: u64 = 0x6100;
+
+/// vCPUs the SGI wake hook was asked to kick
+static WOKEN: AtomicU32 = AtomicU32::new(0);
+
+fn record_wake(targets: u16) {
+    WOKEN.fetch_or(targets as u32, Ordering::Relaxed);
+}
+
+static RECORD_WAKE: fn(u16) = record_wake;
+
+/// Whether `intid` is queued for any vCPU of `vm_id`.
+fn pending_in(vm_id: usize, intid: u32) -> bool {
+    let bit = 1u32 << (intid - 32);
+    vm_state(vm_id)
+        .pending_spis
+        .iter()
+        .any(|s| s.load(Ordering::Acquire) & bit != 0)
+}
+
+fn clear_all(intid: u32) {
+    for vm_id in 0..MAX_VMS {
+        clear_spi(vm_id, intid);
+    }
+}
+
+pub fn run_spi_vm_routing_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Cross-VM SPI Routing Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let saved = CURRENT_VM_ID.load(Ordering::Relaxed);
+    let cleanup = Cleanup::new(|| {
+        clear_all(UART_INTID);
+        clear_all(SENSOR_INTID);
+        clear_all(SPI_INTID);
+        DEVICES[1].reset();
+        CURRENT_VM_ID.store(saved, Ordering::Relaxed);
+    });
+
+    // Test 1: each VM's UART SPI, raised from the other VM, stays its own
+    uart_puts(b"[SPI-ROUTE] Test 1: UART SPI lands in owning VM only...\n");
+    let mut isolated = true;
+    for vm_id in 0..MAX_VMS {
+        clear_all(UART_INTID);
+        CURRENT_VM_ID.store((vm_id + 1) % MAX_VMS, Ordering::Relaxed);
+        inject_spi(vm_id, UART_INTID);
+        isolated &= (0..MAX_VMS).all(|v| pending_in(v, UART_INTID) == (v == vm_id));
+    }
+    if !isolated {
+        uart_puts(b"[SPI-ROUTE] FAILED: UART SPI queued in the wrong VM\n");
+        return;
+    }
+    uart_puts(b"[SPI-ROUTE] Test 1 PASSED\n\n");
+
+    // Test 2: a device of VM 1 signalled while VM 0 is current
+    uart_puts(b"[SPI-ROUTE] Test 2: device SPI follows its owner...\n");
+    clear_all(UART_INTID);
+    CURRENT_VM_ID.store(0, Ordering::Relaxed);
+    DEVICES[1].reset();
+    DEVICES[1].attach_sensor();
+    DEVICES[1].handle_mmio(SENSOR_BASE + SENSOR_THRESHOLD, 70_000, 4, true);
+    DEVICES[1].handle_mmio(SENSOR_BASE + SENSOR_CTRL, 1, 4, true);
+    DEVICES[1].sensor_set_temp(90_000);
+    let raised = pending_in(1, SENSOR_INTID) && !pending_in(0, SENSOR_INTID);
+    DEVICES[1].sensor_set_temp(20_000);
+    let withdrawn = !pending_in(1, SENSOR_INTID);
+    if !raised || !withdrawn {
+        uart_puts(b"[SPI-ROUTE] FAILED: VM 1 sensor alarm misrouted\n");
+        return;
+    }
+    uart_puts(b"[SPI-ROUTE] Test 2 PASSED\n\n");
+
+    // Test 3: inject_spi_current targets the current VM; unknown VMs are
+    // ignored
+    uart_puts(b"[SPI-ROUTE] Test 3: current-VM injection...\n");
+    CURRENT_VM_ID.store(1, Ordering::Relaxed);
+    inject_spi_current(UART_INTID);
+    inject_spi(MAX_VMS, UART_INTID);
+    let current_only = pending_in(1, UART_INTID) && !pending_in(0, UART_INTID);
+    if !current_only {
+        uart_puts(b"[SPI-ROUTE] FAILED: inject_spi_current missed the current VM\n");
+        return;
+    }
+    uart_puts(b"[SPI-ROUTE] Test 3 PASSED\n\n");
+
+    // Test 4: VM 1's distributor, written while VM 0 is current, moves and
+    // wakes VM 1's queued SPI and leaves VM 0's alone
+    uart_puts(b"[SPI-ROUTE] Test 4: GICD writes act on the owning VM...\n");
+    CURRENT_VM_ID.store(0, Ordering::Relaxed);
+    let target = if current_vcpu_id() == 1 { 2 } else { 1 };
+    let bit = 1u32 << (SPI_INTID - 32);
+    let queued = |vm_id: usize, vcpu: usize| {
+        vm_state(vm_id).pending_spis[vcpu].load(Ordering::Acquire) & bit != 0
+    };
+    let mut gicd = VirtualGicd::new();
+    gicd.set_owner_vm(1);
+    vm_state(0).pending_spis[0].fetch_or(bit, Ordering::Release);
+    vm_state(1).pending_spis[0].fetch_or(bit, Ordering::Release);
+    let irouter = GICD_IROUTER + (SPI_INTID as u64 - 32) * 8;
+    gicd.write(irouter, vcpu_affinity(1, target), 8);
+    let migrated = queued(1, target) && !queued(1, 0) && queued(0, 0) && !queued(0, target);
+    WOKEN.store(0, Ordering::Relaxed);
+    set_sgi_wake_hook(Some(&RECORD_WAKE));
+    gicd.write(GICD_ISENABLER1, bit as u64, 4);
+    set_sgi_wake_hook(None);
+    let woken = WOKEN.load(Ordering::Relaxed) == 1 << target;
+    drop(cleanup);
+    if !migrated || !woken {
+        uart_puts(b"[SPI-ROUTE] FAILED: GICD write moved or woke the current VM's SPI\n");
+        return;
+    }
+    uart_puts(b"[SPI-ROUTE] Test 4 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Cross-VM SPI Routing Test PASSED (4 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
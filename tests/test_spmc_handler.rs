@@ -4,7 +4,9 @@
 //! (not the NS-EL2 proxy in ffa::proxy). Uses SmcResult8 directly.
 
 use hypervisor::ffa::{self, smc_forward::SmcResult8};
-use hypervisor::spmc_handler::dispatch_ffa;
+use hypervisor::spmc_handler::{
+    dispatch_ffa, supported_functions, unpack_console_log, CONSOLE_LOG_MAX_CHARS,
+};
 
 fn zero_req(fid: u64) -> SmcResult8 {
     SmcResult8 { x0: fid, x1: 0, x2: 0, x3: 0, x4: 0, x5: 0, x6: 0, x7: 0 }
@@ -187,6 +189,54 @@ pub fn run_tests() {
     assert_eq!(resp.x2, ffa::FFA_DENIED as u64);
     pass += 1;
 
+    // Test 37-39: FFA_CONSOLE_LOG_32 with "hi" packed in w2 -> the two
+    // characters are emitted and SUCCESS returned
+    let mut req = zero_req(ffa::FFA_CONSOLE_LOG_32);
+    req.x1 = 2;
+    req.x2 = u64::from(b'h') | u64::from(b'i') << 8;
+    let mut buf = [0u8; CONSOLE_LOG_MAX_CHARS];
+    assert_eq!(unpack_console_log(&req, &mut buf), Some(2));
+    assert_eq!(&buf[..2], b"hi");
+    crate::uart_puts(b"    console log: ");
+    let resp = dispatch_ffa(&req);
+    crate::uart_puts(b"\n");
+    assert_eq!(resp.x0, ffa::FFA_SUCCESS_32);
+    pass += 3;
+
+    // Test 40: FFA_CONSOLE_LOG_64 packs 8 characters per register
+    let mut req = zero_req(ffa::FFA_CONSOLE_LOG_64);
+    req.x1 = 9;
+    req.x2 = u64::from_le_bytes(*b"SP debug");
+    req.x3 = u64::from(b'!');
+    assert_eq!(unpack_console_log(&req, &mut buf), Some(9));
+    assert_eq!(&buf[..9], b"SP debug!");
+    pass += 1;
+
+    // Test 41-42: zero or oversized character count -> INVALID_PARAMETERS
+    let req = zero_req(ffa::FFA_CONSOLE_LOG_32);
+    let resp = dispatch_ffa(&req);
+    assert_eq!(resp.x2, ffa::FFA_INVALID_PARAMETERS as u64);
+    let mut req = zero_req(ffa::FFA_CONSOLE_LOG_32);
+    req.x1 = 25; // 6 registers x 4 characters max
+    let resp = dispatch_ffa(&req);
+    assert_eq!(resp.x2, ffa::FFA_INVALID_PARAMETERS as u64);
+    pass += 2;
+
+    // Test 43-44: FFA_FEATURES reports every routed call (RXTX_UNMAP
+    // included) as supported, and each one is actually dispatched
+    let features = |fid: u64| {
+        let mut req = zero_req(ffa::FFA_FEATURES);
+        req.x1 = fid;
+        dispatch_ffa(&req).x0
+    };
+    assert!(supported_functions().any(|fid| fid == ffa::FFA_RXTX_UNMAP));
+    for fid in supported_functions() {
+        assert_eq!(features(fid), ffa::FFA_SUCCESS_32);
+        let resp = dispatch_ffa(&zero_req(fid));
+        assert!(resp.x0 != ffa::FFA_ERROR || resp.x2 != ffa::FFA_NOT_SUPPORTED as u64);
+    }
+    pass += 2;
+
     crate::uart_puts(b"    ");
     crate::print_u32(pass);
     crate::uart_puts(b" assertions passed\n");
@@ -0,0 +1,83 @@
+//! Stage-2 walker VTTBR validation tests
+//!
+//! Loads VTTBR_EL2 with garbage (a table address outside the heap, one in
+//! heap memory not yet handed out, an impossible VMID) and checks that
+//! `Stage2Walker::from_vttbr()` reports no Stage-2 and refuses to walk,
+//! while a real heap-allocated table is still accepted.
+
+use super::cleanup::Cleanup;
+use hypervisor::arch::aarch64::mm::mmu::{DynamicIdentityMapper, MemoryAttribute, Stage2Config};
+use hypervisor::ffa::stage2_walker::Stage2Walker;
+use hypervisor::platform::{HEAP_SIZE, HEAP_START};
+use hypervisor::uart_puts;
+
+/// Guest RAM base, mapped in the test table
+const IPA: u64 = 0x4000_0000;
+/// Outside the hypervisor heap
+const GARBAGE_TABLE: u64 = 0xDEAD_0000;
+/// Last heap page, never reached by the bump allocator in the test run
+const UNALLOCATED_TABLE: u64 = HEAP_START + HEAP_SIZE - 0x1000;
+
+fn read_vttbr() -> u64 {
+    let vttbr: u64;
+    unsafe {
+        core::arch::asm!("mrs {}, vttbr_el2", out(reg) vttbr, options(nomem, nostack));
+    }
+    vttbr
+}
+
+fn write_vttbr(vttbr: u64) {
+    unsafe {
+        core::arch::asm!("msr vttbr_el2, {}", "isb", in(reg) vttbr, options(nomem, nostack));
+    }
+}
+
+/// Whether a walker built from `vttbr` claims a Stage-2 or finds `IPA`.
+fn walker_accepts(vttbr: u64) -> bool {
+    write_vttbr(vttbr);
+    let walker = Stage2Walker::from_vttbr();
+    walker.has_stage2() || walker.read_sw_bits(IPA).is_some()
+}
+
+pub fn run_stage2_walker_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Stage-2 Walker VTTBR Validation Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let saved = read_vttbr();
+    let mut mapper = DynamicIdentityMapper::new();
+    let mapped = mapper.map_region(IPA, 0x20_0000, MemoryAttribute::Normal);
+    let valid = Stage2Config::new_with_vmid(mapper.vttbr(), 1).vttbr;
+    let cleanup = Cleanup::new(|| write_vttbr(saved));
+
+    // Test 1: a table address outside the heap is not treated as a Stage-2
+    uart_puts(b"[S2-WALK] Test 1: garbage table address rejected...\n");
+    if walker_accepts(GARBAGE_TABLE) {
+        uart_puts(b"[S2-WALK] FAILED: garbage VTTBR accepted\n");
+        return;
+    }
+    uart_puts(b"[S2-WALK] Test 1 PASSED\n\n");
+
+    // Test 2: a table in unallocated heap or a VMID wider than 8 bits is
+    // garbage too
+    uart_puts(b"[S2-WALK] Test 2: unallocated table / bad VMID rejected...\n");
+    if walker_accepts(UNALLOCATED_TABLE) || walker_accepts(valid | 0x1234 << 48) {
+        uart_puts(b"[S2-WALK] FAILED: implausible VTTBR accepted\n");
+        return;
+    }
+    uart_puts(b"[S2-WALK] Test 2 PASSED\n\n");
+
+    // Test 3: a real Stage-2 built from the heap is still walked
+    uart_puts(b"[S2-WALK] Test 3: valid Stage-2 accepted...\n");
+    let accepted = walker_accepts(valid);
+    drop(cleanup);
+    if mapped.is_err() || !accepted {
+        uart_puts(b"[S2-WALK] FAILED: valid VTTBR rejected\n");
+        return;
+    }
+    uart_puts(b"[S2-WALK] Test 3 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Stage-2 Walker VTTBR Validation Test PASSED (3 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,90 @@
+//! Trapped MSR/MRS emulation tests
+//!
+//! Builds EC=0x18 ISS encodings by hand and feeds them through
+//! handle_msr_mrs_trap() to check the emulated register semantics.
+
+use hypervisor::arch::aarch64::defs::*;
+use hypervisor::arch::aarch64::hypervisor::exception::handle_msr_mrs_trap;
+use hypervisor::arch::aarch64::regs::VcpuContext;
+use hypervisor::arch::aarch64::vcpu_arch_state::VcpuArchState;
+use hypervisor::uart_puts;
+
+/// Build an ESR_EL2 value for a trapped MSR/MRS of S<op0>_<op1>_C<crn>_C<crm>_<op2>.
+fn sysreg_esr(op0: u32, op1: u32, crn: u32, crm: u32, op2: u32, rt: u32, is_read: bool) -> u64 {
+    let iss = (op0 << 20)
+        | (op2 << 17)
+        | (op1 << 14)
+        | (crn << 10)
+        | (rt << 5)
+        | (crm << 1)
+        | (is_read as u32);
+    (0x18u64 << ESR_EC_SHIFT) | iss as u64
+}
+
+pub fn run_sysreg_trap_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Sysreg Trap Emulation Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let mut ctx = VcpuContext::default();
+    let sre = ICC_SRE_SRE as u64;
+
+    // Test 1: MRS ICC_SRE_EL1 reads SRE=1
+    uart_puts(b"[SYSREG] Test 1: ICC_SRE_EL1 read...\n");
+    ctx.gp_regs.x0 = 0;
+    handle_msr_mrs_trap(&mut ctx, sysreg_esr(3, 0, 12, 12, 5, 0, true));
+    if ctx.gp_regs.x0 & sre == 0 {
+        uart_puts(b"[SYSREG] FAILED: ICC_SRE_EL1.SRE should read as 1\n");
+        return;
+    }
+    uart_puts(b"[SYSREG] Test 1 PASSED\n\n");
+
+    // Test 2: MSR ICC_SRE_EL1 = 0 is ignored, SRE stays set
+    uart_puts(b"[SYSREG] Test 2: ICC_SRE_EL1 clear ignored...\n");
+    ctx.gp_regs.x1 = 0;
+    handle_msr_mrs_trap(&mut ctx, sysreg_esr(3, 0, 12, 12, 5, 1, false));
+    ctx.gp_regs.x2 = 0;
+    handle_msr_mrs_trap(&mut ctx, sysreg_esr(3, 0, 12, 12, 5, 2, true));
+    let expected = (ICC_SRE_SRE | ICC_SRE_DFB | ICC_SRE_DIB) as u64;
+    if ctx.gp_regs.x2 != expected {
+        uart_puts(b"[SYSREG] FAILED: ICC_SRE_EL1 should remain SRE|DFB|DIB\n");
+        return;
+    }
+    uart_puts(b"[SYSREG] Test 2 PASSED\n\n");
+
+    // CNTKCTL_EL1 = S3_0_C14_C1_0. Keep the live state so the test leaves
+    // EL1 registers as it found them.
+    let mut orig = VcpuArchState::new();
+    orig.save();
+
+    // Test 3: MSR CNTKCTL_EL1 lands in per-vCPU arch state on save
+    uart_puts(b"[SYSREG] Test 3: CNTKCTL_EL1 write saved...\n");
+    ctx.gp_regs.x3 = CNTKCTL_EL0VCTEN | (1 << 17);
+    handle_msr_mrs_trap(&mut ctx, sysreg_esr(3, 0, 14, 1, 0, 3, false));
+    let mut vcpu_state = orig;
+    vcpu_state.save();
+    if vcpu_state.cntkctl_el1 != CNTKCTL_EL0VCTEN {
+        orig.restore();
+        uart_puts(b"[SYSREG] FAILED: CNTKCTL_EL1 not stored (or RES0 bit kept)\n");
+        return;
+    }
+    uart_puts(b"[SYSREG] Test 3 PASSED\n\n");
+
+    // Test 4: another vCPU's value is replaced by ours on restore
+    uart_puts(b"[SYSREG] Test 4: CNTKCTL_EL1 restored on switch...\n");
+    ctx.gp_regs.x4 = 0;
+    handle_msr_mrs_trap(&mut ctx, sysreg_esr(3, 0, 14, 1, 0, 4, false));
+    vcpu_state.restore();
+    ctx.gp_regs.x5 = 0;
+    handle_msr_mrs_trap(&mut ctx, sysreg_esr(3, 0, 14, 1, 0, 5, true));
+    orig.restore();
+    if ctx.gp_regs.x5 != CNTKCTL_EL0VCTEN {
+        uart_puts(b"[SYSREG] FAILED: CNTKCTL_EL1 not restored\n");
+        return;
+    }
+    uart_puts(b"[SYSREG] Test 4 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Sysreg Trap Emulation Test PASSED (4 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -1,4 +1,11 @@
+use hypervisor::arch::aarch64::defs::ESR_EC_SHIFT;
+use hypervisor::arch::aarch64::hypervisor::exception::{
+    handle_msr_mrs_trap, handle_wfi_with_timer_injection,
+};
+use hypervisor::arch::aarch64::peripherals::gicv3::{GicV3VirtualInterface, PTIMER_IRQ};
 use hypervisor::arch::aarch64::peripherals::timer;
+use hypervisor::arch::aarch64::regs::VcpuContext;
+use hypervisor::arch::aarch64::vcpu_arch_state::VcpuArchState;
 ///! Simple timer interrupt test at EL2
 ///!
 ///! This test demonstrates handling timer interrupts at EL2 (hypervisor level)
@@ -85,3 +92,140 @@ pub fn run_timer_test() {
     uart_puts(b"\n[TIMER TEST] Test complete\n");
     uart_puts(b"========================================\n\n");
 }
+
+const NUM_LRS: u32 = 4;
+
+/// CNTP_TVAL_EL0, CNTP_CTL_EL0, CNTP_CVAL_EL0 (S3_3_C14_C2_<op2>)
+const CNTP_TVAL: u32 = 0;
+const CNTP_CTL: u32 = 1;
+const CNTP_CVAL: u32 = 2;
+
+/// ESR_EL2 of a trapped MSR/MRS of CNTP_<op2> through x`rt`.
+fn cntp_esr(op2: u32, rt: u32, is_read: bool) -> u64 {
+    let iss = (3 << 20) | (op2 << 17) | (3 << 14) | (14 << 10) | (rt << 5) | (2 << 1);
+    (0x18u64 << ESR_EC_SHIFT) | (iss | is_read as u32) as u64
+}
+
+/// Guest MRS of CNTP_<op2>
+fn read_cntp(ctx: &mut VcpuContext, op2: u32) -> u64 {
+    ctx.gp_regs.x0 = u64::MAX;
+    handle_msr_mrs_trap(ctx, cntp_esr(op2, 0, true));
+    ctx.gp_regs.x0
+}
+
+/// Guest MSR of CNTP_<op2>
+fn write_cntp(ctx: &mut VcpuContext, op2: u32, value: u64) {
+    ctx.gp_regs.x1 = value;
+    handle_msr_mrs_trap(ctx, cntp_esr(op2, 1, false));
+}
+
+fn physical_count() -> u64 {
+    let count: u64;
+    unsafe {
+        core::arch::asm!("isb", "mrs {}, cntpct_el0", out(reg) count, options(nostack, nomem))
+    };
+    count
+}
+
+/// True if any List Register holds PTIMER_IRQ.
+fn ptimer_in_lrs() -> bool {
+    (0..NUM_LRS).any(|i| {
+        let lr = GicV3VirtualInterface::read_lr(i);
+        GicV3VirtualInterface::get_lr_state(lr) != 0
+            && GicV3VirtualInterface::get_lr_intid(lr) == PTIMER_IRQ
+    })
+}
+
+/// Run the emulated guest physical timer (CNTP) test
+pub fn run_ptimer_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Guest Physical Timer (CNTP) Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let saved_lrs: [u64; NUM_LRS as usize] =
+        core::array::from_fn(|i| GicV3VirtualInterface::read_lr(i as u32));
+    let saved_vctl = timer::get_ctl();
+    let saved_pctl = timer::get_ptimer_ctl();
+    let saved_pcval = timer::get_ptimer_cval();
+    let restore = || {
+        timer::set_ptimer_ctl(saved_pctl);
+        timer::set_ptimer_cval(saved_pcval);
+        timer::set_ctl(saved_vctl);
+        for (i, lr) in saved_lrs.iter().enumerate() {
+            GicV3VirtualInterface::write_lr(i as u32, *lr);
+        }
+    };
+    let mut ctx = VcpuContext::default();
+    timer::set_ctl(0); // keep the virtual timer out of the WFI path
+
+    // Test 1: CVAL reads back; a TVAL write sets CVAL = CNTPCT + TVAL
+    uart_puts(b"[PTIMER] Test 1: CNTP_CVAL/TVAL emulation...\n");
+    write_cntp(&mut ctx, CNTP_CTL, 0);
+    write_cntp(&mut ctx, CNTP_CVAL, 0x1234_5678_9ABC);
+    let cval = read_cntp(&mut ctx, CNTP_CVAL);
+    let before = physical_count();
+    write_cntp(&mut ctx, CNTP_TVAL, 0x100_0000);
+    let after = physical_count();
+    let armed = read_cntp(&mut ctx, CNTP_CVAL);
+    if cval != 0x1234_5678_9ABC || armed < before + 0x100_0000 || armed > after + 0x100_0000 {
+        restore();
+        uart_puts(b"[PTIMER] FAILED: CNTP_CVAL/TVAL not emulated\n");
+        return;
+    }
+    uart_puts(b"[PTIMER] Test 1 PASSED\n\n");
+
+    // Test 2: ISTATUS ignores writes and follows the comparator; a masked
+    // timer is not pending
+    uart_puts(b"[PTIMER] Test 2: CNTP_CTL enable/mask bits...\n");
+    write_cntp(&mut ctx, CNTP_CTL, 0b111); // ENABLE | IMASK | (ISTATUS)
+    let not_expired = read_cntp(&mut ctx, CNTP_CTL);
+    write_cntp(&mut ctx, CNTP_CVAL, 0);
+    let expired = read_cntp(&mut ctx, CNTP_CTL);
+    if not_expired != 0b011 || expired != 0b111 || timer::is_guest_ptimer_pending() {
+        restore();
+        uart_puts(b"[PTIMER] FAILED: CNTP_CTL ENABLE/IMASK/ISTATUS wrong\n");
+        return;
+    }
+    uart_puts(b"[PTIMER] Test 2 PASSED\n\n");
+
+    // Test 3: an armed, expired, unmasked timer wakes WFI with PPI 30 and
+    // is masked until the guest re-arms it
+    uart_puts(b"[PTIMER] Test 3: expired CNTP injects PTIMER_IRQ...\n");
+    for i in 0..NUM_LRS {
+        GicV3VirtualInterface::write_lr(i, 0);
+    }
+    write_cntp(&mut ctx, CNTP_CTL, 0b001); // ENABLE
+    ctx.pc = 0x4000_4000;
+    handle_wfi_with_timer_injection(&mut ctx); // first WFI at this PC
+    handle_wfi_with_timer_injection(&mut ctx);
+    let injected = ptimer_in_lrs();
+    let masked = read_cntp(&mut ctx, CNTP_CTL) & 0b010 != 0;
+    if !injected || !masked {
+        restore();
+        uart_puts(b"[PTIMER] FAILED: expired CNTP did not inject PPI 30\n");
+        return;
+    }
+    uart_puts(b"[PTIMER] Test 3 PASSED\n\n");
+
+    // Test 4: each vCPU's comparator comes back on restore
+    uart_puts(b"[PTIMER] Test 4: CNTP restored on vCPU switch...\n");
+    write_cntp(&mut ctx, CNTP_CVAL, u64::MAX);
+    write_cntp(&mut ctx, CNTP_CTL, 0b011);
+    let mut vcpu_a = VcpuArchState::new();
+    vcpu_a.save();
+    write_cntp(&mut ctx, CNTP_CVAL, 0x5555_0000);
+    write_cntp(&mut ctx, CNTP_CTL, 0);
+    vcpu_a.restore();
+    let switched =
+        read_cntp(&mut ctx, CNTP_CVAL) == u64::MAX && read_cntp(&mut ctx, CNTP_CTL) == 0b011;
+    restore();
+    if !switched {
+        uart_puts(b"[PTIMER] FAILED: CNTP state not restored\n");
+        return;
+    }
+    uart_puts(b"[PTIMER] Test 4 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Guest Physical Timer (CNTP) Test PASSED (4 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,103 @@
+//! Unbacked IPA data abort injection tests (linux_guest builds)
+//!
+//! Runs a stub guest that installs its own vector table and then accesses
+//! an IPA with no RAM or device behind it. Instead of the vCPU stopping,
+//! the guest's current-EL synchronous vector must run with ESR_EL1,
+//! FAR_EL1 and ELR_EL1 describing the faulting access. A guest without a
+//! vector table still stops.
+
+use hypervisor::arch::aarch64::defs::{BLOCK_MASK_2MB, BLOCK_SIZE_2MB, EC_DABT_SAME, ESR_EC_SHIFT};
+use hypervisor::uart_puts;
+use hypervisor::vm::Vm;
+
+/// IPA outside the VM's Stage-2 map and every device window
+const UNBACKED_IPA: u64 = 0x0C00_0000;
+/// Offset of the faulting LDXR in the stub guest
+const FAULT_OFFSET: u64 = 8;
+/// ESR_ELx.IL (32-bit instruction), DFSC of a synchronous external abort
+const ESR_IL: u64 = 1 << 25;
+const DFSC_SYNC_EXTERNAL_ABORT: u64 = 0x10;
+
+/// Entry at offset 0, current-EL SP_ELx synchronous vector at 0x200.
+#[repr(C, align(2048))]
+struct AbortGuest {
+    code: [u32; 0x280 / 4],
+}
+
+const fn abort_guest() -> AbortGuest {
+    let mut code = [0u32; 0x280 / 4];
+    code[0] = 0xd518c002; // msr vbar_el1, x2
+    code[1] = 0xd5033fdf; // isb
+    code[2] = 0xc85f7c20; // ldxr x0, [x1] (not decodable as MMIO)
+    code[3] = 0xd2800020; // mov x0, #1 (exit hypercall)
+    code[4] = 0xd4000002; // hvc #0
+    code[5] = 0x14000000; // b .
+    code[128] = 0xd5385213; // mrs x19, esr_el1
+    code[129] = 0xd5386014; // mrs x20, far_el1
+    code[130] = 0xd5384035; // mrs x21, elr_el1
+    code[131] = 0xd2800020; // mov x0, #1 (exit hypercall)
+    code[132] = 0xd4000002; // hvc #0
+    code[133] = 0x14000000; // b .
+    AbortGuest { code }
+}
+
+static ABORT_GUEST: AbortGuest = abort_guest();
+
+/// Run the stub guest on vCPU 0 with `vbar` as its vector table; returns
+/// the run result and (x19, x20, x21) as the vector left them.
+fn run_guest(vm: &mut Vm, vbar: u64) -> (Result<(), &'static str>, [u64; 3]) {
+    let entry = &ABORT_GUEST.code as *const _ as u64;
+    let Some(vcpu) = vm.vcpu_mut(0) else {
+        return (Err("no vCPU"), [0; 3]);
+    };
+    vcpu.context_mut().pc = entry;
+    let regs = &mut vcpu.context_mut().gp_regs;
+    regs.x1 = UNBACKED_IPA;
+    regs.x2 = vbar;
+    regs.x19 = 0;
+    regs.x20 = 0;
+    regs.x21 = 0;
+    let result = vcpu.run();
+    let regs = &vcpu.context_mut().gp_regs;
+    (result, [regs.x19, regs.x20, regs.x21])
+}
+
+pub fn run_unbacked_abort_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Unbacked IPA Abort Injection Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let entry = &ABORT_GUEST.code as *const _ as u64;
+    let mut vm = Vm::new(0);
+    vm.init_memory(entry & !BLOCK_MASK_2MB, BLOCK_SIZE_2MB);
+    if vm.create_vcpu(0).is_err() {
+        uart_puts(b"[UNBACKED] FAILED: create_vcpu\n");
+        return;
+    }
+
+    // Test 1: the guest's sync vector runs with the abort described in
+    // ESR_EL1/FAR_EL1 and ELR_EL1 at the faulting load
+    uart_puts(b"[UNBACKED] Test 1: guest vector takes the abort...\n");
+    let (result, [esr, far, elr]) = run_guest(&mut vm, entry);
+    // A load: WnR clear
+    let expected_esr = (EC_DABT_SAME << ESR_EC_SHIFT) | ESR_IL | DFSC_SYNC_EXTERNAL_ABORT;
+    if result.is_err() || esr != expected_esr || far != UNBACKED_IPA || elr != entry + FAULT_OFFSET
+    {
+        uart_puts(b"[UNBACKED] FAILED: abort not delivered to the guest vector\n");
+        return;
+    }
+    uart_puts(b"[UNBACKED] Test 1 PASSED\n\n");
+
+    // Test 2: with no vector table installed the vCPU stops instead
+    uart_puts(b"[UNBACKED] Test 2: no vector table, vCPU stops...\n");
+    let (result, _) = run_guest(&mut vm, 0);
+    if result.is_ok() {
+        uart_puts(b"[UNBACKED] FAILED: abort injected without a vector table\n");
+        return;
+    }
+    uart_puts(b"[UNBACKED] Test 2 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Unbacked IPA Abort Injection Test PASSED (2 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,155 @@
+//! VirtioBalloon device tests
+//!
+//! Posts a memory statistics buffer on the balloon stats queue (laid out in
+//! hypervisor memory, identity-mapped) and checks the parsed values and the
+//! hold/request cycle of the stats buffer.
+
+use core::sync::atomic::Ordering;
+use hypervisor::devices::virtio::balloon::{
+    VirtioBalloon, STATSQ, VIRTIO_BALLOON_STAT_SIZE, VIRTIO_BALLOON_S_MEMFREE,
+    VIRTIO_BALLOON_S_MEMTOT,
+};
+use hypervisor::devices::virtio::mmio::VirtioMmioTransport;
+use hypervisor::devices::virtio::VirtioDevice;
+use hypervisor::devices::MmioDevice;
+use hypervisor::global::current_vm_state;
+use hypervisor::uart_puts;
+
+const QUEUE_SIZE: usize = 8;
+const NUM_STATS: usize = 3;
+const FREE_MEM: u64 = 0x1234_5000;
+const TOTAL_MEM: u64 = 0x4000_0000;
+/// Virtio-mmio slot 6 layout (VIRTIO_SLOT_BALLOON)
+const BALLOON_BASE: u64 = 0x0a00_0c00;
+const BALLOON_INTID: u32 = 54;
+
+/// Descriptor table + avail ring + used ring + one stats buffer.
+#[repr(C, align(4096))]
+struct StatsQueueMem {
+    desc: [[u8; 16]; QUEUE_SIZE],
+    avail: [u16; 2 + QUEUE_SIZE],
+    _pad: [u16; 2],
+    used: [u32; 1 + 2 * QUEUE_SIZE],
+    stats_buf: [u8; NUM_STATS * VIRTIO_BALLOON_STAT_SIZE],
+}
+
+static mut STATSQ_MEM: StatsQueueMem = StatsQueueMem {
+    desc: [[0; 16]; QUEUE_SIZE],
+    avail: [0; 2 + QUEUE_SIZE],
+    _pad: [0; 2],
+    used: [0; 1 + 2 * QUEUE_SIZE],
+    stats_buf: [0; NUM_STATS * VIRTIO_BALLOON_STAT_SIZE],
+};
+
+/// Encode one virtio_balloon_stat entry at index `i` of the stats buffer.
+fn put_stat(buf: &mut [u8], i: usize, tag: u16, val: u64) {
+    let e = &mut buf[i * VIRTIO_BALLOON_STAT_SIZE..(i + 1) * VIRTIO_BALLOON_STAT_SIZE];
+    e[0..2].copy_from_slice(&tag.to_le_bytes());
+    e[2..10].copy_from_slice(&val.to_le_bytes());
+}
+
+pub fn run_virtio_balloon_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  VirtioBalloon Device Test\n");
+    uart_puts(b"========================================\n\n");
+
+    // Test 1: device_id, queues, STATS_VQ feature, config space
+    uart_puts(b"[BALLOON] Test 1: device_id / features / config...\n");
+    let mut balloon = VirtioBalloon::new();
+    balloon.set_target_pages(256);
+    balloon.config_write(4, 128, 4);
+    if balloon.device_id() != 5
+        || balloon.num_queues() != 3
+        || balloon.device_features() & (1 << 1) == 0
+        || balloon.config_read(0, 4) != 256
+        || balloon.actual_pages() != 128
+    {
+        uart_puts(b"[BALLOON] FAILED: device identity/config wrong\n");
+        return;
+    }
+    uart_puts(b"[BALLOON] Test 1 PASSED\n\n");
+
+    // Set up statsq through the MMIO transport
+    let mut transport = VirtioMmioTransport::new(BALLOON_BASE, VirtioBalloon::new(), BALLOON_INTID);
+    let mem = &raw mut STATSQ_MEM;
+    let (desc_addr, avail_addr, used_addr, buf_addr) = unsafe {
+        (
+            (*mem).desc.as_ptr() as u64,
+            (*mem).avail.as_ptr() as u64,
+            (*mem).used.as_ptr() as u64,
+            (*mem).stats_buf.as_ptr() as u64,
+        )
+    };
+    unsafe {
+        let buf = &mut (*mem).stats_buf;
+        put_stat(buf, 0, VIRTIO_BALLOON_S_MEMFREE, FREE_MEM);
+        put_stat(buf, 1, VIRTIO_BALLOON_S_MEMTOT, TOTAL_MEM);
+        put_stat(buf, 2, 0xFF, 0xDEAD); // unknown tag, ignored
+
+        // desc[0] = { addr: stats_buf, len: 30, flags: 0 (driver-readable) }
+        let d = &mut (*mem).desc[0];
+        d[0..8].copy_from_slice(&buf_addr.to_le_bytes());
+        d[8..12].copy_from_slice(&((NUM_STATS * VIRTIO_BALLOON_STAT_SIZE) as u32).to_le_bytes());
+        // avail ring: ring[0] = desc 0, idx = 1
+        (*mem).avail[2] = 0;
+        (*mem).avail[1] = 1;
+    }
+    transport.write(0x030, STATSQ as u64, 4); // QueueSel = statsq
+    transport.write(0x038, QUEUE_SIZE as u64, 4);
+    transport.write(0x080, desc_addr & 0xFFFF_FFFF, 4);
+    transport.write(0x084, desc_addr >> 32, 4);
+    transport.write(0x090, avail_addr & 0xFFFF_FFFF, 4);
+    transport.write(0x094, avail_addr >> 32, 4);
+    transport.write(0x0A0, used_addr & 0xFFFF_FFFF, 4);
+    transport.write(0x0A4, used_addr >> 32, 4);
+    transport.write(0x044, 1, 4); // QueueReady
+
+    let vs = current_vm_state();
+    let spi_bit = 1u32 << (BALLOON_INTID - 32);
+    let clear_spi = || {
+        for spis in vs.pending_spis.iter() {
+            spis.fetch_and(!spi_bit, Ordering::Relaxed);
+        }
+    };
+
+    // Test 2: guest notify -> stats parsed
+    uart_puts(b"[BALLOON] Test 2: parse stats buffer...\n");
+    transport.write(0x050, STATSQ as u64, 4); // QueueNotify
+    clear_spi();
+    let stats = transport.balloon().stats();
+    if stats.free_memory != FREE_MEM || stats.total_memory != TOTAL_MEM || stats.updates != 1 {
+        uart_puts(b"[BALLOON] FAILED: stats() does not match posted buffer\n");
+        return;
+    }
+    uart_puts(b"[BALLOON] Test 2 PASSED\n\n");
+
+    // Test 3: buffer held until the host asks for new stats
+    uart_puts(b"[BALLOON] Test 3: stats buffer held...\n");
+    let used_idx = unsafe { core::ptr::read_volatile(&(*mem).used[0]) >> 16 };
+    if used_idx != 0 {
+        uart_puts(b"[BALLOON] FAILED: stats buffer returned before request\n");
+        return;
+    }
+    uart_puts(b"[BALLOON] Test 3 PASSED\n\n");
+
+    // Test 4: request_stats returns the buffer exactly once
+    uart_puts(b"[BALLOON] Test 4: request_stats...\n");
+    let requested = transport.request_stats();
+    let (used_idx, used_id) = unsafe {
+        (
+            core::ptr::read_volatile(&(*mem).used[0]) >> 16,
+            core::ptr::read_volatile(&(*mem).used[1]),
+        )
+    };
+    let again = transport.request_stats();
+    clear_spi();
+    if !requested || used_idx != 1 || used_id != 0 || again {
+        uart_puts(b"[BALLOON] FAILED: stats buffer not returned once\n");
+        return;
+    }
+    uart_puts(b"[BALLOON] Test 4 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  VirtioBalloon Device Test PASSED (4 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,156 @@
+//! Virtio-blk write path tests
+//!
+//! Drives a read-write virtio-blk disk through its MMIO transport: a
+//! sector written with VIRTIO_BLK_T_OUT reads back through a separate
+//! VIRTIO_BLK_T_IN request, writes reaching past the image fail with
+//! VIRTIO_BLK_S_IOERR without touching it, FLUSH succeeds and unknown
+//! request types complete with VIRTIO_BLK_S_UNSUPP.
+
+use super::virtio_fixture::{
+    clear_spis, last_used_len, setup_queue, submit, ReqQueueMem, SECTOR, VIRTIO_BLK_S_IOERR,
+    VIRTIO_BLK_S_OK, VIRTIO_BLK_T_IN, VIRTIO_BLK_T_OUT,
+};
+use hypervisor::devices::DeviceManager;
+use hypervisor::platform::virtio_slot;
+use hypervisor::uart_puts;
+
+const DISK_SECTORS: u64 = 8;
+const DISK_SIZE: usize = SECTOR * DISK_SECTORS as usize;
+const VIRTIO_BLK_F_FLUSH: u64 = 1 << 9;
+const VIRTIO_BLK_T_FLUSH: u32 = 4;
+const VIRTIO_BLK_T_DISCARD: u32 = 11;
+const VIRTIO_BLK_S_UNSUPP: u8 = 2;
+
+#[repr(C, align(4096))]
+struct Disk([u8; DISK_SIZE]);
+
+static mut QUEUE: ReqQueueMem = ReqQueueMem::EMPTY;
+static mut IMAGE: Disk = Disk([0; DISK_SIZE]);
+
+pub fn run_virtio_blk_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Virtio-blk Write Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let image = &raw mut IMAGE;
+    let queue = &raw mut QUEUE;
+    let mut dm = DeviceManager::new();
+    dm.attach_virtio_blk(image as u64, DISK_SIZE as u64);
+    let (base, intid) = virtio_slot(0);
+    setup_queue(&mut dm, base, queue);
+
+    // Test 1: VIRTIO_BLK_F_FLUSH is offered
+    uart_puts(b"[VBLK] Test 1: flush feature advertised...\n");
+    dm.handle_mmio(base + 0x014, 0, 4, true); // DeviceFeaturesSel
+    let features = dm.handle_mmio(base + 0x010, 0, 4, false).unwrap_or(0);
+    if features & VIRTIO_BLK_F_FLUSH == 0 {
+        uart_puts(b"[VBLK] FAILED: VIRTIO_BLK_F_FLUSH not offered\n");
+        return;
+    }
+    uart_puts(b"[VBLK] Test 1 PASSED\n\n");
+
+    // Test 2: a sector written with OUT lands at sector * 512 and reads
+    // back through a separate IN request; neighbouring sectors untouched
+    uart_puts(b"[VBLK] Test 2: write sector, read it back...\n");
+    let pattern = |i: usize| (i as u8).wrapping_mul(7) ^ 0xA5;
+    unsafe {
+        for (i, b) in (*queue).data.iter_mut().enumerate() {
+            *b = pattern(i);
+        }
+    }
+    let write_status = submit(&mut dm, base, queue, VIRTIO_BLK_T_OUT, 3, SECTOR as u32, 0);
+    let write_len = last_used_len(queue);
+    unsafe { (*queue).data = [0; SECTOR] };
+    let read_status = submit(&mut dm, base, queue, VIRTIO_BLK_T_IN, 3, SECTOR as u32, 1);
+    let read_len = last_used_len(queue);
+    let (read_back, in_place, neighbours_clean) = unsafe {
+        let img = &(*image).0;
+        (
+            (*queue)
+                .data
+                .iter()
+                .enumerate()
+                .all(|(i, &b)| b == pattern(i)),
+            img[3 * SECTOR..4 * SECTOR]
+                .iter()
+                .enumerate()
+                .all(|(i, &b)| b == pattern(i)),
+            img[..3 * SECTOR]
+                .iter()
+                .chain(&img[4 * SECTOR..])
+                .all(|&b| b == 0),
+        )
+    };
+    if write_status != VIRTIO_BLK_S_OK
+        || write_len != 1
+        || read_status != VIRTIO_BLK_S_OK
+        || read_len != SECTOR as u32 + 1
+        || !read_back
+        || !in_place
+        || !neighbours_clean
+    {
+        uart_puts(b"[VBLK] FAILED: written sector does not read back\n");
+        return;
+    }
+    uart_puts(b"[VBLK] Test 2 PASSED\n\n");
+
+    // Test 3: writes reaching past disk_size fail as a whole, including
+    // ones whose sector * 512 overflows; the image is never touched
+    uart_puts(b"[VBLK] Test 3: out-of-range writes rejected...\n");
+    unsafe { (*queue).data = [0xEE; SECTOR] };
+    let straddle = submit(
+        &mut dm,
+        base,
+        queue,
+        VIRTIO_BLK_T_OUT,
+        DISK_SECTORS - 1,
+        1024,
+        2,
+    );
+    let beyond = submit(&mut dm, base, queue, VIRTIO_BLK_T_OUT, DISK_SECTORS, 512, 3);
+    let overflow = submit(
+        &mut dm,
+        base,
+        queue,
+        VIRTIO_BLK_T_OUT,
+        u64::MAX / 256,
+        512,
+        4,
+    );
+    let untouched = unsafe { !(*image).0.contains(&0xEE) };
+    if straddle != VIRTIO_BLK_S_IOERR
+        || beyond != VIRTIO_BLK_S_IOERR
+        || overflow != VIRTIO_BLK_S_IOERR
+        || !untouched
+    {
+        uart_puts(b"[VBLK] FAILED: out-of-range write not rejected\n");
+        return;
+    }
+    uart_puts(b"[VBLK] Test 3 PASSED\n\n");
+
+    // Test 4: FLUSH completes with VIRTIO_BLK_S_OK
+    uart_puts(b"[VBLK] Test 4: flush succeeds...\n");
+    let flush = submit(&mut dm, base, queue, VIRTIO_BLK_T_FLUSH, 0, 0, 5);
+    if flush != VIRTIO_BLK_S_OK || last_used_len(queue) != 1 {
+        clear_spis(&[intid]);
+        uart_puts(b"[VBLK] FAILED: flush did not complete OK\n");
+        return;
+    }
+    uart_puts(b"[VBLK] Test 4 PASSED\n\n");
+
+    // Test 5: an unknown request type completes with VIRTIO_BLK_S_UNSUPP
+    // and leaves the image alone
+    uart_puts(b"[VBLK] Test 5: unsupported request type...\n");
+    let unsupp = submit(&mut dm, base, queue, VIRTIO_BLK_T_DISCARD, 0, 512, 6);
+    let untouched = unsafe { !(*image).0.contains(&0xEE) };
+    clear_spis(&[intid]);
+    if unsupp != VIRTIO_BLK_S_UNSUPP || !untouched {
+        uart_puts(b"[VBLK] FAILED: unknown request type not rejected\n");
+        return;
+    }
+    uart_puts(b"[VBLK] Test 5 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Virtio-blk Write Test PASSED (5 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
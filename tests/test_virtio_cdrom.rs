@@ -0,0 +1,132 @@
+//! Read-only virtio-blk ("CD-ROM") tests
+//!
+//! Attaches a read-write disk and a read-only CD-ROM to one device manager
+//! and checks that they sit in distinct virtio-mmio slots with their own
+//! INTIDs, that only the CD-ROM advertises VIRTIO_BLK_F_RO, and that a
+//! write request fails on the CD-ROM (image untouched) while reads work.
+
+use super::virtio_fixture::{
+    clear_spis, setup_queue, submit, ReqQueueMem, SECTOR, VIRTIO_BLK_S_IOERR, VIRTIO_BLK_S_OK,
+    VIRTIO_BLK_T_IN, VIRTIO_BLK_T_OUT,
+};
+use hypervisor::devices::DeviceManager;
+use hypervisor::platform::virtio_slot;
+use hypervisor::uart_puts;
+
+const DISK_SIZE: usize = 4096;
+const VIRTIO_BLK_F_RO: u64 = 1 << 5;
+
+#[repr(C, align(4096))]
+struct Disk([u8; DISK_SIZE]);
+
+static mut DISK_QUEUE: ReqQueueMem = ReqQueueMem::EMPTY;
+static mut CDROM_QUEUE: ReqQueueMem = ReqQueueMem::EMPTY;
+static mut DISK_IMAGE: Disk = Disk([0; DISK_SIZE]);
+static mut CDROM_IMAGE: Disk = Disk([0xCD; DISK_SIZE]);
+
+pub fn run_virtio_cdrom_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Virtio CD-ROM Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let disk = &raw mut DISK_IMAGE;
+    let cdrom = &raw mut CDROM_IMAGE;
+    let mut dm = DeviceManager::new();
+    dm.attach_virtio_blk(disk as u64, DISK_SIZE as u64);
+    dm.attach_virtio_cdrom(cdrom as u64, DISK_SIZE as u64);
+    let (disk_base, disk_intid) = virtio_slot(0);
+    let (cdrom_base, cdrom_intid) = virtio_slot(3);
+
+    // Test 1: each device has its own MMIO region and INTID
+    uart_puts(b"[CDROM] Test 1: distinct MMIO regions and INTIDs...\n");
+    let is_blk = |dm: &mut DeviceManager, base: u64| {
+        dm.handle_mmio(base, 0, 4, false) == Some(0x7472_6976)
+            && dm.handle_mmio(base + 0x008, 0, 4, false) == Some(2)
+    };
+    if disk_base == cdrom_base
+        || disk_intid == cdrom_intid
+        || !is_blk(&mut dm, disk_base)
+        || !is_blk(&mut dm, cdrom_base)
+        || dm.virtio_intid(disk_base) != Some(disk_intid)
+        || dm.virtio_intid(cdrom_base) != Some(cdrom_intid)
+    {
+        uart_puts(b"[CDROM] FAILED: disk and CD-ROM not in separate slots\n");
+        return;
+    }
+    uart_puts(b"[CDROM] Test 1 PASSED\n\n");
+
+    // Test 2: only the CD-ROM advertises VIRTIO_BLK_F_RO
+    uart_puts(b"[CDROM] Test 2: read-only feature bit...\n");
+    let features = |dm: &mut DeviceManager, base: u64| {
+        dm.handle_mmio(base + 0x014, 0, 4, true); // DeviceFeaturesSel
+        dm.handle_mmio(base + 0x010, 0, 4, false).unwrap_or(0)
+    };
+    if features(&mut dm, disk_base) & VIRTIO_BLK_F_RO != 0
+        || features(&mut dm, cdrom_base) & VIRTIO_BLK_F_RO == 0
+    {
+        uart_puts(b"[CDROM] FAILED: VIRTIO_BLK_F_RO advertised wrongly\n");
+        return;
+    }
+    uart_puts(b"[CDROM] Test 2 PASSED\n\n");
+
+    // Test 3: a write lands on the disk but fails on the CD-ROM, whose
+    // image still reads back unchanged
+    uart_puts(b"[CDROM] Test 3: CD-ROM rejects writes...\n");
+    let (disk_q, cdrom_q) = (&raw mut DISK_QUEUE, &raw mut CDROM_QUEUE);
+    setup_queue(&mut dm, disk_base, disk_q);
+    setup_queue(&mut dm, cdrom_base, cdrom_q);
+    unsafe {
+        (*disk_q).data = [0x5A; SECTOR];
+        (*cdrom_q).data = [0x5A; SECTOR];
+    }
+    let disk_status = submit(
+        &mut dm,
+        disk_base,
+        disk_q,
+        VIRTIO_BLK_T_OUT,
+        0,
+        SECTOR as u32,
+        0,
+    );
+    let cdrom_status = submit(
+        &mut dm,
+        cdrom_base,
+        cdrom_q,
+        VIRTIO_BLK_T_OUT,
+        0,
+        SECTOR as u32,
+        0,
+    );
+    let (disk_written, cdrom_intact) = unsafe {
+        (
+            (&(*disk).0)[..SECTOR].iter().all(|&b| b == 0x5A),
+            (*cdrom).0.iter().all(|&b| b == 0xCD),
+        )
+    };
+    let read_status = submit(
+        &mut dm,
+        cdrom_base,
+        cdrom_q,
+        VIRTIO_BLK_T_IN,
+        0,
+        SECTOR as u32,
+        1,
+    );
+    let read_back = unsafe { (*cdrom_q).data.iter().all(|&b| b == 0xCD) };
+    clear_spis(&[disk_intid, cdrom_intid]);
+    if disk_status != VIRTIO_BLK_S_OK
+        || !disk_written
+        || cdrom_status != VIRTIO_BLK_S_IOERR
+        || !cdrom_intact
+        || read_status != VIRTIO_BLK_S_OK
+        || !read_back
+    {
+        uart_puts(b"[CDROM] FAILED: CD-ROM write not rejected\n");
+        return;
+    }
+    uart_puts(b"[CDROM] Test 3 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Virtio CD-ROM Test PASSED (3 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,178 @@
+//! VirtioInput device tests
+//!
+//! Tests the keyboard config space and event injection into a real
+//! split virtqueue laid out in hypervisor memory (identity-mapped).
+
+use core::sync::atomic::Ordering;
+use hypervisor::devices::virtio::input::{
+    VirtioInput, VirtioInputEvent, EV_KEY, EV_REL, REL_WHEEL, REL_X, REL_Y,
+};
+use hypervisor::devices::virtio::mmio::VirtioMmioTransport;
+use hypervisor::devices::virtio::VirtioDevice;
+use hypervisor::devices::MmioDevice;
+use hypervisor::global::current_vm_state;
+use hypervisor::uart_puts;
+
+const QUEUE_SIZE: usize = 8;
+const KEY_A: u16 = 30;
+
+/// Descriptor table + avail ring + used ring + one event buffer.
+#[repr(C, align(4096))]
+struct EventQueueMem {
+    desc: [[u8; 16]; QUEUE_SIZE],
+    avail: [u16; 2 + QUEUE_SIZE],
+    _pad: [u16; 2],
+    used: [u32; 1 + 2 * QUEUE_SIZE],
+    event_buf: [u8; 8],
+}
+
+static mut EVENTQ_MEM: EventQueueMem = EventQueueMem {
+    desc: [[0; 16]; QUEUE_SIZE],
+    avail: [0; 2 + QUEUE_SIZE],
+    _pad: [0; 2],
+    used: [0; 1 + 2 * QUEUE_SIZE],
+    event_buf: [0; 8],
+};
+
+pub fn run_virtio_input_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  VirtioInput Device Test\n");
+    uart_puts(b"========================================\n\n");
+
+    // Test 1: device_id and queues
+    uart_puts(b"[VINPUT] Test 1: device_id / num_queues...\n");
+    let mut input = VirtioInput::new();
+    if input.device_id() != 18 || input.num_queues() != 2 {
+        uart_puts(b"[VINPUT] FAILED: expected device_id 18 with 2 queues\n");
+        return;
+    }
+    uart_puts(b"[VINPUT] Test 1 PASSED\n\n");
+
+    // Test 2: config space name (select=ID_NAME)
+    uart_puts(b"[VINPUT] Test 2: config name...\n");
+    input.config_write(0, 0x01, 1);
+    input.config_write(1, 0, 1);
+    let size = input.config_read(2, 1);
+    let first4 = input.config_read(8, 4);
+    if size == 0 || first4 != u32::from_le_bytes(*b"hype") as u64 {
+        uart_puts(b"[VINPUT] FAILED: name config wrong\n");
+        return;
+    }
+    uart_puts(b"[VINPUT] Test 2 PASSED\n\n");
+
+    // Test 3: config space ids + EV_KEY/EV_REL bitmaps
+    uart_puts(b"[VINPUT] Test 3: config devids / EV_KEY / EV_REL bits...\n");
+    input.config_write(0, 0x03, 1);
+    let ids_size = input.config_read(2, 1);
+    let bustype = input.config_read(8, 2);
+    input.config_write(0, 0x11, 1);
+    input.config_write(1, EV_KEY as u64, 1);
+    let bits_size = input.config_read(2, 1);
+    let key_a_byte = input.config_read(8 + (KEY_A as u64 / 8), 1);
+    input.config_write(1, EV_REL as u64, 1);
+    let rel_size = input.config_read(2, 1);
+    let rel_bits = input.config_read(8, 2);
+    let rel_expected = (1 << REL_X) | (1 << REL_Y) | (1 << REL_WHEEL);
+    if ids_size != 8 || bustype != 0x06 || bits_size == 0 || key_a_byte & (1 << (KEY_A % 8)) == 0 {
+        uart_puts(b"[VINPUT] FAILED: devids / EV_KEY bitmap wrong\n");
+        return;
+    }
+    if rel_size != 2 || rel_bits != rel_expected {
+        uart_puts(b"[VINPUT] FAILED: EV_REL bitmap wrong\n");
+        return;
+    }
+    uart_puts(b"[VINPUT] Test 3 PASSED\n\n");
+
+    // Set up eventq through the MMIO transport (slot 2 layout)
+    let mut transport = VirtioMmioTransport::new(0x0a00_0400, VirtioInput::new(), 50);
+    let mem = &raw mut EVENTQ_MEM;
+    let (desc_addr, avail_addr, used_addr, buf_addr) = unsafe {
+        (
+            (*mem).desc.as_ptr() as u64,
+            (*mem).avail.as_ptr() as u64,
+            (*mem).used.as_ptr() as u64,
+            (*mem).event_buf.as_ptr() as u64,
+        )
+    };
+    unsafe {
+        // desc[0] = { addr: event_buf, len: 8, flags: WRITE, next: 0 }
+        let d = &mut (*mem).desc[0];
+        d[0..8].copy_from_slice(&buf_addr.to_le_bytes());
+        d[8..12].copy_from_slice(&8u32.to_le_bytes());
+        d[12..14].copy_from_slice(&2u16.to_le_bytes());
+        // avail ring: ring[0] = desc 0, idx = 1
+        (*mem).avail[2] = 0;
+        (*mem).avail[1] = 1;
+    }
+    transport.write(0x030, 0, 4); // QueueSel = eventq
+    transport.write(0x038, QUEUE_SIZE as u64, 4);
+    transport.write(0x080, desc_addr & 0xFFFF_FFFF, 4);
+    transport.write(0x084, desc_addr >> 32, 4);
+    transport.write(0x090, avail_addr & 0xFFFF_FFFF, 4);
+    transport.write(0x094, avail_addr >> 32, 4);
+    transport.write(0x0A0, used_addr & 0xFFFF_FFFF, 4);
+    transport.write(0x0A4, used_addr >> 32, 4);
+    transport.write(0x044, 1, 4); // QueueReady
+
+    let vs = current_vm_state();
+    let spi_bit = 1u32 << (50 - 32);
+    for spis in vs.pending_spis.iter() {
+        spis.fetch_and(!spi_bit, Ordering::Relaxed);
+    }
+
+    // Test 4: keypress lands in eventq with correct type/code/value
+    uart_puts(b"[VINPUT] Test 4: inject keypress...\n");
+    let ev = VirtioInputEvent::new(EV_KEY, KEY_A, 1);
+    if !transport.inject_event(ev) {
+        uart_puts(b"[VINPUT] FAILED: inject_event returned false\n");
+        return;
+    }
+    let (buf, used_idx, used_len) = unsafe {
+        (
+            core::ptr::read_volatile(&(*mem).event_buf),
+            core::ptr::read_volatile(&(*mem).used[0]) >> 16,
+            core::ptr::read_volatile(&(*mem).used[2]),
+        )
+    };
+    if buf != ev.to_bytes() || u16::from_le_bytes([buf[2], buf[3]]) != KEY_A {
+        uart_puts(b"[VINPUT] FAILED: event bytes mismatch\n");
+        return;
+    }
+    if used_idx != 1 || used_len != 8 {
+        uart_puts(b"[VINPUT] FAILED: used ring not updated\n");
+        return;
+    }
+    uart_puts(b"[VINPUT] Test 4 PASSED\n\n");
+
+    // Test 5: interrupt raised (InterruptStatus + pending SPI 50)
+    uart_puts(b"[VINPUT] Test 5: interrupt raised...\n");
+    let isr = transport.read(0x060, 4);
+    let spi_pending = vs
+        .pending_spis
+        .iter()
+        .any(|s| s.load(Ordering::Acquire) & spi_bit != 0);
+    for spis in vs.pending_spis.iter() {
+        spis.fetch_and(!spi_bit, Ordering::Relaxed);
+    }
+    if isr != Some(1) || !spi_pending {
+        uart_puts(b"[VINPUT] FAILED: interrupt not raised\n");
+        return;
+    }
+    uart_puts(b"[VINPUT] Test 5 PASSED\n\n");
+
+    // Test 6: no buffers left -> inject fails gracefully
+    uart_puts(b"[VINPUT] Test 6: eventq empty...\n");
+    if transport.inject_event(ev) {
+        uart_puts(b"[VINPUT] FAILED: inject should fail with no buffers\n");
+        return;
+    }
+    if hypervisor::global::inject_input_event(usize::MAX, ev) {
+        uart_puts(b"[VINPUT] FAILED: out-of-range vm_id accepted\n");
+        return;
+    }
+    uart_puts(b"[VINPUT] Test 6 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  VirtioInput Device Test PASSED (6 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
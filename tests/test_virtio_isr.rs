@@ -0,0 +1,155 @@
+//! Virtio-mmio InterruptStatus/InterruptACK tests
+//!
+//! Completes inflateq buffers on a balloon transport and checks that
+//! InterruptStatus reports the used-buffer bit until the driver writes
+//! InterruptACK, that completions arriving before the ACK share the SPI
+//! already raised, and that a target change reports the config bit.
+
+use core::sync::atomic::Ordering;
+use hypervisor::devices::virtio::balloon::VirtioBalloon;
+use hypervisor::devices::virtio::mmio::VirtioMmioTransport;
+use hypervisor::devices::MmioDevice;
+use hypervisor::global::current_vm_state;
+use hypervisor::uart_puts;
+
+const QUEUE_SIZE: usize = 8;
+/// Virtio-mmio slot 3 layout
+const BALLOON_BASE: u64 = 0x0a00_0600;
+const BALLOON_INTID: u32 = 51;
+/// inflateq
+const QUEUE: u64 = 0;
+
+const INTERRUPT_STATUS: u64 = 0x060;
+const INTERRUPT_ACK: u64 = 0x064;
+const CONFIG_GENERATION: u64 = 0x0FC;
+
+/// Descriptor table + avail ring + used ring.
+#[repr(C, align(4096))]
+struct QueueMem {
+    desc: [[u8; 16]; QUEUE_SIZE],
+    avail: [u16; 2 + QUEUE_SIZE],
+    _pad: [u16; 2],
+    used: [u32; 1 + 2 * QUEUE_SIZE],
+}
+
+static mut QUEUE_MEM: QueueMem = QueueMem {
+    desc: [[0; 16]; QUEUE_SIZE],
+    avail: [0; 2 + QUEUE_SIZE],
+    _pad: [0; 2],
+    used: [0; 1 + 2 * QUEUE_SIZE],
+};
+
+/// Post descriptor `idx` (one 4-byte PFN buffer) and ring the doorbell.
+fn post_and_notify(t: &mut VirtioMmioTransport<VirtioBalloon>, idx: u16) {
+    let mem = &raw mut QUEUE_MEM;
+    let slot = idx as usize % QUEUE_SIZE;
+    unsafe {
+        let d = &mut (*mem).desc[slot];
+        d[0..8].copy_from_slice(&0x4000_0000u64.to_le_bytes());
+        d[8..12].copy_from_slice(&4u32.to_le_bytes());
+        (*mem).avail[2 + slot] = slot as u16;
+        core::ptr::write_volatile(&mut (*mem).avail[1], idx + 1);
+    }
+    t.write(0x050, QUEUE, 4); // QueueNotify
+}
+
+fn isr(t: &mut VirtioMmioTransport<VirtioBalloon>) -> Option<u64> {
+    t.read(INTERRUPT_STATUS, 4)
+}
+
+pub fn run_virtio_isr_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Virtio InterruptStatus/ACK Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let mut t = VirtioMmioTransport::new(BALLOON_BASE, VirtioBalloon::new(), BALLOON_INTID);
+    let mem = &raw mut QUEUE_MEM;
+    let (desc_addr, avail_addr, used_addr) = unsafe {
+        (
+            (*mem).desc.as_ptr() as u64,
+            (*mem).avail.as_ptr() as u64,
+            (*mem).used.as_ptr() as u64,
+        )
+    };
+    t.write(0x030, QUEUE, 4); // QueueSel = inflateq
+    t.write(0x038, QUEUE_SIZE as u64, 4);
+    t.write(0x080, desc_addr & 0xFFFF_FFFF, 4);
+    t.write(0x084, desc_addr >> 32, 4);
+    t.write(0x090, avail_addr & 0xFFFF_FFFF, 4);
+    t.write(0x094, avail_addr >> 32, 4);
+    t.write(0x0A0, used_addr & 0xFFFF_FFFF, 4);
+    t.write(0x0A4, used_addr >> 32, 4);
+    t.write(0x044, 1, 4); // QueueReady
+
+    let vs = current_vm_state();
+    let spi_bit = 1u32 << (BALLOON_INTID - 32);
+    let take_spi = || {
+        let mut pending = false;
+        for spis in vs.pending_spis.iter() {
+            pending |= spis.fetch_and(!spi_bit, Ordering::Relaxed) & spi_bit != 0;
+        }
+        pending
+    };
+    take_spi();
+
+    // Test 1: a completion sets the used-buffer bit and raises the SPI;
+    // InterruptACK clears it
+    uart_puts(b"[VIRTIO-ISR] Test 1: notify -> status 1, ACK -> 0...\n");
+    post_and_notify(&mut t, 0);
+    let raised = take_spi();
+    let before = isr(&mut t);
+    t.write(INTERRUPT_ACK, 1, 4);
+    let after = isr(&mut t);
+    if !raised || before != Some(1) || after != Some(0) || t.stats().interrupts != 1 {
+        uart_puts(b"[VIRTIO-ISR] FAILED: InterruptStatus not set/cleared\n");
+        return;
+    }
+    uart_puts(b"[VIRTIO-ISR] Test 1 PASSED\n\n");
+
+    // Test 2: completions before the ACK share the pending interrupt
+    uart_puts(b"[VIRTIO-ISR] Test 2: unacknowledged batch raises one SPI...\n");
+    post_and_notify(&mut t, 1);
+    let first = take_spi();
+    post_and_notify(&mut t, 2);
+    let second = take_spi();
+    let s = t.stats();
+    let status = isr(&mut t);
+    t.write(INTERRUPT_ACK, 1, 4);
+    if !first || second || status != Some(1) || s.interrupts != 2 || s.interrupts_coalesced != 1 {
+        uart_puts(b"[VIRTIO-ISR] FAILED: SPI raised again before InterruptACK\n");
+        return;
+    }
+    uart_puts(b"[VIRTIO-ISR] Test 2 PASSED\n\n");
+
+    // Test 3: once ACKed, the next completion interrupts again
+    uart_puts(b"[VIRTIO-ISR] Test 3: completion after ACK re-raises...\n");
+    post_and_notify(&mut t, 3);
+    let raised = take_spi();
+    let status = isr(&mut t);
+    t.write(INTERRUPT_ACK, 1, 4);
+    if !raised || status != Some(1) || isr(&mut t) != Some(0) {
+        uart_puts(b"[VIRTIO-ISR] FAILED: no interrupt after InterruptACK\n");
+        return;
+    }
+    uart_puts(b"[VIRTIO-ISR] Test 3 PASSED\n\n");
+
+    // Test 4: a new balloon target reports a config change (bit 1) and
+    // bumps ConfigGeneration
+    uart_puts(b"[VIRTIO-ISR] Test 4: config change sets bit 1...\n");
+    let generation = t.read(CONFIG_GENERATION, 4);
+    t.set_target_pages(64);
+    let raised = take_spi();
+    let status = isr(&mut t);
+    let bumped = t.read(CONFIG_GENERATION, 4) != generation;
+    let target = t.read(0x100, 4);
+    t.write(INTERRUPT_ACK, 2, 4);
+    if !raised || status != Some(2) || !bumped || target != Some(64) || isr(&mut t) != Some(0) {
+        uart_puts(b"[VIRTIO-ISR] FAILED: config change not signalled\n");
+        return;
+    }
+    uart_puts(b"[VIRTIO-ISR] Test 4 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Virtio InterruptStatus/ACK Test PASSED (4 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -1,8 +1,29 @@
 //! VirtioNet device backend tests
+//!
+//! Device identity and config space, plus packet movement through a
+//! virtio-mmio transport: TX chains reach the VSwitch without their
+//! virtio_net_hdr, and frames the VSwitch queued land in posted RX
+//! buffers (or wait in PORT_RX until one is posted).
 
+use super::virtio_fixture::{
+    last_used, post, set_buf_desc, setup_transport_queue, QueueMem, VIRTQ_DESC_F_NEXT,
+    VIRTQ_DESC_F_WRITE,
+};
+use core::sync::atomic::Ordering;
+use hypervisor::devices::virtio::mmio::VirtioMmioTransport;
 use hypervisor::devices::virtio::net::VirtioNet;
 use hypervisor::devices::virtio::VirtioDevice;
+use hypervisor::devices::MmioDevice;
+use hypervisor::global::current_vm_state;
+use hypervisor::platform::{virtio_slot, VIRTIO_SLOT_NET};
 use hypervisor::uart_puts;
+use hypervisor::vswitch::{MAX_FRAME_SIZE, PORT_RX};
+
+/// virtio_net_hdr_v1
+const HDR_SIZE: usize = 12;
+
+static mut RX_MEM: QueueMem = QueueMem::EMPTY;
+static mut TX_MEM: QueueMem = QueueMem::EMPTY;
 
 pub fn run_virtio_net_test() {
     uart_puts(b"\n========================================\n");
@@ -57,11 +78,125 @@ pub fn run_virtio_net_test() {
     assert_eq_vnet(mac1, [0x52, 0x54, 0x00, 0x00, 0x00, 0x02], "VM 1 MAC");
     uart_puts(b"[VNET] Test 6 PASSED\n\n");
 
+    run_queue_tests();
+
     uart_puts(b"========================================\n");
-    uart_puts(b"  VirtioNet Device Test PASSED (8 assertions)\n");
+    uart_puts(b"  VirtioNet Device Test PASSED (11 assertions)\n");
     uart_puts(b"========================================\n\n");
 }
 
+/// Tests 7-9: TX and RX through a virtio-mmio transport on VSwitch port 0.
+fn run_queue_tests() {
+    hypervisor::vswitch::vswitch_reset();
+    hypervisor::vswitch::vswitch_add_port(0);
+    hypervisor::vswitch::vswitch_add_port(1);
+    let mut drain_buf = [0u8; MAX_FRAME_SIZE];
+    while PORT_RX[0].take(&mut drain_buf).is_some() {}
+    while PORT_RX[1].take(&mut drain_buf).is_some() {}
+
+    let (base, intid) = virtio_slot(VIRTIO_SLOT_NET);
+    let mut t = VirtioMmioTransport::new(base, VirtioNet::new(0), intid);
+    let rx = &raw mut RX_MEM;
+    let tx = &raw mut TX_MEM;
+    setup_transport_queue(&mut t, 0, rx);
+    setup_transport_queue(&mut t, 1, tx);
+
+    let vs = current_vm_state();
+    let spi_bit = 1u32 << (intid - 32);
+    let take_spi = || {
+        let mut pending = false;
+        for spis in vs.pending_spis.iter() {
+            pending |= spis.fetch_and(!spi_bit, Ordering::Relaxed) & spi_bit != 0;
+        }
+        pending
+    };
+    take_spi();
+
+    // Broadcast frame from VM 0's MAC
+    let mut frame = [0u8; 64];
+    frame[0..6].copy_from_slice(&[0xFF; 6]);
+    frame[6..12].copy_from_slice(&VirtioNet::mac_for_vm(0));
+    frame[12..14].copy_from_slice(&[0x08, 0x00]);
+    for (i, b) in frame[14..].iter_mut().enumerate() {
+        *b = i as u8;
+    }
+
+    // Test 7: TX chain [hdr][frame] -> frame (header stripped) on port 1,
+    // chain returned with used len 0
+    uart_puts(b"[VNET] Test 7: TX queue forwards to vswitch...\n");
+    unsafe {
+        (&mut (*tx).bufs[0])[..HDR_SIZE].fill(0xEE);
+        (&mut (*tx).bufs[1])[..frame.len()].copy_from_slice(&frame);
+    }
+    set_buf_desc(tx, 0, HDR_SIZE as u32, VIRTQ_DESC_F_NEXT, 1);
+    set_buf_desc(tx, 1, frame.len() as u32, 0, 0);
+    post(tx, 0);
+    t.write(0x050, 1, 4); // QueueNotify TX
+    let mut got = [0u8; MAX_FRAME_SIZE];
+    let len = PORT_RX[1].take(&mut got);
+    assert_eq_vnet(
+        len,
+        Some(frame.len()),
+        "port 1 should get the 64-byte frame",
+    );
+    assert_eq_vnet(
+        got[..frame.len()] == frame[..],
+        true,
+        "frame without header",
+    );
+    assert_eq_vnet(last_used(tx), (1, 0), "TX chain used with len 0");
+    assert_eq_vnet(PORT_RX[0].is_empty(), true, "no self-delivery");
+    take_spi();
+    t.write(0x064, 1, 4); // InterruptACK, as the driver's handler does
+    uart_puts(b"[VNET] Test 7 PASSED\n\n");
+
+    // Test 8: queued frame + guest posts an RX buffer and notifies ->
+    // header (num_buffers = 1) + frame written, used len covers both, SPI
+    uart_puts(b"[VNET] Test 8: RX notify delivers queued frame...\n");
+    PORT_RX[0].store(&frame);
+    set_buf_desc(rx, 0, 128, VIRTQ_DESC_F_WRITE, 0);
+    post(rx, 0);
+    t.write(0x050, 0, 4); // QueueNotify RX
+    let written = unsafe { (*rx).bufs[0] };
+    let expect_hdr = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0];
+    assert_eq_vnet(
+        last_used(rx),
+        (1, (HDR_SIZE + frame.len()) as u32),
+        "RX used len",
+    );
+    assert_eq_vnet(
+        written[..HDR_SIZE] == expect_hdr,
+        true,
+        "hdr num_buffers = 1",
+    );
+    assert_eq_vnet(
+        written[HDR_SIZE..HDR_SIZE + frame.len()] == frame[..],
+        true,
+        "RX frame",
+    );
+    assert_eq_vnet(PORT_RX[0].is_empty(), true, "frame consumed");
+    assert_eq_vnet(take_spi(), true, "RX SPI raised");
+    t.write(0x064, 1, 4); // InterruptACK
+    uart_puts(b"[VNET] Test 8 PASSED\n\n");
+
+    // Test 9: no RX buffer posted -> frame stays queued, no SPI; delivered
+    // once the guest posts one
+    uart_puts(b"[VNET] Test 9: RX without buffer keeps frame...\n");
+    PORT_RX[0].store(&frame);
+    t.drain_rx();
+    assert_eq_vnet(PORT_RX[0].is_empty(), false, "frame kept without a buffer");
+    assert_eq_vnet(take_spi(), false, "no SPI without delivery");
+    set_buf_desc(rx, 1, 128, VIRTQ_DESC_F_WRITE, 0);
+    post(rx, 1);
+    t.drain_rx();
+    assert_eq_vnet(PORT_RX[0].is_empty(), true, "frame delivered after post");
+    assert_eq_vnet(last_used(rx).0, 2, "second RX chain used");
+    assert_eq_vnet(take_spi(), true, "RX SPI raised after post");
+    uart_puts(b"[VNET] Test 9 PASSED\n\n");
+
+    hypervisor::vswitch::vswitch_reset();
+}
+
 fn assert_eq_vnet<T: PartialEq + core::fmt::Debug>(a: T, b: T, msg: &str) {
     if a != b {
         uart_puts(b"[VNET] ASSERTION FAILED: ");
@@ -0,0 +1,250 @@
+//! VirtioVsock device tests
+//!
+//! Exchanges messages between the host side (`vsock_send`/`vsock_recv`)
+//! and split RX/TX virtqueues laid out in hypervisor memory, checking the
+//! virtio_vsock_hdr of every packet the guest would see, and that credit
+//! and oversized packets are answered rather than dropped.
+
+use super::virtio_fixture::{
+    last_used, post, set_buf_desc, setup_transport_queue, QueueMem, VIRTQ_DESC_F_NEXT,
+    VIRTQ_DESC_F_WRITE,
+};
+use core::sync::atomic::Ordering;
+use hypervisor::devices::virtio::mmio::VirtioMmioTransport;
+use hypervisor::devices::virtio::vsock::{
+    VirtioVsock, VsockHdr, VIRTIO_VSOCK_OP_CREDIT_UPDATE, VIRTIO_VSOCK_OP_REQUEST,
+    VIRTIO_VSOCK_OP_RESPONSE, VIRTIO_VSOCK_OP_RST, VIRTIO_VSOCK_OP_RW, VIRTIO_VSOCK_TYPE_STREAM,
+    VMADDR_CID_HOST, VSOCK_HDR_SIZE, VSOCK_MAX_PAYLOAD,
+};
+use hypervisor::devices::virtio::VirtioDevice;
+use hypervisor::devices::MmioDevice;
+use hypervisor::global::current_vm_state;
+use hypervisor::platform::{virtio_slot, VIRTIO_SLOT_VSOCK};
+use hypervisor::uart_puts;
+
+/// Guest CID of VM 0
+const GUEST_CID: u64 = 3;
+const HOST_PORT: u32 = 9999;
+const GUEST_PORT: u32 = 1234;
+/// Messages the inbox holds before the guest must wait for credit
+const INBOX_DEPTH: usize = 4;
+
+static mut RX_MEM: QueueMem = QueueMem::EMPTY;
+static mut TX_MEM: QueueMem = QueueMem::EMPTY;
+
+/// Header at the start of RX buffer `i`.
+fn rx_hdr(mem: *const QueueMem, i: usize) -> VsockHdr {
+    let mut b = [0u8; VSOCK_HDR_SIZE];
+    unsafe { b.copy_from_slice(&(&(*mem).bufs[i])[..VSOCK_HDR_SIZE]) };
+    VsockHdr::from_bytes(&b)
+}
+
+/// Header of a guest → host packet carrying `len` payload bytes.
+fn guest_hdr(op: u16, len: usize) -> VsockHdr {
+    VsockHdr {
+        src_cid: GUEST_CID,
+        dst_cid: VMADDR_CID_HOST,
+        src_port: GUEST_PORT,
+        dst_port: HOST_PORT,
+        len: len as u32,
+        type_: VIRTIO_VSOCK_TYPE_STREAM,
+        op,
+        buf_alloc: 4096,
+        ..Default::default()
+    }
+}
+
+/// Post RX buffer `i`, deliver waiting packets into it and return its
+/// header.
+fn guest_rx(t: &mut VirtioMmioTransport<VirtioVsock>, i: usize) -> VsockHdr {
+    let rx = &raw mut RX_MEM;
+    unsafe { (&mut (*rx).bufs[i])[..VSOCK_HDR_SIZE].fill(0) };
+    set_buf_desc(rx, i, 128, VIRTQ_DESC_F_WRITE, 0);
+    post(rx, i as u16);
+    t.drain_rx();
+    rx_hdr(rx, i)
+}
+
+/// Post a guest → host packet as a [hdr][payload] chain and notify TX.
+fn guest_send(t: &mut VirtioMmioTransport<VirtioVsock>, head: usize, op: u16, payload: &[u8]) {
+    let tx = &raw mut TX_MEM;
+    let hdr = guest_hdr(op, payload.len());
+    unsafe {
+        (&mut (*tx).bufs[head])[..VSOCK_HDR_SIZE].copy_from_slice(&hdr.to_bytes());
+        (&mut (*tx).bufs[head + 1])[..payload.len()].copy_from_slice(payload);
+    }
+    let next = (head + 1) as u16;
+    if payload.is_empty() {
+        set_buf_desc(tx, head, VSOCK_HDR_SIZE as u32, 0, 0);
+    } else {
+        set_buf_desc(tx, head, VSOCK_HDR_SIZE as u32, VIRTQ_DESC_F_NEXT, next);
+        set_buf_desc(tx, head + 1, payload.len() as u32, 0, 0);
+    }
+    post(tx, head as u16);
+    t.write(0x050, 1, 4); // QueueNotify TX
+}
+
+pub fn run_virtio_vsock_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  VirtioVsock Device Test\n");
+    uart_puts(b"========================================\n\n");
+
+    // Test 1: device_id, queues and guest CID in config space
+    uart_puts(b"[VSOCK] Test 1: device_id / num_queues / guest_cid...\n");
+    let vsock = VirtioVsock::new(0);
+    if vsock.device_id() != 19
+        || vsock.num_queues() != 3
+        || vsock.config_read(0, 8) != GUEST_CID
+        || vsock.config_read(4, 4) != 0
+    {
+        uart_puts(b"[VSOCK] FAILED: expected device_id 19, 3 queues, CID 3\n");
+        return;
+    }
+    uart_puts(b"[VSOCK] Test 1 PASSED\n\n");
+
+    let (base, intid) = virtio_slot(VIRTIO_SLOT_VSOCK);
+    let mut t = VirtioMmioTransport::new(base, vsock, intid);
+    let rx = &raw mut RX_MEM;
+    let tx = &raw mut TX_MEM;
+    setup_transport_queue(&mut t, 0, rx);
+    setup_transport_queue(&mut t, 1, tx);
+
+    let vs = current_vm_state();
+    let spi_bit = 1u32 << (intid - 32);
+    let take_spi = || {
+        let mut pending = false;
+        for spis in vs.pending_spis.iter() {
+            pending |= spis.fetch_and(!spi_bit, Ordering::Relaxed) & spi_bit != 0;
+        }
+        pending
+    };
+    take_spi();
+
+    // Test 2: host -> guest. vsock_send() lands a well-formed OP_RW packet
+    // in the posted RX buffer and raises the SPI
+    uart_puts(b"[VSOCK] Test 2: host send reaches guest RX queue...\n");
+    set_buf_desc(rx, 0, 128, VIRTQ_DESC_F_WRITE, 0);
+    post(rx, 0);
+    let msg = b"ping";
+    if !t.vsock_send(HOST_PORT, msg) {
+        uart_puts(b"[VSOCK] FAILED: vsock_send returned false\n");
+        return;
+    }
+    let hdr = rx_hdr(rx, 0);
+    let payload = unsafe { (*rx).bufs[0] };
+    let expect = VsockHdr {
+        src_cid: VMADDR_CID_HOST,
+        dst_cid: GUEST_CID,
+        src_port: HOST_PORT,
+        dst_port: HOST_PORT,
+        len: msg.len() as u32,
+        type_: VIRTIO_VSOCK_TYPE_STREAM,
+        op: VIRTIO_VSOCK_OP_RW,
+        flags: 0,
+        buf_alloc: hdr.buf_alloc,
+        fwd_cnt: 0,
+    };
+    if hdr != expect
+        || hdr.buf_alloc == 0
+        || &payload[VSOCK_HDR_SIZE..VSOCK_HDR_SIZE + msg.len()] != msg
+        || last_used(rx) != (1, (VSOCK_HDR_SIZE + msg.len()) as u32)
+        || !take_spi()
+    {
+        uart_puts(b"[VSOCK] FAILED: RX packet malformed or not signalled\n");
+        return;
+    }
+    uart_puts(b"[VSOCK] Test 2 PASSED\n\n");
+
+    // Test 3: guest -> host. An OP_RW chain on the TX queue comes out of
+    // vsock_recv() with its host port and payload; used len 0
+    uart_puts(b"[VSOCK] Test 3: guest send reaches host...\n");
+    guest_send(&mut t, 0, VIRTIO_VSOCK_OP_RW, b"hello");
+    let got = t.vsock_recv();
+    let ok = got.is_some_and(|m| m.port == HOST_PORT && m.payload() == b"hello");
+    if !ok || last_used(tx) != (1, 0) || t.vsock_recv().is_some() {
+        uart_puts(b"[VSOCK] FAILED: guest message not received by host\n");
+        return;
+    }
+    uart_puts(b"[VSOCK] Test 3 PASSED\n\n");
+
+    // Test 4: connection request answered with RESPONSE; the host's next
+    // message goes to the guest's (ephemeral) source port, with fwd_cnt
+    // crediting the 5 bytes received
+    uart_puts(b"[VSOCK] Test 4: REQUEST -> RESPONSE, reply to peer port...\n");
+    guest_send(&mut t, 2, VIRTIO_VSOCK_OP_REQUEST, &[]);
+    set_buf_desc(rx, 1, 128, VIRTQ_DESC_F_WRITE, 0);
+    post(rx, 1);
+    t.drain_rx();
+    let resp = rx_hdr(rx, 1);
+    set_buf_desc(rx, 2, 128, VIRTQ_DESC_F_WRITE, 0);
+    post(rx, 2);
+    t.vsock_send(HOST_PORT, b"pong");
+    let reply = rx_hdr(rx, 2);
+    if resp.op != VIRTIO_VSOCK_OP_RESPONSE
+        || resp.dst_port != GUEST_PORT
+        || resp.src_port != HOST_PORT
+        || reply.op != VIRTIO_VSOCK_OP_RW
+        || reply.dst_port != GUEST_PORT
+        || reply.fwd_cnt != 5
+        || last_used(rx).0 != 3
+    {
+        uart_puts(b"[VSOCK] FAILED: RESPONSE or peer-port reply wrong\n");
+        return;
+    }
+    take_spi();
+    uart_puts(b"[VSOCK] Test 4 PASSED\n\n");
+
+    // Test 5: a guest past its inbox slots gets RST rather than a silent
+    // drop; taking a message out of the full inbox credits its bytes
+    // back (fwd_cnt) with a CREDIT_UPDATE
+    uart_puts(b"[VSOCK] Test 5: full inbox -> RST, recv -> CREDIT_UPDATE...\n");
+    for i in 0..INBOX_DEPTH {
+        guest_send(&mut t, 2 * i, VIRTIO_VSOCK_OP_RW, b"m");
+    }
+    guest_send(&mut t, 0, VIRTIO_VSOCK_OP_RW, b"overflow");
+    let rst = guest_rx(&mut t, 3);
+    let first = t.vsock_recv();
+    let credit = guest_rx(&mut t, 4);
+    let mut drained = 0;
+    while t.vsock_recv().is_some() {
+        drained += 1;
+    }
+    if rst.op != VIRTIO_VSOCK_OP_RST
+        || rst.dst_port != GUEST_PORT
+        || !first.is_some_and(|m| m.payload() == b"m")
+        || credit.op != VIRTIO_VSOCK_OP_CREDIT_UPDATE
+        || credit.dst_port != GUEST_PORT
+        || credit.fwd_cnt != 5 + 1
+        || drained != INBOX_DEPTH - 1
+    {
+        uart_puts(b"[VSOCK] FAILED: inbox overflow not reset or credit not returned\n");
+        return;
+    }
+    take_spi();
+    uart_puts(b"[VSOCK] Test 5 PASSED\n\n");
+
+    // Test 6: a payload longer than the device takes resets the connection
+    uart_puts(b"[VSOCK] Test 6: oversized packet -> RST...\n");
+    let long = VSOCK_MAX_PAYLOAD + 44;
+    unsafe {
+        (&mut (*tx).bufs[0])[..VSOCK_HDR_SIZE]
+            .copy_from_slice(&guest_hdr(VIRTIO_VSOCK_OP_RW, long).to_bytes());
+    }
+    set_buf_desc(tx, 0, VSOCK_HDR_SIZE as u32, VIRTQ_DESC_F_NEXT, 1);
+    set_buf_desc(tx, 1, 128, VIRTQ_DESC_F_NEXT, 2);
+    set_buf_desc(tx, 2, 128, VIRTQ_DESC_F_NEXT, 3);
+    set_buf_desc(tx, 3, (long - 256) as u32, 0, 0);
+    post(tx, 0);
+    t.write(0x050, 1, 4); // QueueNotify TX
+    let rst = guest_rx(&mut t, 5);
+    if rst.op != VIRTIO_VSOCK_OP_RST || rst.dst_port != GUEST_PORT || t.vsock_recv().is_some() {
+        uart_puts(b"[VSOCK] FAILED: oversized packet not reset\n");
+        return;
+    }
+    take_spi();
+    uart_puts(b"[VSOCK] Test 6 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  VirtioVsock Device Test PASSED (6 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
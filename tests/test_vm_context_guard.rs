@@ -0,0 +1,87 @@
+//! Current-VM consistency guard tests
+//!
+//! Checks `check_current_vm()` against VTTBR values carrying each VM's
+//! VMID, then loads VTTBR_EL2 with VM 1's VMID while `CURRENT_VM_ID` still
+//! names VM 0 and checks the desync is reported.
+
+use super::cleanup::Cleanup;
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::mm::mmu::Stage2Config;
+use hypervisor::global::{check_current_vm, CURRENT_VM_ID, MAX_VMS};
+use hypervisor::platform::HEAP_START;
+use hypervisor::uart_puts;
+
+fn read_vttbr() -> u64 {
+    let vttbr: u64;
+    unsafe {
+        core::arch::asm!("mrs {}, vttbr_el2", out(reg) vttbr, options(nomem, nostack));
+    }
+    vttbr
+}
+
+fn write_vttbr(vttbr: u64) {
+    unsafe {
+        core::arch::asm!("msr vttbr_el2, {}", "isb", in(reg) vttbr, options(nomem, nostack));
+    }
+}
+
+/// VTTBR of a Stage-2 built for `vm_id`.
+fn vttbr_for(vm_id: usize) -> u64 {
+    Stage2Config::new_with_vmid(HEAP_START, vm_id as u16).vttbr
+}
+
+pub fn run_vm_context_guard_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  Current-VM Consistency Guard Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let saved_vm = CURRENT_VM_ID.load(Ordering::Relaxed);
+    let saved_vttbr = read_vttbr();
+    let cleanup = Cleanup::new(|| {
+        write_vttbr(saved_vttbr);
+        CURRENT_VM_ID.store(saved_vm, Ordering::Relaxed);
+    });
+
+    // Test 1: each VM's own Stage-2 passes the check
+    uart_puts(b"[VM-GUARD] Test 1: matching VMID accepted...\n");
+    let mut consistent = true;
+    for vm_id in 0..MAX_VMS {
+        CURRENT_VM_ID.store(vm_id, Ordering::Relaxed);
+        consistent &= check_current_vm(vttbr_for(vm_id)).is_ok();
+    }
+    if !consistent {
+        uart_puts(b"[VM-GUARD] FAILED: consistent context reported as desync\n");
+        return;
+    }
+    uart_puts(b"[VM-GUARD] Test 1 PASSED\n\n");
+
+    // Test 2: another VM's Stage-2 is reported with its VM ID
+    uart_puts(b"[VM-GUARD] Test 2: mismatched VMID detected...\n");
+    CURRENT_VM_ID.store(0, Ordering::Relaxed);
+    let vm1_under_vm0 = check_current_vm(vttbr_for(1));
+    CURRENT_VM_ID.store(1, Ordering::Relaxed);
+    let vm0_under_vm1 = check_current_vm(vttbr_for(0));
+    if vm1_under_vm0 != Err(1) || vm0_under_vm1 != Err(0) {
+        uart_puts(b"[VM-GUARD] FAILED: VMID mismatch not reported\n");
+        return;
+    }
+    uart_puts(b"[VM-GUARD] Test 2 PASSED\n\n");
+
+    // Test 3: the live VTTBR_EL2 is checked, as in handle_mmio_abort
+    uart_puts(b"[VM-GUARD] Test 3: stale CURRENT_VM_ID vs live VTTBR...\n");
+    write_vttbr(vttbr_for(1));
+    CURRENT_VM_ID.store(0, Ordering::Relaxed);
+    let stale = check_current_vm(read_vttbr());
+    CURRENT_VM_ID.store(1, Ordering::Relaxed);
+    let switched = check_current_vm(read_vttbr());
+    drop(cleanup);
+    if stale != Err(1) || switched.is_err() {
+        uart_puts(b"[VM-GUARD] FAILED: live VTTBR desync not detected\n");
+        return;
+    }
+    uart_puts(b"[VM-GUARD] Test 3 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  Current-VM Consistency Guard Test PASSED (3 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,100 @@
+//! WFI periodic tick tests
+//!
+//! Drives handle_wfi_with_timer_injection() directly with the guest virtual
+//! timer disabled and enabled, and checks which case gets a VTIMER_IRQ in
+//! the List Registers.
+
+use hypervisor::arch::aarch64::hypervisor::exception::handle_wfi_with_timer_injection;
+use hypervisor::arch::aarch64::peripherals::gicv3::{GicV3VirtualInterface, VTIMER_IRQ};
+use hypervisor::arch::aarch64::peripherals::timer;
+use hypervisor::arch::aarch64::regs::VcpuContext;
+use hypervisor::uart_puts;
+
+const NUM_LRS: u32 = 4;
+const WFI_ITERATIONS: u32 = 1000;
+
+/// True if any List Register holds VTIMER_IRQ.
+fn vtimer_in_lrs() -> bool {
+    (0..NUM_LRS).any(|i| {
+        let lr = GicV3VirtualInterface::read_lr(i);
+        GicV3VirtualInterface::get_lr_state(lr) != 0
+            && GicV3VirtualInterface::get_lr_intid(lr) == VTIMER_IRQ
+    })
+}
+
+fn clear_lrs() {
+    for i in 0..NUM_LRS {
+        GicV3VirtualInterface::write_lr(i, 0);
+    }
+}
+
+pub fn run_wfi_tick_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  WFI Periodic Tick Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let saved_lrs: [u64; NUM_LRS as usize] =
+        core::array::from_fn(|i| GicV3VirtualInterface::read_lr(i as u32));
+    let saved_ctl = timer::get_ctl();
+    let saved_cval = timer::get_cval();
+    let mut ctx = VcpuContext::default();
+
+    // Test 1: timer disabled -> no VTIMER_IRQ over many WFIs
+    uart_puts(b"[WFI] Test 1: disabled timer, no spurious tick...\n");
+    clear_lrs();
+    timer::set_ctl(0);
+    ctx.pc = 0x4000_1000;
+    let mut spurious = false;
+    for _ in 0..WFI_ITERATIONS {
+        handle_wfi_with_timer_injection(&mut ctx);
+        spurious |= vtimer_in_lrs();
+    }
+    if spurious {
+        clear_lrs();
+        timer::set_cval(saved_cval);
+        timer::set_ctl(saved_ctl);
+        uart_puts(b"[WFI] FAILED: VTIMER_IRQ injected with timer disabled\n");
+        return;
+    }
+    uart_puts(b"[WFI] Test 1 PASSED\n\n");
+
+    // Test 2: timer enabled but masked -> still no tick
+    uart_puts(b"[WFI] Test 2: masked timer, no spurious tick...\n");
+    timer::set_cval(u64::MAX);
+    timer::set_ctl(0b11); // ENABLE | IMASK
+    ctx.pc = 0x4000_2000;
+    for _ in 0..WFI_ITERATIONS {
+        handle_wfi_with_timer_injection(&mut ctx);
+        spurious |= vtimer_in_lrs();
+    }
+    if spurious {
+        clear_lrs();
+        timer::set_cval(saved_cval);
+        timer::set_ctl(saved_ctl);
+        uart_puts(b"[WFI] FAILED: VTIMER_IRQ injected with timer masked\n");
+        return;
+    }
+    uart_puts(b"[WFI] Test 2 PASSED\n\n");
+
+    // Test 3: timer enabled and unmasked -> tick still delivered
+    uart_puts(b"[WFI] Test 3: armed timer gets tick...\n");
+    timer::set_ctl(0b01); // ENABLE
+    ctx.pc = 0x4000_3000;
+    handle_wfi_with_timer_injection(&mut ctx);
+    let injected = vtimer_in_lrs();
+    clear_lrs();
+    timer::set_cval(saved_cval);
+    timer::set_ctl(saved_ctl);
+    for (i, lr) in saved_lrs.iter().enumerate() {
+        GicV3VirtualInterface::write_lr(i as u32, *lr);
+    }
+    if !injected {
+        uart_puts(b"[WFI] FAILED: armed timer got no VTIMER_IRQ\n");
+        return;
+    }
+    uart_puts(b"[WFI] Test 3 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  WFI Periodic Tick Test PASSED (3 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
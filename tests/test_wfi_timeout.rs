@@ -0,0 +1,166 @@
+//! WFI idle timeout tests
+//!
+//! Under a fake clock, drives handle_wfi_with_timer_injection() at one PC
+//! with nothing to inject and checks that a VM's `set_wfi_timeout_ns`
+//! forces the exit to the scheduler once that much time has passed, long
+//! before the WFI iteration cap, and that no timeout keeps it idling.
+//! Pending work found at the WFI wins over an elapsed timeout, and each
+//! vCPU keeps its own idle window.
+
+use core::sync::atomic::Ordering;
+use hypervisor::arch::aarch64::defs::LR_STATE_SHIFT;
+use hypervisor::arch::aarch64::hypervisor::exception::handle_wfi_with_timer_injection;
+use hypervisor::arch::aarch64::peripherals::gicv3::GicV3VirtualInterface;
+use hypervisor::arch::aarch64::peripherals::timer;
+use hypervisor::arch::aarch64::regs::VcpuContext;
+use hypervisor::global::current_vm_state;
+use hypervisor::time;
+use hypervisor::uart_puts;
+use hypervisor::vm::Vm;
+
+const NUM_LRS: u32 = 4;
+const TIMEOUT_NS: u64 = 1_000_000; // 1ms
+/// WFIs per fake-clock step: far below the 500k iteration cap
+const WFIS_PER_STEP: u32 = 10;
+/// virtio-blk SPI, left pending in an LR
+const SPI_INTID: u32 = 48;
+
+fn clear_lrs() {
+    for i in 0..NUM_LRS {
+        GicV3VirtualInterface::write_lr(i, 0);
+    }
+}
+
+/// Issue `WFIS_PER_STEP` WFIs at `ctx.pc`; false if any of them exited.
+fn idle(ctx: &mut VcpuContext) -> bool {
+    (0..WFIS_PER_STEP).all(|_| handle_wfi_with_timer_injection(ctx))
+}
+
+pub fn run_wfi_timeout_test() {
+    uart_puts(b"\n========================================\n");
+    uart_puts(b"  WFI Idle Timeout Test\n");
+    uart_puts(b"========================================\n\n");
+
+    let saved_lrs: [u64; NUM_LRS as usize] =
+        core::array::from_fn(|i| GicV3VirtualInterface::read_lr(i as u32));
+    let saved_ctl = timer::get_ctl();
+    let restore = || {
+        clear_lrs();
+        timer::set_ctl(saved_ctl);
+        for (i, lr) in saved_lrs.iter().enumerate() {
+            GicV3VirtualInterface::write_lr(i as u32, *lr);
+        }
+        time::remove_fake_clock();
+    };
+    clear_lrs();
+    timer::set_ctl(0);
+    time::install_fake_clock(1_000_000_000, 1 << 32);
+    let step = time::ns_to_ticks(TIMEOUT_NS / 4);
+    let vm = Vm::new(0);
+    let mut ctx = VcpuContext::default();
+
+    // Test 1: the setting is per VM and starts out disabled
+    uart_puts(b"[WFI-TIMEOUT] Test 1: set_wfi_timeout_ns() stored...\n");
+    let default = vm.wfi_timeout_ns();
+    vm.set_wfi_timeout_ns(TIMEOUT_NS);
+    if default != 0 || vm.wfi_timeout_ns() != TIMEOUT_NS {
+        vm.set_wfi_timeout_ns(0);
+        restore();
+        uart_puts(b"[WFI-TIMEOUT] FAILED: timeout not stored\n");
+        return;
+    }
+    uart_puts(b"[WFI-TIMEOUT] Test 1 PASSED\n\n");
+
+    // Test 2: idle WFIs keep the vCPU in until the timeout elapses, then
+    // the next one exits to the scheduler
+    uart_puts(b"[WFI-TIMEOUT] Test 2: exit after the configured time...\n");
+    ctx.pc = 0x4000_5000;
+    let mut early_exit = false;
+    for _ in 0..3 {
+        early_exit |= !idle(&mut ctx);
+        time::advance_fake_clock(step);
+    }
+    early_exit |= !idle(&mut ctx);
+    time::advance_fake_clock(step);
+    let timed_out = !handle_wfi_with_timer_injection(&mut ctx);
+    if early_exit || !timed_out {
+        vm.set_wfi_timeout_ns(0);
+        restore();
+        uart_puts(b"[WFI-TIMEOUT] FAILED: WFI exit not driven by the timeout\n");
+        return;
+    }
+    uart_puts(b"[WFI-TIMEOUT] Test 2 PASSED\n\n");
+
+    // Test 3: the exit starts a fresh window for the next WFIs
+    uart_puts(b"[WFI-TIMEOUT] Test 3: window restarts after the exit...\n");
+    let resumed = idle(&mut ctx);
+    if !resumed {
+        vm.set_wfi_timeout_ns(0);
+        restore();
+        uart_puts(b"[WFI-TIMEOUT] FAILED: vCPU kicked out again immediately\n");
+        return;
+    }
+    uart_puts(b"[WFI-TIMEOUT] Test 3 PASSED\n\n");
+
+    // Test 4: with no timeout, elapsed time alone never forces an exit
+    uart_puts(b"[WFI-TIMEOUT] Test 4: timeout 0 keeps idling...\n");
+    vm.set_wfi_timeout_ns(0);
+    ctx.pc = 0x4000_6000;
+    let mut stayed = idle(&mut ctx);
+    time::advance_fake_clock(time::ns_to_ticks(100 * TIMEOUT_NS));
+    stayed &= idle(&mut ctx);
+    if !stayed {
+        restore();
+        uart_puts(b"[WFI-TIMEOUT] FAILED: exit without a timeout set\n");
+        return;
+    }
+    uart_puts(b"[WFI-TIMEOUT] Test 4 PASSED\n\n");
+
+    // Test 5: an interrupt pending in an LR once the timeout has elapsed
+    // still wakes the WFI instead of exiting
+    uart_puts(b"[WFI-TIMEOUT] Test 5: pending LR beats the timeout...\n");
+    vm.set_wfi_timeout_ns(TIMEOUT_NS);
+    ctx.pc = 0x4000_7000;
+    let mut stayed = idle(&mut ctx);
+    time::advance_fake_clock(time::ns_to_ticks(2 * TIMEOUT_NS));
+    GicV3VirtualInterface::write_lr(
+        0,
+        (GicV3VirtualInterface::LR_STATE_PENDING << LR_STATE_SHIFT) | SPI_INTID as u64,
+    );
+    stayed &= handle_wfi_with_timer_injection(&mut ctx);
+    clear_lrs();
+    if !stayed {
+        vm.set_wfi_timeout_ns(0);
+        restore();
+        uart_puts(b"[WFI-TIMEOUT] FAILED: pending interrupt lost to the timeout\n");
+        return;
+    }
+    uart_puts(b"[WFI-TIMEOUT] Test 5 PASSED\n\n");
+
+    // Test 6: another vCPU idling at the same PC has its own window
+    uart_puts(b"[WFI-TIMEOUT] Test 6: idle window per vCPU...\n");
+    let vs = current_vm_state();
+    let prev_vcpu = vs.current_vcpu_id.load(Ordering::Relaxed);
+    ctx.pc = 0x4000_8000;
+    vs.current_vcpu_id.store(0, Ordering::Relaxed);
+    let mut stayed = idle(&mut ctx);
+    time::advance_fake_clock(step * 3);
+    vs.current_vcpu_id.store(1, Ordering::Relaxed);
+    stayed &= idle(&mut ctx);
+    time::advance_fake_clock(step * 2);
+    stayed &= handle_wfi_with_timer_injection(&mut ctx);
+    vs.current_vcpu_id.store(0, Ordering::Relaxed);
+    let vcpu0_out = !handle_wfi_with_timer_injection(&mut ctx);
+    vs.current_vcpu_id.store(prev_vcpu, Ordering::Relaxed);
+    vm.set_wfi_timeout_ns(0);
+    restore();
+    if !stayed || !vcpu0_out {
+        uart_puts(b"[WFI-TIMEOUT] FAILED: vCPUs share one idle window\n");
+        return;
+    }
+    uart_puts(b"[WFI-TIMEOUT] Test 6 PASSED\n\n");
+
+    uart_puts(b"========================================\n");
+    uart_puts(b"  WFI Idle Timeout Test PASSED (6 assertions)\n");
+    uart_puts(b"========================================\n\n");
+}
@@ -0,0 +1,268 @@
+//! Shared virtio test fixture
+//!
+//! Split virtqueues in static memory, either with one block request's
+//! buffers or with a bank of packet buffers, the virtio-mmio sequence that
+//! hands a queue to a transport, block request submission with a
+//! caller-chosen data length, driver-side avail/used ring bookkeeping, and
+//! the completion-SPI bookkeeping the virtio device tests share.
+
+use core::sync::atomic::Ordering;
+use hypervisor::devices::virtio::mmio::VirtioMmioTransport;
+use hypervisor::devices::virtio::VirtioDevice;
+use hypervisor::devices::{DeviceManager, MmioDevice};
+use hypervisor::global::current_vm_state;
+
+pub const QUEUE_SIZE: usize = 8;
+pub const SECTOR: usize = 512;
+pub const VIRTIO_BLK_T_IN: u32 = 0;
+pub const VIRTIO_BLK_T_OUT: u32 = 1;
+pub const VIRTIO_BLK_S_OK: u8 = 0;
+pub const VIRTIO_BLK_S_IOERR: u8 = 1;
+pub const VIRTQ_DESC_F_NEXT: u16 = 1;
+pub const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+/// Driver area of a queue of `N` entries.
+#[repr(C)]
+pub struct AvailRing<const N: usize> {
+    pub flags: u16,
+    pub idx: u16,
+    pub ring: [u16; N],
+}
+
+/// Device area of a queue of `N` entries: (id, len) per element.
+#[repr(C)]
+pub struct UsedRing<const N: usize> {
+    pub flags: u16,
+    pub idx: u16,
+    pub ring: [[u32; 2]; N],
+}
+
+/// Descriptor table + avail ring + used ring of a queue of `N` entries.
+/// `repr(C)` pads the used ring to its 4-byte alignment.
+#[repr(C)]
+pub struct Virtq<const N: usize> {
+    pub desc: [[u8; 16]; N],
+    pub avail: AvailRing<N>,
+    pub used: UsedRing<N>,
+}
+
+impl<const N: usize> Virtq<N> {
+    pub const EMPTY: Self = Self {
+        desc: [[0; 16]; N],
+        avail: AvailRing {
+            flags: 0,
+            idx: 0,
+            ring: [0; N],
+        },
+        used: UsedRing {
+            flags: 0,
+            idx: 0,
+            ring: [[0; 2]; N],
+        },
+    };
+}
+
+/// Virtqueue of `N` entries + one request's buffers.
+#[repr(C, align(4096))]
+pub struct ReqQueueMem<const N: usize = QUEUE_SIZE> {
+    pub vq: Virtq<N>,
+    pub header: [u8; 16],
+    pub data: [u8; SECTOR],
+    pub status: u8,
+}
+
+impl<const N: usize> ReqQueueMem<N> {
+    pub const EMPTY: Self = Self {
+        vq: Virtq::EMPTY,
+        header: [0; 16],
+        data: [0; SECTOR],
+        status: 0xFF,
+    };
+}
+
+/// Virtqueue + one packet buffer per descriptor.
+#[repr(C, align(4096))]
+pub struct QueueMem {
+    pub vq: Virtq<QUEUE_SIZE>,
+    pub bufs: [[u8; 128]; QUEUE_SIZE],
+}
+
+impl QueueMem {
+    pub const EMPTY: Self = Self {
+        vq: Virtq::EMPTY,
+        bufs: [[0; 128]; QUEUE_SIZE],
+    };
+}
+
+pub fn set_desc(d: &mut [u8; 16], addr: u64, len: u32, flags: u16, next: u16) {
+    d[0..8].copy_from_slice(&addr.to_le_bytes());
+    d[8..12].copy_from_slice(&len.to_le_bytes());
+    d[12..14].copy_from_slice(&flags.to_le_bytes());
+    d[14..16].copy_from_slice(&next.to_le_bytes());
+}
+
+/// Point buffer descriptor `i` of `mem` at `bufs[i]`.
+pub fn set_buf_desc(mem: *mut QueueMem, i: usize, len: u32, flags: u16, next: u16) {
+    unsafe {
+        let addr = (*mem).bufs[i].as_ptr() as u64;
+        set_desc(&mut (*mem).vq.desc[i], addr, len, flags, next);
+    }
+}
+
+/// Select queue `idx`, point it at `vq` and mark it ready, one register
+/// `write(offset, value)` at a time.
+fn program_queue<const N: usize>(vq: *const Virtq<N>, idx: u64, mut write: impl FnMut(u64, u64)) {
+    let (desc, avail, used) = unsafe {
+        (
+            (&raw const (*vq).desc) as u64,
+            (&raw const (*vq).avail) as u64,
+            (&raw const (*vq).used) as u64,
+        )
+    };
+    write(0x030, idx); // QueueSel
+    write(0x038, N as u64); // QueueNum
+    write(0x080, desc & 0xFFFF_FFFF);
+    write(0x084, desc >> 32);
+    write(0x090, avail & 0xFFFF_FFFF);
+    write(0x094, avail >> 32);
+    write(0x0A0, used & 0xFFFF_FFFF);
+    write(0x0A4, used >> 32);
+    write(0x044, 1); // QueueReady
+}
+
+/// Program queue 0 of the transport at `base` to use `mem`.
+pub fn setup_queue<const N: usize>(dm: &mut DeviceManager, base: u64, mem: *mut ReqQueueMem<N>) {
+    let vq = unsafe { &raw const (*mem).vq };
+    program_queue(vq, 0, |off, val| {
+        dm.handle_mmio(base + off, val, 4, true);
+    });
+}
+
+/// Program queue `idx` of `t` to use `mem`.
+pub fn setup_transport_queue<D: VirtioDevice>(
+    t: &mut VirtioMmioTransport<D>,
+    idx: u64,
+    mem: *mut QueueMem,
+) {
+    let vq = unsafe { &raw const (*mem).vq };
+    program_queue(vq, idx, |off, val| {
+        t.write(off, val, 4);
+    });
+}
+
+/// Make chain `head` the driver's next available entry of `vq`.
+fn post_chain<const N: usize>(vq: *mut Virtq<N>, head: u16) {
+    unsafe {
+        let idx = core::ptr::read_volatile(&(*vq).avail.idx);
+        (*vq).avail.ring[idx as usize % N] = head;
+        core::ptr::write_volatile(&mut (*vq).avail.idx, idx.wrapping_add(1));
+    }
+}
+
+/// (used idx, len of the most recent used entry) of `vq`
+fn used_tail<const N: usize>(vq: *const Virtq<N>) -> (u16, u32) {
+    unsafe {
+        let idx = core::ptr::read_volatile(&(*vq).used.idx);
+        let slot = idx.wrapping_sub(1) as usize % N;
+        (idx, core::ptr::read_volatile(&(*vq).used.ring[slot][1]))
+    }
+}
+
+/// Make chain `head` the driver's next available entry of `mem`.
+pub fn post(mem: *mut QueueMem, head: u16) {
+    post_chain(unsafe { &raw mut (*mem).vq }, head);
+}
+
+/// (used idx, len of the most recent used entry) of `mem`
+pub fn last_used(mem: *const QueueMem) -> (u16, u32) {
+    used_tail(unsafe { &raw const (*mem).vq })
+}
+
+/// Submit request number `n` of `req_type` at `sector` on queue 0 and
+/// return its status byte. `data_len` bytes of the data buffer are split
+/// over two descriptors; with `data_len` 0 the header chains straight to
+/// the status byte (FLUSH).
+pub fn submit(
+    dm: &mut DeviceManager,
+    base: u64,
+    mem: *mut ReqQueueMem,
+    req_type: u32,
+    sector: u64,
+    data_len: u32,
+    n: u16,
+) -> u8 {
+    unsafe {
+        let header = (*mem).header.as_ptr() as u64;
+        let data = (*mem).data.as_ptr() as u64;
+        let status = &raw mut (*mem).status;
+        // type, reserved, sector
+        let mut hdr = [0u8; 16];
+        hdr[0..4].copy_from_slice(&req_type.to_le_bytes());
+        hdr[8..16].copy_from_slice(&sector.to_le_bytes());
+        (*mem).header = hdr;
+        *status = 0xFF;
+        let data_flags = if req_type == VIRTIO_BLK_T_IN {
+            VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE
+        } else {
+            VIRTQ_DESC_F_NEXT
+        };
+        let half = data_len / 2;
+        let desc = &mut (*mem).vq.desc;
+        if data_len == 0 {
+            set_desc(&mut desc[0], header, 16, VIRTQ_DESC_F_NEXT, 3);
+        } else {
+            set_desc(&mut desc[0], header, 16, VIRTQ_DESC_F_NEXT, 1);
+            set_desc(&mut desc[1], data, half, data_flags, 2);
+            set_desc(
+                &mut desc[2],
+                data + half as u64,
+                data_len - half,
+                data_flags,
+                3,
+            );
+        }
+        set_desc(&mut desc[3], status as u64, 1, VIRTQ_DESC_F_WRITE, 0);
+    }
+    notify_request(dm, base, mem, n)
+}
+
+/// Offer the chain at descriptor 0 as request number `n` on queue 0,
+/// notify the transport at `base` and return the status byte.
+pub fn notify_request<const N: usize>(
+    dm: &mut DeviceManager,
+    base: u64,
+    mem: *mut ReqQueueMem<N>,
+    n: u16,
+) -> u8 {
+    unsafe {
+        (*mem).vq.avail.ring[n as usize % N] = 0;
+        core::ptr::write_volatile(&raw mut (*mem).vq.avail.idx, n + 1);
+    }
+    dm.handle_mmio(base + 0x050, 0, 4, true); // QueueNotify
+    unsafe { core::ptr::read_volatile(&raw const (*mem).status) }
+}
+
+/// Length the device reported for the most recent used entry of `mem`.
+pub fn last_used_len<const N: usize>(mem: *const ReqQueueMem<N>) -> u32 {
+    used_tail(unsafe { &raw const (*mem).vq }).1
+}
+
+/// Whether `intid` is queued for any vCPU of the current VM.
+pub fn spi_pending(intid: u32) -> bool {
+    let bit = 1u32 << (intid - 32);
+    current_vm_state()
+        .pending_spis
+        .iter()
+        .any(|s| s.load(Ordering::Acquire) & bit != 0)
+}
+
+/// Drop the SPIs the completions raised on the current VM.
+pub fn clear_spis(intids: &[u32]) {
+    let vs = current_vm_state();
+    for &intid in intids {
+        let bit = 1u32 << (intid - 32);
+        for spis in vs.pending_spis.iter() {
+            spis.fetch_and(!bit, Ordering::Relaxed);
+        }
+    }
+}